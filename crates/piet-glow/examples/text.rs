@@ -29,8 +29,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     util::with_renderer(move |render_context, width, _height| {
         render_context.clear(None, piet::Color::WHITE);
 
-        let layout = if layout.is_none() || width != last_width {
-            layout.insert({
+        if layout.is_none() || width != last_width {
+            layout = Some({
                 render_context
                     .text()
                     .new_text_layout(TEXT)
@@ -38,13 +38,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .text_color(piet::Color::rgb(0.1, 0.1, 0.1))
                     .build()
                     .expect("failed to build text layout")
-            })
-        } else {
-            layout.as_mut().unwrap()
-        };
+            });
+        }
         last_width = width;
 
-        render_context.draw_text(layout, (10.0, 10.0));
+        render_context.draw_text(layout.as_ref().unwrap(), (10.0, 10.0));
     })
 }
 
@@ -211,20 +211,18 @@ mod util {
                         .is_none());
                 }
                 Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::Resized(size) => {
-                        if size.width != 0 && size.height != 0 {
-                            // Some platforms like EGL require resizing GL surface to update the size
-                            // Notable platforms here are Wayland and macOS, other don't require it
-                            // and the function is no-op, but it's wise to resize it for portability
-                            // reasons.
-                            if let Some((gl_context, gl_surface, _)) = &state {
-                                gl_surface.resize(
-                                    gl_context,
-                                    NonZeroU32::new(size.width).unwrap(),
-                                    NonZeroU32::new(size.height).unwrap(),
-                                );
-                                current_size = Some(size);
-                            }
+                    WindowEvent::Resized(size) if size.width != 0 && size.height != 0 => {
+                        // Some platforms like EGL require resizing GL surface to update the size
+                        // Notable platforms here are Wayland and macOS, other don't require it
+                        // and the function is no-op, but it's wise to resize it for portability
+                        // reasons.
+                        if let Some((gl_context, gl_surface, _)) = &state {
+                            gl_surface.resize(
+                                gl_context,
+                                NonZeroU32::new(size.width).unwrap(),
+                                NonZeroU32::new(size.height).unwrap(),
+                            );
+                            current_size = Some(size);
                         }
                     }
                     WindowEvent::CloseRequested => {
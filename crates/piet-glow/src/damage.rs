@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tracking the damaged areas of frames for partial presentation.
+
+use piet_hardware::piet::kurbo::Rect;
+
+use std::collections::VecDeque;
+
+/// The number of past frames that damage is remembered for.
+///
+/// Swapchains rarely have more than three or four buffers, so older buffers are repainted fully.
+const MAX_AGE: usize = 4;
+
+/// Tracks the damaged areas of a window surface over several frames.
+///
+/// With `EGL_EXT_buffer_age` (`Surface::buffer_age` in `glutin`), the back buffer still contains
+/// the frame that was presented a few frames ago, so only the areas that changed since then
+/// need to be drawn again. With `EGL_KHR_swap_buffers_with_damage`
+/// (`Surface::swap_buffers_with_damage` in `glutin`), the compositor is told which area of the
+/// window changed, so it doesn't recomposite the rest. For mostly idle windows, this saves a
+/// lot of power.
+///
+/// Every frame, mark the areas that changed with [`add_damage`](Self::add_damage), ask for the
+/// area to draw with [`repaint_region`](Self::repaint_region), clip the render context to it and
+/// redraw, then swap with the rectangle from [`swap_rect`](Self::swap_rect) and call
+/// [`end_frame`](Self::end_frame).
+/// [`GlContext::damaged_render_context`] and [`GlContext::finish_damaged_frame`] do this with
+/// the tracker of a [`GlContext`].
+///
+/// [`GlContext`]: crate::GlContext
+/// [`GlContext::damaged_render_context`]: crate::GlContext::damaged_render_context
+/// [`GlContext::finish_damaged_frame`]: crate::GlContext::finish_damaged_frame
+#[derive(Debug, Clone)]
+pub struct DamageTracker {
+    /// The size of the surface.
+    size: (u32, u32),
+
+    /// The damage of the frame being drawn.
+    current: Rect,
+
+    /// The damage of previous frames, most recent first.
+    history: VecDeque<Rect>,
+}
+
+impl DamageTracker {
+    /// Create a new damage tracker for a surface of the given size.
+    ///
+    /// The first frame is damaged completely.
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut tracker = Self {
+            size: (width, height),
+            current: Rect::ZERO,
+            history: VecDeque::with_capacity(MAX_AGE),
+        };
+        tracker.damage_all();
+        tracker
+    }
+
+    /// Resize the surface, which damages it completely.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if self.size != (width, height) {
+            self.size = (width, height);
+            self.history.clear();
+            self.damage_all();
+        }
+    }
+
+    /// Get the size of the surface.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Mark an area of the current frame as changed, in pixels from the top left.
+    pub fn add_damage(&mut self, rect: Rect) {
+        let rect = rect.abs().intersect(self.bounds()).expand();
+        if rect.area() > 0.0 {
+            self.current = if self.current.area() > 0.0 {
+                self.current.union(rect)
+            } else {
+                rect
+            };
+        }
+    }
+
+    /// Mark the whole current frame as changed.
+    pub fn damage_all(&mut self) {
+        self.current = self.bounds();
+    }
+
+    /// Get the area of the current frame that has changed.
+    pub fn damage(&self) -> Rect {
+        self.current
+    }
+
+    /// Get the area that has to be drawn into a back buffer of the given age.
+    ///
+    /// `buffer_age` is the number of frames since the back buffer was presented, as returned by
+    /// `EGL_EXT_buffer_age`. An age of zero means that its contents are undefined, so the whole
+    /// surface has to be drawn. The result has zero area if nothing has to be drawn.
+    pub fn repaint_region(&self, buffer_age: u32) -> Rect {
+        let age = buffer_age as usize;
+        if age == 0 || age > self.history.len() + 1 {
+            return self.bounds();
+        }
+
+        self.history
+            .iter()
+            .take(age - 1)
+            .fold(self.current, |region, &damage| {
+                if damage.area() <= 0.0 {
+                    region
+                } else if region.area() <= 0.0 {
+                    damage
+                } else {
+                    region.union(damage)
+                }
+            })
+    }
+
+    /// Get the damage of the current frame in the form that `eglSwapBuffersWithDamage` takes.
+    ///
+    /// This is `[x, y, width, height]` with the origin at the bottom left of the surface, or
+    /// `None` if nothing changed.
+    pub fn swap_rect(&self) -> Option<[i32; 4]> {
+        if self.current.area() <= 0.0 {
+            return None;
+        }
+
+        let height = self.size.1 as f64;
+        Some([
+            self.current.x0 as i32,
+            (height - self.current.y1) as i32,
+            self.current.width() as i32,
+            self.current.height() as i32,
+        ])
+    }
+
+    /// Finish the current frame after it has been swapped, and start a new one with no damage.
+    pub fn end_frame(&mut self) {
+        if self.history.len() == MAX_AGE {
+            self.history.pop_back();
+        }
+        self.history.push_front(self.current);
+        self.current = Rect::ZERO;
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64)
+    }
+}
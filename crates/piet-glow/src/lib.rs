@@ -27,11 +27,24 @@ use glow::HasContext;
 
 use piet::IntoBrush;
 use piet_hardware::piet::{self, kurbo, Error as Pierror};
+use piet_hardware::SourceBuilder;
 
 use std::borrow::Cow;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fmt;
 use std::mem;
+use std::rc::Rc;
+
+mod damage;
+pub mod prelude;
+
+pub use damage::DamageTracker;
+pub use piet_hardware::{
+    Antialiasing, CompositeMode, CustomShader, HitTester, LayerId, Marker, MarkerPlacement,
+    MemoryUsage, MissingResource, Placeholder, ResourceMemory, SpreadMode, SweepGradient,
+    Transform3d,
+};
 
 macro_rules! c {
     ($e:expr) => {{
@@ -39,15 +52,56 @@ macro_rules! c {
     }};
 }
 
+macro_rules! error {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::error!($($arg)+);
+        }
+
+        #[cfg(all(feature = "log", not(feature = "tracing")))]
+        {
+            log::error!($($arg)+);
+        }
+
+        #[cfg(not(any(feature = "tracing", feature = "log")))]
+        {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
 const VERTEX_SHADER: &str = include_str!("./shaders/glow.v.glsl");
 const FRAGMENT_SHADER: &str = include_str!("./shaders/glow.f.glsl");
 
+/// The texture target for external images, from `GL_OES_EGL_image_external`.
+const TEXTURE_EXTERNAL_OES: u32 = 0x8D65;
+
+/// The `GL_STENCIL_BITS` parameter, which `glow` leaves out since core profiles removed it.
+const STENCIL_BITS: u32 = 0x0D57;
+
+/// The texture parameter and value from `GL_EXT_texture_sRGB_decode` that sample sRGB textures
+/// without decoding them.
+const TEXTURE_SRGB_DECODE_EXT: u32 = 0x8A48;
+const SKIP_DECODE_EXT: u32 = 0x8A4A;
+
+/// The vertex attributes, along with their fixed locations.
+const ATTRIBUTES: [(&str, u32); 3] = [("aPosition", 0), ("aUv", 1), ("aColor", 2)];
+
 #[derive(Debug, Clone, Copy)]
 enum Uniforms {
     Transform = 0,
     ViewportSize = 1,
     ImageTexture = 2,
     MaskTexture = 3,
+    ExternalImageTexture = 4,
+    ImageKind = 5,
+    ColorMatrix = 6,
+    ColorOffset = 7,
+    ClipRect = 8,
+    ClipRadii = 9,
+    BrushParams = 10,
+    BorderColor = 11,
 }
 
 impl Uniforms {
@@ -61,51 +115,546 @@ impl Uniforms {
             Uniforms::ViewportSize => "uViewportSize",
             Uniforms::ImageTexture => "uImage",
             Uniforms::MaskTexture => "uMask",
+            Uniforms::ExternalImageTexture => "uExternalImage",
+            Uniforms::ImageKind => "uImageKind",
+            Uniforms::ColorMatrix => "uColorMatrix",
+            Uniforms::ColorOffset => "uColorOffset",
+            Uniforms::ClipRect => "uClipRect",
+            Uniforms::ClipRadii => "uClipRadii",
+            Uniforms::BrushParams => "uBrushParams",
+            Uniforms::BorderColor => "uBorderColor",
+        }
+    }
+
+    /// Whether a program variant needs to have this uniform.
+    fn is_required(self, variant: ShaderVariant) -> bool {
+        match self {
+            Uniforms::ExternalImageTexture | Uniforms::ImageKind | Uniforms::BrushParams => false,
+
+            // Custom brushes don't sample the image.
+            Uniforms::ImageTexture
+            | Uniforms::ColorMatrix
+            | Uniforms::ColorOffset
+            | Uniforms::BorderColor => !matches!(variant, ShaderVariant::Custom(_)),
+
+            _ => true,
         }
     }
 }
 
-const UNIFORM_COUNT: usize = 4;
+const UNIFORM_COUNT: usize = 12;
 const UNIFORMS: [Uniforms; UNIFORM_COUNT] = [
     Uniforms::Transform,
     Uniforms::ViewportSize,
     Uniforms::ImageTexture,
     Uniforms::MaskTexture,
+    Uniforms::ExternalImageTexture,
+    Uniforms::ImageKind,
+    Uniforms::ColorMatrix,
+    Uniforms::ColorOffset,
+    Uniforms::ClipRect,
+    Uniforms::ClipRadii,
+    Uniforms::BrushParams,
+    Uniforms::BorderColor,
 ];
 
 use Uniforms::*;
 
+/// The variants of the rendering program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ShaderVariant {
+    /// The image is a regular two-dimensional texture.
+    Texture2D,
+
+    /// The image is an external texture, like a video frame or a camera image.
+    External,
+
+    /// A single program that handles every kind of image using uniform branching.
+    Uber,
+
+    /// The color comes from the custom brush shader with this index.
+    Custom(u32),
+}
+
+impl ShaderVariant {
+    /// Get the preprocessor lines to add to the fragment shader for this variant.
+    fn fragment_defines(self, external_images: bool) -> &'static str {
+        match (self, external_images) {
+            (ShaderVariant::Texture2D, _) => "",
+            (ShaderVariant::External, _) => {
+                "#extension GL_OES_EGL_image_external_essl3 : require\n#define PIET_EXTERNAL_IMAGE 1"
+            }
+            (ShaderVariant::Uber, false) => "#define PIET_UBER 1",
+            (ShaderVariant::Uber, true) => {
+                "#extension GL_OES_EGL_image_external_essl3 : require\n#define PIET_UBER 1\n#define PIET_EXTERNAL_SUPPORTED 1"
+            }
+            (ShaderVariant::Custom(_), _) => "#define PIET_CUSTOM_BRUSH 1",
+        }
+    }
+}
+
+/// A kind of draw that may need its own shader program.
+///
+/// This is used to select which programs to compile in [`GlContext::prewarm_selected_shaders`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ShaderKind {
+    /// Drawing shapes, text, gradients and regular images.
+    Texture,
+
+    /// Drawing images imported with [`GlContext::import_external_texture`].
+    ExternalImage,
+}
+
+impl ShaderKind {
+    /// Every kind of shader.
+    const ALL: [ShaderKind; 2] = [ShaderKind::Texture, ShaderKind::ExternalImage];
+}
+
+/// How colors are written to the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum AlphaMode {
+    /// Write colors with premultiplied alpha.
+    ///
+    /// This matches the output of other `piet` backends, like `piet-cairo`.
+    #[default]
+    Premultiplied,
+
+    /// Write colors with straight alpha.
+    ///
+    /// This is useful when the framebuffer is later composited by something that expects
+    /// straight alpha.
+    Straight,
+}
+
+/// The values of `uImageKind` in the uber-shader.
+const IMAGE_KIND_TEXTURE_2D: i32 = 0;
+const IMAGE_KIND_EXTERNAL: i32 = 1;
+
+/// A compiled shader program.
+struct Program<H: HasContext + ?Sized> {
+    /// The program object.
+    program: H::Program,
+
+    /// The uniform locations.
+    ///
+    /// Uniforms that aren't used by this variant are `None`.
+    uniforms: Box<[Option<H::UniformLocation>]>,
+}
+
+impl<H: HasContext + ?Sized> Program<H> {
+    fn uniform(&self, uniform: Uniforms) -> Option<&H::UniformLocation> {
+        self.uniforms.get(uniform.as_index()).unwrap().as_ref()
+    }
+}
+
+/// The depth and stencil attachments of render targets, and whether they are attached.
+type DepthStencils<H> =
+    HashMap<<H as HasContext>::Framebuffer, (<H as HasContext>::Renderbuffer, bool)>;
+
+/// The callback that shows images on overlays.
+type OverlayHandler<H> = Box<dyn FnMut(Overlay<H>) -> bool>;
+
+/// An image that the application is asked to show on an overlay plane or sub-surface.
+///
+/// See [`GlContext::on_overlay`] for more information.
+#[non_exhaustive]
+pub struct Overlay<H: HasContext + ?Sized> {
+    /// The texture that holds the image.
+    pub texture: H::Texture,
+
+    /// The target that the texture is bound to, like `GL_TEXTURE_2D` or
+    /// `GL_TEXTURE_EXTERNAL_OES`.
+    pub target: u32,
+
+    /// The area of the texture to show, in texels.
+    pub source: kurbo::Rect,
+
+    /// Where to show the image, in pixels of the window from its top-left corner.
+    pub rect: kurbo::Rect,
+}
+
 /// A wrapper around a `glow` context.
 struct GpuContext<H: HasContext + ?Sized> {
-    /// A compiled shader program for rendering.
-    render_program: H::Program,
+    /// The compiled shader programs for rendering, compiled as they are needed.
+    programs: RefCell<HashMap<ShaderVariant, Rc<Program<H>>>>,
 
-    /// The uniform locations.
-    uniforms: Box<[H::UniformLocation]>,
+    /// The header to prepend to every shader.
+    shader_header: String,
+
+    /// Can we sample from external textures?
+    external_images: bool,
+
+    /// Use one program for every draw.
+    uber_shader: bool,
 
     /// Do we need to check the indices?
     check_indices: bool,
 
+    /// Can we use `GL_CLAMP_TO_BORDER` for textures?
+    border_clamp: bool,
+
+    /// Are we running on top of ANGLE?
+    angle: bool,
+
+    /// How colors are written to the framebuffer.
+    alpha_mode: AlphaMode,
+
+    /// Is the framebuffer sRGB-encoded?
+    srgb: bool,
+
+    /// Do we need to enable `GL_FRAMEBUFFER_SRGB` to get sRGB encoding?
+    framebuffer_srgb: bool,
+
+    /// Can sRGB textures be sampled without decoding them, so that render targets are sampled
+    /// like other textures?
+    skip_srgb_decode: bool,
+
+    /// The color matrix applied to texture colors.
+    color_matrix: Cell<piet_hardware::ColorMatrix>,
+
+    /// How draws are blended with the framebuffer.
+    composite_mode: Cell<piet_hardware::CompositeMode>,
+
+    /// The shape that draws are clipped to in the fragment shader.
+    clip_shape: Cell<Option<piet_hardware::ClipShape>>,
+
+    /// The pixels that draws are restricted to with the scissor test.
+    scissor: Cell<Option<[u32; 4]>>,
+
+    /// The projection applied after the transform of each draw.
+    projection: Cell<piet_hardware::Projection>,
+
+    /// The code of the custom brushes that were registered, by their index.
+    custom_shaders: RefCell<Vec<String>>,
+
+    /// The custom brush that draws are filled with, if any.
+    custom_brush: Cell<Option<piet_hardware::CustomBrush>>,
+
+    /// The interpolation that textures are sampled with, if it is overridden.
+    sampler_interpolation: Cell<Option<piet::InterpolationMode>>,
+
+    /// Sampler objects for overriding the interpolation of textures, by how they sample.
+    samplers: RefCell<Vec<(SamplerKey, H::Sampler)>>,
+
+    /// The framebuffer of the render target that is bound, if any.
+    render_target: Cell<Option<H::Framebuffer>>,
+
+    /// The depth and stencil attachments of render targets, and whether they are attached.
+    depth_stencils: RefCell<DepthStencils<H>>,
+
+    /// Does the default framebuffer have a stencil buffer?
+    default_stencil: bool,
+
+    /// The number of clips in the stencil buffer of each framebuffer that has any.
+    clip_depths: RefCell<HashMap<Option<H::Framebuffer>, u32>>,
+
+    /// The callback that shows images on overlays, if the application set one.
+    overlay_handler: RefCell<Option<OverlayHandler<H>>>,
+
     /// The underlying context.
     context: H,
 }
 
 impl<H: HasContext + ?Sized> GpuContext<H> {
-    fn uniform(&self, uniform: Uniforms) -> &H::UniformLocation {
-        self.uniforms.get(uniform.as_index()).unwrap()
+    /// Enable sRGB encoding of the framebuffer, if it needs to be enabled.
+    unsafe fn enable_srgb(&self) {
+        if self.srgb && self.framebuffer_srgb {
+            self.context.enable(glow::FRAMEBUFFER_SRGB);
+        }
+    }
+
+    /// Disable sRGB encoding of the framebuffer, if it was enabled.
+    unsafe fn disable_srgb(&self) {
+        if self.srgb && self.framebuffer_srgb {
+            self.context.disable(glow::FRAMEBUFFER_SRGB);
+        }
+    }
+
+    /// Does the bound framebuffer have a stencil buffer?
+    fn has_stencil(&self) -> bool {
+        match self.render_target.get() {
+            Some(framebuffer) => self
+                .depth_stencils
+                .borrow()
+                .get(&framebuffer)
+                .map_or(false, |&(_, attached)| attached),
+            None => self.default_stencil,
+        }
+    }
+
+    /// Get the number of clips in the stencil buffer of the bound framebuffer.
+    fn clip_depth(&self) -> u32 {
+        self.clip_depths
+            .borrow()
+            .get(&self.render_target.get())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Draw the triangles of a clip into the stencil buffer, applying `op` to the pixels whose
+    /// stencil is `reference`.
+    unsafe fn draw_clip(
+        &self,
+        vertex_buffer: &GlVertexBuffer<H>,
+        size: (u32, u32),
+        reference: u32,
+        op: u32,
+    ) -> Result<(), GlError> {
+        let variant = self
+            .variant_for(ShaderKind::Texture)
+            .expect("2D textures are always supported");
+        let program = self.program(variant)?;
+
+        self.context.use_program(Some(program.program));
+        self.context.viewport(0, 0, size.0 as i32, size.1 as i32);
+        self.context
+            .uniform_2_f32(program.uniform(ViewportSize), size.0 as f32, size.1 as f32);
+        self.context.uniform_matrix_3_f32_slice(
+            program.uniform(Transform),
+            false,
+            &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        );
+        self.context
+            .uniform_2_f32(program.uniform(ClipRadii), -1.0, -1.0);
+
+        // Only the stencil buffer is written to.
+        self.context.enable(glow::STENCIL_TEST);
+        self.context.stencil_mask(0xFF);
+        self.context
+            .stencil_func(glow::EQUAL, reference as i32, 0xFF);
+        self.context.stencil_op(glow::KEEP, glow::KEEP, op);
+        self.context.color_mask(false, false, false, false);
+
+        self.context.bind_vertex_array(Some(vertex_buffer.vao));
+        self.context.draw_elements(
+            glow::TRIANGLES,
+            vertex_buffer.num_indices.get() as i32,
+            glow::UNSIGNED_INT,
+            0,
+        );
+
+        self.context.bind_vertex_array(None);
+        self.context.color_mask(true, true, true, true);
+        self.context.disable(glow::STENCIL_TEST);
+        self.context.use_program(None);
+        gl_error(&self.context);
+
+        Ok(())
+    }
+
+    /// Get the program variant used for a kind of draw, or `None` if it isn't supported.
+    fn variant_for(&self, kind: ShaderKind) -> Option<ShaderVariant> {
+        match (self.uber_shader, kind) {
+            (_, ShaderKind::ExternalImage) if !self.external_images => None,
+            (true, _) => Some(ShaderVariant::Uber),
+            (false, ShaderKind::Texture) => Some(ShaderVariant::Texture2D),
+            (false, ShaderKind::ExternalImage) => Some(ShaderVariant::External),
+        }
+    }
+
+    /// Get the program for a shader variant, compiling it if necessary.
+    fn program(&self, variant: ShaderVariant) -> Result<Rc<Program<H>>, GlError> {
+        if let Some(program) = self.programs.borrow().get(&variant) {
+            return Ok(program.clone());
+        }
+
+        // The code of a custom brush goes after the fragment shader, which declares it.
+        let custom_shader = match variant {
+            ShaderVariant::Custom(index) => self.custom_shaders.borrow()[index as usize].clone(),
+            _ => String::new(),
+        };
+
+        let vertex_shader = format!("{}\n{}", self.shader_header, VERTEX_SHADER);
+        let fragment_shader = format!(
+            "{}\n{}\n{}\n{}",
+            self.shader_header,
+            variant.fragment_defines(self.external_images),
+            FRAGMENT_SHADER,
+            custom_shader
+        );
+
+        let program = unsafe {
+            let program = compile_program(&self.context, &vertex_shader, &fragment_shader)?;
+            let _delete_program = CallOnDrop(|| self.context.delete_program(program));
+
+            // Get the uniform locations.
+            let uniforms = UNIFORMS
+                .iter()
+                .map(|uniform| {
+                    match self
+                        .context
+                        .get_uniform_location(program, uniform.as_name())
+                    {
+                        Some(location) => Ok(Some(location)),
+                        None if !uniform.is_required(variant) => Ok(None),
+                        None => Err(GlError(format!(
+                            "failed to get uniform location for {}",
+                            uniform.as_name()
+                        ))),
+                    }
+                })
+                .collect::<Result<Box<[_]>, _>>()?;
+
+            mem::forget(_delete_program);
+            Rc::new(Program { program, uniforms })
+        };
+
+        self.programs.borrow_mut().insert(variant, program.clone());
+        Ok(program)
+    }
+
+    /// Get the wrap mode for a repeat strategy, and the border color if it clamps to one.
+    fn wrap_mode(&self, repeat: piet_hardware::RepeatStrategy) -> (u32, Option<[f32; 4]>) {
+        match repeat {
+            piet_hardware::RepeatStrategy::Color(color) if self.border_clamp => {
+                let (r, g, b, a) = color.as_rgba();
+                (glow::CLAMP_TO_BORDER, Some([c!(r), c!(g), c!(b), c!(a)]))
+            }
+            piet_hardware::RepeatStrategy::Color(_) => {
+                // Border clamping isn't available, so the shader replaces the color outside of
+                // the texture instead. See `border_color`.
+                (glow::CLAMP_TO_EDGE, None)
+            }
+            piet_hardware::RepeatStrategy::Repeat => (glow::REPEAT, None),
+            piet_hardware::RepeatStrategy::Reflect => (glow::MIRRORED_REPEAT, None),
+            piet_hardware::RepeatStrategy::Clamp => (glow::CLAMP_TO_EDGE, None),
+            _ => panic!("unsupported repeat strategy: {repeat:?}"),
+        }
+    }
+
+    /// Get the premultiplied color that the shader should use outside of a texture with
+    /// `repeat`, if the GPU can't clamp to a border color itself.
+    fn border_color(&self, repeat: Option<piet_hardware::RepeatStrategy>) -> Option<[f32; 4]> {
+        match repeat {
+            Some(piet_hardware::RepeatStrategy::Color(color)) if !self.border_clamp => {
+                let (r, g, b, a) = color.as_rgba();
+                Some([c!(r * a), c!(g * a), c!(b * a), c!(a)])
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the sampler object that samples like a texture with `repeat`, but with another
+    /// interpolation.
+    unsafe fn sampler(
+        &self,
+        interpolation: piet::InterpolationMode,
+        repeat: piet_hardware::RepeatStrategy,
+        mipmapped: bool,
+    ) -> Result<H::Sampler, GlError> {
+        let key = SamplerKey {
+            interpolation,
+            repeat,
+            mipmapped,
+        };
+        if let Some((_, sampler)) = self.samplers.borrow().iter().find(|(k, _)| *k == key) {
+            return Ok(*sampler);
+        }
+
+        let sampler = self.context.create_sampler().gl_err()?;
+        let (min_filter, mag_filter) = filters(interpolation, mipmapped);
+        self.context
+            .sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER, min_filter as i32);
+        self.context
+            .sampler_parameter_i32(sampler, glow::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+        let (wrap, _border) = self.wrap_mode(repeat);
+        #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm32")))]
+        if let Some(border) = _border {
+            self.context
+                .sampler_parameter_f32_slice(sampler, glow::TEXTURE_BORDER_COLOR, &border);
+        }
+        self.context
+            .sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_S, wrap as i32);
+        self.context
+            .sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_T, wrap as i32);
+        gl_error(&self.context);
+
+        self.samplers.borrow_mut().push((key, sampler));
+        Ok(sampler)
+    }
+
+    /// Sample the red channel of the bound texture in every channel if it holds coverage, so
+    /// that it reads as premultiplied white.
+    unsafe fn swizzle_coverage(&self, texture: &GlTexture<H>, coverage: bool) {
+        if texture.coverage.replace(coverage) == coverage {
+            return;
+        }
+
+        let swizzle = if coverage {
+            [glow::RED; 4]
+        } else {
+            [glow::RED, glow::GREEN, glow::BLUE, glow::ALPHA]
+        };
+        for (param, value) in [
+            glow::TEXTURE_SWIZZLE_R,
+            glow::TEXTURE_SWIZZLE_G,
+            glow::TEXTURE_SWIZZLE_B,
+            glow::TEXTURE_SWIZZLE_A,
+        ]
+        .into_iter()
+        .zip(swizzle)
+        {
+            self.context
+                .tex_parameter_i32(glow::TEXTURE_2D, param, value as i32);
+        }
     }
 }
 
 impl<H: HasContext + ?Sized> Drop for GpuContext<H> {
     fn drop(&mut self) {
-        unsafe {
-            self.context.delete_program(self.render_program);
+        for program in self.programs.get_mut().values() {
+            unsafe {
+                self.context.delete_program(program.program);
+            }
+        }
+
+        for (_, sampler) in self.samplers.get_mut().drain(..) {
+            unsafe {
+                self.context.delete_sampler(sampler);
+            }
         }
     }
 }
 
+/// How a sampler object samples textures.
+#[derive(Clone, Copy, PartialEq)]
+struct SamplerKey {
+    /// The interpolation to sample with.
+    interpolation: piet::InterpolationMode,
+
+    /// How the texture repeats.
+    repeat: piet_hardware::RepeatStrategy,
+
+    /// Whether the texture has mip levels.
+    mipmapped: bool,
+}
+
 /// A wrapper around a `glow` texture.
-struct GlTexture<H: HasContext + ?Sized>(H::Texture);
+struct GlTexture<H: HasContext + ?Sized> {
+    /// The underlying texture.
+    texture: H::Texture,
+
+    /// The target that the texture is bound to.
+    target: u32,
+
+    /// The framebuffer that renders into this texture, if it is a render target.
+    framebuffer: Option<H::Framebuffer>,
+
+    /// The interpolation that the texture is currently sampled with, if it is known.
+    interpolation: Cell<Option<piet::InterpolationMode>>,
+
+    /// How the texture repeats, or `None` for external textures.
+    repeat: Option<piet_hardware::RepeatStrategy>,
+
+    /// Whether the texture has mip levels that are kept up to date.
+    mipmapped: Cell<bool>,
+
+    /// Whether the texture holds a single channel of coverage.
+    coverage: Cell<bool>,
+}
 
 /// A wrapper around a `glow` vertex buffer.
 struct GlVertexBuffer<H: HasContext + ?Sized> {
@@ -145,11 +694,25 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
     type Error = GlError;
 
     fn clear(&self, color: piet_hardware::piet::Color) {
-        let (r, g, b, a) = color.as_rgba();
+        let (mut r, mut g, mut b, a) = color.as_rgba();
+
+        if self.srgb {
+            r = srgb_to_linear(r);
+            g = srgb_to_linear(g);
+            b = srgb_to_linear(b);
+        }
+
+        if self.alpha_mode == AlphaMode::Premultiplied {
+            r *= a;
+            g *= a;
+            b *= a;
+        }
 
         unsafe {
+            self.enable_srgb();
             self.context.clear_color(c!(r), c!(g), c!(b), c!(a));
             self.context.clear(glow::COLOR_BUFFER_BIT);
+            self.disable_srgb();
         }
     }
 
@@ -175,10 +738,7 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 self.context.bind_texture(glow::TEXTURE_2D, None);
             });
 
-            let (min_filter, mag_filter) = match interpolation {
-                piet::InterpolationMode::NearestNeighbor => (glow::NEAREST, glow::NEAREST),
-                piet::InterpolationMode::Bilinear => (glow::LINEAR, glow::LINEAR),
-            };
+            let (min_filter, mag_filter) = filters(interpolation, false);
 
             self.context.tex_parameter_i32(
                 glow::TEXTURE_2D,
@@ -191,39 +751,47 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 mag_filter as i32,
             );
 
-            let (wrap_s, wrap_t) = match repeat {
-                piet_hardware::RepeatStrategy::Color(_color) => {
-                    #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm32")))]
-                    {
-                        let (r, g, b, a) = _color.as_rgba();
-                        self.context.tex_parameter_f32_slice(
-                            glow::TEXTURE_2D,
-                            glow::TEXTURE_BORDER_COLOR,
-                            &[c!(r), c!(g), c!(b), c!(a)],
-                        );
-                    }
-
-                    (glow::CLAMP_TO_BORDER, glow::CLAMP_TO_BORDER)
-                }
-                piet_hardware::RepeatStrategy::Repeat => (glow::REPEAT, glow::REPEAT),
-                piet_hardware::RepeatStrategy::Clamp => (glow::CLAMP_TO_EDGE, glow::CLAMP_TO_EDGE),
-                _ => panic!("unsupported repeat strategy: {repeat:?}"),
-            };
+            let (wrap, _border) = self.wrap_mode(repeat);
+            #[cfg(not(any(target_arch = "wasm32", target_arch = "wasm32")))]
+            if let Some(border) = _border {
+                self.context.tex_parameter_f32_slice(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_BORDER_COLOR,
+                    &border,
+                );
+            }
 
             self.context
-                .tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, wrap_s as i32);
+                .tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, wrap as i32);
             self.context
-                .tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, wrap_t as i32);
+                .tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, wrap as i32);
 
             gl_error(&self.context);
 
-            Ok(GlTexture(texture))
+            Ok(GlTexture {
+                texture,
+                target: glow::TEXTURE_2D,
+                framebuffer: None,
+                interpolation: Cell::new(Some(interpolation)),
+                repeat: Some(repeat),
+                mipmapped: Cell::new(false),
+                coverage: Cell::new(false),
+            })
         }
     }
 
     fn delete_texture(&self, texture: Self::Texture) {
         unsafe {
-            self.context.delete_texture(texture.0);
+            if let Some(framebuffer) = texture.framebuffer {
+                self.clip_depths.borrow_mut().remove(&Some(framebuffer));
+                if let Some((renderbuffer, _)) =
+                    self.depth_stencils.borrow_mut().remove(&framebuffer)
+                {
+                    self.context.delete_renderbuffer(renderbuffer);
+                }
+                self.context.delete_framebuffer(framebuffer);
+            }
+            self.context.delete_texture(texture.texture);
         }
     }
 
@@ -234,6 +802,16 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         format: piet::ImageFormat,
         data: Option<&[u8]>,
     ) {
+        #[cfg(target_arch = "wasm32")]
+        let expanded = data
+            .filter(|_| format == piet::ImageFormat::Grayscale)
+            .map(expand_coverage);
+        #[cfg(target_arch = "wasm32")]
+        let (format, data) = match format {
+            piet::ImageFormat::Grayscale => (piet::ImageFormat::RgbaPremul, expanded.as_deref()),
+            format => (format, data),
+        };
+
         let data_width = match format {
             piet::ImageFormat::Grayscale => 1,
             piet::ImageFormat::Rgb => 3,
@@ -241,6 +819,11 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
             _ => panic!("unsupported image format: {format:?}"),
         };
 
+        if texture.target != glow::TEXTURE_2D {
+            error!("cannot write to an external texture");
+            return;
+        }
+
         if let Some(data) = data {
             let total_len = usize::try_from(width)
                 .ok()
@@ -251,7 +834,8 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         }
 
         unsafe {
-            self.context.bind_texture(glow::TEXTURE_2D, Some(texture.0));
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
             let _guard = CallOnDrop(|| {
                 self.context.bind_texture(glow::TEXTURE_2D, None);
             });
@@ -278,6 +862,21 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 data_type,
                 data,
             );
+            self.swizzle_coverage(texture, format == glow::RED);
+
+            // The old mip levels no longer match, so stop sampling from them.
+            if texture.mipmapped.replace(false) {
+                let interpolation = texture
+                    .interpolation
+                    .get()
+                    .unwrap_or(piet::InterpolationMode::Bilinear);
+                let (min_filter, _) = filters(interpolation, false);
+                self.context.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MIN_FILTER,
+                    min_filter as i32,
+                );
+            }
         }
 
         gl_error(&self.context);
@@ -291,6 +890,17 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         format: piet_hardware::piet::ImageFormat,
         data: &[u8],
     ) {
+        #[cfg(target_arch = "wasm32")]
+        let expanded;
+        #[cfg(target_arch = "wasm32")]
+        let (format, data) = match format {
+            piet::ImageFormat::Grayscale => {
+                expanded = expand_coverage(data);
+                (piet::ImageFormat::RgbaPremul, &*expanded)
+            }
+            format => (format, data),
+        };
+
         let data_width = match format {
             piet::ImageFormat::Grayscale => 1,
             piet::ImageFormat::Rgb => 3,
@@ -301,8 +911,14 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         let total_len = (width * height * data_width) as usize;
         assert_eq!(data.len(), total_len);
 
+        if texture.target != glow::TEXTURE_2D {
+            error!("cannot write to an external texture");
+            return;
+        }
+
         unsafe {
-            self.context.bind_texture(glow::TEXTURE_2D, Some(texture.0));
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
             let _guard = CallOnDrop(|| {
                 self.context.bind_texture(glow::TEXTURE_2D, None);
             });
@@ -315,6 +931,9 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 _ => panic!("unsupported image format: {format:?}"),
             };
 
+            // Rows aren't necessarily aligned to four bytes.
+            self.context.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+
             self.context.tex_sub_image_2d(
                 glow::TEXTURE_2D,
                 0,
@@ -326,6 +945,10 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 data_type,
                 glow::PixelUnpackData::Slice(data),
             );
+
+            if texture.mipmapped.get() {
+                self.context.generate_mipmap(glow::TEXTURE_2D);
+            }
         }
 
         gl_error(&self.context);
@@ -336,24 +959,27 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         texture: &Self::Texture,
         interpolation: piet_hardware::piet::InterpolationMode,
     ) {
+        // Images are sampled differently from draw to draw, so skip redundant state changes.
+        if texture.interpolation.replace(Some(interpolation)) == Some(interpolation) {
+            return;
+        }
+
         unsafe {
-            self.context.bind_texture(glow::TEXTURE_2D, Some(texture.0));
+            self.context
+                .bind_texture(texture.target, Some(texture.texture));
             let _guard = CallOnDrop(|| {
-                self.context.bind_texture(glow::TEXTURE_2D, None);
+                self.context.bind_texture(texture.target, None);
             });
 
-            let (min_filter, mag_filter) = match interpolation {
-                piet::InterpolationMode::NearestNeighbor => (glow::NEAREST, glow::NEAREST),
-                piet::InterpolationMode::Bilinear => (glow::LINEAR, glow::LINEAR),
-            };
+            let (min_filter, mag_filter) = filters(interpolation, texture.mipmapped.get());
 
             self.context.tex_parameter_i32(
-                glow::TEXTURE_2D,
+                texture.target,
                 glow::TEXTURE_MIN_FILTER,
                 min_filter as i32,
             );
             self.context.tex_parameter_i32(
-                glow::TEXTURE_2D,
+                texture.target,
                 glow::TEXTURE_MAG_FILTER,
                 mag_filter as i32,
             );
@@ -384,32 +1010,17 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
             self.context
                 .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
 
-            // Set up vertex attributes.
+            // Set up vertex attributes, at the locations bound when the programs were linked.
             let vertex_attributes = [
-                (
-                    "aPosition",
-                    2,
-                    glow::FLOAT,
-                    bytemuck::offset_of!(Vertex, pos),
-                ),
-                ("aUv", 2, glow::FLOAT, bytemuck::offset_of!(Vertex, uv)),
-                (
-                    "aColor",
-                    4,
-                    glow::UNSIGNED_BYTE,
-                    bytemuck::offset_of!(Vertex, color),
-                ),
+                (2, glow::FLOAT, Vertex::POS_OFFSET),
+                (2, glow::FLOAT, Vertex::UV_OFFSET),
+                (4, glow::UNSIGNED_BYTE, Vertex::COLOR_OFFSET),
             ];
 
-            let stride = std::mem::size_of::<Vertex>() as i32;
-            for (name, size, data_type, offset) in vertex_attributes {
-                let location = self
-                    .context
-                    .get_attrib_location(self.render_program, name)
-                    .ok_or_else(|| {
-                        GlError(format!("failed to get attribute location for {name}"))
-                    })?;
-
+            let stride = Vertex::STRIDE as i32;
+            for (&(_, location), (size, data_type, offset)) in
+                ATTRIBUTES.iter().zip(vertex_attributes)
+            {
                 self.context.enable_vertex_attrib_array(location);
                 self.context.vertex_attrib_pointer_f32(
                     location,
@@ -459,6 +1070,9 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
                 self.context.bind_vertex_array(None);
             });
 
+            // The array buffer binding isn't part of the vertex array, unlike the element one.
+            self.context
+                .bind_buffer(glow::ARRAY_BUFFER, Some(buffer.vbo));
             self.context.buffer_data_u8_slice(
                 glow::ARRAY_BUFFER,
                 bytemuck::cast_slice(vertices),
@@ -485,58 +1099,179 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
         transform: &piet_hardware::piet::kurbo::Affine,
         size: (u32, u32),
     ) -> Result<(), Self::Error> {
+        // Pick the program based on the kind of texture being drawn.
+        let external = current_texture.target == TEXTURE_EXTERNAL_OES;
+        let kind = if external {
+            ShaderKind::ExternalImage
+        } else {
+            ShaderKind::Texture
+        };
+        let custom_brush = self.custom_brush.get();
+        let variant = match custom_brush {
+            Some(brush) => ShaderVariant::Custom(brush.shader.into_raw()),
+            None => self
+                .variant_for(kind)
+                .ok_or_else(|| GlError("external textures are not supported".into()))?,
+        };
+        let program = self.program(variant)?;
+
         unsafe {
             // Use our program.
-            self.context.use_program(Some(self.render_program));
+            self.context.use_program(Some(program.program));
             let _unbind_program = CallOnDrop(|| {
                 self.context.use_program(None);
             });
 
             // Set viewport size.
             self.context.viewport(0, 0, size.0 as i32, size.1 as i32);
-            self.context.uniform_2_f32(
-                Some(self.uniform(ViewportSize)),
-                size.0 as f32,
-                size.1 as f32,
-            );
+            self.context
+                .uniform_2_f32(program.uniform(ViewportSize), size.0 as f32, size.1 as f32);
 
-            // Set the transform.
-            let [a, b, c, d, e, f] = transform.as_coeffs();
-            let transform = [
-                c!(a),
-                c!(b),
-                c!(0.0),
-                c!(c),
-                c!(d),
-                c!(0.0),
-                c!(e),
-                c!(f),
-                c!(1.0),
-            ];
-            self.context.uniform_matrix_3_f32_slice(
-                Some(self.uniform(Transform)),
-                false,
-                &transform,
+            // Set the transform, followed by the projection, in column-major order.
+            let m = self.projection.get().after(transform);
+            let transform = [m[0], m[3], m[6], m[1], m[4], m[7], m[2], m[5], m[8]];
+            self.context
+                .uniform_matrix_3_f32_slice(program.uniform(Transform), false, &transform);
+
+            // Set the color matrix, splitting off the offset column.
+            let rows = self.color_matrix.get().rows();
+            let mut matrix = [0.0; 16];
+            for (column, slot) in matrix.chunks_exact_mut(4).enumerate() {
+                for (row, value) in slot.iter_mut().enumerate() {
+                    *value = rows[row][column];
+                }
+            }
+            self.context
+                .uniform_matrix_4_f32_slice(program.uniform(ColorMatrix), false, &matrix);
+            self.context.uniform_4_f32(
+                program.uniform(ColorOffset),
+                rows[0][4],
+                rows[1][4],
+                rows[2][4],
+                rows[3][4],
             );
 
-            // Set the image texture.
-            self.context.active_texture(glow::TEXTURE1);
+            // Set the clip shape, turning it off with a negative radius.
+            let (rect, radii) = match self.clip_shape.get() {
+                Some(shape) => (shape.rect, shape.radii),
+                None => ([0.0; 4], [-1.0; 2]),
+            };
+            self.context.uniform_4_f32(
+                program.uniform(ClipRect),
+                rect[0],
+                rect[1],
+                rect[2],
+                rect[3],
+            );
             self.context
-                .bind_texture(glow::TEXTURE_2D, Some(current_texture.0));
+                .uniform_2_f32(program.uniform(ClipRadii), radii[0], radii[1]);
+
+            // Set the border color to emulate, turning it off with a negative alpha.
+            let [r, g, b, a] = self
+                .border_color(current_texture.repeat)
+                .unwrap_or([0.0, 0.0, 0.0, -1.0]);
             self.context
-                .uniform_1_i32(Some(self.uniform(ImageTexture)), 1);
+                .uniform_4_f32(program.uniform(BorderColor), r, g, b, a);
+
+            // Set the parameters of the custom brush.
+            if let Some(brush) = custom_brush {
+                self.context
+                    .uniform_4_f32_slice(program.uniform(BrushParams), &brush.params);
+            }
+
+            // Set the image texture.
+            if variant == ShaderVariant::Uber {
+                // External images are bound to a separate sampler in the uber-shader.
+                let (unit, kind) = if external {
+                    (glow::TEXTURE2, IMAGE_KIND_EXTERNAL)
+                } else {
+                    (glow::TEXTURE1, IMAGE_KIND_TEXTURE_2D)
+                };
+
+                self.context.active_texture(unit);
+                self.context
+                    .bind_texture(current_texture.target, Some(current_texture.texture));
+                self.context.uniform_1_i32(program.uniform(ImageTexture), 1);
+                self.context
+                    .uniform_1_i32(program.uniform(ExternalImageTexture), 2);
+                self.context.uniform_1_i32(program.uniform(ImageKind), kind);
+            } else {
+                self.context.active_texture(glow::TEXTURE1);
+                self.context
+                    .bind_texture(current_texture.target, Some(current_texture.texture));
+                self.context.uniform_1_i32(program.uniform(ImageTexture), 1);
+            }
+
+            // Sample the image through a sampler object if its interpolation is overridden.
+            let sampler = match (self.sampler_interpolation.get(), current_texture.repeat) {
+                (Some(interpolation), Some(repeat)) => {
+                    Some(self.sampler(interpolation, repeat, current_texture.mipmapped.get())?)
+                }
+                _ => None,
+            };
+            self.context.bind_sampler(1, sampler);
+            let _unbind_sampler = CallOnDrop(|| {
+                if sampler.is_some() {
+                    self.context.bind_sampler(1, None);
+                }
+            });
 
             // Set the mask texture.
             self.context.active_texture(glow::TEXTURE0);
             self.context
-                .bind_texture(glow::TEXTURE_2D, Some(mask_texture.0));
-            self.context
-                .uniform_1_i32(Some(self.uniform(MaskTexture)), 0);
+                .bind_texture(glow::TEXTURE_2D, Some(mask_texture.texture));
+            self.context.uniform_1_i32(program.uniform(MaskTexture), 0);
 
             // Enable blending.
             self.context.enable(glow::BLEND);
-            self.context
-                .blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            match self.alpha_mode {
+                AlphaMode::Premultiplied => {
+                    let (src, dst) = self.composite_mode.get().blend_factors();
+                    self.context
+                        .blend_func(blend_factor(src), blend_factor(dst));
+                }
+                AlphaMode::Straight => {
+                    self.context.blend_func_separate(
+                        glow::SRC_ALPHA,
+                        glow::ONE_MINUS_SRC_ALPHA,
+                        glow::ONE,
+                        glow::ONE_MINUS_SRC_ALPHA,
+                    );
+                }
+            }
+            self.enable_srgb();
+            let _disable_srgb = CallOnDrop(|| self.disable_srgb());
+
+            // Only draw inside of the clips in the stencil buffer.
+            let clip_depth = self.clip_depth();
+            if clip_depth > 0 {
+                self.context.enable(glow::STENCIL_TEST);
+                self.context
+                    .stencil_func(glow::EQUAL, clip_depth as i32, 0xFF);
+                self.context.stencil_op(glow::KEEP, glow::KEEP, glow::KEEP);
+            }
+            let _disable_stencil = CallOnDrop(|| {
+                if clip_depth > 0 {
+                    self.context.disable(glow::STENCIL_TEST);
+                }
+            });
+
+            // Only draw inside of the scissor rectangle, with the origin at the bottom left.
+            let scissor = self.scissor.get();
+            if let Some([x0, y0, x1, y1]) = scissor {
+                self.context.enable(glow::SCISSOR_TEST);
+                self.context.scissor(
+                    x0 as i32,
+                    size.1 as i32 - y1 as i32,
+                    (x1 - x0) as i32,
+                    (y1 - y0) as i32,
+                );
+            }
+            let _disable_scissor = CallOnDrop(|| {
+                if scissor.is_some() {
+                    self.context.disable(glow::SCISSOR_TEST);
+                }
+            });
 
             // Set the vertex array.
             self.context.bind_vertex_array(Some(vertex_buffer.vao));
@@ -557,113 +1292,1420 @@ impl<H: HasContext + ?Sized> piet_hardware::GpuContext for GpuContext<H> {
             Ok(())
         }
     }
-}
 
-/// A wrapper around a [`glow`] context with cached information.
-pub struct GlContext<H: HasContext + ?Sized> {
-    source: piet_hardware::Source<GpuContext<H>>,
-    text: Text,
-}
+    fn set_color_matrix(&self, matrix: Option<&piet_hardware::ColorMatrix>) -> bool {
+        self.color_matrix.set(matrix.copied().unwrap_or_default());
+        true
+    }
 
-impl<H: HasContext + ?Sized> GlContext<H> {
-    /// Create a new [`GlContext`] from a [`glow`] context.
-    ///
-    /// # Safety
-    ///
-    /// The context must be current while calling new, and the context must be current
-    /// when this type is dropped.
+    fn set_composite_mode(&self, mode: Option<piet_hardware::CompositeMode>) -> bool {
+        // Blending straight alpha only works for drawing over the framebuffer.
+        if mode.is_some() && self.alpha_mode == AlphaMode::Straight {
+            return false;
+        }
+
+        self.composite_mode.set(mode.unwrap_or_default());
+        true
+    }
+
+    fn set_clip_shape(&self, shape: Option<&piet_hardware::ClipShape>) -> bool {
+        self.clip_shape.set(shape.copied());
+        true
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        self.scissor.set(rect);
+        true
+    }
+
+    fn set_projection(&self, projection: Option<&piet_hardware::Projection>) -> bool {
+        self.projection.set(projection.copied().unwrap_or_default());
+        true
+    }
+
+    fn set_custom_brush(&self, brush: Option<&piet_hardware::CustomBrush>) -> bool {
+        let known = brush.map_or(true, |brush| {
+            (brush.shader.into_raw() as usize) < self.custom_shaders.borrow().len()
+        });
+        if known {
+            self.custom_brush.set(brush.copied());
+        }
+        known
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<piet::InterpolationMode>) -> bool {
+        self.sampler_interpolation.set(interpolation);
+        true
+    }
+
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        // Render targets are drawn into after the levels would be generated.
+        if texture.target != glow::TEXTURE_2D || texture.framebuffer.is_some() {
+            return false;
+        }
+        if texture.mipmapped.get() {
+            return true;
+        }
+
+        unsafe {
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+            let _guard = CallOnDrop(|| {
+                self.context.bind_texture(glow::TEXTURE_2D, None);
+            });
+
+            self.context.generate_mipmap(glow::TEXTURE_2D);
+            let interpolation = texture
+                .interpolation
+                .get()
+                .unwrap_or(piet::InterpolationMode::Bilinear);
+            let (min_filter, _) = filters(interpolation, true);
+            self.context.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                min_filter as i32,
+            );
+        }
+
+        gl_error(&self.context);
+        texture.mipmapped.set(true);
+        true
+    }
+
+    fn read_pixels(
+        &self,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        (_, target_height): (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        unsafe {
+            // OpenGL puts the origin at the bottom left.
+            self.context.read_pixels(
+                x as i32,
+                (target_height - y - height) as i32,
+                width as i32,
+                height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(data),
+            );
+            gl_error(&self.context);
+        }
+
+        // Flip the rows so that they go from top to bottom.
+        let stride = width as usize * 4;
+        let rows = height as usize;
+        for row in 0..rows / 2 {
+            let (top, bottom) = data.split_at_mut((rows - row - 1) * stride);
+            top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+
+        // The framebuffer holds straight alpha in this mode.
+        if self.alpha_mode == AlphaMode::Straight {
+            for pixel in data.chunks_exact_mut(4) {
+                let alpha = pixel[3] as u16;
+                for channel in &mut pixel[..3] {
+                    *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn clear_rect(
+        &self,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        (_, target_height): (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, Self::Error> {
+        unsafe {
+            // OpenGL puts the origin at the bottom left.
+            self.context.enable(glow::SCISSOR_TEST);
+            self.context.scissor(
+                x as i32,
+                (target_height - y - height) as i32,
+                width as i32,
+                height as i32,
+            );
+        }
+
+        // Clearing is never blended, and only touches the scissor box.
+        piet_hardware::GpuContext::clear(self, color);
+
+        unsafe {
+            self.context.disable(glow::SCISSOR_TEST);
+            gl_error(&self.context);
+        }
+
+        Ok(true)
+    }
+
+    fn show_overlay(
+        &self,
+        texture: &Self::Texture,
+        source: kurbo::Rect,
+        rect: kurbo::Rect,
+    ) -> bool {
+        // Only the window is composited with the overlays below it.
+        if self.render_target.get().is_some() {
+            return false;
+        }
+
+        match &mut *self.overlay_handler.borrow_mut() {
+            Some(handler) => handler(Overlay {
+                texture: texture.texture,
+                target: texture.target,
+                source,
+                rect,
+            }),
+            None => false,
+        }
+    }
+
+    fn create_render_target(
+        &self,
+        (width, height): (u32, u32),
+    ) -> Result<Option<Self::Texture>, Self::Error> {
+        let mut texture = self.create_texture(
+            piet::InterpolationMode::Bilinear,
+            piet_hardware::RepeatStrategy::Clamp,
+        )?;
+
+        unsafe {
+            // Allocate storage that matches the default framebuffer's encoding.
+            let internal_format = if self.srgb {
+                glow::SRGB8_ALPHA8
+            } else {
+                glow::RGBA8
+            };
+            self.context
+                .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+            self.context.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+
+            // Other textures hold encoded colors, which the shader decodes.
+            if self.srgb && self.skip_srgb_decode {
+                self.context.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    TEXTURE_SRGB_DECODE_EXT,
+                    SKIP_DECODE_EXT as i32,
+                );
+            }
+            self.context.bind_texture(glow::TEXTURE_2D, None);
+
+            let framebuffer = match self.context.create_framebuffer() {
+                Ok(framebuffer) => framebuffer,
+                Err(err) => {
+                    self.delete_texture(texture);
+                    return Err(GlError(err));
+                }
+            };
+            texture.framebuffer = Some(framebuffer);
+
+            self.context
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            self.context.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture.texture),
+                0,
+            );
+            let status = self.context.check_framebuffer_status(glow::FRAMEBUFFER);
+            self.context
+                .bind_framebuffer(glow::FRAMEBUFFER, self.render_target.get());
+            gl_error(&self.context);
+
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                error!("render target framebuffer is incomplete: {:#x}", status);
+                self.delete_texture(texture);
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(texture))
+    }
+
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        // `None` binds the default framebuffer, since the previous binding can't be queried.
+        let framebuffer = target.and_then(|t| t.framebuffer);
+        self.render_target.set(framebuffer);
+        unsafe {
+            self.context
+                .bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        }
+
+        Ok(())
+    }
+
+    fn render_targets_flipped(&self) -> bool {
+        // Framebuffers put the origin at the bottom left.
+        true
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        // Render targets are never multisampled, but the default framebuffer can be.
+        if self.render_target.get().is_some() {
+            return 1;
+        }
+
+        unsafe { self.context.get_parameter_i32(glow::SAMPLES).max(1) as u32 }
+    }
+
+    fn create_depth_stencil(&self, (width, height): (u32, u32)) -> Result<bool, Self::Error> {
+        // The attachments of the default framebuffer belong to the window system.
+        let framebuffer = match self.render_target.get() {
+            Some(framebuffer) => framebuffer,
+            None => return Ok(false),
+        };
+
+        // A new depth and stencil attachment holds no clips.
+        self.clip_depths.borrow_mut().remove(&Some(framebuffer));
+
+        let mut depth_stencils = self.depth_stencils.borrow_mut();
+        let (renderbuffer, attached) = match depth_stencils.get(&framebuffer) {
+            Some(&entry) => entry,
+            None => {
+                let renderbuffer = unsafe { self.context.create_renderbuffer() }.gl_err()?;
+                depth_stencils.insert(framebuffer, (renderbuffer, false));
+                (renderbuffer, false)
+            }
+        };
+
+        unsafe {
+            self.context
+                .bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
+            self.context.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::DEPTH24_STENCIL8,
+                width as i32,
+                height as i32,
+            );
+            self.context.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            // Attach it to check that the framebuffer can use it, and to clear it.
+            self.context.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_STENCIL_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(renderbuffer),
+            );
+            let status = self.context.check_framebuffer_status(glow::FRAMEBUFFER);
+            if status == glow::FRAMEBUFFER_COMPLETE {
+                self.context.clear_depth_f32(1.0);
+                self.context.clear_stencil(0);
+                self.context
+                    .clear(glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+            }
+            if !attached || status != glow::FRAMEBUFFER_COMPLETE {
+                self.context.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    None,
+                );
+            }
+            gl_error(&self.context);
+
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                error!("depth and stencil attachment is incomplete: {:#x}", status);
+                depth_stencils.remove(&framebuffer);
+                self.context.delete_renderbuffer(renderbuffer);
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        let depth = self.clip_depth();
+        if !self.has_stencil() || depth >= 0xFF {
+            return Ok(false);
+        }
+
+        unsafe {
+            // Start from an empty stencil buffer.
+            if depth == 0 {
+                self.context.stencil_mask(0xFF);
+                self.context.clear_stencil(0);
+                self.context.clear(glow::STENCIL_BUFFER_BIT);
+            }
+
+            self.draw_clip(vertex_buffer, size, depth, glow::INCR)?;
+        }
+
+        self.clip_depths
+            .borrow_mut()
+            .insert(self.render_target.get(), depth + 1);
+        Ok(true)
+    }
+
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        let depth = self.clip_depth();
+        if depth == 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            self.draw_clip(vertex_buffer, size, depth, glow::DECR)?;
+        }
+
+        let mut clip_depths = self.clip_depths.borrow_mut();
+        if depth == 1 {
+            clip_depths.remove(&self.render_target.get());
+        } else {
+            clip_depths.insert(self.render_target.get(), depth - 1);
+        }
+        Ok(())
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        let framebuffer = match self.render_target.get() {
+            Some(framebuffer) => framebuffer,
+            None => return Ok(()),
+        };
+
+        if let Some((renderbuffer, attached)) =
+            self.depth_stencils.borrow_mut().get_mut(&framebuffer)
+        {
+            *attached = attach;
+            unsafe {
+                self.context.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    attach.then_some(*renderbuffer),
+                );
+                gl_error(&self.context);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn barrier(&self) -> Result<(), Self::Error> {
+        piet_hardware::GpuContext::flush(self)?;
+
+        // Reset the state that draws and uploads leave behind to the defaults of GL.
+        unsafe {
+            for unit in [glow::TEXTURE2, glow::TEXTURE1, glow::TEXTURE0] {
+                self.context.active_texture(unit);
+                self.context.bind_texture(glow::TEXTURE_2D, None);
+            }
+            self.context.disable(glow::BLEND);
+            self.context.blend_func(glow::ONE, glow::ZERO);
+            self.context.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            gl_error(&self.context);
+        }
+
+        Ok(())
+    }
+}
+
+/// A wrapper around a [`glow`] context with cached information.
+pub struct GlContext<H: HasContext + ?Sized> {
+    source: piet_hardware::Source<GpuContext<H>>,
+    text: Text,
+
+    /// The damage of the frames drawn with `damaged_render_context`.
+    damage: DamageTracker,
+}
+
+/// A builder for a [`GlContext`].
+#[derive(Debug, Clone, Default)]
+pub struct GlContextBuilder {
+    /// Options for the underlying source.
+    source: SourceBuilder,
+
+    /// Whether to use ANGLE workarounds, or `None` to detect it.
+    angle: Option<bool>,
+
+    /// Whether to use a single program for every draw.
+    uber_shader: bool,
+
+    /// How colors are written to the framebuffer.
+    alpha_mode: AlphaMode,
+
+    /// Whether the framebuffer is sRGB-encoded.
+    srgb: bool,
+}
+
+impl GlContextBuilder {
+    /// Create a new builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the options used for the underlying [`piet_hardware::Source`].
+    pub fn source(mut self, source: SourceBuilder) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Enable or disable workarounds for running on top of [ANGLE].
+    ///
+    /// ANGLE translates OpenGL ES to Direct3D, Vulkan or Metal and is stricter than most
+    /// native drivers. In this mode, shaders use high precision floats and texture border
+    /// clamping is emulated in the shader unless the driver explicitly supports it. Uploads always use RGBA
+    /// ordering with byte-aligned rows, so `GL_BGRA` is never required. By default, ANGLE is
+    /// detected using the `GL_RENDERER` string.
+    ///
+    /// [ANGLE]: https://chromium.googlesource.com/angle/angle
+    pub fn angle(mut self, angle: bool) -> Self {
+        self.angle = Some(angle);
+        self
+    }
+
+    /// Use a single "uber-shader" program for every draw.
+    ///
+    /// Normally, a separate program is compiled for each kind of draw the first time it is
+    /// used. In this mode, one program handles every kind of draw by branching on uniforms.
+    /// This avoids hitching the first time a new kind of draw is used, as well as the cost of
+    /// switching programs between draws, at the cost of slightly more work per fragment. This
+    /// tends to be a win on desktop GPUs, where uniform branching is cheap. Defaults to `false`.
+    pub fn uber_shader(mut self, uber_shader: bool) -> Self {
+        self.uber_shader = uber_shader;
+        self
+    }
+
+    /// Set how colors are written to the framebuffer.
+    ///
+    /// Defaults to [`AlphaMode::Premultiplied`].
+    pub fn alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Treat the framebuffer as sRGB-encoded.
+    ///
+    /// In this mode, colors are converted to linear space before blending and the framebuffer
+    /// encodes them back into sRGB. On desktop OpenGL, this enables `GL_FRAMEBUFFER_SRGB`
+    /// while drawing. On OpenGL ES, the encoding depends on the surface, so this should only
+    /// be enabled for sRGB surfaces. Defaults to `false`, which blends in sRGB space like
+    /// other `piet` backends.
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    /// Create a new [`GlContext`] from a [`glow`] context.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this function, and the context must be
+    /// current when the resulting type is dropped.
+    pub unsafe fn build<H: HasContext>(self, context: H) -> Result<GlContext<H>, Pierror> {
+        GlContext::with_builder(context, self)
+    }
+}
+
+impl<H: HasContext + ?Sized> GlContext<H> {
+    /// Create a new [`GlContext`] from a [`glow`] context.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling new, and the context must be current
+    /// when this type is dropped.
     pub unsafe fn new(context: H) -> Result<Self, Pierror>
+    where
+        H: Sized,
+    {
+        Self::with_builder(context, GlContextBuilder::new())
+    }
+
+    /// Create a new builder for a [`GlContext`].
+    pub fn builder() -> GlContextBuilder {
+        GlContextBuilder::new()
+    }
+
+    unsafe fn with_builder(context: H, builder: GlContextBuilder) -> Result<Self, Pierror>
     where
         H: Sized,
     {
         // Get the current version.
         let version = context.version();
 
-        // Check that the version is supported.
-        let has_supported_version = if version.is_embedded {
-            version.major >= 3
-        } else {
-            version.major >= 4 || (version.major >= 3 && version.minor >= 3)
-        };
-        if !has_supported_version {
-            return Err(Pierror::BackendError(
-                "OpenGL version 3.3 (or 3.0 ES) or higher is required".into(),
-            ));
-        }
+        // Check that the version is supported.
+        let has_supported_version = if version.is_embedded {
+            version.major >= 3
+        } else {
+            version.major >= 4 || (version.major >= 3 && version.minor >= 3)
+        };
+        if !has_supported_version {
+            return Err(Pierror::BackendError(
+                "OpenGL version 3.3 (or 3.0 ES) or higher is required".into(),
+            ));
+        }
+
+        let angle = builder.angle.unwrap_or_else(|| {
+            context
+                .get_parameter_string(glow::RENDERER)
+                .contains("ANGLE")
+        });
+
+        let mut shader_header = match (version.is_embedded, angle) {
+            (true, true) => "#version 300 es\n#define PIET_HIGHP 1",
+            (true, false) => "#version 300 es",
+            (false, _) => "#version 330 core",
+        }
+        .to_string();
+        if builder.alpha_mode == AlphaMode::Straight {
+            shader_header.push_str("\n#define PIET_STRAIGHT_ALPHA 1");
+        }
+        if builder.srgb {
+            shader_header.push_str("\n#define PIET_SRGB 1");
+        }
+
+        let robust_buffer = context
+            .supported_extensions()
+            .contains("GL_ARB_robust_buffer_access_behavior")
+            || context
+                .supported_extensions()
+                .contains("GL_KHR_robust_buffer_access_behavior");
+
+        let border_clamp = !version.is_embedded
+            || context
+                .supported_extensions()
+                .contains("GL_EXT_texture_border_clamp")
+            || context
+                .supported_extensions()
+                .contains("GL_OES_texture_border_clamp");
+
+        let external_images = version.is_embedded
+            && context
+                .supported_extensions()
+                .contains("GL_OES_EGL_image_external_essl3");
+
+        let framebuffer_srgb = !version.is_embedded;
+        let skip_srgb_decode = context
+            .supported_extensions()
+            .contains("GL_EXT_texture_sRGB_decode");
+
+        // Core profiles can't query the stencil bits of the default framebuffer, so clips only
+        // use its stencil buffer on OpenGL ES.
+        let default_stencil = version.is_embedded && context.get_parameter_i32(STENCIL_BITS) > 0;
+
+        let gpu_context = GpuContext {
+            context,
+            programs: RefCell::new(HashMap::new()),
+            shader_header,
+            external_images,
+            uber_shader: builder.uber_shader,
+            check_indices: !robust_buffer,
+            border_clamp,
+            angle,
+            alpha_mode: builder.alpha_mode,
+            srgb: builder.srgb,
+            framebuffer_srgb,
+            skip_srgb_decode,
+            color_matrix: Cell::new(piet_hardware::ColorMatrix::IDENTITY),
+            composite_mode: Cell::new(piet_hardware::CompositeMode::SourceOver),
+            clip_shape: Cell::new(None),
+            scissor: Cell::new(None),
+            projection: Cell::new(piet_hardware::Projection::IDENTITY),
+            custom_shaders: RefCell::new(Vec::new()),
+            custom_brush: Cell::new(None),
+            sampler_interpolation: Cell::new(None),
+            samplers: RefCell::new(Vec::new()),
+            render_target: Cell::new(None),
+            depth_stencils: RefCell::new(HashMap::new()),
+            default_stencil,
+            clip_depths: RefCell::new(HashMap::new()),
+            overlay_handler: RefCell::new(None),
+        };
+
+        // Compile the default program up front so errors are reported early.
+        let default_variant = if builder.uber_shader {
+            ShaderVariant::Uber
+        } else {
+            ShaderVariant::Texture2D
+        };
+        gpu_context
+            .program(default_variant)
+            .map_err(|e| Pierror::BackendError(e.into()))?;
+
+        builder.source.build(gpu_context).map(|source| GlContext {
+            text: Text(source.text().clone()),
+            source,
+            damage: DamageTracker::new(0, 0),
+        })
+    }
+
+    /// Get a reference to the underlying [`glow`] context.
+    pub fn context(&self) -> &H {
+        &self.source.context().context
+    }
+
+    /// Whether ANGLE workarounds are in use.
+    pub fn is_angle(&self) -> bool {
+        self.source.context().angle
+    }
+
+    /// How colors are written to the framebuffer.
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.source.context().alpha_mode
+    }
+
+    /// Compile every shader program that may be used for drawing.
+    ///
+    /// Shader programs are usually compiled the first time they're needed, which can cause a
+    /// hitch the first time a certain kind of draw is used. Calling this function while the
+    /// application is starting up avoids that.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this function.
+    pub unsafe fn prewarm_shaders(&self) -> Result<(), Pierror> {
+        self.prewarm_selected_shaders(&ShaderKind::ALL)
+    }
+
+    /// Compile the shader programs used for the given kinds of draws.
+    ///
+    /// Kinds of draws that aren't supported by this context are skipped. See
+    /// [`prewarm_shaders`] for more information.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this function.
+    ///
+    /// [`prewarm_shaders`]: Self::prewarm_shaders
+    pub unsafe fn prewarm_selected_shaders(&self, kinds: &[ShaderKind]) -> Result<(), Pierror> {
+        let gpu = self.source.context();
+
+        for &kind in kinds {
+            if let Some(variant) = gpu.variant_for(kind) {
+                gpu.program(variant)
+                    .map_err(|e| Pierror::BackendError(e.into()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile the code of a custom brush, for use with [`Brush::custom`].
+    ///
+    /// The code is GLSL, and is added to the end of the fragment shader. It defines
+    /// `vec4 piet_brush(vec2 position, vec4 params[2])`, which returns the premultiplied color
+    /// at a position in user space; see [`piet_hardware::BrushShaderInterface`]. Returns an
+    /// error if the code doesn't define it or fails to compile.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this function.
+    pub unsafe fn register_brush_shader(&self, source: &str) -> Result<CustomShader, Pierror> {
+        piet_hardware::ShaderInterface::CURRENT
+            .check_brush_shader(source)
+            .map_err(|e| Pierror::BackendError(e.into()))?;
+
+        let gpu = self.source.context();
+        let index = {
+            let mut shaders = gpu.custom_shaders.borrow_mut();
+            shaders.push(source.to_owned());
+            shaders.len() as u32 - 1
+        };
+
+        // Compile the program now, so that errors are reported here instead of when drawing.
+        if let Err(e) = gpu.program(ShaderVariant::Custom(index)) {
+            gpu.custom_shaders.borrow_mut().pop();
+            return Err(Pierror::BackendError(e.into()));
+        }
+
+        Ok(CustomShader::from_raw(index))
+    }
+
+    /// Whether external textures can be imported with [`import_external_texture`].
+    ///
+    /// This requires OpenGL ES 3.0 and the `GL_OES_EGL_image_external_essl3` extension.
+    ///
+    /// [`import_external_texture`]: Self::import_external_texture
+    pub fn supports_external_textures(&self) -> bool {
+        self.source.context().external_images
+    }
+
+    /// Set a callback that shows images on an overlay plane or sub-surface of the window.
+    ///
+    /// Images made with [`Image::with_overlay`] are handed to the callback when they are
+    /// drawn onto the window, for example to attach the EGL image behind the texture to a
+    /// Wayland sub-surface or a DRM plane below the window. If the callback returns `true`,
+    /// the area of the window is cleared to transparent so that the overlay shows through,
+    /// which needs a framebuffer with an alpha channel. If it returns `false`, the image is
+    /// drawn into the window as usual. The callback replaces the one that was set before.
+    pub fn on_overlay(&mut self, handler: impl FnMut(Overlay<H>) -> bool + 'static) {
+        *self.source.context().overlay_handler.borrow_mut() = Some(Box::new(handler));
+    }
+
+    /// Repack the glyphs in the text atlas to reduce fragmentation.
+    ///
+    /// See [`piet_hardware::Source::compact_atlas`] for more information.
+    pub fn compact_atlas(&mut self) {
+        self.source.compact_atlas()
+    }
+
+    /// Rasterize the glyphs of a text layout into the text atlas, and keep them there.
+    ///
+    /// See [`piet_hardware::Source::pin_text`] for more information.
+    pub fn pin_text(&mut self, layout: &TextLayout, scale: f64) -> Result<(), Pierror> {
+        self.source.pin_text(&layout.0, scale)
+    }
+
+    /// Let the glyphs of a text layout pinned with [`pin_text`](Self::pin_text) be evicted
+    /// again.
+    pub fn unpin_text(&mut self, layout: &TextLayout, scale: f64) {
+        self.source.unpin_text(&layout.0, scale)
+    }
+
+    /// Upload an image that is waiting for the upload budget right away.
+    ///
+    /// See [`piet_hardware::Source::upload_now`] for more information.
+    pub fn upload_now(&mut self, image: &Image<H>) {
+        self.source.upload_now(&image.0)
+    }
+
+    /// Drop the blurred rectangles that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_shadow_cache`] for more information.
+    pub fn clear_shadow_cache(&mut self) {
+        self.source.clear_shadow_cache()
+    }
+
+    /// Drop the color ramps of linear gradients that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_gradient_cache`] for more information.
+    pub fn clear_gradient_cache(&mut self) {
+        self.source.clear_gradient_cache()
+    }
+
+    /// Start a new frame of occlusion queries.
+    ///
+    /// See [`piet_hardware::Source::begin_occlusion_frame`] for more information.
+    pub fn begin_occlusion_frame(&mut self) {
+        self.source.begin_occlusion_frame()
+    }
+
+    /// Drop the tessellations of fills that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_tessellation_cache`] for more information.
+    pub fn clear_tessellation_cache(&mut self) {
+        self.source.clear_tessellation_cache()
+    }
+
+    /// Get the strategy that the edges of shapes are antialiased with.
+    ///
+    /// See [`piet_hardware::Source::antialiasing`] for more information.
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.source.antialiasing()
+    }
+
+    /// Get how much GPU memory the resources of this context take up, by kind of resource.
+    ///
+    /// See [`piet_hardware::Source::memory_usage`] for more information.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.source.memory_usage()
+    }
+
+    /// Start measuring the peaks of [`memory_usage`](Self::memory_usage) again.
+    ///
+    /// See [`piet_hardware::Source::reset_peak_memory_usage`] for more information.
+    pub fn reset_peak_memory_usage(&mut self) {
+        self.source.reset_peak_memory_usage()
+    }
+
+    /// Limit how many bytes of images are uploaded to the GPU every frame.
+    ///
+    /// See [`piet_hardware::Source::set_upload_budget`] for more information.
+    pub fn set_upload_budget(&mut self, budget: Option<usize>) {
+        self.source.set_upload_budget(budget)
+    }
+
+    /// Set what is drawn instead of images that are waiting to be uploaded, and glyphs that
+    /// failed to rasterize.
+    ///
+    /// See [`piet_hardware::Source::set_placeholder`] for more information.
+    pub fn set_placeholder(&mut self, placeholder: impl Into<Placeholder>) {
+        self.source.set_placeholder(placeholder)
+    }
+
+    /// Set a callback that is told every time a resource is drawn as the placeholder.
+    ///
+    /// See [`piet_hardware::Source::on_missing_resource`] for more information.
+    pub fn on_missing_resource(&mut self, callback: impl FnMut(MissingResource<'_>) + 'static) {
+        self.source.on_missing_resource(callback)
+    }
+
+    /// Whether any images are waiting for a later frame to be uploaded.
+    ///
+    /// See [`piet_hardware::Source::has_pending_uploads`] for more information.
+    pub fn has_pending_uploads(&self) -> bool {
+        self.source.has_pending_uploads()
+    }
+
+    /// Free the buffer that pixels are converted in before they are uploaded.
+    ///
+    /// See [`piet_hardware::Source::release_staging_buffer`] for more information.
+    pub fn release_staging_buffer(&mut self) {
+        self.source.release_staging_buffer()
+    }
+
+    /// Resize the target that this context renders to, such as when the window is resized.
+    ///
+    /// See [`piet_hardware::Source::resize`] for more information.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Pierror> {
+        self.source.resize(width, height)
+    }
+
+    /// Wrap an external texture as an [`Image`].
+    ///
+    /// External textures are bound to `GL_TEXTURE_EXTERNAL_OES` and are usually created by
+    /// binding an `EGLImage` with `glEGLImageTargetTexture2DOES`. They are commonly produced
+    /// by video decoders, cameras and Wayland `dmabuf`s. The resulting image is drawn without
+    /// copying the texture data; however, it cannot be written to.
+    ///
+    /// The image takes ownership of the texture and deletes it when it is dropped.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current, and `texture` must be a valid texture that has been
+    /// bound to the `GL_TEXTURE_EXTERNAL_OES` target and has the given size.
+    pub unsafe fn import_external_texture(
+        &self,
+        texture: H::Texture,
+        width: u32,
+        height: u32,
+    ) -> Result<Image<H>, Pierror> {
+        let gpu = self.source.context();
+        if !gpu.external_images {
+            return Err(Pierror::NotSupported);
+        }
+
+        // External textures only support linear or nearest filtering and edge clamping.
+        gpu.context
+            .bind_texture(TEXTURE_EXTERNAL_OES, Some(texture));
+        let _guard = CallOnDrop(|| {
+            gpu.context.bind_texture(TEXTURE_EXTERNAL_OES, None);
+        });
+
+        for (param, value) in [
+            (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+            (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+        ] {
+            gpu.context
+                .tex_parameter_i32(TEXTURE_EXTERNAL_OES, param, value as i32);
+        }
+
+        gl_error(&gpu.context);
+
+        let texture = GlTexture {
+            texture,
+            target: TEXTURE_EXTERNAL_OES,
+            framebuffer: None,
+            interpolation: Cell::new(None),
+            repeat: None,
+            mipmapped: Cell::new(false),
+            coverage: Cell::new(false),
+        };
+        Ok(Image(self.source.image_from_raw(
+            texture,
+            kurbo::Size::new(width as f64, height as f64),
+        )))
+    }
+
+    /// Get a render context.
+    ///
+    /// The render context draws to the framebuffer that is currently bound, which is
+    /// `width` by `height` pixels. To draw to several windows, make each one's surface current
+    /// in turn; this requires the windows to share a single OpenGL context.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method, as well as any of the
+    /// [`piet::RenderContext`] methods.
+    pub unsafe fn render_context(&mut self, width: u32, height: u32) -> RenderContext<'_, H> {
+        RenderContext {
+            context: self.source.render_context(width, height),
+            text: &mut self.text,
+        }
+    }
+
+    /// Get a render context that only redraws the areas of a window that changed.
+    ///
+    /// Mark the areas that changed since the last frame with
+    /// [`damage_tracker_mut`](Self::damage_tracker_mut) first. `buffer_age` is the age of the
+    /// back buffer from `EGL_EXT_buffer_age`, or zero if it isn't known. The render context
+    /// is clipped to the area that has to be drawn again, which is returned along with it.
+    /// Since [`clear`](piet::RenderContext::clear) ignores the clip, clear that area instead
+    /// of the whole window. After finishing the frame, swap the buffers with the rectangle
+    /// from [`finish_damaged_frame`](Self::finish_damaged_frame).
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method, as well as any of the
+    /// [`piet::RenderContext`] methods.
+    pub unsafe fn damaged_render_context(
+        &mut self,
+        width: u32,
+        height: u32,
+        buffer_age: u32,
+    ) -> (RenderContext<'_, H>, kurbo::Rect) {
+        self.damage.resize(width, height);
+        let region = self.damage.repaint_region(buffer_age);
+
+        let mut context = self.source.render_context(width, height);
+        piet::RenderContext::clip(&mut context, region);
+        let rc = RenderContext {
+            context,
+            text: &mut self.text,
+        };
+        (rc, region)
+    }
+
+    /// Get the tracker for the damage of frames drawn with
+    /// [`damaged_render_context`](Self::damaged_render_context).
+    pub fn damage_tracker(&self) -> &DamageTracker {
+        &self.damage
+    }
+
+    /// Get a mutable reference to the tracker for the damage of frames drawn with
+    /// [`damaged_render_context`](Self::damaged_render_context), to mark areas as changed.
+    pub fn damage_tracker_mut(&mut self) -> &mut DamageTracker {
+        &mut self.damage
+    }
+
+    /// Finish a frame drawn with [`damaged_render_context`](Self::damaged_render_context).
+    ///
+    /// Returns the damage of the frame in the form that `eglSwapBuffersWithDamage` takes, or
+    /// `None` if nothing changed and the buffers don't have to be swapped.
+    pub fn finish_damaged_frame(&mut self) -> Option<[i32; 4]> {
+        let rect = self.damage.swap_rect();
+        self.damage.end_frame();
+        rect
+    }
+
+    /// Get a render context that draws one tile of a larger frame.
+    ///
+    /// The framebuffer that is currently bound must be `tile.width` by `tile.height` pixels.
+    /// See [`piet_hardware::Source::tile_context`] for more information.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method, as well as any of the
+    /// [`piet::RenderContext`] methods.
+    pub unsafe fn tile_context(&mut self, tile: piet_hardware::Tile) -> RenderContext<'_, H> {
+        RenderContext {
+            context: self.source.tile_context(tile),
+            text: &mut self.text,
+        }
+    }
+
+    /// Render a scene into an offscreen framebuffer and read it back as a thumbnail.
+    ///
+    /// See [`piet_hardware::Source::render_thumbnail`] for more information. The default
+    /// framebuffer is bound again afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method.
+    pub unsafe fn render_thumbnail(
+        &mut self,
+        scene_size: kurbo::Size,
+        max_size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, H>) -> Result<(), Pierror>,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        let text = &mut self.text;
+        self.source
+            .render_thumbnail(scene_size, max_size, |context| {
+                draw(RenderContext { context, text })
+            })
+    }
+
+    /// Render a scene into an offscreen framebuffer and keep only its coverage as an image.
+    ///
+    /// See [`piet_hardware::Source::render_coverage`] for more information. The default
+    /// framebuffer is bound again afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method.
+    pub unsafe fn render_coverage(
+        &mut self,
+        size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, H>) -> Result<(), Pierror>,
+    ) -> Result<Image<H>, Pierror> {
+        let text = &mut self.text;
+        self.source
+            .render_coverage(size, |context| draw(RenderContext { context, text }))
+            .map(Image)
+    }
+
+    /// Draw a series of test scenes and check the pixels that are read back.
+    ///
+    /// See [`piet_hardware::conformance::run`] for more information. The default framebuffer
+    /// is bound again afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The context must be current while calling this method.
+    pub unsafe fn check_conformance(
+        &mut self,
+    ) -> Result<piet_hardware::conformance::Report, Pierror> {
+        piet_hardware::conformance::run(&mut self.source)
+    }
+}
+
+/// The whole point.
+pub struct RenderContext<'a, H: HasContext + ?Sized> {
+    context: piet_hardware::RenderContext<'a, GpuContext<H>>,
+    text: &'a mut Text,
+}
+
+impl<H: HasContext + ?Sized> RenderContext<'_, H> {
+    /// Get the errors that have occurred since the last call to `status` or `take_errors`.
+    ///
+    /// See [`piet_hardware::RenderContext::errors`] for more information.
+    pub fn errors(&self) -> impl Iterator<Item = &piet_hardware::DrawError> + '_ {
+        self.context.errors()
+    }
+
+    /// Take the errors that have occurred since the last call to `status` or `take_errors`.
+    pub fn take_errors(&mut self) -> Vec<piet_hardware::DrawError> {
+        self.context.take_errors()
+    }
+
+    /// Get the opacity that everything drawn is multiplied by.
+    pub fn global_alpha(&self) -> f64 {
+        self.context.global_alpha()
+    }
+
+    /// Set the opacity that everything drawn is multiplied by.
+    ///
+    /// See [`piet_hardware::RenderContext::set_global_alpha`] for more information.
+    pub fn set_global_alpha(&mut self, alpha: f64) {
+        self.context.set_global_alpha(alpha)
+    }
+
+    /// Get the tolerance that curves are flattened with, in pixels of the target.
+    pub fn tolerance(&self) -> f64 {
+        self.context.tolerance()
+    }
+
+    /// Set the tolerance that curves are flattened with, in pixels of the target.
+    ///
+    /// See [`piet_hardware::RenderContext::set_tolerance`] for more information.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.context.set_tolerance(tolerance)
+    }
+
+    /// Whether stroke widths are measured in device pixels instead of user space.
+    pub fn non_scaling_stroke(&self) -> bool {
+        self.context.non_scaling_stroke()
+    }
+
+    /// Set whether stroke widths are measured in device pixels instead of user space.
+    ///
+    /// See [`piet_hardware::RenderContext::set_non_scaling_stroke`] for more information.
+    pub fn set_non_scaling_stroke(&mut self, enabled: bool) {
+        self.context.set_non_scaling_stroke(enabled)
+    }
+
+    /// Read back an area of the target into a CPU-side image with the given format.
+    ///
+    /// See [`piet_hardware::RenderContext::capture_image_buf`] for more information.
+    pub fn capture_image_buf(
+        &mut self,
+        src_rect: impl Into<kurbo::Rect>,
+        format: piet::ImageFormat,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        self.context.capture_image_buf(src_rect, format)
+    }
+
+    /// Start recording the shapes that are drawn into a [`Scene`] instead of drawing them.
+    ///
+    /// See [`piet_hardware::RenderContext::begin_scene`] for more information.
+    pub fn begin_scene(&mut self) -> Result<(), Pierror> {
+        self.context.begin_scene()
+    }
+
+    /// Start recording a [`Scene`] that stays accurate when it is drawn scaled up by `detail`.
+    ///
+    /// See [`piet_hardware::RenderContext::begin_scene_with_detail`] for more information.
+    pub fn begin_scene_with_detail(&mut self, detail: f64) -> Result<(), Pierror> {
+        self.context.begin_scene_with_detail(detail)
+    }
+
+    /// Finish recording a [`Scene`].
+    ///
+    /// See [`piet_hardware::RenderContext::end_scene`] for more information.
+    pub fn end_scene(&mut self) -> Result<Scene<H>, Pierror> {
+        self.context.end_scene().map(Scene)
+    }
+
+    /// Draw a [`Scene`] with the current transform, clip and global alpha.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_scene`] for more information.
+    pub fn draw_scene(&mut self, scene: &Scene<H>) -> Result<(), Pierror> {
+        self.context.draw_scene(&scene.0)
+    }
+
+    /// Draw the level of a [`SceneLevels`] that fits the current transform best.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_scene_levels`] for more information.
+    pub fn draw_scene_levels(&mut self, levels: &SceneLevels<H>) -> Result<(), Pierror> {
+        self.context.draw_scene_levels(&levels.0)
+    }
+
+    /// Draw every layer of a [`LayerTree`] with the current transform, clip and global alpha.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_layers`] for more information.
+    pub fn draw_layers(&mut self, tree: &LayerTree<H>) -> Result<(), Pierror> {
+        self.context.draw_layers(&tree.0)
+    }
 
-        let shader_header = if version.is_embedded {
-            "#version 300 es"
-        } else {
-            "#version 330 core"
-        };
+    /// Save the state, and draw into an offscreen layer that is composited back with the
+    /// opacity `alpha` when it is restored.
+    ///
+    /// See [`piet_hardware::RenderContext::save_layer`] for more information.
+    pub fn save_layer(&mut self, bounds: piet::kurbo::Rect, alpha: f64) -> Result<(), Pierror> {
+        self.context.save_layer(bounds, alpha)
+    }
 
-        let format_shader = |shader| format!("{shader_header}\n{shader}");
-
-        // Create a program to use for text rendering.
-        let program = compile_program(
-            &context,
-            &format_shader(VERTEX_SHADER),
-            &format_shader(FRAGMENT_SHADER),
-        )
-        .map_err(|e| Pierror::BackendError(e.into()))?;
-
-        // Get the uniform locations.
-        let uniforms = UNIFORMS
-            .iter()
-            .map(|uniform| {
-                context
-                    .get_uniform_location(program, uniform.as_name())
-                    .ok_or_else(|| {
-                        Pierror::BackendError(
-                            format!("failed to get uniform location for {}", uniform.as_name())
-                                .into(),
-                        )
-                    })
-            })
-            .collect::<Result<Box<[_]>, _>>()?;
+    /// Get the Porter-Duff operator that drawing is combined with the target with.
+    pub fn composite_mode(&self) -> CompositeMode {
+        self.context.composite_mode()
+    }
 
-        let robust_buffer = context
-            .supported_extensions()
-            .contains("GL_ARB_robust_buffer_access_behavior")
-            || context
-                .supported_extensions()
-                .contains("GL_KHR_robust_buffer_access_behavior");
+    /// Set the Porter-Duff operator that drawing is combined with the target with.
+    ///
+    /// See [`piet_hardware::RenderContext::set_composite_mode`] for more information.
+    pub fn set_composite_mode(&mut self, mode: CompositeMode) -> Result<(), Pierror> {
+        self.context.set_composite_mode(mode)
+    }
 
-        piet_hardware::Source::new(GpuContext {
-            context,
-            uniforms,
-            check_indices: !robust_buffer,
-            render_program: program,
-        })
-        .map(|source| GlContext {
-            text: Text(source.text().clone()),
-            source,
-        })
+    /// Draw with the Porter-Duff operator `mode` inside of `f`, and go back to the previous
+    /// one afterwards.
+    ///
+    /// See [`piet_hardware::RenderContext::set_composite_mode`] for more information.
+    pub fn with_composite_mode<R>(
+        &mut self,
+        mode: CompositeMode,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> Result<R, Pierror> {
+        let previous = self.composite_mode();
+        self.set_composite_mode(mode)?;
+
+        let result = f(self);
+        self.set_composite_mode(previous)?;
+        Ok(result)
     }
 
-    /// Get a reference to the underlying [`glow`] context.
-    pub fn context(&self) -> &H {
-        &self.source.context().context
+    /// Submit the drawing so far to the GPU without finishing the frame.
+    ///
+    /// See [`piet_hardware::RenderContext::flush_pending`] for more information.
+    pub fn flush_pending(&mut self) -> Result<(), Pierror> {
+        self.context.flush_pending()
     }
 
-    /// Get a render context.
+    /// Submit the drawing so far, so that the application can issue its own GPU commands.
     ///
-    /// # Safety
+    /// See [`piet_hardware::RenderContext::barrier`] for more information.
+    pub fn barrier(&mut self) -> Result<(), Pierror> {
+        self.context.barrier()
+    }
+
+    /// Create a brush from a gradient that continues past its ends with `spread`.
     ///
-    /// The context must be current while calling this method, as well as any of the
-    /// [`piet::RenderContext`] methods.
-    pub unsafe fn render_context(&mut self, width: u32, height: u32) -> RenderContext<'_, H> {
-        RenderContext {
-            context: self.source.render_context(width, height),
-            text: &mut self.text,
-        }
+    /// See [`piet_hardware::RenderContext::gradient_with_spread`] for more information.
+    pub fn gradient_with_spread(
+        &mut self,
+        gradient: impl Into<piet::FixedGradient>,
+        spread: SpreadMode,
+    ) -> Result<Brush<H>, Pierror> {
+        self.context
+            .gradient_with_spread(gradient, spread)
+            .map(Brush)
     }
-}
 
-/// The whole point.
-pub struct RenderContext<'a, H: HasContext + ?Sized> {
-    context: piet_hardware::RenderContext<'a, GpuContext<H>>,
-    text: &'a mut Text,
+    /// Create a brush that fills shapes with a sweep gradient, like a color wheel.
+    ///
+    /// See [`piet_hardware::RenderContext::sweep_gradient`] for more information.
+    pub fn sweep_gradient(&mut self, gradient: &SweepGradient) -> Result<Brush<H>, Pierror> {
+        self.context.sweep_gradient(gradient).map(Brush)
+    }
+
+    /// Draw a blurred rectangle with rounded corners, like the shadow of a card.
+    ///
+    /// See [`piet_hardware::RenderContext::blurred_rounded_rect`] for more information.
+    pub fn blurred_rounded_rect(
+        &mut self,
+        rect: impl Into<kurbo::Rect>,
+        corner_radius: f64,
+        blur_radius: f64,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let rect = rect.into();
+        let brush = brush.make_brush(self, || rect);
+        self.context
+            .blurred_rounded_rect(rect, corner_radius, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Draw a text layout with a soft shadow below it, like a subtitle.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_text_with_shadow`] for more information.
+    pub fn draw_text_with_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<kurbo::Point>,
+        offset: impl Into<kurbo::Vec2>,
+        blur_radius: f64,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let offset = offset.into();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2() + offset;
+        let brush = brush.make_brush(self, || bounds);
+        self.context
+            .draw_text_with_shadow(&layout.0, pos, offset, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Draw only the soft shadow of a text layout at `pos`.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_text_shadow`] for more information.
+    pub fn draw_text_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<kurbo::Point>,
+        blur_radius: f64,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2();
+        let brush = brush.make_brush(self, || bounds);
+        self.context
+            .draw_text_shadow(&layout.0, pos, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Tessellate a filled shape into a [`Marker`], measured in device pixels.
+    ///
+    /// See [`piet_hardware::RenderContext::fill_marker`] for more information.
+    pub fn fill_marker(&mut self, shape: impl kurbo::Shape) -> Result<Marker, Pierror> {
+        self.context.fill_marker(shape)
+    }
+
+    /// Tessellate the stroke of a shape into a [`Marker`], measured in device pixels.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_marker`] for more information.
+    pub fn stroke_marker(
+        &mut self,
+        shape: impl kurbo::Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<Marker, Pierror> {
+        self.context.stroke_marker(shape, width, style)
+    }
+
+    /// Tessellate the fill of a shape into a [`CachedPath`] that is kept on the GPU.
+    ///
+    /// See [`piet_hardware::RenderContext::make_cached_path`] for more information.
+    pub fn make_cached_path(&mut self, shape: impl kurbo::Shape) -> Result<CachedPath<H>, Pierror> {
+        self.context.make_cached_path(shape).map(CachedPath)
+    }
+
+    /// Fill a [`CachedPath`] with the non-zero fill rule, without tessellating it again.
+    ///
+    /// See [`piet_hardware::RenderContext::fill_cached`] for more information.
+    pub fn fill_cached(
+        &mut self,
+        path: &CachedPath<H>,
+        brush: &impl IntoBrush<Self>,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        self.context.fill_cached(&path.0, &brush.as_ref().0)
+    }
+
+    /// Stroke a [`CachedPath`], only tessellating the stroke if it was last drawn with another
+    /// width or style.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_cached`] for more information.
+    pub fn stroke_cached(
+        &mut self,
+        path: &CachedPath<H>,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        self.context
+            .stroke_cached(&path.0, &brush.as_ref().0, width, style)
+    }
+
+    /// Make an occlusion query for an area, and get whether it was hidden in the last frame.
+    ///
+    /// See [`piet_hardware::RenderContext::occlusion_query`] for more information.
+    pub fn occlusion_query(&mut self, id: u64, bounds: piet::kurbo::Rect) -> bool {
+        self.context.occlusion_query(id, bounds)
+    }
+
+    /// Create a [`HitTester`] that flattens shapes like this context does.
+    ///
+    /// See [`piet_hardware::RenderContext::hit_tester`] for more information.
+    pub fn hit_tester(&self) -> HitTester {
+        self.context.hit_tester()
+    }
+
+    /// Decode an image and upload it.
+    ///
+    /// See [`piet_hardware::RenderContext::make_image_from_encoded`] for more information.
+    #[cfg(feature = "loader")]
+    pub fn make_image_from_encoded(&mut self, bytes: &[u8]) -> Result<Image<H>, Pierror> {
+        self.context.make_image_from_encoded(bytes).map(Image)
+    }
+
+    /// Get the outline of a stroke as a path, in user space.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_outline`] for more information.
+    pub fn stroke_outline(
+        &mut self,
+        shape: impl kurbo::Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<kurbo::BezPath, Pierror> {
+        self.context.stroke_outline(shape, width, style)
+    }
+
+    /// Stamp a [`Marker`] at every placement with one draw call.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_markers`] for more information.
+    pub fn draw_markers<P: Into<MarkerPlacement>>(
+        &mut self,
+        marker: &Marker,
+        brush: &impl IntoBrush<Self>,
+        placements: impl IntoIterator<Item = P>,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || marker.bounds());
+        self.context
+            .draw_markers(marker, &brush.as_ref().0, placements)
+    }
 }
 
 impl<H: HasContext + ?Sized> piet::RenderContext for RenderContext<'_, H> {
@@ -802,6 +2844,30 @@ impl<H: HasContext + ?Sized> piet::RenderContext for RenderContext<'_, H> {
 /// The brush type.
 pub struct Brush<H: HasContext + ?Sized>(piet_hardware::Brush<GpuContext<H>>);
 
+impl<H: HasContext + ?Sized> Brush<H> {
+    /// Get a copy of this brush with its opacity multiplied by `alpha`.
+    pub fn with_alpha(&self, alpha: f64) -> Self {
+        Self(self.0.with_alpha(alpha))
+    }
+
+    /// Get a copy of this brush with its color multiplied by `color`.
+    pub fn with_tint(&self, color: piet::Color) -> Self {
+        Self(self.0.with_tint(color))
+    }
+
+    /// Create a brush that fills shapes with an image, stretched over `bounds`.
+    pub fn image(image: &Image<H>, bounds: kurbo::Rect) -> Self {
+        Self(piet_hardware::Brush::image(&image.0, bounds))
+    }
+
+    /// Create a brush that fills shapes with the color computed by a custom shader.
+    ///
+    /// The shader is created by [`GlContext::register_brush_shader`].
+    pub fn custom(shader: CustomShader, params: [f32; 8]) -> Self {
+        Self(piet_hardware::Brush::custom(shader, params))
+    }
+}
+
 impl<H: HasContext + ?Sized> Clone for Brush<H> {
     fn clone(&self) -> Self {
         Brush(self.0.clone())
@@ -818,6 +2884,195 @@ impl<H: HasContext + ?Sized> IntoBrush<RenderContext<'_, H>> for Brush<H> {
     }
 }
 
+/// A path whose triangles are tessellated once and kept on the GPU.
+///
+/// See [`piet_hardware::CachedPath`] for more information.
+pub struct CachedPath<H: HasContext + ?Sized>(piet_hardware::CachedPath<GpuContext<H>>);
+
+impl<H: HasContext + ?Sized> CachedPath<H> {
+    /// Get the path, in user space.
+    pub fn path(&self) -> &piet::kurbo::BezPath {
+        self.0.path()
+    }
+
+    /// Get the bounding box of the path, in user space.
+    pub fn bounds(&self) -> piet::kurbo::Rect {
+        self.0.bounds()
+    }
+}
+
+/// Geometry that is kept on the GPU and drawn again with different transforms.
+///
+/// See [`piet_hardware::Scene`] for more information.
+pub struct Scene<H: HasContext + ?Sized>(piet_hardware::Scene<GpuContext<H>>);
+
+impl<H: HasContext + ?Sized> Scene<H> {
+    /// Whether nothing was drawn into this scene.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The scale that this scene was recorded to be drawn at.
+    pub fn detail(&self) -> f64 {
+        self.0.detail()
+    }
+}
+
+/// Recordings of the same geometry at several levels of detail.
+///
+/// See [`piet_hardware::SceneLevels`] for more information.
+pub struct SceneLevels<H: HasContext + ?Sized>(piet_hardware::SceneLevels<GpuContext<H>>);
+
+impl<H: HasContext + ?Sized> Default for SceneLevels<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: HasContext + ?Sized> SceneLevels<H> {
+    /// Create a new set of levels without any levels.
+    pub fn new() -> Self {
+        Self(piet_hardware::SceneLevels::new())
+    }
+
+    /// Get the number of levels.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no levels.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the details of the levels, from the least to the most detailed.
+    pub fn details(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.details()
+    }
+
+    /// Get the level of detail that geometry drawn with `transform` should be recorded at.
+    pub fn detail_for(transform: piet::kurbo::Affine) -> f64 {
+        piet_hardware::SceneLevels::<GpuContext<H>>::detail_for(transform)
+    }
+
+    /// Get the level of detail to record for drawing with `transform`, if there is no level
+    /// for it yet.
+    pub fn missing_detail(&self, transform: piet::kurbo::Affine) -> Option<f64> {
+        self.0.missing_detail(transform)
+    }
+
+    /// Add a level, returning the level with the same detail that it replaces.
+    pub fn insert(&mut self, scene: Scene<H>) -> Option<Scene<H>> {
+        self.0.insert(scene.0).map(Scene)
+    }
+
+    /// Remove the level with the given detail.
+    pub fn remove(&mut self, detail: f64) -> Option<Scene<H>> {
+        self.0.remove(detail).map(Scene)
+    }
+
+    /// Remove every level.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// Remove the levels that are more than `distance` powers of two away from the level for
+    /// drawing with `transform`.
+    pub fn prune(&mut self, transform: piet::kurbo::Affine, distance: u32) {
+        self.0.prune(transform, distance)
+    }
+}
+
+/// A tree of layers that are only recorded again when they change.
+///
+/// See [`piet_hardware::LayerTree`] for more information.
+pub struct LayerTree<H: HasContext + ?Sized>(piet_hardware::LayerTree<GpuContext<H>>);
+
+impl<H: HasContext + ?Sized> Default for LayerTree<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: HasContext + ?Sized> LayerTree<H> {
+    /// Create a new, empty layer tree.
+    pub fn new() -> Self {
+        Self(piet_hardware::LayerTree::new())
+    }
+
+    /// Get the number of layers in the tree.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no layers in the tree.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add an empty, invalid layer on top of the children of `parent`, or on top of the
+    /// tree if `parent` is `None`.
+    pub fn add_layer(&mut self, parent: Option<LayerId>) -> Result<LayerId, Pierror> {
+        self.0.add_layer(parent)
+    }
+
+    /// Remove a layer and all of its children.
+    pub fn remove_layer(&mut self, id: LayerId) -> Result<(), Pierror> {
+        self.0.remove_layer(id)
+    }
+
+    /// Whether the layer with this ID is in the tree.
+    pub fn contains(&self, id: LayerId) -> bool {
+        self.0.contains(id)
+    }
+
+    /// Get the transform from a layer's coordinates to its parent's.
+    pub fn transform(&self, id: LayerId) -> Result<piet::kurbo::Affine, Pierror> {
+        self.0.transform(id)
+    }
+
+    /// Set the transform from a layer's coordinates to its parent's.
+    pub fn set_transform(
+        &mut self,
+        id: LayerId,
+        transform: piet::kurbo::Affine,
+    ) -> Result<(), Pierror> {
+        self.0.set_transform(id, transform)
+    }
+
+    /// Get the transform in three dimensions that a layer is composited with.
+    pub fn transform_3d(&self, id: LayerId) -> Result<Transform3d, Pierror> {
+        self.0.transform_3d(id)
+    }
+
+    /// Set the transform in three dimensions that a layer is composited with.
+    ///
+    /// See [`piet_hardware::LayerTree::set_transform_3d`] for more information.
+    pub fn set_transform_3d(&mut self, id: LayerId, transform: Transform3d) -> Result<(), Pierror> {
+        self.0.set_transform_3d(id, transform)
+    }
+
+    /// Mark the contents of a layer as changed, so it has to be recorded again.
+    pub fn invalidate(&mut self, id: LayerId) -> Result<(), Pierror> {
+        self.0.invalidate(id)
+    }
+
+    /// Whether the contents of a layer have to be recorded again.
+    pub fn is_invalid(&self, id: LayerId) -> Result<bool, Pierror> {
+        self.0.is_invalid(id)
+    }
+
+    /// Iterate over the layers that have to be recorded again.
+    pub fn invalid_layers(&self) -> impl Iterator<Item = LayerId> + '_ {
+        self.0.invalid_layers()
+    }
+
+    /// Set the recorded contents of a layer, which makes it valid again.
+    pub fn set_scene(&mut self, id: LayerId, scene: Scene<H>) -> Result<Option<Scene<H>>, Pierror> {
+        self.0.set_scene(id, scene.0).map(|old| old.map(Scene))
+    }
+}
+
 /// The image type.
 pub struct Image<H: HasContext + ?Sized>(piet_hardware::Image<GpuContext<H>>);
 
@@ -827,6 +3082,32 @@ impl<H: HasContext + ?Sized> Clone for Image<H> {
     }
 }
 
+impl<H: HasContext + ?Sized> Image<H> {
+    /// Create a view of the `rect` portion of this image that shares its texture.
+    pub fn sub_image(&self, rect: kurbo::Rect) -> Self {
+        Self(self.0.sub_image(rect))
+    }
+
+    /// Get a copy of this image that is sampled with `interpolation` when it is used as a
+    /// brush.
+    pub fn with_interpolation(&self, interpolation: piet::InterpolationMode) -> Self {
+        Self(self.0.with_interpolation(interpolation))
+    }
+
+    /// Whether drawing this image tries to show it on an overlay of the window.
+    pub fn overlay(&self) -> bool {
+        self.0.overlay()
+    }
+
+    /// Get a copy of this image that is shown on an overlay of the window when it is drawn,
+    /// if an [overlay callback](GlContext::on_overlay) accepts it.
+    ///
+    /// See [`piet_hardware::Image::with_overlay`] for more information.
+    pub fn with_overlay(&self, overlay: bool) -> Self {
+        Self(self.0.with_overlay(overlay))
+    }
+}
+
 impl<H: HasContext + ?Sized> piet::Image for Image<H> {
     fn size(&self) -> kurbo::Size {
         self.0.size()
@@ -837,6 +3118,20 @@ impl<H: HasContext + ?Sized> piet::Image for Image<H> {
 #[derive(Clone)]
 pub struct TextLayout(piet_hardware::TextLayout);
 
+impl TextLayout {
+    /// The index in the original text where it was truncated, if it was truncated.
+    pub fn truncated_at(&self) -> Option<usize> {
+        self.0.truncated_at()
+    }
+
+    /// Get the underlying [`Buffer`] that holds the laid out text.
+    ///
+    /// [`Buffer`]: piet_hardware::cosmic_text::Buffer
+    pub fn buffer(&self) -> &piet_hardware::cosmic_text::Buffer {
+        self.0.buffer()
+    }
+}
+
 impl piet::TextLayout for TextLayout {
     fn size(&self) -> kurbo::Size {
         self.0.size()
@@ -878,6 +3173,23 @@ impl piet::TextLayout for TextLayout {
 /// The text layout builder type.
 pub struct TextLayoutBuilder(piet_hardware::TextLayoutBuilder);
 
+impl TextLayoutBuilder {
+    /// Place tab stops at every multiple of `width`, measured from the start of the line.
+    pub fn tab_width(self, width: f64) -> Self {
+        Self(self.0.tab_width(width))
+    }
+
+    /// Limit the layout to the given number of lines.
+    pub fn max_lines(self, max_lines: usize) -> Self {
+        Self(self.0.max_lines(max_lines))
+    }
+
+    /// Set how the text is truncated when it has too many lines.
+    pub fn truncation(self, truncation: piet_hardware::Truncation) -> Self {
+        Self(self.0.truncation(truncation))
+    }
+}
+
 impl piet::TextLayoutBuilder for TextLayoutBuilder {
     type Out = TextLayout;
 
@@ -910,6 +3222,18 @@ impl piet::TextLayoutBuilder for TextLayoutBuilder {
 #[derive(Clone)]
 pub struct Text(piet_hardware::Text);
 
+impl Text {
+    /// Run a function with read access to the [`FontSystem`] used for text layout.
+    ///
+    /// [`FontSystem`]: piet_hardware::cosmic_text::FontSystem
+    pub fn with_font_system<R>(
+        &self,
+        f: impl FnOnce(&piet_hardware::cosmic_text::FontSystem) -> R,
+    ) -> R {
+        self.0.with_font_system(f)
+    }
+}
+
 impl piet::Text for Text {
     type TextLayoutBuilder = TextLayoutBuilder;
     type TextLayout = TextLayout;
@@ -941,6 +3265,9 @@ fn compile_program<H: HasContext + ?Sized>(
 
         context.attach_shader(program, vertex_shader);
         context.attach_shader(program, fragment_shader);
+        for (name, location) in ATTRIBUTES {
+            context.bind_attrib_location(program, location, name);
+        }
         let _unlink_shaders = CallOnDrop(|| {
             context.detach_shader(program, vertex_shader);
             context.detach_shader(program, fragment_shader);
@@ -979,6 +3306,34 @@ unsafe fn compile_shader<H: HasContext + ?Sized>(
     Ok(shader)
 }
 
+/// Get the minification and magnification filters that sample with `interpolation`.
+/// Expand coverage to premultiplied white, since WebGL can't swizzle textures.
+#[cfg(target_arch = "wasm32")]
+fn expand_coverage(data: &[u8]) -> Vec<u8> {
+    data.iter().flat_map(|&coverage| [coverage; 4]).collect()
+}
+
+fn filters(interpolation: piet::InterpolationMode, mipmapped: bool) -> (u32, u32) {
+    match (interpolation, mipmapped) {
+        (piet::InterpolationMode::NearestNeighbor, _) => (glow::NEAREST, glow::NEAREST),
+        (piet::InterpolationMode::Bilinear, false) => (glow::LINEAR, glow::LINEAR),
+        (piet::InterpolationMode::Bilinear, true) => (glow::LINEAR_MIPMAP_LINEAR, glow::LINEAR),
+    }
+}
+
+fn blend_factor(factor: piet_hardware::BlendFactor) -> u32 {
+    use piet_hardware::BlendFactor;
+
+    match factor {
+        BlendFactor::Zero => glow::ZERO,
+        BlendFactor::SourceAlpha => glow::SRC_ALPHA,
+        BlendFactor::OneMinusSourceAlpha => glow::ONE_MINUS_SRC_ALPHA,
+        BlendFactor::DestinationAlpha => glow::DST_ALPHA,
+        BlendFactor::OneMinusDestinationAlpha => glow::ONE_MINUS_DST_ALPHA,
+        _ => glow::ONE,
+    }
+}
+
 fn gl_error(h: &(impl HasContext + ?Sized)) {
     let err = unsafe { h.get_error() };
 
@@ -995,7 +3350,16 @@ fn gl_error(h: &(impl HasContext + ?Sized)) {
             _ => "Unknown GL error",
         };
 
-        tracing::error!("GL error: {}", error_str)
+        error!("GL error: {}", error_str)
+    }
+}
+
+/// Convert an sRGB-encoded color channel to a linear one.
+fn srgb_to_linear(channel: f64) -> f64 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
     }
 }
 
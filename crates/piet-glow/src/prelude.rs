@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! The types and traits that most drawing code needs, for glob imports.
+//!
+//! The `piet` traits are imported anonymously, so that their methods can be called without
+//! clashing with the types of the same names in this crate.
+
+pub use crate::{
+    Brush, CompositeMode, GlContext, Image, LayerId, LayerTree, Marker, MarkerPlacement,
+    RenderContext, Scene, SceneLevels, SpreadMode, SweepGradient, Text, TextLayout,
+    TextLayoutBuilder,
+};
+
+pub use piet_hardware::piet::kurbo::{Affine, Point, Rect, Shape, Size, Vec2};
+pub use piet_hardware::piet::{
+    Color, Error as Pierror, Image as _, InterpolationMode, IntoBrush, RenderContext as _,
+    Text as _, TextLayout as _, TextLayoutBuilder as _,
+};
@@ -23,27 +23,149 @@
 // Assume that the appropriate version of OpenGL is already set.
 
 #ifdef GL_ES
+#ifdef PIET_HIGHP
+precision highp float;
+#else
 precision mediump float;
+#endif
 out vec4 outFragColor;
 #define gl_FragColor outFragColor
 #define texture2D texture
 #endif
 
-// Vertex shader gives us the color, the texture coordinates, and the mask coordinates.
+// Vertex shader gives us the color, the texture coordinates and the position in pixels,
+// multiplied by w. Positions in pixels are too large for `mediump` on wide targets.
 in vec4 fRgbaColor;
 in vec2 fTexCoord;
-in vec2 fMaskCoord;
+in highp vec3 fPixelCoord;
+
+// The size of the viewport in pixels, to find the mask coordinates.
+uniform vec2 uViewportSize;
 
 // We also take samplers (textures) for the image and the mask.
+#ifdef PIET_EXTERNAL_IMAGE
+uniform samplerExternalOES uImage;
+#else
 uniform sampler2D uImage;
+#endif
 uniform sampler2D uMask;
 
+// The uber-shader selects the image sampler at runtime:
+// 0 = uImage, 1 = uExternalImage.
+#ifdef PIET_UBER
+#ifdef PIET_EXTERNAL_SUPPORTED
+uniform samplerExternalOES uExternalImage;
+#endif
+uniform int uImageKind;
+#endif
+
+// The color matrix applied to straight-alpha texture colors, with its offset column split out.
+uniform mat4 uColorMatrix;
+uniform vec4 uColorOffset;
+
+// The rectangle that is clipped to and the radii of its corners, in pixels. A negative radius
+// turns the clip off.
+uniform highp vec4 uClipRect;
+uniform vec2 uClipRadii;
+
+// The premultiplied color outside of the image, for GPUs that can't clamp to a border color
+// themselves. A negative alpha turns it off.
+uniform vec4 uBorderColor;
+
+vec4 sampleImage(vec2 coord) {
+    if (uBorderColor.a >= 0.0
+        && (any(lessThan(coord, vec2(0.0))) || any(greaterThan(coord, vec2(1.0))))) {
+        return uBorderColor;
+    }
+
+#if defined(PIET_UBER) && defined(PIET_EXTERNAL_SUPPORTED)
+    if (uImageKind == 1) {
+        return texture2D(uExternalImage, coord);
+    }
+#endif
+    return texture2D(uImage, coord);
+}
+
+// Apply the color matrix to a premultiplied color.
+vec4 applyColorMatrix(vec4 color) {
+    vec4 straight = vec4(0.0);
+    if (color.a > 0.0) {
+        straight = vec4(color.rgb / color.a, color.a);
+    }
+    vec4 result = clamp(uColorMatrix * straight + uColorOffset, 0.0, 1.0);
+    return vec4(result.rgb * result.a, result.a);
+}
+
+// Get the coverage of the clip at a pixel's center, like `ClipShape::coverage`.
+float clipCoverage(highp vec2 pixel) {
+    if (uClipRadii.x < 0.0) {
+        return 1.0;
+    }
+
+    // Fold the point into the bottom right quadrant, measured from the corner's center.
+    highp vec2 center = (uClipRect.xy + uClipRect.zw) / 2.0;
+    highp vec2 halfSize = (uClipRect.zw - uClipRect.xy) / 2.0;
+    highp vec2 q = abs(pixel - center) - (halfSize - uClipRadii);
+
+    float dist;
+    if (q.x > 0.0 && q.y > 0.0) {
+        if (uClipRadii.x > 0.0 && uClipRadii.y > 0.0) {
+            float f = length(q / uClipRadii);
+            float gradient = length(q / uClipRadii / uClipRadii);
+            dist = f * (f - 1.0) / gradient;
+        } else {
+            dist = length(q);
+        }
+    } else {
+        dist = max(q.x - uClipRadii.x, q.y - uClipRadii.y);
+    }
+
+    return clamp(0.5 - dist, 0.0, 1.0);
+}
+
+#ifdef PIET_CUSTOM_BRUSH
+// The parameters of the custom brush, whose code is added after this shader.
+uniform vec4 uBrushParams[2];
+vec4 piet_brush(vec2 position, vec4 params[2]);
+#endif
+
+#ifdef PIET_SRGB
+// Convert sRGB-encoded color channels to linear ones, since the framebuffer encodes them again.
+vec3 toLinear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(low, high, step(vec3(0.04045), color));
+}
+#endif
+
 void main() {
-    vec4 textureColor = texture2D(uImage, fTexCoord);
-    vec4 mainColor = fRgbaColor * textureColor;
+    // Vertex colors use straight alpha, while textures are premultiplied.
+    vec4 rgbaColor = fRgbaColor;
+#ifdef PIET_CUSTOM_BRUSH
+    // The texture coordinates of a custom brush are the position in user space.
+    vec4 textureColor = piet_brush(fTexCoord, uBrushParams);
+#else
+    vec4 textureColor = applyColorMatrix(sampleImage(fTexCoord));
+#endif
+#ifdef PIET_SRGB
+    rgbaColor.rgb = toLinear(rgbaColor.rgb);
+    if (textureColor.a > 0.0) {
+        textureColor.rgb = toLinear(textureColor.rgb / textureColor.a) * textureColor.a;
+    }
+#endif
+    vec4 mainColor = vec4(rgbaColor.rgb * rgbaColor.a, rgbaColor.a) * textureColor;
+
+    // The mask stores coverage in its alpha channel.
+    highp vec2 pixelCoord = fPixelCoord.xy / fPixelCoord.z;
+    float coverage = texture2D(uMask, pixelCoord / uViewportSize).a;
+    coverage *= clipCoverage(pixelCoord);
+    vec4 finalColor = mainColor * coverage;
 
-    vec4 maskColor = texture2D(uMask, fMaskCoord);
-    vec4 finalColor = mainColor * maskColor;
+#ifdef PIET_STRAIGHT_ALPHA
+    if (finalColor.a > 0.0) {
+        finalColor.rgb /= finalColor.a;
+    }
+#endif
 
     gl_FragColor = finalColor;
 }
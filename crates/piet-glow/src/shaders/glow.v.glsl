@@ -23,8 +23,12 @@
 // Assume that the appropriate version of OpenGL is already set.
 
 #ifdef GL_ES
+#ifdef PIET_HIGHP
+precision highp float;
+#else
 precision mediump float;
 #endif
+#endif
 
 // Vertex shader takes inputs of this form:
 // struct Vertex {
@@ -36,10 +40,12 @@ in vec2 aPosition;
 in vec2 aUv;
 in vec4 aColor;
 
-// Fragment shader expects color, texture coordinates and mask coordinates.
+// Fragment shader expects color, texture coordinates and the position in pixels. The position
+// is multiplied by w, which is passed along with it, so that dividing by the interpolated w
+// interpolates it linearly on the screen, even for draws with perspective.
 out vec4 fRgbaColor;
 out vec2 fTexCoord;
-out vec2 fMaskCoord;
+out highp vec3 fPixelCoord;
 
 // Uniforms:
 // - transform: 3x3 matrix for transforming vertices.
@@ -48,23 +54,21 @@ uniform mat3 uTransform;
 uniform vec2 uViewportSize;
 
 void main() {
-    // Transform the vertex position.
-    vec3 pos = uTransform * vec3(aPosition, 1.0);
-    pos /= pos.z;
+    // Transform the vertex position. The third component is w, which is only different
+    // from one for draws with perspective.
+    highp vec3 pos = uTransform * vec3(aPosition, 1.0);
 
-    // Transform the vertex position to clip space.
+    // Transform the vertex position to clip space, leaving the perspective divide to the GPU
+    // so that it clips points behind the viewer.
     gl_Position = vec4(
-        (2.0 * pos.x / uViewportSize.x) - 1.0,
-        1.0 - (2.0 * pos.y / uViewportSize.y),
+        (2.0 * pos.x / uViewportSize.x) - pos.z,
+        pos.z - (2.0 * pos.y / uViewportSize.y),
         0.0,
-        1.0
+        pos.z
     );
 
-    // Transform to mask-space coordinates.
-    fMaskCoord = vec2(
-        pos.x / uViewportSize.x,
-        pos.y / uViewportSize.y
-    );
+    // The mask and the clip shape are measured in pixels.
+    fPixelCoord = pos;
 
     // Pass through the texture coordinates and color.
     fTexCoord = aUv;
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for tracking the damage of frames.
+
+use piet_glow::DamageTracker;
+use piet_hardware::piet::kurbo::Rect;
+
+const FULL: Rect = Rect::new(0.0, 0.0, 100.0, 50.0);
+
+#[test]
+fn first_frame_is_damaged_completely() {
+    let tracker = DamageTracker::new(100, 50);
+    assert_eq!(tracker.damage(), FULL);
+    for age in 0..4 {
+        assert_eq!(tracker.repaint_region(age), FULL);
+    }
+}
+
+#[test]
+fn repaint_region_covers_damage_since_buffer_age() {
+    let mut tracker = DamageTracker::new(100, 50);
+    tracker.end_frame();
+
+    let first = Rect::new(10.0, 10.0, 20.0, 20.0);
+    tracker.add_damage(first);
+    tracker.end_frame();
+
+    let second = Rect::new(40.0, 5.0, 50.0, 15.0);
+    tracker.add_damage(second);
+
+    // A buffer from the last frame only misses this frame's damage.
+    assert_eq!(tracker.repaint_region(1), second);
+    assert_eq!(tracker.repaint_region(2), first.union(second));
+
+    // The buffer from the first frame was fully damaged since.
+    assert_eq!(tracker.repaint_region(3), FULL);
+
+    // An unknown age, or one older than the history, repaints everything.
+    assert_eq!(tracker.repaint_region(0), FULL);
+    assert_eq!(tracker.repaint_region(4), FULL);
+    assert_eq!(tracker.repaint_region(u32::MAX), FULL);
+}
+
+#[test]
+fn history_is_limited() {
+    let mut tracker = DamageTracker::new(100, 50);
+    for _ in 0..8 {
+        tracker.end_frame();
+    }
+
+    // Nothing changed in the remembered frames, but older buffers can't be trusted.
+    assert_eq!(tracker.repaint_region(4), Rect::ZERO);
+    assert_eq!(tracker.repaint_region(5), Rect::ZERO);
+    assert_eq!(tracker.repaint_region(6), FULL);
+}
+
+#[test]
+fn damage_is_expanded_to_pixels_and_clipped() {
+    let mut tracker = DamageTracker::new(100, 50);
+    tracker.end_frame();
+
+    tracker.add_damage(Rect::new(90.5, 40.2, 120.0, 44.7));
+    assert_eq!(tracker.damage(), Rect::new(90.0, 40.0, 100.0, 45.0));
+
+    // Damage outside of the surface is ignored.
+    tracker.end_frame();
+    tracker.add_damage(Rect::new(-20.0, -20.0, -10.0, -10.0));
+    assert_eq!(tracker.damage(), Rect::ZERO);
+}
+
+#[test]
+fn swap_rect_is_flipped() {
+    let mut tracker = DamageTracker::new(100, 50);
+    assert_eq!(tracker.swap_rect(), Some([0, 0, 100, 50]));
+    tracker.end_frame();
+
+    assert_eq!(tracker.swap_rect(), None);
+    tracker.add_damage(Rect::new(10.0, 5.0, 30.0, 15.0));
+    assert_eq!(tracker.swap_rect(), Some([10, 35, 20, 10]));
+}
+
+#[test]
+fn resize_damages_everything() {
+    let mut tracker = DamageTracker::new(100, 50);
+    tracker.end_frame();
+    tracker.end_frame();
+
+    tracker.resize(100, 50);
+    assert_eq!(tracker.repaint_region(1), Rect::ZERO);
+
+    tracker.resize(200, 80);
+    assert_eq!(tracker.size(), (200, 80));
+    assert_eq!(tracker.repaint_region(1), Rect::new(0.0, 0.0, 200.0, 80.0));
+    tracker.end_frame();
+    assert_eq!(tracker.repaint_region(2), Rect::new(0.0, 0.0, 200.0, 80.0));
+}
@@ -0,0 +1,549 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! A backend for `piet-hardware` that rasterizes on the CPU.
+//!
+//! [`SoftwareContext`] implements [`GpuContext`] without a GPU, following the same rules as
+//! the shaders in the real backends. It can be used as a fallback on machines without GL or
+//! Vulkan, or to render golden images in tests. Textures are sampled with their interpolation
+//! mode, and color matrices, composite modes, clip shapes and scissor rectangles are
+//! supported. Each pixel is sampled once at its center and there is no dithering, so the
+//! output is the same on every machine. Projections, custom brushes, mipmaps, render targets
+//! and stencil clips aren't supported, so `piet-hardware` falls back to something else or
+//! reports them as unsupported.
+//!
+//! ```
+//! use piet_gpu_soft::SoftwareContext;
+//! use piet_hardware::piet::{kurbo::Rect, Color, RenderContext as _};
+//! use piet_hardware::Source;
+//!
+//! let mut source = Source::new(SoftwareContext::new(16, 16)).unwrap();
+//! {
+//!     let mut rc = source.render_context(16, 16);
+//!     rc.fill(Rect::new(0.0, 0.0, 8.0, 16.0), &Color::RED);
+//!     rc.finish().unwrap();
+//! }
+//!
+//! let pixels = source.context().pixels();
+//! assert_eq!(pixels[0], [0xFF, 0, 0, 0xFF]);
+//! assert_eq!(pixels[15], [0, 0, 0, 0]);
+//! ```
+
+use piet_hardware::piet::kurbo::{Affine, Point};
+use piet_hardware::piet::{self, InterpolationMode};
+use piet_hardware::{
+    BlendFactor, ClipShape, ColorMatrix, CompositeMode, GpuContext, RepeatStrategy, Vertex,
+};
+
+use std::cell::{Cell, RefCell};
+use std::convert::Infallible;
+
+/// A texture stored on the CPU, as premultiplied RGBA.
+pub struct SoftwareTexture(RefCell<TextureData>);
+
+struct TextureData {
+    size: (u32, u32),
+    pixels: Vec<[f32; 4]>,
+    repeat: RepeatStrategy,
+    interpolation: InterpolationMode,
+}
+
+impl TextureData {
+    /// Sample the texture at the given UV coordinates.
+    ///
+    /// Like in OpenGL, bilinear interpolation blends the four pixels whose centers are
+    /// closest, each of which repeats like the texture does.
+    fn sample(&self, uv: [f32; 2], interpolation: InterpolationMode) -> [f32; 4] {
+        let (width, height) = self.size;
+        if width == 0 || height == 0 {
+            return [0.0; 4];
+        }
+
+        if interpolation == InterpolationMode::NearestNeighbor {
+            let texel = |coord: f32, len: u32| (coord * len as f32).floor() as i64;
+            return self.texel(texel(uv[0], width), texel(uv[1], height));
+        }
+
+        let x = uv[0] * width as f32 - 0.5;
+        let y = uv[1] * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let lerp =
+            |a: [f32; 4], b: [f32; 4], t: f32| [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t);
+        let top = lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), fx);
+        let bottom = lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), fx);
+        lerp(top, bottom, fy)
+    }
+
+    /// Get a pixel of the texture, repeating it like the texture does.
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let (width, height) = self.size;
+
+        let texel = |texel: i64, len: u32| -> Option<u32> {
+            match self.repeat {
+                RepeatStrategy::Repeat => Some(texel.rem_euclid(len as i64) as u32),
+                RepeatStrategy::Reflect => {
+                    let texel = texel.rem_euclid(2 * len as i64);
+                    Some(texel.min(2 * len as i64 - 1 - texel) as u32)
+                }
+                RepeatStrategy::Clamp => Some(texel.clamp(0, len as i64 - 1) as u32),
+                _ => (0..len as i64).contains(&texel).then_some(texel as u32),
+            }
+        };
+
+        match (texel(x, width), texel(y, height)) {
+            (Some(x), Some(y)) => self.pixels[(y * width + x) as usize],
+            _ => match self.repeat {
+                RepeatStrategy::Color(color) => {
+                    let (r, g, b, a) = color.as_rgba();
+                    [(r * a) as f32, (g * a) as f32, (b * a) as f32, a as f32]
+                }
+                _ => [0.0; 4],
+            },
+        }
+    }
+}
+
+/// A vertex buffer stored on the CPU.
+pub struct SoftwareBuffer(RefCell<(Vec<Vertex>, Vec<u32>)>);
+
+/// A GPU context that draws into a CPU-side framebuffer.
+pub struct SoftwareContext {
+    /// The size of the framebuffer.
+    size: (u32, u32),
+
+    /// The framebuffer, as premultiplied RGBA.
+    pixels: RefCell<Vec<[f32; 4]>>,
+
+    /// The shape that draws are clipped to, on top of the mask.
+    clip_shape: Cell<Option<ClipShape>>,
+
+    /// The rectangle that draws are restricted to, as `[x0, y0, x1, y1]`.
+    scissor: Cell<Option<[u32; 4]>>,
+
+    /// The color matrix applied to texture colors.
+    color_matrix: Cell<Option<ColorMatrix>>,
+
+    /// How draws are combined with the framebuffer.
+    composite_mode: Cell<CompositeMode>,
+
+    /// The interpolation that textures are sampled with instead of their own.
+    sampler_interpolation: Cell<Option<InterpolationMode>>,
+
+    /// The largest texture that can be created.
+    max_texture_size: (u32, u32),
+}
+
+impl SoftwareContext {
+    /// Create a new context with a transparent framebuffer of the given size.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            size: (width, height),
+            pixels: RefCell::new(vec![[0.0; 4]; width as usize * height as usize]),
+            clip_shape: Cell::new(None),
+            scissor: Cell::new(None),
+            color_matrix: Cell::new(None),
+            composite_mode: Cell::new(CompositeMode::SourceOver),
+            sampler_interpolation: Cell::new(None),
+            max_texture_size: (4096, 4096),
+        }
+    }
+
+    /// Limit the size of textures, which is 4096 by 4096 pixels by default.
+    ///
+    /// Small limits make the text atlas fill up quickly, which is useful in tests.
+    pub fn with_max_texture_size(mut self, width: u32, height: u32) -> Self {
+        self.max_texture_size = (width, height);
+        self
+    }
+
+    /// Get the size of the framebuffer.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Resize the framebuffer, clearing it to transparent.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+        *self.pixels.get_mut() = vec![[0.0; 4]; width as usize * height as usize];
+    }
+
+    /// Get the premultiplied RGBA pixels of the framebuffer, row by row.
+    pub fn pixels(&self) -> Vec<[u8; 4]> {
+        self.pixels.borrow().iter().map(|&px| to_u8(px)).collect()
+    }
+}
+
+impl GpuContext for SoftwareContext {
+    type Texture = SoftwareTexture;
+    type VertexBuffer = SoftwareBuffer;
+    type Error = Infallible;
+
+    fn clear(&self, color: piet::Color) {
+        let (r, g, b, a) = color.as_rgba();
+        let color = [(r * a) as f32, (g * a) as f32, (b * a) as f32, a as f32];
+        self.pixels.borrow_mut().fill(color);
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        Ok(SoftwareTexture(RefCell::new(TextureData {
+            size: (0, 0),
+            pixels: vec![],
+            repeat,
+            interpolation,
+        })))
+    }
+
+    fn delete_texture(&self, _texture: Self::Texture) {}
+
+    fn write_texture(
+        &self,
+        texture: &Self::Texture,
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        let mut texture = texture.0.borrow_mut();
+        texture.size = size;
+        texture.pixels = vec![[0.0; 4]; size.0 as usize * size.1 as usize];
+        if let Some(data) = data {
+            self.write_subtexture_data(&mut texture, (0, 0), size, format, data);
+        }
+    }
+
+    fn write_subtexture(
+        &self,
+        texture: &Self::Texture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    ) {
+        let mut texture = texture.0.borrow_mut();
+        self.write_subtexture_data(&mut texture, offset, size, format, data);
+    }
+
+    fn set_texture_interpolation(&self, texture: &Self::Texture, interpolation: InterpolationMode) {
+        texture.0.borrow_mut().interpolation = interpolation;
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        self.max_texture_size
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        Ok(SoftwareBuffer(RefCell::new((vec![], vec![]))))
+    }
+
+    fn delete_vertex_buffer(&self, _buffer: Self::VertexBuffer) {}
+
+    fn write_vertices(&self, buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        *buffer.0.borrow_mut() = (vertices.to_vec(), indices.to_vec());
+    }
+
+    fn push_buffers(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        current_texture: &Self::Texture,
+        mask_texture: &Self::Texture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        let (vertices, indices) = &*vertex_buffer.0.borrow();
+        let texture = current_texture.0.borrow();
+        let mask = mask_texture.0.borrow();
+        let clip_shape = self.clip_shape.get();
+        let color_matrix = self.color_matrix.get();
+        let (src_factor, dst_factor) = self.composite_mode.get().blend_factors();
+        let interpolation = self
+            .sampler_interpolation
+            .get()
+            .unwrap_or(texture.interpolation);
+        let mut pixels = self.pixels.borrow_mut();
+
+        // Only draw the pixels that are inside of the target and the scissor rectangle.
+        let [left, top, right, bottom] = self.scissor.get().unwrap_or([0, 0, u32::MAX, u32::MAX]);
+        let right = right.min(size.0).min(self.size.0);
+        let bottom = bottom.min(size.1).min(self.size.1);
+
+        for tri in indices.chunks_exact(3) {
+            let verts = [0, 1, 2].map(|i| &vertices[tri[i] as usize]);
+            let pos = verts.map(|v| *transform * Point::new(v.pos[0] as f64, v.pos[1] as f64));
+
+            let min_x = pos.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+            let min_y = pos.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+            let max_x = pos.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+            let max_y = pos.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+            let clamp_x = |x: f64| x.clamp(left as f64, right.max(left) as f64) as u32;
+            let clamp_y = |y: f64| y.clamp(top as f64, bottom.max(top) as f64) as u32;
+
+            for y in clamp_y(min_y.floor())..clamp_y(max_y.ceil()) {
+                for x in clamp_x(min_x.floor())..clamp_x(max_x.ceil()) {
+                    let center = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+                    let weights = match barycentric(center, pos) {
+                        Some(weights) => weights,
+                        None => continue,
+                    };
+
+                    let uv = [0, 1].map(|i| {
+                        (0..3)
+                            .map(|j| weights[j] * verts[j].uv[i] as f64)
+                            .sum::<f64>() as f32
+                    });
+                    let color = [0, 1, 2, 3].map(|i| {
+                        (0..3)
+                            .map(|j| weights[j] * verts[j].color[i] as f64 / 255.0)
+                            .sum::<f64>() as f32
+                    });
+
+                    // Vertex colors use straight alpha, while textures are premultiplied.
+                    let mut texel = texture.sample(uv, interpolation);
+                    if let Some(matrix) = &color_matrix {
+                        texel = apply_color_matrix(matrix, texel);
+                    }
+                    let coverage = mask.sample(
+                        [
+                            center.x as f32 / size.0 as f32,
+                            center.y as f32 / size.1 as f32,
+                        ],
+                        InterpolationMode::NearestNeighbor,
+                    )[3] * clip_shape
+                        .map_or(1.0, |clip| clip.coverage(center.x as f32, center.y as f32));
+                    let src = [0, 1, 2, 3].map(|i| {
+                        let vertex = if i == 3 {
+                            color[3]
+                        } else {
+                            color[i] * color[3]
+                        };
+                        vertex * texel[i] * coverage
+                    });
+
+                    let dst = &mut pixels[(y * self.size.0 + x) as usize];
+                    let src_factor = blend_factor(src_factor, src, *dst);
+                    let dst_factor = blend_factor(dst_factor, src, *dst);
+                    *dst = [0, 1, 2, 3].map(|i| src[i] * src_factor + dst[i] * dst_factor);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        self.color_matrix.set(
+            matrix
+                .copied()
+                .filter(|matrix| *matrix != ColorMatrix::IDENTITY),
+        );
+        true
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        self.composite_mode
+            .set(mode.unwrap_or(CompositeMode::SourceOver));
+        true
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        self.clip_shape.set(shape.copied());
+        true
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        self.scissor.set(rect);
+        true
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        self.sampler_interpolation.set(interpolation);
+        true
+    }
+
+    fn read_pixels(
+        &self,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        _target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        // Pixels outside of the framebuffer read as transparent.
+        let pixels = self.pixels.borrow();
+        if width == 0 {
+            return Ok(true);
+        }
+        let rows = data.chunks_mut(width as usize * 4).take(height as usize);
+        for (row, out) in (y as usize..).zip(rows) {
+            for (col, out) in (x as usize..).zip(out.chunks_exact_mut(4)) {
+                let pixel = match row < self.size.1 as usize && col < self.size.0 as usize {
+                    true => to_u8(pixels[row * self.size.0 as usize + col]),
+                    false => [0; 4],
+                };
+                out.copy_from_slice(&pixel);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn clear_rect(
+        &self,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        _target_size: (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, Self::Error> {
+        let (r, g, b, a) = color.as_rgba();
+        let color = [(r * a) as f32, (g * a) as f32, (b * a) as f32, a as f32];
+
+        // The area is clipped to the framebuffer.
+        let (fb_width, fb_height) = self.size;
+        let (x0, x1) = (x.min(fb_width), x.saturating_add(width).min(fb_width));
+        let (y0, y1) = (y.min(fb_height), y.saturating_add(height).min(fb_height));
+        let mut pixels = self.pixels.borrow_mut();
+        for row in y0..y1 {
+            let start = (row * fb_width) as usize;
+            pixels[start + x0 as usize..start + x1 as usize].fill(color);
+        }
+
+        Ok(true)
+    }
+}
+
+impl SoftwareContext {
+    fn write_subtexture_data(
+        &self,
+        texture: &mut TextureData,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    ) {
+        let channels = match format {
+            piet::ImageFormat::Grayscale => 1,
+            piet::ImageFormat::Rgb => 3,
+            _ => 4,
+        };
+
+        // Pixels outside of the texture, or missing from the data, are skipped.
+        let (tex_width, tex_height) = texture.size;
+        for row in 0..height.min(tex_height.saturating_sub(y)) {
+            for col in 0..width.min(tex_width.saturating_sub(x)) {
+                let index = (row * width + col) as usize * channels;
+                let px = match data.get(index..index + channels) {
+                    Some(px) => px,
+                    None => return,
+                };
+                let [r, g, b, a] = match format {
+                    // Coverage is sampled as premultiplied white.
+                    piet::ImageFormat::Grayscale => [px[0]; 4],
+                    piet::ImageFormat::Rgb => [px[0], px[1], px[2], 0xFF],
+                    _ => [px[0], px[1], px[2], px[3]],
+                }
+                .map(|c| c as f32 / 255.0);
+
+                let pixel = match format {
+                    piet::ImageFormat::RgbaSeparate => [r * a, g * a, b * a, a],
+                    _ => [r, g, b, a],
+                };
+                let offset = ((y + row) * texture.size.0 + x + col) as usize;
+                texture.pixels[offset] = pixel;
+            }
+        }
+    }
+}
+
+/// Get the barycentric weights of a point inside of a triangle.
+///
+/// Points on an edge are only inside of one of the triangles sharing that edge, so that
+/// translucent shapes aren't drawn twice along the seams of the tessellation.
+fn barycentric(pt: Point, [a, b, c]: [Point; 3]) -> Option<[f64; 3]> {
+    let area = (b - a).cross(c - a);
+    if area == 0.0 || !area.is_finite() {
+        return None;
+    }
+
+    // Orient the triangle consistently, so the tie-breaking rule works.
+    let (b, c, swapped) = if area < 0.0 {
+        (c, b, true)
+    } else {
+        (b, c, false)
+    };
+
+    let edge = |p: Point, q: Point| {
+        let w = (q - p).cross(pt - p);
+        let d = q - p;
+        let owns_edge = d.y > 0.0 || (d.y == 0.0 && d.x > 0.0);
+        (w > 0.0 || (w == 0.0 && owns_edge)).then_some(w)
+    };
+
+    let wa = edge(b, c)?;
+    let wb = edge(c, a)?;
+    let wc = edge(a, b)?;
+    let total = wa + wb + wc;
+    let (wa, wb, wc) = (wa / total, wb / total, wc / total);
+
+    Some(if swapped { [wa, wc, wb] } else { [wa, wb, wc] })
+}
+
+/// Apply a color matrix to a premultiplied color, like the shaders do.
+fn apply_color_matrix(matrix: &ColorMatrix, color: [f32; 4]) -> [f32; 4] {
+    let straight = match color[3] > 0.0 {
+        true => [
+            color[0] / color[3],
+            color[1] / color[3],
+            color[2] / color[3],
+            color[3],
+        ],
+        false => [0.0; 4],
+    };
+    let [r, g, b, a] = matrix.rows().map(|row| {
+        let sum = (0..4).map(|i| row[i] * straight[i]).sum::<f32>() + row[4];
+        sum.clamp(0.0, 1.0)
+    });
+    [r * a, g * a, b * a, a]
+}
+
+/// Get the value of a blend factor for premultiplied source and destination colors.
+fn blend_factor(factor: BlendFactor, src: [f32; 4], dst: [f32; 4]) -> f32 {
+    match factor {
+        BlendFactor::Zero => 0.0,
+        BlendFactor::SourceAlpha => src[3],
+        BlendFactor::OneMinusSourceAlpha => 1.0 - src[3],
+        BlendFactor::DestinationAlpha => dst[3],
+        BlendFactor::OneMinusDestinationAlpha => 1.0 - dst[3],
+        _ => 1.0,
+    }
+}
+
+/// Convert a premultiplied color to bytes.
+fn to_u8(color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks the exact pixels that the software backend draws.
+
+use piet_gpu_soft::{SoftwareContext, SoftwareTexture};
+use piet_hardware::piet::kurbo::{Affine, Rect};
+use piet_hardware::piet::{Color, ImageFormat, InterpolationMode, RenderContext as _};
+use piet_hardware::{ColorMatrix, CompositeMode, GpuContext, RepeatStrategy, Source, Vertex};
+
+/// Create a texture with the given RGBA pixels.
+fn texture(
+    context: &SoftwareContext,
+    interpolation: InterpolationMode,
+    size: (u32, u32),
+    pixels: &[[u8; 4]],
+) -> SoftwareTexture {
+    let texture = context
+        .create_texture(interpolation, RepeatStrategy::Clamp)
+        .unwrap();
+    context.write_texture(
+        &texture,
+        size,
+        ImageFormat::RgbaPremul,
+        Some(pixels.concat().as_slice()),
+    );
+    texture
+}
+
+/// Draw `texture` over the whole framebuffer with a white vertex color and no mask.
+fn draw_texture(context: &SoftwareContext, texture: &SoftwareTexture) {
+    let size = context.size();
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    let vertex = |x: f32, y: f32| Vertex {
+        pos: [x * width, y * height],
+        uv: [x, y],
+        color: [0xFF; 4],
+    };
+
+    let buffer = context.create_vertex_buffer().unwrap();
+    context.write_vertices(
+        &buffer,
+        &[
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+        ],
+        &[0, 1, 2, 0, 2, 3],
+    );
+    let mask = texture_of(context, [0xFF; 4]);
+    context
+        .push_buffers(&buffer, texture, &mask, &Affine::IDENTITY, size)
+        .unwrap();
+}
+
+/// Create a texture with a single pixel.
+fn texture_of(context: &SoftwareContext, pixel: [u8; 4]) -> SoftwareTexture {
+    texture(
+        context,
+        InterpolationMode::NearestNeighbor,
+        (1, 1),
+        &[pixel],
+    )
+}
+
+/// The red channel of every pixel in the framebuffer.
+fn reds(context: &SoftwareContext) -> Vec<u8> {
+    context.pixels().iter().map(|px| px[0]).collect()
+}
+
+#[test]
+fn textures_are_sampled_with_their_interpolation() {
+    let context = SoftwareContext::new(4, 1);
+    let pixels = [[0, 0, 0, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF]];
+
+    let nearest = texture(
+        &context,
+        InterpolationMode::NearestNeighbor,
+        (2, 1),
+        &pixels,
+    );
+    draw_texture(&context, &nearest);
+    assert_eq!(reds(&context), [0x00, 0x00, 0xFF, 0xFF]);
+
+    let bilinear = texture(&context, InterpolationMode::Bilinear, (2, 1), &pixels);
+    draw_texture(&context, &bilinear);
+    assert_eq!(reds(&context), [0x00, 0x40, 0xBF, 0xFF]);
+
+    // Changing the interpolation of a texture, or overriding it for the sampler, is honored.
+    context.set_texture_interpolation(&bilinear, InterpolationMode::NearestNeighbor);
+    draw_texture(&context, &bilinear);
+    assert_eq!(reds(&context), [0x00, 0x00, 0xFF, 0xFF]);
+
+    assert!(context.set_sampler_interpolation(Some(InterpolationMode::Bilinear)));
+    draw_texture(&context, &nearest);
+    assert_eq!(reds(&context), [0x00, 0x40, 0xBF, 0xFF]);
+}
+
+#[test]
+fn color_matrix_applies_to_straight_alpha() {
+    let context = SoftwareContext::new(1, 1);
+    let half_red = texture_of(&context, [0x80, 0x00, 0x00, 0x80]);
+
+    // Swap red and green, and keep alpha.
+    #[rustfmt::skip]
+    let swap = ColorMatrix([
+        0.0, 1.0, 0.0, 0.0, 0.0,
+        1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]);
+    assert!(context.set_color_matrix(Some(&swap)));
+    draw_texture(&context, &half_red);
+    assert_eq!(context.pixels(), [[0x00, 0x80, 0x00, 0x80]]);
+
+    // The offset is added to the straight color, and the result is premultiplied again.
+    #[rustfmt::skip]
+    let brighten = ColorMatrix([
+        1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0, 1.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]);
+    context.clear(Color::TRANSPARENT);
+    assert!(context.set_color_matrix(Some(&brighten)));
+    draw_texture(&context, &half_red);
+    assert_eq!(context.pixels(), [[0x80, 0x00, 0x80, 0x80]]);
+
+    context.clear(Color::TRANSPARENT);
+    assert!(context.set_color_matrix(None));
+    draw_texture(&context, &half_red);
+    assert_eq!(context.pixels(), [[0x80, 0x00, 0x00, 0x80]]);
+}
+
+#[test]
+fn composite_modes_blend_with_the_framebuffer() {
+    let mut source = Source::new(SoftwareContext::new(2, 1)).unwrap();
+    let mut rc = source.render_context(2, 1);
+    rc.clear(None, Color::TRANSPARENT);
+    rc.fill(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::BLUE);
+
+    // Source-in keeps the source only where the destination is opaque.
+    rc.set_composite_mode(CompositeMode::SourceIn).unwrap();
+    rc.fill(Rect::new(0.0, 0.0, 2.0, 1.0), &Color::RED);
+    rc.finish().unwrap();
+    drop(rc);
+    assert_eq!(
+        source.context().pixels(),
+        [[0xFF, 0x00, 0x00, 0xFF], [0x00, 0x00, 0x00, 0x00]]
+    );
+
+    // Destination-out erases the destination.
+    let mut rc = source.render_context(2, 1);
+    rc.set_composite_mode(CompositeMode::DestinationOut)
+        .unwrap();
+    rc.fill(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::BLACK);
+    rc.finish().unwrap();
+    drop(rc);
+    assert_eq!(source.context().pixels(), [[0x00; 4]; 2]);
+}
+
+#[test]
+fn scissor_restricts_draws() {
+    let context = SoftwareContext::new(4, 4);
+    let white = texture_of(&context, [0xFF; 4]);
+
+    assert!(context.set_scissor(Some([1, 2, 3, 10])));
+    draw_texture(&context, &white);
+    let drawn = context
+        .pixels()
+        .iter()
+        .enumerate()
+        .filter(|(_, px)| px[3] != 0)
+        .map(|(i, _)| (i % 4, i / 4))
+        .collect::<Vec<_>>();
+    assert_eq!(drawn, [(1, 2), (2, 2), (1, 3), (2, 3)]);
+
+    assert!(context.set_scissor(None));
+    draw_texture(&context, &white);
+    assert!(context.pixels().iter().all(|px| *px == [0xFF; 4]));
+}
+
+#[test]
+fn out_of_bounds_accesses_are_clipped() {
+    let context = SoftwareContext::new(2, 2);
+    context.clear(Color::WHITE);
+
+    // Clearing past the edge only clears the pixels inside of the framebuffer.
+    assert!(context
+        .clear_rect((1, 1), (4, 4), (2, 2), Color::BLACK)
+        .unwrap());
+    assert_eq!(context.pixels()[3], [0x00, 0x00, 0x00, 0xFF]);
+    assert!(context
+        .clear_rect((8, 8), (2, 2), (2, 2), Color::BLACK)
+        .unwrap());
+
+    // Reading past the edge reads transparent pixels.
+    let mut data = [0xAA; 3 * 3 * 4];
+    assert!(context
+        .read_pixels((1, 0), (3, 3), (2, 2), &mut data)
+        .unwrap());
+    let data = data.chunks_exact(4).collect::<Vec<_>>();
+    assert_eq!(data[0], [0xFF; 4]);
+    assert_eq!(data[3], [0x00, 0x00, 0x00, 0xFF]);
+    assert!(data
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % 3 != 0 || *i == 6)
+        .all(|(_, px)| *px == [0x00; 4]));
+
+    // A short buffer is filled as far as it goes.
+    let mut short = [0xAA; 6];
+    assert!(context
+        .read_pixels((0, 0), (2, 2), (2, 2), &mut short)
+        .unwrap());
+    assert_eq!(short, [0xFF, 0xFF, 0xFF, 0xFF, 0xAA, 0xAA]);
+
+    // Writing past the edge of a texture, or with too little data, writes what fits.
+    let texture = texture(
+        &context,
+        InterpolationMode::NearestNeighbor,
+        (2, 1),
+        &[[0x00; 4], [0x00; 4]],
+    );
+    context.write_subtexture(
+        &texture,
+        (1, 0),
+        (2, 2),
+        ImageFormat::RgbaPremul,
+        &[0xFF; 4],
+    );
+    context.write_subtexture(
+        &texture,
+        (5, 5),
+        (1, 1),
+        ImageFormat::RgbaPremul,
+        &[0xFF; 4],
+    );
+    context.clear(Color::TRANSPARENT);
+    draw_texture(&context, &texture);
+    assert_eq!(context.pixels()[0], [0x00; 4]);
+    assert_eq!(context.pixels()[1], [0xFF; 4]);
+}
@@ -591,7 +591,7 @@ impl piet_hardware::GpuContext for GlContext {
         let (r, g, b, a) = color.as_rgba();
 
         unsafe {
-            gl::ClearColor(r as f32, g as f32, b as f32, a as f32);
+            gl::ClearColor((r * a) as f32, (g * a) as f32, (b * a) as f32, a as f32);
             gl::Clear(gl::COLOR_BUFFER_BIT);
             gl_error();
         }
@@ -910,7 +910,7 @@ impl piet_hardware::GpuContext for GlContext {
 
             // Set the blend mode.
             gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
 
             // Set vertex attributes.
             gl::BindVertexArray(vertex_buffer.vao);
@@ -1009,10 +1009,10 @@ uniform sampler2D mask;
 
 void main() {
     vec4 textureColor = texture2D(tex, fTexCoord);
-    vec4 mainColor = rgbaColor * textureColor;
+    vec4 mainColor = vec4(rgbaColor.rgb * rgbaColor.a, rgbaColor.a) * textureColor;
 
-    vec4 maskColor = texture2D(mask, fMaskCoord);
-    vec4 finalColor = mainColor * maskColor;
+    float coverage = texture2D(mask, fMaskCoord).a;
+    vec4 finalColor = mainColor * coverage;
 
     gl_FragColor = finalColor;
 }
@@ -22,35 +22,98 @@
 //! The text atlas, which is used to cache glyphs.
 
 use super::gpu_backend::{GpuContext, RepeatStrategy};
+use super::memory::{Category, MemoryTracker};
 use super::resources::Texture;
-use super::ResultExt;
+use super::{AtlasStrategy, ResultExt, Staging};
 
 use ahash::RandomState;
-use cosmic_text::{CacheKey, FontSystem, LayoutGlyph, Placement, SwashCache, SwashContent};
-use etagere::{Allocation, AtlasAllocator};
-use hashbrown::hash_map::{Entry, HashMap};
+use cosmic_text::{
+    CacheKey, FontSystem, LayoutGlyph, Placement, SwashCache, SwashContent, SwashImage,
+};
+use etagere::{Allocation, AllocatorOptions, AtlasAllocator, BucketedAtlasAllocator};
+use hashbrown::hash_map::HashMap;
 
-use piet::kurbo::{Point, Rect, Size};
+use piet::kurbo::{Point, Rect, Size, Vec2};
 use piet::{Error as Pierror, InterpolationMode};
 
 use std::rc::Rc;
 
+/// The number of frames that a glyph is kept for after it was last drawn, when the atlas is
+/// compacted because it filled up.
+const MAX_IDLE_FRAMES: u64 = 120;
+
+/// The number of glyphs that are moved into the repacked atlas in each frame.
+///
+/// Glyphs have to be rasterized again to be moved, so repacking a full atlas at once would
+/// stall a frame.
+const REPACK_GLYPHS_PER_FRAME: usize = 64;
+
+/// The largest font size, in pixels, that glyphs are rasterized at.
+///
+/// Text that is scaled up further than this is drawn scaled up from glyphs of this size, so
+/// that huge glyphs don't take over the atlas.
+const MAX_GLYPH_SIZE: f32 = 256.0;
+
 /// The atlas, combining all of the glyphs into a single texture.
 pub(crate) struct Atlas<C: GpuContext + ?Sized> {
+    /// The context that the texture belongs to.
+    context: Rc<C>,
+
+    /// Keeps track of the memory used by the texture.
+    memory: Rc<MemoryTracker>,
+
     /// The texture atlas.
     texture: Rc<Texture<C>>,
 
     /// The size of the texture atlas.
     size: (u32, u32),
 
+    /// The format of the texture atlas.
+    ///
+    /// The atlas only stores coverage until the first color glyph is drawn.
+    format: piet::ImageFormat,
+
     /// The allocator for the texture atlas.
-    allocator: AtlasAllocator,
+    allocator: Allocator,
+
+    /// How the allocator packs glyphs.
+    config: (AtlasStrategy, AllocatorOptions),
 
     /// The hash map between the glyphs used and the texture allocation.
     glyphs: HashMap<CacheKey, Position, RandomState>,
 
     /// The cache for the swash layout.
     swash_cache: SwashCache,
+
+    /// The number of frames that have been started.
+    frame: u64,
+
+    /// Whether the atlas filled up, so it should be compacted.
+    needs_compaction: bool,
+
+    /// The compaction in progress.
+    repack: Option<Repack<C>>,
+}
+
+/// An atlas that glyphs are being moved into, a few in every frame.
+///
+/// Glyphs keep being drawn from the old texture until every live glyph has been moved,
+/// including the ones that were added in the meantime, and then the textures are swapped.
+struct Repack<C: GpuContext + ?Sized> {
+    /// The new texture.
+    texture: Texture<C>,
+
+    /// The allocator for the new texture.
+    allocator: Allocator,
+
+    /// The glyphs that were moved so far.
+    glyphs: HashMap<CacheKey, Position, RandomState>,
+
+    /// The glyphs that still have to be moved, with the next one last.
+    queue: Vec<CacheKey>,
+
+    /// Glyphs last drawn before this frame are left behind, unless they are pinned.
+    min_frame: u64,
 }
 
 /// The data needed for rendering a glyph.
@@ -65,6 +128,47 @@ pub(crate) struct GlyphData {
     pub(crate) offset: Point,
 }
 
+/// The allocators that can be used for the atlas.
+enum Allocator {
+    /// A shelf allocator.
+    Shelf(AtlasAllocator),
+
+    /// A bucketed shelf allocator.
+    Bucketed(BucketedAtlasAllocator),
+}
+
+impl Allocator {
+    /// Create a new allocator.
+    fn new(strategy: AtlasStrategy, size: (u32, u32), options: &AllocatorOptions) -> Self {
+        let size = [size.0 as i32, size.1 as i32].into();
+
+        match strategy {
+            AtlasStrategy::Shelf => Self::Shelf(AtlasAllocator::with_options(size, options)),
+            AtlasStrategy::Bucketed => {
+                Self::Bucketed(BucketedAtlasAllocator::with_options(size, options))
+            }
+        }
+    }
+
+    /// Allocate a rectangle.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<Allocation> {
+        let size = [width as i32, height as i32].into();
+
+        match self {
+            Self::Shelf(allocator) => allocator.allocate(size),
+            Self::Bucketed(allocator) => allocator.allocate(size),
+        }
+    }
+
+    /// Free an allocation.
+    fn deallocate(&mut self, allocation: &Allocation) {
+        match self {
+            Self::Shelf(allocator) => allocator.deallocate(allocation.id),
+            Self::Bucketed(allocator) => allocator.deallocate(allocation.id),
+        }
+    }
+}
+
 /// The positioning of a glyph in the atlas.
 struct Position {
     /// The allocation of the glyph in the atlas.
@@ -72,31 +176,67 @@ struct Position {
 
     /// Placement of the glyph.
     placement: Placement,
+
+    /// The frame that the glyph was last drawn in.
+    last_used: u64,
+
+    /// How many times the glyph was pinned, which keeps it from being evicted.
+    pins: u32,
 }
 
 impl<C: GpuContext + ?Sized> Atlas<C> {
     /// Create a new, empty texture atlas.
-    pub(crate) fn new(context: &Rc<C>) -> Result<Self, Pierror> {
+    pub(crate) fn new(
+        context: &Rc<C>,
+        memory: &Rc<MemoryTracker>,
+        hasher: RandomState,
+        (strategy, options): (AtlasStrategy, AllocatorOptions),
+    ) -> Result<Self, Pierror> {
         let (max_width, max_height) = context.max_texture_size();
         let texture = Texture::new(
             context,
             InterpolationMode::Bilinear,
             RepeatStrategy::Color(piet::Color::TRANSPARENT),
         )
-        .piet_err()?;
+        .piet_err()?
+        .tracked(memory, Category::GlyphAtlases);
 
         // Initialize the texture to be transparent.
-        texture.write_texture((max_width, max_height), piet::ImageFormat::RgbaPremul, None);
+        let format = piet::ImageFormat::Grayscale;
+        texture.write_texture((max_width, max_height), format, None);
 
         Ok(Atlas {
+            context: context.clone(),
+            memory: memory.clone(),
             texture: Rc::new(texture),
             size: (max_width, max_height),
-            allocator: AtlasAllocator::new([max_width as i32, max_height as i32].into()),
-            glyphs: HashMap::with_hasher(RandomState::new()),
+            format,
+            allocator: Allocator::new(strategy, (max_width, max_height), &options),
+            config: (strategy, options),
+            glyphs: HashMap::with_hasher(hasher),
             swash_cache: SwashCache::new(),
+            frame: 0,
+            needs_compaction: false,
+            repack: None,
         })
     }
 
+    /// Start a new frame.
+    ///
+    /// If the atlas filled up, the glyphs that were drawn recently are repacked into a new
+    /// texture over the next frames. Textures are only swapped between frames, since glyphs
+    /// that were already drawn in a frame may not have been flushed to the GPU yet.
+    pub(crate) fn begin_frame(&mut self, font_system: &mut FontSystem, staging: &mut Staging) {
+        self.frame += 1;
+
+        if self.needs_compaction && self.repack.is_none() {
+            self.needs_compaction = false;
+            let min_frame = self.frame.saturating_sub(MAX_IDLE_FRAMES);
+            self.start_repack(min_frame);
+        }
+        self.continue_repack(font_system, staging, REPACK_GLYPHS_PER_FRAME);
+    }
+
     /// Get a reference to the inner texture.
     pub(crate) fn texture(&self) -> &Rc<Texture<C>> {
         &self.texture
@@ -104,12 +244,17 @@ impl<C: GpuContext + ?Sized> Atlas<C> {
 
     /// Get the UV rectangle for the given glyph.
     ///
-    /// This function rasterizes the glyph if it isn't already cached.
+    /// The glyph is rasterized at `scale` times its font size, the scale that it ends up at on
+    /// the target, but its size and offset are returned in the units of its layout. This
+    /// function rasterizes the glyph if it isn't already cached.
     pub(crate) fn uv_rect(
         &mut self,
         glyph: &LayoutGlyph,
+        scale: f64,
         font_system: &mut FontSystem,
+        staging: &mut Staging,
     ) -> Result<GlyphData, Pierror> {
+        let (key, scale) = scaled_key(glyph.cache_key, scale);
         let alloc_to_rect = {
             let (width, height) = self.size;
             move |posn: &Position| {
@@ -129,89 +274,422 @@ impl<C: GpuContext + ?Sized> Atlas<C> {
 
                 GlyphData {
                     uv_rect,
-                    size: size.into(),
-                    offset: offset.into(),
+                    size: Size::from(size) / scale,
+                    offset: (Vec2::from(offset) / scale).to_point(),
                 }
             }
         };
 
-        let key = glyph.cache_key;
+        if let Some(posn) = self.glyphs.get_mut(&key) {
+            posn.last_used = self.frame;
+            return Ok(alloc_to_rect(posn));
+        }
+
+        // Get the swash image.
+        let sw_image = self.rasterize(key, font_system)?;
+        if matches!(sw_image.content, SwashContent::Color) {
+            self.promote(font_system, staging);
+        }
+
+        // Render it to a buffer.
+        let buffer = glyph_pixels(&sw_image, self.format, staging)?;
+        let (width, height) = (sw_image.placement.width, sw_image.placement.height);
+
+        // Glyphs without any pixels, like spaces, can't be allocated.
+        if width == 0 || height == 0 {
+            return Ok(GlyphData {
+                uv_rect: Rect::ZERO,
+                size: Size::ZERO,
+                offset: Point::new(
+                    sw_image.placement.left as f64 / scale,
+                    sw_image.placement.top as f64 / scale,
+                ),
+            });
+        }
+
+        // Find a place for it in the texture.
+        let alloc = match self.allocator.allocate(width, height) {
+            Some(alloc) => alloc,
+            None => {
+                // The atlas is full, so make room by evicting the glyphs that haven't been
+                // drawn in this frame, and repack the rest before the next frame.
+                self.evict_idle();
+                self.needs_compaction = true;
+
+                self.allocator.allocate(width, height).ok_or_else(|| {
+                    Pierror::BackendError("Failed to allocate glyph in texture atlas.".into())
+                })?
+            }
+        };
+
+        // Insert the glyph into the texture.
+        self.texture.write_subtexture(
+            (alloc.rectangle.min.x as u32, alloc.rectangle.min.y as u32),
+            (width, height),
+            self.format,
+            buffer,
+        );
+
+        // Insert the allocation into the map.
+        let alloc = self.glyphs.entry(key).or_insert(Position {
+            allocation: alloc,
+            placement: sw_image.placement,
+            last_used: self.frame,
+            pins: 0,
+        });
+
+        // Return the UV rectangle.
+        Ok(alloc_to_rect(alloc))
+    }
+
+    /// Rasterize a glyph on the CPU, without adding it to the atlas.
+    pub(crate) fn rasterize(
+        &mut self,
+        key: CacheKey,
+        font_system: &mut FontSystem,
+    ) -> Result<SwashImage, Pierror> {
+        self.swash_cache
+            .get_image_uncached(font_system, key)
+            .ok_or_else(|| {
+                Pierror::BackendError(format!("Failed to outline glyph {}", key.glyph_id).into())
+            })
+    }
+
+    /// Rasterize a glyph into the atlas if it isn't there yet, and keep it from being evicted
+    /// until it is unpinned as often as it was pinned.
+    ///
+    /// Glyphs without any pixels aren't kept in the atlas, so pinning them does nothing.
+    pub(crate) fn pin(
+        &mut self,
+        glyph: &LayoutGlyph,
+        scale: f64,
+        font_system: &mut FontSystem,
+        staging: &mut Staging,
+    ) -> Result<(), Pierror> {
+        self.uv_rect(glyph, scale, font_system, staging)?;
+
+        let (key, _) = scaled_key(glyph.cache_key, scale);
+        if let Some(posn) = self.glyphs.get_mut(&key) {
+            posn.pins += 1;
+        }
+        Ok(())
+    }
+
+    /// Undo one call to [`pin`](Self::pin) for a glyph.
+    pub(crate) fn unpin(&mut self, glyph: &LayoutGlyph, scale: f64) {
+        let (key, _) = scaled_key(glyph.cache_key, scale);
+        if let Some(posn) = self.glyphs.get_mut(&key) {
+            posn.pins = posn.pins.saturating_sub(1);
+        }
+    }
 
-        match self.glyphs.entry(key) {
-            Entry::Occupied(o) => {
-                let alloc = o.get();
-                Ok(alloc_to_rect(alloc))
+    /// Free the glyphs that haven't been drawn in the current frame, unless they are pinned.
+    ///
+    /// Their pixels are left in the texture, since nothing in this frame refers to them.
+    fn evict_idle(&mut self) {
+        let frame = self.frame;
+        let allocator = &mut self.allocator;
+
+        self.glyphs.retain(|_, posn| {
+            let keep = posn.last_used == frame || posn.pins > 0;
+            if !keep {
+                allocator.deallocate(&posn.allocation);
             }
+            keep
+        });
+    }
+
+    /// Repack every cached glyph into the atlas.
+    ///
+    /// Glyphs are inserted from tallest to shortest, which packs them onto shelves much more
+    /// tightly than the order in which they were first drawn. Pinned glyphs go first. Glyphs
+    /// that no longer fit are dropped, and will be rasterized again the next time they are
+    /// drawn.
+    pub(crate) fn compact(&mut self, font_system: &mut FontSystem, staging: &mut Staging) {
+        self.start_repack(0);
+        self.continue_repack(font_system, staging, usize::MAX);
+    }
+
+    /// Switch the atlas to premultiplied RGBA, so that it can hold color glyphs.
+    ///
+    /// Every cached glyph is rasterized again into the same place, so the UV rectangles that
+    /// were already handed out in this frame stay valid.
+    fn promote(&mut self, font_system: &mut FontSystem, staging: &mut Staging) {
+        if !matches!(self.format, piet::ImageFormat::Grayscale) {
+            return;
+        }
 
-            Entry::Vacant(v) => {
-                // Get the swash image.
-                let sw_image = self
-                    .swash_cache
-                    .get_image_uncached(font_system, glyph.cache_key)
-                    .ok_or_else(|| {
-                        Pierror::BackendError({
-                            format!("Failed to outline glyph {}", glyph.cache_key.glyph_id).into()
-                        })
-                    })?;
-
-                // Render it to a buffer.
-                let mut buffer = vec![
-                    0u32;
-                    sw_image.placement.width as usize
-                        * sw_image.placement.height as usize
-                ];
-                match sw_image.content {
-                    SwashContent::Color => {
-                        // Copy the color to the buffer.
-                        buffer
-                            .iter_mut()
-                            .zip(sw_image.data.chunks(4))
-                            .for_each(|(buf, input)| {
-                                let color =
-                                    u32::from_ne_bytes([input[0], input[1], input[2], input[3]]);
-                                *buf = color;
-                            });
-                    }
-                    SwashContent::Mask => {
-                        // Copy the mask to the buffer.
-                        buffer
-                            .iter_mut()
-                            .zip(sw_image.data.iter())
-                            .for_each(|(buf, input)| {
-                                let color = u32::from_ne_bytes([255, 255, 255, *input]);
-                                *buf = color;
-                            });
-                    }
-                    _ => return Err(Pierror::NotSupported),
+        // A repack in progress would be in the wrong format, so it is started over.
+        if self.repack.take().is_some() {
+            self.needs_compaction = true;
+        }
+
+        let format = piet::ImageFormat::RgbaPremul;
+        self.format = format;
+        self.texture.write_texture(self.size, format, None);
+
+        let Self {
+            texture,
+            allocator,
+            glyphs,
+            swash_cache,
+            ..
+        } = self;
+        glyphs.retain(|key, posn| {
+            let buffer = swash_cache
+                .get_image_uncached(font_system, *key)
+                .and_then(|sw_image| glyph_pixels(&sw_image, format, staging).ok());
+            match buffer {
+                Some(buffer) => {
+                    texture.write_subtexture(
+                        (
+                            posn.allocation.rectangle.min.x as u32,
+                            posn.allocation.rectangle.min.y as u32,
+                        ),
+                        (posn.placement.width, posn.placement.height),
+                        format,
+                        buffer,
+                    );
+                    true
                 }
+                None => {
+                    allocator.deallocate(&posn.allocation);
+                    false
+                }
+            }
+        });
+    }
 
-                let (width, height) = (sw_image.placement.width, sw_image.placement.height);
+    /// Start repacking the glyphs that were drawn in or after `min_frame` or are pinned into
+    /// a new texture, dropping the rest.
+    ///
+    /// A repack that is already in progress is started over.
+    fn start_repack(&mut self, min_frame: u64) {
+        self.repack = None;
 
-                // Find a place for it in the texture.
-                let alloc = self
-                    .allocator
-                    .allocate([width as i32, height as i32].into())
-                    .ok_or_else(|| {
-                        Pierror::BackendError("Failed to allocate glyph in texture atlas.".into())
-                    })?;
+        let texture = match Texture::new(
+            &self.context,
+            InterpolationMode::Bilinear,
+            RepeatStrategy::Color(piet::Color::TRANSPARENT),
+        ) {
+            Ok(texture) => texture.tracked(&self.memory, Category::GlyphAtlases),
+            Err(err) => {
+                warn!(
+                    "failed to create a texture to repack the atlas into: {}",
+                    err
+                );
+                return;
+            }
+        };
+        texture.write_texture(self.size, self.format, None);
+
+        let (strategy, options) = &self.config;
+        self.repack = Some(Repack {
+            texture,
+            allocator: Allocator::new(*strategy, self.size, options),
+            glyphs: HashMap::with_hasher(self.glyphs.hasher().clone()),
+            queue: Vec::new(),
+            min_frame,
+        });
+    }
 
-                // Insert the glyph into the texture.
-                self.texture.write_subtexture(
+    /// Move up to `budget` glyphs into the repacked texture, and switch over to it once
+    /// every live glyph has been moved.
+    fn continue_repack(
+        &mut self,
+        font_system: &mut FontSystem,
+        staging: &mut Staging,
+        budget: usize,
+    ) {
+        let Self {
+            glyphs: live,
+            swash_cache,
+            repack,
+            format,
+            ..
+        } = self;
+        let repack = match repack {
+            Some(repack) => repack,
+            None => return,
+        };
+
+        let mut moved = 0;
+        loop {
+            if repack.queue.is_empty() {
+                // Queue the glyphs that haven't been moved yet, including the ones that were
+                // drawn since the repack started. Glyphs are inserted from tallest to
+                // shortest, which packs them onto shelves much more tightly than the order in
+                // which they were first drawn, and pinned glyphs go first.
+                let mut queue = live
+                    .iter()
+                    .filter(|(key, posn)| {
+                        (posn.last_used >= repack.min_frame || posn.pins > 0)
+                            && !repack.glyphs.contains_key(*key)
+                    })
+                    .map(|(key, posn)| (*key, posn.placement, posn.pins))
+                    .collect::<Vec<_>>();
+                queue.sort_unstable_by_key(|&(_, placement, pins)| {
+                    (pins > 0, placement.height, placement.width)
+                });
+                repack.queue = queue.into_iter().map(|(key, ..)| key).collect();
+
+                if repack.queue.is_empty() {
+                    break;
+                }
+            }
+            if moved >= budget {
+                return;
+            }
+
+            let key = repack.queue.pop().unwrap();
+            moved += 1;
+            let posn = match live.get(&key) {
+                Some(posn) => posn,
+                None => continue,
+            };
+
+            // Glyphs that no longer fit are dropped, and are rasterized again the next time
+            // they are drawn.
+            let sw_image = match swash_cache.get_image_uncached(font_system, key) {
+                Some(image) => image,
+                None => continue,
+            };
+            let (width, height) = (sw_image.placement.width, sw_image.placement.height);
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let alloc = match repack.allocator.allocate(width, height) {
+                Some(alloc) => alloc,
+                None => continue,
+            };
+            if let Ok(buffer) = glyph_pixels(&sw_image, *format, staging) {
+                repack.texture.write_subtexture(
                     (alloc.rectangle.min.x as u32, alloc.rectangle.min.y as u32),
                     (width, height),
-                    piet::ImageFormat::RgbaPremul,
-                    bytemuck::cast_slice::<_, u8>(&buffer),
+                    *format,
+                    buffer,
                 );
-
-                // Insert the allocation into the map.
-                let alloc = v.insert(Position {
+            }
+            repack.glyphs.insert(
+                key,
+                Position {
                     allocation: alloc,
                     placement: sw_image.placement,
-                });
+                    last_used: posn.last_used,
+                    pins: posn.pins,
+                },
+            );
+        }
 
-                // Return the UV rectangle.
-                Ok(alloc_to_rect(alloc))
+        // Every glyph has been moved, so switch over to the new texture, keeping the usage of
+        // the glyphs up to date.
+        let repack = self.repack.take().unwrap();
+        let mut glyphs = repack.glyphs;
+        for (key, posn) in &mut glyphs {
+            if let Some(live) = self.glyphs.get(key) {
+                posn.last_used = live.last_used;
+                posn.pins = live.pins;
             }
         }
+
+        self.texture = Rc::new(repack.texture);
+        self.allocator = repack.allocator;
+        self.glyphs = glyphs;
+    }
+}
+
+/// Get the cache key for rasterizing a glyph at `scale` times its font size.
+///
+/// Returns the key along with the scale that it was actually rasterized at, which is limited
+/// by [`MAX_GLYPH_SIZE`].
+fn scaled_key(key: CacheKey, scale: f64) -> (CacheKey, f64) {
+    // The scale is rounded, so that zooming in doesn't rasterize every glyph again in each
+    // frame.
+    let scale = (scale * 4.0).round() / 4.0;
+    let font_size = f32::from_bits(key.font_size_bits);
+    if !(scale.is_finite() && scale > 0.0 && font_size > 0.0) || scale == 1.0 {
+        return (key, 1.0);
+    }
+
+    let scaled_size = (font_size * scale as f32).min(MAX_GLYPH_SIZE.max(font_size));
+    let key = CacheKey {
+        font_size_bits: scaled_size.to_bits(),
+        ..key
+    };
+    (key, (scaled_size / font_size) as f64)
+}
+
+/// Get the scale that every glyph in `glyphs` can be rasterized at, as close to `scale` as
+/// [`MAX_GLYPH_SIZE`] allows.
+///
+/// Like for the atlas, the scale is rounded to a quarter.
+pub(crate) fn common_scale<'a>(
+    glyphs: impl IntoIterator<Item = &'a LayoutGlyph>,
+    scale: f64,
+) -> f64 {
+    let scale = (scale * 4.0).round() / 4.0;
+    if !(scale.is_finite() && scale > 0.0) {
+        return 1.0;
+    }
+
+    glyphs.into_iter().fold(scale, |scale, glyph| {
+        let font_size = f32::from_bits(glyph.cache_key.font_size_bits);
+        if font_size > 0.0 {
+            scale.min((MAX_GLYPH_SIZE / font_size).max(1.0) as f64)
+        } else {
+            scale
+        }
+    })
+}
+
+/// Get the cache key for rasterizing a glyph at exactly `scale` times its font size.
+pub(crate) fn key_at_scale(key: CacheKey, scale: f64) -> CacheKey {
+    if scale == 1.0 {
+        return key;
+    }
+
+    let font_size = f32::from_bits(key.font_size_bits);
+    CacheKey {
+        font_size_bits: (font_size * scale as f32).to_bits(),
+        ..key
     }
 }
+
+/// Convert a rasterized glyph into pixels of the atlas format.
+fn glyph_pixels<'a>(
+    sw_image: &SwashImage,
+    format: piet::ImageFormat,
+    staging: &'a mut Staging,
+) -> Result<&'a [u8], Pierror> {
+    let buffer = staging.zeroed(
+        sw_image.placement.width as usize
+            * sw_image.placement.height as usize
+            * format.bytes_per_pixel(),
+    );
+    match (sw_image.content, format) {
+        (SwashContent::Mask, piet::ImageFormat::Grayscale) => {
+            // Copy the coverage to the buffer.
+            buffer
+                .iter_mut()
+                .zip(sw_image.data.iter())
+                .for_each(|(buf, input)| *buf = *input);
+        }
+        (SwashContent::Color, piet::ImageFormat::RgbaPremul) => {
+            // Copy the color to the buffer.
+            buffer
+                .iter_mut()
+                .zip(sw_image.data.iter())
+                .for_each(|(buf, input)| *buf = *input);
+        }
+        (SwashContent::Mask, piet::ImageFormat::RgbaPremul) => {
+            // Copy the mask to the buffer, as premultiplied white.
+            buffer
+                .chunks_exact_mut(4)
+                .zip(sw_image.data.iter())
+                .for_each(|(buf, input)| buf.fill(*input));
+        }
+        _ => return Err(Pierror::NotSupported),
+    }
+
+    Ok(buffer)
+}
@@ -23,16 +23,272 @@
 
 use super::gpu_backend::{GpuContext, RepeatStrategy, Vertex};
 use super::image::Image;
+use super::memory::{Category, MemoryTracker};
 use super::resources::Texture;
 use super::{RenderContext, ResultExt, UV_WHITE};
 
-use piet::kurbo::{Affine, Circle, Point, Rect, Shape};
-use piet::{Error as Pierror, FixedLinearGradient, FixedRadialGradient, Image as _};
+use ahash::RandomState;
+use hashbrown::HashMap;
+
+use piet::kurbo::{Affine, Circle, Point, Rect, Shape, Size, Vec2};
+use piet::{
+    Error as Pierror, FixedLinearGradient, FixedRadialGradient, GradientStop, GradientStops,
+    Image as _,
+};
 
 use std::borrow::Cow;
 use std::rc::Rc;
 
+/// The least number of pixels between two stops of a color ramp.
+const PIXELS_PER_STOP: f32 = 2.0;
+
+/// The number of color ramps to keep in the cache.
+const MAX_CACHED_RAMPS: usize = 64;
+
+/// How a gradient continues past its first and last stops.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum SpreadMode {
+    /// Continue with the colors of the first and last stops.
+    #[default]
+    Pad,
+
+    /// Start the gradient over from its first stop.
+    Repeat,
+
+    /// Run the gradient backwards and forwards, mirroring every other repetition.
+    Reflect,
+}
+
+impl SpreadMode {
+    /// Get the way a color ramp with this spread is sampled.
+    fn repeat_strategy(self) -> RepeatStrategy {
+        match self {
+            Self::Pad => RepeatStrategy::Clamp,
+            Self::Repeat => RepeatStrategy::Repeat,
+            Self::Reflect => RepeatStrategy::Reflect,
+        }
+    }
+
+    /// Get the equivalent `tiny-skia` spread mode.
+    pub(crate) fn to_tiny_skia(self) -> tiny_skia::SpreadMode {
+        match self {
+            Self::Pad => tiny_skia::SpreadMode::Pad,
+            Self::Repeat => tiny_skia::SpreadMode::Repeat,
+            Self::Reflect => tiny_skia::SpreadMode::Reflect,
+        }
+    }
+}
+
+/// The position and color of every stop of a ramp, and how it spreads.
+type RampKey = (Vec<(u32, u32)>, SpreadMode);
+
+/// The color ramps of linear gradients that were created recently.
+///
+/// Applications tend to create the same few gradients every frame, so ramps are keyed by
+/// their stops. Once the cache is full, the least recently used ramp is dropped.
+pub(crate) struct RampCache<C: GpuContext + ?Sized> {
+    /// The cached ramps, keyed by their stops and spread.
+    ramps: HashMap<RampKey, CachedRamp<C>, RandomState>,
+
+    /// Incremented every time a ramp is looked up.
+    clock: u64,
+
+    /// The tracker that the memory of ramps is counted in.
+    memory: Rc<MemoryTracker>,
+
+    /// The least number of pixels in a ramp.
+    min_width: u32,
+}
+
+/// A color ramp in the cache.
+struct CachedRamp<C: GpuContext + ?Sized> {
+    /// The color ramp.
+    ramp: Image<C>,
+
+    /// The value of the clock when the ramp was last used.
+    last_used: u64,
+}
+
+impl<C: GpuContext + ?Sized> RampCache<C> {
+    /// Create a new, empty cache.
+    pub(crate) fn new(memory: Rc<MemoryTracker>, min_width: u32, hasher: RandomState) -> Self {
+        Self {
+            ramps: HashMap::with_hasher(hasher),
+            clock: 0,
+            memory,
+            min_width,
+        }
+    }
+
+    /// Get the number of pixels in the ramp for `stops`.
+    ///
+    /// Ramps are made wide enough that stops that are close together don't blend into each
+    /// other, up to the largest texture the context supports.
+    fn width(&self, context: &C, stops: &[GradientStop]) -> u32 {
+        let min_gap = stops
+            .windows(2)
+            .map(|pair| pair[1].pos - pair[0].pos)
+            .filter(|&gap| gap > 0.0)
+            .fold(1.0, f32::min);
+        let width = self
+            .min_width
+            .max((PIXELS_PER_STOP / min_gap).ceil() as u32);
+
+        let max_width = context.max_texture_size().0.max(1);
+        if width > max_width {
+            warn!(
+                "a gradient needs a color ramp of {} pixels, but textures can only be {} wide",
+                width, max_width
+            );
+        }
+        width.min(max_width)
+    }
+
+    /// Get the color ramp for `stops`, writing it if it isn't cached.
+    fn get(
+        &mut self,
+        context: &Rc<C>,
+        stops: &[GradientStop],
+        spread: SpreadMode,
+    ) -> Result<Image<C>, Pierror> {
+        let key = stops
+            .iter()
+            .map(|stop| (stop.pos.to_bits(), stop.color.as_rgba_u32()))
+            .collect::<Vec<_>>();
+        let key = (key, spread);
+
+        self.clock += 1;
+        if let Some(cached) = self.ramps.get_mut(&key) {
+            cached.last_used = self.clock;
+            return Ok(cached.ramp.clone());
+        }
+
+        let texture = Texture::new(
+            context,
+            piet::InterpolationMode::Bilinear,
+            spread.repeat_strategy(),
+        )
+        .piet_err()?
+        .tracked(&self.memory, Category::Other);
+        let width = self.width(context, stops);
+        texture.write_linear_ramp(stops, width, spread)?;
+        let ramp = Image::new(texture, Size::new(width as f64, 1.0));
+
+        if self.ramps.len() >= MAX_CACHED_RAMPS {
+            let oldest = self
+                .ramps
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.ramps.remove(&oldest);
+            }
+        }
+
+        self.ramps.insert(
+            key,
+            CachedRamp {
+                ramp: ramp.clone(),
+                last_used: self.clock,
+            },
+        );
+        Ok(ramp)
+    }
+
+    /// Drop every cached ramp.
+    pub(crate) fn clear(&mut self) {
+        self.ramps.clear();
+    }
+}
+
+/// A shader that a backend compiled for use in [custom brushes](Brush::custom).
+///
+/// Backends hand these out when the code of a brush is registered with them. The number
+/// identifies the shader to the backend that created it, and means nothing to other backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CustomShader(u32);
+
+impl CustomShader {
+    /// Create a handle from the number that a backend identifies the shader by.
+    pub fn from_raw(id: u32) -> Self {
+        Self(id)
+    }
+
+    /// Get the number that the backend identifies the shader by.
+    pub fn into_raw(self) -> u32 {
+        self.0
+    }
+}
+
+/// The shader of a custom brush, along with its parameters.
+///
+/// This is passed to [`GpuContext::set_custom_brush`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct CustomBrush {
+    /// The shader that computes the color.
+    pub shader: CustomShader,
+
+    /// The parameters that are passed to the shader.
+    pub params: [f32; 8],
+}
+
+impl CustomBrush {
+    /// Create a new custom brush.
+    pub fn new(shader: CustomShader, params: [f32; 8]) -> Self {
+        Self { shader, params }
+    }
+}
+
+/// A gradient whose color changes with the angle around a center point.
+///
+/// The stops run clockwise from `start_angle`, with the first and last stops meeting there
+/// after a full turn. The gradient is rendered into a texture that covers the circle of
+/// `radius` around the center; outside of it, the colors at its edge are stretched outwards,
+/// so the radius should cover the shapes that are filled with it.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SweepGradient {
+    /// The point that the gradient sweeps around.
+    pub center: Point,
+
+    /// The angle at which the first stop starts, in radians clockwise from the x axis.
+    pub start_angle: f64,
+
+    /// The radius of the circle that the gradient is rendered for.
+    pub radius: f64,
+
+    /// The colors of the gradient, positioned from zero to one turn.
+    pub stops: Vec<GradientStop>,
+}
+
+impl SweepGradient {
+    /// Create a new sweep gradient that starts at the x axis.
+    pub fn new(center: impl Into<Point>, radius: f64, stops: impl GradientStops) -> Self {
+        Self {
+            center: center.into(),
+            start_angle: 0.0,
+            radius,
+            stops: stops.to_vec(),
+        }
+    }
+
+    /// Start the gradient at `angle`, in radians clockwise from the x axis.
+    pub fn with_start_angle(mut self, angle: f64) -> Self {
+        self.start_angle = angle;
+        self
+    }
+}
+
 /// The brush type used by the GPU renderer.
+///
+/// Gradients, images and custom shaders are anchored in user space: their coordinates are
+/// measured in the space that shapes are drawn in, so they move along with the shapes when
+/// the transform changes. Strokes that are built in device space, like
+/// [non-scaling strokes](RenderContext::set_non_scaling_stroke) and hairlines, are mapped back
+/// into user space before the brush is applied to them. Only
+/// [markers](RenderContext::draw_markers) apply the brush around their own origin.
 pub struct Brush<C: GpuContext + ?Sized>(BrushInner<C>);
 
 impl<C: GpuContext + ?Sized> Clone for Brush<C> {
@@ -50,8 +306,69 @@ enum BrushInner<C: GpuContext + ?Sized> {
         /// The image to apply.
         image: Image<C>,
 
-        /// The position to offset the gradient rectangle by.
-        offset: Point,
+        /// The rectangle that the image is stretched over.
+        bounds: Rect,
+
+        /// The color to multiply the texture by, with straight alpha.
+        tint: piet::Color,
+    },
+
+    /// A linear gradient, realized as a one-dimensional color ramp.
+    ///
+    /// The position along the gradient is an affine function of the vertex position, so it
+    /// is exact to compute it for every vertex and let the GPU interpolate it.
+    Linear {
+        /// The color ramp, from the start to the end of the gradient.
+        ramp: Image<C>,
+
+        /// The start of the gradient.
+        start: Point,
+
+        /// The end of the gradient.
+        end: Point,
+
+        /// How the ramp continues past its ends.
+        spread: SpreadMode,
+
+        /// The color to multiply the ramp by, with straight alpha.
+        tint: piet::Color,
+    },
+
+    /// A radial gradient, realized as a texture covering its circle.
+    ///
+    /// Radial gradients change in both directions, so they can't use a ramp. The texture is
+    /// clamped to its edges, which already have the color of the last stop. Gradients that
+    /// repeat cover the area of the target that was visible when they were created instead.
+    Radial {
+        /// The rendered gradient.
+        image: Image<C>,
+
+        /// The rectangle that the texture covers.
+        bounds: Rect,
+
+        /// The color to multiply the texture by, with straight alpha.
+        tint: piet::Color,
+    },
+
+    /// A sweep gradient, rendered into a texture like a radial gradient.
+    Sweep {
+        /// The rendered gradient.
+        image: Image<C>,
+
+        /// The rectangle that the texture covers.
+        bounds: Rect,
+
+        /// The color to multiply the texture by, with straight alpha.
+        tint: piet::Color,
+    },
+
+    /// A shader registered with the backend, which computes the color from the position.
+    Custom {
+        /// The shader and its parameters.
+        brush: CustomBrush,
+
+        /// The color to multiply the shader's color by, with straight alpha.
+        tint: piet::Color,
     },
 }
 
@@ -60,7 +377,7 @@ impl<C: GpuContext + ?Sized> piet::IntoBrush<RenderContext<'_, C>> for Brush<C>
         &'a self,
         _piet: &mut RenderContext<'_, C>,
         _bbox: impl FnOnce() -> Rect,
-    ) -> Cow<'a, <RenderContext<'_, C> as piet::RenderContext>::Brush> {
+    ) -> Cow<'a, Brush<C>> {
         Cow::Borrowed(self)
     }
 }
@@ -71,100 +388,344 @@ impl<C: GpuContext + ?Sized> Brush<C> {
         Self(BrushInner::Solid(color))
     }
 
+    /// Get the color of this brush, if it is a solid color.
+    pub(crate) fn solid_color(&self) -> Option<piet::Color> {
+        match self.0 {
+            BrushInner::Solid(color) => Some(color),
+            _ => None,
+        }
+    }
+
     /// Create a new brush from a linear gradient.
     pub(crate) fn linear_gradient(
         context: &Rc<C>,
+        ramps: &mut RampCache<C>,
         gradient: FixedLinearGradient,
+        spread: SpreadMode,
+    ) -> Result<Self, Pierror> {
+        Ok(Self(BrushInner::Linear {
+            ramp: ramps.get(context, &gradient.stops, spread)?,
+            start: gradient.start,
+            end: gradient.end,
+            spread,
+            tint: piet::Color::WHITE,
+        }))
+    }
+
+    /// Create a new brush from a radial gradient.
+    ///
+    /// `visible` is the area of the target in user space, and `scale` the number of pixels
+    /// per unit of user space; gradients that don't pad are rendered for that area.
+    pub(crate) fn radial_gradient(
+        context: &Rc<C>,
+        memory: &Rc<MemoryTracker>,
+        gradient: FixedRadialGradient,
+        spread: SpreadMode,
+        visible: Rect,
+        scale: f64,
     ) -> Result<Self, Pierror> {
         let texture = Texture::new(
             context,
             piet::InterpolationMode::Bilinear,
             RepeatStrategy::Clamp,
         )
-        .piet_err()?;
+        .piet_err()?
+        .tracked(memory, Category::Other);
 
-        let bounds = Rect::from_points(gradient.start, gradient.end);
-        let offset = -bounds.origin().to_vec2();
+        let (bounds, size) = match spread {
+            SpreadMode::Pad => {
+                // Round the size out to whole pixels, so that the texture covers the whole circle.
+                let bounds = Circle::new(gradient.center, gradient.radius).bounding_box();
+                let size = Size::new(
+                    bounds.width().ceil().max(1.0),
+                    bounds.height().ceil().max(1.0),
+                );
+                (Rect::from_origin_size(bounds.origin(), size), size)
+            }
 
-        texture.write_linear_gradient(&gradient, bounds.size(), offset)?;
-        Ok(Self::textured(texture, bounds))
+            SpreadMode::Repeat | SpreadMode::Reflect => {
+                // The gradient never ends, so render it at the resolution of the target.
+                let (max_width, max_height) = context.max_texture_size();
+                let size = Size::new(
+                    (visible.width() * scale)
+                        .ceil()
+                        .clamp(1.0, max_width as f64),
+                    (visible.height() * scale)
+                        .ceil()
+                        .clamp(1.0, max_height as f64),
+                );
+                (visible, size)
+            }
+        };
+
+        let transform =
+            Affine::scale_non_uniform(size.width / bounds.width(), size.height / bounds.height())
+                * Affine::translate(-bounds.origin().to_vec2());
+        texture.write_radial_gradient(&gradient, size, spread, transform)?;
+        Ok(Self(BrushInner::Radial {
+            image: Image::new(texture, size),
+            bounds,
+            tint: piet::Color::WHITE,
+        }))
     }
 
-    /// Create a new brush from a radial gradient.
-    pub(crate) fn radial_gradient(
+    /// Create a new brush from a sweep gradient.
+    pub(crate) fn sweep_gradient(
         context: &Rc<C>,
-        gradient: FixedRadialGradient,
+        memory: &Rc<MemoryTracker>,
+        gradient: &SweepGradient,
     ) -> Result<Self, Pierror> {
         let texture = Texture::new(
             context,
             piet::InterpolationMode::Bilinear,
             RepeatStrategy::Clamp,
         )
-        .piet_err()?;
+        .piet_err()?
+        .tracked(memory, Category::Other);
 
-        let bounds = Circle::new(gradient.center, gradient.radius).bounding_box();
+        let bounds = Circle::new(gradient.center, gradient.radius.abs()).bounding_box();
+        let size = Size::new(
+            bounds.width().ceil().max(1.0),
+            bounds.height().ceil().max(1.0),
+        );
         let offset = -bounds.origin().to_vec2();
 
-        texture.write_radial_gradient(&gradient, bounds.size(), offset)?;
-        Ok(Self::textured(texture, bounds))
+        texture.write_sweep_gradient(
+            gradient.center,
+            gradient.start_angle,
+            &gradient.stops,
+            size,
+            offset,
+        )?;
+        Ok(Self(BrushInner::Sweep {
+            image: Image::new(texture, size),
+            bounds: Rect::from_origin_size(bounds.origin(), size),
+            tint: piet::Color::WHITE,
+        }))
     }
 
-    /// Create a new brush from a texture.
-    fn textured(texture: Texture<C>, bounds: Rect) -> Self {
-        // Create a new image.
-        let image = Image::new(texture, bounds.size());
-
+    /// Create a brush that fills shapes with an image, stretched over `bounds`.
+    ///
+    /// Areas outside of `bounds` are transparent, unless the image is a
+    /// [`sub_image`](Image::sub_image), in which case they show the rest of its texture.
+    pub fn image(image: &Image<C>, bounds: Rect) -> Self {
         Self(BrushInner::Texture {
-            image,
-            offset: bounds.origin(),
+            image: image.clone(),
+            bounds,
+            tint: piet::Color::WHITE,
         })
     }
 
+    /// Create a brush that fills shapes with the color computed by a custom shader.
+    ///
+    /// The shader is called with the position in user space and `params`; see
+    /// [`BrushShaderInterface`](crate::BrushShaderInterface). Drawing with the brush fails
+    /// with [`NotSupported`](Pierror::NotSupported) if the backend doesn't know the shader,
+    /// and while a [`Scene`](crate::Scene) is being recorded.
+    pub fn custom(shader: CustomShader, params: [f32; 8]) -> Self {
+        Self(BrushInner::Custom {
+            brush: CustomBrush::new(shader, params),
+            tint: piet::Color::WHITE,
+        })
+    }
+
+    /// Get the shader and parameters of this brush, if it is a custom brush.
+    pub(crate) fn custom_brush(&self) -> Option<&CustomBrush> {
+        match self.0 {
+            BrushInner::Custom { ref brush, .. } => Some(brush),
+            _ => None,
+        }
+    }
+
+    /// Get a copy of this brush with its opacity multiplied by `alpha`.
+    ///
+    /// This works for every kind of brush, and doesn't require any textures to be
+    /// rewritten, so it is cheap enough to call once per frame for fades.
+    pub fn with_alpha(&self, alpha: f64) -> Self {
+        let alpha = alpha.clamp(0.0, 1.0);
+
+        self.map_color(|color| {
+            let (_, _, _, a) = color.as_rgba();
+            color.with_alpha(a * alpha)
+        })
+    }
+
+    /// Get a copy of this brush with its color multiplied by `color`.
+    ///
+    /// This tints gradients and images the same way that a solid brush is tinted, and fades
+    /// them using the alpha of `color`.
+    pub fn with_tint(&self, color: piet::Color) -> Self {
+        self.map_color(|tint| multiply_colors(tint, color))
+    }
+
+    /// Get a copy of this brush with its color, or the tint of its texture, changed.
+    fn map_color(&self, f: impl FnOnce(piet::Color) -> piet::Color) -> Self {
+        let mut brush = self.clone();
+        match &mut brush.0 {
+            BrushInner::Solid(color) => *color = f(*color),
+            BrushInner::Texture { tint, .. }
+            | BrushInner::Linear { tint, .. }
+            | BrushInner::Radial { tint, .. }
+            | BrushInner::Sweep { tint, .. }
+            | BrushInner::Custom { tint, .. } => *tint = f(*tint),
+        }
+        brush
+    }
+
     /// Get the texture associated with this brush.
     pub(crate) fn texture(&self, _size: (u32, u32)) -> Option<&Image<C>> {
         match self.0 {
-            BrushInner::Solid(_) => None,
-            BrushInner::Texture { ref image, .. } => Some(image),
+            BrushInner::Solid(_) | BrushInner::Custom { .. } => None,
+            BrushInner::Texture { ref image, .. }
+            | BrushInner::Radial { ref image, .. }
+            | BrushInner::Sweep { ref image, .. } => Some(image),
+            BrushInner::Linear { ref ramp, .. } => Some(ramp),
         }
     }
 
     /// Transform a two-dimensional point into a vertex using this brush.
     pub(crate) fn make_vertex(&self, point: [f32; 2]) -> Vertex {
+        let rgba = |color: piet::Color| {
+            let (r, g, b, a) = color.as_rgba8();
+            [r, g, b, a]
+        };
+
         match self.0 {
             BrushInner::Solid(color) => Vertex {
                 pos: point,
                 uv: UV_WHITE,
-                color: {
-                    let (r, g, b, a) = color.as_rgba8();
-                    [r, g, b, a]
-                },
+                color: rgba(color),
             },
 
-            BrushInner::Texture { ref image, offset } => {
-                // Create a transform to convert from image coordinates to
-                // UV coordinates.
-                let uv_transform =
-                    Affine::scale_non_uniform(1.0 / image.size().width, 1.0 / image.size().height)
-                        * Affine::translate(-offset.to_vec2());
-                let uv = uv_transform * Point::new(point[0] as f64, point[1] as f64);
+            BrushInner::Texture {
+                ref image,
+                bounds,
+                tint,
+            }
+            | BrushInner::Radial {
+                ref image,
+                bounds,
+                tint,
+            }
+            | BrushInner::Sweep {
+                ref image,
+                bounds,
+                tint,
+            } => {
+                let uv = image.uv_transform(bounds) * Point::new(point[0] as f64, point[1] as f64);
                 Vertex {
                     pos: point,
                     uv: [uv.x as f32, uv.y as f32],
-                    color: [0xFF, 0xFF, 0xFF, 0xFF],
+                    color: rgba(tint),
                 }
             }
+
+            BrushInner::Linear {
+                ref ramp,
+                start,
+                end,
+                spread,
+                tint,
+            } => {
+                // Project the point onto the gradient. A degenerate gradient uses its last
+                // color, like the other piet backends.
+                let axis = end - start;
+                let len2 = axis.hypot2();
+                let t = if len2 > 0.0 {
+                    let point = Point::new(point[0] as f64, point[1] as f64);
+                    (point - start).dot(axis) / len2
+                } else {
+                    1.0
+                };
+
+                // Map the ends of the gradient to the centers of the ramp's end pixels. `t` isn't
+                // clamped, since it is interpolated between vertices; the sampler clamps it.
+                // Ramps that repeat span the whole texture, so that the sampler wraps them.
+                let width = ramp.size().width;
+                let u = match spread {
+                    SpreadMode::Pad => (t * (width - 1.0) + 0.5) / width,
+                    SpreadMode::Repeat | SpreadMode::Reflect => t,
+                };
+                Vertex {
+                    pos: point,
+                    uv: [u as f32, 0.5],
+                    color: rgba(tint),
+                }
+            }
+
+            // The shader is given the position in user space.
+            BrushInner::Custom { tint, .. } => Vertex {
+                pos: point,
+                uv: point,
+                color: rgba(tint),
+            },
         }
     }
+
+    /// Make a vertex at a point in user space, with its position measured from `eye`.
+    pub(crate) fn make_vertex_from(&self, point: Point, eye: Vec2) -> Vertex {
+        let mut vertex = self.make_vertex([point.x as f32, point.y as f32]);
+        let pos = point - eye;
+        vertex.pos = [pos.x as f32, pos.y as f32];
+        vertex
+    }
 }
 
 impl<C: GpuContext + ?Sized> Clone for BrushInner<C> {
     fn clone(&self) -> Self {
         match self {
             Self::Solid(color) => Self::Solid(*color),
-            Self::Texture { image, offset } => Self::Texture {
+            Self::Texture {
+                image,
+                bounds,
+                tint,
+            } => Self::Texture {
                 image: image.clone(),
-                offset: *offset,
+                bounds: *bounds,
+                tint: *tint,
+            },
+            Self::Linear {
+                ramp,
+                start,
+                end,
+                spread,
+                tint,
+            } => Self::Linear {
+                ramp: ramp.clone(),
+                start: *start,
+                end: *end,
+                spread: *spread,
+                tint: *tint,
+            },
+            Self::Radial {
+                image,
+                bounds,
+                tint,
+            } => Self::Radial {
+                image: image.clone(),
+                bounds: *bounds,
+                tint: *tint,
+            },
+            Self::Sweep {
+                image,
+                bounds,
+                tint,
+            } => Self::Sweep {
+                image: image.clone(),
+                bounds: *bounds,
+                tint: *tint,
+            },
+            Self::Custom { brush, tint } => Self::Custom {
+                brush: *brush,
+                tint: *tint,
             },
         }
     }
 }
+
+/// Multiply two straight-alpha colors, channel by channel.
+fn multiply_colors(a: piet::Color, b: piet::Color) -> piet::Color {
+    let (r1, g1, b1, a1) = a.as_rgba();
+    let (r2, g2, b2, a2) = b.as_rgba();
+    piet::Color::rgba(r1 * r2, g1 * g2, b1 * b2, a1 * a2)
+}
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! The builder for the [`Source`] type.
+
+use super::gpu_backend::GpuContext;
+use super::Source;
+
+use ahash::RandomState;
+use etagere::{size2, AllocatorOptions};
+use piet::{Error as Pierror, InterpolationMode};
+
+use std::rc::Rc;
+
+/// Fixed seeds used for hashers when deterministic mode is enabled.
+const DETERMINISTIC_SEEDS: [u64; 4] = [
+    0x243f_6a88_85a3_08d3,
+    0x1319_8a2e_0370_7344,
+    0xa409_3822_299f_31d0,
+    0x082e_fa98_ec4e_6c89,
+];
+
+/// How subpaths are closed when they are stroked.
+///
+/// Fills always close every subpath, so this only affects strokes.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub enum SubpathClosing {
+    /// Only close subpaths that end with [`PathEl::ClosePath`].
+    ///
+    /// This matches `kurbo` and the other `piet` backends. An open subpath whose last point
+    /// is the same as its first point is drawn with caps at both ends rather than a join.
+    ///
+    /// [`PathEl::ClosePath`]: piet::kurbo::PathEl::ClosePath
+    #[default]
+    Explicit,
+
+    /// Also close subpaths whose last point is within this many pixels of their first point.
+    ///
+    /// This is useful for paths built by hand that return to their starting point without
+    /// a `ClosePath`, which would otherwise have a visible notch at the start. The distance
+    /// is measured on the target, so endpoints that are distinct when zoomed in aren't joined.
+    Coincident(f64),
+}
+
+/// The strategy used to pack glyphs into the text atlas.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum AtlasStrategy {
+    /// Pack glyphs onto shelves, which are split into items as needed.
+    ///
+    /// This packs glyphs of similar heights tightly.
+    #[default]
+    Shelf,
+
+    /// Pack glyphs onto shelves of fixed-size buckets.
+    ///
+    /// This is faster than [`AtlasStrategy::Shelf`] and handles many small glyphs well, but
+    /// wastes more space when glyph sizes vary.
+    Bucketed,
+}
+
+/// The resolution that clipping masks are rasterized at.
+///
+/// Masks are upsampled with bilinear filtering when they are drawn, so lower resolutions work
+/// well for soft or curved clips, but blur the edges of sharp ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MaskResolution {
+    /// Rasterize masks at the size of the target.
+    #[default]
+    Full,
+
+    /// Rasterize masks at half the width and height of the target.
+    Half,
+
+    /// Rasterize masks at a quarter of the width and height of the target.
+    Quarter,
+}
+
+impl MaskResolution {
+    /// The amount that each dimension of the target is divided by.
+    pub(crate) fn divisor(self) -> u32 {
+        match self {
+            Self::Full => 1,
+            Self::Half => 2,
+            Self::Quarter => 4,
+        }
+    }
+}
+
+/// How the edges of shapes are antialiased.
+///
+/// Multisampling is a property of the target, so it is set up by the backend. The strategy
+/// is checked against the target when the [`Source`] is created, and
+/// [`Source::antialiasing`] reports the one that ended up being used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Antialiasing {
+    /// Don't antialias edges, beyond what the target does on its own.
+    ///
+    /// This is the cheapest option, which suits embedded devices.
+    None,
+
+    /// Use multisampling with at least this many samples per pixel.
+    ///
+    /// If the target isn't multisampled, this falls back to [`Antialiasing::None`]. When it
+    /// is, the sample count of the target is reported, even if it differs from this one.
+    Msaa(u32),
+
+    /// Fade shapes out over a one pixel fringe outside of their edges.
+    ///
+    /// This works on any target, at the cost of some extra geometry. Since the fringe lies
+    /// outside of the shape, pixel-aligned edges bleed into the pixels next to them, and
+    /// overlapping parts of the fringe can make the edges of self-intersecting shapes
+    /// slightly darker. Cached paths are tessellated again when they are drawn, so this has
+    /// to be opted into.
+    Feather,
+}
+
+impl Default for Antialiasing {
+    fn default() -> Self {
+        Self::Msaa(4)
+    }
+}
+
+impl Antialiasing {
+    /// Choose the strategy to use on a target with `samples` samples per pixel.
+    pub(crate) fn resolve(self, samples: u32) -> Self {
+        match self {
+            _ if samples > 1 => Self::Msaa(samples),
+            Self::Msaa(_) => Self::None,
+            strategy => strategy,
+        }
+    }
+}
+
+/// A builder for a [`Source`].
+///
+/// This can be used to configure the renderer before it is created.
+#[derive(Debug, Clone, Default)]
+pub struct SourceBuilder {
+    /// Whether or not to render deterministically.
+    deterministic: bool,
+
+    /// How subpaths are closed.
+    subpath_closing: SubpathClosing,
+
+    /// How glyphs are packed into the atlas.
+    atlas_strategy: AtlasStrategy,
+
+    /// Options for the atlas allocator.
+    atlas_options: AllocatorOptions,
+
+    /// The resolution of clipping masks.
+    mask_resolution: MaskResolution,
+
+    /// Don't combine clips on the CPU.
+    no_clip_combining: bool,
+
+    /// Sample images with nearest-neighbor interpolation by default.
+    nearest_images: bool,
+
+    /// How the edges of shapes are antialiased.
+    antialiasing: Antialiasing,
+
+    /// The least number of pixels in the color ramps of linear gradients.
+    gradient_resolution: Option<u32>,
+
+    /// The number of fills whose tessellations are kept for reuse.
+    tessellation_cache: Option<usize>,
+}
+
+impl SourceBuilder {
+    /// Create a new builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable deterministic rendering.
+    ///
+    /// When enabled, all internal hash maps use fixed seeds, so glyph placement in the
+    /// atlas and the order in which cached resources are visited are the same between
+    /// runs. This is useful for golden-image testing. Defaults to `false`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Set how subpaths are closed when they are stroked.
+    ///
+    /// Defaults to [`SubpathClosing::Explicit`].
+    pub fn subpath_closing(mut self, subpath_closing: SubpathClosing) -> Self {
+        self.subpath_closing = subpath_closing;
+        self
+    }
+
+    /// Set the strategy used to pack glyphs into the text atlas.
+    ///
+    /// Defaults to [`AtlasStrategy::Shelf`].
+    pub fn atlas_strategy(mut self, strategy: AtlasStrategy) -> Self {
+        self.atlas_strategy = strategy;
+        self
+    }
+
+    /// Set the number of columns the text atlas is split into.
+    ///
+    /// More columns means more, shorter shelves, which reduces fragmentation when small and
+    /// large glyphs are mixed. Defaults to 1.
+    pub fn atlas_columns(mut self, columns: u32) -> Self {
+        self.atlas_options.num_columns = columns.clamp(1, i32::MAX as u32) as i32;
+        self
+    }
+
+    /// Use vertical shelves instead of horizontal ones in the text atlas.
+    ///
+    /// Defaults to `false`.
+    pub fn atlas_vertical_shelves(mut self, vertical: bool) -> Self {
+        self.atlas_options.vertical_shelves = vertical;
+        self
+    }
+
+    /// Round the sizes of glyphs in the text atlas up to a multiple of this alignment.
+    ///
+    /// Coarser alignment makes freed space easier to reuse, at the cost of some padding.
+    /// Defaults to `(1, 1)`.
+    pub fn atlas_alignment(mut self, width: u32, height: u32) -> Self {
+        self.atlas_options.alignment = size2(
+            width.clamp(1, i32::MAX as u32) as i32,
+            height.clamp(1, i32::MAX as u32) as i32,
+        );
+        self
+    }
+
+    /// Set the resolution that clipping masks are rasterized at.
+    ///
+    /// Lower resolutions make clipping cheaper to rasterize and upload, which matters for
+    /// large targets. Defaults to [`MaskResolution::Full`].
+    pub fn mask_resolution(mut self, resolution: MaskResolution) -> Self {
+        self.mask_resolution = resolution;
+        self
+    }
+
+    /// Enable or disable combining clips on the CPU.
+    ///
+    /// When enabled, clips to axis-aligned rectangles are intersected with each other and with
+    /// the next path directly. A stack of rectangles followed by one arbitrary path is
+    /// rasterized into the clipping mask once, but every clip after that path is still
+    /// rasterized and intersected with the mask on its own. Defaults to `true`.
+    pub fn clip_combining(mut self, combine: bool) -> Self {
+        self.no_clip_combining = !combine;
+        self
+    }
+
+    /// Set the interpolation that images are sampled with by default.
+    ///
+    /// This applies to images used as brushes, since `draw_image` is always given an
+    /// interpolation mode. Use [`InterpolationMode::NearestNeighbor`] for pixel art, so that
+    /// images scaled by whole numbers stay sharp. Defaults to [`InterpolationMode::Bilinear`].
+    pub fn image_interpolation(mut self, interpolation: InterpolationMode) -> Self {
+        self.nearest_images = interpolation == InterpolationMode::NearestNeighbor;
+        self
+    }
+
+    /// Set how the edges of shapes are antialiased.
+    ///
+    /// This falls back to another strategy if the target doesn't support it; see
+    /// [`Antialiasing`]. Defaults to [`Antialiasing::Msaa`] with four samples, which leaves
+    /// edges aliased on targets that aren't multisampled.
+    pub fn antialiasing(mut self, antialiasing: Antialiasing) -> Self {
+        self.antialiasing = antialiasing;
+        self
+    }
+
+    /// Set the least number of pixels in the color ramps of linear gradients.
+    ///
+    /// Gradients are looked up in a ramp, which is widened for gradients with stops that are
+    /// closer together than this allows, up to the largest texture the GPU supports. Higher
+    /// resolutions keep long gradients smooth, while lower ones save memory. Defaults to 256.
+    pub fn gradient_resolution(mut self, pixels: u32) -> Self {
+        self.gradient_resolution = Some(pixels.max(1));
+        self
+    }
+
+    /// Set the number of fills whose tessellations are kept for reuse.
+    ///
+    /// Filling a shape with the same path elements, fill rule and tolerance as one of the
+    /// recently filled ones skips tessellating it, which saves a lot of time in animations
+    /// that draw the same shapes every frame. Set this to zero to disable the cache. Defaults
+    /// to 256.
+    pub fn tessellation_cache(mut self, fills: usize) -> Self {
+        self.tessellation_cache = Some(fills);
+        self
+    }
+
+    /// Build a [`Source`] from a GPU context.
+    pub fn build<C: GpuContext>(self, context: C) -> Result<Source<C>, Pierror> {
+        self.build_from_rc(Rc::new(context))
+    }
+
+    /// Build a [`Source`] from a GPU context wrapped in an `Rc`.
+    pub fn build_from_rc<C: GpuContext + ?Sized>(
+        self,
+        context: Rc<C>,
+    ) -> Result<Source<C>, Pierror> {
+        Source::with_builder(context, self)
+    }
+
+    /// Create the hasher state used for the maps inside of the renderer.
+    pub(crate) fn random_state(&self) -> RandomState {
+        if self.deterministic {
+            let [k0, k1, k2, k3] = DETERMINISTIC_SEEDS;
+            RandomState::with_seeds(k0, k1, k2, k3)
+        } else {
+            RandomState::new()
+        }
+    }
+
+    /// Whether deterministic rendering is enabled.
+    pub(crate) fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// How subpaths are closed.
+    pub(crate) fn closing(&self) -> SubpathClosing {
+        self.subpath_closing
+    }
+
+    /// The resolution of clipping masks.
+    pub(crate) fn mask_scale(&self) -> MaskResolution {
+        self.mask_resolution
+    }
+
+    /// Whether clips are combined on the CPU.
+    pub(crate) fn combines_clips(&self) -> bool {
+        !self.no_clip_combining
+    }
+
+    /// The interpolation that images are sampled with by default.
+    pub(crate) fn default_interpolation(&self) -> InterpolationMode {
+        if self.nearest_images {
+            InterpolationMode::NearestNeighbor
+        } else {
+            InterpolationMode::Bilinear
+        }
+    }
+
+    /// The antialiasing strategy that was asked for.
+    pub(crate) fn antialiasing_strategy(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// The least number of pixels in the color ramps of linear gradients.
+    pub(crate) fn ramp_width(&self) -> u32 {
+        self.gradient_resolution.unwrap_or(256)
+    }
+
+    /// The number of fills whose tessellations are cached.
+    pub(crate) fn fill_cache_size(&self) -> usize {
+        self.tessellation_cache.unwrap_or(256)
+    }
+
+    /// How glyphs are packed into the atlas.
+    pub(crate) fn atlas_config(&self) -> (AtlasStrategy, AllocatorOptions) {
+        (self.atlas_strategy, self.atlas_options)
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Paths that are tessellated once and kept on the GPU.
+
+use super::brush::Brush;
+use super::gpu_backend::{GpuContext, Vertex};
+use super::memory::{Category, MemoryTracker};
+use super::rasterizer::Precision;
+use super::resources::VertexBuffer;
+use super::ResultExt;
+
+use piet::kurbo::{BezPath, Point, Rect, Vec2};
+use piet::{Error as Pierror, StrokeStyle};
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+/// A path whose triangles are tessellated once and kept on the GPU, to be drawn again in
+/// later frames.
+///
+/// Cached paths are created with [`RenderContext::make_cached_path`] and drawn with
+/// [`RenderContext::fill_cached`] and [`RenderContext::stroke_cached`]. The fill is
+/// tessellated right away, and the stroke the first time it is drawn with a width and
+/// style. Drawing a cached path again only writes its vertices to the GPU when the brush
+/// changes, so an application that redraws the same icons or glyph outlines every frame
+/// decides for itself which geometry is reused, instead of relying on
+/// [`SourceBuilder::tessellation_cache`].
+///
+/// Like a [`Scene`], the path is drawn in user space with the current transform. Curves are
+/// flattened finely enough for the transform the path was made with, so drawing it scaled
+/// up further shows their segments.
+///
+/// [`RenderContext::make_cached_path`]: crate::RenderContext::make_cached_path
+/// [`RenderContext::fill_cached`]: crate::RenderContext::fill_cached
+/// [`RenderContext::stroke_cached`]: crate::RenderContext::stroke_cached
+/// [`SourceBuilder::tessellation_cache`]: crate::SourceBuilder::tessellation_cache
+/// [`Scene`]: crate::Scene
+pub struct CachedPath<C: GpuContext + ?Sized> {
+    /// The path, in user space.
+    path: BezPath,
+
+    /// The bounding box of the path.
+    bounds: Rect,
+
+    /// How finely the path is flattened.
+    precision: Precision,
+
+    /// The triangles of the fill.
+    fill: Geometry<C>,
+
+    /// The triangles of the stroke that was drawn last.
+    stroke: RefCell<Option<CachedStroke<C>>>,
+}
+
+/// The triangles of a stroke, with the width and style it was tessellated with.
+struct CachedStroke<C: GpuContext + ?Sized> {
+    /// The width of the stroke.
+    width: f64,
+
+    /// The style of the stroke.
+    style: StrokeStyle,
+
+    /// The triangles of the stroke.
+    geometry: Rc<Geometry<C>>,
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for CachedPath<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedPath")
+            .field("bounds", &self.bounds)
+            .field("triangles", &(self.fill.indices.len() / 3))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: GpuContext + ?Sized> CachedPath<C> {
+    /// Create a cached path from its tessellated fill.
+    pub(crate) fn new(path: BezPath, precision: Precision, fill: Geometry<C>) -> Self {
+        Self {
+            bounds: piet::kurbo::Shape::bounding_box(&path),
+            path,
+            precision,
+            fill,
+            stroke: RefCell::new(None),
+        }
+    }
+
+    /// Get the path, in user space.
+    pub fn path(&self) -> &BezPath {
+        &self.path
+    }
+
+    /// Get the bounding box of the path, in user space.
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Get how finely the path is flattened.
+    pub(crate) fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Get the triangles of the fill.
+    pub(crate) fn fill(&self) -> &Geometry<C> {
+        &self.fill
+    }
+
+    /// Get the triangles of the stroke with this width and style, if they were kept.
+    pub(crate) fn stroke(&self, width: f64, style: &StrokeStyle) -> Option<Rc<Geometry<C>>> {
+        self.stroke
+            .borrow()
+            .as_ref()
+            .filter(|stroke| stroke.width == width && stroke.style == *style)
+            .map(|stroke| stroke.geometry.clone())
+    }
+
+    /// Keep the triangles of a stroke, replacing the ones that were kept before.
+    pub(crate) fn set_stroke(&self, width: f64, style: &StrokeStyle, geometry: Rc<Geometry<C>>) {
+        *self.stroke.borrow_mut() = Some(CachedStroke {
+            width,
+            style: style.clone(),
+            geometry,
+        });
+    }
+}
+
+/// Triangles in user space, with a vertex buffer on the GPU that they are written to.
+pub(crate) struct Geometry<C: GpuContext + ?Sized> {
+    /// The positions of the vertices.
+    points: Vec<Point>,
+
+    /// The indices of the triangles.
+    indices: Vec<u32>,
+
+    /// The buffer that the vertices are written to.
+    vbo: VertexBuffer<C>,
+
+    /// The solid color and opacity that the buffer was last written with, if any.
+    written: Cell<Option<(u32, u64)>>,
+}
+
+impl<C: GpuContext + ?Sized> Geometry<C> {
+    /// Create the geometry of tessellated triangles.
+    pub(crate) fn new(
+        context: &Rc<C>,
+        memory: &Rc<MemoryTracker>,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<Self, Pierror> {
+        let vbo = VertexBuffer::new(context)
+            .piet_err()?
+            .tracked(memory, Category::VertexBuffers);
+        let points = vertices
+            .iter()
+            .map(|vertex| Point::new(vertex.pos[0] as f64, vertex.pos[1] as f64))
+            .collect();
+
+        Ok(Self {
+            points,
+            indices: indices.to_vec(),
+            vbo,
+            written: Cell::new(None),
+        })
+    }
+
+    /// Whether there are no triangles to draw.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Write the vertices for a brush and opacity into the buffer, unless they are already
+    /// there, and get the buffer.
+    ///
+    /// Only solid colors are remembered, since the vertices of other brushes depend on where
+    /// the brush is.
+    pub(crate) fn write(&self, brush: &Brush<C>, alpha: f64) -> &C::VertexBuffer {
+        let key = brush
+            .solid_color()
+            .map(|color| (color.as_rgba_u32(), alpha.to_bits()));
+        if key.is_none() || self.written.get() != key {
+            let vertices: Vec<_> = self
+                .points
+                .iter()
+                .map(|&point| {
+                    let mut vertex = brush.make_vertex_from(point, Vec2::ZERO);
+                    vertex.color[3] = (vertex.color[3] as f64 * alpha).round() as u8;
+                    vertex
+                })
+                .collect();
+            self.vbo.upload(&vertices, &self.indices);
+            self.written.set(key);
+        }
+
+        self.vbo.resource()
+    }
+}
@@ -0,0 +1,1755 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Capture the calls made to a [`GpuContext`] during a frame, and replay them.
+
+use crate::brush::{CustomBrush, CustomShader};
+use crate::filter::ColorMatrix;
+use crate::gpu_backend::{
+    ClipShape, CompositeMode, GpuContext, PresentMode, RepeatStrategy, Vertex,
+};
+use crate::projection::Projection;
+
+use piet::kurbo::{Affine, Rect};
+use piet::{Color, Error as Pierror, ImageFormat, InterpolationMode};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::str::{FromStr, SplitWhitespace};
+
+/// The first line of every capture.
+const HEADER: &str = "piet-hardware capture 1";
+
+/// A [`GpuContext`] that can write the calls made during a frame to a file.
+///
+/// Wrap a backend in this type and call [`capture_next_frame`](Self::capture_next_frame), for
+/// instance from a debug key binding. Every call from the next one up to and including
+/// [`present`](GpuContext::present) is then written out in a line-based text format, which
+/// [`replay`] sends to any other [`GpuContext`]. This way a rendering glitch can be reproduced
+/// without the application that caused it.
+///
+/// To make captures self-contained, this keeps a copy of the data last written to each texture
+/// and vertex buffer, and writes it out at the start of the capture. Writes to parts of a
+/// texture are folded into that copy, so it takes as much memory as the texture itself. What was drawn into render
+/// targets before the capture started can't be reproduced.
+pub struct CaptureContext<C: GpuContext> {
+    /// The context that does the actual work.
+    inner: C,
+
+    /// The resources that exist, and the capture in progress.
+    state: RefCell<State>,
+}
+
+impl<C: GpuContext + fmt::Debug> fmt::Debug for CaptureContext<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureContext")
+            .field("inner", &self.inner)
+            .field("capturing", &self.is_capturing())
+            .finish()
+    }
+}
+
+impl<C: GpuContext> CaptureContext<C> {
+    /// Wrap a context.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            state: RefCell::new(State::default()),
+        }
+    }
+
+    /// Get a reference to the wrapped context.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the context.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Write the calls of the next frame to `writer`.
+    ///
+    /// Capturing starts at the next call to the context, so this should be called between
+    /// frames, and stops after the next call to [`present`](GpuContext::present). If
+    /// `elide_pixels` is set, the contents of textures are left out; this keeps the capture
+    /// small and free of user images, which are replayed as opaque white instead.
+    ///
+    /// Errors while writing are logged, and end the capture.
+    pub fn capture_next_frame(&self, writer: impl Write + 'static, elide_pixels: bool) {
+        self.state.borrow_mut().requested = Some(Capture {
+            writer: Box::new(writer),
+            elide_pixels,
+        });
+    }
+
+    /// Whether a frame is being captured, or will be captured.
+    pub fn is_capturing(&self) -> bool {
+        let state = self.state.borrow();
+        state.requested.is_some() || state.capture.is_some()
+    }
+
+    /// Record a call that was made to the inner context.
+    fn record(&self, call: Call) {
+        self.state.borrow_mut().record(call);
+    }
+
+    /// Create an ID for a new resource.
+    fn next_id(&self) -> u32 {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        state.next_id
+    }
+}
+
+/// A texture created by a [`CaptureContext`].
+pub struct CaptureTexture<T> {
+    /// The ID that the texture has in captures.
+    id: u32,
+
+    /// The texture of the inner context.
+    inner: T,
+}
+
+impl<T> fmt::Debug for CaptureTexture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureTexture")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> CaptureTexture<T> {
+    /// Get a reference to the texture of the wrapped context.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A vertex buffer created by a [`CaptureContext`].
+pub struct CaptureVertexBuffer<B> {
+    /// The ID that the buffer has in captures.
+    id: u32,
+
+    /// The buffer of the inner context.
+    inner: B,
+}
+
+impl<B> fmt::Debug for CaptureVertexBuffer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureVertexBuffer")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> CaptureVertexBuffer<B> {
+    /// Get a reference to the vertex buffer of the wrapped context.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+impl<C: GpuContext> GpuContext for CaptureContext<C> {
+    type Texture = CaptureTexture<C::Texture>;
+    type VertexBuffer = CaptureVertexBuffer<C::VertexBuffer>;
+    type Error = C::Error;
+
+    fn clear(&self, color: Color) {
+        self.inner.clear(color);
+        self.record(Call::Clear(color));
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.inner.flush()?;
+        self.record(Call::Flush);
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        let inner = self.inner.create_texture(interpolation, repeat)?;
+        let id = self.next_id();
+        self.record(Call::CreateTexture {
+            id,
+            interpolation,
+            repeat,
+        });
+        Ok(CaptureTexture { id, inner })
+    }
+
+    fn delete_texture(&self, texture: Self::Texture) {
+        self.inner.delete_texture(texture.inner);
+        self.record(Call::DeleteTexture(texture.id));
+    }
+
+    fn write_texture(
+        &self,
+        texture: &Self::Texture,
+        size: (u32, u32),
+        format: ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        self.inner.write_texture(&texture.inner, size, format, data);
+        self.record(Call::WriteTexture {
+            id: texture.id,
+            size,
+            format,
+            data: data.map(<[u8]>::to_vec),
+        });
+    }
+
+    fn write_subtexture(
+        &self,
+        texture: &Self::Texture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: ImageFormat,
+        data: &[u8],
+    ) {
+        self.inner
+            .write_subtexture(&texture.inner, offset, size, format, data);
+        self.record(Call::WriteSubtexture {
+            id: texture.id,
+            offset,
+            size,
+            format,
+            data: data.to_vec(),
+        });
+    }
+
+    fn set_texture_interpolation(&self, texture: &Self::Texture, interpolation: InterpolationMode) {
+        self.inner
+            .set_texture_interpolation(&texture.inner, interpolation);
+        self.record(Call::SetTextureInterpolation {
+            id: texture.id,
+            interpolation,
+        });
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        self.inner.max_texture_size()
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        let inner = self.inner.create_vertex_buffer()?;
+        let id = self.next_id();
+        self.record(Call::CreateVertexBuffer(id));
+        Ok(CaptureVertexBuffer { id, inner })
+    }
+
+    fn delete_vertex_buffer(&self, buffer: Self::VertexBuffer) {
+        self.inner.delete_vertex_buffer(buffer.inner);
+        self.record(Call::DeleteVertexBuffer(buffer.id));
+    }
+
+    fn write_vertices(&self, buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        self.inner.write_vertices(&buffer.inner, vertices, indices);
+        self.record(Call::WriteVertices {
+            id: buffer.id,
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+        });
+    }
+
+    fn push_buffers(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        current_texture: &Self::Texture,
+        mask_texture: &Self::Texture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.push_buffers(
+            &vertex_buffer.inner,
+            &current_texture.inner,
+            &mask_texture.inner,
+            transform,
+            size,
+        )?;
+        self.record(Call::PushBuffers {
+            buffer: vertex_buffer.id,
+            texture: current_texture.id,
+            mask: mask_texture.id,
+            transform: *transform,
+            size,
+        });
+        Ok(())
+    }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        let supported = self.inner.set_color_matrix(matrix);
+        if supported {
+            self.record(Call::SetColorMatrix(matrix.copied()));
+        }
+        supported
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        let supported = self.inner.set_composite_mode(mode);
+        if supported {
+            self.record(Call::SetCompositeMode(mode));
+        }
+        supported
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        let supported = self.inner.set_clip_shape(shape);
+        if supported {
+            self.record(Call::SetClipShape(shape.copied()));
+        }
+        supported
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        let supported = self.inner.set_scissor(rect);
+        if supported {
+            self.record(Call::SetScissor(rect));
+        }
+        supported
+    }
+
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        let supported = self.inner.set_projection(projection);
+        if supported {
+            self.record(Call::SetProjection(projection.copied()));
+        }
+        supported
+    }
+
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        let supported = self.inner.set_custom_brush(brush);
+        if supported {
+            self.record(Call::SetCustomBrush(brush.copied()));
+        }
+        supported
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        let supported = self.inner.set_sampler_interpolation(interpolation);
+        if supported {
+            self.record(Call::SetSamplerInterpolation(interpolation));
+        }
+        supported
+    }
+
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        let supported = self.inner.generate_mipmaps(&texture.inner);
+        if supported {
+            self.record(Call::GenerateMipmaps(texture.id));
+        }
+        supported
+    }
+
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.read_pixels(origin, size, target_size, data)?;
+        self.record(Call::ReadPixels {
+            origin,
+            size,
+            target_size,
+        });
+        Ok(supported)
+    }
+
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: Color,
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.clear_rect(origin, size, target_size, color)?;
+        if supported {
+            self.record(Call::ClearRect {
+                origin,
+                size,
+                target_size,
+                color,
+            });
+        }
+        Ok(supported)
+    }
+
+    fn show_overlay(&self, _texture: &Self::Texture, _source: Rect, _rect: Rect) -> bool {
+        // A replay can't show overlays, so the image is drawn into the capture instead.
+        false
+    }
+
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<Self::Texture>, Self::Error> {
+        let inner = match self.inner.create_render_target(size)? {
+            Some(inner) => inner,
+            None => return Ok(None),
+        };
+        let id = self.next_id();
+        self.record(Call::CreateRenderTarget { id, size });
+        Ok(Some(CaptureTexture { id, inner }))
+    }
+
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        self.inner
+            .set_render_target(target.map(|target| &target.inner))?;
+        self.record(Call::SetRenderTarget(target.map(|target| target.id)));
+        Ok(())
+    }
+
+    fn render_targets_flipped(&self) -> bool {
+        self.inner.render_targets_flipped()
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        self.inner.msaa_samples()
+    }
+
+    fn begin_occlusion_query(&self, id: u64) -> bool {
+        self.inner.begin_occlusion_query(id)
+    }
+
+    fn end_occlusion_query(&self, id: u64) {
+        self.inner.end_occlusion_query(id)
+    }
+
+    fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+        self.inner.occlusion_query_result(id)
+    }
+
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+        let supported = self.inner.create_depth_stencil(size)?;
+        if supported {
+            self.record(Call::CreateDepthStencil(size));
+        }
+        Ok(supported)
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        self.inner.attach_depth_stencil(attach)?;
+        self.record(Call::AttachDepthStencil(attach));
+        Ok(())
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.push_clip_path(&vertex_buffer.inner, size)?;
+        if supported {
+            self.record(Call::PushClipPath {
+                buffer: vertex_buffer.id,
+                size,
+            });
+        }
+        Ok(supported)
+    }
+
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.pop_clip(&vertex_buffer.inner, size)?;
+        self.record(Call::PopClip {
+            buffer: vertex_buffer.id,
+            size,
+        });
+        Ok(())
+    }
+
+    fn present(&self) -> Result<(), Self::Error> {
+        self.inner.present()?;
+        self.record(Call::Present);
+        Ok(())
+    }
+
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        // This doesn't change what is drawn, so it isn't captured.
+        self.inner.set_present_mode(mode)
+    }
+
+    fn barrier(&self) -> Result<(), Self::Error> {
+        self.inner.barrier()?;
+        self.record(Call::Barrier);
+        Ok(())
+    }
+}
+
+/// A capture that is being written.
+struct Capture {
+    /// Where the capture is written to.
+    writer: Box<dyn Write>,
+
+    /// Whether to leave out the contents of textures.
+    elide_pixels: bool,
+}
+
+impl Capture {
+    /// Write a call as a line.
+    fn write(&mut self, call: &Call) -> io::Result<()> {
+        let mut line = String::new();
+        call.write(&mut line, self.elide_pixels);
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())
+    }
+}
+
+/// The resources of a [`CaptureContext`], and what is needed to recreate them.
+#[derive(Default)]
+struct State {
+    /// The last ID that was handed out.
+    next_id: u32,
+
+    /// The calls that recreate each texture, starting with the one that created it.
+    textures: BTreeMap<u32, Vec<Call>>,
+
+    /// The last call that wrote to each vertex buffer.
+    buffers: BTreeMap<u32, Option<Call>>,
+
+    /// The render target that is bound.
+    render_target: Option<u32>,
+
+    /// The color matrix that is set.
+    color_matrix: Option<ColorMatrix>,
+
+    /// The composite mode that is set.
+    composite_mode: Option<CompositeMode>,
+
+    /// The clip shape that is set.
+    clip_shape: Option<ClipShape>,
+
+    /// The scissor rectangle that is set.
+    scissor: Option<[u32; 4]>,
+
+    /// The projection that is set.
+    projection: Option<Projection>,
+
+    /// The custom brush that is set.
+    custom_brush: Option<CustomBrush>,
+
+    /// The interpolation that textures are sampled with, if it is overridden.
+    sampler_interpolation: Option<InterpolationMode>,
+
+    /// The clips that are pushed, along with the render target that they were pushed on.
+    clips: Vec<(Option<u32>, Call)>,
+
+    /// The capture that starts at the next call.
+    requested: Option<Capture>,
+
+    /// The capture in progress.
+    capture: Option<Capture>,
+}
+
+impl State {
+    /// Record a call, writing it to the capture if there is one.
+    fn record(&mut self, call: Call) {
+        if let Some(mut capture) = self.requested.take() {
+            match self.write_resources(&mut capture) {
+                Ok(()) => self.capture = Some(capture),
+                Err(err) => warn!("failed to start capturing a frame: {}", err),
+            }
+        }
+
+        if let Some(capture) = &mut self.capture {
+            let present = matches!(call, Call::Present);
+            let result = capture.write(&call).and_then(|()| {
+                if present {
+                    capture.writer.flush()?;
+                }
+                Ok(())
+            });
+
+            match result {
+                Ok(()) if present => {
+                    self.capture = None;
+                    debug!("finished capturing a frame");
+                }
+                Ok(()) => {}
+                Err(err) => {
+                    warn!("failed to capture a frame: {}", err);
+                    self.capture = None;
+                }
+            }
+        }
+
+        self.remember(call);
+    }
+
+    /// Write the header and the calls that recreate every resource.
+    fn write_resources(&self, capture: &mut Capture) -> io::Result<()> {
+        writeln!(capture.writer, "{HEADER}")?;
+
+        for calls in self.textures.values() {
+            let (create, rest) = calls
+                .split_first()
+                .expect("texture without a creation call");
+            capture.write(create)?;
+
+            // Depth and stencil attachments belong to the bound render target.
+            if let Call::CreateRenderTarget { id, .. } = *create {
+                if !rest.is_empty() {
+                    capture.write(&Call::SetRenderTarget(Some(id)))?;
+                    rest.iter().try_for_each(|call| capture.write(call))?;
+                    capture.write(&Call::SetRenderTarget(None))?;
+                }
+            } else {
+                rest.iter().try_for_each(|call| capture.write(call))?;
+            }
+        }
+
+        for (&id, write) in &self.buffers {
+            capture.write(&Call::CreateVertexBuffer(id))?;
+            if let Some(write) = write {
+                capture.write(write)?;
+            }
+        }
+
+        // Clips belong to the render target that they were pushed on.
+        let mut bound = None;
+        for (target, call) in &self.clips {
+            if *target != bound {
+                capture.write(&Call::SetRenderTarget(*target))?;
+                bound = *target;
+            }
+            capture.write(call)?;
+        }
+        if self.render_target != bound {
+            capture.write(&Call::SetRenderTarget(self.render_target))?;
+        }
+        if self.color_matrix.is_some() {
+            capture.write(&Call::SetColorMatrix(self.color_matrix))?;
+        }
+        if self.composite_mode.is_some() {
+            capture.write(&Call::SetCompositeMode(self.composite_mode))?;
+        }
+        if self.clip_shape.is_some() {
+            capture.write(&Call::SetClipShape(self.clip_shape))?;
+        }
+        if self.scissor.is_some() {
+            capture.write(&Call::SetScissor(self.scissor))?;
+        }
+        if self.projection.is_some() {
+            capture.write(&Call::SetProjection(self.projection))?;
+        }
+        if self.custom_brush.is_some() {
+            capture.write(&Call::SetCustomBrush(self.custom_brush))?;
+        }
+        if self.sampler_interpolation.is_some() {
+            capture.write(&Call::SetSamplerInterpolation(self.sampler_interpolation))?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep track of what a call did to the resources.
+    fn remember(&mut self, call: Call) {
+        match call {
+            Call::CreateTexture { id, .. } | Call::CreateRenderTarget { id, .. } => {
+                self.textures.insert(id, vec![call]);
+            }
+            Call::DeleteTexture(id) => {
+                self.textures.remove(&id);
+            }
+            Call::WriteTexture { id, .. } => {
+                // This replaces everything that was written before.
+                if let Some(calls) = self.textures.get_mut(&id) {
+                    calls.retain(|call| {
+                        !matches!(
+                            call,
+                            Call::WriteTexture { .. }
+                                | Call::WriteSubtexture { .. }
+                                | Call::GenerateMipmaps(_)
+                        )
+                    });
+                    calls.push(call);
+                }
+            }
+            Call::WriteSubtexture { id, .. } => {
+                if let Some(calls) = self.textures.get_mut(&id) {
+                    // Fold the write into the last full image, so that textures that are
+                    // updated every frame, like the text atlas, don't grow a history.
+                    let image = calls.iter_mut().rev().find_map(|call| match call {
+                        Call::WriteTexture {
+                            size, format, data, ..
+                        } => Some((*size, *format, data)),
+                        _ => None,
+                    });
+
+                    match image {
+                        Some((size, format, data)) => {
+                            let data = data.get_or_insert_with(|| {
+                                vec![
+                                    0;
+                                    size.0 as usize * size.1 as usize * format.bytes_per_pixel()
+                                ]
+                            });
+                            blit(data, size, format, &call);
+                        }
+                        None => calls.push(call),
+                    }
+                }
+            }
+            Call::SetTextureInterpolation { id, .. } => {
+                if let Some(calls) = self.textures.get_mut(&id) {
+                    calls.retain(|call| !matches!(call, Call::SetTextureInterpolation { .. }));
+                    calls.push(call);
+                }
+            }
+            Call::GenerateMipmaps(id) => {
+                if let Some(calls) = self.textures.get_mut(&id) {
+                    calls.retain(|call| !matches!(call, Call::GenerateMipmaps(_)));
+                    calls.push(call);
+                }
+            }
+            Call::CreateVertexBuffer(id) => {
+                self.buffers.insert(id, None);
+            }
+            Call::DeleteVertexBuffer(id) => {
+                self.buffers.remove(&id);
+            }
+            Call::WriteVertices { id, .. } => {
+                if let Some(write) = self.buffers.get_mut(&id) {
+                    *write = Some(call);
+                }
+            }
+            Call::SetRenderTarget(target) => self.render_target = target,
+            Call::SetColorMatrix(matrix) => self.color_matrix = matrix,
+            Call::SetCompositeMode(mode) => self.composite_mode = mode,
+            Call::SetClipShape(shape) => self.clip_shape = shape,
+            Call::SetScissor(rect) => self.scissor = rect,
+            Call::SetProjection(projection) => self.projection = projection,
+            Call::SetCustomBrush(brush) => self.custom_brush = brush,
+            Call::SetSamplerInterpolation(interpolation) => {
+                self.sampler_interpolation = interpolation
+            }
+            Call::PushClipPath { .. } => self.clips.push((self.render_target, call)),
+            Call::PopClip { .. } => {
+                if let Some(index) = self
+                    .clips
+                    .iter()
+                    .rposition(|(target, _)| *target == self.render_target)
+                {
+                    self.clips.remove(index);
+                }
+            }
+            Call::CreateDepthStencil(_) | Call::AttachDepthStencil(_) => {
+                let calls = match self.render_target.and_then(|id| self.textures.get_mut(&id)) {
+                    Some(calls) => calls,
+                    None => return,
+                };
+                let kind = mem::discriminant(&call);
+                calls.retain(|call| mem::discriminant(call) != kind);
+                calls.push(call);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Copy the pixels of a [`Call::WriteSubtexture`] into an image of the given size and format.
+fn blit(image: &mut [u8], size: (u32, u32), format: ImageFormat, call: &Call) {
+    let (offset, sub_size, sub_format, sub_data) = match call {
+        Call::WriteSubtexture {
+            offset,
+            size,
+            format,
+            data,
+            ..
+        } => (*offset, *size, *format, data),
+        _ => return,
+    };
+
+    let converted;
+    let sub_data = if sub_format == format {
+        sub_data
+    } else {
+        let premultiplied = match sub_format {
+            ImageFormat::Grayscale => sub_data.iter().flat_map(|&l| [l, l, l, 0xFF]).collect(),
+            ImageFormat::Rgb => sub_data
+                .chunks_exact(3)
+                .flat_map(|px| [px[0], px[1], px[2], 0xFF])
+                .collect(),
+            ImageFormat::RgbaSeparate => {
+                let mut data = sub_data.clone();
+                crate::premultiply(&mut data);
+                data
+            }
+            _ => sub_data.clone(),
+        };
+        converted = match crate::convert_premultiplied(premultiplied, format) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        &converted
+    };
+
+    // Copy the rows that are inside of the image.
+    let bpp = format.bytes_per_pixel();
+    let width = sub_size.0.min(size.0.saturating_sub(offset.0)) as usize * bpp;
+    let rows = sub_size.1.min(size.1.saturating_sub(offset.1)) as usize;
+    for row in 0..rows {
+        let src = row * sub_size.0 as usize * bpp;
+        let dst = ((offset.1 as usize + row) * size.0 as usize + offset.0 as usize) * bpp;
+        match (
+            sub_data.get(src..src + width),
+            image.get_mut(dst..dst + width),
+        ) {
+            (Some(src), Some(dst)) => dst.copy_from_slice(src),
+            _ => return,
+        }
+    }
+}
+
+/// A call to a [`GpuContext`], as it is written in captures.
+enum Call {
+    Clear(Color),
+    Flush,
+    CreateTexture {
+        id: u32,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    },
+    DeleteTexture(u32),
+    WriteTexture {
+        id: u32,
+        size: (u32, u32),
+        format: ImageFormat,
+        data: Option<Vec<u8>>,
+    },
+    WriteSubtexture {
+        id: u32,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: ImageFormat,
+        data: Vec<u8>,
+    },
+    SetTextureInterpolation {
+        id: u32,
+        interpolation: InterpolationMode,
+    },
+    GenerateMipmaps(u32),
+    CreateVertexBuffer(u32),
+    DeleteVertexBuffer(u32),
+    WriteVertices {
+        id: u32,
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+    },
+    PushBuffers {
+        buffer: u32,
+        texture: u32,
+        mask: u32,
+        transform: Affine,
+        size: (u32, u32),
+    },
+    SetColorMatrix(Option<ColorMatrix>),
+    SetCompositeMode(Option<CompositeMode>),
+    SetClipShape(Option<ClipShape>),
+    SetScissor(Option<[u32; 4]>),
+    SetProjection(Option<Projection>),
+    SetCustomBrush(Option<CustomBrush>),
+    SetSamplerInterpolation(Option<InterpolationMode>),
+    ReadPixels {
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+    },
+    ClearRect {
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: Color,
+    },
+    CreateRenderTarget {
+        id: u32,
+        size: (u32, u32),
+    },
+    SetRenderTarget(Option<u32>),
+    CreateDepthStencil((u32, u32)),
+    AttachDepthStencil(bool),
+    PushClipPath {
+        buffer: u32,
+        size: (u32, u32),
+    },
+    PopClip {
+        buffer: u32,
+        size: (u32, u32),
+    },
+    Present,
+    Barrier,
+}
+
+impl Call {
+    /// Write the call as a line, without the line break.
+    fn write(&self, out: &mut String, elide_pixels: bool) {
+        // Writing to a `String` never fails.
+        let _ = self.write_fmt(out, elide_pixels);
+    }
+
+    fn write_fmt(&self, out: &mut String, elide_pixels: bool) -> fmt::Result {
+        let pixels = |out: &mut String, data: Option<&Vec<u8>>| match data {
+            None => out.write_str(" none"),
+            Some(_) if elide_pixels => out.write_str(" elided"),
+            Some(data) => {
+                out.push(' ');
+                write_hex(out, data);
+                Ok(())
+            }
+        };
+
+        match self {
+            Self::Clear(color) => write!(out, "clear {:08x}", color.as_rgba_u32()),
+            Self::Flush => write!(out, "flush"),
+            Self::CreateTexture {
+                id,
+                interpolation,
+                repeat,
+            } => {
+                write!(
+                    out,
+                    "create_texture {id} {} ",
+                    interpolation_name(*interpolation)
+                )?;
+                match repeat {
+                    RepeatStrategy::Repeat => out.write_str("repeat"),
+                    RepeatStrategy::Reflect => out.write_str("reflect"),
+                    RepeatStrategy::Clamp => out.write_str("clamp"),
+                    RepeatStrategy::Color(color) => {
+                        write!(out, "color:{:08x}", color.as_rgba_u32())
+                    }
+                }
+            }
+            Self::DeleteTexture(id) => write!(out, "delete_texture {id}"),
+            Self::WriteTexture {
+                id,
+                size,
+                format,
+                data,
+            } => {
+                write!(
+                    out,
+                    "write_texture {id} {} {} {}",
+                    size.0,
+                    size.1,
+                    format_name(*format)
+                )?;
+                pixels(out, data.as_ref())
+            }
+            Self::WriteSubtexture {
+                id,
+                offset,
+                size,
+                format,
+                data,
+            } => {
+                write!(
+                    out,
+                    "write_subtexture {id} {} {} {} {} {}",
+                    offset.0,
+                    offset.1,
+                    size.0,
+                    size.1,
+                    format_name(*format)
+                )?;
+                pixels(out, Some(data))
+            }
+            Self::SetTextureInterpolation { id, interpolation } => write!(
+                out,
+                "set_texture_interpolation {id} {}",
+                interpolation_name(*interpolation)
+            ),
+            Self::GenerateMipmaps(id) => write!(out, "generate_mipmaps {id}"),
+            Self::CreateVertexBuffer(id) => write!(out, "create_vertex_buffer {id}"),
+            Self::DeleteVertexBuffer(id) => write!(out, "delete_vertex_buffer {id}"),
+            Self::WriteVertices {
+                id,
+                vertices,
+                indices,
+            } => {
+                let mut bytes = Vec::with_capacity(vertices.len() * Vertex::STRIDE as usize);
+                for vertex in vertices {
+                    for value in vertex.pos.iter().chain(&vertex.uv) {
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                    bytes.extend_from_slice(&vertex.color);
+                }
+                write!(out, "write_vertices {id} ")?;
+                write_hex(out, &bytes);
+
+                bytes.clear();
+                for index in indices {
+                    bytes.extend_from_slice(&index.to_le_bytes());
+                }
+                out.push(' ');
+                write_hex(out, &bytes);
+                Ok(())
+            }
+            Self::PushBuffers {
+                buffer,
+                texture,
+                mask,
+                transform,
+                size,
+            } => {
+                write!(out, "push_buffers {buffer} {texture} {mask}")?;
+                for coeff in transform.as_coeffs() {
+                    write!(out, " {coeff}")?;
+                }
+                write!(out, " {} {}", size.0, size.1)
+            }
+            Self::SetColorMatrix(None) => write!(out, "set_color_matrix none"),
+            Self::SetColorMatrix(Some(matrix)) => {
+                out.write_str("set_color_matrix")?;
+                for value in matrix.0 {
+                    write!(out, " {value}")?;
+                }
+                Ok(())
+            }
+            Self::SetCompositeMode(mode) => write!(
+                out,
+                "set_composite_mode {}",
+                mode.map_or("none", composite_mode_name)
+            ),
+            Self::SetSamplerInterpolation(interpolation) => write!(
+                out,
+                "set_sampler_interpolation {}",
+                interpolation.map_or("none", interpolation_name)
+            ),
+            Self::SetClipShape(None) => write!(out, "set_clip_shape none"),
+            Self::SetClipShape(Some(shape)) => {
+                out.write_str("set_clip_shape")?;
+                for value in shape.rect.iter().chain(&shape.radii) {
+                    write!(out, " {value}")?;
+                }
+                Ok(())
+            }
+            Self::SetScissor(None) => write!(out, "set_scissor none"),
+            Self::SetScissor(Some([x0, y0, x1, y1])) => {
+                write!(out, "set_scissor {x0} {y0} {x1} {y1}")
+            }
+            Self::SetProjection(None) => write!(out, "set_projection none"),
+            Self::SetProjection(Some(projection)) => {
+                out.write_str("set_projection")?;
+                for value in projection.0 {
+                    write!(out, " {value}")?;
+                }
+                Ok(())
+            }
+            Self::SetCustomBrush(None) => write!(out, "set_custom_brush none"),
+            Self::SetCustomBrush(Some(brush)) => {
+                write!(out, "set_custom_brush {}", brush.shader.into_raw())?;
+                for value in brush.params {
+                    write!(out, " {value}")?;
+                }
+                Ok(())
+            }
+            Self::ReadPixels {
+                origin,
+                size,
+                target_size,
+            } => write!(
+                out,
+                "read_pixels {} {} {} {} {} {}",
+                origin.0, origin.1, size.0, size.1, target_size.0, target_size.1
+            ),
+            Self::ClearRect {
+                origin,
+                size,
+                target_size,
+                color,
+            } => write!(
+                out,
+                "clear_rect {} {} {} {} {} {} {:08x}",
+                origin.0,
+                origin.1,
+                size.0,
+                size.1,
+                target_size.0,
+                target_size.1,
+                color.as_rgba_u32()
+            ),
+            Self::CreateRenderTarget { id, size } => {
+                write!(out, "create_render_target {id} {} {}", size.0, size.1)
+            }
+            Self::SetRenderTarget(None) => write!(out, "set_render_target none"),
+            Self::SetRenderTarget(Some(id)) => write!(out, "set_render_target {id}"),
+            Self::CreateDepthStencil(size) => {
+                write!(out, "create_depth_stencil {} {}", size.0, size.1)
+            }
+            Self::AttachDepthStencil(attach) => write!(out, "attach_depth_stencil {attach}"),
+            Self::PushClipPath { buffer, size } => {
+                write!(out, "push_clip_path {buffer} {} {}", size.0, size.1)
+            }
+            Self::PopClip { buffer, size } => {
+                write!(out, "pop_clip {buffer} {} {}", size.0, size.1)
+            }
+            Self::Present => write!(out, "present"),
+            Self::Barrier => write!(out, "barrier"),
+        }
+    }
+
+    /// Parse a line of a capture.
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut tokens = line.split_whitespace();
+        let tokens = &mut tokens;
+        let name = next(tokens)?;
+
+        let call = match name {
+            "clear" => Self::Clear(parse_color(next(tokens)?)?),
+            "flush" => Self::Flush,
+            "create_texture" => Self::CreateTexture {
+                id: parse(tokens)?,
+                interpolation: parse_interpolation(next(tokens)?)?,
+                repeat: match next(tokens)? {
+                    "repeat" => RepeatStrategy::Repeat,
+                    "reflect" => RepeatStrategy::Reflect,
+                    "clamp" => RepeatStrategy::Clamp,
+                    token => match token.strip_prefix("color:") {
+                        Some(color) => RepeatStrategy::Color(parse_color(color)?),
+                        None => return Err(format!("unknown repeat strategy `{token}`")),
+                    },
+                },
+            },
+            "delete_texture" => Self::DeleteTexture(parse(tokens)?),
+            "write_texture" => {
+                let id = parse(tokens)?;
+                let size = parse_pair(tokens)?;
+                let format = parse_format(next(tokens)?)?;
+                let data = parse_pixels(next(tokens)?, size, format)?;
+                Self::WriteTexture {
+                    id,
+                    size,
+                    format,
+                    data,
+                }
+            }
+            "write_subtexture" => {
+                let id = parse(tokens)?;
+                let offset = parse_pair(tokens)?;
+                let size = parse_pair(tokens)?;
+                let format = parse_format(next(tokens)?)?;
+                let data =
+                    parse_pixels(next(tokens)?, size, format)?.ok_or("a sub-image needs pixels")?;
+                Self::WriteSubtexture {
+                    id,
+                    offset,
+                    size,
+                    format,
+                    data,
+                }
+            }
+            "set_texture_interpolation" => Self::SetTextureInterpolation {
+                id: parse(tokens)?,
+                interpolation: parse_interpolation(next(tokens)?)?,
+            },
+            "generate_mipmaps" => Self::GenerateMipmaps(parse(tokens)?),
+            "create_vertex_buffer" => Self::CreateVertexBuffer(parse(tokens)?),
+            "delete_vertex_buffer" => Self::DeleteVertexBuffer(parse(tokens)?),
+            "write_vertices" => {
+                let id = parse(tokens)?;
+                let vertices = parse_hex(next(tokens)?)?;
+                let indices = parse_hex(next(tokens)?)?;
+                if vertices.len() % Vertex::STRIDE as usize != 0 || indices.len() % 4 != 0 {
+                    return Err("vertex data has the wrong length".into());
+                }
+
+                let float = |bytes: &[u8]| f32::from_le_bytes(bytes.try_into().unwrap());
+                Self::WriteVertices {
+                    id,
+                    vertices: vertices
+                        .chunks_exact(Vertex::STRIDE as usize)
+                        .map(|bytes| Vertex {
+                            pos: [float(&bytes[0..4]), float(&bytes[4..8])],
+                            uv: [float(&bytes[8..12]), float(&bytes[12..16])],
+                            color: bytes[16..20].try_into().unwrap(),
+                        })
+                        .collect(),
+                    indices: indices
+                        .chunks_exact(4)
+                        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                        .collect(),
+                }
+            }
+            "push_buffers" => Self::PushBuffers {
+                buffer: parse(tokens)?,
+                texture: parse(tokens)?,
+                mask: parse(tokens)?,
+                transform: {
+                    let mut coeffs = [0.0; 6];
+                    for coeff in &mut coeffs {
+                        *coeff = parse(tokens)?;
+                    }
+                    Affine::new(coeffs)
+                },
+                size: parse_pair(tokens)?,
+            },
+            "set_color_matrix" => Self::SetColorMatrix(match tokens.clone().next() {
+                Some("none") => {
+                    tokens.next();
+                    None
+                }
+                _ => {
+                    let mut matrix = ColorMatrix::IDENTITY;
+                    for value in &mut matrix.0 {
+                        *value = parse(tokens)?;
+                    }
+                    Some(matrix)
+                }
+            }),
+            "set_composite_mode" => Self::SetCompositeMode(match next(tokens)? {
+                "none" => None,
+                token => Some(parse_composite_mode(token)?),
+            }),
+            "set_sampler_interpolation" => Self::SetSamplerInterpolation(match next(tokens)? {
+                "none" => None,
+                token => Some(parse_interpolation(token)?),
+            }),
+            "set_clip_shape" => Self::SetClipShape(match tokens.clone().next() {
+                Some("none") => {
+                    tokens.next();
+                    None
+                }
+                _ => {
+                    let mut shape = ClipShape::new([0.0; 4], [0.0; 2]);
+                    for value in shape.rect.iter_mut().chain(&mut shape.radii) {
+                        *value = parse(tokens)?;
+                    }
+                    Some(shape)
+                }
+            }),
+            "set_scissor" => Self::SetScissor(match tokens.clone().next() {
+                Some("none") => {
+                    tokens.next();
+                    None
+                }
+                _ => Some([
+                    parse(tokens)?,
+                    parse(tokens)?,
+                    parse(tokens)?,
+                    parse(tokens)?,
+                ]),
+            }),
+            "set_projection" => Self::SetProjection(match tokens.clone().next() {
+                Some("none") => {
+                    tokens.next();
+                    None
+                }
+                _ => {
+                    let mut projection = Projection::IDENTITY;
+                    for value in &mut projection.0 {
+                        *value = parse(tokens)?;
+                    }
+                    Some(projection)
+                }
+            }),
+            "set_custom_brush" => Self::SetCustomBrush(match tokens.clone().next() {
+                Some("none") => {
+                    tokens.next();
+                    None
+                }
+                _ => {
+                    let mut brush =
+                        CustomBrush::new(CustomShader::from_raw(parse(tokens)?), [0.0; 8]);
+                    for value in &mut brush.params {
+                        *value = parse(tokens)?;
+                    }
+                    Some(brush)
+                }
+            }),
+            "read_pixels" => Self::ReadPixels {
+                origin: parse_pair(tokens)?,
+                size: parse_pair(tokens)?,
+                target_size: parse_pair(tokens)?,
+            },
+            "clear_rect" => Self::ClearRect {
+                origin: parse_pair(tokens)?,
+                size: parse_pair(tokens)?,
+                target_size: parse_pair(tokens)?,
+                color: parse_color(next(tokens)?)?,
+            },
+            "create_render_target" => Self::CreateRenderTarget {
+                id: parse(tokens)?,
+                size: parse_pair(tokens)?,
+            },
+            "set_render_target" => Self::SetRenderTarget(match next(tokens)? {
+                "none" => None,
+                id => Some(id.parse().map_err(|_| format!("invalid ID `{id}`"))?),
+            }),
+            "create_depth_stencil" => Self::CreateDepthStencil(parse_pair(tokens)?),
+            "attach_depth_stencil" => Self::AttachDepthStencil(parse(tokens)?),
+            "push_clip_path" => Self::PushClipPath {
+                buffer: parse(tokens)?,
+                size: parse_pair(tokens)?,
+            },
+            "pop_clip" => Self::PopClip {
+                buffer: parse(tokens)?,
+                size: parse_pair(tokens)?,
+            },
+            "present" => Self::Present,
+            "barrier" => Self::Barrier,
+            name => return Err(format!("unknown call `{name}`")),
+        };
+
+        match tokens.next() {
+            Some(token) => Err(format!("unexpected `{token}` after `{name}`")),
+            None => Ok(call),
+        }
+    }
+}
+
+/// Write bytes as hexadecimal, or `-` if there are none.
+fn write_hex(out: &mut String, data: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    if data.is_empty() {
+        out.push('-');
+    }
+
+    out.reserve(data.len() * 2);
+    for &byte in data {
+        out.push(DIGITS[usize::from(byte >> 4)] as char);
+        out.push(DIGITS[usize::from(byte & 0xf)] as char);
+    }
+}
+
+fn parse_hex(token: &str) -> Result<Vec<u8>, String> {
+    if token == "-" {
+        return Ok(Vec::new());
+    }
+
+    let digit = |digit: u8| match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        _ => Err(format!("invalid hexadecimal digit `{}`", digit as char)),
+    };
+
+    if token.len() % 2 != 0 {
+        return Err("hexadecimal data has an odd length".into());
+    }
+    token
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|pair| Ok((digit(pair[0])? << 4) | digit(pair[1])?))
+        .collect()
+}
+
+/// Parse the pixels of an image, replacing elided ones with opaque white.
+fn parse_pixels(
+    token: &str,
+    size: (u32, u32),
+    format: ImageFormat,
+) -> Result<Option<Vec<u8>>, String> {
+    let len = size.0 as usize * size.1 as usize * format.bytes_per_pixel();
+    let data = match token {
+        "none" => return Ok(None),
+        "elided" => vec![0xFF; len],
+        token => parse_hex(token)?,
+    };
+
+    if data.len() != len {
+        return Err("image data has the wrong length".into());
+    }
+    Ok(Some(data))
+}
+
+fn next<'a>(tokens: &mut SplitWhitespace<'a>) -> Result<&'a str, String> {
+    tokens.next().ok_or_else(|| "unexpected end of line".into())
+}
+
+fn parse<T: FromStr>(tokens: &mut SplitWhitespace<'_>) -> Result<T, String> {
+    let token = next(tokens)?;
+    token
+        .parse()
+        .map_err(|_| format!("invalid argument `{token}`"))
+}
+
+fn parse_pair(tokens: &mut SplitWhitespace<'_>) -> Result<(u32, u32), String> {
+    Ok((parse(tokens)?, parse(tokens)?))
+}
+
+fn parse_color(token: &str) -> Result<Color, String> {
+    u32::from_str_radix(token, 16)
+        .map(Color::from_rgba32_u32)
+        .map_err(|_| format!("invalid color `{token}`"))
+}
+
+fn interpolation_name(interpolation: InterpolationMode) -> &'static str {
+    match interpolation {
+        InterpolationMode::NearestNeighbor => "nearest",
+        InterpolationMode::Bilinear => "bilinear",
+    }
+}
+
+fn parse_interpolation(token: &str) -> Result<InterpolationMode, String> {
+    match token {
+        "nearest" => Ok(InterpolationMode::NearestNeighbor),
+        "bilinear" => Ok(InterpolationMode::Bilinear),
+        token => Err(format!("unknown interpolation mode `{token}`")),
+    }
+}
+
+fn composite_mode_name(mode: CompositeMode) -> &'static str {
+    match mode {
+        CompositeMode::Clear => "clear",
+        CompositeMode::Source => "source",
+        CompositeMode::Destination => "destination",
+        CompositeMode::SourceOver => "source-over",
+        CompositeMode::DestinationOver => "destination-over",
+        CompositeMode::SourceIn => "source-in",
+        CompositeMode::DestinationIn => "destination-in",
+        CompositeMode::SourceOut => "source-out",
+        CompositeMode::DestinationOut => "destination-out",
+        CompositeMode::SourceAtop => "source-atop",
+        CompositeMode::DestinationAtop => "destination-atop",
+        CompositeMode::Xor => "xor",
+        CompositeMode::Plus => "plus",
+    }
+}
+
+fn parse_composite_mode(token: &str) -> Result<CompositeMode, String> {
+    Ok(match token {
+        "clear" => CompositeMode::Clear,
+        "source" => CompositeMode::Source,
+        "destination" => CompositeMode::Destination,
+        "source-over" => CompositeMode::SourceOver,
+        "destination-over" => CompositeMode::DestinationOver,
+        "source-in" => CompositeMode::SourceIn,
+        "destination-in" => CompositeMode::DestinationIn,
+        "source-out" => CompositeMode::SourceOut,
+        "destination-out" => CompositeMode::DestinationOut,
+        "source-atop" => CompositeMode::SourceAtop,
+        "destination-atop" => CompositeMode::DestinationAtop,
+        "xor" => CompositeMode::Xor,
+        "plus" => CompositeMode::Plus,
+        token => return Err(format!("unknown composite mode `{token}`")),
+    })
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Grayscale => "grayscale",
+        ImageFormat::Rgb => "rgb",
+        ImageFormat::RgbaSeparate => "rgba-separate",
+        _ => "rgba-premul",
+    }
+}
+
+fn parse_format(token: &str) -> Result<ImageFormat, String> {
+    match token {
+        "grayscale" => Ok(ImageFormat::Grayscale),
+        "rgb" => Ok(ImageFormat::Rgb),
+        "rgba-separate" => Ok(ImageFormat::RgbaSeparate),
+        "rgba-premul" => Ok(ImageFormat::RgbaPremul),
+        token => Err(format!("unknown image format `{token}`")),
+    }
+}
+
+/// Send the calls in a capture written by [`CaptureContext`] to a context.
+///
+/// Pixels that were elided from the capture are replaced with opaque white ones.
+pub fn replay<C: GpuContext + ?Sized>(
+    context: &C,
+    reader: impl BufRead,
+) -> Result<(), ReplayError> {
+    let mut replayer = Replayer {
+        context,
+        textures: BTreeMap::new(),
+        buffers: BTreeMap::new(),
+    };
+    let mut lines = reader.lines().enumerate();
+
+    let result = (|| {
+        match lines.next() {
+            Some((_, Ok(line))) if line.trim() == HEADER => {}
+            Some((_, Err(err))) => return Err((0, err.into())),
+            _ => return Err((0, "not a `piet-hardware` capture".into())),
+        }
+
+        for (index, line) in lines {
+            let line = line.map_err(|err| (index, err.into()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            Call::parse(&line)
+                .map_err(Into::into)
+                .and_then(|call| replayer.apply(call))
+                .map_err(|err| (index, err))?;
+        }
+
+        Ok(())
+    })();
+
+    // Clean up whatever the capture left behind.
+    for (_, texture) in mem::take(&mut replayer.textures) {
+        context.delete_texture(texture);
+    }
+    for (_, buffer) in mem::take(&mut replayer.buffers) {
+        context.delete_vertex_buffer(buffer);
+    }
+
+    result.map_err(|(index, error): (usize, Box<dyn StdError>)| ReplayError {
+        line: index + 1,
+        error,
+    })
+}
+
+/// The resources of a capture that is being replayed.
+struct Replayer<'a, C: GpuContext + ?Sized> {
+    context: &'a C,
+    textures: BTreeMap<u32, C::Texture>,
+    buffers: BTreeMap<u32, C::VertexBuffer>,
+}
+
+impl<C: GpuContext + ?Sized> Replayer<'_, C> {
+    fn texture(&self, id: u32) -> Result<&C::Texture, String> {
+        self.textures
+            .get(&id)
+            .ok_or_else(|| format!("unknown texture {id}"))
+    }
+
+    fn buffer(&self, id: u32) -> Result<&C::VertexBuffer, String> {
+        self.buffers
+            .get(&id)
+            .ok_or_else(|| format!("unknown vertex buffer {id}"))
+    }
+
+    fn apply(&mut self, call: Call) -> Result<(), Box<dyn StdError>> {
+        let context = self.context;
+        let backend = |err: C::Error| -> Box<dyn StdError> { Box::new(err) };
+
+        match call {
+            Call::Clear(color) => context.clear(color),
+            Call::Flush => context.flush().map_err(backend)?,
+            Call::CreateTexture {
+                id,
+                interpolation,
+                repeat,
+            } => {
+                let texture = context
+                    .create_texture(interpolation, repeat)
+                    .map_err(backend)?;
+                self.textures.insert(id, texture);
+            }
+            Call::DeleteTexture(id) => {
+                let texture = self
+                    .textures
+                    .remove(&id)
+                    .ok_or_else(|| format!("unknown texture {id}"))?;
+                context.delete_texture(texture);
+            }
+            Call::WriteTexture {
+                id,
+                size,
+                format,
+                data,
+            } => context.write_texture(self.texture(id)?, size, format, data.as_deref()),
+            Call::WriteSubtexture {
+                id,
+                offset,
+                size,
+                format,
+                data,
+            } => context.write_subtexture(self.texture(id)?, offset, size, format, &data),
+            Call::SetTextureInterpolation { id, interpolation } => {
+                context.set_texture_interpolation(self.texture(id)?, interpolation)
+            }
+            Call::GenerateMipmaps(id) => {
+                // Mip levels only change how minified images look, so they are skipped when
+                // the context can't have them.
+                context.generate_mipmaps(self.texture(id)?);
+            }
+            Call::CreateVertexBuffer(id) => {
+                let buffer = context.create_vertex_buffer().map_err(backend)?;
+                self.buffers.insert(id, buffer);
+            }
+            Call::DeleteVertexBuffer(id) => {
+                let buffer = self
+                    .buffers
+                    .remove(&id)
+                    .ok_or_else(|| format!("unknown vertex buffer {id}"))?;
+                context.delete_vertex_buffer(buffer);
+            }
+            Call::WriteVertices {
+                id,
+                vertices,
+                indices,
+            } => context.write_vertices(self.buffer(id)?, &vertices, &indices),
+            Call::PushBuffers {
+                buffer,
+                texture,
+                mask,
+                transform,
+                size,
+            } => context
+                .push_buffers(
+                    self.buffer(buffer)?,
+                    self.texture(texture)?,
+                    self.texture(mask)?,
+                    &transform,
+                    size,
+                )
+                .map_err(backend)?,
+            Call::SetColorMatrix(matrix) => {
+                if !context.set_color_matrix(matrix.as_ref()) {
+                    return Err("color matrices are not supported".into());
+                }
+            }
+            Call::SetCompositeMode(mode) => {
+                if !context.set_composite_mode(mode) {
+                    return Err("composite modes are not supported".into());
+                }
+            }
+            Call::SetSamplerInterpolation(interpolation) => {
+                if !context.set_sampler_interpolation(interpolation) {
+                    return Err("sampler interpolation is not supported".into());
+                }
+            }
+            Call::SetClipShape(shape) => {
+                if !context.set_clip_shape(shape.as_ref()) {
+                    return Err("clip shapes are not supported".into());
+                }
+            }
+            Call::SetScissor(rect) => {
+                if !context.set_scissor(rect) {
+                    return Err("scissor rectangles are not supported".into());
+                }
+            }
+            Call::SetProjection(projection) => {
+                if !context.set_projection(projection.as_ref()) {
+                    return Err("projections are not supported".into());
+                }
+            }
+            Call::SetCustomBrush(brush) => {
+                if !context.set_custom_brush(brush.as_ref()) {
+                    return Err("custom brush is not supported".into());
+                }
+            }
+            Call::ReadPixels {
+                origin,
+                size,
+                target_size,
+            } => {
+                let mut data = vec![0; size.0 as usize * size.1 as usize * 4];
+                context
+                    .read_pixels(origin, size, target_size, &mut data)
+                    .map_err(backend)?;
+            }
+            Call::ClearRect {
+                origin,
+                size,
+                target_size,
+                color,
+            } => {
+                if !context
+                    .clear_rect(origin, size, target_size, color)
+                    .map_err(backend)?
+                {
+                    return Err("clearing part of the target is not supported".into());
+                }
+            }
+            Call::CreateRenderTarget { id, size } => {
+                let target = context
+                    .create_render_target(size)
+                    .map_err(backend)?
+                    .ok_or("render targets are not supported")?;
+                self.textures.insert(id, target);
+            }
+            Call::SetRenderTarget(id) => {
+                let target = id.map(|id| self.texture(id)).transpose()?;
+                context.set_render_target(target).map_err(backend)?;
+            }
+            Call::CreateDepthStencil(size) => {
+                if !context.create_depth_stencil(size).map_err(backend)? {
+                    return Err("depth and stencil attachments are not supported".into());
+                }
+            }
+            Call::AttachDepthStencil(attach) => {
+                context.attach_depth_stencil(attach).map_err(backend)?
+            }
+            Call::PushClipPath { buffer, size } => {
+                if !context
+                    .push_clip_path(self.buffer(buffer)?, size)
+                    .map_err(backend)?
+                {
+                    return Err("stencil clips are not supported".into());
+                }
+            }
+            Call::PopClip { buffer, size } => context
+                .pop_clip(self.buffer(buffer)?, size)
+                .map_err(backend)?,
+            Call::Present => context.present().map_err(backend)?,
+            Call::Barrier => context.barrier().map_err(backend)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// An error that occurred while replaying a capture.
+#[derive(Debug)]
+pub struct ReplayError {
+    /// The line of the capture that failed, starting at one.
+    line: usize,
+
+    /// What went wrong.
+    error: Box<dyn StdError>,
+}
+
+impl ReplayError {
+    /// The line of the capture that failed, starting at one.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the underlying error.
+    pub fn into_inner(self) -> Box<dyn StdError> {
+        self.error
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to replay line {}: {}", self.line, self.error)
+    }
+}
+
+impl StdError for ReplayError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+impl From<ReplayError> for Pierror {
+    fn from(err: ReplayError) -> Self {
+        Pierror::BackendError(Box::new(err))
+    }
+}
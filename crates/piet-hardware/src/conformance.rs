@@ -0,0 +1,538 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks that a [`GpuContext`] draws what `piet-hardware` expects.
+//!
+//! [`run`] draws a series of small scenes, such as fills, strokes, clips, transforms, images,
+//! gradients and text, reads them back and compares pixels against the expected colors. New
+//! backends can run it to check that they implement the [`GpuContext`] contract correctly.
+//!
+//! Rasterizers are free to differ in how they anti-alias edges, so only pixels that are well
+//! away from the edges of shapes are compared, with a small tolerance for rounding.
+
+use super::gpu_backend::GpuContext;
+use super::{RenderContext, ResultExt, Source};
+
+use piet::kurbo::{Affine, BezPath, Circle, Line, Point, Rect, Vec2};
+use piet::{
+    Color, Error as Pierror, ImageFormat, InterpolationMode, LinearGradient, RenderContext as _,
+    Text, TextLayout, TextLayoutBuilder, UnitPoint,
+};
+
+use std::fmt;
+
+/// The width and height of the target that the scenes are drawn on.
+pub const SIZE: u32 = 64;
+
+/// How far a channel may be from the expected value.
+const TOLERANCE: u8 = 3;
+
+/// Run every check against the context of a source.
+///
+/// The scenes are drawn into render targets if the context supports them. Otherwise they are
+/// drawn on the bound target, which must be [`SIZE`] by [`SIZE`] pixels. Either way,
+/// [`GpuContext::read_pixels`] needs to be supported, or this returns
+/// [`Error::NotSupported`](Pierror::NotSupported).
+pub fn run<C: GpuContext + ?Sized>(source: &mut Source<C>) -> Result<Report, Pierror> {
+    let mut report = Report::default();
+
+    for case in cases::<C>() {
+        let data = match source.render_offscreen((SIZE, SIZE), |mut rc| (case.draw)(&mut rc)) {
+            Err(Pierror::NotSupported) => render_bound(source, case.draw),
+            result => result,
+        };
+
+        let result = match data {
+            Ok(data) => (case.check)(&Pixels(&data)),
+            Err(Pierror::NotSupported) => return Err(Pierror::NotSupported),
+            Err(err) => Err(format!("failed to draw: {err}")),
+        };
+
+        match result {
+            Ok(()) => report.passed += 1,
+            Err(message) => report.failures.push(Failure {
+                case: case.name,
+                message,
+            }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Draw on the bound target, and read it back.
+fn render_bound<C: GpuContext + ?Sized>(
+    source: &mut Source<C>,
+    draw: Draw<C>,
+) -> Result<Vec<u8>, Pierror> {
+    let last_target_size = source.target_size;
+    let result = (|| {
+        let mut rc = source.render_context(SIZE, SIZE);
+        rc.clear(None, Color::TRANSPARENT);
+        draw(&mut rc)?;
+        drop(rc);
+        source.context.flush().piet_err()?;
+
+        source.read_pixels((0, 0), (SIZE, SIZE), (SIZE, SIZE))
+    })();
+    source.target_size = last_target_size;
+    result
+}
+
+/// The results of the checks.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The number of checks that passed.
+    passed: usize,
+
+    /// The checks that failed.
+    failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Whether every check passed.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The number of checks that passed.
+    pub fn passed(&self) -> usize {
+        self.passed
+    }
+
+    /// The checks that failed.
+    pub fn failures(&self) -> &[Failure] {
+        &self.failures
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n{failure}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A check that failed.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// The name of the scene.
+    case: &'static str,
+
+    /// What went wrong.
+    message: String,
+}
+
+impl Failure {
+    /// The name of the scene that was drawn.
+    pub fn case(&self) -> &str {
+        self.case
+    }
+
+    /// What went wrong.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.case, self.message)
+    }
+}
+
+/// Draws a scene.
+type Draw<C> = fn(&mut RenderContext<'_, C>) -> Result<(), Pierror>;
+
+/// A scene, and how to check it.
+struct Case<C: GpuContext + ?Sized> {
+    /// The name of the scene.
+    name: &'static str,
+
+    /// Draws the scene on a transparent target.
+    draw: Draw<C>,
+
+    /// Checks the pixels of the scene.
+    check: fn(&Pixels<'_>) -> Result<(), String>,
+}
+
+/// Premultiplied RGBA pixels that were read back.
+struct Pixels<'a>(&'a [u8]);
+
+impl Pixels<'_> {
+    /// Get the pixel at a position.
+    fn at(&self, x: u32, y: u32) -> [u8; 4] {
+        let index = (y * SIZE + x) as usize * 4;
+        [
+            self.0[index],
+            self.0[index + 1],
+            self.0[index + 2],
+            self.0[index + 3],
+        ]
+    }
+
+    /// Check that the pixel at a position is close to a premultiplied color.
+    fn expect(&self, x: u32, y: u32, expected: [u8; 4]) -> Result<(), String> {
+        let found = self.at(x, y);
+        if found
+            .iter()
+            .zip(expected)
+            .all(|(&found, expected)| found.abs_diff(expected) <= TOLERANCE)
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "pixel at ({x}, {y}) is {found:?}, expected {expected:?}"
+            ))
+        }
+    }
+
+    /// Check several pixels.
+    fn expect_all(&self, probes: &[(u32, u32, [u8; 4])]) -> Result<(), String> {
+        probes
+            .iter()
+            .try_for_each(|&(x, y, expected)| self.expect(x, y, expected))
+    }
+}
+
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+/// The full target.
+const FULL: Rect = Rect::new(0.0, 0.0, SIZE as f64, SIZE as f64);
+
+fn cases<C: GpuContext + ?Sized>() -> Vec<Case<C>> {
+    vec![
+        Case {
+            name: "clear",
+            draw: |rc| {
+                rc.clear(None, Color::RED);
+                rc.clear(Rect::new(0.0, 0.0, 32.0, 64.0), Color::TRANSPARENT);
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(8, 32, TRANSPARENT), (56, 32, RED)]),
+        },
+        Case {
+            name: "fill rectangle",
+            draw: |rc| {
+                rc.fill(Rect::new(16.0, 16.0, 48.0, 48.0), &Color::BLUE);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (20, 20, BLUE),
+                    (43, 43, BLUE),
+                    (8, 32, TRANSPARENT),
+                    (56, 32, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "fill with alpha",
+            draw: |rc| {
+                rc.fill(FULL, &Color::RED);
+                rc.fill(
+                    Rect::new(0.0, 0.0, 64.0, 32.0),
+                    &Color::rgba8(0, 0, 255, 128),
+                );
+                rc.fill(
+                    Rect::new(0.0, 32.0, 64.0, 64.0),
+                    &Color::rgba8(0, 255, 0, 0),
+                );
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(32, 16, [127, 0, 128, 255]), (32, 48, RED)]),
+        },
+        Case {
+            name: "fill circle",
+            draw: |rc| {
+                rc.fill(Circle::new((32.0, 32.0), 24.0), &Color::LIME);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (32, 32, GREEN),
+                    (32, 12, GREEN),
+                    (4, 4, TRANSPARENT),
+                    (60, 60, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "fill even-odd",
+            draw: |rc| {
+                let mut path = BezPath::new();
+                for rect in [FULL.inset(-8.0), FULL.inset(-24.0)] {
+                    path.move_to((rect.x0, rect.y0));
+                    path.line_to((rect.x1, rect.y0));
+                    path.line_to((rect.x1, rect.y1));
+                    path.line_to((rect.x0, rect.y1));
+                    path.close_path();
+                }
+                rc.fill_even_odd(path, &Color::RED);
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(12, 32, RED), (32, 32, TRANSPARENT), (4, 4, TRANSPARENT)]),
+        },
+        Case {
+            name: "stroke",
+            draw: |rc| {
+                rc.stroke(Line::new((8.0, 32.0), (56.0, 32.0)), &Color::BLUE, 8.0);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (32, 30, BLUE),
+                    (32, 33, BLUE),
+                    (32, 40, TRANSPARENT),
+                    (4, 32, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "transform",
+            draw: |rc| {
+                rc.transform(Affine::translate((32.0, 32.0)) * Affine::scale(2.0));
+                rc.fill(Rect::new(0.0, 0.0, 8.0, 8.0), &Color::RED);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (34, 34, RED),
+                    (45, 45, RED),
+                    (28, 28, TRANSPARENT),
+                    (51, 51, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "save and restore",
+            draw: |rc| {
+                rc.save()?;
+                rc.transform(Affine::translate((100.0, 100.0)));
+                rc.clip(Rect::new(0.0, 0.0, 1.0, 1.0));
+                rc.restore()?;
+                rc.fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::LIME);
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(8, 8, GREEN), (24, 24, TRANSPARENT)]),
+        },
+        Case {
+            name: "clip",
+            draw: |rc| {
+                rc.clip(Rect::new(0.0, 0.0, 32.0, 64.0));
+                rc.fill(FULL, &Color::RED);
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(8, 32, RED), (28, 8, RED), (36, 32, TRANSPARENT)]),
+        },
+        Case {
+            name: "clip to a transformed path",
+            draw: |rc| {
+                rc.transform(Affine::rotate_about(
+                    std::f64::consts::FRAC_PI_4,
+                    Point::new(32.0, 32.0),
+                ));
+                rc.clip(Rect::new(16.0, 16.0, 48.0, 48.0));
+                rc.transform(Affine::rotate_about(
+                    -std::f64::consts::FRAC_PI_4,
+                    Point::new(32.0, 32.0),
+                ));
+                rc.fill(FULL, &Color::BLUE);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (32, 32, BLUE),
+                    (32, 12, BLUE),
+                    (12, 12, TRANSPARENT),
+                    (52, 52, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "clip to a path",
+            draw: |rc| {
+                rc.clip(triangle());
+                rc.fill(FULL, &Color::RED);
+                rc.status()
+            },
+            check: |px| px.expect_all(&[(8, 8, RED), (40, 40, TRANSPARENT), (60, 60, TRANSPARENT)]),
+        },
+        Case {
+            name: "clip to two paths",
+            draw: |rc| {
+                rc.clip(Circle::new((32.0, 32.0), 24.0));
+                rc.clip(triangle());
+                rc.fill(FULL, &Color::BLUE);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (20, 20, BLUE),
+                    (32, 12, BLUE),
+                    (44, 44, TRANSPARENT),
+                    (4, 4, TRANSPARENT),
+                ])
+            },
+        },
+        Case {
+            name: "image",
+            draw: |rc| {
+                let pixels = [
+                    255, 0, 0, 255, 0, 255, 0, 255, //
+                    0, 0, 255, 255, 255, 255, 255, 255,
+                ];
+                let image = rc.make_image(2, 2, &pixels, ImageFormat::RgbaSeparate)?;
+                rc.draw_image(&image, FULL, InterpolationMode::NearestNeighbor);
+                rc.status()
+            },
+            check: |px| {
+                px.expect_all(&[
+                    (16, 16, RED),
+                    (48, 16, GREEN),
+                    (16, 48, BLUE),
+                    (48, 48, WHITE),
+                ])
+            },
+        },
+        Case {
+            name: "image with separate alpha",
+            draw: |rc| {
+                let pixels = [255, 255, 255, 128];
+                let image = rc.make_image(1, 1, &pixels, ImageFormat::RgbaSeparate)?;
+                rc.draw_image(&image, FULL, InterpolationMode::NearestNeighbor);
+                rc.status()
+            },
+            check: |px| px.expect(32, 32, [128, 128, 128, 128]),
+        },
+        Case {
+            name: "linear gradient",
+            draw: |rc| {
+                let gradient = LinearGradient::new(
+                    UnitPoint::LEFT,
+                    UnitPoint::RIGHT,
+                    (Color::BLACK, Color::WHITE),
+                );
+                rc.fill(FULL, &gradient);
+                rc.status()
+            },
+            check: |px| {
+                let row: Vec<_> = (0..SIZE).map(|x| px.at(x, 32)).collect();
+                if row.iter().any(|pixel| pixel[3] < 255 - TOLERANCE) {
+                    return Err("the gradient isn't opaque".into());
+                }
+                if row[1][0] > 16 || row[SIZE as usize - 2][0] < 239 {
+                    return Err(format!(
+                        "the gradient goes from {:?} to {:?}, expected black to white",
+                        row[1],
+                        row[SIZE as usize - 2]
+                    ));
+                }
+                match row
+                    .windows(2)
+                    .position(|pair| pair[1][0].saturating_add(TOLERANCE) < pair[0][0])
+                {
+                    Some(x) => Err(format!("the gradient gets darker at x = {}", x + 1)),
+                    None => Ok(()),
+                }
+            },
+        },
+        Case {
+            name: "text",
+            draw: |rc| {
+                let layout = rc
+                    .text()
+                    .new_text_layout("Ham")
+                    .text_color(Color::BLACK)
+                    .build()?;
+                rc.draw_text(&layout, (4.0, 4.0));
+
+                // Mark where the text is allowed to be.
+                let bounds = layout.image_bounds() + Vec2::new(4.0, 4.0);
+                rc.fill(Rect::new(0.0, 63.0, 1.0, 64.0), &Color::RED);
+                rc.fill(
+                    Rect::new(bounds.x1.min(62.0), 63.0, bounds.x1.min(62.0) + 1.0, 64.0),
+                    &Color::BLUE,
+                );
+                rc.status()
+            },
+            check: |px| {
+                // Fonts may not be available, in which case nothing is drawn.
+                let right = (0..SIZE).find(|&x| px.at(x, 63)[2] > 128).unwrap_or(SIZE);
+                // The markers may be anti-aliased into the row above them.
+                let inked = |x: u32, y: u32| y < 62 && px.at(x, y)[3] > 64;
+                let any_ink = (0..SIZE).any(|y| (0..SIZE).any(|x| inked(x, y)));
+                if right <= 5 || !any_ink {
+                    return Ok(());
+                }
+
+                match (0..SIZE).find(|&x| (0..62).any(|y| inked(x, y)) && (x < 2 || x > right + 2))
+                {
+                    Some(x) => Err(format!("text was drawn at x = {x}, outside of its bounds")),
+                    None => Ok(()),
+                }
+            },
+        },
+        Case {
+            name: "text color",
+            draw: |rc| {
+                let layout = rc
+                    .text()
+                    .new_text_layout("MMMM")
+                    .text_color(Color::WHITE)
+                    .build()?;
+                rc.draw_text(&layout, (4.0, 4.0));
+                rc.status()
+            },
+            check: |px| {
+                // Glyphs are coverage, so white text is premultiplied white wherever it is drawn.
+                let pixels = (0..SIZE).flat_map(|y| (0..SIZE).map(move |x| (x, y)));
+                for (x, y) in pixels {
+                    let [r, g, b, a] = px.at(x, y);
+                    if [r, g, b].iter().any(|&c| c.abs_diff(a) > TOLERANCE) {
+                        return Err(format!(
+                            "pixel at ({x}, {y}) is {:?}, expected white text",
+                            [r, g, b, a]
+                        ));
+                    }
+                }
+                Ok(())
+            },
+        },
+    ]
+}
+
+/// A triangle over the top-left half of the target.
+fn triangle() -> BezPath {
+    let mut path = BezPath::new();
+    path.move_to((0.0, 0.0));
+    path.line_to((SIZE as f64, 0.0));
+    path.line_to((0.0, SIZE as f64));
+    path.close_path();
+    path
+}
@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Splitting strokes into dashes.
+
+use piet::kurbo::{BezPath, PathEl, Point, Shape};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// The number of flattened paths to keep around.
+const CACHE_SIZE: usize = 8;
+
+/// The maximum number of dash periods in a path.
+///
+/// Paths with more periods than this are drawn without dashes.
+const MAX_DASHES: f64 = 100_000.0;
+
+/// Splits paths into dashes.
+///
+/// Flattening a path and measuring its length is the expensive part of dashing, so the
+/// flattened paths are cached. Animating the dash offset of the same path, like a "marching
+/// ants" selection, only has to walk the cached polylines again.
+pub(crate) struct Dasher {
+    /// Recently flattened paths, with the most recently used one first.
+    cache: Vec<Flattened>,
+
+    /// The dashed path.
+    output: BezPath,
+
+    /// The elements of the last path that was looked up in the cache.
+    elements: Vec<u64>,
+}
+
+/// A path that has been flattened into polylines.
+struct Flattened {
+    /// The hash of the path elements and tolerance used to make this.
+    key: u64,
+
+    /// The tolerance and the tags and coordinates of the path elements, as bits, so that
+    /// paths with the same hash aren't mixed up.
+    elements: Vec<u64>,
+
+    /// The polylines that make up the path.
+    polylines: Vec<Polyline>,
+}
+
+/// A subpath that has been flattened.
+#[derive(Default)]
+struct Polyline {
+    /// The points of the polyline.
+    ///
+    /// For closed subpaths, the last point is the same as the first.
+    points: Vec<Point>,
+
+    /// The distance along the polyline of each point.
+    lengths: Vec<f64>,
+}
+
+impl Polyline {
+    fn push(&mut self, pt: Point) {
+        let length = match (self.points.last(), self.lengths.last()) {
+            (Some(&last), Some(&length)) => length + last.distance(pt),
+            _ => 0.0,
+        };
+
+        self.points.push(pt);
+        self.lengths.push(length);
+    }
+
+    fn total_length(&self) -> f64 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    /// Get the point at a distance along the polyline, starting the search at `segment`.
+    ///
+    /// Returns the point and the index of the segment it is on.
+    fn point_at(&self, distance: f64, mut segment: usize) -> (Point, usize) {
+        while segment + 2 < self.points.len() && self.lengths[segment + 1] < distance {
+            segment += 1;
+        }
+
+        let (start, end) = (self.lengths[segment], self.lengths[segment + 1]);
+        let t = if end > start {
+            ((distance - start) / (end - start)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        (
+            self.points[segment].lerp(self.points[segment + 1], t),
+            segment,
+        )
+    }
+}
+
+impl Dasher {
+    pub(crate) fn new() -> Self {
+        Self {
+            cache: Vec::new(),
+            output: BezPath::new(),
+            elements: Vec::new(),
+        }
+    }
+
+    /// Split a shape into dashes.
+    ///
+    /// Returns `None` if the pattern is invalid, in which case the shape should be drawn
+    /// without dashes.
+    pub(crate) fn dash(
+        &mut self,
+        shape: &impl Shape,
+        tolerance: f64,
+        pattern: &[f64],
+        offset: f64,
+    ) -> Option<&BezPath> {
+        let period: f64 = pattern.iter().sum();
+        if !period.is_finite()
+            || period <= 0.0
+            || pattern.iter().any(|len| !len.is_finite() || *len < 0.0)
+        {
+            return None;
+        }
+
+        // Patterns with an odd number of lengths are repeated to get an even number.
+        let period = if pattern.len() % 2 == 1 {
+            period * 2.0
+        } else {
+            period
+        };
+        let dash_len = |i: usize| pattern[i % pattern.len()];
+
+        self.flatten(shape, tolerance);
+        let polylines = &self.cache[0].polylines;
+
+        // Don't generate an unreasonable number of dashes.
+        let total: f64 = polylines.iter().map(Polyline::total_length).sum();
+        if total / period > MAX_DASHES {
+            return None;
+        }
+
+        let output = &mut self.output;
+        output.truncate(0);
+
+        for polyline in polylines {
+            if polyline.points.len() < 2 {
+                continue;
+            }
+
+            // Find where the pattern starts. The pattern restarts for every subpath.
+            let mut phase = offset.rem_euclid(period);
+            let mut index = 0;
+            while phase > 0.0 && phase >= dash_len(index) {
+                phase -= dash_len(index);
+                index += 1;
+            }
+
+            let total = polyline.total_length();
+            let mut distance = 0.0;
+            let mut segment = 0;
+            let mut remaining = dash_len(index) - phase;
+
+            while distance < total {
+                let end = (distance + remaining).min(total);
+
+                // Even indices are dashes, odd indices are gaps.
+                if index % 2 == 0 {
+                    let (start_pt, start_segment) = polyline.point_at(distance, segment);
+                    output.move_to(start_pt);
+
+                    segment = start_segment;
+                    while segment + 2 < polyline.points.len() && polyline.lengths[segment + 1] < end
+                    {
+                        segment += 1;
+                        output.line_to(polyline.points[segment]);
+                    }
+
+                    let (end_pt, end_segment) = polyline.point_at(end, segment);
+                    output.line_to(end_pt);
+                    segment = end_segment;
+                }
+
+                distance = end;
+                index += 1;
+                remaining = dash_len(index);
+            }
+        }
+
+        Some(output)
+    }
+
+    /// Move the flattened version of a shape to the front of the cache, flattening it if needed.
+    fn flatten(&mut self, shape: &impl Shape, tolerance: f64) {
+        let elements = &mut self.elements;
+        elements.clear();
+        elements.push(tolerance.to_bits());
+        for el in shape.path_elements(tolerance) {
+            push_element(el, elements);
+        }
+        let key = {
+            let mut hasher = DefaultHasher::new();
+            elements.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        match self
+            .cache
+            .iter()
+            .position(|flat| flat.key == key && flat.elements == *elements)
+        {
+            Some(i) => {
+                // Move the entry to the front.
+                self.cache[..=i].rotate_right(1);
+            }
+
+            None => {
+                let mut polylines: Vec<Polyline> = Vec::new();
+                let mut start = None;
+                piet::kurbo::flatten(shape.path_elements(tolerance), tolerance, |el| match el {
+                    PathEl::MoveTo(pt) => {
+                        start = Some(pt);
+                        polylines.push(Polyline::default());
+                        polylines.last_mut().unwrap().push(pt);
+                    }
+                    PathEl::LineTo(pt) => {
+                        if polylines.is_empty() {
+                            polylines.push(Polyline::default());
+                        }
+                        let polyline = polylines.last_mut().unwrap();
+                        if polyline.points.is_empty() {
+                            // Segments after a `ClosePath` continue from the start of the subpath.
+                            polyline.push(start.unwrap_or(pt));
+                        }
+                        polyline.push(pt);
+                    }
+                    PathEl::ClosePath => {
+                        if let (Some(start), Some(polyline)) = (start, polylines.last_mut()) {
+                            polyline.push(start);
+                        }
+                        polylines.push(Polyline::default());
+                    }
+                    _ => {}
+                });
+
+                if self.cache.len() == CACHE_SIZE {
+                    self.cache.pop();
+                }
+                self.cache.insert(
+                    0,
+                    Flattened {
+                        key,
+                        elements: elements.clone(),
+                        polylines,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Push the tag and the coordinates of a path element, as bits.
+fn push_element(el: PathEl, out: &mut Vec<u64>) {
+    let (tag, points): (u64, &[Point]) = match &el {
+        PathEl::MoveTo(p) => (0, std::slice::from_ref(p)),
+        PathEl::LineTo(p) => (1, std::slice::from_ref(p)),
+        PathEl::QuadTo(p1, p2) => (2, &[*p1, *p2]),
+        PathEl::CurveTo(p1, p2, p3) => (3, &[*p1, *p2, *p3]),
+        PathEl::ClosePath => (4, &[]),
+    };
+
+    out.push(tag);
+    for pt in points {
+        out.extend([pt.x.to_bits(), pt.y.to_bits()]);
+    }
+}
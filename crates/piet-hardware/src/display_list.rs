@@ -0,0 +1,536 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Lists of drawing commands that are recorded once and played back later.
+
+use super::brush::Brush;
+use super::filter::ColorFilter;
+use super::gpu_backend::{CompositeMode, GpuContext};
+use super::image::Image;
+use super::text::{Text, TextLayout};
+use super::RenderContext;
+
+use piet::kurbo::{Affine, BezPath, Circle, Line, Point, Rect, RoundedRect, Shape};
+use piet::{Error as Pierror, FixedGradient, InterpolationMode, StrokeStyle};
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Evaluate `$body` with `$shape` bound to the shape inside of a [`Geometry`].
+macro_rules! with_shape {
+    ($geometry:expr, |$shape:ident| $body:expr) => {
+        match $geometry {
+            Geometry::Rect($shape) => $body,
+            Geometry::RoundedRect($shape) => $body,
+            Geometry::Circle($shape) => $body,
+            Geometry::Line($shape) => $body,
+            Geometry::Path($shape) => $body,
+        }
+    };
+}
+
+/// The tolerance that shapes are converted to paths with.
+///
+/// The paths are flattened again when the list is played, so this only needs to be fine
+/// enough for the list to be zoomed into.
+const PATH_TOLERANCE: f64 = 1e-3;
+
+/// A list of drawing commands, recorded with a [`Recorder`] and played back with
+/// [`RenderContext::play`].
+///
+/// Unlike a [`Scene`], a display list keeps the commands themselves rather than their
+/// triangles, so it can hold clips, clears, color filters, custom brushes, composite modes
+/// and text, and is tessellated again whenever it is played. This suits static parts of a
+/// user interface that are recorded once and drawn every frame with different transforms.
+///
+/// [`Scene`]: crate::Scene
+pub struct DisplayList<C: GpuContext + ?Sized> {
+    /// The recorded commands, in order.
+    ops: Vec<Op<C>>,
+}
+
+/// A recorded shape.
+///
+/// Shapes that are drawn faster than general paths keep their kind, so that playing them
+/// back takes the same path through the renderer as drawing them directly.
+enum Geometry {
+    Rect(Rect),
+    RoundedRect(RoundedRect),
+    Circle(Circle),
+    Line(Line),
+    Path(BezPath),
+}
+
+impl Geometry {
+    fn new(shape: impl Shape) -> Self {
+        if let Some(rect) = shape.as_rect() {
+            Self::Rect(rect)
+        } else if let Some(rounded) = shape.as_rounded_rect() {
+            Self::RoundedRect(rounded)
+        } else if let Some(circle) = shape.as_circle() {
+            Self::Circle(circle)
+        } else if let Some(line) = shape.as_line() {
+            Self::Line(line)
+        } else {
+            Self::Path(shape.into_path(PATH_TOLERANCE))
+        }
+    }
+
+    fn bounding_box(&self) -> Rect {
+        with_shape!(self, |shape| shape.bounding_box())
+    }
+}
+
+/// A single recorded command.
+enum Op<C: GpuContext + ?Sized> {
+    Save,
+    Restore,
+    Transform(Affine),
+    GlobalAlpha(f64),
+    CompositeMode(CompositeMode),
+    Clip(Geometry),
+    Clear(Option<Rect>, piet::Color),
+    Fill {
+        shape: Geometry,
+        brush: Brush<C>,
+        even_odd: bool,
+    },
+    Stroke {
+        shape: Geometry,
+        brush: Brush<C>,
+        width: f64,
+        style: StrokeStyle,
+    },
+    Text(TextLayout, Point),
+    Image {
+        image: Image<C>,
+        src_rect: Rect,
+        dst_rect: Rect,
+        interp: InterpolationMode,
+        filter: Option<ColorFilter>,
+    },
+    BlurredRect {
+        rect: Rect,
+        blur_radius: f64,
+        brush: Brush<C>,
+    },
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for DisplayList<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DisplayList")
+            .field("commands", &self.ops.len())
+            .finish()
+    }
+}
+
+impl<C: GpuContext + ?Sized> DisplayList<C> {
+    /// Whether nothing was recorded into this list.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The number of commands in this list.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+}
+
+/// A [`piet::RenderContext`] that records what is drawn into a [`DisplayList`].
+///
+/// Recorders are created with [`RenderContext::recorder`], which they borrow to create
+/// brushes, images and text layouts. Nothing is drawn until the list is played. The recorder
+/// starts with an identity transform, which the transform of the context is prepended to
+/// when the list is played.
+pub struct Recorder<'r, 'a, C: GpuContext + ?Sized> {
+    /// The context that brushes, images and text are created with.
+    context: &'r mut RenderContext<'a, C>,
+
+    /// The commands recorded so far.
+    ops: Vec<Op<C>>,
+
+    /// The transforms of the saved states, with the current one last.
+    transforms: Vec<Affine>,
+
+    /// The global alpha of the current state.
+    alpha: Vec<f64>,
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for Recorder<'_, '_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("commands", &self.ops.len())
+            .field("depth", &self.transforms.len())
+            .finish()
+    }
+}
+
+impl<'r, 'a, C: GpuContext + ?Sized> Recorder<'r, 'a, C> {
+    /// Finish recording and get the [`DisplayList`].
+    ///
+    /// States that are still saved are restored when the list is played.
+    pub fn into_display_list(self) -> DisplayList<C> {
+        DisplayList { ops: self.ops }
+    }
+
+    /// Get the opacity that everything recorded is multiplied by.
+    pub fn global_alpha(&self) -> f64 {
+        *self.alpha.last().unwrap()
+    }
+
+    /// Set the opacity that everything recorded is multiplied by.
+    ///
+    /// This is relative to the global alpha of the context the list is played into, and is
+    /// saved and restored like [`RenderContext::set_global_alpha`].
+    pub fn set_global_alpha(&mut self, alpha: f64) {
+        let alpha = if alpha.is_nan() {
+            0.0
+        } else {
+            alpha.clamp(0.0, 1.0)
+        };
+        *self.alpha.last_mut().unwrap() = alpha;
+        self.ops.push(Op::GlobalAlpha(alpha));
+    }
+
+    /// Set the Porter-Duff operator that the following commands are combined with the target
+    /// with.
+    ///
+    /// Like in [`RenderContext::set_composite_mode`], this isn't saved and restored with the
+    /// rest of the state. The mode of the context is set back once the list has been played.
+    pub fn set_composite_mode(&mut self, mode: CompositeMode) {
+        self.ops.push(Op::CompositeMode(mode));
+    }
+
+    /// Record drawing an image with a color filter applied to it.
+    pub fn draw_image_filtered(
+        &mut self,
+        image: &Image<C>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        filter: &ColorFilter,
+    ) {
+        let src_rect = Rect::ZERO.with_size(piet::Image::size(image));
+        self.draw_image_area_filtered(image, src_rect, dst_rect, interp, filter)
+    }
+
+    /// Record drawing an area of an image with a color filter applied to it.
+    pub fn draw_image_area_filtered(
+        &mut self,
+        image: &Image<C>,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+        filter: &ColorFilter,
+    ) {
+        self.ops.push(Op::Image {
+            image: image.clone(),
+            src_rect: src_rect.into(),
+            dst_rect: dst_rect.into(),
+            interp,
+            filter: Some(*filter),
+        });
+    }
+
+    /// Create a brush from a gradient that continues past its ends with `spread`.
+    ///
+    /// See [`RenderContext::gradient_with_spread`].
+    pub fn gradient_with_spread(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        spread: super::SpreadMode,
+    ) -> Result<Brush<C>, Pierror> {
+        self.context.gradient_with_spread(gradient, spread)
+    }
+}
+
+impl<'a, C: GpuContext + ?Sized> RenderContext<'a, C> {
+    /// Start recording a [`DisplayList`] that can be played back into this context later.
+    pub fn recorder(&mut self) -> Recorder<'_, 'a, C> {
+        Recorder {
+            context: self,
+            ops: Vec::new(),
+            transforms: vec![Affine::IDENTITY],
+            alpha: vec![1.0],
+        }
+    }
+
+    /// Play back a [`DisplayList`].
+    ///
+    /// The list is drawn relative to the current transform, clip and global alpha, as if its
+    /// commands were drawn inside of a [`save`] and [`restore`]. Clears ignore the transform
+    /// and clip like [`clear`] does. Errors from single commands are reported like the ones
+    /// from drawing directly, while errors from saving and restoring the state or setting the
+    /// composite mode stop playback and are returned.
+    ///
+    /// [`save`]: piet::RenderContext::save
+    /// [`restore`]: piet::RenderContext::restore
+    /// [`clear`]: piet::RenderContext::clear
+    pub fn play(&mut self, list: &DisplayList<C>) -> Result<(), Pierror> {
+        use piet::RenderContext as _;
+
+        let depth = self.state.len();
+        let composite_mode = self.composite_mode;
+        self.save()?;
+
+        let played = self.play_ops(&list.ops);
+
+        // Restore the states the list left saved, along with the one saved above.
+        let mut restored = Ok(());
+        while self.state.len() > depth {
+            restored = restored.and(self.restore());
+        }
+        let reset = self.set_composite_mode(composite_mode);
+
+        played.and(restored).and(reset)
+    }
+
+    /// Apply the commands of a display list, stopping at the first one that fails.
+    fn play_ops(&mut self, ops: &[Op<C>]) -> Result<(), Pierror> {
+        use piet::RenderContext as _;
+
+        // Global alpha in the list is relative to the alpha of each saved state.
+        let mut base_alpha = vec![self.global_alpha()];
+        for op in ops {
+            match op {
+                Op::Save => {
+                    base_alpha.push(self.global_alpha());
+                    self.save()?;
+                }
+                Op::Restore => {
+                    base_alpha.pop();
+                    self.restore()?;
+                }
+                Op::Transform(transform) => self.transform(*transform),
+                Op::GlobalAlpha(alpha) => self.set_global_alpha(base_alpha.last().unwrap() * alpha),
+                Op::CompositeMode(mode) => self.set_composite_mode(*mode)?,
+                Op::Clip(shape) => with_shape!(shape, |shape| self.clip(shape)),
+                Op::Clear(region, color) => self.clear(*region, *color),
+                Op::Fill {
+                    shape,
+                    brush,
+                    even_odd: false,
+                } => with_shape!(shape, |shape| self.fill(shape, brush)),
+                Op::Fill {
+                    shape,
+                    brush,
+                    even_odd: true,
+                } => with_shape!(shape, |shape| self.fill_even_odd(shape, brush)),
+                Op::Stroke {
+                    shape,
+                    brush,
+                    width,
+                    style,
+                } => with_shape!(shape, |shape| self
+                    .stroke_styled(shape, brush, *width, style)),
+                Op::Text(layout, pos) => self.draw_text(layout, *pos),
+                Op::Image {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    interp,
+                    filter: None,
+                } => self.draw_image_area(image, *src_rect, *dst_rect, *interp),
+                Op::Image {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    interp,
+                    filter: Some(filter),
+                } => self.draw_image_area_filtered(image, *src_rect, *dst_rect, *interp, filter),
+                Op::BlurredRect {
+                    rect,
+                    blur_radius,
+                    brush,
+                } => self.blurred_rect(*rect, *blur_radius, brush),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: GpuContext + ?Sized> piet::IntoBrush<Recorder<'_, '_, C>> for Brush<C> {
+    fn make_brush<'b>(
+        &'b self,
+        _piet: &mut Recorder<'_, '_, C>,
+        _bbox: impl FnOnce() -> Rect,
+    ) -> Cow<'b, Brush<C>> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<C: GpuContext + ?Sized> Recorder<'_, '_, C> {
+    fn push_fill(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>, even_odd: bool) {
+        let shape = Geometry::new(shape);
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        self.ops.push(Op::Fill {
+            shape,
+            brush,
+            even_odd,
+        });
+    }
+}
+
+impl<C: GpuContext + ?Sized> piet::RenderContext for Recorder<'_, '_, C> {
+    type Brush = Brush<C>;
+    type Text = Text;
+    type TextLayout = TextLayout;
+    type Image = Image<C>;
+
+    fn status(&mut self) -> Result<(), Pierror> {
+        Ok(())
+    }
+
+    fn solid_brush(&mut self, color: piet::Color) -> Self::Brush {
+        Brush::solid(color)
+    }
+
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Pierror> {
+        self.context.gradient(gradient)
+    }
+
+    fn clear(&mut self, region: impl Into<Option<Rect>>, color: piet::Color) {
+        self.ops.push(Op::Clear(region.into(), color));
+    }
+
+    fn stroke(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>, width: f64) {
+        self.stroke_styled(shape, brush, width, &StrokeStyle::default())
+    }
+
+    fn stroke_styled(
+        &mut self,
+        shape: impl Shape,
+        brush: &impl piet::IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
+    ) {
+        let shape = Geometry::new(shape);
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        self.ops.push(Op::Stroke {
+            shape,
+            brush,
+            width,
+            style: style.clone(),
+        });
+    }
+
+    fn fill(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>) {
+        self.push_fill(shape, brush, false)
+    }
+
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>) {
+        self.push_fill(shape, brush, true)
+    }
+
+    fn clip(&mut self, shape: impl Shape) {
+        self.ops.push(Op::Clip(Geometry::new(shape)));
+    }
+
+    fn text(&mut self) -> &mut Self::Text {
+        self.context.text()
+    }
+
+    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        self.ops.push(Op::Text(layout.clone(), pos.into()));
+    }
+
+    fn save(&mut self) -> Result<(), Pierror> {
+        self.transforms.push(*self.transforms.last().unwrap());
+        self.alpha.push(*self.alpha.last().unwrap());
+        self.ops.push(Op::Save);
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), Pierror> {
+        if self.transforms.len() <= 1 {
+            return Err(Pierror::StackUnbalance);
+        }
+
+        self.transforms.pop();
+        self.alpha.pop();
+        self.ops.push(Op::Restore);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Pierror> {
+        Ok(())
+    }
+
+    fn transform(&mut self, transform: Affine) {
+        let slot = self.transforms.last_mut().unwrap();
+        *slot = transform * *slot;
+        self.ops.push(Op::Transform(transform));
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: piet::ImageFormat,
+    ) -> Result<Self::Image, Pierror> {
+        self.context.make_image(width, height, buf, format)
+    }
+
+    fn draw_image(
+        &mut self,
+        image: &Self::Image,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        let src_rect = Rect::ZERO.with_size(piet::Image::size(image));
+        self.draw_image_area(image, src_rect, dst_rect, interp)
+    }
+
+    fn draw_image_area(
+        &mut self,
+        image: &Self::Image,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: InterpolationMode,
+    ) {
+        self.ops.push(Op::Image {
+            image: image.clone(),
+            src_rect: src_rect.into(),
+            dst_rect: dst_rect.into(),
+            interp,
+            filter: None,
+        });
+    }
+
+    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Pierror> {
+        // Nothing has been drawn yet.
+        Err(Pierror::NotSupported)
+    }
+
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl piet::IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || rect).into_owned();
+        self.ops.push(Op::BlurredRect {
+            rect,
+            blur_radius,
+            brush,
+        });
+    }
+
+    fn current_transform(&self) -> Affine {
+        *self.transforms.last().unwrap()
+    }
+}
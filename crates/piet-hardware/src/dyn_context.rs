@@ -0,0 +1,637 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! A type-erased [`GpuContext`], for picking a backend at runtime.
+
+use crate::brush::CustomBrush;
+use crate::filter::ColorMatrix;
+use crate::gpu_backend::{
+    ClipShape, CompositeMode, GpuContext, PresentMode, RepeatStrategy, Vertex,
+};
+use crate::projection::Projection;
+
+use piet::kurbo::{Affine, Rect};
+use piet::InterpolationMode;
+
+use std::any::Any;
+use std::error::Error as StdError;
+use std::fmt;
+
+/// A [`GpuContext`] whose backend is chosen at runtime.
+///
+/// This wraps any other [`GpuContext`] and erases its type, so that an application can keep
+/// a single, non-generic `Source<DynGpuContext>` no matter which backend it ends up using.
+/// Textures and vertex buffers are boxed, and passing a resource created by one
+/// `DynGpuContext` into another one backed by a different type will panic.
+pub struct DynGpuContext {
+    inner: Box<dyn ErasedContext>,
+}
+
+impl fmt::Debug for DynGpuContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynGpuContext")
+            .field("backend", &self.inner.type_name())
+            .finish()
+    }
+}
+
+impl DynGpuContext {
+    /// Erase the type of a [`GpuContext`].
+    pub fn new<C>(context: C) -> Self
+    where
+        C: GpuContext + 'static,
+        C::Texture: 'static,
+        C::VertexBuffer: 'static,
+    {
+        Self {
+            inner: Box::new(context),
+        }
+    }
+
+    /// Get a reference to the underlying context, if it is of type `C`.
+    pub fn downcast_ref<C: GpuContext + 'static>(&self) -> Option<&C> {
+        self.inner.as_any().downcast_ref()
+    }
+}
+
+/// A texture created by a [`DynGpuContext`].
+pub struct DynTexture(Box<dyn Any>);
+
+impl fmt::Debug for DynTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DynTexture { .. }")
+    }
+}
+
+/// A vertex buffer created by a [`DynGpuContext`].
+pub struct DynVertexBuffer(Box<dyn Any>);
+
+impl fmt::Debug for DynVertexBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DynVertexBuffer { .. }")
+    }
+}
+
+/// An error that occurred in a [`DynGpuContext`].
+#[derive(Debug)]
+pub struct DynError(Box<dyn StdError>);
+
+impl DynError {
+    /// Get the underlying error.
+    pub fn into_inner(self) -> Box<dyn StdError> {
+        self.0
+    }
+}
+
+impl fmt::Display for DynError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for DynError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+impl GpuContext for DynGpuContext {
+    type Texture = DynTexture;
+    type VertexBuffer = DynVertexBuffer;
+    type Error = DynError;
+
+    fn clear(&self, color: piet::Color) {
+        self.inner.clear(color)
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        self.inner.create_texture(interpolation, repeat)
+    }
+
+    fn delete_texture(&self, texture: Self::Texture) {
+        self.inner.delete_texture(texture)
+    }
+
+    fn write_texture(
+        &self,
+        texture: &Self::Texture,
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        self.inner.write_texture(texture, size, format, data)
+    }
+
+    fn write_subtexture(
+        &self,
+        texture: &Self::Texture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    ) {
+        self.inner
+            .write_subtexture(texture, offset, size, format, data)
+    }
+
+    fn set_texture_interpolation(&self, texture: &Self::Texture, interpolation: InterpolationMode) {
+        self.inner.set_texture_interpolation(texture, interpolation)
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        self.inner.max_texture_size()
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        self.inner.create_vertex_buffer()
+    }
+
+    fn delete_vertex_buffer(&self, buffer: Self::VertexBuffer) {
+        self.inner.delete_vertex_buffer(buffer)
+    }
+
+    fn write_vertices(&self, buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        self.inner.write_vertices(buffer, vertices, indices)
+    }
+
+    fn push_buffers(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        current_texture: &Self::Texture,
+        mask_texture: &Self::Texture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.push_buffers(
+            vertex_buffer,
+            current_texture,
+            mask_texture,
+            transform,
+            size,
+        )
+    }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        self.inner.set_color_matrix(matrix)
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        self.inner.set_composite_mode(mode)
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        self.inner.set_clip_shape(shape)
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        self.inner.set_scissor(rect)
+    }
+
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        self.inner.set_projection(projection)
+    }
+
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        self.inner.set_custom_brush(brush)
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        self.inner.set_sampler_interpolation(interpolation)
+    }
+
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        self.inner.generate_mipmaps(texture)
+    }
+
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        self.inner.read_pixels(origin, size, target_size, data)
+    }
+
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, Self::Error> {
+        self.inner.clear_rect(origin, size, target_size, color)
+    }
+
+    fn show_overlay(&self, texture: &Self::Texture, source: Rect, rect: Rect) -> bool {
+        self.inner.show_overlay(texture, source, rect)
+    }
+
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<Self::Texture>, Self::Error> {
+        self.inner.create_render_target(size)
+    }
+
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        self.inner.set_render_target(target)
+    }
+
+    fn render_targets_flipped(&self) -> bool {
+        self.inner.render_targets_flipped()
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        self.inner.msaa_samples()
+    }
+
+    fn begin_occlusion_query(&self, id: u64) -> bool {
+        self.inner.begin_occlusion_query(id)
+    }
+
+    fn end_occlusion_query(&self, id: u64) {
+        self.inner.end_occlusion_query(id)
+    }
+
+    fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+        self.inner.occlusion_query_result(id)
+    }
+
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+        self.inner.create_depth_stencil(size)
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        self.inner.attach_depth_stencil(attach)
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        self.inner.push_clip_path(vertex_buffer, size)
+    }
+
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.pop_clip(vertex_buffer, size)
+    }
+
+    fn present(&self) -> Result<(), Self::Error> {
+        self.inner.present()
+    }
+
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        self.inner.set_present_mode(mode)
+    }
+
+    fn barrier(&self) -> Result<(), Self::Error> {
+        self.inner.barrier()
+    }
+}
+
+/// The object-safe version of [`GpuContext`].
+trait ErasedContext {
+    fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+    fn clear(&self, color: piet::Color);
+    fn flush(&self) -> Result<(), DynError>;
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<DynTexture, DynError>;
+    fn delete_texture(&self, texture: DynTexture);
+    fn write_texture(
+        &self,
+        texture: &DynTexture,
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: Option<&[u8]>,
+    );
+    fn write_subtexture(
+        &self,
+        texture: &DynTexture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    );
+    fn set_texture_interpolation(&self, texture: &DynTexture, interpolation: InterpolationMode);
+    fn max_texture_size(&self) -> (u32, u32);
+    fn create_vertex_buffer(&self) -> Result<DynVertexBuffer, DynError>;
+    fn delete_vertex_buffer(&self, buffer: DynVertexBuffer);
+    fn write_vertices(&self, buffer: &DynVertexBuffer, vertices: &[Vertex], indices: &[u32]);
+    fn push_buffers(
+        &self,
+        vertex_buffer: &DynVertexBuffer,
+        current_texture: &DynTexture,
+        mask_texture: &DynTexture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), DynError>;
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool;
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool;
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool;
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool;
+    fn set_projection(&self, projection: Option<&Projection>) -> bool;
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool;
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool;
+    fn generate_mipmaps(&self, texture: &DynTexture) -> bool;
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, DynError>;
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, DynError>;
+    fn show_overlay(&self, texture: &DynTexture, source: Rect, rect: Rect) -> bool;
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<DynTexture>, DynError>;
+    fn set_render_target(&self, target: Option<&DynTexture>) -> Result<(), DynError>;
+    fn render_targets_flipped(&self) -> bool;
+    fn msaa_samples(&self) -> u32;
+    fn begin_occlusion_query(&self, id: u64) -> bool;
+    fn end_occlusion_query(&self, id: u64);
+    fn occlusion_query_result(&self, id: u64) -> Option<bool>;
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, DynError>;
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), DynError>;
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &DynVertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, DynError>;
+    fn pop_clip(&self, vertex_buffer: &DynVertexBuffer, size: (u32, u32)) -> Result<(), DynError>;
+    fn present(&self) -> Result<(), DynError>;
+    fn set_present_mode(&self, mode: PresentMode) -> bool;
+    fn barrier(&self) -> Result<(), DynError>;
+}
+
+impl<C> ErasedContext for C
+where
+    C: GpuContext + 'static,
+    C::Texture: 'static,
+    C::VertexBuffer: 'static,
+{
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<C>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clear(&self, color: piet::Color) {
+        GpuContext::clear(self, color)
+    }
+
+    fn flush(&self) -> Result<(), DynError> {
+        GpuContext::flush(self).map_err(erase_error)
+    }
+
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<DynTexture, DynError> {
+        GpuContext::create_texture(self, interpolation, repeat)
+            .map(|texture| DynTexture(Box::new(texture)))
+            .map_err(erase_error)
+    }
+
+    fn delete_texture(&self, texture: DynTexture) {
+        GpuContext::delete_texture(self, *downcast_box(texture.0))
+    }
+
+    fn write_texture(
+        &self,
+        texture: &DynTexture,
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        GpuContext::write_texture(self, downcast(&*texture.0), size, format, data)
+    }
+
+    fn write_subtexture(
+        &self,
+        texture: &DynTexture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    ) {
+        GpuContext::write_subtexture(self, downcast(&*texture.0), offset, size, format, data)
+    }
+
+    fn set_texture_interpolation(&self, texture: &DynTexture, interpolation: InterpolationMode) {
+        GpuContext::set_texture_interpolation(self, downcast(&*texture.0), interpolation)
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        GpuContext::max_texture_size(self)
+    }
+
+    fn create_vertex_buffer(&self) -> Result<DynVertexBuffer, DynError> {
+        GpuContext::create_vertex_buffer(self)
+            .map(|buffer| DynVertexBuffer(Box::new(buffer)))
+            .map_err(erase_error)
+    }
+
+    fn delete_vertex_buffer(&self, buffer: DynVertexBuffer) {
+        GpuContext::delete_vertex_buffer(self, *downcast_box(buffer.0))
+    }
+
+    fn write_vertices(&self, buffer: &DynVertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        GpuContext::write_vertices(self, downcast(&*buffer.0), vertices, indices)
+    }
+
+    fn push_buffers(
+        &self,
+        vertex_buffer: &DynVertexBuffer,
+        current_texture: &DynTexture,
+        mask_texture: &DynTexture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), DynError> {
+        GpuContext::push_buffers(
+            self,
+            downcast(&*vertex_buffer.0),
+            downcast(&*current_texture.0),
+            downcast(&*mask_texture.0),
+            transform,
+            size,
+        )
+        .map_err(erase_error)
+    }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        GpuContext::set_color_matrix(self, matrix)
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        GpuContext::set_composite_mode(self, mode)
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        GpuContext::set_clip_shape(self, shape)
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        GpuContext::set_scissor(self, rect)
+    }
+
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        GpuContext::set_projection(self, projection)
+    }
+
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        GpuContext::set_custom_brush(self, brush)
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        GpuContext::set_sampler_interpolation(self, interpolation)
+    }
+
+    fn generate_mipmaps(&self, texture: &DynTexture) -> bool {
+        GpuContext::generate_mipmaps(self, downcast(&*texture.0))
+    }
+
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, DynError> {
+        GpuContext::read_pixels(self, origin, size, target_size, data).map_err(erase_error)
+    }
+
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, DynError> {
+        GpuContext::clear_rect(self, origin, size, target_size, color).map_err(erase_error)
+    }
+
+    fn show_overlay(&self, texture: &DynTexture, source: Rect, rect: Rect) -> bool {
+        GpuContext::show_overlay(self, downcast(&*texture.0), source, rect)
+    }
+
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<DynTexture>, DynError> {
+        GpuContext::create_render_target(self, size)
+            .map(|texture| texture.map(|texture| DynTexture(Box::new(texture))))
+            .map_err(erase_error)
+    }
+
+    fn set_render_target(&self, target: Option<&DynTexture>) -> Result<(), DynError> {
+        GpuContext::set_render_target(self, target.map(|texture| downcast(&*texture.0)))
+            .map_err(erase_error)
+    }
+
+    fn render_targets_flipped(&self) -> bool {
+        GpuContext::render_targets_flipped(self)
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        GpuContext::msaa_samples(self)
+    }
+
+    fn begin_occlusion_query(&self, id: u64) -> bool {
+        GpuContext::begin_occlusion_query(self, id)
+    }
+
+    fn end_occlusion_query(&self, id: u64) {
+        GpuContext::end_occlusion_query(self, id)
+    }
+
+    fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+        GpuContext::occlusion_query_result(self, id)
+    }
+
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, DynError> {
+        GpuContext::create_depth_stencil(self, size).map_err(erase_error)
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), DynError> {
+        GpuContext::attach_depth_stencil(self, attach).map_err(erase_error)
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &DynVertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, DynError> {
+        GpuContext::push_clip_path(self, downcast(&*vertex_buffer.0), size).map_err(erase_error)
+    }
+
+    fn pop_clip(&self, vertex_buffer: &DynVertexBuffer, size: (u32, u32)) -> Result<(), DynError> {
+        GpuContext::pop_clip(self, downcast(&*vertex_buffer.0), size).map_err(erase_error)
+    }
+
+    fn present(&self) -> Result<(), DynError> {
+        GpuContext::present(self).map_err(erase_error)
+    }
+
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        GpuContext::set_present_mode(self, mode)
+    }
+
+    fn barrier(&self) -> Result<(), DynError> {
+        GpuContext::barrier(self).map_err(erase_error)
+    }
+}
+
+fn erase_error<E: StdError + 'static>(error: E) -> DynError {
+    DynError(Box::new(error))
+}
+
+fn downcast<T: 'static>(resource: &dyn Any) -> &T {
+    resource
+        .downcast_ref()
+        .expect("resource was created by a different kind of context")
+}
+
+fn downcast_box<T: 'static>(resource: Box<dyn Any>) -> Box<T> {
+    resource
+        .downcast()
+        .expect("resource was created by a different kind of context")
+}
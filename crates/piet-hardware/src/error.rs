@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Errors that occur while drawing, along with the context they occurred in.
+
+use piet::kurbo::Rect;
+use piet::Error as Pierror;
+
+use std::error::Error as StdError;
+use std::fmt;
+
+/// The drawing operation that caused an error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// An error occurred while clearing the target.
+    Clear,
+
+    /// An error occurred while stroking a shape.
+    Stroke,
+
+    /// An error occurred while filling a shape.
+    Fill,
+
+    /// An error occurred while clipping to a shape.
+    Clip,
+
+    /// An error occurred while drawing text.
+    DrawText,
+
+    /// An error occurred while drawing an image.
+    DrawImage,
+
+    /// An error occurred while drawing a blurred rectangle.
+    BlurredRect,
+}
+
+impl Operation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Operation::Clear => "clear",
+            Operation::Stroke => "stroke",
+            Operation::Fill => "fill",
+            Operation::Clip => "clip",
+            Operation::DrawText => "draw_text",
+            Operation::DrawImage => "draw_image",
+            Operation::BlurredRect => "blurred_rect",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error that occurred during a drawing operation.
+///
+/// These are collected by the [`RenderContext`] and can be retrieved with
+/// [`RenderContext::take_errors`].
+///
+/// [`RenderContext`]: crate::RenderContext
+/// [`RenderContext::take_errors`]: crate::RenderContext::take_errors
+#[derive(Debug)]
+pub struct DrawError {
+    /// The operation that failed.
+    operation: Operation,
+
+    /// The bounding box of the shape being drawn, in user space.
+    bounds: Option<Rect>,
+
+    /// The underlying error.
+    error: Pierror,
+}
+
+impl DrawError {
+    pub(crate) fn new(operation: Operation, bounds: Option<Rect>, error: Pierror) -> Self {
+        Self {
+            operation,
+            bounds,
+            error,
+        }
+    }
+
+    /// The operation that failed.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// The bounding box of the shape that was being drawn, if there was one.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// Get a reference to the underlying error.
+    pub fn error(&self) -> &Pierror {
+        &self.error
+    }
+
+    /// Convert this into the underlying error.
+    pub fn into_error(self) -> Pierror {
+        self.error
+    }
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bounds {
+            Some(bounds) => write!(
+                f,
+                "{} failed at ({}, {}, {}, {}): {}",
+                self.operation, bounds.x0, bounds.y0, bounds.x1, bounds.y1, self.error
+            ),
+            None => write!(f, "{} failed: {}", self.operation, self.error),
+        }
+    }
+}
+
+impl StdError for DrawError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Color filters that can be applied when drawing images.
+
+/// A filter applied to the colors of an image as it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ColorFilter {
+    /// Multiply the image by a color.
+    ///
+    /// This is applied through the vertex color, so it is supported by every backend. Drawing
+    /// a white, monochrome icon with a tint will paint it in the tint color.
+    Tint(piet::Color),
+
+    /// Convert the image to grayscale, using the Rec. 709 luma coefficients.
+    Grayscale,
+
+    /// Transform the image's colors using a color matrix.
+    Matrix(ColorMatrix),
+}
+
+impl ColorFilter {
+    /// Get the color matrix that this filter needs, if any.
+    pub(crate) fn color_matrix(&self) -> Option<ColorMatrix> {
+        match self {
+            Self::Tint(_) => None,
+            Self::Grayscale => Some(ColorMatrix::GRAYSCALE),
+            Self::Matrix(matrix) => Some(*matrix),
+        }
+    }
+
+    /// Get the vertex color that this filter needs.
+    pub(crate) fn vertex_color(&self) -> piet::Color {
+        match self {
+            Self::Tint(color) => *color,
+            _ => piet::Color::WHITE,
+        }
+    }
+}
+
+/// A 4x5 matrix that transforms colors.
+///
+/// The matrix is stored in row-major order, like the SVG `feColorMatrix` filter. Each row
+/// computes one of the red, green, blue and alpha output channels from the straight-alpha
+/// input channels, with the fifth column being a constant offset. All channels range from
+/// zero to one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMatrix(pub [f32; 20]);
+
+impl ColorMatrix {
+    /// The identity matrix, which leaves colors unchanged.
+    #[rustfmt::skip]
+    pub const IDENTITY: Self = Self([
+        1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]);
+
+    /// A matrix that converts colors to grayscale.
+    #[rustfmt::skip]
+    pub const GRAYSCALE: Self = Self([
+        0.2126, 0.7152, 0.0722, 0.0, 0.0,
+        0.2126, 0.7152, 0.0722, 0.0, 0.0,
+        0.2126, 0.7152, 0.0722, 0.0, 0.0,
+        0.0,    0.0,    0.0,    1.0, 0.0,
+    ]);
+
+    /// Get the four rows of the matrix.
+    pub fn rows(&self) -> [[f32; 5]; 4] {
+        let mut rows = [[0.0; 5]; 4];
+        for (row, chunk) in rows.iter_mut().zip(self.0.chunks_exact(5)) {
+            row.copy_from_slice(chunk);
+        }
+        rows
+    }
+}
+
+impl Default for ColorMatrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
@@ -21,12 +21,23 @@
 
 //! Defines the GPU backend for piet-hardware.
 
-use piet::kurbo::Affine;
+use super::{ColorMatrix, CustomBrush, Projection};
+
+use piet::kurbo::{Affine, Rect};
 use piet::InterpolationMode;
 
 use std::error::Error;
+use std::mem;
+use std::rc::Rc;
 
 /// The backend for the GPU renderer.
+///
+/// # Alpha
+///
+/// Every texture written by `piet-hardware` contains premultiplied alpha, while vertex colors
+/// use straight alpha. The mask texture stores coverage in its alpha channel. To match other
+/// `piet` backends, implementations should premultiply the vertex color, multiply it by the
+/// texture color and the mask coverage, and blend the result with `ONE, ONE_MINUS_SRC_ALPHA`.
 pub trait GpuContext {
     /// The type associated with a GPU texture.
     type Texture;
@@ -40,6 +51,8 @@ pub trait GpuContext {
     type Error: Error + 'static;
 
     /// Clear the screen with the given color.
+    ///
+    /// The color uses straight alpha.
     fn clear(&self, color: piet::Color);
 
     /// Flush the GPU commands.
@@ -56,6 +69,9 @@ pub trait GpuContext {
     fn delete_texture(&self, texture: Self::Texture);
 
     /// Write an image to a texture.
+    ///
+    /// `Grayscale` data is a single channel of coverage, which should be stored with one byte
+    /// per pixel and sampled as premultiplied white, with the coverage in every channel.
     fn write_texture(
         &self,
         texture: &Self::Texture,
@@ -93,6 +109,12 @@ pub trait GpuContext {
     fn write_vertices(&self, buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]);
 
     /// Push buffer data to the GPU.
+    ///
+    /// This draws the vertices that were last written to `vertex_buffer`. A buffer may be
+    /// pushed several times without being written to in between, with a different transform
+    /// each time, which is used to draw retained [`Scene`](crate::Scene)s.
+    ///
+    /// See the [trait documentation](GpuContext#alpha) for how the colors should be combined.
     fn push_buffers(
         &self,
         vertex_buffer: &Self::VertexBuffer,
@@ -101,6 +123,502 @@ pub trait GpuContext {
         transform: &Affine,
         size: (u32, u32),
     ) -> Result<(), Self::Error>;
+
+    /// Set the color matrix applied to texture colors in subsequent calls to `push_buffers`.
+    ///
+    /// The matrix is applied to the straight-alpha texture color before it is multiplied by
+    /// the vertex color. `None` resets it to the identity. Returns `false` if color matrices
+    /// are not supported, which is the default.
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        matrix.is_none()
+    }
+
+    /// Set how subsequent calls to `push_buffers` are combined with the render target.
+    ///
+    /// The final color is blended with the [factors](CompositeMode::blend_factors) of the
+    /// mode instead of `ONE, ONE_MINUS_SRC_ALPHA`. `None` goes back to
+    /// [`CompositeMode::SourceOver`]. Returns `false` if composite modes are not supported,
+    /// which is the default.
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        mode.is_none()
+    }
+
+    /// Set the shape that subsequent calls to `push_buffers` are clipped to.
+    ///
+    /// The final color is multiplied by the [coverage](ClipShape::coverage) of the shape, as
+    /// well as by the mask. `None` removes the shape. Returns `false` if clipping in the
+    /// shader is not supported, which is the default; every clip goes through the mask then.
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        shape.is_none()
+    }
+
+    /// Restrict subsequent calls to `push_buffers` to a rectangle of the target.
+    ///
+    /// The rectangle is `[x0, y0, x1, y1]` in the pixels of the target, from its top-left
+    /// corner, and lies within the target. `None` removes it. Returns `false` if scissor
+    /// rectangles are not supported, which is the default; the clip goes through the
+    /// [clip shape](Self::set_clip_shape) or the mask then.
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        rect.is_none()
+    }
+
+    /// Set the projection applied to vertex positions in subsequent calls to `push_buffers`.
+    ///
+    /// The projection is applied after the transform of `push_buffers`, in the pixels of the
+    /// target, and is followed by a perspective divide; see [`Projection::after`]. To draw
+    /// with perspective, pass the position to the rasterizer with its w component instead of
+    /// dividing in the vertex shader, and interpolate the mask and clip coordinates linearly
+    /// in screen space. Points with a w of zero or less are behind the viewer and aren't
+    /// drawn. `None` resets it to the identity. Returns `false` if projections are not
+    /// supported, which is the default.
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        projection.is_none()
+    }
+
+    /// Set the custom brush that fills subsequent calls to `push_buffers`.
+    ///
+    /// The shader of the brush computes the color that the texture would otherwise provide,
+    /// from the texture coordinates of the vertices, which hold their position in user space;
+    /// see [`BrushShaderInterface`](crate::BrushShaderInterface). `None` goes back to sampling
+    /// the texture. Returns `false` if the backend doesn't know the shader, or doesn't support
+    /// custom brushes, which is the default.
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        brush.is_none()
+    }
+
+    /// Set the interpolation that the texture is sampled with in subsequent calls to
+    /// `push_buffers`, instead of the texture's own interpolation mode.
+    ///
+    /// This lets one texture be drawn with several interpolation modes without changing it in
+    /// between, which matters for backends that batch their draws. `None` goes back to the
+    /// texture's own mode. Returns `false` if this is not supported, which is the default; the
+    /// mode of the texture is changed before it is drawn then.
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        interpolation.is_none()
+    }
+
+    /// Generate mip levels for a texture, so that it doesn't alias when it is sampled with
+    /// bilinear interpolation at a fraction of its size.
+    ///
+    /// The levels are kept up to date by `write_subtexture` and dropped by `write_texture`.
+    /// This does nothing if the texture already has them. Returns `false` if the texture can't
+    /// have mip levels, which is the default.
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        let _ = texture;
+        false
+    }
+
+    /// Read back an area of the render target.
+    ///
+    /// `origin` and `size` describe the area in pixels, with the origin at the top left of a
+    /// target of size `target_size`. The pixels are written to `data` as tightly packed rows of
+    /// premultiplied RGBA, from top to bottom, and must include everything pushed so far.
+    /// Returns `Ok(false)` if reading back is not supported, which is the default.
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        let _ = (origin, size, target_size, data);
+        Ok(false)
+    }
+
+    /// Replace an area of the render target with a color, without blending.
+    ///
+    /// `origin` and `size` describe the area in pixels, like in `read_pixels`. Unlike drawing
+    /// a rectangle, this writes the color as is, so a transparent color leaves transparent
+    /// pixels behind; this is what offscreen targets and layers need. Returns `Ok(false)` if
+    /// clearing part of the target is not supported, which is the default.
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: piet::Color,
+    ) -> Result<bool, Self::Error> {
+        let _ = (origin, size, target_size, color);
+        Ok(false)
+    }
+
+    /// Show an area of a texture on an overlay plane or sub-surface of the compositor, below
+    /// the render target.
+    ///
+    /// `source` is the area of the texture in texels, and `rect` is where it is shown, in
+    /// pixels of the render target from its top-left corner. Afterwards, the area is drawn
+    /// over with [`CompositeMode::Clear`] so that the overlay shows through. Returns `false`
+    /// if the texture can't be shown on an overlay, which is the default; it is drawn like
+    /// any other image then.
+    fn show_overlay(&self, texture: &Self::Texture, source: Rect, rect: Rect) -> bool {
+        let _ = (texture, source, rect);
+        false
+    }
+
+    /// Create a texture of the given size that can be rendered into.
+    ///
+    /// The texture holds premultiplied RGBA. Returns `Ok(None)` if offscreen rendering is not
+    /// supported, which is the default.
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<Self::Texture>, Self::Error> {
+        let _ = size;
+        Ok(None)
+    }
+
+    /// Redirect subsequent calls to `clear`, `push_buffers` and `read_pixels` to a texture
+    /// created by `create_render_target`.
+    ///
+    /// `None` restores the original render target. The texture must not be deleted while it
+    /// is bound.
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        let _ = target;
+        Ok(())
+    }
+
+    /// Whether the textures created by `create_render_target` hold their rows from the bottom
+    /// of the target to the top, like framebuffers in OpenGL.
+    ///
+    /// This decides which way up render targets are sampled when they are drawn. The default
+    /// is `false`.
+    fn render_targets_flipped(&self) -> bool {
+        false
+    }
+
+    /// The number of samples per pixel of the surface that is drawn to.
+    ///
+    /// This decides whether `piet-hardware` antialiases edges on its own. The default is 1.
+    fn msaa_samples(&self) -> u32 {
+        1
+    }
+
+    /// Start an occlusion query, which counts whether any samples of the draws until
+    /// `end_occlusion_query` pass the scissor and stencil tests.
+    ///
+    /// `piet-hardware` draws the area of a [`RenderContext::occlusion_query`] with the
+    /// `Destination` composite mode while the query runs, so that areas which are clipped
+    /// away entirely can be skipped. Returns `false` if occlusion queries are not supported,
+    /// which is the default.
+    ///
+    /// [`RenderContext::occlusion_query`]: crate::RenderContext::occlusion_query
+    fn begin_occlusion_query(&self, id: u64) -> bool {
+        let _ = id;
+        false
+    }
+
+    /// End the occlusion query that was started with `begin_occlusion_query`.
+    fn end_occlusion_query(&self, id: u64) {
+        let _ = id;
+    }
+
+    /// Get whether any samples passed in the latest run of an occlusion query whose result is
+    /// available, without waiting for the GPU.
+    ///
+    /// Returns `None` if no result is available yet, which is the default.
+    fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+        let _ = id;
+        None
+    }
+
+    /// Create a depth and stencil attachment for the bound render target, or resize the one
+    /// that it has.
+    ///
+    /// The attachment belongs to the render target and is deleted along with it. It starts
+    /// out with a depth of 1 and a stencil of 0, and keeps whether it is attached when it is
+    /// resized. Returns `Ok(false)` if the render target can't have one, which is the default.
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+        let _ = size;
+        Ok(false)
+    }
+
+    /// Attach or detach the depth and stencil attachment of the bound render target.
+    ///
+    /// Subsequent calls to `push_buffers` draw with the attachment while it is attached. Depth
+    /// and stencil tests always pass unless clips are pushed with `push_clip_path`, so
+    /// attaching it doesn't change what is drawn. Does nothing if the render target has no
+    /// attachment.
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        let _ = attach;
+        Ok(())
+    }
+
+    /// Clip subsequent calls to `push_buffers` to the triangles last written to
+    /// `vertex_buffer`, using the stencil buffer of the bound render target.
+    ///
+    /// The triangles are in the pixels of a target of size `size`, and don't overlap. Only
+    /// the pixels whose centers they cover are kept, without antialiasing, like the mask.
+    /// Clips nest, so each one is intersected with the ones before it until it is undone by
+    /// `pop_clip`, and they only apply to the render target that they were pushed on. Returns
+    /// `Ok(false)` if the render target has no stencil buffer, or it can't hold another clip,
+    /// which is the default; the clip goes through the mask then.
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        let _ = (vertex_buffer, size);
+        Ok(false)
+    }
+
+    /// Undo the clip that was pushed last on the bound render target.
+    ///
+    /// `vertex_buffer` and `size` are the same as in the call to `push_clip_path`, and the
+    /// same triangles were written to the buffer again.
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        let _ = (vertex_buffer, size);
+        Ok(())
+    }
+
+    /// Present the frame that was drawn to the render target.
+    ///
+    /// This is called by [`finish`](piet::RenderContext::finish) after `flush`, for backends
+    /// that own their swapchain. The default does nothing, which suits backends where the
+    /// windowing system presents the frame, like OpenGL's buffer swaps.
+    fn present(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Set how frames are presented, for backends that own their swapchain.
+    ///
+    /// The mode applies from the next frame on. Returns `false` if the backend doesn't present
+    /// frames itself, which is the default; presentation is then up to the windowing system.
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        let _ = mode;
+        false
+    }
+
+    /// Submit every draw so far, and hand the GPU over to the application.
+    ///
+    /// Commands that the application issues afterwards, on the same device or GL context,
+    /// run after the draws, and draws that are pushed afterwards run after them. Backends
+    /// that leave state bound between draws reset it here, so that it doesn't leak into the
+    /// application's commands. The default flushes.
+    fn barrier(&self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+macro_rules! forward_gpu_context {
+    ($($ty:ty),* $(,)?) => {$(
+        impl<C: GpuContext + ?Sized> GpuContext for $ty {
+            type Texture = C::Texture;
+            type VertexBuffer = C::VertexBuffer;
+            type Error = C::Error;
+
+            fn clear(&self, color: piet::Color) {
+                (**self).clear(color)
+            }
+
+            fn flush(&self) -> Result<(), Self::Error> {
+                (**self).flush()
+            }
+
+            fn create_texture(
+                &self,
+                interpolation: InterpolationMode,
+                repeat: RepeatStrategy,
+            ) -> Result<Self::Texture, Self::Error> {
+                (**self).create_texture(interpolation, repeat)
+            }
+
+            fn delete_texture(&self, texture: Self::Texture) {
+                (**self).delete_texture(texture)
+            }
+
+            fn write_texture(
+                &self,
+                texture: &Self::Texture,
+                size: (u32, u32),
+                format: piet::ImageFormat,
+                data: Option<&[u8]>,
+            ) {
+                (**self).write_texture(texture, size, format, data)
+            }
+
+            fn write_subtexture(
+                &self,
+                texture: &Self::Texture,
+                offset: (u32, u32),
+                size: (u32, u32),
+                format: piet::ImageFormat,
+                data: &[u8],
+            ) {
+                (**self).write_subtexture(texture, offset, size, format, data)
+            }
+
+            fn set_texture_interpolation(
+                &self,
+                texture: &Self::Texture,
+                interpolation: InterpolationMode,
+            ) {
+                (**self).set_texture_interpolation(texture, interpolation)
+            }
+
+            fn max_texture_size(&self) -> (u32, u32) {
+                (**self).max_texture_size()
+            }
+
+            fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+                (**self).create_vertex_buffer()
+            }
+
+            fn delete_vertex_buffer(&self, buffer: Self::VertexBuffer) {
+                (**self).delete_vertex_buffer(buffer)
+            }
+
+            fn write_vertices(
+                &self,
+                buffer: &Self::VertexBuffer,
+                vertices: &[Vertex],
+                indices: &[u32],
+            ) {
+                (**self).write_vertices(buffer, vertices, indices)
+            }
+
+            fn push_buffers(
+                &self,
+                vertex_buffer: &Self::VertexBuffer,
+                current_texture: &Self::Texture,
+                mask_texture: &Self::Texture,
+                transform: &Affine,
+                size: (u32, u32),
+            ) -> Result<(), Self::Error> {
+                (**self).push_buffers(vertex_buffer, current_texture, mask_texture, transform, size)
+            }
+
+            fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+                (**self).set_color_matrix(matrix)
+            }
+
+            fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+                (**self).set_composite_mode(mode)
+            }
+
+            fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+                (**self).set_clip_shape(shape)
+            }
+
+            fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+                (**self).set_scissor(rect)
+            }
+
+            fn set_projection(&self, projection: Option<&Projection>) -> bool {
+                (**self).set_projection(projection)
+            }
+
+            fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+                (**self).set_custom_brush(brush)
+            }
+
+            fn push_clip_path(
+                &self,
+                vertex_buffer: &Self::VertexBuffer,
+                size: (u32, u32),
+            ) -> Result<bool, Self::Error> {
+                (**self).push_clip_path(vertex_buffer, size)
+            }
+
+            fn pop_clip(
+                &self,
+                vertex_buffer: &Self::VertexBuffer,
+                size: (u32, u32),
+            ) -> Result<(), Self::Error> {
+                (**self).pop_clip(vertex_buffer, size)
+            }
+
+            fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+                (**self).set_sampler_interpolation(interpolation)
+            }
+
+            fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+                (**self).generate_mipmaps(texture)
+            }
+
+            fn read_pixels(
+                &self,
+                origin: (u32, u32),
+                size: (u32, u32),
+                target_size: (u32, u32),
+                data: &mut [u8],
+            ) -> Result<bool, Self::Error> {
+                (**self).read_pixels(origin, size, target_size, data)
+            }
+
+            fn clear_rect(
+                &self,
+                origin: (u32, u32),
+                size: (u32, u32),
+                target_size: (u32, u32),
+                color: piet::Color,
+            ) -> Result<bool, Self::Error> {
+                (**self).clear_rect(origin, size, target_size, color)
+            }
+
+            fn show_overlay(&self, texture: &Self::Texture, source: Rect, rect: Rect) -> bool {
+                (**self).show_overlay(texture, source, rect)
+            }
+
+            fn create_render_target(
+                &self,
+                size: (u32, u32),
+            ) -> Result<Option<Self::Texture>, Self::Error> {
+                (**self).create_render_target(size)
+            }
+
+            fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+                (**self).set_render_target(target)
+            }
+
+            fn render_targets_flipped(&self) -> bool {
+                (**self).render_targets_flipped()
+            }
+
+            fn msaa_samples(&self) -> u32 {
+                (**self).msaa_samples()
+            }
+
+            fn begin_occlusion_query(&self, id: u64) -> bool {
+                (**self).begin_occlusion_query(id)
+            }
+
+            fn end_occlusion_query(&self, id: u64) {
+                (**self).end_occlusion_query(id)
+            }
+
+            fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+                (**self).occlusion_query_result(id)
+            }
+
+            fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+                (**self).create_depth_stencil(size)
+            }
+
+            fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+                (**self).attach_depth_stencil(attach)
+            }
+
+            fn present(&self) -> Result<(), Self::Error> {
+                (**self).present()
+            }
+
+            fn set_present_mode(&self, mode: PresentMode) -> bool {
+                (**self).set_present_mode(mode)
+            }
+
+            fn barrier(&self) -> Result<(), Self::Error> {
+                (**self).barrier()
+            }
+        }
+    )*};
+}
+
+forward_gpu_context! {
+    &C,
+    &mut C,
+    Box<C>,
+    Rc<C>,
 }
 
 /// The strategy to use for repeating.
@@ -110,6 +628,9 @@ pub enum RepeatStrategy {
     /// Repeat the image.
     Repeat,
 
+    /// Repeat the image, mirroring every other repetition.
+    Reflect,
+
     /// Clamp to the edge of the image.
     Clamp,
 
@@ -117,6 +638,178 @@ pub enum RepeatStrategy {
     Color(piet::Color),
 }
 
+/// How frames are presented to the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum PresentMode {
+    /// Wait for the vertical blank, so that frames never tear.
+    ///
+    /// This is supported everywhere, and is what backends use unless told otherwise.
+    #[default]
+    Vsync,
+
+    /// Replace the queued frame with the newest one, so frames don't tear and don't wait.
+    ///
+    /// Backends that can't do this fall back to [`Vsync`](Self::Vsync).
+    Mailbox,
+
+    /// Show frames as soon as they are ready, even if they tear.
+    ///
+    /// This has the lowest latency. Backends that can't do this fall back to
+    /// [`Mailbox`](Self::Mailbox), and then to [`Vsync`](Self::Vsync).
+    Immediate,
+}
+
+/// How the colors of a draw are combined with the colors that are already on the target.
+///
+/// These are the Porter-Duff operators on premultiplied colors, where the source is the
+/// color being drawn and the destination is the target. Only the pixels that the drawn
+/// shapes cover are changed. Since anti-aliasing and clips through the mask fade the source,
+/// modes that clear the destination where the source is transparent, like
+/// [`DestinationIn`](Self::DestinationIn), also do so at the edges of those clips.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum CompositeMode {
+    /// Clear the destination.
+    Clear,
+
+    /// Replace the destination with the source.
+    Source,
+
+    /// Keep the destination.
+    Destination,
+
+    /// Draw the source over the destination.
+    #[default]
+    SourceOver,
+
+    /// Draw the source behind the destination.
+    DestinationOver,
+
+    /// Keep the source where the destination is.
+    SourceIn,
+
+    /// Keep the destination where the source is.
+    DestinationIn,
+
+    /// Keep the source where the destination isn't.
+    SourceOut,
+
+    /// Keep the destination where the source isn't.
+    DestinationOut,
+
+    /// Draw the source over the destination, only where the destination is.
+    SourceAtop,
+
+    /// Draw the destination over the source, only where the source is.
+    DestinationAtop,
+
+    /// Keep the source where the destination isn't, and the destination where the source
+    /// isn't.
+    Xor,
+
+    /// Add the source to the destination.
+    Plus,
+}
+
+impl CompositeMode {
+    /// Get the factors that the source and the destination are multiplied by before they
+    /// are added together.
+    pub fn blend_factors(self) -> (BlendFactor, BlendFactor) {
+        use BlendFactor::*;
+
+        match self {
+            Self::Clear => (Zero, Zero),
+            Self::Source => (One, Zero),
+            Self::Destination => (Zero, One),
+            Self::SourceOver => (One, OneMinusSourceAlpha),
+            Self::DestinationOver => (OneMinusDestinationAlpha, One),
+            Self::SourceIn => (DestinationAlpha, Zero),
+            Self::DestinationIn => (Zero, SourceAlpha),
+            Self::SourceOut => (OneMinusDestinationAlpha, Zero),
+            Self::DestinationOut => (Zero, OneMinusSourceAlpha),
+            Self::SourceAtop => (DestinationAlpha, OneMinusSourceAlpha),
+            Self::DestinationAtop => (OneMinusDestinationAlpha, SourceAlpha),
+            Self::Xor => (OneMinusDestinationAlpha, OneMinusSourceAlpha),
+            Self::Plus => (One, One),
+        }
+    }
+}
+
+/// A factor that a color is multiplied by when it is blended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BlendFactor {
+    /// Zero.
+    Zero,
+
+    /// One.
+    One,
+
+    /// The alpha of the source.
+    SourceAlpha,
+
+    /// One minus the alpha of the source.
+    OneMinusSourceAlpha,
+
+    /// The alpha of the destination.
+    DestinationAlpha,
+
+    /// One minus the alpha of the destination.
+    OneMinusDestinationAlpha,
+}
+
+/// A rounded rectangle that is clipped to in the fragment shader.
+///
+/// Everything is measured in pixels of the target, with the origin at the top left. Plain
+/// rectangles have radii of zero, and ellipses have radii of half their size.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ClipShape {
+    /// The left, top, right and bottom edges of the rectangle.
+    pub rect: [f32; 4],
+
+    /// The horizontal and vertical radii of the corners.
+    ///
+    /// They are no larger than half of the width and height of the rectangle.
+    pub radii: [f32; 2],
+}
+
+impl ClipShape {
+    /// Create a new clip shape.
+    pub const fn new(rect: [f32; 4], radii: [f32; 2]) -> Self {
+        Self { rect, radii }
+    }
+
+    /// Get the coverage of the pixel whose center is at `(x, y)`, from 0 to 1.
+    ///
+    /// This is what shaders are expected to compute: the signed distance from the pixel
+    /// center to the edge, turned into a coverage over one pixel. The distance to the
+    /// elliptical corners is approximated.
+    pub fn coverage(&self, x: f32, y: f32) -> f32 {
+        let [x0, y0, x1, y1] = self.rect;
+        let [rx, ry] = self.radii;
+
+        // Fold the point into the bottom right quadrant, measured from the corner's center.
+        let qx = (x - (x0 + x1) / 2.0).abs() - ((x1 - x0) / 2.0 - rx);
+        let qy = (y - (y0 + y1) / 2.0).abs() - ((y1 - y0) / 2.0 - ry);
+
+        let distance = if qx > 0.0 && qy > 0.0 {
+            if rx > 0.0 && ry > 0.0 {
+                let f = (qx / rx).hypot(qy / ry);
+                let gradient = (qx / rx / rx).hypot(qy / ry / ry);
+                f * (f - 1.0) / gradient
+            } else {
+                qx.hypot(qy)
+            }
+        } else {
+            (qx - rx).max(qy - ry)
+        };
+
+        (0.5 - distance).clamp(0.0, 1.0)
+    }
+}
+
 /// The format to be provided to the vertex array.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[non_exhaustive]
@@ -137,6 +830,25 @@ pub struct VertexFormat {
     pub stride: u32,
 }
 
+impl VertexFormat {
+    /// Create a new vertex format.
+    pub const fn new(
+        data_type: DataType,
+        format: DataFormat,
+        num_components: u32,
+        offset: u32,
+        stride: u32,
+    ) -> Self {
+        Self {
+            data_type,
+            format,
+            num_components,
+            offset,
+            stride,
+        }
+    }
+}
+
 /// The data format associated with a vertex array.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[non_exhaustive]
@@ -172,10 +884,32 @@ pub struct Vertex {
     /// The coordinate of the vertex in the texture.
     pub uv: [f32; 2],
 
-    /// The color of the vertex, in four SRGB channels.
+    /// The color of the vertex, in four SRGB channels with straight alpha.
     pub color: [u8; 4],
 }
 
+impl Vertex {
+    /// The offset of [`pos`](Self::pos) in bytes.
+    pub const POS_OFFSET: u32 = 0;
+
+    /// The offset of [`uv`](Self::uv) in bytes.
+    pub const UV_OFFSET: u32 = 8;
+
+    /// The offset of [`color`](Self::color) in bytes.
+    pub const COLOR_OFFSET: u32 = 16;
+
+    /// The size of a vertex in bytes.
+    pub const STRIDE: u32 = 20;
+}
+
+// `repr(C)` lays the fields out in order, and they all have the same alignment, so there is
+// no padding between them.
+const _: () = assert!(mem::size_of::<Vertex>() == Vertex::STRIDE as usize);
+const _: () =
+    assert!(mem::size_of::<[f32; 2]>() == (Vertex::UV_OFFSET - Vertex::POS_OFFSET) as usize);
+const _: () =
+    assert!(mem::size_of::<[f32; 2]>() == (Vertex::COLOR_OFFSET - Vertex::UV_OFFSET) as usize);
+
 /// The type of the buffer to use.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum BufferType {
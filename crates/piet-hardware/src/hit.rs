@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Testing whether points hit the shapes that are drawn.
+
+use super::builder::SubpathClosing;
+use super::gpu_backend::Vertex;
+use super::rasterizer::{Precision, Rasterizer};
+
+use lyon_tessellation::FillRule;
+use piet::kurbo::{Affine, Point, Shape, Vec2};
+use piet::{Error as Pierror, LineJoin};
+
+use std::f64::consts::SQRT_2;
+use std::fmt;
+
+/// Tests whether points hit filled and stroked shapes, the same way they are drawn.
+///
+/// Curves are flattened into the same triangles that a [`RenderContext`] with the same
+/// tolerance would draw, so a point hits a shape exactly when it lands on a pixel that the
+/// shape covers, up to anti-aliasing. This keeps editors from selecting shapes that look
+/// like they were missed, or the other way around.
+///
+/// Points and tolerances are measured on the target, after the shape's transform.
+///
+/// [`RenderContext`]: crate::RenderContext
+pub struct HitTester {
+    /// The rasterizer that shapes are tessellated with.
+    rasterizer: Rasterizer,
+
+    /// The tolerance that curves are flattened with, in pixels.
+    tolerance: f64,
+}
+
+impl fmt::Debug for HitTester {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HitTester")
+            .field("tolerance", &self.tolerance)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for HitTester {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HitTester {
+    /// Create a hit tester that matches a render context with the default settings.
+    pub fn new() -> Self {
+        Self::with_closing(SubpathClosing::default(), 1.0)
+    }
+
+    /// Create a hit tester that closes subpaths and flattens curves like a render context.
+    pub(crate) fn with_closing(closing: SubpathClosing, tolerance: f64) -> Self {
+        Self {
+            rasterizer: Rasterizer::new(closing),
+            tolerance,
+        }
+    }
+
+    /// The tolerance that curves are flattened with, in pixels.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Set the tolerance that curves are flattened with, in pixels.
+    ///
+    /// This should match [`RenderContext::set_tolerance`].
+    ///
+    /// [`RenderContext::set_tolerance`]: crate::RenderContext::set_tolerance
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance.max(super::MIN_TOLERANCE);
+    }
+
+    /// Whether `point` hits `shape` when it is filled with the non-zero rule and drawn with
+    /// `transform`.
+    pub fn hit_fill(
+        &mut self,
+        shape: impl Shape,
+        transform: Affine,
+        point: Point,
+    ) -> Result<bool, Pierror> {
+        self.hit_fill_with(shape, FillRule::NonZero, transform, point)
+    }
+
+    /// Whether `point` hits `shape` when it is filled with the even-odd rule and drawn with
+    /// `transform`.
+    pub fn hit_fill_even_odd(
+        &mut self,
+        shape: impl Shape,
+        transform: Affine,
+        point: Point,
+    ) -> Result<bool, Pierror> {
+        self.hit_fill_with(shape, FillRule::EvenOdd, transform, point)
+    }
+
+    /// Whether `point` hits the stroke of `shape` when it is drawn with `transform`.
+    ///
+    /// The width is measured in the shape's coordinates, like in
+    /// [`stroke_styled`](piet::RenderContext::stroke_styled).
+    pub fn hit_stroke(
+        &mut self,
+        shape: impl Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+        transform: Affine,
+        point: Point,
+    ) -> Result<bool, Pierror> {
+        // Joins and caps don't reach further than this from the shape.
+        let reach = match style.line_join {
+            LineJoin::Miter { limit } => limit.max(SQRT_2),
+            _ => SQRT_2,
+        } * width
+            / 2.0;
+        let point = match self.user_point(&shape, reach, transform, point) {
+            Some(point) => point,
+            None => return Ok(false),
+        };
+
+        let origin = shape.bounding_box().center().to_vec2();
+        let precision = Precision::new(self.tolerance, transform).with_origin(origin);
+        self.rasterizer
+            .stroke_shape(shape, precision, width, style, |pos| relative(pos, origin))?;
+        Ok(self.hit_triangles(point - origin))
+    }
+
+    fn hit_fill_with(
+        &mut self,
+        shape: impl Shape,
+        rule: FillRule,
+        transform: Affine,
+        point: Point,
+    ) -> Result<bool, Pierror> {
+        let point = match self.user_point(&shape, 0.0, transform, point) {
+            Some(point) => point,
+            None => return Ok(false),
+        };
+
+        let origin = shape.bounding_box().center().to_vec2();
+        let precision = Precision::new(self.tolerance, transform).with_origin(origin);
+        self.rasterizer
+            .fill_shape(shape, rule, precision, |pos| relative(pos, origin))?;
+        Ok(self.hit_triangles(point - origin))
+    }
+
+    /// Map a point on the target into the shape's coordinates, if it can be near the shape.
+    fn user_point(
+        &self,
+        shape: &impl Shape,
+        reach: f64,
+        transform: Affine,
+        point: Point,
+    ) -> Option<Point> {
+        // Nothing is visible through a degenerate transform.
+        let det = transform.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let point = transform.inverse() * point;
+        shape
+            .bounding_box()
+            .inflate(reach, reach)
+            .contains(point)
+            .then_some(point)
+    }
+
+    /// Whether a point lies in any of the tessellated triangles, and clear them.
+    fn hit_triangles(&mut self, point: Point) -> bool {
+        let vertices = self.rasterizer.vertices();
+        let position = |index: u32| {
+            let [x, y] = vertices[index as usize].pos;
+            Point::new(x as f64, y as f64)
+        };
+
+        let hit = self.rasterizer.indices().chunks_exact(3).any(|triangle| {
+            let [a, b, c] = [
+                position(triangle[0]),
+                position(triangle[1]),
+                position(triangle[2]),
+            ];
+            let sides = [
+                (b - a).cross(point - a),
+                (c - b).cross(point - b),
+                (a - c).cross(point - c),
+            ];
+            sides.iter().all(|&side| side >= 0.0) || sides.iter().all(|&side| side <= 0.0)
+        });
+
+        self.rasterizer.clear();
+        hit
+    }
+}
+
+/// Make a vertex at a position relative to `origin`.
+fn relative(pos: Point, origin: Vec2) -> Vertex {
+    let pos = pos - origin;
+    Vertex {
+        pos: [pos.x as f32, pos.y as f32],
+        ..Vertex::default()
+    }
+}
@@ -24,17 +24,36 @@
 use super::gpu_backend::GpuContext;
 use super::resources::Texture;
 
-use piet::kurbo::Size;
+use piet::kurbo::{Affine, Point, Rect, Size};
+use piet::InterpolationMode;
 
 use std::rc::Rc;
 
 /// The image type used by the GPU renderer.
+///
+/// An image can be a view of part of a texture that is shared with other images; see
+/// [`sub_image`](Self::sub_image).
 pub struct Image<C: GpuContext + ?Sized> {
     /// The texture.
     texture: Rc<Texture<C>>,
 
     /// The size of the image.
     size: Size,
+
+    /// The position of the image in the texture, in texels.
+    origin: Point,
+
+    /// The size of the whole texture, in texels.
+    texture_size: Size,
+
+    /// The interpolation that the image is sampled with when it is used as a brush.
+    interpolation: InterpolationMode,
+
+    /// Whether drawing the image tries to show it on an overlay of the compositor.
+    overlay: bool,
+
+    /// Whether every pixel of the image is known to be opaque.
+    opaque: bool,
 }
 
 impl<C: GpuContext + ?Sized> Image<C> {
@@ -43,13 +62,113 @@ impl<C: GpuContext + ?Sized> Image<C> {
         Self {
             texture: Rc::new(texture),
             size,
+            origin: Point::ORIGIN,
+            texture_size: size,
+            interpolation: InterpolationMode::Bilinear,
+            overlay: false,
+            opaque: false,
         }
     }
 
+    /// Mark whether every pixel of the image is opaque.
+    pub(crate) fn with_opaque(self, opaque: bool) -> Self {
+        Self { opaque, ..self }
+    }
+
+    /// Whether every pixel of the image is known to be opaque.
+    pub(crate) fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
     /// Get the texture.
-    pub(crate) fn texture(&self) -> &Texture<C> {
+    pub(crate) fn texture(&self) -> &Rc<Texture<C>> {
         &self.texture
     }
+
+    /// Get the texture and the interpolation to sample it with.
+    pub(crate) fn sampled(&self) -> (&Rc<Texture<C>>, InterpolationMode) {
+        (&self.texture, self.interpolation)
+    }
+
+    /// Get the interpolation that this image is sampled with when it is used as a brush.
+    pub fn interpolation(&self) -> InterpolationMode {
+        self.interpolation
+    }
+
+    /// Get a copy of this image that is sampled with `interpolation` when it is used as a
+    /// brush.
+    ///
+    /// The copy shares this image's texture.
+    pub fn with_interpolation(&self, interpolation: InterpolationMode) -> Self {
+        Self {
+            interpolation,
+            ..self.clone()
+        }
+    }
+
+    /// Create a view of an area of this image, without copying it.
+    ///
+    /// The view shares this image's texture. `rect` is in the pixels of this image, and is
+    /// clipped to it. This is useful for sprite sheets and cropped images. When the view is
+    /// drawn with bilinear filtering, the texels around it aren't blended in.
+    pub fn sub_image(&self, rect: Rect) -> Self {
+        let rect = rect
+            .abs()
+            .intersect(Rect::from_origin_size(Point::ORIGIN, self.size));
+
+        Self {
+            texture: self.texture.clone(),
+            size: rect.size(),
+            origin: self.origin + rect.origin().to_vec2(),
+            texture_size: self.texture_size,
+            interpolation: self.interpolation,
+            overlay: self.overlay,
+            opaque: self.opaque,
+        }
+    }
+
+    /// Whether drawing this image tries to show it on an overlay of the compositor.
+    pub fn overlay(&self) -> bool {
+        self.overlay
+    }
+
+    /// Get a copy of this image that is shown on an overlay plane or sub-surface of the
+    /// compositor when it is drawn, if the backend supports it.
+    ///
+    /// This is meant for opaque video frames, which the compositor can then scan out without
+    /// them going through the render target, saving power. The image is only put on an
+    /// overlay when it is drawn onto the window without clips, layers, opacity or rotation;
+    /// otherwise, or if the backend refuses, it is drawn like any other image. The overlay is
+    /// below the window, so anything drawn over the image afterwards still shows on top.
+    pub fn with_overlay(&self, overlay: bool) -> Self {
+        Self {
+            overlay,
+            ..self.clone()
+        }
+    }
+
+    /// Get the size of the whole texture that this image is a part of, in texels.
+    pub(crate) fn texture_size(&self) -> Size {
+        self.texture_size
+    }
+
+    /// Convert an area of this image, in pixels, to the same area of the texture, in texels.
+    pub(crate) fn texel_rect(&self, rect: Rect) -> Rect {
+        rect + self.origin.to_vec2()
+    }
+
+    /// Get the transform that maps `bounds` onto this image, in texture coordinates.
+    pub(crate) fn uv_transform(&self, bounds: Rect) -> Affine {
+        Affine::scale_non_uniform(
+            1.0 / self.texture_size.width,
+            1.0 / self.texture_size.height,
+        ) * Affine::translate(self.origin.to_vec2())
+            * Affine::scale_non_uniform(
+                self.size.width / bounds.width(),
+                self.size.height / bounds.height(),
+            )
+            * Affine::translate(-bounds.origin().to_vec2())
+    }
 }
 
 impl<C: GpuContext + ?Sized> Clone for Image<C> {
@@ -57,6 +176,11 @@ impl<C: GpuContext + ?Sized> Clone for Image<C> {
         Self {
             texture: self.texture.clone(),
             size: self.size,
+            origin: self.origin,
+            texture_size: self.texture_size,
+            interpolation: self.interpolation,
+            overlay: self.overlay,
+            opaque: self.opaque,
         }
     }
 }
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! The contract between `piet-hardware` and the shaders of a backend.
+
+use super::gpu_backend::{DataFormat, DataType, Vertex, VertexFormat};
+
+use std::error::Error;
+use std::fmt;
+
+/// What the shaders of a backend have to accept from `piet-hardware`.
+///
+/// This describes the vertices, uniforms and textures that are passed to
+/// [`GpuContext::push_buffers`], so that backends can check their pipelines against it. The
+/// version is increased whenever a change would require shaders to be changed.
+///
+/// [`GpuContext::push_buffers`]: crate::GpuContext::push_buffers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ShaderInterface {
+    /// The version of the interface.
+    pub version: u32,
+
+    /// The attributes of a [`Vertex`], in the order of their shader locations.
+    pub attributes: &'static [VertexFormat],
+
+    /// The size of a [`Vertex`] in bytes.
+    pub stride: u32,
+
+    /// The size of an index in bytes.
+    pub index_size: u32,
+
+    /// The values that are the same for every vertex in a call to `push_buffers`.
+    pub uniforms: &'static [Uniform],
+
+    /// The textures that are sampled in the fragment shader.
+    pub textures: &'static [TextureSlot],
+
+    /// What the code of a [custom brush](crate::Brush::custom) has to define.
+    pub brush_shader: BrushShaderInterface,
+}
+
+impl ShaderInterface {
+    /// The interface of this version of `piet-hardware`.
+    pub const CURRENT: ShaderInterface = ShaderInterface {
+        version: 1,
+        attributes: &[
+            VertexFormat::new(
+                DataType::Position,
+                DataFormat::Float,
+                2,
+                Vertex::POS_OFFSET,
+                Vertex::STRIDE,
+            ),
+            VertexFormat::new(
+                DataType::Texture,
+                DataFormat::Float,
+                2,
+                Vertex::UV_OFFSET,
+                Vertex::STRIDE,
+            ),
+            VertexFormat::new(
+                DataType::Color,
+                DataFormat::UnsignedByte,
+                4,
+                Vertex::COLOR_OFFSET,
+                Vertex::STRIDE,
+            ),
+        ],
+        stride: Vertex::STRIDE,
+        index_size: 4,
+        uniforms: &[
+            Uniform::Transform,
+            Uniform::ViewportSize,
+            Uniform::ColorMatrix,
+            Uniform::ClipShape,
+            Uniform::BrushParams,
+        ],
+        textures: &[TextureSlot::Color, TextureSlot::Mask],
+        brush_shader: BrushShaderInterface {
+            entry_point: "piet_brush",
+            params: 8,
+        },
+    };
+
+    /// Check that a backend was written against this version of the interface.
+    pub fn check_version(&self, version: u32) -> Result<(), InterfaceMismatch> {
+        if version == self.version {
+            Ok(())
+        } else {
+            Err(InterfaceMismatch::Version {
+                expected: self.version,
+                found: version,
+            })
+        }
+    }
+
+    /// Check that a pipeline reads vertices the way they are written.
+    ///
+    /// `attributes` may be in any order, and may include attributes that aren't part of the
+    /// interface.
+    pub fn check_vertex_layout(
+        &self,
+        stride: u32,
+        attributes: &[VertexFormat],
+    ) -> Result<(), InterfaceMismatch> {
+        if stride != self.stride {
+            return Err(InterfaceMismatch::Stride {
+                expected: self.stride,
+                found: stride,
+            });
+        }
+
+        for expected in self.attributes {
+            let found = attributes
+                .iter()
+                .find(|attribute| attribute.data_type == expected.data_type);
+            if found != Some(expected) {
+                return Err(InterfaceMismatch::Attribute {
+                    expected: *expected,
+                    found: found.copied(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the code of a custom brush defines what it has to.
+    ///
+    /// This only looks for the definition of the entry point; backends still have to compile
+    /// the code to find out whether it is valid.
+    pub fn check_brush_shader(&self, source: &str) -> Result<(), InterfaceMismatch> {
+        let name = self.brush_shader.entry_point;
+        let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        let defined = source.match_indices(name).any(|(start, _)| {
+            let before = source[..start].chars().next_back();
+            let after = source[start + name.len()..].trim_start();
+            !before.map_or(false, is_ident) && after.starts_with('(')
+        });
+
+        if defined {
+            Ok(())
+        } else {
+            Err(InterfaceMismatch::EntryPoint { name })
+        }
+    }
+}
+
+/// What the code of a custom brush has to define.
+///
+/// The code is written in the shading language of the backend and compiled along with its
+/// fragment shader. It defines a function named `entry_point`, which takes the position of
+/// the fragment in the user space of the shape being filled and the parameters of the brush,
+/// as `params / 4` four-component vectors, and returns a premultiplied color. That color is
+/// used in place of the [`Color`](TextureSlot::Color) texture, without the color matrix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BrushShaderInterface {
+    /// The name of the function that computes the color.
+    pub entry_point: &'static str,
+
+    /// The number of parameters that are passed to the function.
+    pub params: u32,
+}
+
+/// A value that is the same for every vertex in a call to `push_buffers`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Uniform {
+    /// The `transform` argument, an affine map from vertex positions to pixels.
+    ///
+    /// Backends that support it apply the projection from `set_projection` after it.
+    Transform,
+
+    /// The `size` argument, the size of the target in pixels.
+    ///
+    /// Pixels are mapped to clip space with the origin at the top left, and the mask texture
+    /// is sampled at the pixel's position divided by this size.
+    ViewportSize,
+
+    /// The color matrix from `set_color_matrix`, a 4x4 matrix and an offset.
+    ColorMatrix,
+
+    /// The shape from `set_clip_shape`, a rectangle and the radii of its corners.
+    ///
+    /// The final color is multiplied by its [coverage](crate::ClipShape::coverage) at the
+    /// pixel's center. This is only set for backends that support it.
+    ClipShape,
+
+    /// The parameters of the custom brush from `set_custom_brush`.
+    ///
+    /// This is only set while a custom brush is set, for backends that support them.
+    BrushParams,
+}
+
+/// A texture that is sampled in the fragment shader.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum TextureSlot {
+    /// The `current_texture` argument, sampled at the vertex's texture coordinates.
+    ///
+    /// It holds premultiplied RGBA and is sampled with its own interpolation mode and
+    /// [`RepeatStrategy`](crate::RepeatStrategy). Its color is unpremultiplied, transformed by
+    /// the color matrix, and premultiplied again before it is multiplied by the vertex color.
+    Color,
+
+    /// The `mask_texture` argument, sampled at the pixel's position on the target.
+    ///
+    /// Its alpha channel holds the coverage of the clip, which the final color is multiplied
+    /// by.
+    Mask,
+}
+
+/// A way in which a backend doesn't match the [`ShaderInterface`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InterfaceMismatch {
+    /// The backend was written against another version of the interface.
+    Version {
+        /// The version of the interface.
+        expected: u32,
+
+        /// The version of the backend.
+        found: u32,
+    },
+
+    /// The size of a vertex is different.
+    Stride {
+        /// The size of a vertex.
+        expected: u32,
+
+        /// The stride that the pipeline uses.
+        found: u32,
+    },
+
+    /// An attribute is missing, or is read differently.
+    Attribute {
+        /// The attribute as it is written.
+        expected: VertexFormat,
+
+        /// The attribute as the pipeline reads it, if it reads it at all.
+        found: Option<VertexFormat>,
+    },
+
+    /// The code of a custom brush doesn't define its entry point.
+    EntryPoint {
+        /// The name of the entry point.
+        name: &'static str,
+    },
+}
+
+impl fmt::Display for InterfaceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Version { expected, found } => write!(
+                f,
+                "backend targets shader interface version {found}, expected {expected}"
+            ),
+            Self::Stride { expected, found } => {
+                write!(f, "vertex stride is {found} bytes, expected {expected}")
+            }
+            Self::Attribute {
+                expected,
+                found: Some(found),
+            } => write!(f, "vertex attribute is {found:?}, expected {expected:?}"),
+            Self::Attribute {
+                expected,
+                found: None,
+            } => write!(f, "vertex attribute {expected:?} is missing"),
+            Self::EntryPoint { name } => {
+                write!(f, "custom brush shader doesn't define `{name}`")
+            }
+        }
+    }
+}
+
+impl Error for InterfaceMismatch {}
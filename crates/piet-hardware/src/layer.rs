@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! A tree of retained layers that are only recorded again when they change.
+
+use super::gpu_backend::GpuContext;
+use super::projection::{Homography, Transform3d};
+use super::scene::Scene;
+
+use piet::kurbo::Affine;
+use piet::Error as Pierror;
+
+use std::fmt;
+
+/// The identifier of a layer in a [`LayerTree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerId {
+    /// The index of the layer's slot.
+    index: u32,
+
+    /// The generation of the slot, so that the IDs of removed layers aren't reused.
+    generation: u32,
+}
+
+/// A tree of layers, each of which holds a [`Scene`] that is kept on the GPU.
+///
+/// Every layer has a transform relative to its parent, which can be changed without
+/// recording anything again. When the contents of a layer change, it is
+/// [`invalidate`](Self::invalidate)d, and only the invalidated layers have to be recorded
+/// again before the tree is drawn. Each frame, record every layer from
+/// [`invalid_layers`](Self::invalid_layers) with [`RenderContext::begin_scene`] and
+/// [`RenderContext::end_scene`], store it with [`set_scene`](Self::set_scene), then draw the
+/// whole tree with [`RenderContext::draw_layers`]. Large, mostly static trees can then be
+/// drawn without tessellating anything.
+///
+/// A layer can also be composited with a [`Transform3d`], which tilts or flips its contents in
+/// depth, like the cards of mobile interfaces. The contents and the children of the layer are
+/// still recorded in 2D, and are transformed as a flat plane.
+///
+/// [`RenderContext::begin_scene`]: crate::RenderContext::begin_scene
+/// [`RenderContext::end_scene`]: crate::RenderContext::end_scene
+/// [`RenderContext::draw_layers`]: crate::RenderContext::draw_layers
+pub struct LayerTree<C: GpuContext + ?Sized> {
+    /// The slots for the layers.
+    slots: Vec<Slot<C>>,
+
+    /// The indices of the empty slots.
+    free: Vec<u32>,
+
+    /// The layers without a parent, in the order they are drawn.
+    roots: Vec<LayerId>,
+}
+
+/// A slot in a layer tree.
+struct Slot<C: GpuContext + ?Sized> {
+    /// The generation of the slot, which is bumped whenever its layer is removed.
+    generation: u32,
+
+    /// The layer in this slot, if there is one.
+    layer: Option<Layer<C>>,
+}
+
+/// A layer in a layer tree.
+struct Layer<C: GpuContext + ?Sized> {
+    /// The parent of this layer.
+    parent: Option<LayerId>,
+
+    /// The children of this layer, in the order they are drawn.
+    children: Vec<LayerId>,
+
+    /// The transform from this layer's coordinates to its parent's.
+    transform: Affine,
+
+    /// The transform in three dimensions that is applied after `transform`.
+    transform_3d: Transform3d,
+
+    /// The recorded contents of this layer.
+    scene: Option<Scene<C>>,
+
+    /// Whether the contents have changed since they were recorded.
+    invalid: bool,
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for LayerTree<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerTree")
+            .field("layers", &self.len())
+            .field("roots", &self.roots)
+            .finish()
+    }
+}
+
+impl<C: GpuContext + ?Sized> Default for LayerTree<C> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+}
+
+impl<C: GpuContext + ?Sized> LayerTree<C> {
+    /// Create a new, empty layer tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of layers in the tree.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Whether there are no layers in the tree.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add an empty, invalid layer on top of the children of `parent`, or on top of the
+    /// tree if `parent` is `None`.
+    pub fn add_layer(&mut self, parent: Option<LayerId>) -> Result<LayerId, Pierror> {
+        if let Some(parent) = parent {
+            self.layer(parent)?;
+        }
+
+        let layer = Layer {
+            parent,
+            children: Vec::new(),
+            transform: Affine::IDENTITY,
+            transform_3d: Transform3d::IDENTITY,
+            scene: None,
+            invalid: true,
+        };
+
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.layer = Some(layer);
+                LayerId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    layer: Some(layer),
+                });
+                LayerId {
+                    index: (self.slots.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.layer_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+
+        Ok(id)
+    }
+
+    /// Remove a layer and all of its children.
+    pub fn remove_layer(&mut self, id: LayerId) -> Result<(), Pierror> {
+        let parent = self.layer(id)?.parent;
+        let siblings = match parent {
+            Some(parent) => &mut self.layer_mut(parent)?.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            if let Some(layer) = slot.layer.take() {
+                stack.extend(layer.children);
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(id.index);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the layer with this ID is in the tree.
+    pub fn contains(&self, id: LayerId) -> bool {
+        self.layer(id).is_ok()
+    }
+
+    /// Get the transform from a layer's coordinates to its parent's.
+    pub fn transform(&self, id: LayerId) -> Result<Affine, Pierror> {
+        self.layer(id).map(|layer| layer.transform)
+    }
+
+    /// Set the transform from a layer's coordinates to its parent's.
+    ///
+    /// This doesn't invalidate the layer.
+    pub fn set_transform(&mut self, id: LayerId, transform: Affine) -> Result<(), Pierror> {
+        self.layer_mut(id)?.transform = transform;
+        Ok(())
+    }
+
+    /// Get the transform in three dimensions that a layer is composited with.
+    pub fn transform_3d(&self, id: LayerId) -> Result<Transform3d, Pierror> {
+        self.layer(id).map(|layer| layer.transform_3d)
+    }
+
+    /// Set the transform in three dimensions that a layer is composited with.
+    ///
+    /// It is applied in the coordinates of the parent, after the 2D
+    /// [transform](Self::set_transform) of the layer, and applies to its children as well.
+    /// Drawing a tree where this isn't an affine transform returns
+    /// [`Pierror::NotSupported`] if the backend doesn't support projections. This doesn't
+    /// invalidate the layer.
+    pub fn set_transform_3d(&mut self, id: LayerId, transform: Transform3d) -> Result<(), Pierror> {
+        self.layer_mut(id)?.transform_3d = transform;
+        Ok(())
+    }
+
+    /// Mark the contents of a layer as changed, so it has to be recorded again.
+    ///
+    /// The layer keeps being drawn with its old contents until a new scene is set.
+    pub fn invalidate(&mut self, id: LayerId) -> Result<(), Pierror> {
+        self.layer_mut(id)?.invalid = true;
+        Ok(())
+    }
+
+    /// Whether the contents of a layer have to be recorded again.
+    pub fn is_invalid(&self, id: LayerId) -> Result<bool, Pierror> {
+        self.layer(id).map(|layer| layer.invalid)
+    }
+
+    /// Iterate over the layers that have to be recorded again.
+    pub fn invalid_layers(&self) -> impl Iterator<Item = LayerId> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.layer
+                .as_ref()
+                .filter(|layer| layer.invalid)
+                .map(|_| LayerId {
+                    index: index as u32,
+                    generation: slot.generation,
+                })
+        })
+    }
+
+    /// Set the recorded contents of a layer, which makes it valid again.
+    ///
+    /// Returns the scene that the layer held before.
+    pub fn set_scene(&mut self, id: LayerId, scene: Scene<C>) -> Result<Option<Scene<C>>, Pierror> {
+        let layer = self.layer_mut(id)?;
+        layer.invalid = false;
+        Ok(layer.scene.replace(scene))
+    }
+
+    /// Visit the scenes of the tree in the order they are drawn, with their transforms
+    /// relative to the tree.
+    pub(crate) fn visit(&self, mut f: impl FnMut(&Scene<C>, Homography)) {
+        let mut stack = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&id| (id, Homography::IDENTITY))
+            .collect::<Vec<_>>();
+
+        while let Some((id, parent_transform)) = stack.pop() {
+            let layer = match self.layer(id) {
+                Ok(layer) => layer,
+                Err(_) => continue,
+            };
+
+            let transform =
+                parent_transform * layer.transform_3d.flatten() * Homography::from(layer.transform);
+            if let Some(scene) = &layer.scene {
+                f(scene, transform);
+            }
+
+            stack.extend(layer.children.iter().rev().map(|&child| (child, transform)));
+        }
+    }
+
+    fn layer(&self, id: LayerId) -> Result<&Layer<C>, Pierror> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.layer.as_ref())
+            .ok_or(Pierror::InvalidInput)
+    }
+
+    fn layer_mut(&mut self, id: LayerId) -> Result<&mut Layer<C>, Pierror> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.layer.as_mut())
+            .ok_or(Pierror::InvalidInput)
+    }
+}
@@ -36,6 +36,19 @@
 //! Note that this crate generally uses thread-unsafe primitives. This is because UI management is
 //! usually pinned to one thread anyways, and it's a bad idea to do drawing outside of that thread.
 //!
+//! ## Multiple targets
+//!
+//! A single [`Source`] can render to several targets, like the swapchains of several windows,
+//! as long as its [`GpuContext`] can draw to all of them. Each call to
+//! [`Source::render_context`] takes the size of the target being drawn to, and everything that
+//! depends on it is kept per [`RenderContext`]. The glyph atlas, images and gradients are
+//! shared between all targets, and clipping masks are pooled for each target size. Finish
+//! drawing to one target before starting on the next.
+//!
+//! Frames that are too large for the GPU can be split into tiles with [`Tile::split`] and
+//! drawn one tile at a time with [`Source::tile_context`], which only needs a target and
+//! clipping masks as large as a tile.
+//!
 //! ## Implementation
 //!
 //! This crate works first and foremost by converting drawing operations to a series of
@@ -43,44 +56,142 @@
 
 #![forbid(unsafe_code, rust_2018_idioms)]
 
+pub use cosmic_text;
 use cosmic_text::LayoutGlyph;
 use line_straddler::{LineGenerator, LineType};
 pub use piet;
 
 use lyon_tessellation::FillRule;
 
-use piet::kurbo::{Affine, Point, Rect, Shape, Size};
+use piet::kurbo::{Affine, BezPath, Point, Rect, Shape, Size, Vec2};
 use piet::{Error as Pierror, FixedGradient, Image as _, InterpolationMode};
 
 use piet_cosmic_text::Metadata;
 use tinyvec::TinyVec;
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::error::Error as StdError;
+use std::f64::consts::SQRT_2;
 use std::fmt;
 use std::mem;
 use std::rc::Rc;
 
+#[macro_use]
+mod logging;
+
 mod atlas;
 mod brush;
+mod builder;
+mod cached_path;
+mod capture;
+pub mod conformance;
+mod dash;
+mod display_list;
+mod dyn_context;
+mod error;
+mod filter;
 mod gpu_backend;
+mod hit;
 mod image;
+mod interface;
+mod layer;
+#[cfg(feature = "loader")]
+mod loader;
+mod marker;
 mod mask;
+mod memory;
+mod occlusion;
+mod placeholder;
+#[cfg(feature = "plotters")]
+mod plotters;
+pub mod prelude;
+mod projection;
 mod rasterizer;
+mod recording;
 mod resources;
+mod scene;
+mod shadow;
+mod staging;
 mod text;
-
-pub use self::brush::Brush;
-pub use self::gpu_backend::{BufferType, GpuContext, RepeatStrategy, Vertex, VertexFormat};
+mod tile;
+mod upload;
+
+pub use self::brush::{Brush, CustomBrush, CustomShader, SpreadMode, SweepGradient};
+pub use self::builder::{
+    Antialiasing, AtlasStrategy, MaskResolution, SourceBuilder, SubpathClosing,
+};
+pub use self::cached_path::CachedPath;
+pub use self::capture::{replay, CaptureContext, CaptureTexture, CaptureVertexBuffer, ReplayError};
+pub use self::display_list::{DisplayList, Recorder};
+pub use self::dyn_context::{DynError, DynGpuContext, DynTexture, DynVertexBuffer};
+pub use self::error::{DrawError, Operation};
+pub use self::filter::{ColorFilter, ColorMatrix};
+pub use self::gpu_backend::{
+    BlendFactor, BufferType, ClipShape, CompositeMode, DataFormat, DataType, GpuContext,
+    PresentMode, RepeatStrategy, Vertex, VertexFormat,
+};
+pub use self::hit::HitTester;
 pub use self::image::Image;
-pub use self::text::{Text, TextLayout, TextLayoutBuilder};
+pub use self::interface::{
+    BrushShaderInterface, InterfaceMismatch, ShaderInterface, TextureSlot, Uniform,
+};
+pub use self::layer::{LayerId, LayerTree};
+#[cfg(feature = "loader")]
+pub use self::loader::{DecodedImage, ImageLoader, LoadError, LoadHandle};
+pub use self::marker::{Marker, MarkerPlacement};
+pub use self::memory::{MemoryUsage, ResourceMemory};
+pub use self::placeholder::{MissingResource, Placeholder};
+#[cfg(feature = "plotters")]
+pub use self::plotters::{PlottersBackend, PlottersError};
+pub use self::projection::{Projection, Transform3d};
+pub use self::recording::{Command, RecordingContext, RecordingTexture, RecordingVertexBuffer};
+pub use self::scene::{Scene, SceneLevels};
+pub use self::text::{Text, TextLayout, TextLayoutBuilder, Truncation};
+pub use self::tile::{Tile, Tiles};
 
 pub(crate) use atlas::{Atlas, GlyphData};
-pub(crate) use mask::MaskSlot;
-pub(crate) use rasterizer::{Rasterizer, TessRect};
+pub(crate) use brush::RampCache;
+pub(crate) use cached_path::Geometry;
+pub(crate) use mask::{MaskPool, MaskSlot};
+pub(crate) use memory::{Category, MemoryTracker};
+pub(crate) use occlusion::Occlusion;
+pub(crate) use placeholder::MissingCallback;
+pub(crate) use projection::Homography;
+pub(crate) use rasterizer::{Precision, Rasterizer, TessRect};
 pub(crate) use resources::{Texture, VertexBuffer};
+pub(crate) use scene::SceneRecorder;
+pub(crate) use shadow::ShadowCache;
+pub(crate) use staging::Staging;
+pub(crate) use upload::UploadQueue;
 
 const UV_WHITE: [f32; 2] = [0.5, 0.5];
 
+/// How many times its own size the view has to be from the origin before vertices are
+/// measured from the view instead.
+const EYE_DISTANCE: f64 = 64.0;
+
+/// The smallest tolerance that curves can be flattened with, in pixels.
+const MIN_TOLERANCE: f64 = 1.0e-3;
+
+/// Strokes thinner than this on the target, in pixels, are drawn as hairlines.
+const HAIRLINE_WIDTH: f64 = 1.0;
+
+/// Strokes that fit in a square this large on the target, in pixels, are drawn as a square.
+const TINY_PATH_SIZE: f64 = 1.0;
+
+/// Hairlines that would cover less than this much of a pixel are skipped.
+const MIN_HAIRLINE_COVERAGE: f64 = 1.0 / 255.0;
+
+/// The maximum number of errors to keep around before discarding the oldest ones.
+const MAX_ERRORS: usize = 64;
+
+/// The number of times a thumbnail is rendered at double size and then reduced.
+const THUMBNAIL_LEVELS: u32 = 2;
+
+/// How many texels an image has to be squeezed into a pixel before it is drawn from mip levels.
+const MIPMAP_THRESHOLD: f64 = 2.0;
+
 /// The source of the GPU renderer.
 pub struct Source<C: GpuContext + ?Sized> {
     /// The context to use for the GPU renderer.
@@ -100,6 +211,52 @@ pub struct Source<C: GpuContext + ?Sized> {
 
     /// The font atlas.
     atlas: Option<Atlas<C>>,
+
+    /// The options this source was built with.
+    options: SourceBuilder,
+
+    /// How the edges of shapes are antialiased.
+    antialiasing: Antialiasing,
+
+    /// The size of the target last rendered to.
+    target_size: (u32, u32),
+
+    /// The render target that is bound instead of the original one, if any.
+    render_target: Option<Rc<Texture<C>>>,
+
+    /// Render targets for layers that can be reused.
+    layer_targets: Vec<Rc<Texture<C>>>,
+
+    /// The size of the render targets for layers.
+    layer_size: (u32, u32),
+
+    /// Clipping masks that can be reused, for every target size.
+    masks: MaskPool<C>,
+
+    /// Blurred rectangles that were drawn recently.
+    shadows: ShadowCache<C>,
+
+    /// Color ramps of linear gradients that were created recently.
+    ramps: RampCache<C>,
+
+    /// The buffer that pixels are converted in before they are uploaded.
+    staging: Staging,
+
+    /// The bytes of GPU memory that resources take up.
+    memory: Rc<MemoryTracker>,
+
+    /// Images that are waiting for a later frame to be uploaded.
+    uploads: UploadQueue<C>,
+
+    /// The texture that is drawn instead of images that are waiting to be uploaded, and glyphs
+    /// that failed to rasterize.
+    placeholder: Rc<Texture<C>>,
+
+    /// The callback that is told about resources drawn as the placeholder.
+    on_missing: RefCell<Option<MissingCallback>>,
+
+    /// The occlusion queries of the current frame, and the results of the last one.
+    occlusion: Occlusion,
 }
 
 impl<C: GpuContext + fmt::Debug + ?Sized> fmt::Debug for Source<C> {
@@ -116,40 +273,85 @@ struct Buffers<C: GpuContext + ?Sized> {
 
     /// The VBO for vertices.
     vbo: VertexBuffer<C>,
+
+    /// Vertex buffers for clips in the stencil buffer that aren't in use.
+    stencil: Vec<VertexBuffer<C>>,
 }
 
 impl<C: GpuContext + ?Sized> Source<C> {
     /// Create a new source from a context wrapped in an `Rc`.
     pub fn from_rc(context: Rc<C>) -> Result<Self, Pierror> {
-        let make_white_pixel = || {
-            const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+        Self::with_builder(context, SourceBuilder::new())
+    }
+
+    /// Create a new builder for a source.
+    pub fn builder() -> SourceBuilder {
+        SourceBuilder::new()
+    }
 
-            // Setup a white pixel texture.
+    /// Create a new source using the options in a builder.
+    pub(crate) fn with_builder(context: Rc<C>, options: SourceBuilder) -> Result<Self, Pierror> {
+        debug!("creating a new source with options {:?}", &options);
+
+        let memory = Rc::new(MemoryTracker::default());
+        let make_pixel = |pixel: [u8; 4]| {
+            // Setup a single pixel texture.
             let texture = Texture::new(
                 &context,
                 InterpolationMode::NearestNeighbor,
                 RepeatStrategy::Repeat,
             )
-            .piet_err()?;
+            .piet_err()?
+            .tracked(&memory, Category::Other);
 
-            texture.write_texture((1, 1), piet::ImageFormat::RgbaSeparate, Some(&WHITE));
+            texture.write_texture((1, 1), piet::ImageFormat::RgbaPremul, Some(&pixel));
 
             Result::<_, Pierror>::Ok(texture)
         };
 
         Ok(Self {
-            white_pixel: make_white_pixel()?,
+            white_pixel: make_pixel([0xFF, 0xFF, 0xFF, 0xFF])?,
+            placeholder: Rc::new(make_pixel([0; 4])?),
+            on_missing: RefCell::new(None),
+            uploads: UploadQueue::new(),
+            occlusion: Occlusion::new(options.random_state()),
             buffers: {
-                let vbo = VertexBuffer::new(&context).piet_err()?;
+                let vbo = VertexBuffer::new(&context)
+                    .piet_err()?
+                    .tracked(&memory, Category::VertexBuffers);
 
                 Buffers {
-                    rasterizer: Rasterizer::new(),
+                    rasterizer: Rasterizer::new(options.closing())
+                        .with_fill_cache(options.fill_cache_size(), options.random_state()),
                     vbo,
+                    stencil: Vec::new(),
                 }
             },
-            atlas: Some(Atlas::new(&context)?),
+            atlas: Some(Atlas::new(
+                &context,
+                &memory,
+                options.random_state(),
+                options.atlas_config(),
+            )?),
+            masks: MaskPool::new(
+                options.mask_scale(),
+                options.combines_clips(),
+                memory.clone(),
+            ),
+            shadows: ShadowCache::new(memory.clone(), options.random_state()),
+            ramps: RampCache::new(memory.clone(), options.ramp_width(), options.random_state()),
+            staging: Staging::default(),
+            memory,
+            antialiasing: options
+                .antialiasing_strategy()
+                .resolve(context.msaa_samples()),
             context,
-            text: Text::new(),
+            text: Text::new(options.random_state()),
+            options,
+            target_size: (0, 0),
+            render_target: None,
+            layer_targets: Vec::new(),
+            layer_size: (0, 0),
         })
     }
 
@@ -166,17 +368,105 @@ impl<C: GpuContext + ?Sized> Source<C> {
         &self.context
     }
 
+    /// Whether this source renders deterministically.
+    ///
+    /// See [`SourceBuilder::deterministic`] for more information.
+    pub fn is_deterministic(&self) -> bool {
+        self.options.is_deterministic()
+    }
+
+    /// The strategy that the edges of shapes are antialiased with.
+    ///
+    /// This is the one set with [`SourceBuilder::antialiasing`], unless the target couldn't
+    /// support it.
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// Resize the target that this source renders to.
+    ///
+    /// Call this when the window is resized. The size is checked against what the GPU
+    /// supports, and pooled clipping masks of the old size are dropped instead of being kept
+    /// around until they are evicted. Sources that render to several targets of different
+    /// sizes don't need to call this, since masks of every size are pooled.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Pierror> {
+        if width == 0 || height == 0 {
+            return Err(Pierror::InvalidInput);
+        }
+
+        let (mask_width, mask_height) = self.masks.mask_size((width, height));
+        let (max_width, max_height) = self.context.max_texture_size();
+        if mask_width > max_width || mask_height > max_height {
+            return Err(Pierror::InvalidInput);
+        }
+
+        self.masks.retain_target((width, height));
+        if self.layer_size != (width, height) {
+            self.layer_targets.clear();
+        }
+        self.target_size = (width, height);
+        Ok(())
+    }
+
     /// Create a new rendering context.
     pub fn render_context(&mut self, width: u32, height: u32) -> RenderContext<'_, C> {
+        self.tile_context(Tile {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    /// Create a new rendering context that draws one tile of a larger frame.
+    ///
+    /// The target is `tile.width` by `tile.height` pixels, and drawing happens in the
+    /// coordinates of the whole frame, so the same drawing code can be run once for every
+    /// tile from [`Tile::split`]. The clipping masks are only as large as a tile, and shapes
+    /// that fall outside of the tile are skipped before they are tessellated. This makes it
+    /// possible to render frames that are too large to allocate on the GPU, by reading each
+    /// tile back or copying it elsewhere once it is finished.
+    pub fn tile_context(&mut self, tile: Tile) -> RenderContext<'_, C> {
+        self.uploads.begin_frame();
+        if let Some(atlas) = self.atlas.as_mut() {
+            let staging = &mut self.staging;
+            self.text
+                .with_font_system_mut(|fs| atlas.begin_frame(fs, staging));
+        }
+
+        self.frame_context(tile)
+    }
+
+    /// Create a rendering context that draws into the frame that is already in progress.
+    ///
+    /// Unlike [`tile_context`](Self::tile_context), this doesn't start a new frame, so the
+    /// upload budget and the glyph atlas carry on as they were.
+    fn frame_context(&mut self, tile: Tile) -> RenderContext<'_, C> {
+        self.target_size = (tile.width, tile.height);
         RenderContext {
             source: self,
-            size: (width, height),
+            size: (tile.width, tile.height),
+            origin: Vec2::new(tile.x as f64, tile.y as f64),
             state: TinyVec::from([RenderState::default()]),
-            status: Ok(()),
+            errors: VecDeque::new(),
             tolerance: 1.0,
+            recording: None,
+            stencil: true,
+            composite_mode: CompositeMode::SourceOver,
+            occlusion_queries: true,
         }
     }
 
+    /// Create an image from a texture that was created outside of this crate.
+    ///
+    /// The texture is deleted using [`GpuContext::delete_texture`] when the image is dropped.
+    pub fn image_from_raw(&self, texture: C::Texture, size: Size) -> Image<C> {
+        let texture =
+            Texture::from_raw(&self.context, texture).tracked(&self.memory, Category::Images);
+        texture.set_bytes(size.width as usize * size.height as usize * 4);
+        Image::new(texture, size).with_interpolation(self.options.default_interpolation())
+    }
+
     /// Get a reference to the text backend.
     pub fn text(&self) -> &Text {
         &self.text
@@ -186,99 +476,2230 @@ impl<C: GpuContext + ?Sized> Source<C> {
     pub fn text_mut(&mut self) -> &mut Text {
         &mut self.text
     }
+
+    /// Repack the glyphs in the text atlas to reduce fragmentation.
+    ///
+    /// This rasterizes every cached glyph again, so it is best called between frames, after
+    /// large amounts of differently sized text have been drawn. When the atlas fills up, the
+    /// glyphs that haven't been drawn in the current frame are evicted and the ones drawn
+    /// recently are repacked into a new texture over the next few frames, so this is rarely
+    /// needed. Unlike that, this repacks every glyph at once.
+    pub fn compact_atlas(&mut self) {
+        if let Some(atlas) = self.atlas.as_mut() {
+            let staging = &mut self.staging;
+            self.text
+                .with_font_system_mut(|fs| atlas.compact(fs, staging));
+        }
+    }
+
+    /// Rasterize the glyphs of a text layout into the text atlas, and keep them there.
+    ///
+    /// Pinned glyphs are never evicted when the atlas fills up, so text that has to show up
+    /// without a hitch, like a HUD, can be prepared during a loading screen. `scale` is the
+    /// scale that the text is drawn at, like the scale factor of the window, since glyphs are
+    /// rasterized at the size they end up at. Every pin should be undone with
+    /// [`unpin_text`](Self::unpin_text) and the same scale once the text isn't needed
+    /// anymore. Glyphs that fail to rasterize aren't pinned, and the first failure is
+    /// returned.
+    pub fn pin_text(&mut self, layout: &TextLayout, scale: f64) -> Result<(), Pierror> {
+        let atlas = match self.atlas.as_mut() {
+            Some(atlas) => atlas,
+            None => return Ok(()),
+        };
+
+        let staging = &mut self.staging;
+        self.text.with_font_system_mut(|fs| {
+            let mut result = Ok(());
+            for run in layout.buffer().layout_runs() {
+                for glyph in run.glyphs {
+                    if let Err(e) = atlas.pin(glyph, scale, fs, staging) {
+                        trace!("failed to pin glyph: {}", e);
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
+                    }
+                }
+            }
+            result
+        })
+    }
+
+    /// Let the glyphs of a text layout pinned with [`pin_text`](Self::pin_text) be evicted
+    /// again.
+    pub fn unpin_text(&mut self, layout: &TextLayout, scale: f64) {
+        if let Some(atlas) = self.atlas.as_mut() {
+            for run in layout.buffer().layout_runs() {
+                for glyph in run.glyphs {
+                    atlas.unpin(glyph, scale);
+                }
+            }
+        }
+    }
+
+    /// Upload an image that is waiting for the upload budget right away.
+    ///
+    /// See [`set_upload_budget`](Self::set_upload_budget). Images that have to show up
+    /// without a placeholder, like cursors, can be uploaded with this while loading. Images
+    /// that were already uploaded are left alone.
+    pub fn upload_now(&mut self, image: &Image<C>) {
+        self.uploads.upload_now(image.texture());
+    }
+
+    /// Set how frames are presented, from the next frame on.
+    ///
+    /// Latency-sensitive applications, like drawing tools and games, can use this to opt out of
+    /// vsync. Returns [`Pierror::NotSupported`] if the backend doesn't present frames itself.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> Result<(), Pierror> {
+        if self.context.set_present_mode(mode) {
+            Ok(())
+        } else {
+            Err(Pierror::NotSupported)
+        }
+    }
+
+    /// Drop the clipping masks that are kept around for reuse.
+    ///
+    /// Masks are pooled by target size, so this can be used to free memory after a window is
+    /// closed or resized.
+    pub fn release_masks(&mut self) {
+        self.masks.clear();
+    }
+
+    /// Drop the render targets of layers that are kept around for reuse.
+    ///
+    /// See [`RenderContext::save_layer`]. They are also dropped when the source is resized,
+    /// or when a layer is saved on a target of another size.
+    pub fn release_layers(&mut self) {
+        self.layer_targets.clear();
+    }
+
+    /// Drop the blurred rectangles that are kept around for reuse.
+    ///
+    /// Blurred rectangles are cached by size, corner radius and blur radius, and the most
+    /// recent ones are kept. This frees their textures right away.
+    pub fn clear_shadow_cache(&mut self) {
+        self.shadows.clear();
+    }
+
+    /// Drop the color ramps of linear gradients that are kept around for reuse.
+    ///
+    /// Gradients with the same stops share one ramp texture, and the most recently used
+    /// ones are kept. This frees their textures right away.
+    pub fn clear_gradient_cache(&mut self) {
+        self.ramps.clear();
+    }
+
+    /// Start a new frame of occlusion queries.
+    ///
+    /// The queries made since the last call become the ones that
+    /// [`RenderContext::occlusion_query`] reports, so this should be called once at the start
+    /// of every frame, before any of its targets are drawn. Until it is called, no query is
+    /// reported as hidden.
+    pub fn begin_occlusion_frame(&mut self) {
+        self.occlusion.begin_frame();
+    }
+
+    /// Drop the tessellations of fills that are kept around for reuse.
+    ///
+    /// See [`SourceBuilder::tessellation_cache`] for more information.
+    pub fn clear_tessellation_cache(&mut self) {
+        self.buffers.rasterizer.clear_fill_cache();
+    }
+
+    /// Get how much GPU memory the resources of this source take up, by kind of resource.
+    ///
+    /// This counts every resource that is still alive, including images and scenes that
+    /// the application holds on to. A steadily growing count between frames points to
+    /// resources that are never dropped.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory.usage()
+    }
+
+    /// Start measuring the peaks of [`memory_usage`](Self::memory_usage) again from the
+    /// current usage.
+    pub fn reset_peak_memory_usage(&mut self) {
+        self.memory.reset_peaks();
+    }
+
+    /// Limit how many bytes of images are uploaded to the GPU every frame.
+    ///
+    /// Images made with [`make_image`](piet::RenderContext::make_image) that don't fit in the
+    /// budget of the current frame are uploaded in later frames, in the order they were made,
+    /// and the [placeholder](Self::set_placeholder) is drawn in their place
+    /// until then. This keeps a screenful of new images from stalling a single frame. Every
+    /// [`render_context`](Self::render_context) and [`tile_context`](Self::tile_context)
+    /// starts a new frame, and the first image of a frame is always uploaded, even if it is
+    /// larger than the budget. Glyphs are always uploaded right away. `None`, the default,
+    /// uploads every image when it is made.
+    pub fn set_upload_budget(&mut self, budget: Option<usize>) {
+        self.uploads.set_budget(budget);
+    }
+
+    /// Set what is drawn instead of images that are waiting to be uploaded, and glyphs that
+    /// failed to rasterize.
+    ///
+    /// The default is transparent, which leaves the area blank. A visible color or a
+    /// [checkerboard](Placeholder::Checkerboard) makes missing resources easy to spot.
+    pub fn set_placeholder(&mut self, placeholder: impl Into<Placeholder>) {
+        let (size, pixels) = placeholder.into().pixels();
+        self.placeholder
+            .write_texture(size, piet::ImageFormat::RgbaPremul, Some(&pixels));
+    }
+
+    /// Set a callback that is told every time a resource is drawn as the
+    /// [placeholder](Self::set_placeholder).
+    ///
+    /// This lets applications log missing resources, or request another frame once they may
+    /// be available. The callback replaces the one that was set before.
+    pub fn on_missing_resource(&mut self, callback: impl FnMut(MissingResource<'_>) + 'static) {
+        *self.on_missing.get_mut() = Some(Box::new(callback));
+    }
+
+    /// Tell the callback that a resource is drawn as the placeholder.
+    fn report_missing(&self, resource: MissingResource<'_>) {
+        if let Some(callback) = &mut *self.on_missing.borrow_mut() {
+            callback(resource);
+        }
+    }
+
+    /// Whether any images are waiting for a later frame to be uploaded.
+    ///
+    /// Applications that only redraw when something changes should keep requesting frames
+    /// while this is true, so that the images replace their placeholders.
+    pub fn has_pending_uploads(&self) -> bool {
+        self.uploads.has_pending()
+    }
+
+    /// Free the buffer that pixels are converted in before they are uploaded.
+    ///
+    /// Images that aren't premultiplied and glyphs are converted in a buffer that is kept
+    /// between uploads, so that uploading them doesn't allocate. It is as large as the
+    /// largest such image uploaded so far, which this frees.
+    pub fn release_staging_buffer(&mut self) {
+        self.staging.shrink();
+    }
+
+    /// Render a scene offscreen and read it back as a thumbnail.
+    ///
+    /// `draw` is given a context that draws a scene of `scene_size` logical units, scaled down
+    /// to fit within `max_size` pixels while keeping its aspect ratio. It should return the
+    /// result of [`status`](piet::RenderContext::status) so that drawing errors are reported.
+    /// The scene is rendered at a higher resolution and halved a few times, like a mip chain,
+    /// so that thin lines and text stay legible. The result uses straight alpha.
+    ///
+    /// This needs [`GpuContext::create_render_target`] and [`GpuContext::read_pixels`] to be
+    /// supported. The original render target is bound again before returning.
+    pub fn render_thumbnail(
+        &mut self,
+        scene_size: Size,
+        max_size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, C>) -> Result<(), Pierror>,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        use piet::RenderContext as _;
+
+        if !scene_size.is_finite()
+            || scene_size.width <= 0.0
+            || scene_size.height <= 0.0
+            || max_size.0 == 0
+            || max_size.1 == 0
+        {
+            return Err(Pierror::InvalidInput);
+        }
+
+        let scale =
+            (max_size.0 as f64 / scene_size.width).min(max_size.1 as f64 / scene_size.height);
+        let width = ((scene_size.width * scale).round() as u32).clamp(1, max_size.0);
+        let height = ((scene_size.height * scale).round() as u32).clamp(1, max_size.1);
+
+        // Render at the largest multiple that the GPU allows.
+        let (max_width, max_height) = self.context.max_texture_size();
+        let mut levels = 0;
+        while levels < THUMBNAIL_LEVELS
+            && width.saturating_mul(2 << levels) <= max_width
+            && height.saturating_mul(2 << levels) <= max_height
+        {
+            levels += 1;
+        }
+        let target_size = (width << levels, height << levels);
+
+        let mut data = self.render_offscreen(target_size, |mut rc| {
+            rc.transform(Affine::scale(scale * (1u32 << levels) as f64));
+            draw(rc)
+        })?;
+
+        let (mut data_width, mut data_height) = target_size;
+        for _ in 0..levels {
+            data = halve(&data, data_width, data_height);
+            data_width /= 2;
+            data_height /= 2;
+        }
+        unpremultiply(&mut data);
+
+        Ok(piet::ImageBuf::from_raw(
+            data,
+            piet::ImageFormat::RgbaSeparate,
+            width as usize,
+            height as usize,
+        ))
+    }
+
+    /// Render a scene offscreen into an image that only holds its coverage.
+    ///
+    /// `draw` is given a context that draws into a transparent target of `size` pixels. It
+    /// should return the result of [`status`](piet::RenderContext::status) so that drawing
+    /// errors are reported. The colors of the scene are dropped and only its alpha is kept,
+    /// with one byte per pixel. The image is drawn as white, so it can be drawn in any color
+    /// with an image [`Brush`] that is [tinted](Brush::with_tint), or with
+    /// [`ColorFilter::Tint`]. For example, drawing it several times at small offsets outlines
+    /// the scene.
+    ///
+    /// This needs [`GpuContext::create_render_target`] and [`GpuContext::read_pixels`] to be
+    /// supported, since the scene is read back before it is uploaded. The original render
+    /// target is bound again before returning.
+    pub fn render_coverage(
+        &mut self,
+        size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, C>) -> Result<(), Pierror>,
+    ) -> Result<Image<C>, Pierror> {
+        let (max_width, max_height) = self.context.max_texture_size();
+        if size.0 == 0 || size.1 == 0 || size.0 > max_width || size.1 > max_height {
+            return Err(Pierror::InvalidInput);
+        }
+
+        let data = self.render_offscreen(size, draw)?;
+        let coverage = data
+            .chunks_exact(4)
+            .map(|pixel| pixel[3])
+            .collect::<Vec<_>>();
+
+        let interpolation = self.options.default_interpolation();
+        let texture = Texture::new(
+            &self.context,
+            interpolation,
+            RepeatStrategy::Color(piet::Color::TRANSPARENT),
+        )
+        .piet_err()?
+        .tracked(&self.memory, Category::Images);
+        texture.write_texture(size, piet::ImageFormat::Grayscale, Some(&coverage));
+
+        Ok(Image::new(texture, Size::new(size.0 as f64, size.1 as f64))
+            .with_interpolation(interpolation))
+    }
+
+    /// Run `f` with the backend clipping to a shape and scissoring to a rectangle.
+    fn with_clip<R>(
+        &self,
+        clip_shape: Option<&ClipShape>,
+        scissor: Option<[u32; 4]>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        if clip_shape.is_some() {
+            self.context.set_clip_shape(clip_shape);
+        }
+        if scissor.is_some() {
+            self.context.set_scissor(scissor);
+        }
+        let result = f();
+        if clip_shape.is_some() {
+            self.context.set_clip_shape(None);
+        }
+        if scissor.is_some() {
+            self.context.set_scissor(None);
+        }
+
+        result
+    }
+
+    /// Draw the vertices in a buffer, sampling `texture` with the given interpolation.
+    ///
+    /// Without a texture, the white pixel is used.
+    fn push_buffers(
+        &self,
+        vertex_buffer: &C::VertexBuffer,
+        texture: Option<(&Texture<C>, InterpolationMode)>,
+        mask: &Texture<C>,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), Pierror> {
+        // Backends that can't sample with another interpolation for one draw have the
+        // interpolation of the texture changed instead.
+        let (texture, per_draw) = match texture {
+            Some((texture, _)) if self.uploads.is_pending(texture) => {
+                self.report_missing(MissingResource::PendingUpload);
+                (&*self.placeholder, false)
+            }
+            Some((texture, interpolation)) => {
+                let per_draw = self.context.set_sampler_interpolation(Some(interpolation));
+                if !per_draw {
+                    texture.set_interpolation(interpolation);
+                }
+                (texture, per_draw)
+            }
+            None => (&self.white_pixel, false),
+        };
+
+        let result = self
+            .context
+            .push_buffers(
+                vertex_buffer,
+                texture.resource(),
+                mask.resource(),
+                transform,
+                size,
+            )
+            .piet_err();
+        if per_draw {
+            self.context.set_sampler_interpolation(None);
+        }
+
+        result
+    }
+
+    /// Draw into a new render target, and read it back as premultiplied RGBA pixels.
+    ///
+    /// The target starts out transparent. The scene is drawn as part of the current frame, so
+    /// it shares its upload budget. The original render target is bound again before
+    /// returning.
+    fn render_offscreen(
+        &mut self,
+        size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, C>) -> Result<(), Pierror>,
+    ) -> Result<Vec<u8>, Pierror> {
+        use piet::RenderContext as _;
+
+        let target = self
+            .context
+            .create_render_target(size)
+            .piet_err()?
+            .ok_or(Pierror::NotSupported)?;
+        let target =
+            Texture::from_raw(&self.context, target).tracked(&self.memory, Category::Other);
+        target.set_bytes(size.0 as usize * size.1 as usize * 4);
+        let previous = self.bind_render_target(Some(Rc::new(target)))?;
+
+        let last_target_size = self.target_size;
+        let result = (|| {
+            // Give the target a stencil buffer, so that clips don't need masks.
+            if self.context.create_depth_stencil(size).piet_err()? {
+                self.context.attach_depth_stencil(true).piet_err()?;
+            }
+
+            // Offscreen rendering is part of the current frame, rather than a new one.
+            let mut rc = self.frame_context(Tile {
+                x: 0,
+                y: 0,
+                width: size.0,
+                height: size.1,
+            });
+            rc.occlusion_queries = false;
+            rc.clear(None, piet::Color::TRANSPARENT);
+            draw(rc)?;
+            self.context.flush().piet_err()?;
+
+            self.read_pixels((0, 0), size, size)
+        })();
+        self.target_size = last_target_size;
+
+        let unbound = self.bind_render_target(previous);
+        let data = result?;
+        unbound?;
+        Ok(data)
+    }
+
+    /// Bind a render target, or the original one for `None`, and return the one that was
+    /// bound before.
+    fn bind_render_target(
+        &mut self,
+        target: Option<Rc<Texture<C>>>,
+    ) -> Result<Option<Rc<Texture<C>>>, Pierror> {
+        self.context
+            .set_render_target(target.as_deref().map(Texture::resource))
+            .piet_err()?;
+        Ok(mem::replace(&mut self.render_target, target))
+    }
+
+    /// Get a render target for a layer of a target of `size`, reusing one if possible.
+    ///
+    /// Returns `None` if offscreen rendering is not supported. New targets are bound, so that
+    /// they get a depth and stencil attachment.
+    fn layer_target(&mut self, size: (u32, u32)) -> Result<Option<Rc<Texture<C>>>, Pierror> {
+        if self.layer_size != size {
+            self.layer_targets.clear();
+            self.layer_size = size;
+        }
+        if let Some(target) = self.layer_targets.pop() {
+            self.bind_render_target(Some(target.clone()))?;
+            return Ok(Some(target));
+        }
+
+        let target = match self.context.create_render_target(size).piet_err()? {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let target =
+            Texture::from_raw(&self.context, target).tracked(&self.memory, Category::Other);
+        target.set_bytes(size.0 as usize * size.1 as usize * 4);
+        let target = Rc::new(target);
+        let previous = self.bind_render_target(Some(target.clone()))?;
+
+        // Give the target a stencil buffer, so that clips inside of the layer don't need masks.
+        let attached = self.context.create_depth_stencil(size).and_then(|created| {
+            if created {
+                self.context.attach_depth_stencil(true)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = attached.piet_err() {
+            self.bind_render_target(previous)?;
+            return Err(e);
+        }
+
+        Ok(Some(target))
+    }
+
+    /// Read back an area of a target as premultiplied RGBA pixels.
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+    ) -> Result<Vec<u8>, Pierror> {
+        let mut data = vec![0u8; size.0 as usize * size.1 as usize * 4];
+        if size.0 == 0 || size.1 == 0 {
+            return Ok(data);
+        }
+
+        let supported = self
+            .context
+            .read_pixels(origin, size, target_size, &mut data)
+            .piet_err()?;
+        if !supported {
+            return Err(Pierror::NotSupported);
+        }
+
+        Ok(data)
+    }
+
+    /// Save the contents of the target last rendered to as a PNG file.
+    ///
+    /// The image is written with straight alpha and tagged as sRGB. This needs
+    /// [`GpuContext::read_pixels`] to be supported, and the target to still be bound.
+    #[cfg(feature = "png")]
+    pub fn screenshot(&self, path: impl AsRef<std::path::Path>) -> Result<(), Pierror> {
+        let file = std::fs::File::create(path).piet_err()?;
+        self.write_png(std::io::BufWriter::new(file))
+    }
+
+    /// Write the contents of the target last rendered to as a PNG image.
+    ///
+    /// See [`screenshot`](Self::screenshot) for more information.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, writer: impl std::io::Write) -> Result<(), Pierror> {
+        let (width, height) = self.target_size;
+        let mut data = self.read_pixels((0, 0), (width, height), (width, height))?;
+        unpremultiply(&mut data);
+
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header().piet_err()?;
+        writer.write_image_data(&data).piet_err()?;
+        writer.finish().piet_err()
+    }
+}
+
+/// The whole point of this crate.
+pub struct RenderContext<'a, C: GpuContext + ?Sized> {
+    /// The source of the GPU renderer.
+    source: &'a mut Source<C>,
+
+    /// The width and height of the target.
+    size: (u32, u32),
+
+    /// The position of the target in the frame, when rendering in tiles.
+    origin: Vec2,
+
+    /// The current state of the renderer.
+    state: TinyVec<[RenderState<C>; 1]>,
+
+    /// The errors that have occurred since `status` was last called.
+    errors: VecDeque<DrawError>,
+
+    /// Tolerance for tessellation, in pixels of the target.
+    tolerance: f64,
+
+    /// The scene being recorded, if any.
+    recording: Option<SceneRecorder<C>>,
+
+    /// Whether clips may go to the stencil buffer, until the backend refuses one.
+    stencil: bool,
+
+    /// How drawing is combined with the target.
+    composite_mode: CompositeMode,
+
+    /// Whether occlusion queries are made, which they aren't on internal offscreen targets.
+    occlusion_queries: bool,
+}
+
+struct RenderState<C: GpuContext + ?Sized> {
+    /// The current transform in pixel space.
+    transform: Affine,
+
+    /// The current clipping mask.
+    mask: MaskSlot<C>,
+
+    /// The clip that is applied in the shader, on top of the mask.
+    clip_shape: Option<ClipShape>,
+
+    /// The pixels of the target that drawing is restricted to by the backend, if any.
+    scissor: Option<[u32; 4]>,
+
+    /// The clips that were pushed to the stencil buffer, in the order they were pushed, along
+    /// with their paths in the pixels of the target.
+    stencil_clips: Vec<(VertexBuffer<C>, BezPath)>,
+
+    /// The opacity applied to everything drawn.
+    alpha: f64,
+
+    /// Whether stroke widths are measured in device pixels instead of user space.
+    non_scaling_stroke: bool,
+
+    /// The layer that is drawn into until this state is restored, if it was saved with
+    /// `save_layer`.
+    layer: Option<SavedLayer<C>>,
+}
+
+/// A layer that is composited back onto the previous render target when it is restored.
+struct SavedLayer<C: GpuContext + ?Sized> {
+    /// The render target that the layer is drawn into.
+    target: Rc<Texture<C>>,
+
+    /// The render target that was bound before the layer.
+    previous: Option<Rc<Texture<C>>>,
+
+    /// The area of the target that is composited back, in pixels.
+    area: Rect,
+
+    /// The opacity that the layer is composited with.
+    alpha: f64,
 }
 
-/// The whole point of this crate.
-pub struct RenderContext<'a, C: GpuContext + ?Sized> {
-    /// The source of the GPU renderer.
-    source: &'a mut Source<C>,
+impl<C: GpuContext + ?Sized> Default for RenderState<C> {
+    fn default() -> Self {
+        Self {
+            transform: Affine::IDENTITY,
+            mask: MaskSlot::new(),
+            clip_shape: None,
+            scissor: None,
+            stencil_clips: Vec::new(),
+            alpha: 1.0,
+            non_scaling_stroke: false,
+            layer: None,
+        }
+    }
+}
+
+impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
+    /// Fill in a rectangle.
+    fn fill_rects(
+        &mut self,
+        rects: impl IntoIterator<Item = TessRect>,
+        texture: Option<(&Rc<Texture<C>>, InterpolationMode)>,
+    ) -> Result<(), Pierror> {
+        let eye = self.eye();
+        self.source
+            .buffers
+            .rasterizer
+            .fill_rects(rects.into_iter().map(|rect| TessRect {
+                pos: rect.pos - eye,
+                ..rect
+            }));
+
+        // Push the buffers to the GPU.
+        self.push_buffers(texture)
+    }
+
+    /// Fill in the provided shape.
+    fn fill_impl(
+        &mut self,
+        shape: impl Shape,
+        brush: &Brush<C>,
+        mode: FillRule,
+    ) -> Result<(), Pierror> {
+        if !self.is_visible(shape.bounding_box()) {
+            return Ok(());
+        }
+
+        let eye = self.eye();
+        let precision = self.precision().with_origin(eye);
+        let cvt_vertex = |pos| brush.make_vertex_from(pos, eye);
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        let rect = shape.as_rect();
+        rasterizer.fill_shape(shape, mode, precision, cvt_vertex)?;
+        if self.source.antialiasing == Antialiasing::Feather {
+            rasterizer.feather(precision, cvt_vertex);
+        }
+
+        // Push the incoming buffers.
+        self.push_brush(brush)?;
+
+        if let Some(rect) = rect {
+            if brush
+                .solid_color()
+                .map_or(false, |color| color.as_rgba8().3 == 0xFF)
+            {
+                self.cover(rect);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stroke_impl(
+        &mut self,
+        shape: impl Shape,
+        brush: &Brush<C>,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<(), Pierror> {
+        let state = self.state.last().unwrap();
+        let transform = state.transform;
+        let scale = match state.non_scaling_stroke {
+            true => 1.0,
+            false => transform.determinant().abs().sqrt(),
+        };
+
+        // Strokes that fit in a pixel are drawn as that pixel.
+        let device_width = width * scale;
+        let extent = transform
+            .transform_rect_bbox(shape.bounding_box())
+            .inflate(device_width, device_width);
+        if extent.width() < TINY_PATH_SIZE && extent.height() < TINY_PATH_SIZE {
+            let length = shape.perimeter(self.precision().tolerance())
+                * transform.determinant().abs().sqrt();
+            return self.dot_impl(extent.center(), brush, length * device_width);
+        }
+
+        if device_width < HAIRLINE_WIDTH {
+            return self.hairline_impl(shape, brush, device_width, style, scale);
+        }
+
+        if !state.non_scaling_stroke {
+            let precision = self.precision().with_origin(self.eye());
+            return self.stroke_with(shape, brush, width, style, precision, Affine::IDENTITY);
+        }
+
+        // Stroke the path in device space and map the triangles back, so that the transform
+        // doesn't scale the width. Nothing is visible through a degenerate transform.
+        let det = transform.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return Ok(());
+        }
+
+        let path = transform * shape.into_path(self.precision().tolerance());
+        let precision = Precision::new(self.flatten_tolerance(), Affine::IDENTITY);
+        self.stroke_with(path, brush, width, style, precision, transform.inverse())
+    }
+
+    /// Draw a pixel-sized square around `center`, in device space, faded by `coverage`.
+    ///
+    /// This stands in for a shape that would fit inside of a pixel, where `coverage` is how
+    /// much of the pixel the shape would have covered.
+    fn dot_impl(&mut self, center: Point, brush: &Brush<C>, coverage: f64) -> Result<(), Pierror> {
+        let transform = self.state.last().unwrap().transform;
+        let det = transform.determinant();
+        if !(coverage >= MIN_HAIRLINE_COVERAGE && det != 0.0 && det.is_finite()) {
+            return Ok(());
+        }
+
+        let half = TINY_PATH_SIZE / 2.0;
+        let rect = Rect::from_center_size(center, (TINY_PATH_SIZE, TINY_PATH_SIZE));
+        if !self.is_visible(rect.inflate(half, half)) {
+            return Ok(());
+        }
+
+        let to_user = transform.inverse();
+        let eye = self.eye();
+        let alpha = coverage.min(1.0);
+        let corners = [
+            Point::new(rect.x0, rect.y0),
+            Point::new(rect.x1, rect.y0),
+            Point::new(rect.x1, rect.y1),
+            Point::new(rect.x0, rect.y1),
+        ];
+        let vertices = corners.map(|pos| {
+            let mut vertex = brush.make_vertex_from(to_user * pos, eye);
+            vertex.color[3] = (vertex.color[3] as f64 * alpha).round() as u8;
+            vertex
+        });
+        self.source
+            .buffers
+            .rasterizer
+            .extend(vertices, &[0, 1, 2, 0, 2, 3]);
+
+        self.push_brush(brush)
+    }
+
+    /// Stroke a shape that is thinner than a pixel on the target.
+    ///
+    /// The outline is drawn in device space as a strip of quads one pixel wide, without
+    /// tessellating it, and faded by how much of that pixel the stroke would have covered.
+    /// `scale` is the scale from the width of the stroke to pixels.
+    fn hairline_impl(
+        &mut self,
+        shape: impl Shape,
+        brush: &Brush<C>,
+        device_width: f64,
+        style: &piet::StrokeStyle,
+        scale: f64,
+    ) -> Result<(), Pierror> {
+        let transform = self.state.last().unwrap().transform;
+        let det = transform.determinant();
+        if !(device_width >= MIN_HAIRLINE_COVERAGE && det != 0.0 && det.is_finite()) {
+            return Ok(());
+        }
+
+        let bounds = transform
+            .transform_rect_bbox(shape.bounding_box())
+            .inflate(HAIRLINE_WIDTH, HAIRLINE_WIDTH);
+        if !self.is_visible(bounds) {
+            return Ok(());
+        }
+
+        // Dashes are measured along the transformed path.
+        let mut hairline = style.clone();
+        if !style.dash_pattern.is_empty() {
+            let pattern = style.dash_pattern.iter().map(|len| len * scale);
+            hairline.set_dash_pattern(pattern.collect::<Vec<_>>());
+            hairline.set_dash_offset(style.dash_offset * scale);
+        }
+
+        let tolerance = self.flatten_tolerance();
+        let path = transform * shape.into_path(self.precision().tolerance());
+        let alpha = device_width / HAIRLINE_WIDTH;
+        let to_user = transform.inverse();
+        let eye = self.eye();
+        let cvt_vertex = |pos| {
+            let mut vertex = brush.make_vertex_from(to_user * pos, eye);
+            vertex.color[3] = (vertex.color[3] as f64 * alpha).round() as u8;
+            vertex
+        };
+
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        rasterizer.stroke_hairline(path, tolerance, HAIRLINE_WIDTH, &hairline, cvt_vertex)?;
+        if self.source.antialiasing == Antialiasing::Feather {
+            let precision = Precision::new(tolerance, Affine::IDENTITY);
+            rasterizer.feather(precision, cvt_vertex);
+        }
+
+        self.push_brush(brush)
+    }
+
+    /// Stroke the provided shape, mapping the triangles into user space with `to_user`.
+    fn stroke_with(
+        &mut self,
+        shape: impl Shape,
+        brush: &Brush<C>,
+        width: f64,
+        style: &piet::StrokeStyle,
+        precision: Precision,
+        to_user: Affine,
+    ) -> Result<(), Pierror> {
+        // Miter joins and square caps can reach further out than half of the width.
+        let reach = match style.line_join {
+            piet::LineJoin::Miter { limit } => limit.max(SQRT_2),
+            _ => SQRT_2,
+        };
+        let bounds = shape.bounding_box().inflate(width * reach, width * reach);
+        if !self.is_visible(to_user.transform_rect_bbox(bounds)) {
+            return Ok(());
+        }
+
+        let eye = self.eye();
+        let cvt_vertex = |pos| brush.make_vertex_from(to_user * pos, eye);
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        rasterizer.stroke_shape(shape, precision, width, style, cvt_vertex)?;
+        if self.source.antialiasing == Antialiasing::Feather {
+            rasterizer.feather(precision, cvt_vertex);
+        }
+
+        // Push the incoming buffers.
+        self.push_brush(brush)
+    }
+
+    /// Draw a blurred rectangle with rounded corners.
+    fn blurred_rect_impl(
+        &mut self,
+        rect: Rect,
+        corner_radius: f64,
+        blur_radius: f64,
+        brush: &Brush<C>,
+    ) -> Result<(), Pierror> {
+        let color = brush.solid_color().ok_or(Pierror::NotSupported)?;
+        let rect = rect.abs();
+        if !(rect.area().is_finite() && corner_radius.is_finite() && blur_radius.is_finite()) {
+            return Err(Pierror::InvalidInput);
+        }
+
+        // Without a blur, this is just a rectangle.
+        if blur_radius <= 0.0 {
+            let shape = piet::kurbo::RoundedRect::from_rect(rect, corner_radius.max(0.0));
+            return self.fill_impl(shape, brush, FillRule::NonZero);
+        }
+
+        let reach = (shadow::BLUR_EXTENT * blur_radius).ceil();
+        if !self.is_visible(rect.inflate(reach, reach)) {
+            return Ok(());
+        }
+
+        let shadow = self.source.shadows.get(
+            &self.source.context,
+            rect.size(),
+            corner_radius.max(0.0),
+            blur_radius,
+        )?;
+        self.fill_rects(
+            shadow.rects(rect, color),
+            Some((shadow.texture(), InterpolationMode::Bilinear)),
+        )
+    }
+
+    /// The scale that glyphs are rasterized at.
+    ///
+    /// Glyphs are rasterized at the size they end up at on the target, so that text drawn on
+    /// HiDPI targets or under a zoom stays sharp.
+    fn glyph_scale(&self) -> f64 {
+        let transform = self.state.last().unwrap().transform;
+        transform.determinant().abs().sqrt()
+    }
+
+    /// Draw the blurred glyphs of a text layout.
+    fn text_shadow_impl(
+        &mut self,
+        layout: &TextLayout,
+        pos: Point,
+        blur_radius: f64,
+        brush: &Brush<C>,
+    ) -> Result<(), Pierror> {
+        let color = brush.solid_color().ok_or(Pierror::NotSupported)?;
+        if !(pos.is_finite() && blur_radius.is_finite()) {
+            return Err(Pierror::InvalidInput);
+        }
+
+        let blur_radius = blur_radius.max(0.0);
+        let padding = (shadow::BLUR_EXTENT * blur_radius).ceil();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2();
+        if !self.is_visible(bounds.inflate(padding, padding)) {
+            return Ok(());
+        }
+
+        // Rasterize the glyphs at the size they end up at on the target, like `draw_text`.
+        let glyphs = layout
+            .buffer()
+            .layout_runs()
+            .flat_map(|run| run.glyphs.iter());
+        let scale = atlas::common_scale(glyphs, self.glyph_scale());
+
+        let shadow = {
+            let context = &self.source.context;
+            let text = &self.source.text;
+            let atlas = self.source.atlas.as_mut().unwrap();
+            self.source
+                .shadows
+                .get_text(layout.identity(), blur_radius, scale, |memory| {
+                    shadow::render_text(context, memory, text, atlas, layout, blur_radius, scale)
+                })?
+        };
+        let texture = match shadow.texture() {
+            Some(texture) => texture,
+            None => return Ok(()),
+        };
+
+        self.fill_rects(
+            [TessRect {
+                pos: shadow.area() + pos.to_vec2(),
+                uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+                color,
+            }],
+            Some((texture, InterpolationMode::Bilinear)),
+        )
+    }
+
+    /// Create an image from pixels, uploading them in a later frame if `throttle` is set and
+    /// they don't fit in the upload budget.
+    fn make_image_impl(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &[u8],
+        format: piet::ImageFormat,
+        throttle: bool,
+    ) -> Result<Image<C>, Pierror> {
+        let interpolation = self.source.options.default_interpolation();
+        let tex = Texture::new(
+            &self.source.context,
+            interpolation,
+            RepeatStrategy::Color(piet::Color::TRANSPARENT),
+        )
+        .piet_err()?
+        .tracked(&self.source.memory, Category::Images);
+        let image = Image::new(tex, Size::new(width as f64, height as f64))
+            .with_interpolation(interpolation);
+
+        // Textures always contain premultiplied alpha, and grayscale textures only hold
+        // coverage, so gray images are expanded to opaque colors.
+        let (format, data) = match format {
+            piet::ImageFormat::RgbaSeparate => {
+                let data = self.source.staging.copy_of(buf);
+                premultiply(data);
+                (piet::ImageFormat::RgbaPremul, &*data)
+            }
+            piet::ImageFormat::Grayscale => {
+                let data = self.source.staging.zeroed(buf.len() * 4);
+                data.chunks_exact_mut(4)
+                    .zip(buf)
+                    .for_each(|(pixel, &luma)| pixel.copy_from_slice(&[luma, luma, luma, 0xFF]));
+                (piet::ImageFormat::RgbaPremul, &*data)
+            }
+            _ => (format, buf),
+        };
+
+        // Opaque images hide whatever they are drawn over, for occlusion queries.
+        let opaque = match format {
+            piet::ImageFormat::Rgb => true,
+            piet::ImageFormat::RgbaPremul => data.chunks_exact(4).all(|pixel| pixel[3] == 0xFF),
+            _ => false,
+        };
+        let image = image.with_opaque(opaque);
+
+        let size = (width as u32, height as u32);
+        if throttle {
+            self.source
+                .uploads
+                .write(image.texture(), size, format, data);
+        } else {
+            image.texture().write_texture(size, format, Some(data));
+        }
+
+        Ok(image)
+    }
+
+    /// Draw an area of an image, optionally with a color filter.
+    fn draw_image_impl(
+        &mut self,
+        image: &Image<C>,
+        src_rect: Rect,
+        pos_rect: Rect,
+        interp: piet::InterpolationMode,
+        filter: Option<&ColorFilter>,
+    ) -> Result<(), Pierror> {
+        // Images that are opaque where they are sampled hide what is under them.
+        let opaque = image.is_opaque()
+            && filter.is_none()
+            && {
+                let whole = Rect::from_origin_size(Point::ORIGIN, image.size());
+                whole.union(src_rect.abs()) == whole
+            }
+            && !self.source.uploads.is_pending(image.texture());
+
+        // Video frames go straight to the compositor, if nothing has to be blended with them.
+        if image.overlay() && filter.is_none() && self.show_overlay(image, src_rect, pos_rect)? {
+            if opaque {
+                self.cover(pos_rect);
+            }
+            return Ok(());
+        }
+
+        // Keep bilinear filtering from blending in the texels around a sub-rectangle, like
+        // the neighboring sprites of a sprite sheet.
+        let texture_size = image.texture_size();
+        let texel_rect = image.texel_rect(src_rect);
+        let texel_rect = match interp {
+            piet::InterpolationMode::Bilinear => inset_half_texel(texel_rect, texture_size),
+            piet::InterpolationMode::NearestNeighbor => texel_rect,
+        };
+
+        // Create a rectangle for UV.
+        let uv_rect = Rect::new(
+            texel_rect.x0 / texture_size.width,
+            texel_rect.y0 / texture_size.height,
+            texel_rect.x1 / texture_size.width,
+            texel_rect.y1 / texture_size.height,
+        );
+
+        // Sample images that are drawn much smaller than they are from mip levels, so that
+        // they don't alias. Sub-images are left alone, since the levels blend in the texels
+        // around them.
+        if interp == piet::InterpolationMode::Bilinear
+            && image.size() == texture_size
+            && !self.source.uploads.is_pending(image.texture())
+        {
+            let [a, b, c, d, _, _] = self.state.last().unwrap().transform.as_coeffs();
+            let texels_per_pixel = f64::max(
+                src_rect.width().abs() / (pos_rect.width() * a.hypot(b)).abs(),
+                src_rect.height().abs() / (pos_rect.height() * c.hypot(d)).abs(),
+            );
+            if texels_per_pixel >= MIPMAP_THRESHOLD {
+                self.source
+                    .context
+                    .generate_mipmaps(image.texture().resource());
+            }
+        }
+
+        // Set up the color matrix, if the filter needs one. It can't be recorded in a scene.
+        let matrix = filter.and_then(|filter| filter.color_matrix());
+        if let Some(matrix) = &matrix {
+            if self.recording.is_some() || !self.source.context.set_color_matrix(Some(matrix)) {
+                return Err(Pierror::NotSupported);
+            }
+        }
+
+        // Use this to draw the image.
+        let result = self.fill_rects(
+            [TessRect {
+                pos: pos_rect,
+                uv: uv_rect,
+                color: filter.map_or(piet::Color::WHITE, |filter| filter.vertex_color()),
+            }],
+            Some((image.texture(), interp)),
+        );
+
+        if matrix.is_some() {
+            self.source.context.set_color_matrix(None);
+        }
+
+        if result.is_ok() && opaque {
+            self.cover(pos_rect);
+        }
+
+        result
+    }
+
+    /// Try to show an image on an overlay of the compositor instead of drawing it.
+    fn show_overlay(
+        &mut self,
+        image: &Image<C>,
+        src_rect: Rect,
+        pos_rect: Rect,
+    ) -> Result<bool, Pierror> {
+        let state = self.state.last().unwrap();
+        let clipped = !state.mask.is_empty()
+            || state.clip_shape.is_some()
+            || state.scissor.is_some()
+            || !state.stencil_clips.is_empty();
+        if clipped
+            || state.alpha < 1.0
+            || self.recording.is_some()
+            || self.composite_mode != CompositeMode::SourceOver
+            || self.state.iter().any(|state| state.layer.is_some())
+            || self.source.uploads.is_pending(image.texture())
+        {
+            return Ok(false);
+        }
+
+        // Overlays are axis-aligned rectangles, so the image may only be scaled and moved.
+        let transform = self.device_transform();
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let upright = |rect: Rect| rect.x0 < rect.x1 && rect.y0 < rect.y1;
+        if b != 0.0 || c != 0.0 || a <= 0.0 || d <= 0.0 || !upright(pos_rect) || !upright(src_rect)
+        {
+            return Ok(false);
+        }
+
+        // The overlay only shows through if a hole can be punched into the target.
+        if !self
+            .source
+            .context
+            .set_composite_mode(Some(CompositeMode::Clear))
+        {
+            return Ok(false);
+        }
+
+        let rect = transform.transform_rect_bbox(pos_rect);
+        let source = image.texel_rect(src_rect);
+        let result = if self
+            .source
+            .context
+            .show_overlay(image.texture().resource(), source, rect)
+        {
+            let uv_white = Point::new(UV_WHITE[0] as f64, UV_WHITE[1] as f64);
+            self.fill_rects(
+                [TessRect {
+                    pos: pos_rect,
+                    uv: Rect::from_points(uv_white, uv_white),
+                    color: piet::Color::WHITE,
+                }],
+                None,
+            )
+            .map(|()| true)
+        } else {
+            Ok(false)
+        };
+
+        self.source.context.set_composite_mode(None);
+        result
+    }
+
+    /// Draw an image with a color filter applied to it.
+    ///
+    /// Tints are supported everywhere, while other filters need support from the
+    /// [`GpuContext`] and report [`Pierror::NotSupported`] otherwise.
+    pub fn draw_image_filtered(
+        &mut self,
+        image: &Image<C>,
+        dst_rect: impl Into<Rect>,
+        interp: piet::InterpolationMode,
+        filter: &ColorFilter,
+    ) {
+        let src_rect = Rect::ZERO.with_size(image.size());
+        self.draw_image_area_filtered(image, src_rect, dst_rect, interp, filter)
+    }
+
+    /// Draw an area of an image with a color filter applied to it.
+    pub fn draw_image_area_filtered(
+        &mut self,
+        image: &Image<C>,
+        src_rect: impl Into<Rect>,
+        dst_rect: impl Into<Rect>,
+        interp: piet::InterpolationMode,
+        filter: &ColorFilter,
+    ) {
+        let pos_rect = dst_rect.into();
+        if let Err(e) = self.draw_image_impl(image, src_rect.into(), pos_rect, interp, Some(filter))
+        {
+            self.report(Operation::DrawImage, Some(pos_rect), e);
+        }
+    }
+
+    /// Get the precision that shapes are tessellated with under the current transform.
+    fn precision(&self) -> Precision {
+        Precision::new(
+            self.flatten_tolerance(),
+            self.state.last().unwrap().transform,
+        )
+    }
+
+    /// Get the tolerance that curves are flattened with, which is finer while recording a
+    /// scene that is drawn scaled up.
+    fn flatten_tolerance(&self) -> f64 {
+        match &self.recording {
+            Some(recorder) => (self.tolerance / recorder.detail()).max(MIN_TOLERANCE),
+            None => self.tolerance,
+        }
+    }
+
+    /// Get the point in user space that vertices are measured from.
+    ///
+    /// Vertices are stored as `f32`, which can't tell apart nearby points with coordinates in
+    /// the millions, so shapes jitter when a map or CAD drawing is zoomed into. When the view
+    /// is far from the origin compared to its size, its position is rounded to a power of two
+    /// and subtracted in `f64` before the cast, and added back by the transform on the GPU.
+    fn eye(&self) -> Vec2 {
+        let transform = self.device_transform();
+        let det = transform.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return Vec2::ZERO;
+        }
+
+        let (width, height) = self.size;
+        let view = transform.inverse().transform_rect_bbox(Rect::new(
+            0.0,
+            0.0,
+            width as f64,
+            height as f64,
+        ));
+        let extent = view.width().max(view.height());
+        let center = view.center().to_vec2();
+        if !(extent > 0.0 && center.is_finite() && center.hypot() > extent * EYE_DISTANCE) {
+            return Vec2::ZERO;
+        }
+
+        let grid = 2.0f64.powi(extent.log2().ceil() as i32);
+        Vec2::new(
+            (center.x / grid).round() * grid,
+            (center.y / grid).round() * grid,
+        )
+    }
+
+    /// Get the transform from user space to the pixels of the target.
+    fn device_transform(&self) -> Affine {
+        Affine::translate(-self.origin) * self.state.last().unwrap().transform
+    }
+
+    /// Hide the occlusion queries under an opaque rectangle, in user space.
+    ///
+    /// Only rectangles that stay upright on the target and aren't masked or blended count.
+    fn cover(&mut self, rect: Rect) {
+        if !self.source.occlusion.is_waiting() {
+            return;
+        }
+
+        let state = self.state.last().unwrap();
+        let masked =
+            !state.mask.is_empty() || state.clip_shape.is_some() || !state.stencil_clips.is_empty();
+        let blended = state.alpha < 1.0
+            || !matches!(
+                self.composite_mode,
+                CompositeMode::SourceOver | CompositeMode::Source
+            );
+        let transform = self.device_transform();
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let upright = (b == 0.0 && c == 0.0) || (a == 0.0 && d == 0.0);
+        if masked || blended || !upright {
+            return;
+        }
+
+        let mut rect = transform.transform_rect_bbox(rect);
+        if let Some([x0, y0, x1, y1]) = state.scissor {
+            rect = rect.intersect(Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64));
+        }
+        self.cover_target(rect);
+    }
+
+    /// Hide the occlusion queries under an opaque area of the target.
+    fn cover_target(&mut self, rect: Rect) {
+        if self.recording.is_some() || self.state.iter().any(|state| state.layer.is_some()) {
+            return;
+        }
+
+        // Only pixels that are entirely inside of the area are certain to be covered.
+        let inner = Rect::new(
+            rect.x0.ceil(),
+            rect.y0.ceil(),
+            rect.x1.floor(),
+            rect.y1.floor(),
+        );
+        if !inner.is_empty() {
+            self.source.occlusion.cover(inner);
+        }
+    }
+
+    /// Whether anything inside of `bounds`, in user space, can end up on the target.
+    fn is_visible(&self, bounds: Rect) -> bool {
+        // Recorded scenes can be drawn anywhere.
+        if self.recording.is_some() {
+            return true;
+        }
+
+        let bounds = self.device_transform().transform_rect_bbox(bounds);
+
+        // Let invalid shapes through, so that they are reported.
+        if !(bounds.origin().is_finite() && bounds.size().is_finite()) {
+            return true;
+        }
+
+        bounds.x1 >= 0.0
+            && bounds.y1 >= 0.0
+            && bounds.x0 <= self.size.0 as f64
+            && bounds.y0 <= self.size.1 as f64
+    }
+
+    /// Read back an area of the target as premultiplied RGBA pixels.
+    ///
+    /// The area is transformed by the current transform, and then rounded out to whole pixels
+    /// and clipped to the target.
+    fn read_area(&mut self, src_rect: Rect) -> Result<((u32, u32), Vec<u8>), Pierror> {
+        let transform = self.device_transform();
+        let target = Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64);
+        let area = (transform * src_rect.to_path(self.tolerance))
+            .bounding_box()
+            .expand()
+            .intersect(target);
+
+        let origin = (area.x0 as u32, area.y0 as u32);
+        let size = (area.width() as u32, area.height() as u32);
+        let data = self.source.read_pixels(origin, size, self.size)?;
+
+        Ok((size, data))
+    }
+
+    /// Read back an area of the target into a CPU-side image with the given format.
+    ///
+    /// Unlike [`capture_image_area`], the pixels stay in memory, which makes this useful for
+    /// exporting the drawing or comparing it in tests. The area is transformed by the current
+    /// transform, and then rounded out to whole pixels.
+    ///
+    /// [`capture_image_area`]: piet::RenderContext::capture_image_area
+    pub fn capture_image_buf(
+        &mut self,
+        src_rect: impl Into<Rect>,
+        format: piet::ImageFormat,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        let ((width, height), data) = self.read_area(src_rect.into())?;
+        let data = convert_premultiplied(data, format)?;
+        Ok(piet::ImageBuf::from_raw(
+            data,
+            format,
+            width as usize,
+            height as usize,
+        ))
+    }
+
+    /// Start recording the shapes that are drawn into a [`Scene`] instead of drawing them.
+    ///
+    /// Recording starts with an identity transform, full opacity and no clip, and the global
+    /// alpha that is set while recording is baked into the scene. Shapes are tessellated with
+    /// the tolerance of this context, so scenes that are zoomed into should be recorded again
+    /// once the zoom changes by a lot. Clipping, clearing the whole target and color matrix
+    /// filters are not supported while recording, and text has to be recorded again after
+    /// [`Source::compact_atlas`] or after the text atlas fills up. A [`DisplayList`] records
+    /// all of those, at the cost of tessellating again whenever it is played.
+    ///
+    /// Returns [`Pierror::InvalidInput`] if a scene is already being recorded.
+    pub fn begin_scene(&mut self) -> Result<(), Pierror> {
+        self.begin_scene_with_detail(1.0)
+    }
+
+    /// Start recording a [`Scene`] that stays accurate when it is drawn scaled up by `detail`.
+    ///
+    /// Curves are flattened with the tolerance of this context divided by `detail`. See
+    /// [`begin_scene`](Self::begin_scene) for more information, and [`SceneLevels`] for
+    /// keeping the recordings at several details around.
+    ///
+    /// Returns [`Pierror::InvalidInput`] if a scene is already being recorded, or if `detail`
+    /// isn't positive.
+    pub fn begin_scene_with_detail(&mut self, detail: f64) -> Result<(), Pierror> {
+        if self.recording.is_some() || !(detail.is_finite() && detail > 0.0) {
+            return Err(Pierror::InvalidInput);
+        }
+
+        self.recording = Some(SceneRecorder::new(
+            self.state.len(),
+            self.source.memory.clone(),
+            detail,
+        ));
+        self.state.push(RenderState::default());
+        Ok(())
+    }
+
+    /// Finish recording a [`Scene`] started with [`begin_scene`](Self::begin_scene).
+    ///
+    /// The transform, global alpha and saved states from before recording are restored.
+    pub fn end_scene(&mut self) -> Result<Scene<C>, Pierror> {
+        let recorder = self.recording.take().ok_or(Pierror::InvalidInput)?;
+
+        while self.state.len() > recorder.depth() {
+            if let Some(mut state) = self.state.pop() {
+                state.mask.recycle(&mut self.source.masks);
+            }
+        }
+
+        recorder.finish(&self.source.context)
+    }
+
+    /// Tessellate a filled shape into a [`Marker`], measured in device pixels.
+    ///
+    /// The shape is tessellated with the tolerance of this context, around the origin the
+    /// marker is placed at.
+    pub fn fill_marker(&mut self, shape: impl Shape) -> Result<Marker, Pierror> {
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        let precision = Precision::new(self.tolerance, Affine::IDENTITY);
+        rasterizer.fill_shape(shape, FillRule::NonZero, precision, |pos| Vertex {
+            pos: [pos.x as f32, pos.y as f32],
+            ..Vertex::default()
+        })?;
+
+        let marker = Marker::new(rasterizer.vertices(), rasterizer.indices());
+        rasterizer.clear();
+        Ok(marker)
+    }
+
+    /// Tessellate the stroke of a shape into a [`Marker`], measured in device pixels.
+    ///
+    /// See [`fill_marker`](Self::fill_marker) for more information.
+    pub fn stroke_marker(
+        &mut self,
+        shape: impl Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<Marker, Pierror> {
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        let precision = Precision::new(self.tolerance, Affine::IDENTITY);
+        rasterizer.stroke_shape(shape, precision, width, style, |pos| Vertex {
+            pos: [pos.x as f32, pos.y as f32],
+            ..Vertex::default()
+        })?;
+
+        let marker = Marker::new(rasterizer.vertices(), rasterizer.indices());
+        rasterizer.clear();
+        Ok(marker)
+    }
+
+    /// Tessellate the fill of a shape into a [`CachedPath`] that is kept on the GPU.
+    ///
+    /// Curves are flattened with the tolerance of this context for the current transform.
+    pub fn make_cached_path(&mut self, shape: impl Shape) -> Result<CachedPath<C>, Pierror> {
+        let path = shape.into_path(self.precision().tolerance());
+        let precision = self.precision();
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        let result = rasterizer
+            .fill_shape(&path, FillRule::NonZero, precision, |pos| Vertex {
+                pos: [pos.x as f32, pos.y as f32],
+                ..Vertex::default()
+            })
+            .and_then(|()| {
+                Geometry::new(
+                    &self.source.context,
+                    &self.source.memory,
+                    rasterizer.vertices(),
+                    rasterizer.indices(),
+                )
+            });
+        rasterizer.clear();
+
+        Ok(CachedPath::new(path, precision, result?))
+    }
+
+    /// Fill a [`CachedPath`] with the non-zero fill rule, without tessellating it again.
+    ///
+    /// The vertices are only written to the GPU again if the brush isn't the same solid color
+    /// and global alpha as the last time the path was filled. Custom brushes, scenes being
+    /// recorded and [feathered](Antialiasing::Feather) edges need the path to be tessellated
+    /// again, like [`fill`](piet::RenderContext::fill) does.
+    pub fn fill_cached(
+        &mut self,
+        path: &CachedPath<C>,
+        brush: &impl piet::IntoBrush<Self>,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        if !self.draws_cached(&brush) {
+            return self.fill_impl(path.path(), &brush, FillRule::NonZero);
+        }
+        if path.fill().is_empty() || !self.is_visible(path.bounds()) {
+            return Ok(());
+        }
+
+        self.push_geometry(path.fill(), &brush)
+    }
+
+    /// Stroke a [`CachedPath`], only tessellating the stroke if it was last drawn with another
+    /// width or style.
+    ///
+    /// Only the last stroke of a path is kept. Hairlines and
+    /// [non-scaling strokes](Self::set_non_scaling_stroke) depend on the transform, so they are
+    /// tessellated every time, along with the strokes that [`fill_cached`](Self::fill_cached)
+    /// can't draw from the GPU.
+    pub fn stroke_cached(
+        &mut self,
+        path: &CachedPath<C>,
+        brush: &impl piet::IntoBrush<Self>,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        let state = self.state.last().unwrap();
+        let hairline = width * state.transform.determinant().abs().sqrt() < HAIRLINE_WIDTH;
+        if !self.draws_cached(&brush) || state.non_scaling_stroke || hairline {
+            return self.stroke_impl(path.path(), &brush, width, style);
+        }
+
+        let reach = match style.line_join {
+            piet::LineJoin::Miter { limit } => limit.max(SQRT_2),
+            _ => SQRT_2,
+        };
+        if !self.is_visible(path.bounds().inflate(width * reach, width * reach)) {
+            return Ok(());
+        }
+
+        let geometry = match path.stroke(width, style) {
+            Some(geometry) => geometry,
+            None => {
+                let rasterizer = &mut self.source.buffers.rasterizer;
+                let result = rasterizer
+                    .stroke_shape(path.path(), path.precision(), width, style, |pos| Vertex {
+                        pos: [pos.x as f32, pos.y as f32],
+                        ..Vertex::default()
+                    })
+                    .and_then(|()| {
+                        Geometry::new(
+                            &self.source.context,
+                            &self.source.memory,
+                            rasterizer.vertices(),
+                            rasterizer.indices(),
+                        )
+                    });
+                rasterizer.clear();
+
+                let geometry = Rc::new(result?);
+                path.set_stroke(width, style, geometry.clone());
+                geometry
+            }
+        };
+        if geometry.is_empty() {
+            return Ok(());
+        }
+
+        self.push_geometry(&geometry, &brush)
+    }
+
+    /// Make an occlusion query for an area, and get whether the same query was hidden in the
+    /// last frame.
+    ///
+    /// The query is hidden if every pixel under `bounds`, in user space, is covered by opaque
+    /// drawing later in the frame, or is outside of the target and the clip. Making a query
+    /// with the same `id` every frame before drawing some expensive content, like a window
+    /// preview that may be stacked under others, lets that content be skipped while this
+    /// returns `true`. Results are from the frame before the last
+    /// [`Source::begin_occlusion_frame`], so uncovered content shows up a frame late.
+    ///
+    /// Only solid rectangles, images without transparent pixels and clears count as covering,
+    /// and only when they are opaque, stay upright on the target and aren't clipped to a
+    /// shape or drawn into a layer or scene. Backends that support
+    /// [`GpuContext::begin_occlusion_query`] also report areas that are clipped away
+    /// entirely by the stencil buffer. Queries made while recording a scene are never hidden.
+    /// When a frame is drawn in tiles or onto several targets, a query is hidden if it is
+    /// hidden on every target that it was made on.
+    pub fn occlusion_query(&mut self, id: u64, bounds: Rect) -> bool {
+        let area = self.device_transform().transform_rect_bbox(bounds);
+        if !self.occlusion_queries
+            || self.recording.is_some()
+            || !(area.origin().is_finite() && area.size().is_finite())
+        {
+            return false;
+        }
+
+        let mut visible = Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64);
+        if let Some([x0, y0, x1, y1]) = self.state.last().unwrap().scissor {
+            visible = visible.intersect(Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64));
+        }
+        self.source
+            .occlusion
+            .query(id, area.expand().intersect(visible));
+
+        // Let the backend count whether any pixel of the area passes the clip, by drawing it
+        // without changing the target.
+        let result = self.with_composite_mode(CompositeMode::Destination, |rc| {
+            if !rc.source.context.begin_occlusion_query(id) {
+                return Ok(());
+            }
+
+            let uv_white = Point::new(UV_WHITE[0] as f64, UV_WHITE[1] as f64);
+            let result = rc.fill_rects(
+                [TessRect {
+                    pos: bounds,
+                    uv: Rect::from_points(uv_white, uv_white),
+                    color: piet::Color::WHITE,
+                }],
+                None,
+            );
+            rc.source.context.end_occlusion_query(id);
+            result
+        });
+        if let Err(e) | Ok(Err(e)) = result {
+            warn!("failed to run an occlusion query: {}", e);
+        }
+
+        self.source.occlusion.was_hidden(id)
+            || self.source.context.occlusion_query_result(id) == Some(false)
+    }
+
+    /// Create a [`HitTester`] that flattens shapes like this context does.
+    ///
+    /// The hit tester uses the tolerance of this context and the way its source closes
+    /// subpaths, so points hit shapes exactly where they are drawn. Pass the current transform
+    /// to its methods to test points on the target.
+    pub fn hit_tester(&self) -> HitTester {
+        HitTester::with_closing(self.source.options.closing(), self.tolerance)
+    }
+
+    /// Get the outline of a stroke as a path, in user space.
+    ///
+    /// The outline covers the same area as [`stroke_styled`] would with the same arguments,
+    /// including dashes, and is flattened with the tolerance of the current transform. It is
+    /// made of the triangles of the stroke, so it is meant to be filled with the non-zero
+    /// rule; this makes it useful for hit-testing strokes with [`Shape::contains`] or for
+    /// clipping to them, but not for stroking again.
+    ///
+    /// [`stroke_styled`]: piet::RenderContext::stroke_styled
+    pub fn stroke_outline(
+        &mut self,
+        shape: impl Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<BezPath, Pierror> {
+        // Keep the positions small, since they are stored as `f32` while tessellating.
+        let origin = shape.bounding_box().center().to_vec2();
+        let precision = self.precision().with_origin(origin);
+
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        rasterizer.stroke_shape(shape, precision, width, style, |pos| {
+            let pos = pos - origin;
+            Vertex {
+                pos: [pos.x as f32, pos.y as f32],
+                ..Vertex::default()
+            }
+        })?;
+
+        let outline = rasterizer.to_path(origin);
+        rasterizer.clear();
+        Ok(outline)
+    }
+
+    /// Stamp a [`Marker`] at every placement with one draw call.
+    ///
+    /// Each placement is moved through the current transform, but the marker itself keeps
+    /// its size in device pixels and is only rotated by the placement's rotation. The brush is
+    /// applied around the marker's origin, so every stamp looks the same. Placements whose
+    /// stamp can't reach the target are skipped.
+    pub fn draw_markers<P: Into<MarkerPlacement>>(
+        &mut self,
+        marker: &Marker,
+        brush: &impl piet::IntoBrush<Self>,
+        placements: impl IntoIterator<Item = P>,
+    ) -> Result<(), Pierror> {
+        let transform = self.state.last().unwrap().transform;
+        let det = transform.determinant();
+        if marker.is_empty() || det == 0.0 || !det.is_finite() {
+            return Ok(());
+        }
+
+        let brush = brush.make_brush(self, || marker.bounds());
+        let to_user = transform.inverse();
+        let eye = self.eye();
+
+        // The stamps can reach this far from their position, however they are rotated.
+        let bounds = marker.bounds();
+        let reach = [bounds.x0, bounds.x1]
+            .iter()
+            .flat_map(|&x| [bounds.y0, bounds.y1].map(|y| x.hypot(y)))
+            .fold(0.0, f64::max);
+
+        for placement in placements {
+            let MarkerPlacement { position, rotation } = placement.into();
+            let device_pos = transform * position;
+            let device_bounds = Rect::from_center_size(device_pos, (reach * 2.0, reach * 2.0));
+            if !self.is_visible(to_user.transform_rect_bbox(device_bounds)) {
+                continue;
+            }
+
+            let stamp =
+                to_user * Affine::translate(device_pos.to_vec2()) * Affine::rotate(rotation);
+            let vertices = marker.points().iter().map(|&point| {
+                let pos = stamp * point - eye;
+                Vertex {
+                    pos: [pos.x as f32, pos.y as f32],
+                    ..brush.make_vertex([point.x as f32, point.y as f32])
+                }
+            });
+            self.source
+                .buffers
+                .rasterizer
+                .extend(vertices, marker.indices());
+        }
+
+        if self.source.buffers.rasterizer.indices().is_empty() {
+            return Ok(());
+        }
+
+        self.push_brush(&brush)
+    }
+
+    /// Create a brush from a gradient that continues past its ends with `spread`.
+    ///
+    /// This works like [`gradient`](piet::RenderContext::gradient), which pads gradients.
+    /// Radial gradients that repeat never end, so they are rendered into a texture that
+    /// covers the target with the current transform; shapes outside of it, or drawn with
+    /// another transform, see the colors at its edges stretched outwards.
+    pub fn gradient_with_spread(
+        &mut self,
+        gradient: impl Into<FixedGradient>,
+        spread: SpreadMode,
+    ) -> Result<Brush<C>, Pierror> {
+        match gradient.into() {
+            FixedGradient::Linear(linear) => {
+                Brush::linear_gradient(&self.source.context, &mut self.source.ramps, linear, spread)
+            }
+            FixedGradient::Radial(radial) => {
+                let transform = self.device_transform();
+                let target = Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64);
+                let visible = transform.inverse().transform_rect_bbox(target);
+                let scale = transform.determinant().abs().sqrt();
+                if spread != SpreadMode::Pad && !(visible.is_finite() && scale > 0.0) {
+                    return Err(Pierror::InvalidInput);
+                }
+
+                Brush::radial_gradient(
+                    &self.source.context,
+                    &self.source.memory,
+                    radial,
+                    spread,
+                    visible,
+                    scale,
+                )
+            }
+        }
+    }
+
+    /// Create a brush that fills shapes with a sweep gradient, like a color wheel.
+    ///
+    /// Like a radial gradient, the gradient is rendered into a new texture every time this
+    /// is called, so the brush should be kept around instead of being created every frame.
+    pub fn sweep_gradient(&mut self, gradient: &SweepGradient) -> Result<Brush<C>, Pierror> {
+        Brush::sweep_gradient(&self.source.context, &self.source.memory, gradient)
+    }
+
+    /// Draw a blurred rectangle with rounded corners, like the shadow of a card.
+    ///
+    /// This works like [`blurred_rect`](piet::RenderContext::blurred_rect). Shadows are
+    /// rendered once for every size, corner radius and blur radius and then reused, and long
+    /// sides stretch the middle of a cached shadow, so drawing the same shadow many times per
+    /// frame is cheap. Only solid brushes are supported.
+    pub fn blurred_rounded_rect(
+        &mut self,
+        rect: impl Into<Rect>,
+        corner_radius: f64,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let rect = rect.into();
+        let brush = brush.make_brush(self, || rect);
+        if let Err(e) = self.blurred_rect_impl(rect, corner_radius, blur_radius, brush.as_ref()) {
+            self.report(Operation::BlurredRect, Some(rect), e);
+        }
+    }
+
+    /// Draw a text layout with a soft shadow below it, like a subtitle.
+    ///
+    /// The shadow is the coverage of the glyphs, moved by `offset` and blurred like
+    /// [`blurred_rect`](piet::RenderContext::blurred_rect). Several shadows can be stacked
+    /// with [`draw_text_shadow`](Self::draw_text_shadow) instead. Only solid brushes are
+    /// supported.
+    pub fn draw_text_with_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<Point>,
+        offset: impl Into<Vec2>,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        self.draw_text_shadow(layout, pos + offset.into(), blur_radius, brush);
+        piet::RenderContext::draw_text(self, layout, pos);
+    }
+
+    /// Draw only the soft shadow of a text layout at `pos`.
+    ///
+    /// The glyphs are rasterized and blurred on the CPU every time this is called, so the
+    /// cost grows with the size of the text and the blur radius. Underlines and
+    /// strikethroughs don't cast a shadow.
+    pub fn draw_text_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<Point>,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2();
+        let brush = brush.make_brush(self, || bounds);
+        if let Err(e) = self.text_shadow_impl(layout, pos, blur_radius, brush.as_ref()) {
+            self.report(Operation::DrawText, Some(bounds), e);
+        }
+    }
+
+    /// Draw a [`Scene`] that was recorded from this context's [`Source`].
+    ///
+    /// The scene is drawn with the current transform, clip and global alpha, without
+    /// tessellating or uploading anything. A global alpha below one needs
+    /// [`GpuContext::set_color_matrix`] to be supported.
+    pub fn draw_scene(&mut self, scene: &Scene<C>) -> Result<(), Pierror> {
+        self.draw_scenes(|f| f(scene, Homography::IDENTITY))
+    }
+
+    /// Draw the level of a [`SceneLevels`] that fits the current transform best.
+    ///
+    /// Nothing is drawn if there are no levels. See [`draw_scene`](Self::draw_scene) for more
+    /// information.
+    pub fn draw_scene_levels(&mut self, levels: &SceneLevels<C>) -> Result<(), Pierror> {
+        match levels.select(self.state.last().unwrap().transform) {
+            Some(scene) => self.draw_scene(scene),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw every layer of a [`LayerTree`] whose scenes were recorded from this context's
+    /// [`Source`].
+    ///
+    /// Parents are drawn below their children. The tree is drawn like a single scene, with
+    /// the current transform, clip and global alpha; see [`draw_scene`](Self::draw_scene).
+    pub fn draw_layers(&mut self, tree: &LayerTree<C>) -> Result<(), Pierror> {
+        self.draw_scenes(|f| tree.visit(f))
+    }
+
+    /// Save the state like [`save`], and draw into an offscreen layer until it is restored.
+    ///
+    /// On [`restore`], the part of the layer inside of `bounds`, in user space, is composited
+    /// back with the opacity `alpha` and the clip of the restored state. Since the shapes in
+    /// the layer are blended with each other first, a group of shapes fades out as a whole
+    /// instead of showing through itself. The layer starts out transparent, with no clip and
+    /// a global alpha of one.
+    ///
+    /// This needs [`GpuContext::create_render_target`] to be supported. Otherwise, `alpha` is
+    /// multiplied into the global alpha of the saved state instead. Layers can't be saved
+    /// while a scene is being recorded.
+    ///
+    /// [`save`]: piet::RenderContext::save
+    /// [`restore`]: piet::RenderContext::restore
+    pub fn save_layer(&mut self, bounds: Rect, alpha: f64) -> Result<(), Pierror> {
+        use piet::RenderContext as _;
+
+        if self.recording.is_some() {
+            return Err(Pierror::NotSupported);
+        }
+        if !(bounds.origin().is_finite() && bounds.size().is_finite() && alpha.is_finite()) {
+            return Err(Pierror::InvalidInput);
+        }
+        let alpha = alpha.clamp(0.0, 1.0);
+
+        let target_rect = Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64);
+        let area = self
+            .device_transform()
+            .transform_rect_bbox(bounds)
+            .expand()
+            .intersect(target_rect);
+
+        let previous = self.source.render_target.clone();
+        let target = self.source.layer_target(self.size)?;
+        self.save()?;
+
+        let state = self.state.last_mut().unwrap();
+        match target {
+            Some(target) => {
+                self.source.context.clear(piet::Color::TRANSPARENT);
+                state.alpha = 1.0;
+                state.layer = Some(SavedLayer {
+                    target,
+                    previous,
+                    area,
+                    alpha,
+                });
+            }
+            None => state.alpha *= alpha,
+        }
+        Ok(())
+    }
+
+    /// Get the Porter-Duff operator that drawing is combined with the target with.
+    pub fn composite_mode(&self) -> CompositeMode {
+        self.composite_mode
+    }
+
+    /// Set the Porter-Duff operator that drawing is combined with the target with.
+    ///
+    /// See [`CompositeMode`] for how the modes behave. Anything but
+    /// [`CompositeMode::SourceOver`] needs [`GpuContext::set_composite_mode`] to be supported,
+    /// and can't be used while a scene is being recorded. The mode isn't saved and restored
+    /// with the rest of the state; see [`with_composite_mode`](Self::with_composite_mode).
+    pub fn set_composite_mode(&mut self, mode: CompositeMode) -> Result<(), Pierror> {
+        if self.recording.is_some() && mode != CompositeMode::SourceOver {
+            return Err(Pierror::NotSupported);
+        }
+
+        let set = match mode {
+            CompositeMode::SourceOver => None,
+            mode => Some(mode),
+        };
+        if !self.source.context.set_composite_mode(set) {
+            return Err(Pierror::NotSupported);
+        }
+
+        self.composite_mode = mode;
+        Ok(())
+    }
+
+    /// Draw with the Porter-Duff operator `mode` inside of `f`, and go back to the previous
+    /// one afterwards.
+    ///
+    /// See [`set_composite_mode`](Self::set_composite_mode) for more information.
+    pub fn with_composite_mode<R>(
+        &mut self,
+        mode: CompositeMode,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> Result<R, Pierror> {
+        let previous = self.composite_mode;
+        self.set_composite_mode(mode)?;
+
+        let result = f(self);
+        self.set_composite_mode(previous)?;
+        Ok(result)
+    }
+
+    /// Submit the drawing so far to the GPU without finishing the frame.
+    ///
+    /// This lets the GPU start on the first part of a frame while the rest of it is still
+    /// being built, instead of getting all of it at once in [`finish`](piet::RenderContext::finish).
+    /// Nothing is presented until the frame is finished.
+    pub fn flush_pending(&mut self) -> Result<(), Pierror> {
+        self.source.context.flush().piet_err()
+    }
+
+    /// Submit the drawing so far, so that the application can issue its own GPU commands.
+    ///
+    /// Everything drawn before the barrier runs before commands that the application issues
+    /// afterwards on the same device or GL context, and everything drawn after it runs after
+    /// them, so custom content can be layered between piet drawing deterministically. Unlike
+    /// [`flush_pending`](Self::flush_pending), this also unbinds the state that the backend
+    /// keeps between draws. The application has to leave the render target bound, and undo
+    /// any state that it enables, like depth testing or scissoring, before drawing resumes.
+    pub fn barrier(&mut self) -> Result<(), Pierror> {
+        self.source.context.barrier().piet_err()
+    }
+
+    /// Draw the scenes that `visit` passes to its callback, each with an extra transform.
+    fn draw_scenes(
+        &mut self,
+        visit: impl FnOnce(&mut dyn FnMut(&Scene<C>, Homography)),
+    ) -> Result<(), Pierror> {
+        if self.recording.is_some() {
+            return Err(Pierror::NotSupported);
+        }
+
+        // Fade the scene out through the color matrix.
+        let alpha = self.state.last().unwrap().alpha;
+        let fade = alpha < 1.0;
+        if fade {
+            let mut matrix = ColorMatrix::IDENTITY;
+            matrix.0[18] = alpha as f32;
+            if !self.source.context.set_color_matrix(Some(&matrix)) {
+                return Err(Pierror::NotSupported);
+            }
+        }
+
+        let transform = self.device_transform();
+        let size = self.size;
+        let (clip_shape, scissor) = {
+            let state = self.state.last().unwrap();
+            (state.clip_shape, state.scissor)
+        };
+        let result = self.source.with_clip(clip_shape.as_ref(), scissor, || {
+            let mask = {
+                let state = self.state.last_mut().unwrap();
+                state.mask.texture()?.unwrap_or(&self.source.white_pixel)
+            };
+
+            let mut result = Ok(());
+            visit(&mut |scene, scene_transform| {
+                if result.is_err() {
+                    return;
+                }
+
+                // Scenes with perspective are drawn through the projection of the backend,
+                // which works in the pixels of the target.
+                let (scene_transform, projection) = match scene_transform.as_affine() {
+                    Some(affine) => (affine, None),
+                    None if transform.determinant() == 0.0 => return,
+                    None => {
+                        let projection = Homography::from(transform)
+                            * scene_transform
+                            * Homography::from(transform.inverse());
+                        (Affine::IDENTITY, Some(projection.to_projection()))
+                    }
+                };
+                if let Some(projection) = &projection {
+                    if !self.source.context.set_projection(Some(projection)) {
+                        result = Err(Pierror::NotSupported);
+                        return;
+                    }
+                }
+
+                for batch in scene.batches() {
+                    if result.is_err() {
+                        break;
+                    }
+
+                    result = self.source.push_buffers(
+                        batch.vbo.resource(),
+                        batch
+                            .texture
+                            .as_ref()
+                            .map(|(texture, interp)| (&**texture, *interp)),
+                        mask,
+                        &(transform * scene_transform * batch.transform),
+                        size,
+                    );
+                }
+
+                if projection.is_some() {
+                    self.source.context.set_projection(None);
+                }
+            });
 
-    /// The width and height of the target.
-    size: (u32, u32),
+            result
+        });
 
-    /// The current state of the renderer.
-    state: TinyVec<[RenderState<C>; 1]>,
+        if fade {
+            self.source.context.set_color_matrix(None);
+        }
 
-    /// The result to use for `status`.
-    status: Result<(), Pierror>,
+        result
+    }
 
-    /// Tolerance for tesselation.
-    tolerance: f64,
-}
+    /// Clip to a shape with the stencil buffer, returning `false` if the backend can't.
+    fn stencil_clip(&mut self, shape: &impl Shape, transform: Affine) -> Result<bool, Pierror> {
+        let path = transform * shape.path_elements(self.tolerance).collect::<BezPath>();
+        let buffer = match self.source.buffers.stencil.pop() {
+            Some(buffer) => buffer,
+            None => VertexBuffer::new(&self.source.context).piet_err()?,
+        };
+        if let Err(e) = self.upload_clip(&buffer, &path) {
+            self.source.buffers.stencil.push(buffer);
+            return Err(e);
+        }
 
-struct RenderState<C: GpuContext + ?Sized> {
-    /// The current transform in pixel space.
-    transform: Affine,
+        let pushed = self
+            .source
+            .context
+            .push_clip_path(buffer.resource(), self.size)
+            .piet_err();
+        if matches!(pushed, Ok(true)) {
+            self.state
+                .last_mut()
+                .unwrap()
+                .stencil_clips
+                .push((buffer, path));
+        } else {
+            // Don't tessellate every clip for a backend that won't take them.
+            self.source.buffers.stencil.push(buffer);
+            self.stencil = false;
+        }
+        pushed
+    }
 
-    /// The current clipping mask.
-    mask: MaskSlot<C>,
-}
+    /// Tessellate the path of a clip, in the pixels of the target, into a vertex buffer.
+    fn upload_clip(&mut self, buffer: &VertexBuffer<C>, path: &BezPath) -> Result<(), Pierror> {
+        let precision = Precision::new(self.tolerance, Affine::IDENTITY);
+        let rasterizer = &mut self.source.buffers.rasterizer;
+        let result = rasterizer.fill_shape(path, FillRule::NonZero, precision, |pos| Vertex {
+            pos: [pos.x as f32, pos.y as f32],
+            uv: UV_WHITE,
+            color: [0xFF; 4],
+        });
+        if result.is_ok() {
+            buffer.upload(rasterizer.vertices(), rasterizer.indices());
+        }
+        rasterizer.clear();
+        result
+    }
 
-impl<C: GpuContext + ?Sized> Default for RenderState<C> {
-    fn default() -> Self {
-        Self {
-            transform: Affine::IDENTITY,
-            mask: MaskSlot::new(),
+    /// Undo the clips that a state pushed to the stencil buffer.
+    fn pop_stencil_clips(&mut self, state: &mut RenderState<C>) -> Result<(), Pierror> {
+        let mut result = Ok(());
+        while let Some((buffer, path)) = state.stencil_clips.pop() {
+            // The triangles are written again, since backends may reuse the buffer once drawn.
+            if result.is_ok() {
+                result = self.upload_clip(&buffer, &path).and_then(|()| {
+                    self.source
+                        .context
+                        .pop_clip(buffer.resource(), self.size)
+                        .piet_err()
+                });
+            }
+            self.source.buffers.stencil.push(buffer);
         }
+        result
     }
-}
 
-impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
-    /// Fill in a rectangle.
-    fn fill_rects(
-        &mut self,
-        rects: impl IntoIterator<Item = TessRect>,
-        texture: Option<&Texture<C>>,
-    ) -> Result<(), Pierror> {
-        self.source.buffers.rasterizer.fill_rects(rects);
+    /// Bind the render target that was bound before a layer, and draw the layer onto it.
+    fn composite_layer(&mut self, layer: SavedLayer<C>) -> Result<(), Pierror> {
+        let SavedLayer {
+            target,
+            previous,
+            area,
+            alpha,
+        } = layer;
+        self.source.bind_render_target(previous)?;
+
+        let result = if area.is_empty() {
+            Ok(())
+        } else {
+            let (width, height) = (self.size.0 as f64, self.size.1 as f64);
+            let uv = if self.source.context.render_targets_flipped() {
+                Rect::new(
+                    area.x0 / width,
+                    1.0 - area.y0 / height,
+                    area.x1 / width,
+                    1.0 - area.y1 / height,
+                )
+            } else {
+                Rect::new(
+                    area.x0 / width,
+                    area.y0 / height,
+                    area.x1 / width,
+                    area.y1 / height,
+                )
+            };
+
+            // Draw in the pixels of the target, with the clip of the restored state.
+            let transform = mem::replace(
+                &mut self.state.last_mut().unwrap().transform,
+                Affine::translate(self.origin),
+            );
+            let result = self.fill_rects(
+                [TessRect {
+                    pos: area + self.origin,
+                    uv,
+                    color: piet::Color::WHITE.with_alpha(alpha),
+                }],
+                Some((&target, InterpolationMode::NearestNeighbor)),
+            );
+            self.state.last_mut().unwrap().transform = transform;
+            result
+        };
 
-        // Push the buffers to the GPU.
-        self.push_buffers(texture)
+        self.source.layer_targets.push(target);
+        result
     }
 
-    /// Fill in the provided shape.
-    fn fill_impl(
-        &mut self,
-        shape: impl Shape,
-        brush: &Brush<C>,
-        mode: FillRule,
-    ) -> Result<(), Pierror> {
-        self.source
-            .buffers
-            .rasterizer
-            .fill_shape(shape, mode, self.tolerance, |vert| {
-                let pos = vert.position();
-                brush.make_vertex(pos.into())
-            })?;
+    /// Push the values currently in the renderer to the GPU, filled with `brush`.
+    fn push_brush(&mut self, brush: &Brush<C>) -> Result<(), Pierror> {
+        let custom = match brush.custom_brush() {
+            Some(custom) => custom,
+            None => return self.push_buffers(brush.texture(self.size).map(Image::sampled)),
+        };
 
-        // Push the incoming buffers.
-        self.push_buffers(brush.texture(self.size).as_ref().map(|t| t.texture()))
+        // The shader is state of the backend, so it can't be recorded in a scene.
+        if self.recording.is_some() || !self.source.context.set_custom_brush(Some(custom)) {
+            self.source.buffers.rasterizer.clear();
+            return Err(Pierror::NotSupported);
+        }
+
+        let result = self.push_buffers(None);
+        self.source.context.set_custom_brush(None);
+        result
     }
 
-    fn stroke_impl(
+    /// Push the values currently in the renderer to the GPU.
+    ///
+    /// The texture is sampled with the given interpolation for this draw only.
+    fn push_buffers(
         &mut self,
-        shape: impl Shape,
-        brush: &Brush<C>,
-        width: f64,
-        style: &piet::StrokeStyle,
+        texture: Option<(&Rc<Texture<C>>, InterpolationMode)>,
     ) -> Result<(), Pierror> {
-        self.source.buffers.rasterizer.stroke_shape(
-            shape,
-            self.tolerance,
-            width,
-            style,
-            |vert| {
-                let pos = vert.position();
-                brush.make_vertex(pos.into())
-            },
-        )?;
+        // The vertices are measured from the eye.
+        let eye = self.eye();
 
-        // Push the incoming buffers.
-        self.push_buffers(brush.texture(self.size).as_ref().map(|t| t.texture()))
-    }
+        // Apply the global opacity.
+        let alpha = self.state.last().unwrap().alpha;
+        if alpha < 1.0 {
+            self.source.buffers.rasterizer.multiply_alpha(alpha);
+        }
+
+        // Keep the triangles around if a scene is being recorded.
+        if let Some(recorder) = self.recording.as_mut() {
+            let rasterizer = &mut self.source.buffers.rasterizer;
+            let result = recorder.push(
+                &self.source.context,
+                rasterizer.vertices(),
+                rasterizer.indices(),
+                texture,
+                self.state.last().unwrap().transform * Affine::translate(eye),
+            );
+            rasterizer.clear();
+            return result;
+        }
 
-    /// Push the values currently in the renderer to the GPU.
-    fn push_buffers(&mut self, texture: Option<&Texture<C>>) -> Result<(), Pierror> {
         // Upload the vertex and index buffers.
         self.source.buffers.vbo.upload(
             self.source.buffers.rasterizer.vertices(),
@@ -286,28 +2707,23 @@ impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
         );
 
         // Decide which mask and transform to use.
-        let (transform, mask) = {
+        let transform = self.device_transform() * Affine::translate(eye);
+        let (mask, clip_shape, scissor) = {
             let state = self.state.last_mut().unwrap();
-
             let mask = state.mask.texture()?.unwrap_or(&self.source.white_pixel);
-
-            (&state.transform, mask)
+            (mask, state.clip_shape, state.scissor)
         };
 
-        // Decide the texture to use.
-        let texture = texture.unwrap_or(&self.source.white_pixel);
-
         // Draw!
-        self.source
-            .context
-            .push_buffers(
+        self.source.with_clip(clip_shape.as_ref(), scissor, || {
+            self.source.push_buffers(
                 self.source.buffers.vbo.resource(),
-                texture.resource(),
-                mask.resource(),
-                transform,
+                texture.map(|(texture, interp)| (&**texture, interp)),
+                mask,
+                &transform,
                 self.size,
             )
-            .piet_err()?;
+        })?;
 
         // Clear the original buffers.
         self.source.buffers.rasterizer.clear();
@@ -315,6 +2731,109 @@ impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
         Ok(())
     }
 
+    /// Whether a brush can be drawn with geometry that is kept on the GPU.
+    fn draws_cached(&self, brush: &Brush<C>) -> bool {
+        self.recording.is_none()
+            && brush.custom_brush().is_none()
+            && self.source.antialiasing != Antialiasing::Feather
+    }
+
+    /// Draw geometry that is kept on the GPU with a brush.
+    fn push_geometry(&mut self, geometry: &Geometry<C>, brush: &Brush<C>) -> Result<(), Pierror> {
+        let alpha = self.state.last().unwrap().alpha;
+        let vbo = geometry.write(brush, alpha);
+
+        let transform = self.device_transform();
+        let (mask, clip_shape, scissor) = {
+            let state = self.state.last_mut().unwrap();
+            let mask = state.mask.texture()?.unwrap_or(&self.source.white_pixel);
+            (mask, state.clip_shape, state.scissor)
+        };
+
+        self.source.with_clip(clip_shape.as_ref(), scissor, || {
+            self.source.push_buffers(
+                vbo,
+                brush
+                    .texture(self.size)
+                    .map(Image::sampled)
+                    .map(|(texture, interp)| (&**texture, interp)),
+                mask,
+                &transform,
+                self.size,
+            )
+        })
+    }
+
+    /// Record an error that occurred during a drawing operation.
+    fn report(&mut self, operation: Operation, bounds: Option<Rect>, error: Pierror) {
+        // Don't let the list grow without bound if the user never checks it.
+        if self.errors.len() >= MAX_ERRORS {
+            self.errors.pop_front();
+        }
+
+        self.errors
+            .push_back(DrawError::new(operation, bounds, error));
+    }
+
+    /// Get the errors that have occurred since the last call to `status` or `take_errors`.
+    ///
+    /// Unlike `status`, which only returns the most recent error, this includes every
+    /// error along with the operation that caused it and the bounds of the shape involved,
+    /// from oldest to newest.
+    pub fn errors(&self) -> impl Iterator<Item = &DrawError> + '_ {
+        self.errors.iter()
+    }
+
+    /// Take the errors that have occurred since the last call to `status` or `take_errors`.
+    ///
+    /// This clears the list of errors, so a subsequent call to `status` will return `Ok`.
+    pub fn take_errors(&mut self) -> Vec<DrawError> {
+        mem::take(&mut self.errors).into()
+    }
+
+    /// Get the opacity that everything drawn is multiplied by.
+    pub fn global_alpha(&self) -> f64 {
+        self.state.last().unwrap().alpha
+    }
+
+    /// Set the opacity that everything drawn is multiplied by.
+    ///
+    /// This is saved and restored along with the transform, and is multiplied into the
+    /// opacity of any brush used. It is clamped between 0 and 1, and NaN is treated as 0.
+    pub fn set_global_alpha(&mut self, alpha: f64) {
+        let alpha = if alpha.is_nan() { 0.0 } else { alpha };
+        self.state.last_mut().unwrap().alpha = alpha.clamp(0.0, 1.0);
+    }
+
+    /// Get the tolerance that curves are flattened with, in pixels of the target.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Set the tolerance that curves are flattened with, in pixels of the target.
+    ///
+    /// This is the largest distance between a curve and the straight segments it is drawn
+    /// with. It is scaled by the current transform, so zoomed in shapes are tessellated more
+    /// finely. Lower it for precision-sensitive drawings such as CAD, at the cost of more
+    /// triangles.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance.max(MIN_TOLERANCE);
+    }
+
+    /// Whether stroke widths are measured in device pixels instead of user space.
+    pub fn non_scaling_stroke(&self) -> bool {
+        self.state.last().unwrap().non_scaling_stroke
+    }
+
+    /// Set whether stroke widths are measured in device pixels instead of user space.
+    ///
+    /// When enabled, strokes are tessellated from the transformed path, so a hairline stays
+    /// one pixel wide however far a map or diagram is zoomed. Dash lengths are measured in
+    /// device pixels as well. This is saved and restored along with the transform.
+    pub fn set_non_scaling_stroke(&mut self, enabled: bool) {
+        self.state.last_mut().unwrap().non_scaling_stroke = enabled;
+    }
+
     /// Get the source of this render context.
     pub fn source(&self) -> &Source<C> {
         self.source
@@ -326,22 +2845,35 @@ impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
     }
 }
 
-macro_rules! leap {
-    ($self:expr, $e:expr) => {{
-        match $e {
-            Ok(v) => v,
-            Err(e) => {
-                $self.status = Err(Pierror::BackendError(e.into()));
-                return;
+impl<C: GpuContext + ?Sized> Drop for RenderContext<'_, C> {
+    fn drop(&mut self) {
+        self.source.occlusion.finish_target();
+
+        // Keep the masks around for the next frame, and leave the stencil buffer clear.
+        let mut states = mem::take(&mut self.state);
+        for state in states.iter_mut().rev() {
+            state.mask.recycle(&mut self.source.masks);
+            if let Err(e) = self.pop_stencil_clips(state) {
+                warn!("failed to pop stencil clips: {}", e);
+            }
+
+            // Layers that were never restored are dropped without being composited.
+            if let Some(layer) = state.layer.take() {
+                if let Err(e) = self.source.bind_render_target(layer.previous) {
+                    warn!("failed to unbind layer: {}", e);
+                }
+                self.source.layer_targets.push(layer.target);
             }
         }
-    }};
-    ($self:expr, $e:expr, $err:expr) => {{
+    }
+}
+
+macro_rules! leap {
+    ($self:expr, $op:expr, $bounds:expr, $e:expr) => {{
         match $e {
             Ok(v) => v,
             Err(e) => {
-                let err = $err;
-                $self.status = Err(err.into());
+                $self.report($op, $bounds, e);
                 return;
             }
         }
@@ -355,7 +2887,13 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
     type Image = Image<C>;
 
     fn status(&mut self) -> Result<(), Pierror> {
-        mem::replace(&mut self.status, Ok(()))
+        let last = self.errors.pop_back();
+        self.errors.clear();
+
+        match last {
+            Some(err) => Err(err.into_error()),
+            None => Ok(()),
+        }
     }
 
     fn solid_brush(&mut self, color: piet::Color) -> Self::Brush {
@@ -363,45 +2901,84 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
     }
 
     fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Pierror> {
-        match gradient.into() {
-            FixedGradient::Linear(linear) => Brush::linear_gradient(&self.source.context, linear),
-            FixedGradient::Radial(radial) => Brush::radial_gradient(&self.source.context, radial),
-        }
+        self.gradient_with_spread(gradient, SpreadMode::Pad)
     }
 
     fn clear(&mut self, region: impl Into<Option<Rect>>, color: piet::Color) {
         let region = region.into();
 
-        // Use optimized clear if possible.
-        if region.is_none() && self.state.last().unwrap().mask.is_empty() {
-            self.source.context.clear(color);
+        // A recorded scene has no target to clear.
+        if region.is_none() && self.recording.is_some() {
+            self.report(Operation::Clear, None, Pierror::NotSupported);
             return;
         }
 
-        // Otherwise, fall back to filling in the screen rectangle.
+        // Clearing ignores the clip and the transform, so the whole target can be cleared.
+        let region = match region {
+            Some(region) => region,
+            None => {
+                self.source.context.clear(color);
+                self.cover_target(Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64));
+                return;
+            }
+        };
+
+        if color.as_rgba8().3 == 0xFF {
+            self.cover_target(region - self.origin);
+        }
+
+        // Replace the pixels in the region, so that clearing to transparent works on
+        // offscreen targets.
+        if self.recording.is_none() {
+            let target = Rect::new(0.0, 0.0, self.size.0 as f64, self.size.1 as f64);
+            let area = (region - self.origin).expand().intersect(target);
+            if area.is_empty() {
+                return;
+            }
+
+            let origin = (area.x0 as u32, area.y0 as u32);
+            let size = (area.width() as u32, area.height() as u32);
+            match self
+                .source
+                .context
+                .clear_rect(origin, size, self.size, color)
+                .piet_err()
+            {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => {
+                    self.report(Operation::Clear, Some(region), e);
+                    return;
+                }
+            }
+        }
+
+        // Otherwise, fall back to blending a rectangle over the region, without the transform,
+        // clip and opacity.
+        let state = mem::take(self.state.last_mut().unwrap());
         let result = self.fill_rects(
             {
                 let uv_white = Point::new(UV_WHITE[0] as f64, UV_WHITE[1] as f64);
                 [TessRect {
-                    pos: region.unwrap_or_else(|| {
-                        Rect::from_origin_size((0.0, 0.0), (self.size.0 as f64, self.size.1 as f64))
-                    }),
+                    pos: region,
                     uv: Rect::from_points(uv_white, uv_white),
                     color,
                 }]
             },
             None,
         );
+        *self.state.last_mut().unwrap() = state;
 
-        leap!(self, result);
+        leap!(self, Operation::Clear, Some(region), result);
     }
 
     fn stroke(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>, width: f64) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
+        let bounds = shape.bounding_box();
+        let brush = brush.make_brush(self, || bounds);
         if let Err(e) =
             self.stroke_impl(shape, brush.as_ref(), width, &piet::StrokeStyle::default())
         {
-            self.status = Err(e);
+            self.report(Operation::Stroke, Some(bounds), e);
         }
     }
 
@@ -412,33 +2989,85 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
         width: f64,
         style: &piet::StrokeStyle,
     ) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
+        let bounds = shape.bounding_box();
+        let brush = brush.make_brush(self, || bounds);
         if let Err(e) = self.stroke_impl(shape, brush.as_ref(), width, style) {
-            self.status = Err(e);
+            self.report(Operation::Stroke, Some(bounds), e);
         }
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
+        let bounds = shape.bounding_box();
+        let brush = brush.make_brush(self, || bounds);
         if let Err(e) = self.fill_impl(shape, brush.as_ref(), FillRule::NonZero) {
-            self.status = Err(e);
+            self.report(Operation::Fill, Some(bounds), e);
         }
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl piet::IntoBrush<Self>) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
+        let bounds = shape.bounding_box();
+        let brush = brush.make_brush(self, || bounds);
         if let Err(e) = self.fill_impl(shape, brush.as_ref(), FillRule::EvenOdd) {
-            self.status = Err(e);
+            self.report(Operation::Fill, Some(bounds), e);
         }
     }
 
     fn clip(&mut self, shape: impl Shape) {
+        let bounds = shape.bounding_box();
+
+        // Clips are applied when a scene is drawn, not when it is recorded.
+        if self.recording.is_some() {
+            self.report(Operation::Clip, Some(bounds), Pierror::NotSupported);
+            return;
+        }
+
+        let transform = self.device_transform();
+        let state = self.state.last_mut().unwrap();
+
+        // Rectangles on pixel edges are clipped to by the backend, without any coverage.
+        if let Some(rect) = mask::scissor_rect(&shape, transform, self.size) {
+            let rect = match state.scissor {
+                Some(scissor) => intersect_scissors(scissor, rect),
+                None => rect,
+            };
+            if self.source.context.set_scissor(Some(rect)) {
+                self.source.context.set_scissor(None);
+                state.scissor = Some(rect);
+                return;
+            }
+        }
+
+        // Simple shapes are clipped to in the shader, without touching the mask.
+        if state.clip_shape.is_none() {
+            if let Some(clip) = mask::clip_shape(&shape, transform) {
+                if self.source.context.set_clip_shape(Some(&clip)) {
+                    self.source.context.set_clip_shape(None);
+                    state.clip_shape = Some(clip);
+                    return;
+                }
+            }
+        }
+
+        // Other shapes are clipped with the stencil buffer, if the target has one.
+        if self.stencil
+            && leap!(
+                self,
+                Operation::Clip,
+                Some(bounds),
+                self.stencil_clip(&shape, transform)
+            )
+        {
+            return;
+        }
+
         let state = self.state.last_mut().unwrap();
-        let transform = state.transform;
         leap!(
             self,
+            Operation::Clip,
+            Some(bounds),
             state.mask.clip(
                 &self.source.context,
+                &mut self.source.masks,
                 shape,
                 self.tolerance,
                 transform,
@@ -455,46 +3084,83 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
         struct RestoreAtlas<'a, 'b, G: GpuContext + ?Sized> {
             context: &'a mut RenderContext<'b, G>,
             atlas: Option<Atlas<G>>,
+            staging: Staging,
         }
 
         impl<G: GpuContext + ?Sized> Drop for RestoreAtlas<'_, '_, G> {
             fn drop(&mut self) {
                 self.context.source.atlas = Some(self.atlas.take().unwrap());
+                self.context.source.staging = mem::take(&mut self.staging);
             }
         }
 
         let pos = pos.into();
         let mut restore = RestoreAtlas {
             atlas: self.source.atlas.take(),
+            staging: mem::take(&mut self.source.staging),
             context: self,
         };
 
-        // Iterate over the glyphs and use them to write.
+        // Iterate over the glyphs and use them to write. Every glyph carries its own color in
+        // its vertices, so highlighted text with many color runs is still a single draw call.
         let texture = restore.atlas.as_ref().unwrap().texture().clone();
 
+        let glyph_scale = restore.context.glyph_scale();
+
         let text = restore.context.text().clone();
         let mut line_state = TextProcessingState::new();
+        let mut missing_glyphs = Vec::new();
         let rects = layout
             .buffer()
             .layout_runs()
-            .flat_map(|run| {
-                // Combine the run's glyphs and the layout's y position.
+            .enumerate()
+            .flat_map(|(i, run)| {
+                // Combine the run's glyphs, the layout's y position and the tab stop shift.
                 run.glyphs
                     .iter()
-                    .map(move |glyph| (glyph, run.line_y as f64))
+                    .enumerate()
+                    .map(move |(j, glyph)| (glyph, run.line_y as f64, layout.tab_shift(i, j)))
             })
             .filter_map({
                 let atlas = restore.atlas.as_mut().unwrap();
-                |(glyph, line_y)| {
+                let staging = &mut restore.staging;
+                |(glyph, line_y, tab_shift)| {
+                    let color = match glyph.color_opt {
+                        Some(color) => {
+                            let [r, g, b, a] = [color.r(), color.g(), color.b(), color.a()];
+                            piet::Color::rgba8(r, g, b, a)
+                        }
+                        None => piet::util::DEFAULT_TEXT_COLOR,
+                    };
+
                     // Get the rectangle in texture space representing the glyph.
                     let GlyphData {
                         uv_rect,
                         offset,
                         size,
-                    } = match text.with_font_system_mut(|fs| atlas.uv_rect(glyph, fs)) {
+                    } = match text
+                        .with_font_system_mut(|fs| atlas.uv_rect(glyph, glyph_scale, fs, staging))
+                    {
                         Ok(rect) => rect,
                         Err(e) => {
-                            tracing::trace!("failed to get uv rect: {}", e);
+                            trace!("failed to get uv rect: {}", e);
+
+                            // Keep drawing the rest of the text, with the placeholder in the
+                            // em box of the glyph.
+                            let font_size = f32::from_bits(glyph.cache_key.font_size_bits) as f64;
+                            let x = glyph.x_int as f64 + tab_shift + pos.x;
+                            let baseline = glyph.y_int as f64 + line_y + pos.y;
+                            let placeholder = TessRect {
+                                pos: Rect::new(
+                                    x,
+                                    baseline - font_size * 0.8,
+                                    x + glyph.w as f64,
+                                    baseline + font_size * 0.2,
+                                ),
+                                uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+                                color,
+                            };
+                            missing_glyphs.push((placeholder, e));
                             return None;
                         }
                     };
@@ -502,23 +3168,16 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
                     // Get the rectangle in screen space representing the glyph.
                     let pos_rect = Rect::from_origin_size(
                         (
-                            glyph.x_int as f64 + pos.x + offset.x,
+                            glyph.x_int as f64 + tab_shift + pos.x + offset.x,
                             glyph.y_int as f64 + line_y + pos.y - offset.y,
                         ),
                         size,
                     );
 
-                    let color = match glyph.color_opt {
-                        Some(color) => {
-                            let [r, g, b, a] = [color.r(), color.g(), color.b(), color.a()];
-                            piet::Color::rgba8(r, g, b, a)
-                        }
-                        None => piet::util::DEFAULT_TEXT_COLOR,
-                    };
-
                     // Register the glyph in the atlas.
                     line_state.handle_glyph(
                         glyph,
+                        tab_shift as f32,
                         line_y as f32 - (f32::from_bits(glyph.cache_key.font_size_bits) * 0.9),
                         color,
                         false,
@@ -531,10 +3190,32 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
                     })
                 }
             });
-        let result = restore.context.fill_rects(rects, Some(&texture));
+        let result = restore
+            .context
+            .fill_rects(rects, Some((&texture, InterpolationMode::Bilinear)));
 
         drop(restore);
 
+        // Draw the glyphs that failed to rasterize as the placeholder, and report the first
+        // failure.
+        let mut glyph_error = None;
+        if !missing_glyphs.is_empty() {
+            let placeholder = self.source.placeholder.clone();
+            let rects = missing_glyphs.iter().map(|(rect, _)| rect.clone());
+            let placeholder_result = self.fill_rects(
+                rects,
+                Some((&placeholder, InterpolationMode::NearestNeighbor)),
+            );
+            for (_, e) in &missing_glyphs {
+                self.source.report_missing(MissingResource::Glyph(e));
+            }
+
+            glyph_error = missing_glyphs.into_iter().next().map(|(_, e)| e);
+            if let Err(e) = placeholder_result {
+                glyph_error.get_or_insert(e);
+            }
+        }
+
         let lines_result = {
             let lines = line_state.lines();
             if lines.is_empty() {
@@ -574,17 +3255,31 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
             }
         };
 
-        leap!(self, result);
-        leap!(self, lines_result);
+        let bounds = Some(piet::TextLayout::image_bounds(layout) + pos.to_vec2());
+        if let Some(err) = glyph_error {
+            self.report(Operation::DrawText, bounds, err);
+        }
+        leap!(self, Operation::DrawText, bounds, result);
+        leap!(self, Operation::DrawText, bounds, lines_result);
     }
 
     fn save(&mut self) -> Result<(), Pierror> {
         let current_state = self.state.last().expect("Impossible lack of RenderState");
 
-        // incorrectly only clone the transform, not the mask texture
+        // Stencil clips stay in the stencil buffer, so only the ones pushed after this are
+        // popped when it is restored.
+        let mask = current_state
+            .mask
+            .inherit(&self.source.context, &mut self.source.masks)?;
         let new_state = RenderState {
             transform: current_state.transform,
-            mask: MaskSlot::default(),
+            mask,
+            clip_shape: current_state.clip_shape,
+            scissor: current_state.scissor,
+            stencil_clips: Vec::new(),
+            alpha: current_state.alpha,
+            non_scaling_stroke: current_state.non_scaling_stroke,
+            layer: None,
         };
         self.state.push(new_state);
 
@@ -596,7 +3291,16 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
             return Err(Pierror::StackUnbalance);
         }
 
-        self.state.pop();
+        if let Some(mut state) = self.state.pop() {
+            state.mask.recycle(&mut self.source.masks);
+            let popped = self.pop_stencil_clips(&mut state);
+            let composited = match state.layer.take() {
+                Some(layer) => self.composite_layer(layer),
+                None => Ok(()),
+            };
+            popped?;
+            composited?;
+        }
         Ok(())
     }
 
@@ -604,6 +3308,10 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
         self.source
             .context
             .flush()
+            .map_err(|x| Pierror::BackendError(x.into()))?;
+        self.source
+            .context
+            .present()
             .map_err(|x| Pierror::BackendError(x.into()))
     }
 
@@ -619,16 +3327,7 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
         buf: &[u8],
         format: piet::ImageFormat,
     ) -> Result<Self::Image, Pierror> {
-        let tex = Texture::new(
-            &self.source.context,
-            InterpolationMode::Bilinear,
-            RepeatStrategy::Color(piet::Color::TRANSPARENT),
-        )
-        .piet_err()?;
-
-        tex.write_texture((width as u32, height as u32), format, Some(buf));
-
-        Ok(Image::new(tex, Size::new(width as f64, height as f64)))
+        self.make_image_impl(width, height, buf, format, true)
     }
 
     fn draw_image(
@@ -647,48 +3346,29 @@ impl<C: GpuContext + ?Sized> piet::RenderContext for RenderContext<'_, C> {
         dst_rect: impl Into<Rect>,
         interp: piet::InterpolationMode,
     ) {
-        // Create a rectangle for the destination and a rectangle for UV.
         let pos_rect = dst_rect.into();
-        let uv_rect = {
-            let scale_x = 1.0 / image.size().width;
-            let scale_y = 1.0 / image.size().height;
-
-            let src_rect = src_rect.into();
-            Rect::new(
-                src_rect.x0 * scale_x,
-                src_rect.y0 * scale_y,
-                src_rect.x1 * scale_x,
-                src_rect.y1 * scale_y,
-            )
-        };
-
-        // Set the interpolation mode.
-        image.texture().set_interpolation(interp);
-
-        // Use this to draw the image.
-        if let Err(e) = self.fill_rects(
-            [TessRect {
-                pos: pos_rect,
-                uv: uv_rect,
-                color: piet::Color::WHITE,
-            }],
-            Some(image.texture()),
-        ) {
-            self.status = Err(e);
+        if let Err(e) = self.draw_image_impl(image, src_rect.into(), pos_rect, interp, None) {
+            self.report(Operation::DrawImage, Some(pos_rect), e);
         }
     }
 
-    fn capture_image_area(&mut self, _src_rect: impl Into<Rect>) -> Result<Self::Image, Pierror> {
-        Err(Pierror::Unimplemented)
+    fn capture_image_area(&mut self, src_rect: impl Into<Rect>) -> Result<Self::Image, Pierror> {
+        // The pixels were already read back, so there is no point in holding them back.
+        let ((width, height), data) = self.read_area(src_rect.into())?;
+        self.make_image_impl(
+            width as usize,
+            height as usize,
+            &data,
+            piet::ImageFormat::RgbaPremul,
+            false,
+        )
     }
 
-    fn blurred_rect(
-        &mut self,
-        _rect: Rect,
-        _blur_radius: f64,
-        _brush: &impl piet::IntoBrush<Self>,
-    ) {
-        self.status = Err(Pierror::NotSupported);
+    fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl piet::IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || rect);
+        if let Err(e) = self.blurred_rect_impl(rect, 0.0, blur_radius, brush.as_ref()) {
+            self.report(Operation::BlurredRect, Some(rect), e);
+        }
     }
 
     fn current_transform(&self) -> Affine {
@@ -719,6 +3399,7 @@ impl TextProcessingState {
     fn handle_glyph(
         &mut self,
         glyph: &LayoutGlyph,
+        x_shift: f32,
         line_y: f32,
         color: piet::Color,
         is_bold: bool,
@@ -729,7 +3410,7 @@ impl TextProcessingState {
             line_y,
             font_size: f32::from_bits(glyph.cache_key.font_size_bits),
             width: glyph.w,
-            x: glyph.x,
+            x: glyph.x + x_shift,
             style: line_straddler::GlyphStyle {
                 bold: is_bold,
                 color: match glyph.color_opt {
@@ -778,6 +3459,114 @@ impl TextProcessingState {
     }
 }
 
+/// Get the pixels that are inside of both scissor rectangles.
+fn intersect_scissors(a: [u32; 4], b: [u32; 4]) -> [u32; 4] {
+    let [x0, y0] = [a[0].max(b[0]), a[1].max(b[1])];
+    [x0, y0, a[2].min(b[2]).max(x0), a[3].min(b[3]).max(y0)]
+}
+
+/// Premultiply a buffer of RGBA pixels in place.
+fn premultiply(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Un-premultiply a buffer of RGBA pixels in place.
+fn unpremultiply(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        if alpha == 0 {
+            continue;
+        }
+
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u16 * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+}
+
+/// Move the edges of `src_rect` that are inside of an image of `size` half a texel inwards.
+///
+/// Edges on the border of the image are left alone, since the texture's repeat strategy
+/// decides what is sampled there. Areas thinner than a texel collapse to their center.
+fn inset_half_texel(src_rect: Rect, size: Size) -> Rect {
+    let inset = |start: f64, end: f64, len: f64| {
+        if start > end {
+            return (start, end);
+        }
+
+        let start_inset = if start > 0.0 { start + 0.5 } else { start };
+        let end_inset = if end < len { end - 0.5 } else { end };
+        if start_inset <= end_inset {
+            (start_inset, end_inset)
+        } else {
+            let center = (start + end) / 2.0;
+            (center, center)
+        }
+    };
+
+    let (x0, x1) = inset(src_rect.x0, src_rect.x1, size.width);
+    let (y0, y1) = inset(src_rect.y0, src_rect.y1, size.height);
+    Rect::new(x0, y0, x1, y1)
+}
+
+/// Halve the size of a buffer of premultiplied RGBA pixels by averaging 2x2 blocks.
+///
+/// `width` and `height` must be even.
+fn halve(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut halved = Vec::with_capacity(width * height);
+
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            let texel =
+                |dx: usize, dy: usize, c: usize| data[((y + dy) * width + x + dx) * 4 + c] as u16;
+
+            for c in 0..4 {
+                let sum = texel(0, 0, c) + texel(1, 0, c) + texel(0, 1, c) + texel(1, 1, c);
+                halved.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+
+    halved
+}
+
+/// Convert a buffer of premultiplied RGBA pixels to another format.
+fn convert_premultiplied(mut data: Vec<u8>, format: piet::ImageFormat) -> Result<Vec<u8>, Pierror> {
+    match format {
+        piet::ImageFormat::RgbaPremul => Ok(data),
+        piet::ImageFormat::RgbaSeparate => {
+            unpremultiply(&mut data);
+            Ok(data)
+        }
+        piet::ImageFormat::Rgb => {
+            unpremultiply(&mut data);
+            Ok(data
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect())
+        }
+        piet::ImageFormat::Grayscale => {
+            unpremultiply(&mut data);
+            Ok(data
+                .chunks_exact(4)
+                .map(|pixel| {
+                    let luma = 0.2126 * pixel[0] as f32
+                        + 0.7152 * pixel[1] as f32
+                        + 0.0722 * pixel[2] as f32;
+                    luma.round() as u8
+                })
+                .collect())
+        }
+        _ => Err(Pierror::NotSupported),
+    }
+}
+
 trait ResultExt<T, E: StdError + 'static> {
     fn piet_err(self) -> Result<T, Pierror>;
 }
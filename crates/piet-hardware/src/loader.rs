@@ -0,0 +1,377 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding images on a background thread.
+
+use super::gpu_backend::GpuContext;
+use super::image::Image;
+use super::{premultiply, RenderContext};
+
+use piet::{Error as Pierror, RenderContext as _};
+
+use image::imageops;
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// Decodes images on a worker thread.
+///
+/// Images are decoded and converted to premultiplied RGBA, which is the format that
+/// `piet-hardware` uploads textures in, so the render thread only has to upload them. The
+/// worker thread exits once the loader and all of its pending jobs are dropped.
+#[derive(Debug)]
+pub struct ImageLoader {
+    /// The channel used to send jobs to the worker thread.
+    jobs: Sender<Job>,
+}
+
+/// A job sent to the worker thread.
+struct Job {
+    /// Where to get the encoded image from.
+    source: JobSource,
+
+    /// The channel to send the result back on.
+    result: Sender<Result<DecodedImage, LoadError>>,
+}
+
+/// Where to get an encoded image from.
+enum JobSource {
+    /// Read the image from a file.
+    Path(PathBuf),
+
+    /// Decode the image from memory.
+    Bytes(Vec<u8>),
+}
+
+impl ImageLoader {
+    /// Create a new image loader, spawning its worker thread.
+    pub fn new() -> Result<Self, Pierror> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+
+        thread::Builder::new()
+            .name("piet-hardware image loader".into())
+            .spawn(move || {
+                for job in receiver {
+                    // The handle may have been dropped, in which case nobody cares.
+                    job.result.send(job.source.decode()).ok();
+                }
+            })
+            .map_err(|err| Pierror::BackendError(err.into()))?;
+
+        Ok(Self { jobs })
+    }
+
+    /// Start loading an image from a file.
+    pub fn load_file(&self, path: impl Into<PathBuf>) -> LoadHandle {
+        self.submit(JobSource::Path(path.into()))
+    }
+
+    /// Start decoding an image from its encoded bytes.
+    pub fn load_bytes(&self, bytes: impl Into<Vec<u8>>) -> LoadHandle {
+        self.submit(JobSource::Bytes(bytes.into()))
+    }
+
+    fn submit(&self, source: JobSource) -> LoadHandle {
+        let (result, receiver) = mpsc::channel();
+        let job = Job { source, result };
+
+        if let Err(mpsc::SendError(job)) = self.jobs.send(job) {
+            job.result
+                .send(Err(LoadError("the image loader thread has stopped".into())))
+                .ok();
+        }
+
+        LoadHandle {
+            receiver,
+            done: false,
+        }
+    }
+}
+
+impl JobSource {
+    /// Decode the image into premultiplied RGBA.
+    fn decode(self) -> Result<DecodedImage, LoadError> {
+        match self {
+            Self::Path(path) => {
+                let bytes = fs::read(path).map_err(|err| LoadError(err.into()))?;
+                DecodedImage::decode(&bytes)
+            }
+            Self::Bytes(bytes) => DecodedImage::decode(&bytes),
+        }
+    }
+}
+
+/// A handle to an image that is being loaded by an [`ImageLoader`].
+#[derive(Debug)]
+pub struct LoadHandle {
+    /// The channel the result is sent on.
+    receiver: Receiver<Result<DecodedImage, LoadError>>,
+
+    /// Whether the result has already been taken.
+    done: bool,
+}
+
+impl LoadHandle {
+    /// Take the decoded image if it is ready, without blocking.
+    ///
+    /// Returns `None` while the image is still being decoded, and after the result has been
+    /// taken.
+    pub fn try_take(&mut self) -> Option<Result<DecodedImage, LoadError>> {
+        if self.done {
+            return None;
+        }
+
+        let result = match self.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => {
+                Err(LoadError("the image loader thread has stopped".into()))
+            }
+        };
+
+        self.done = true;
+        Some(result)
+    }
+
+    /// Block until the image is decoded and take it.
+    ///
+    /// Returns `None` if the result has already been taken.
+    pub fn wait(self) -> Option<Result<DecodedImage, LoadError>> {
+        if self.done {
+            return None;
+        }
+
+        Some(
+            self.receiver
+                .recv()
+                .unwrap_or_else(|_| Err(LoadError("the image loader thread has stopped".into()))),
+        )
+    }
+}
+
+/// An image that has been decoded and is ready to be uploaded.
+#[derive(Clone)]
+pub struct DecodedImage {
+    /// The width of the image.
+    width: u32,
+
+    /// The height of the image.
+    height: u32,
+
+    /// The pixels of the image, in premultiplied RGBA.
+    data: Vec<u8>,
+}
+
+impl fmt::Debug for DecodedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecodedImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DecodedImage {
+    /// Decode a PNG, JPEG, WebP, GIF or BMP image on the current thread.
+    ///
+    /// The image is turned upright if it has an EXIF orientation, like photos taken with a
+    /// phone often do.
+    pub fn decode(bytes: &[u8]) -> Result<Self, LoadError> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|err| LoadError(err.into()))?
+            .into_rgba8();
+        let image = match exif_orientation(bytes).unwrap_or(1) {
+            2 => imageops::flip_horizontal(&image),
+            3 => imageops::rotate180(&image),
+            4 => imageops::flip_vertical(&image),
+            5 => imageops::flip_horizontal(&imageops::rotate90(&image)),
+            6 => imageops::rotate90(&image),
+            7 => imageops::flip_horizontal(&imageops::rotate270(&image)),
+            8 => imageops::rotate270(&image),
+            _ => image,
+        };
+
+        let (width, height) = image.dimensions();
+        let mut data = image.into_raw();
+        premultiply(&mut data);
+
+        Ok(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// The size of the image, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The pixels of the image, in premultiplied RGBA.
+    pub fn pixels(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Upload the image to the GPU.
+    pub fn upload<C: GpuContext + ?Sized>(
+        &self,
+        context: &mut RenderContext<'_, C>,
+    ) -> Result<Image<C>, Pierror> {
+        context.make_image(
+            self.width as usize,
+            self.height as usize,
+            &self.data,
+            piet::ImageFormat::RgbaPremul,
+        )
+    }
+}
+
+/// An error that occurred while loading an image.
+#[derive(Debug)]
+pub struct LoadError(Box<dyn StdError + Send + Sync + 'static>);
+
+impl LoadError {
+    /// Get the underlying error.
+    pub fn into_inner(self) -> Box<dyn StdError + Send + Sync + 'static> {
+        self.0
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load image: {}", self.0)
+    }
+}
+
+impl StdError for LoadError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+impl From<LoadError> for Pierror {
+    fn from(err: LoadError) -> Self {
+        Pierror::BackendError(Box::new(err))
+    }
+}
+
+impl<C: GpuContext + ?Sized> RenderContext<'_, C> {
+    /// Decode an image and upload it.
+    ///
+    /// This blocks while the image is decoded; use an [`ImageLoader`] to decode large images
+    /// in the background. See [`DecodedImage::decode`] for the supported formats.
+    pub fn make_image_from_encoded(&mut self, bytes: &[u8]) -> Result<Image<C>, Pierror> {
+        DecodedImage::decode(bytes)?.upload(self)
+    }
+}
+
+/// Find the EXIF orientation of a JPEG, PNG or WebP image.
+///
+/// This is a number from 1 to 8, where 1 is upright. See the TIFF specification for the rest.
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    tiff_orientation(exif_block(bytes)?)
+}
+
+/// Find the TIFF structure that holds the EXIF data of an image.
+fn exif_block(bytes: &[u8]) -> Option<&[u8]> {
+    const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+    if let Some(mut rest) = bytes.strip_prefix(&[0xFF, 0xD8]) {
+        // JPEG stores it in an APP1 segment, before the start of the scan.
+        while let [0xFF, marker, hi, lo, ..] = *rest {
+            let len = usize::from(u16::from_be_bytes([hi, lo]));
+            let segment = rest.get(4..2 + len)?;
+            match marker {
+                0xE1 if segment.starts_with(EXIF_PREFIX) => {
+                    return Some(&segment[EXIF_PREFIX.len()..])
+                }
+                0xDA => return None,
+                _ => rest = &rest[2 + len..],
+            }
+        }
+    } else if let Some(mut rest) = bytes.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        // PNG stores it in an `eXIf` chunk.
+        while rest.len() >= 8 {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let data = rest.get(8..8usize.checked_add(len)?)?;
+            if &rest[4..8] == b"eXIf" {
+                return Some(data);
+            }
+            rest = rest.get(12 + len..)?;
+        }
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        // WebP stores it in an `EXIF` chunk, sometimes with the JPEG prefix.
+        let mut rest = &bytes[12..];
+        while rest.len() >= 8 {
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let data = rest.get(8..8usize.checked_add(len)?)?;
+            if &rest[..4] == b"EXIF" {
+                return Some(data.strip_prefix(EXIF_PREFIX).unwrap_or(data));
+            }
+            rest = rest.get(8 + len + len % 2..).unwrap_or_default();
+        }
+    }
+
+    None
+}
+
+/// Read the orientation tag from the first directory of a TIFF structure.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    const ORIENTATION: u16 = 0x0112;
+    const SHORT: u16 = 3;
+
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let directory = u32_at(4)? as usize;
+    let entries = u16_at(directory)?;
+    (0..usize::from(entries))
+        .map(|i| directory.saturating_add(2 + i * 12))
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION))
+        .and_then(|entry| match u16_at(entry + 2)? {
+            SHORT => u16_at(entry + 8),
+            _ => None,
+        })
+        .filter(|orientation| (1..=8).contains(orientation))
+}
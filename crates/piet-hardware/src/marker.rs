@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Shapes that are stamped at many points with a fixed size on the target.
+
+use super::gpu_backend::Vertex;
+
+use piet::kurbo::{Point, Rect};
+
+/// A shape that has been tessellated once, to be stamped at many points.
+///
+/// Markers are created with [`RenderContext::fill_marker`] or
+/// [`RenderContext::stroke_marker`] and drawn with [`RenderContext::draw_markers`]. They are
+/// measured in device pixels, so arrowheads and scatter plot points keep their size however
+/// the drawing is transformed.
+///
+/// [`RenderContext::fill_marker`]: crate::RenderContext::fill_marker
+/// [`RenderContext::stroke_marker`]: crate::RenderContext::stroke_marker
+/// [`RenderContext::draw_markers`]: crate::RenderContext::draw_markers
+#[derive(Debug, Clone)]
+pub struct Marker {
+    /// The positions of the vertices, around the marker's origin.
+    points: Vec<Point>,
+
+    /// The indices of the triangles.
+    indices: Vec<u32>,
+
+    /// The bounding box of the marker.
+    bounds: Rect,
+}
+
+impl Marker {
+    /// Create a marker from tessellated triangles.
+    pub(crate) fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let points: Vec<_> = vertices
+            .iter()
+            .map(|vertex| Point::new(vertex.pos[0] as f64, vertex.pos[1] as f64))
+            .collect();
+        let bounds = points
+            .iter()
+            .map(|&point| Rect::from_points(point, point))
+            .reduce(|a, b| a.union(b))
+            .unwrap_or(Rect::ZERO);
+
+        Self {
+            points,
+            indices: indices.to_vec(),
+            bounds,
+        }
+    }
+
+    /// Get the bounding box of the marker, around its origin.
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Whether the marker has no triangles to draw.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Get the positions of the vertices.
+    pub(crate) fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Get the indices of the triangles.
+    pub(crate) fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+/// Where a [`Marker`] is stamped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkerPlacement {
+    /// The point the marker's origin is placed at, in user space.
+    pub position: Point,
+
+    /// The clockwise rotation of the marker in radians, applied on the target.
+    pub rotation: f64,
+}
+
+impl MarkerPlacement {
+    /// Place a marker at a point without rotating it.
+    pub fn new(position: impl Into<Point>) -> Self {
+        Self {
+            position: position.into(),
+            rotation: 0.0,
+        }
+    }
+
+    /// Rotate the marker clockwise by `rotation` radians.
+    pub fn with_rotation(mut self, rotation: f64) -> Self {
+        self.rotation = rotation;
+        self
+    }
+}
+
+impl From<Point> for MarkerPlacement {
+    fn from(position: Point) -> Self {
+        Self::new(position)
+    }
+}
+
+impl From<(Point, f64)> for MarkerPlacement {
+    fn from((position, rotation): (Point, f64)) -> Self {
+        Self::new(position).with_rotation(rotation)
+    }
+}
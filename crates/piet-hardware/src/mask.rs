@@ -21,11 +21,12 @@
 
 //! The mask used for clipping.
 
-use super::gpu_backend::{GpuContext, RepeatStrategy};
+use super::gpu_backend::{ClipShape, GpuContext, RepeatStrategy};
+use super::memory::{Category, MemoryTracker};
 use super::resources::Texture;
-use super::ResultExt;
+use super::{MaskResolution, ResultExt};
 
-use piet::kurbo::{Affine, PathEl, Shape};
+use piet::kurbo::{self, Affine, PathEl, Point, Rect, Shape};
 use piet::{Error as Pierror, InterpolationMode};
 
 use std::mem;
@@ -33,6 +34,87 @@ use std::rc::Rc;
 
 use tiny_skia::{ClipMask, FillRule, PathBuilder, Pixmap};
 
+/// The maximum number of unused masks to keep around.
+const MAX_POOLED_MASKS: usize = 8;
+
+/// How far the edges of a rectangle can be from the edges of pixels to be scissored to.
+const PIXEL_EDGE_TOLERANCE: f64 = 1.0e-3;
+
+/// A pool of masks that are not currently in use.
+///
+/// Masks are the size of the target they are drawn to, so when one `Source` renders to
+/// several targets, the pool keeps masks of each size around for reuse.
+pub(crate) struct MaskPool<C: GpuContext + ?Sized> {
+    /// The unused masks, from least to most recently used.
+    masks: Vec<Mask<C>>,
+
+    /// The resolution that masks are rasterized at.
+    resolution: MaskResolution,
+
+    /// Whether to combine clips to rectangles on the CPU before rasterizing them.
+    combine: bool,
+
+    /// The tracker that the memory of masks is counted in.
+    memory: Rc<MemoryTracker>,
+}
+
+impl<C: GpuContext + ?Sized> MaskPool<C> {
+    /// Create a new, empty pool for masks of the given resolution.
+    ///
+    /// If `combine` is set, clips to rectangles are combined on the CPU.
+    pub(crate) fn new(
+        resolution: MaskResolution,
+        combine: bool,
+        memory: Rc<MemoryTracker>,
+    ) -> Self {
+        Self {
+            masks: Vec::new(),
+            resolution,
+            combine,
+            memory,
+        }
+    }
+
+    /// Get the size of the masks used for a target of the given size.
+    pub(crate) fn mask_size(&self, (target_width, target_height): (u32, u32)) -> (u32, u32) {
+        let divisor = self.resolution.divisor();
+        (
+            (target_width + divisor - 1) / divisor,
+            (target_height + divisor - 1) / divisor,
+        )
+    }
+
+    /// Drop every pooled mask that doesn't fit a target of the given size.
+    pub(crate) fn retain_target(&mut self, target_size: (u32, u32)) {
+        let (width, height) = self.mask_size(target_size);
+        self.masks
+            .retain(|mask| mask.pixmap.width() == width && mask.pixmap.height() == height);
+    }
+
+    /// Take a mask of the given size out of the pool.
+    fn take(&mut self, width: u32, height: u32) -> Option<Mask<C>> {
+        let index = self
+            .masks
+            .iter()
+            .rposition(|mask| mask.pixmap.width() == width && mask.pixmap.height() == height)?;
+        Some(self.masks.remove(index))
+    }
+
+    /// Put a mask back into the pool.
+    fn give(&mut self, mask: Mask<C>) {
+        if self.masks.len() >= MAX_POOLED_MASKS {
+            self.masks.remove(0);
+        }
+
+        self.masks.push(mask);
+    }
+
+    /// Drop every pooled mask.
+    pub(crate) fn clear(&mut self) {
+        self.masks.clear();
+    }
+}
+
 /// A wrapper around an `Option<Mask>` that supports being easily drawn into.
 pub(crate) struct MaskSlot<C: GpuContext + ?Sized> {
     /// The slot containing the mask.
@@ -67,11 +149,37 @@ impl<C: GpuContext + ?Sized> MaskSlot<C> {
         Self::default()
     }
 
-    /// Is this mask empty?
+    /// Whether nothing has been drawn into this slot, so that it doesn't clip anything.
     pub(crate) fn is_empty(&self) -> bool {
-        match &self.slot {
-            MaskSlotState::Empty(_) => true,
-            MaskSlotState::Mask(_) => false,
+        matches!(self.slot, MaskSlotState::Empty(_))
+    }
+
+    /// Create a slot that clips to the same area as this one.
+    ///
+    /// Clipping the new slot further leaves this one untouched.
+    pub(crate) fn inherit(&self, context: &Rc<C>, pool: &mut MaskPool<C>) -> Result<Self, Pierror> {
+        let parent = match self.slot {
+            MaskSlotState::Mask(ref mask) => mask,
+            MaskSlotState::Empty(_) => return Ok(Self::new()),
+        };
+
+        let (width, height) = (parent.pixmap.width(), parent.pixmap.height());
+        let mut mask = Mask::new(context, pool, width, height, None)?;
+        mask.mask.clone_from(&parent.mask);
+        mask.rect = parent.rect;
+        mask.dirty = true;
+
+        Ok(Self {
+            slot: MaskSlotState::Mask(mask),
+            path_builder: PathBuilder::new(),
+        })
+    }
+
+    /// Return this slot's mask to the pool, leaving the slot empty.
+    pub(crate) fn recycle(&mut self, pool: &mut MaskPool<C>) {
+        if let MaskSlotState::Mask(mask) = mem::replace(&mut self.slot, MaskSlotState::Empty(None))
+        {
+            pool.give(mask);
         }
     }
 
@@ -79,52 +187,87 @@ impl<C: GpuContext + ?Sized> MaskSlot<C> {
     pub(crate) fn clip(
         &mut self,
         context: &Rc<C>,
+        pool: &mut MaskPool<C>,
         shape: impl Shape,
         tolerance: f64,
         transform: Affine,
-        (width, height): (u32, u32),
+        (target_width, target_height): (u32, u32),
     ) -> Result<(), Pierror> {
-        // TODO: There has to be a better way of doing this.
-        let path = {
-            let path = shape.into_path(tolerance);
-            let transformed = transform * path;
-
-            let mut builder = mem::take(&mut self.path_builder);
-            shape_to_skia_path(&mut builder, transformed, tolerance);
-            builder.finish().expect("path builder failed")
+        // Nothing is drawn to an empty target, so there is nothing to clip.
+        if target_width == 0 || target_height == 0 {
+            return Ok(());
+        }
+
+        // Scale the mask down to the requested resolution.
+        let (width, height) = pool.mask_size((target_width, target_height));
+        let transform = Affine::scale_non_uniform(
+            width as f64 / target_width as f64,
+            height as f64 / target_height as f64,
+        ) * transform;
+
+        // Clips to axis-aligned rectangles are combined without rasterizing anything.
+        let rect = if pool.combine {
+            shape
+                .as_rect()
+                .and_then(|rect| transform_axis_aligned(transform, rect))
+        } else {
+            None
         };
 
-        match self.slot {
-            MaskSlotState::Mask(ref mut mask) => {
-                // Intersect the new path with the existing mask.
-                mask.mask.intersect_path(&path, FillRule::EvenOdd, false);
-                mask.dirty = true;
-            }
+        // Create a mask if there isn't already one.
+        if let MaskSlotState::Empty(ref mut texture) = self.slot {
+            let mut mask = Mask::new(context, pool, width, height, texture.take())?;
+            mask.rect = Some(Rect::new(0.0, 0.0, width as f64, height as f64));
+            self.slot = MaskSlotState::Mask(mask);
+        }
 
-            MaskSlotState::Empty(ref mut texture) => {
-                // Create a mask if there isn't already one.
-                let texture = match texture.take() {
-                    Some(texture) => texture,
-                    None => Texture::new(
-                        context,
-                        InterpolationMode::Bilinear,
-                        RepeatStrategy::Color(piet::Color::TRANSPARENT),
-                    )
-                    .piet_err()?,
-                };
+        let mask = match self.slot {
+            MaskSlotState::Mask(ref mut mask) => mask,
+            MaskSlotState::Empty(_) => unreachable!(),
+        };
+        mask.dirty = true;
 
-                let mut mask = Mask {
-                    texture,
-                    pixmap: Pixmap::new(width, height).unwrap(),
-                    mask: ClipMask::new(),
-                    dirty: true,
-                };
+        match (mask.rect, rect) {
+            (Some(current), Some(rect)) => {
+                // Both are rectangles, so just intersect them.
+                mask.rect = Some(current.intersect(rect));
+                return Ok(());
+            }
 
-                mask.mask
-                    .set_path(width, height, &path, FillRule::EvenOdd, false)
-                    .ok_or_else(|| Pierror::BackendError("Failed to set clipping path".into()))?;
+            (current, rect) => {
+                // Clip the path to the current rectangle, so that a mask that is only a
+                // rectangle doesn't need to be rasterized before the path is. Paths on top of
+                // a rasterized mask are rasterized again and intersected with it. This also
+                // keeps huge coordinates, which `tiny-skia` can overflow on, out of the path.
+                let bounds = current.unwrap_or(Rect::new(0.0, 0.0, width as f64, height as f64));
+                let clipped = match rect {
+                    Some(rect) => clip_to_rect(rect.path_elements(0.1), bounds, tolerance)?,
+                    None => clip_to_rect(
+                        shape.path_elements(tolerance).map(|el| transform * el),
+                        bounds,
+                        tolerance,
+                    )?,
+                };
 
-                self.slot = MaskSlotState::Mask(mask);
+                // This is `None` if the path is empty, which clips out everything.
+                let mut builder = mem::take(&mut self.path_builder);
+                elements_to_skia_path(&mut builder, clipped.into_iter())?;
+                let path = builder.finish();
+
+                // This fails if the path doesn't cover any pixels.
+                let filled = path.and_then(|path| {
+                    if current.is_some() {
+                        mask.mask
+                            .set_path(width, height, &path, FillRule::Winding, false)
+                    } else {
+                        mask.mask.intersect_path(&path, FillRule::Winding, false)
+                    }
+                });
+                if filled.is_none() {
+                    clip_out_everything(&mut mask.mask, width, height);
+                }
+
+                mask.rect = None;
             }
         }
 
@@ -149,14 +292,54 @@ struct Mask<C: GpuContext + ?Sized> {
     /// The pixmap we use as scratch space for drawing.
     pixmap: tiny_skia::Pixmap,
 
+    /// The alpha channel of the pixmap, which is all that the texture stores.
+    coverage: Vec<u8>,
+
     /// The clipping mask we use to calculate the mask.
     mask: tiny_skia::ClipMask,
 
+    /// If this is set, the mask is this rectangle and `mask` is not used.
+    rect: Option<Rect>,
+
     /// Whether the mask contains data that needs to be uploaded to the texture.
     dirty: bool,
 }
 
 impl<C: GpuContext + ?Sized> Mask<C> {
+    /// Take a mask of the given size from the pool, or create one around `texture`.
+    fn new(
+        context: &Rc<C>,
+        pool: &mut MaskPool<C>,
+        width: u32,
+        height: u32,
+        texture: Option<Texture<C>>,
+    ) -> Result<Self, Pierror> {
+        // Reuse a mask from the pool if there is one.
+        if let Some(mask) = pool.take(width, height) {
+            return Ok(mask);
+        }
+
+        let texture = match texture {
+            Some(texture) => texture,
+            None => Texture::new(
+                context,
+                InterpolationMode::Bilinear,
+                RepeatStrategy::Color(piet::Color::TRANSPARENT),
+            )
+            .piet_err()?
+            .tracked(&pool.memory, Category::Masks),
+        };
+
+        Ok(Mask {
+            texture,
+            pixmap: Pixmap::new(width, height).unwrap(),
+            coverage: Vec::new(),
+            mask: ClipMask::new(),
+            rect: None,
+            dirty: true,
+        })
+    }
+
     /// Upload the mask to the texture.
     fn upload(&mut self) -> Result<&Texture<C>, Pierror> {
         if self.dirty {
@@ -168,28 +351,49 @@ impl<C: GpuContext + ?Sized> Mask<C> {
                 shader: tiny_skia::Shader::SolidColor(tiny_skia::Color::from_rgba8(
                     0xFF, 0xFF, 0xFF, 0xFF,
                 )),
+                anti_alias: false,
                 ..Default::default()
             };
-            let rect = tiny_skia::Rect::from_xywh(
-                0.0,
-                0.0,
-                self.pixmap.width() as f32,
-                self.pixmap.height() as f32,
-            )
-            .unwrap();
-            self.pixmap.fill_rect(
-                rect,
-                &paint,
-                tiny_skia::Transform::identity(),
-                Some(&self.mask),
-            );
+            match self.rect {
+                Some(rect) => {
+                    // An empty rectangle leaves the pixmap cleared.
+                    let rect = tiny_skia::Rect::from_ltrb(
+                        rect.x0 as f32,
+                        rect.y0 as f32,
+                        rect.x1 as f32,
+                        rect.y1 as f32,
+                    );
+                    if let Some(rect) = rect {
+                        self.pixmap
+                            .fill_rect(rect, &paint, tiny_skia::Transform::identity(), None);
+                    }
+                }
+
+                None => {
+                    let rect = tiny_skia::Rect::from_xywh(
+                        0.0,
+                        0.0,
+                        self.pixmap.width() as f32,
+                        self.pixmap.height() as f32,
+                    )
+                    .unwrap();
+                    self.pixmap.fill_rect(
+                        rect,
+                        &paint,
+                        tiny_skia::Transform::identity(),
+                        Some(&self.mask),
+                    );
+                }
+            }
 
-            // Finally, upload the pixmap to the texture.
-            let data = self.pixmap.data();
+            // Finally, upload the coverage of the pixmap to the texture.
+            self.coverage.clear();
+            self.coverage
+                .extend(self.pixmap.data().chunks_exact(4).map(|pixel| pixel[3]));
             self.texture.write_texture(
                 (self.pixmap.width(), self.pixmap.height()),
-                piet::ImageFormat::RgbaSeparate,
-                Some(data),
+                piet::ImageFormat::Grayscale,
+                Some(&self.coverage),
             );
 
             self.dirty = false;
@@ -199,21 +403,269 @@ impl<C: GpuContext + ?Sized> Mask<C> {
     }
 }
 
-fn shape_to_skia_path(builder: &mut PathBuilder, shape: impl Shape, tolerance: f64) {
-    shape.path_elements(tolerance).for_each(|el| match el {
-        PathEl::MoveTo(pt) => builder.move_to(pt.x as f32, pt.y as f32),
-        PathEl::LineTo(pt) => builder.line_to(pt.x as f32, pt.y as f32),
-        PathEl::QuadTo(p1, p2) => {
-            builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32)
-        }
-        PathEl::CurveTo(p1, p2, p3) => builder.cubic_to(
-            p1.x as f32,
-            p1.y as f32,
-            p2.x as f32,
-            p2.y as f32,
-            p3.x as f32,
-            p3.y as f32,
-        ),
-        PathEl::ClosePath => builder.close(),
+/// Get the shape to clip to in the shader, if `shape` is a rectangle, a rounded rectangle with
+/// the same radius at every corner or a circle, and stays aligned to the axes.
+pub(crate) fn clip_shape(shape: &impl Shape, transform: Affine) -> Option<ClipShape> {
+    let (rect, radius) = if let Some(rect) = shape.as_rect() {
+        (rect, 0.0)
+    } else if let Some(rounded) = shape.as_rounded_rect() {
+        (rounded.rect(), rounded.radii().as_single_radius()?)
+    } else if let Some(circle) = shape.as_circle() {
+        let diameter = circle.radius * 2.0;
+        (
+            Rect::from_center_size(circle.center, (diameter, diameter)),
+            circle.radius.abs(),
+        )
+    } else {
+        return None;
+    };
+
+    // A quarter turn swaps the axes that the radius is scaled along.
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let (scale_x, scale_y) = if b == 0.0 && c == 0.0 {
+        (a.abs(), d.abs())
+    } else if a == 0.0 && d == 0.0 {
+        (c.abs(), b.abs())
+    } else {
+        return None;
+    };
+
+    let rect = transform.transform_rect_bbox(rect);
+    let radii = [
+        (radius * scale_x).min(rect.width() / 2.0),
+        (radius * scale_y).min(rect.height() / 2.0),
+    ];
+    let values = [rect.x0, rect.y0, rect.x1, rect.y1, radii[0], radii[1]];
+    if values.iter().any(|value| !value.is_finite()) {
+        return None;
+    }
+
+    Some(ClipShape::new(
+        [
+            rect.x0 as f32,
+            rect.y0 as f32,
+            rect.x1 as f32,
+            rect.y1 as f32,
+        ],
+        [radii[0] as f32, radii[1] as f32],
+    ))
+}
+
+/// Get the pixels of a target of size `size` to scissor to, if `shape` is a rectangle that
+/// stays aligned to the axes and has its edges between pixels.
+pub(crate) fn scissor_rect(
+    shape: &impl Shape,
+    transform: Affine,
+    size: (u32, u32),
+) -> Option<[u32; 4]> {
+    let rect = transform_axis_aligned(transform, shape.as_rect()?)?;
+
+    // Edges through pixels need the coverage of the clip shape or the mask.
+    let snapped = rect.round();
+    let offsets = [
+        rect.x0 - snapped.x0,
+        rect.y0 - snapped.y0,
+        rect.x1 - snapped.x1,
+        rect.y1 - snapped.y1,
+    ];
+    if !offsets
+        .iter()
+        .all(|offset| offset.abs() < PIXEL_EDGE_TOLERANCE)
+    {
+        return None;
+    }
+
+    let clamp = |value: f64, max: u32| value.max(0.0).min(max as f64) as u32;
+    Some([
+        clamp(snapped.x0, size.0),
+        clamp(snapped.y0, size.1),
+        clamp(snapped.x1, size.0),
+        clamp(snapped.y1, size.1),
+    ])
+}
+
+/// Transform a rectangle, if it stays aligned to the axes.
+fn transform_axis_aligned(transform: Affine, rect: Rect) -> Option<Rect> {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    if (b == 0.0 && c == 0.0) || (a == 0.0 && d == 0.0) {
+        Some(transform.transform_rect_bbox(rect))
+    } else {
+        None
+    }
+}
+
+/// Flatten a path and clip each of its subpaths to a rectangle.
+///
+/// This uses the Sutherland-Hodgman algorithm, which works for any path when clipping to a
+/// convex region like a rectangle. Points inside of the rectangle keep their winding number,
+/// so the result is filled with the same non-zero rule as the original path.
+fn clip_to_rect(
+    elements: impl Iterator<Item = PathEl>,
+    rect: Rect,
+    tolerance: f64,
+) -> Result<Vec<PathEl>, Pierror> {
+    let mut polygons = vec![];
+    let mut current = vec![];
+    let (mut start, mut last) = (Point::ZERO, Point::ZERO);
+
+    for el in elements {
+        let finite = match el {
+            PathEl::MoveTo(p) | PathEl::LineTo(p) => p.is_finite(),
+            PathEl::QuadTo(p1, p2) => p1.is_finite() && p2.is_finite(),
+            PathEl::CurveTo(p1, p2, p3) => p1.is_finite() && p2.is_finite() && p3.is_finite(),
+            PathEl::ClosePath => true,
+        };
+        if !finite {
+            return Err(Pierror::InvalidInput);
+        }
+
+        match el {
+            PathEl::MoveTo(pt) => {
+                polygons.push(mem::take(&mut current));
+                current.push(pt);
+                start = pt;
+                last = pt;
+            }
+            PathEl::LineTo(pt) => {
+                current.push(pt);
+                last = pt;
+            }
+            PathEl::QuadTo(..) | PathEl::CurveTo(..) => {
+                let (end, hull) = match el {
+                    PathEl::QuadTo(p1, p2) => (p2, Rect::from_points(last, p1).union_pt(p2)),
+                    PathEl::CurveTo(p1, p2, p3) => {
+                        (p3, Rect::from_points(last, p1).union_pt(p2).union_pt(p3))
+                    }
+                    _ => unreachable!(),
+                };
+
+                // A curve whose control points are outside of the rectangle can be replaced by
+                // its chord, which is also outside of it.
+                let overlap = hull.intersect(rect);
+                if overlap.width() < 0.0 || overlap.height() < 0.0 {
+                    current.push(end);
+                } else {
+                    // Bound the number of segments for huge curves.
+                    let size = hull.width().max(hull.height());
+                    let tolerance = tolerance.max(size * 1e-6);
+                    kurbo::flatten([PathEl::MoveTo(last), el], tolerance, |el| {
+                        if let PathEl::LineTo(pt) = el {
+                            current.push(pt);
+                        }
+                    });
+                }
+
+                last = end;
+            }
+            PathEl::ClosePath => {
+                // Anything after this starts a new subpath at the same point.
+                polygons.push(mem::take(&mut current));
+                current.push(start);
+                last = start;
+            }
+        }
+    }
+    polygons.push(current);
+
+    let mut clipped = vec![];
+    for polygon in polygons {
+        // Skip polygons that can't cover anything.
+        if polygon.len() < 3 {
+            continue;
+        }
+
+        let polygon = clip_polygon(polygon, rect);
+        let mut points = polygon.into_iter();
+        if let Some(first) = points.next() {
+            clipped.push(PathEl::MoveTo(first));
+            clipped.extend(points.map(PathEl::LineTo));
+            clipped.push(PathEl::ClosePath);
+        }
+    }
+
+    Ok(clipped)
+}
+
+/// Clip a polygon to a rectangle.
+fn clip_polygon(mut polygon: Vec<Point>, rect: Rect) -> Vec<Point> {
+    // Clip against each edge in turn, as (axis, bound, keep points greater than the bound).
+    let edges = [
+        (0, rect.x0, true),
+        (0, rect.x1, false),
+        (1, rect.y0, true),
+        (1, rect.y1, false),
+    ];
+
+    for (axis, bound, greater) in edges {
+        let coord = |pt: Point| if axis == 0 { pt.x } else { pt.y };
+        let inside = |pt: Point| (coord(pt) >= bound) == greater || coord(pt) == bound;
+        let intersect = |a: Point, b: Point| {
+            let t = (bound - coord(a)) / (coord(b) - coord(a));
+            let pt = a.lerp(b, t);
+            if axis == 0 {
+                Point::new(bound, pt.y)
+            } else {
+                Point::new(pt.x, bound)
+            }
+        };
+
+        let input = mem::take(&mut polygon);
+        let mut prev = match input.last() {
+            Some(&prev) => prev,
+            None => break,
+        };
+        for pt in input {
+            if inside(pt) {
+                if !inside(prev) {
+                    polygon.push(intersect(prev, pt));
+                }
+                polygon.push(pt);
+            } else if inside(prev) {
+                polygon.push(intersect(prev, pt));
+            }
+            prev = pt;
+        }
+    }
+
+    polygon
+}
+
+/// Make a clip mask that clips out every pixel.
+fn clip_out_everything(mask: &mut ClipMask, width: u32, height: u32) {
+    // `set_path` zeroes the mask before filling it, so a path outside of the mask leaves it empty.
+    let outside = PathBuilder::from_rect(tiny_skia::Rect::from_xywh(-2.0, -2.0, 1.0, 1.0).unwrap());
+    let _ = mask.set_path(width, height, &outside, FillRule::EvenOdd, false);
+}
+
+/// Convert path elements into a `tiny-skia` path.
+///
+/// Fails if any point isn't finite, since `tiny-skia` can't clip those.
+pub(crate) fn elements_to_skia_path(
+    builder: &mut PathBuilder,
+    mut elements: impl Iterator<Item = PathEl>,
+) -> Result<(), Pierror> {
+    let finite = |pt: &Point| (pt.x as f32).is_finite() && (pt.y as f32).is_finite();
+
+    elements.try_for_each(|el| {
+        match el {
+            PathEl::MoveTo(pt) if finite(&pt) => builder.move_to(pt.x as f32, pt.y as f32),
+            PathEl::LineTo(pt) if finite(&pt) => builder.line_to(pt.x as f32, pt.y as f32),
+            PathEl::QuadTo(p1, p2) if finite(&p1) && finite(&p2) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32)
+            }
+            PathEl::CurveTo(p1, p2, p3) if finite(&p1) && finite(&p2) && finite(&p3) => builder
+                .cubic_to(
+                    p1.x as f32,
+                    p1.y as f32,
+                    p2.x as f32,
+                    p2.y as f32,
+                    p3.x as f32,
+                    p3.y as f32,
+                ),
+            PathEl::ClosePath => builder.close(),
+            _ => return Err(Pierror::InvalidInput),
+        }
+
+        Ok(())
     })
 }
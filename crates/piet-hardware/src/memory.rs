@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Keeping track of how much GPU memory is allocated.
+
+use std::cell::Cell;
+
+/// How many bytes of GPU memory a kind of resource takes up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ResourceMemory {
+    /// The bytes that are allocated right now.
+    pub current: usize,
+
+    /// The most bytes that were allocated at once since the peak was last reset.
+    pub peak: usize,
+}
+
+impl ResourceMemory {
+    /// Change the current usage from `old` bytes to `new` bytes.
+    fn resize(&mut self, old: usize, new: usize) {
+        self.current = self.current.saturating_sub(old).saturating_add(new);
+        self.peak = self.peak.max(self.current);
+    }
+}
+
+/// How much GPU memory the resources of a [`Source`](crate::Source) take up.
+///
+/// This is estimated from the size and format of the data that is uploaded, so backends
+/// that pad textures, keep mip levels or grow buffers ahead of time use somewhat more.
+/// Textures created outside of this crate are assumed to have four bytes per pixel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct MemoryUsage {
+    /// Images made with [`make_image`](piet::RenderContext::make_image) or wrapped with
+    /// [`Source::image_from_raw`](crate::Source::image_from_raw).
+    pub images: ResourceMemory,
+
+    /// The atlas that glyphs are rasterized into.
+    pub glyph_atlases: ResourceMemory,
+
+    /// Clipping masks, including the ones that are pooled for reuse.
+    pub masks: ResourceMemory,
+
+    /// Vertex and index buffers, including the ones of recorded scenes.
+    pub vertex_buffers: ResourceMemory,
+
+    /// Everything else, like gradients, blurred shadows and offscreen render targets.
+    pub other: ResourceMemory,
+
+    /// All of the resources together.
+    pub total: ResourceMemory,
+}
+
+/// A kind of resource whose memory is tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Category {
+    Images,
+    GlyphAtlases,
+    Masks,
+    VertexBuffers,
+    Other,
+}
+
+/// Counts the bytes that the resources of a source take up.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryTracker {
+    /// The usage so far.
+    usage: Cell<MemoryUsage>,
+}
+
+impl MemoryTracker {
+    /// Get the usage so far.
+    pub(crate) fn usage(&self) -> MemoryUsage {
+        self.usage.get()
+    }
+
+    /// Change the size of a resource in `category` from `old` bytes to `new` bytes.
+    pub(crate) fn resize(&self, category: Category, old: usize, new: usize) {
+        let mut usage = self.usage.get();
+        let memory = match category {
+            Category::Images => &mut usage.images,
+            Category::GlyphAtlases => &mut usage.glyph_atlases,
+            Category::Masks => &mut usage.masks,
+            Category::VertexBuffers => &mut usage.vertex_buffers,
+            Category::Other => &mut usage.other,
+        };
+        memory.resize(old, new);
+        usage.total.resize(old, new);
+        self.usage.set(usage);
+    }
+
+    /// Start measuring the peaks again from the current usage.
+    pub(crate) fn reset_peaks(&self) {
+        let mut usage = self.usage.get();
+        for memory in [
+            &mut usage.images,
+            &mut usage.glyph_atlases,
+            &mut usage.masks,
+            &mut usage.vertex_buffers,
+            &mut usage.other,
+            &mut usage.total,
+        ] {
+            memory.peak = memory.current;
+        }
+        self.usage.set(usage);
+    }
+}
@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Finding drawing that was hidden behind opaque drawing in the last frame.
+
+use piet::kurbo::Rect;
+
+use ahash::RandomState;
+use hashbrown::HashMap;
+
+use std::mem;
+
+/// The occlusion queries of the current frame, and the results of the last one.
+pub(crate) struct Occlusion {
+    /// Whether each query was hidden in the last frame.
+    last_frame: HashMap<u64, bool, RandomState>,
+
+    /// Whether each query was hidden in the targets of this frame that were finished.
+    this_frame: HashMap<u64, bool, RandomState>,
+
+    /// The queries on the current target that may still be covered.
+    pending: Vec<Query>,
+}
+
+/// An occlusion query on the current target.
+struct Query {
+    /// The identifier that the application chose for the query.
+    id: u64,
+
+    /// The pixels of the target that must be covered for the query to be hidden.
+    area: Rect,
+
+    /// Whether the area has been covered since the query was made.
+    hidden: bool,
+}
+
+impl Occlusion {
+    /// Create a new set of queries, with maps that use `hasher`.
+    pub(crate) fn new(hasher: RandomState) -> Self {
+        Self {
+            last_frame: HashMap::with_hasher(hasher.clone()),
+            this_frame: HashMap::with_hasher(hasher),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Start a new frame, keeping the results of the one that ended.
+    pub(crate) fn begin_frame(&mut self) {
+        mem::swap(&mut self.last_frame, &mut self.this_frame);
+        self.this_frame.clear();
+        self.pending.clear();
+    }
+
+    /// Whether the query with this identifier was hidden in the last frame.
+    pub(crate) fn was_hidden(&self, id: u64) -> bool {
+        self.last_frame.get(&id).copied().unwrap_or(false)
+    }
+
+    /// Whether any query on the current target can still be covered.
+    pub(crate) fn is_waiting(&self) -> bool {
+        self.pending.iter().any(|query| !query.hidden)
+    }
+
+    /// Make a query for an area of the current target.
+    ///
+    /// An empty area is hidden right away.
+    pub(crate) fn query(&mut self, id: u64, area: Rect) {
+        self.pending.push(Query {
+            id,
+            area,
+            hidden: area.is_empty(),
+        });
+    }
+
+    /// Mark the queries that lie entirely within an opaque area of the target as hidden.
+    pub(crate) fn cover(&mut self, opaque: Rect) {
+        for query in &mut self.pending {
+            let area = query.area;
+            query.hidden |= opaque.x0 <= area.x0
+                && opaque.y0 <= area.y0
+                && opaque.x1 >= area.x1
+                && opaque.y1 >= area.y1;
+        }
+    }
+
+    /// Finish the queries on the current target.
+    ///
+    /// When a frame is drawn in tiles, a query is only hidden if it is hidden in every tile.
+    pub(crate) fn finish_target(&mut self) {
+        for query in self.pending.drain(..) {
+            *self.this_frame.entry(query.id).or_insert(true) &= query.hidden;
+        }
+    }
+}
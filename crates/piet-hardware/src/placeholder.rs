@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! What is drawn in place of resources that aren't available.
+
+use piet::Error as Pierror;
+
+/// The number of squares along each side of a [`Placeholder::Checkerboard`].
+const CHECKERBOARD_SQUARES: u32 = 8;
+
+/// What is drawn instead of an image that is waiting to be uploaded, or a glyph that failed to
+/// rasterize.
+///
+/// Placeholders are multiplied by the tint of the brush or filter that the resource is drawn
+/// with, or by the color of the text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Placeholder {
+    /// A solid color.
+    Color(piet::Color),
+
+    /// A checkerboard of two colors, with eight squares along each side of the resource.
+    Checkerboard(piet::Color, piet::Color),
+}
+
+impl Default for Placeholder {
+    /// A transparent color, which leaves the area blank.
+    fn default() -> Self {
+        Self::Color(piet::Color::TRANSPARENT)
+    }
+}
+
+impl From<piet::Color> for Placeholder {
+    fn from(color: piet::Color) -> Self {
+        Self::Color(color)
+    }
+}
+
+impl Placeholder {
+    /// Get the size and premultiplied RGBA pixels of the texture that is drawn.
+    pub(crate) fn pixels(&self) -> ((u32, u32), Vec<u8>) {
+        let premultiplied = |color: piet::Color| {
+            let (r, g, b, a) = color.as_rgba8();
+            let mut pixel = [r, g, b, a];
+            super::premultiply(&mut pixel);
+            pixel
+        };
+
+        match *self {
+            Self::Color(color) => ((1, 1), premultiplied(color).to_vec()),
+            Self::Checkerboard(first, second) => {
+                let colors = [premultiplied(first), premultiplied(second)];
+                let pixels = (0..CHECKERBOARD_SQUARES)
+                    .flat_map(|y| (0..CHECKERBOARD_SQUARES).map(move |x| (x + y) % 2))
+                    .flat_map(|i| colors[i as usize])
+                    .collect();
+                ((CHECKERBOARD_SQUARES, CHECKERBOARD_SQUARES), pixels)
+            }
+        }
+    }
+}
+
+/// A resource that is drawn as a [`Placeholder`], which is passed to the callback set with
+/// [`Source::on_missing_resource`](crate::Source::on_missing_resource).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MissingResource<'a> {
+    /// An image that is waiting to be uploaded in a later frame.
+    PendingUpload,
+
+    /// A glyph that failed to rasterize.
+    Glyph(&'a Pierror),
+}
+
+/// The callback that is told about resources drawn as the placeholder.
+pub(crate) type MissingCallback = Box<dyn FnMut(MissingResource<'_>)>;
@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! A [`plotters`] drawing backend on top of a [`piet`] render context.
+//!
+//! [`plotters`]: https://crates.io/crates/plotters
+
+use piet::kurbo::{Affine, BezPath, Circle, Line, Point, Rect};
+use piet::{
+    Color, Error as Pierror, FontFamily, FontWeight, ImageFormat, InterpolationMode, Text as _,
+    TextAttribute, TextLayout as _, TextLayoutBuilder as _,
+};
+
+use plotters_backend::text_anchor::{HPos, VPos};
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+    FontStyle, FontTransform,
+};
+
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fmt;
+
+/// A [`DrawingBackend`] that draws plots to a [`piet`] render context.
+///
+/// This works with any [`piet::RenderContext`], including the ones from this crate and the
+/// backends built on it. Shapes and text are drawn as vectors rather than pixel by pixel, so
+/// charts are tessellated and drawn on the GPU.
+pub struct PlottersBackend<'a, R: piet::RenderContext> {
+    /// The render context to draw to.
+    ///
+    /// This is in a `RefCell` so that text can be measured through a shared reference.
+    context: RefCell<&'a mut R>,
+
+    /// The size of the area to draw in.
+    size: (u32, u32),
+}
+
+impl<R: piet::RenderContext> fmt::Debug for PlottersBackend<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlottersBackend")
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, R: piet::RenderContext> PlottersBackend<'a, R> {
+    /// Create a new backend that draws to a `width` by `height` area of a render context.
+    ///
+    /// The area starts at the origin of the context's current transform.
+    pub fn new(context: &'a mut R, width: u32, height: u32) -> Self {
+        Self {
+            context: RefCell::new(context),
+            size: (width, height),
+        }
+    }
+
+    /// Lay out text with the given style.
+    fn layout<S: BackendTextStyle>(
+        &self,
+        text: &str,
+        style: &S,
+    ) -> Result<R::TextLayout, DrawingErrorKind<PlottersError>> {
+        let mut context = self.context.borrow_mut();
+        let factory = context.text();
+
+        let family = match style.family() {
+            plotters_backend::FontFamily::Serif => FontFamily::SERIF,
+            plotters_backend::FontFamily::SansSerif => FontFamily::SANS_SERIF,
+            plotters_backend::FontFamily::Monospace => FontFamily::MONOSPACE,
+            plotters_backend::FontFamily::Name(name) => {
+                factory.font_family(name).unwrap_or(FontFamily::SANS_SERIF)
+            }
+        };
+
+        let mut builder = factory
+            .new_text_layout(text.to_string())
+            .font(family, style.size())
+            .text_color(color(style.color()));
+        builder = match style.style() {
+            FontStyle::Normal => builder,
+            FontStyle::Bold => builder.default_attribute(TextAttribute::Weight(FontWeight::BOLD)),
+            FontStyle::Italic | FontStyle::Oblique => {
+                builder.default_attribute(TextAttribute::Style(piet::FontStyle::Italic))
+            }
+        };
+
+        builder.build().map_err(error)
+    }
+}
+
+impl<R: piet::RenderContext> DrawingBackend for PlottersBackend<'_, R> {
+    type ErrorType = PlottersError;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<PlottersError>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<PlottersError>> {
+        self.context.get_mut().finish().map_err(error)
+    }
+
+    fn draw_pixel(
+        &mut self,
+        (x, y): BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let rect = Rect::new(x as f64, y as f64, x as f64 + 1.0, y as f64 + 1.0);
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(self::color(color));
+        context.fill(rect, &brush);
+        Ok(())
+    }
+
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let line = Line::new(center(from), center(to));
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(color(style.color()));
+        context.stroke(line, &brush, style.stroke_width() as f64);
+        Ok(())
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(color(style.color()));
+
+        if fill {
+            // Filled rectangles include the pixels at both corners.
+            let (x0, y0) = upper_left;
+            let (x1, y1) = bottom_right;
+            let rect = Rect::new(x0 as f64, y0 as f64, x1 as f64 + 1.0, y1 as f64 + 1.0);
+            context.fill(rect, &brush);
+        } else {
+            let rect = Rect::from_points(center(upper_left), center(bottom_right));
+            context.stroke(rect, &brush, style.stroke_width() as f64);
+        }
+
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let mut points = path.into_iter().map(center);
+        let mut bez = BezPath::new();
+        match points.next() {
+            Some(first) => bez.move_to(first),
+            None => return Ok(()),
+        }
+        points.for_each(|pt| bez.line_to(pt));
+
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(color(style.color()));
+        context.stroke(bez, &brush, style.stroke_width() as f64);
+        Ok(())
+    }
+
+    fn draw_circle<S: BackendStyle>(
+        &mut self,
+        center: BackendCoord,
+        radius: u32,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let circle = Circle::new(self::center(center), radius as f64);
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(color(style.color()));
+
+        if fill {
+            context.fill(circle, &brush);
+        } else {
+            context.stroke(circle, &brush, style.stroke_width() as f64);
+        }
+
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vert: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        let mut points = vert.into_iter().map(center);
+        let mut bez = BezPath::new();
+        match points.next() {
+            Some(first) => bez.move_to(first),
+            None => return Ok(()),
+        }
+        points.for_each(|pt| bez.line_to(pt));
+        bez.close_path();
+
+        let context = self.context.get_mut();
+        let brush = context.solid_brush(color(style.color()));
+        context.fill(bez, &brush);
+        Ok(())
+    }
+
+    fn draw_text<S: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &S,
+        (x, y): BackendCoord,
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        let layout = self.layout(text, style)?;
+
+        // Move the text so that the anchor is at the requested position.
+        let size = layout.size();
+        let anchor = style.anchor();
+        let dx = match anchor.h_pos {
+            HPos::Left => 0.0,
+            HPos::Center => -size.width / 2.0,
+            HPos::Right => -size.width,
+        };
+        let dy = match anchor.v_pos {
+            VPos::Top => 0.0,
+            VPos::Center => -size.height / 2.0,
+            VPos::Bottom => -size.height,
+        };
+
+        // Rotations are clockwise.
+        let angle = match style.transform() {
+            FontTransform::None => 0.0,
+            FontTransform::Rotate90 => FRAC_PI_2,
+            FontTransform::Rotate180 => PI,
+            FontTransform::Rotate270 => PI + FRAC_PI_2,
+        };
+
+        let context = self.context.get_mut();
+        context.save().map_err(error)?;
+        context.transform(Affine::translate((x as f64, y as f64)) * Affine::rotate(angle));
+        context.draw_text(&layout, (dx, dy));
+        context.restore().map_err(error)
+    }
+
+    fn estimate_text_size<S: BackendTextStyle>(
+        &self,
+        text: &str,
+        style: &S,
+    ) -> Result<(u32, u32), DrawingErrorKind<PlottersError>> {
+        let size = self.layout(text, style)?.size();
+        Ok((size.width.ceil() as u32, size.height.ceil() as u32))
+    }
+
+    fn blit_bitmap(
+        &mut self,
+        (x, y): BackendCoord,
+        (width, height): (u32, u32),
+        src: &[u8],
+    ) -> Result<(), DrawingErrorKind<PlottersError>> {
+        // Not every backend supports RGB textures, so add an alpha channel.
+        let rgba = src
+            .chunks_exact(3)
+            .flat_map(|px| [px[0], px[1], px[2], 0xFF])
+            .collect::<Vec<_>>();
+
+        let context = self.context.get_mut();
+        let image = context
+            .make_image(
+                width as usize,
+                height as usize,
+                &rgba,
+                ImageFormat::RgbaPremul,
+            )
+            .map_err(error)?;
+
+        let rect = Rect::new(
+            x as f64,
+            y as f64,
+            x as f64 + width as f64,
+            y as f64 + height as f64,
+        );
+        context.draw_image(&image, rect, InterpolationMode::NearestNeighbor);
+        Ok(())
+    }
+}
+
+/// An error that occurred while drawing a plot.
+///
+/// `piet` errors can't be sent between threads, so this keeps their message.
+#[derive(Debug)]
+pub struct PlottersError(String);
+
+impl fmt::Display for PlottersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for PlottersError {}
+
+impl From<Pierror> for PlottersError {
+    fn from(err: Pierror) -> Self {
+        Self(err.to_string())
+    }
+}
+
+/// Convert a `piet` error to a `plotters` error.
+fn error(err: Pierror) -> DrawingErrorKind<PlottersError> {
+    DrawingErrorKind::DrawingError(err.into())
+}
+
+/// Convert a `plotters` color to a `piet` color.
+fn color(color: BackendColor) -> Color {
+    let (r, g, b) = color.rgb;
+    Color::rgb8(r, g, b).with_alpha(color.alpha)
+}
+
+/// Get the center of a pixel, where one pixel wide lines are drawn crisply.
+fn center((x, y): BackendCoord) -> Point {
+    Point::new(x as f64 + 0.5, y as f64 + 0.5)
+}
@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Perspective transforms for compositing flat content in three dimensions.
+
+use piet::kurbo::{Affine, Point};
+
+use std::ops::Mul;
+
+/// A 4x4 matrix that transforms points in three dimensions.
+///
+/// The matrix is stored in row-major order and transforms column vectors, so `a * b`
+/// applies `b` first, like [`Affine`]. The x and y axes are those of the 2D content, and the
+/// z axis points towards the viewer. Flat content is placed at `z = 0` and transformed as a
+/// plane, so a transform with [`perspective`](Self::perspective) can tilt or flip it in
+/// depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform3d(pub [f64; 16]);
+
+impl Transform3d {
+    /// The identity transform.
+    #[rustfmt::skip]
+    pub const IDENTITY: Self = Self([
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    ]);
+
+    /// A translation.
+    #[rustfmt::skip]
+    pub fn translate(x: f64, y: f64, z: f64) -> Self {
+        Self([
+            1.0, 0.0, 0.0, x,
+            0.0, 1.0, 0.0, y,
+            0.0, 0.0, 1.0, z,
+            0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+
+    /// A scale along each axis.
+    #[rustfmt::skip]
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        Self([
+            x,   0.0, 0.0, 0.0,
+            0.0, y,   0.0, 0.0,
+            0.0, 0.0, z,   0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+
+    /// A rotation around the x axis, in radians, which tilts content forwards or backwards.
+    #[rustfmt::skip]
+    pub fn rotate_x(angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self([
+            1.0, 0.0, 0.0,  0.0,
+            0.0, cos, -sin, 0.0,
+            0.0, sin, cos,  0.0,
+            0.0, 0.0, 0.0,  1.0,
+        ])
+    }
+
+    /// A rotation around the y axis, in radians, which turns content like a card being
+    /// flipped.
+    #[rustfmt::skip]
+    pub fn rotate_y(angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self([
+            cos,  0.0, sin, 0.0,
+            0.0,  1.0, 0.0, 0.0,
+            -sin, 0.0, cos, 0.0,
+            0.0,  0.0, 0.0, 1.0,
+        ])
+    }
+
+    /// A rotation around the z axis, in radians, which rotates content in its plane.
+    pub fn rotate_z(angle: f64) -> Self {
+        Affine::rotate(angle).into()
+    }
+
+    /// A perspective projection, with the viewer `distance` units in front of the origin.
+    ///
+    /// This works like the CSS `perspective()` function: points closer to the viewer are
+    /// drawn larger, and points at or behind the viewer aren't drawn. The vanishing point is
+    /// the origin, which can be moved with [`around`](Self::around).
+    #[rustfmt::skip]
+    pub fn perspective(distance: f64) -> Self {
+        Self([
+            1.0, 0.0, 0.0,             0.0,
+            0.0, 1.0, 0.0,             0.0,
+            0.0, 0.0, 1.0,             0.0,
+            0.0, 0.0, -1.0 / distance, 1.0,
+        ])
+    }
+
+    /// Apply this transform around `center` instead of the origin.
+    ///
+    /// For example, `Transform3d::perspective(800.0) * Transform3d::rotate_y(angle)` flips a
+    /// card around its center when it is applied `around` the center of the card.
+    pub fn around(self, center: Point) -> Self {
+        Self::translate(center.x, center.y, 0.0) * self * Self::translate(-center.x, -center.y, 0.0)
+    }
+
+    /// Get the transform of the plane at `z = 0` onto the screen.
+    ///
+    /// The z coordinate of the input is zero and the z coordinate of the output is dropped,
+    /// which leaves the rows and columns for x, y and w.
+    pub(crate) fn flatten(&self) -> Homography {
+        let m = &self.0;
+        Homography([
+            m[0], m[1], m[3], //
+            m[4], m[5], m[7], //
+            m[12], m[13], m[15],
+        ])
+    }
+}
+
+impl Default for Transform3d {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<Affine> for Transform3d {
+    #[rustfmt::skip]
+    fn from(affine: Affine) -> Self {
+        let [a, b, c, d, e, f] = affine.as_coeffs();
+        Self([
+            a,   c,   0.0, e,
+            b,   d,   0.0, f,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+}
+
+impl Mul for Transform3d {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut out = [0.0; 16];
+        for row in 0..4 {
+            for col in 0..4 {
+                out[row * 4 + col] = (0..4)
+                    .map(|i| self.0[row * 4 + i] * rhs.0[i * 4 + col])
+                    .sum();
+            }
+        }
+        Self(out)
+    }
+}
+
+/// A 3x3 matrix that projects points in the pixels of the render target.
+///
+/// The matrix is stored in row-major order. A point `(x, y)` is transformed as the column
+/// vector `(x, y, 1)`, and the result is divided by its third component. Backends get this
+/// from [`GpuContext::set_projection`](crate::GpuContext::set_projection).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection(pub [f32; 9]);
+
+impl Projection {
+    /// The identity projection, which leaves points unchanged.
+    #[rustfmt::skip]
+    pub const IDENTITY: Self = Self([
+        1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        0.0, 0.0, 1.0,
+    ]);
+
+    /// Get the matrix that applies `affine`, then this projection.
+    ///
+    /// The result is in the same row-major order as this projection.
+    pub fn after(&self, affine: &Affine) -> [f32; 9] {
+        let [a, b, c, d, e, f] = affine.as_coeffs();
+        #[rustfmt::skip]
+        let affine = [
+            a as f32, c as f32, e as f32,
+            b as f32, d as f32, f as f32,
+            0.0,      0.0,      1.0,
+        ];
+
+        let mut out = [0.0; 9];
+        for row in 0..3 {
+            for col in 0..3 {
+                out[row * 3 + col] = (0..3)
+                    .map(|i| self.0[row * 3 + i] * affine[i * 3 + col])
+                    .sum();
+            }
+        }
+        out
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A projective transform of the plane, in double precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Homography([f64; 9]);
+
+impl Homography {
+    /// The identity transform.
+    pub(crate) const IDENTITY: Self = Self([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+
+    /// Get the affine transform that this is, if it doesn't have any perspective.
+    pub(crate) fn as_affine(&self) -> Option<Affine> {
+        let m = &self.0;
+        if m[6] != 0.0 || m[7] != 0.0 || m[8] == 0.0 {
+            return None;
+        }
+
+        let w = m[8];
+        Some(Affine::new([
+            m[0] / w,
+            m[3] / w,
+            m[1] / w,
+            m[4] / w,
+            m[2] / w,
+            m[5] / w,
+        ]))
+    }
+
+    /// Get this transform as a projection for the backend.
+    pub(crate) fn to_projection(self) -> Projection {
+        Projection(self.0.map(|x| x as f32))
+    }
+}
+
+impl From<Affine> for Homography {
+    fn from(affine: Affine) -> Self {
+        let [a, b, c, d, e, f] = affine.as_coeffs();
+        Self([a, c, e, b, d, f, 0.0, 0.0, 1.0])
+    }
+}
+
+impl Mul for Homography {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut out = [0.0; 9];
+        for row in 0..3 {
+            for col in 0..3 {
+                out[row * 3 + col] = (0..3)
+                    .map(|i| self.0[row * 3 + i] * rhs.0[i * 3 + col])
+                    .sum();
+            }
+        }
+        Self(out)
+    }
+}
@@ -21,10 +21,14 @@
 
 //! The rasterizer, powered by `lyon_tessellation`.
 
+use super::builder::SubpathClosing;
+use super::dash::Dasher;
 use super::gpu_backend::Vertex;
 use super::ResultExt;
 
+use ahash::RandomState;
 use arrayvec::ArrayVec;
+use hashbrown::HashMap;
 
 use lyon_tessellation::path::{Event, PathEvent};
 use lyon_tessellation::{
@@ -32,9 +36,76 @@ use lyon_tessellation::{
     StrokeTessellator, StrokeVertex, VertexBuffers,
 };
 
-use piet::kurbo::{PathEl, Point, Rect, Shape};
+use piet::kurbo::{Affine, BezPath, PathEl, Point, Rect, Shape, Vec2};
 use piet::{Color, Error as Pierror, LineCap, LineJoin};
 
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::mem;
+
+/// The most that one unit of user space is assumed to be magnified by.
+///
+/// Tessellating more finely than this runs into the precision of the `f32` vertices anyway.
+const MAX_SCALE: f64 = 1.0e6;
+
+/// The most vertices that a fill can have for its tessellation to be cached.
+const MAX_CACHED_VERTICES: usize = 1 << 16;
+
+/// How precisely shapes are tessellated, measured in pixels of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Precision {
+    /// The largest distance between a curve and the segments it is drawn with, in pixels.
+    tolerance: f64,
+
+    /// The most pixels that one unit of user space is stretched to.
+    scale: f64,
+
+    /// The point that vertices are measured from, so that they keep their precision as `f32`.
+    origin: Vec2,
+}
+
+impl Precision {
+    /// Get the precision of shapes drawn with `transform`.
+    pub(crate) fn new(tolerance: f64, transform: Affine) -> Self {
+        // The largest singular value of the linear part of the transform.
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        let sum = a * a + b * b + c * c + d * d;
+        let det = a * d - b * c;
+        let scale = ((sum + (sum * sum - 4.0 * det * det).max(0.0).sqrt()) / 2.0).sqrt();
+
+        Self {
+            tolerance,
+            scale: if scale.is_finite() && scale > 0.0 {
+                scale.min(MAX_SCALE)
+            } else {
+                1.0
+            },
+            origin: Vec2::ZERO,
+        }
+    }
+
+    /// Measure vertices from `origin` before they are converted to `f32`.
+    pub(crate) fn with_origin(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Get the tolerance in user space.
+    pub(crate) fn tolerance(&self) -> f64 {
+        self.tolerance / self.scale
+    }
+
+    /// Convert a tessellated point back to the space of the shape.
+    fn absolute(&self, point: lyon_tessellation::math::Point) -> Point {
+        Point::new(point.x as f64, point.y as f64) + self.origin
+    }
+
+    /// Convert a distance in pixels to user space.
+    fn distance(&self, pixels: f64) -> f64 {
+        pixels / self.scale
+    }
+}
+
 pub(crate) struct Rasterizer {
     /// Buffers for tessellating the path.
     buffers: VertexBuffers<Vertex, u32>,
@@ -44,15 +115,50 @@ pub(crate) struct Rasterizer {
 
     /// The stroke tessellator.
     stroke_tessellator: StrokeTessellator,
+
+    /// How to close subpaths.
+    closing: SubpathClosing,
+
+    /// Splits strokes into dashes.
+    dasher: Dasher,
+
+    /// The points that the vertices of the last shape were tessellated from.
+    points: Vec<Point>,
+
+    /// The first vertex and index of the last shape.
+    last_shape: (usize, usize),
+
+    /// Fills that were tessellated recently, if they are cached.
+    fill_cache: Option<FillCache>,
 }
 
 impl Rasterizer {
     /// Create a new rasterizer.
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(closing: SubpathClosing) -> Self {
         Self {
             buffers: VertexBuffers::new(),
             fill_tessellator: FillTessellator::new(),
             stroke_tessellator: StrokeTessellator::new(),
+            closing,
+            dasher: Dasher::new(),
+            points: Vec::new(),
+            last_shape: (0, 0),
+            fill_cache: None,
+        }
+    }
+
+    /// Keep the tessellations of the last `capacity` different fills around for reuse.
+    pub(crate) fn with_fill_cache(mut self, capacity: usize, hasher: RandomState) -> Self {
+        if capacity > 0 {
+            self.fill_cache = Some(FillCache::new(capacity, hasher));
+        }
+        self
+    }
+
+    /// Drop the tessellations of fills that are kept around for reuse.
+    pub(crate) fn clear_fill_cache(&mut self) {
+        if let Some(cache) = &mut self.fill_cache {
+            cache.clear();
         }
     }
 
@@ -66,10 +172,58 @@ impl Rasterizer {
         &self.buffers.indices
     }
 
+    /// Convert the triangles in the buffers into a path, with each triangle as a subpath.
+    ///
+    /// Every triangle is wound the same way, so that filling the path with the non-zero rule
+    /// covers the same area as the triangles. Positions are offset by `origin`.
+    pub(crate) fn to_path(&self, origin: Vec2) -> BezPath {
+        let point = |index: u32| {
+            let [x, y] = self.buffers.vertices[index as usize].pos;
+            Point::new(x as f64, y as f64) + origin
+        };
+
+        let mut path = BezPath::new();
+        for triangle in self.buffers.indices.chunks_exact(3) {
+            let [a, mut b, mut c] = [point(triangle[0]), point(triangle[1]), point(triangle[2])];
+            let area = (b - a).cross(c - a);
+            if area == 0.0 {
+                continue;
+            }
+            if area < 0.0 {
+                mem::swap(&mut b, &mut c);
+            }
+
+            path.move_to(a);
+            path.line_to(b);
+            path.line_to(c);
+            path.close_path();
+        }
+
+        path
+    }
+
+    /// Multiply the alpha of every vertex currently in the buffers by `alpha`.
+    pub(crate) fn multiply_alpha(&mut self, alpha: f64) {
+        for vertex in &mut self.buffers.vertices {
+            vertex.color[3] = (vertex.color[3] as f64 * alpha).round() as u8;
+        }
+    }
+
     /// Clear the rasterizer's buffers.
     pub(crate) fn clear(&mut self) {
         self.buffers.vertices.clear();
         self.buffers.indices.clear();
+        self.points.clear();
+        self.last_shape = (0, 0);
+    }
+
+    /// Add triangles that were tessellated ahead of time.
+    pub(crate) fn extend(&mut self, vertices: impl IntoIterator<Item = Vertex>, indices: &[u32]) {
+        let base = self.buffers.vertices.len() as u32;
+        self.buffers.vertices.extend(vertices);
+        self.buffers
+            .indices
+            .extend(indices.iter().map(|index| index + base));
     }
 
     /// Tessellate a series of rectangles.
@@ -124,12 +278,42 @@ impl Rasterizer {
         &mut self,
         shape: impl Shape,
         mode: FillRule,
-        tolerance: f64,
-        cvt_vertex: impl Fn(FillVertex<'_>) -> Vertex,
+        precision: Precision,
+        cvt_vertex: impl Fn(Point) -> Vertex,
     ) -> Result<(), Pierror> {
+        let tolerance = precision.tolerance();
+        check_finite(&shape, tolerance)?;
+
+        // Reuse the tessellation of the same shape if it was filled recently.
+        self.start_shape();
+        let key = self
+            .fill_cache
+            .as_ref()
+            .map(|_| FillKey::new(&shape, mode, tolerance));
+        if let Some(cached) = key
+            .as_ref()
+            .and_then(|key| self.fill_cache.as_mut()?.get(key))
+        {
+            let (first_vertex, _) = self.last_shape;
+            self.points.extend_from_slice(&cached.points);
+            self.buffers
+                .vertices
+                .extend(cached.points.iter().map(|&point| cvt_vertex(point)));
+            self.buffers.indices.extend(
+                cached
+                    .indices
+                    .iter()
+                    .map(|&index| index + first_vertex as u32),
+            );
+            return Ok(());
+        }
+
         // Create a new buffers builder.
-        let mut builder = BuffersBuilder::new(&mut self.buffers, move |vertex: FillVertex<'_>| {
-            cvt_vertex(vertex)
+        let points = RefCell::new(mem::take(&mut self.points));
+        let mut builder = BuffersBuilder::new(&mut self.buffers, |vertex: FillVertex<'_>| {
+            let point = precision.absolute(vertex.position());
+            points.borrow_mut().push(point);
+            cvt_vertex(point)
         });
 
         // Create fill options.
@@ -138,34 +322,59 @@ impl Rasterizer {
         options.tolerance = tolerance as f32;
 
         // Fill the shape.
-        self.fill_tessellator
+        let result = self
+            .fill_tessellator
             .tessellate(
-                shape_to_lyon_path(&shape, tolerance),
+                shape_to_lyon_path(&shape, precision, self.closing),
                 &options,
                 &mut builder,
             )
-            .piet_err()
+            .piet_err();
+        self.points = points.into_inner();
+
+        // Don't leave a partial shape behind.
+        if result.is_err() {
+            self.clear();
+            return result;
+        }
+
+        if let (Some(cache), Some(key)) = (&mut self.fill_cache, key) {
+            if self.points.len() <= MAX_CACHED_VERTICES {
+                let (first_vertex, first_index) = self.last_shape;
+                let indices = self.buffers.indices[first_index..]
+                    .iter()
+                    .map(|&index| index - first_vertex as u32)
+                    .collect();
+                cache.insert(key, self.points.clone(), indices);
+            }
+        }
+
+        result
     }
 
     /// Tessellate the stroke of a shape.
     pub(crate) fn stroke_shape(
         &mut self,
         shape: impl Shape,
-        tolerance: f64,
+        precision: Precision,
         width: f64,
         style: &piet::StrokeStyle,
-        cvt_vertex: impl Fn(StrokeVertex<'_, '_>) -> Vertex,
+        cvt_vertex: impl Fn(Point) -> Vertex,
     ) -> Result<(), Pierror> {
-        // TODO: Support dashing.
-        if !style.dash_pattern.is_empty() {
-            return Err(Pierror::NotSupported);
+        let tolerance = precision.tolerance();
+        check_finite(&shape, tolerance)?;
+        if !width.is_finite() || width < 0.0 {
+            return Err(Pierror::InvalidInput);
         }
 
         // Create a new buffers builder.
-        let mut builder =
-            BuffersBuilder::new(&mut self.buffers, move |vertex: StrokeVertex<'_, '_>| {
-                cvt_vertex(vertex)
-            });
+        self.start_shape();
+        let points = RefCell::new(mem::take(&mut self.points));
+        let mut builder = BuffersBuilder::new(&mut self.buffers, |vertex: StrokeVertex<'_, '_>| {
+            let point = precision.absolute(vertex.position());
+            points.borrow_mut().push(point);
+            cvt_vertex(point)
+        });
 
         let cvt_line_cap = |cap: LineCap| match cap {
             LineCap::Butt => lyon_tessellation::LineCap::Butt,
@@ -188,14 +397,373 @@ impl Rasterizer {
             }
         };
 
-        // Fill the shape.
-        self.stroke_tessellator
-            .tessellate(
-                shape_to_lyon_path(&shape, tolerance),
+        // Split the shape into dashes if needed.
+        let dashed = if style.dash_pattern.is_empty() {
+            None
+        } else {
+            self.dasher
+                .dash(&shape, tolerance, &style.dash_pattern, style.dash_offset)
+        };
+
+        // Stroke the shape.
+        let result = match dashed {
+            Some(dashed) => self.stroke_tessellator.tessellate(
+                shape_to_lyon_path(dashed, precision, self.closing),
                 &options,
                 &mut builder,
-            )
-            .piet_err()
+            ),
+            None => self.stroke_tessellator.tessellate(
+                shape_to_lyon_path(&shape, precision, self.closing),
+                &options,
+                &mut builder,
+            ),
+        }
+        .piet_err();
+        self.points = points.into_inner();
+
+        // Don't leave a partial shape behind.
+        if result.is_err() {
+            self.clear();
+        }
+
+        result
+    }
+
+    /// Draw the stroke of a shape in device space as a strip of quads, `width` pixels wide.
+    ///
+    /// This is meant for strokes around a pixel wide, where the shape of the joins can't be
+    /// seen: the quads of neighboring segments meet at a miter, and round and square caps
+    /// extend the ends by half of the width.
+    pub(crate) fn stroke_hairline(
+        &mut self,
+        shape: impl Shape,
+        tolerance: f64,
+        width: f64,
+        style: &piet::StrokeStyle,
+        cvt_vertex: impl Fn(Point) -> Vertex,
+    ) -> Result<(), Pierror> {
+        check_finite(&shape, tolerance)?;
+        if !width.is_finite() || width < 0.0 {
+            return Err(Pierror::InvalidInput);
+        }
+
+        let dashed = if style.dash_pattern.is_empty() {
+            None
+        } else {
+            self.dasher
+                .dash(&shape, tolerance, &style.dash_pattern, style.dash_offset)
+        };
+        let cap = match style.line_cap {
+            LineCap::Butt => 0.0,
+            LineCap::Round | LineCap::Square => width / 2.0,
+        };
+
+        // Like `start_shape`, without borrowing the dashes.
+        self.points.clear();
+        self.last_shape = (self.buffers.vertices.len(), self.buffers.indices.len());
+        let mut strip = HairlineStrip {
+            buffers: &mut self.buffers,
+            points: &mut self.points,
+            polyline: Vec::new(),
+            half_width: width / 2.0,
+            cap,
+        };
+        let mut emit = |el| match el {
+            PathEl::MoveTo(pt) => {
+                strip.finish(false, &cvt_vertex);
+                strip.push(pt);
+            }
+            PathEl::LineTo(pt) => strip.push(pt),
+            PathEl::ClosePath => strip.finish(true, &cvt_vertex),
+            _ => {}
+        };
+        match dashed {
+            Some(dashed) => piet::kurbo::flatten(dashed, tolerance, &mut emit),
+            None => piet::kurbo::flatten(shape.path_elements(tolerance), tolerance, &mut emit),
+        }
+        strip.finish(false, &cvt_vertex);
+
+        Ok(())
+    }
+
+    /// Mark the start of a shape that can be feathered.
+    fn start_shape(&mut self) {
+        self.points.clear();
+        self.last_shape = (self.buffers.vertices.len(), self.buffers.indices.len());
+    }
+
+    /// Fade the last shape that was tessellated out over a one pixel fringe.
+    ///
+    /// The fringe is drawn outside of the edges that only belong to one triangle, which are
+    /// the edges of the shape. `cvt_vertex` should be the same as the one that the shape was
+    /// tessellated with.
+    pub(crate) fn feather(&mut self, precision: Precision, cvt_vertex: impl Fn(Point) -> Vertex) {
+        let (first_vertex, first_index) = self.last_shape;
+        if self.points.len() != self.buffers.vertices.len() - first_vertex {
+            return;
+        }
+
+        // Vertices with the same position aren't always shared, so compare positions.
+        let point = |index: u32| self.points[index as usize - first_vertex];
+        let key = |point: Point| (point.x.to_bits(), point.y.to_bits());
+        let mut edges = Vec::new();
+        for triangle in self.buffers.indices[first_index..].chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+            if (point(b) - point(a)).cross(point(c) - point(a)) == 0.0 {
+                continue;
+            }
+
+            for (a, b, c) in [(a, b, c), (b, c, a), (c, a, b)] {
+                let (ka, kb) = (key(point(a)), key(point(b)));
+                edges.push((ka.min(kb), ka.max(kb), a, b, c));
+            }
+        }
+        edges.sort_unstable_by_key(|&(ka, kb, ..)| (ka, kb));
+
+        let width = precision.distance(1.0);
+        let mut corners = BTreeMap::<_, ArrayVec<(u32, u32), 2>>::new();
+        let mut i = 0;
+        while i < edges.len() {
+            let (ka, kb, a, b, c) = edges[i];
+            let shared = edges[i + 1..]
+                .iter()
+                .take_while(|edge| (edge.0, edge.1) == (ka, kb))
+                .count();
+            i += shared + 1;
+            if shared > 0 {
+                continue;
+            }
+
+            // Push the edge away from the rest of its triangle.
+            let (pa, pb) = (point(a), point(b));
+            let dir = pb - pa;
+            let mut normal = Vec2::new(-dir.y, dir.x) / dir.hypot();
+            if normal.dot(point(c) - pa) > 0.0 {
+                normal = -normal;
+            }
+
+            let base = self.buffers.vertices.len() as u32;
+            for pos in [pa, pb] {
+                let mut vertex = cvt_vertex(pos + normal * width);
+                vertex.color[3] = 0;
+                self.buffers.vertices.push(vertex);
+            }
+            self.buffers
+                .indices
+                .extend([a, b, base + 1, a, base + 1, base]);
+
+            for (index, outer, pos) in [(a, base, pa), (b, base + 1, pb)] {
+                corners
+                    .entry(key(pos))
+                    .or_default()
+                    .try_push((index, outer))
+                    .ok();
+            }
+        }
+
+        // Fill in the gaps between the fringes of neighboring edges.
+        for corner in corners.values() {
+            if let [(index, first), (_, second)] = corner[..] {
+                self.buffers.indices.extend([index, first, second]);
+            }
+        }
+    }
+}
+
+/// Builds the quads of a hairline one subpath at a time.
+struct HairlineStrip<'a> {
+    /// The buffers that the quads are added to.
+    buffers: &'a mut VertexBuffers<Vertex, u32>,
+
+    /// The positions of the vertices that were added, for feathering.
+    points: &'a mut Vec<Point>,
+
+    /// The points of the current subpath.
+    polyline: Vec<Point>,
+
+    /// Half of the width of the hairline.
+    half_width: f64,
+
+    /// How far to extend the ends of open subpaths.
+    cap: f64,
+}
+
+impl HairlineStrip<'_> {
+    /// The longest that the offset at a join can be, relative to half of the width.
+    const MITER_LIMIT: f64 = 2.0;
+
+    fn push(&mut self, pt: Point) {
+        if self.polyline.last() != Some(&pt) {
+            self.polyline.push(pt);
+        }
+    }
+
+    /// Add the quads of the current subpath.
+    fn finish(&mut self, closed: bool, cvt_vertex: &impl Fn(Point) -> Vertex) {
+        let mut polyline = mem::take(&mut self.polyline);
+        if closed && polyline.len() > 2 && polyline.first() == polyline.last() {
+            polyline.pop();
+        }
+        let closed = closed && polyline.len() > 2;
+        if polyline.len() < 2 {
+            self.polyline = polyline;
+            self.polyline.clear();
+            return;
+        }
+
+        let normal = |a: Point, b: Point| {
+            let dir = (b - a).normalize();
+            Vec2::new(-dir.y, dir.x)
+        };
+        let count = polyline.len();
+        let segments = if closed { count } else { count - 1 };
+        let base = self.buffers.vertices.len() as u32;
+
+        for i in 0..count {
+            let next = normal(polyline[i], polyline[(i + 1) % count]);
+            let prev = normal(polyline[(i + count - 1) % count], polyline[i]);
+            let mut pos = polyline[i];
+            let offset = if !closed && i == 0 {
+                pos -= Vec2::new(next.y, -next.x) * self.cap;
+                next
+            } else if !closed && i == count - 1 {
+                pos += Vec2::new(prev.y, -prev.x) * self.cap;
+                prev
+            } else {
+                // Meet the neighboring quads at a miter, unless it is too long.
+                let miter = prev + next;
+                let cos = miter.hypot() / 2.0;
+                if cos < 1.0 / Self::MITER_LIMIT {
+                    next
+                } else {
+                    miter.normalize() / cos
+                }
+            };
+
+            for pos in [
+                pos + offset * self.half_width,
+                pos - offset * self.half_width,
+            ] {
+                self.points.push(pos);
+                self.buffers.vertices.push(cvt_vertex(pos));
+            }
+        }
+
+        for i in 0..segments as u32 {
+            let (a, b) = (base + i * 2, base + ((i + 1) % count as u32) * 2);
+            self.buffers.indices.extend([a, a + 1, b + 1, a, b + 1, b]);
+        }
+
+        self.polyline = polyline;
+        self.polyline.clear();
+    }
+}
+
+/// The fills that were tessellated recently.
+///
+/// Animated scenes tend to fill the same shapes every frame, so their tessellations are
+/// keyed by their path elements. Once the cache is full, the least recently used one is
+/// dropped.
+struct FillCache {
+    /// The cached tessellations.
+    fills: HashMap<FillKey, CachedFill, RandomState>,
+
+    /// Incremented every time a fill is looked up.
+    clock: u64,
+
+    /// The most fills to keep.
+    capacity: usize,
+}
+
+/// What decides how a shape is tessellated.
+#[derive(PartialEq, Eq, Hash)]
+struct FillKey {
+    /// The kind and the coordinates of every path element, as bits.
+    elements: Vec<u64>,
+
+    /// Whether the even-odd fill rule is used.
+    even_odd: bool,
+
+    /// The tolerance in user space, as bits.
+    tolerance: u64,
+}
+
+impl FillKey {
+    /// Get the key for filling `shape` with `mode`.
+    fn new(shape: &impl Shape, mode: FillRule, tolerance: f64) -> Self {
+        let mut elements = Vec::new();
+        for el in shape.path_elements(tolerance) {
+            let (kind, points): (u64, &[Point]) = match &el {
+                PathEl::MoveTo(p) => (0, std::slice::from_ref(p)),
+                PathEl::LineTo(p) => (1, std::slice::from_ref(p)),
+                PathEl::QuadTo(p1, p2) => (2, &[*p1, *p2]),
+                PathEl::CurveTo(p1, p2, p3) => (3, &[*p1, *p2, *p3]),
+                PathEl::ClosePath => (4, &[]),
+            };
+            elements.push(kind);
+            elements.extend(points.iter().flat_map(|p| [p.x.to_bits(), p.y.to_bits()]));
+        }
+
+        Self {
+            elements,
+            even_odd: mode == FillRule::EvenOdd,
+            tolerance: tolerance.to_bits(),
+        }
+    }
+}
+
+/// A tessellation in the cache.
+struct CachedFill {
+    /// The points of the vertices, in the space of the shape.
+    points: Vec<Point>,
+
+    /// The indices into the points.
+    indices: Vec<u32>,
+
+    /// The value of the clock when the fill was last used.
+    last_used: u64,
+}
+
+impl FillCache {
+    /// Create a new, empty cache.
+    fn new(capacity: usize, hasher: RandomState) -> Self {
+        Self {
+            fills: HashMap::with_hasher(hasher),
+            clock: 0,
+            capacity,
+        }
+    }
+
+    /// Get the tessellation for `key`, if it is cached.
+    fn get(&mut self, key: &FillKey) -> Option<&CachedFill> {
+        self.clock += 1;
+        let cached = self.fills.get_mut(key)?;
+        cached.last_used = self.clock;
+        Some(cached)
+    }
+
+    /// Add a tessellation to the cache.
+    fn insert(&mut self, key: FillKey, points: Vec<Point>, indices: Vec<u32>) {
+        if self.fills.len() >= self.capacity {
+            let oldest = self.fills.values().map(|cached| cached.last_used).min();
+            if let Some(oldest) = oldest {
+                self.fills.retain(|_, cached| cached.last_used != oldest);
+            }
+        }
+
+        self.fills.insert(
+            key,
+            CachedFill {
+                points,
+                indices,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Drop every cached tessellation.
+    fn clear(&mut self) {
+        self.fills.clear();
     }
 }
 
@@ -212,7 +780,30 @@ pub(crate) struct TessRect {
     pub(crate) color: Color,
 }
 
-fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item = PathEvent> + '_ {
+/// Make sure that every point in a shape is finite, since the tessellators can't handle them.
+///
+/// Points are checked as `f32`, since that is what the tessellators use.
+fn check_finite(shape: &impl Shape, tolerance: f64) -> Result<(), Pierror> {
+    let finite = |pt: &Point| (pt.x as f32).is_finite() && (pt.y as f32).is_finite();
+    let all_finite = shape.path_elements(tolerance).all(|el| match el {
+        PathEl::MoveTo(p) | PathEl::LineTo(p) => finite(&p),
+        PathEl::QuadTo(p1, p2) => [p1, p2].iter().all(finite),
+        PathEl::CurveTo(p1, p2, p3) => [p1, p2, p3].iter().all(finite),
+        PathEl::ClosePath => true,
+    });
+
+    if all_finite {
+        Ok(())
+    } else {
+        Err(Pierror::InvalidInput)
+    }
+}
+
+fn shape_to_lyon_path(
+    shape: &impl Shape,
+    precision: Precision,
+    closing: SubpathClosing,
+) -> impl Iterator<Item = PathEvent> + '_ {
     use std::iter::Fuse;
 
     fn convert_point(pt: Point) -> lyon_tessellation::path::geom::Point<f32> {
@@ -230,8 +821,40 @@ fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item
         /// The first point of the current subpath.
         first: Option<Point>,
 
-        // Whether or not we need to close the path.
-        needs_close: bool,
+        /// The first point of the most recent subpath, even if it has been closed.
+        start: Option<Point>,
+
+        /// Whether the current subpath has any segments.
+        has_segments: bool,
+
+        /// How to close subpaths.
+        closing: SubpathClosing,
+
+        /// The precision the path is tessellated with.
+        precision: Precision,
+    }
+
+    impl<I> PathConverter<I> {
+        /// Get the point that a new segment ending at `to` starts from.
+        ///
+        /// If there is no current subpath, this begins a new one. Like `kurbo`, segments after
+        /// a `ClosePath` continue from the start of the closed subpath.
+        fn segment_start(&mut self, to: Point, events: &mut ArrayVec<PathEvent, 2>) -> Point {
+            self.has_segments = true;
+
+            match self.last.replace(to) {
+                Some(from) => from,
+                None => {
+                    let from = self.start.unwrap_or(to);
+                    self.first = Some(from);
+                    self.start = Some(from);
+                    events.push(Event::Begin {
+                        at: convert_point(from),
+                    });
+                    from
+                }
+            }
+        }
     }
 
     impl<I: Iterator<Item = PathEl>> Iterator for PathConverter<I> {
@@ -239,20 +862,23 @@ fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item
 
         fn next(&mut self) -> Option<Self::Item> {
             let close = |this: &mut PathConverter<I>, close| {
-                if let (Some(first), Some(last)) = (this.first.take(), this.last.take()) {
-                    if (!approx_eq(first.x, last.x) || !approx_eq(first.y, last.y))
-                        || (this.needs_close || close)
-                    {
-                        this.needs_close = false;
-                        return Some(Event::End {
-                            last: convert_point(last),
-                            first: convert_point(first),
-                            close,
-                        });
-                    }
-                }
-
-                None
+                // Every subpath that has begun must end, even if it has no segments.
+                let (first, last) = (this.first.take()?, this.last.take()?);
+                let has_segments = mem::replace(&mut this.has_segments, false);
+
+                let close = close
+                    || match this.closing {
+                        SubpathClosing::Explicit => false,
+                        SubpathClosing::Coincident(pixels) => {
+                            has_segments && first.distance(last) <= this.precision.distance(pixels)
+                        }
+                    };
+
+                Some(Event::End {
+                    last: convert_point(last),
+                    first: convert_point(first),
+                    close,
+                })
             };
 
             let el = match self.iter.next() {
@@ -270,6 +896,7 @@ fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item
 
                     // Set the first point.
                     self.first = Some(pt);
+                    self.start = Some(pt);
                     self.last = Some(pt);
 
                     let mut v = ArrayVec::new();
@@ -281,36 +908,39 @@ fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item
                 }
 
                 PathEl::LineTo(pt) => {
-                    self.needs_close = true;
-                    let from = self.last.replace(pt).expect("last point should be set");
+                    let mut v = ArrayVec::new();
+                    let from = self.segment_start(pt, &mut v);
 
-                    Some(one(Event::Line {
+                    v.push(Event::Line {
                         from: convert_point(from),
                         to: convert_point(pt),
-                    }))
+                    });
+                    Some(v)
                 }
 
                 PathEl::QuadTo(ctrl1, pt) => {
-                    self.needs_close = true;
-                    let from = self.last.replace(pt).expect("last point should be set");
+                    let mut v = ArrayVec::new();
+                    let from = self.segment_start(pt, &mut v);
 
-                    Some(one(Event::Quadratic {
+                    v.push(Event::Quadratic {
                         from: convert_point(from),
                         ctrl: convert_point(ctrl1),
                         to: convert_point(pt),
-                    }))
+                    });
+                    Some(v)
                 }
 
                 PathEl::CurveTo(ctrl1, ctrl2, pt) => {
-                    self.needs_close = true;
-                    let from = self.last.replace(pt).expect("last point should be set");
+                    let mut v = ArrayVec::new();
+                    let from = self.segment_start(pt, &mut v);
 
-                    Some(one(Event::Cubic {
+                    v.push(Event::Cubic {
                         from: convert_point(from),
                         ctrl1: convert_point(ctrl1),
                         ctrl2: convert_point(ctrl2),
                         to: convert_point(pt),
-                    }))
+                    });
+                    Some(v)
                 }
 
                 PathEl::ClosePath => {
@@ -322,19 +952,24 @@ fn shape_to_lyon_path(shape: &impl Shape, tolerance: f64) -> impl Iterator<Item
         }
     }
 
+    // Measure the path from the origin while it is still in `f64`.
+    let to_origin = Affine::translate(-precision.origin);
+
     PathConverter {
-        iter: shape.path_elements(tolerance).fuse(),
+        iter: shape
+            .path_elements(precision.tolerance())
+            .map(move |el| to_origin * el)
+            .fuse(),
         last: None,
         first: None,
-        needs_close: false,
+        start: None,
+        has_segments: false,
+        closing,
+        precision,
     }
     .flatten()
 }
 
-fn approx_eq(a: f64, b: f64) -> bool {
-    (a - b).abs() < 0.01
-}
-
 fn one(p: PathEvent) -> ArrayVec<PathEvent, 2> {
     let mut v = ArrayVec::new();
     v.push(p);
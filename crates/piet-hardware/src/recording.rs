@@ -0,0 +1,895 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Record the calls made to a [`GpuContext`] into a list that can be inspected.
+
+use crate::brush::CustomBrush;
+use crate::filter::ColorMatrix;
+use crate::gpu_backend::{
+    ClipShape, CompositeMode, GpuContext, PresentMode, RepeatStrategy, Vertex,
+};
+use crate::projection::Projection;
+
+use piet::kurbo::{Affine, Rect};
+use piet::{Color, ImageFormat, InterpolationMode};
+
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use std::mem;
+
+/// A [`GpuContext`] that records every call made to it.
+///
+/// Wrap a backend in this type and the calls are passed through to it, while a [`Command`] is
+/// appended to a list for each of them. Tests can then look at [`commands`](Self::commands) to
+/// check which textures were created, which vertices were uploaded and how draws were batched,
+/// without comparing pixels. Textures and vertex buffers are referred to by IDs that count up
+/// from one.
+///
+/// Optional features are only recorded when the wrapped context supports them, since
+/// otherwise `piet-hardware` falls back to something else, which is recorded instead.
+///
+/// ```
+/// use piet_hardware::{Command, GpuContext, RecordingContext};
+///
+/// /// Count the draw calls that were issued through `context`.
+/// fn count_draws<C: GpuContext>(context: &RecordingContext<C>) -> usize {
+///     context
+///         .commands()
+///         .iter()
+///         .filter(|command| matches!(command, Command::PushBuffers { .. }))
+///         .count()
+/// }
+/// ```
+pub struct RecordingContext<C: GpuContext> {
+    /// The context that does the actual work.
+    inner: C,
+
+    /// The calls that were recorded.
+    commands: RefCell<Vec<Command>>,
+
+    /// The last ID that was handed out.
+    next_id: Cell<u32>,
+}
+
+impl<C: GpuContext + fmt::Debug> fmt::Debug for RecordingContext<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingContext")
+            .field("inner", &self.inner)
+            .field("commands", &self.commands.borrow().len())
+            .finish()
+    }
+}
+
+impl<C: GpuContext> RecordingContext<C> {
+    /// Wrap a context.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            commands: RefCell::new(Vec::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Get a reference to the wrapped context.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the context.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Get the calls that were recorded so far.
+    pub fn commands(&self) -> Ref<'_, [Command]> {
+        Ref::map(self.commands.borrow(), Vec::as_slice)
+    }
+
+    /// Take the calls that were recorded so far, and start a new list.
+    pub fn take_commands(&self) -> Vec<Command> {
+        mem::take(&mut *self.commands.borrow_mut())
+    }
+
+    /// Record a call that was made to the inner context.
+    fn record(&self, command: Command) {
+        self.commands.borrow_mut().push(command);
+    }
+
+    /// Create an ID for a new resource.
+    fn next_id(&self) -> u32 {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        id
+    }
+}
+
+/// A texture created by a [`RecordingContext`].
+pub struct RecordingTexture<T> {
+    /// The ID that the texture has in the commands.
+    id: u32,
+
+    /// The texture of the inner context.
+    inner: T,
+}
+
+impl<T> fmt::Debug for RecordingTexture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingTexture")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> RecordingTexture<T> {
+    /// Get the ID that the texture has in the commands.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get a reference to the texture of the wrapped context.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A vertex buffer created by a [`RecordingContext`].
+pub struct RecordingVertexBuffer<B> {
+    /// The ID that the buffer has in the commands.
+    id: u32,
+
+    /// The buffer of the inner context.
+    inner: B,
+}
+
+impl<B> fmt::Debug for RecordingVertexBuffer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingVertexBuffer")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> RecordingVertexBuffer<B> {
+    /// Get the ID that the buffer has in the commands.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get a reference to the vertex buffer of the wrapped context.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+/// A call that was made to a [`RecordingContext`].
+///
+/// Textures and vertex buffers are referred to by their IDs.
+#[derive(Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Command {
+    /// The target was cleared.
+    Clear(Color),
+
+    /// The context was flushed.
+    Flush,
+
+    /// A texture was created.
+    CreateTexture {
+        /// The ID of the new texture.
+        id: u32,
+
+        /// The interpolation mode of the texture.
+        interpolation: InterpolationMode,
+
+        /// How the texture is repeated.
+        repeat: RepeatStrategy,
+    },
+
+    /// A texture was deleted.
+    DeleteTexture(u32),
+
+    /// A texture was written to, from scratch.
+    WriteTexture {
+        /// The ID of the texture.
+        id: u32,
+
+        /// The new size of the texture.
+        size: (u32, u32),
+
+        /// The format of the data.
+        format: ImageFormat,
+
+        /// The data, if any.
+        data: Option<Vec<u8>>,
+    },
+
+    /// A part of a texture was written to.
+    WriteSubtexture {
+        /// The ID of the texture.
+        id: u32,
+
+        /// Where the data was written.
+        offset: (u32, u32),
+
+        /// The size of the data.
+        size: (u32, u32),
+
+        /// The format of the data.
+        format: ImageFormat,
+
+        /// The data.
+        data: Vec<u8>,
+    },
+
+    /// The interpolation mode of a texture was changed.
+    SetTextureInterpolation {
+        /// The ID of the texture.
+        id: u32,
+
+        /// The new interpolation mode.
+        interpolation: InterpolationMode,
+    },
+
+    /// Mipmaps were generated for a texture.
+    GenerateMipmaps(u32),
+
+    /// A vertex buffer was created.
+    CreateVertexBuffer(u32),
+
+    /// A vertex buffer was deleted.
+    DeleteVertexBuffer(u32),
+
+    /// Vertices were uploaded to a vertex buffer.
+    WriteVertices {
+        /// The ID of the vertex buffer.
+        id: u32,
+
+        /// The vertices.
+        vertices: Vec<Vertex>,
+
+        /// The indices into the vertices.
+        indices: Vec<u32>,
+    },
+
+    /// A vertex buffer was drawn.
+    PushBuffers {
+        /// The ID of the vertex buffer.
+        buffer: u32,
+
+        /// The ID of the texture that was drawn with.
+        texture: u32,
+
+        /// The ID of the mask that was drawn with.
+        mask: u32,
+
+        /// The transform that was applied.
+        transform: Affine,
+
+        /// The size of the target.
+        size: (u32, u32),
+    },
+
+    /// The color matrix was set.
+    SetColorMatrix(Option<ColorMatrix>),
+
+    /// The composite mode was set.
+    SetCompositeMode(Option<CompositeMode>),
+
+    /// The clip shape was set.
+    SetClipShape(Option<ClipShape>),
+
+    /// The scissor rectangle was set.
+    SetScissor(Option<[u32; 4]>),
+
+    /// The projection was set.
+    SetProjection(Option<Projection>),
+
+    /// The custom brush was set.
+    SetCustomBrush(Option<CustomBrush>),
+
+    /// The interpolation that textures are sampled with was overridden.
+    SetSamplerInterpolation(Option<InterpolationMode>),
+
+    /// Pixels were read back from the target.
+    ReadPixels {
+        /// The origin of the area that was read.
+        origin: (u32, u32),
+
+        /// The size of the area that was read.
+        size: (u32, u32),
+
+        /// The size of the target.
+        target_size: (u32, u32),
+    },
+
+    /// A part of the target was cleared.
+    ClearRect {
+        /// The origin of the area that was cleared.
+        origin: (u32, u32),
+
+        /// The size of the area that was cleared.
+        size: (u32, u32),
+
+        /// The size of the target.
+        target_size: (u32, u32),
+
+        /// The color that the area was cleared to.
+        color: Color,
+    },
+
+    /// A texture was shown on an overlay.
+    ShowOverlay {
+        /// The ID of the texture.
+        id: u32,
+
+        /// The part of the texture that is shown.
+        source: Rect,
+
+        /// Where the texture is shown.
+        rect: Rect,
+    },
+
+    /// A render target was created.
+    CreateRenderTarget {
+        /// The ID of the new texture.
+        id: u32,
+
+        /// The size of the render target.
+        size: (u32, u32),
+    },
+
+    /// A render target was bound, or the surface if `None`.
+    SetRenderTarget(Option<u32>),
+
+    /// A depth-stencil buffer was created.
+    CreateDepthStencil((u32, u32)),
+
+    /// The depth-stencil buffer was attached or detached.
+    AttachDepthStencil(bool),
+
+    /// A clip path was pushed.
+    PushClipPath {
+        /// The ID of the vertex buffer holding the path.
+        buffer: u32,
+
+        /// The size of the target.
+        size: (u32, u32),
+    },
+
+    /// A clip path was popped.
+    PopClip {
+        /// The ID of the vertex buffer holding the path.
+        buffer: u32,
+
+        /// The size of the target.
+        size: (u32, u32),
+    },
+
+    /// The frame was presented.
+    Present,
+
+    /// The present mode was set.
+    SetPresentMode(PresentMode),
+
+    /// A barrier was inserted between draws.
+    Barrier,
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clear(value) => f.debug_tuple("Clear").field(value).finish(),
+            Self::Flush => f.write_str("Flush"),
+            Self::CreateTexture {
+                id,
+                interpolation,
+                repeat,
+            } => f
+                .debug_struct("CreateTexture")
+                .field("id", id)
+                .field("interpolation", &DebugInterpolation(*interpolation))
+                .field("repeat", repeat)
+                .finish(),
+            Self::DeleteTexture(value) => f.debug_tuple("DeleteTexture").field(value).finish(),
+            Self::WriteTexture {
+                id,
+                size,
+                format,
+                data,
+            } => f
+                .debug_struct("WriteTexture")
+                .field("id", id)
+                .field("size", size)
+                .field("format", format)
+                .field("data", data)
+                .finish(),
+            Self::WriteSubtexture {
+                id,
+                offset,
+                size,
+                format,
+                data,
+            } => f
+                .debug_struct("WriteSubtexture")
+                .field("id", id)
+                .field("offset", offset)
+                .field("size", size)
+                .field("format", format)
+                .field("data", data)
+                .finish(),
+            Self::SetTextureInterpolation { id, interpolation } => f
+                .debug_struct("SetTextureInterpolation")
+                .field("id", id)
+                .field("interpolation", &DebugInterpolation(*interpolation))
+                .finish(),
+            Self::GenerateMipmaps(value) => f.debug_tuple("GenerateMipmaps").field(value).finish(),
+            Self::CreateVertexBuffer(value) => {
+                f.debug_tuple("CreateVertexBuffer").field(value).finish()
+            }
+            Self::DeleteVertexBuffer(value) => {
+                f.debug_tuple("DeleteVertexBuffer").field(value).finish()
+            }
+            Self::WriteVertices {
+                id,
+                vertices,
+                indices,
+            } => f
+                .debug_struct("WriteVertices")
+                .field("id", id)
+                .field("vertices", vertices)
+                .field("indices", indices)
+                .finish(),
+            Self::PushBuffers {
+                buffer,
+                texture,
+                mask,
+                transform,
+                size,
+            } => f
+                .debug_struct("PushBuffers")
+                .field("buffer", buffer)
+                .field("texture", texture)
+                .field("mask", mask)
+                .field("transform", transform)
+                .field("size", size)
+                .finish(),
+            Self::SetColorMatrix(value) => f.debug_tuple("SetColorMatrix").field(value).finish(),
+            Self::SetCompositeMode(value) => {
+                f.debug_tuple("SetCompositeMode").field(value).finish()
+            }
+            Self::SetClipShape(value) => f.debug_tuple("SetClipShape").field(value).finish(),
+            Self::SetScissor(value) => f.debug_tuple("SetScissor").field(value).finish(),
+            Self::SetProjection(value) => f.debug_tuple("SetProjection").field(value).finish(),
+            Self::SetCustomBrush(value) => f.debug_tuple("SetCustomBrush").field(value).finish(),
+            Self::SetSamplerInterpolation(value) => f
+                .debug_tuple("SetSamplerInterpolation")
+                .field(&value.map(DebugInterpolation))
+                .finish(),
+            Self::ReadPixels {
+                origin,
+                size,
+                target_size,
+            } => f
+                .debug_struct("ReadPixels")
+                .field("origin", origin)
+                .field("size", size)
+                .field("target_size", target_size)
+                .finish(),
+            Self::ClearRect {
+                origin,
+                size,
+                target_size,
+                color,
+            } => f
+                .debug_struct("ClearRect")
+                .field("origin", origin)
+                .field("size", size)
+                .field("target_size", target_size)
+                .field("color", color)
+                .finish(),
+            Self::ShowOverlay { id, source, rect } => f
+                .debug_struct("ShowOverlay")
+                .field("id", id)
+                .field("source", source)
+                .field("rect", rect)
+                .finish(),
+            Self::CreateRenderTarget { id, size } => f
+                .debug_struct("CreateRenderTarget")
+                .field("id", id)
+                .field("size", size)
+                .finish(),
+            Self::SetRenderTarget(value) => f.debug_tuple("SetRenderTarget").field(value).finish(),
+            Self::CreateDepthStencil(value) => {
+                f.debug_tuple("CreateDepthStencil").field(value).finish()
+            }
+            Self::AttachDepthStencil(value) => {
+                f.debug_tuple("AttachDepthStencil").field(value).finish()
+            }
+            Self::PushClipPath { buffer, size } => f
+                .debug_struct("PushClipPath")
+                .field("buffer", buffer)
+                .field("size", size)
+                .finish(),
+            Self::PopClip { buffer, size } => f
+                .debug_struct("PopClip")
+                .field("buffer", buffer)
+                .field("size", size)
+                .finish(),
+            Self::Present => f.write_str("Present"),
+            Self::SetPresentMode(value) => f.debug_tuple("SetPresentMode").field(value).finish(),
+            Self::Barrier => f.write_str("Barrier"),
+        }
+    }
+}
+
+/// Print an [`InterpolationMode`], which doesn't implement [`Debug`](fmt::Debug).
+#[derive(Clone, Copy)]
+struct DebugInterpolation(InterpolationMode);
+
+impl fmt::Debug for DebugInterpolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.0 {
+            InterpolationMode::NearestNeighbor => "NearestNeighbor",
+            InterpolationMode::Bilinear => "Bilinear",
+        })
+    }
+}
+
+impl<C: GpuContext> GpuContext for RecordingContext<C> {
+    type Texture = RecordingTexture<C::Texture>;
+    type VertexBuffer = RecordingVertexBuffer<C::VertexBuffer>;
+    type Error = C::Error;
+
+    fn clear(&self, color: Color) {
+        self.inner.clear(color);
+        self.record(Command::Clear(color));
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.inner.flush()?;
+        self.record(Command::Flush);
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        interpolation: InterpolationMode,
+        repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        let inner = self.inner.create_texture(interpolation, repeat)?;
+        let id = self.next_id();
+        self.record(Command::CreateTexture {
+            id,
+            interpolation,
+            repeat,
+        });
+        Ok(RecordingTexture { id, inner })
+    }
+
+    fn delete_texture(&self, texture: Self::Texture) {
+        self.inner.delete_texture(texture.inner);
+        self.record(Command::DeleteTexture(texture.id));
+    }
+
+    fn write_texture(
+        &self,
+        texture: &Self::Texture,
+        size: (u32, u32),
+        format: ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        self.inner.write_texture(&texture.inner, size, format, data);
+        self.record(Command::WriteTexture {
+            id: texture.id,
+            size,
+            format,
+            data: data.map(<[u8]>::to_vec),
+        });
+    }
+
+    fn write_subtexture(
+        &self,
+        texture: &Self::Texture,
+        offset: (u32, u32),
+        size: (u32, u32),
+        format: ImageFormat,
+        data: &[u8],
+    ) {
+        self.inner
+            .write_subtexture(&texture.inner, offset, size, format, data);
+        self.record(Command::WriteSubtexture {
+            id: texture.id,
+            offset,
+            size,
+            format,
+            data: data.to_vec(),
+        });
+    }
+
+    fn set_texture_interpolation(&self, texture: &Self::Texture, interpolation: InterpolationMode) {
+        self.inner
+            .set_texture_interpolation(&texture.inner, interpolation);
+        self.record(Command::SetTextureInterpolation {
+            id: texture.id,
+            interpolation,
+        });
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        self.inner.max_texture_size()
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        let inner = self.inner.create_vertex_buffer()?;
+        let id = self.next_id();
+        self.record(Command::CreateVertexBuffer(id));
+        Ok(RecordingVertexBuffer { id, inner })
+    }
+
+    fn delete_vertex_buffer(&self, buffer: Self::VertexBuffer) {
+        self.inner.delete_vertex_buffer(buffer.inner);
+        self.record(Command::DeleteVertexBuffer(buffer.id));
+    }
+
+    fn write_vertices(&self, buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        self.inner.write_vertices(&buffer.inner, vertices, indices);
+        self.record(Command::WriteVertices {
+            id: buffer.id,
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+        });
+    }
+
+    fn push_buffers(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        current_texture: &Self::Texture,
+        mask_texture: &Self::Texture,
+        transform: &Affine,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.push_buffers(
+            &vertex_buffer.inner,
+            &current_texture.inner,
+            &mask_texture.inner,
+            transform,
+            size,
+        )?;
+        self.record(Command::PushBuffers {
+            buffer: vertex_buffer.id,
+            texture: current_texture.id,
+            mask: mask_texture.id,
+            transform: *transform,
+            size,
+        });
+        Ok(())
+    }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        let supported = self.inner.set_color_matrix(matrix);
+        if supported {
+            self.record(Command::SetColorMatrix(matrix.copied()));
+        }
+        supported
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        let supported = self.inner.set_composite_mode(mode);
+        if supported {
+            self.record(Command::SetCompositeMode(mode));
+        }
+        supported
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        let supported = self.inner.set_clip_shape(shape);
+        if supported {
+            self.record(Command::SetClipShape(shape.copied()));
+        }
+        supported
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        let supported = self.inner.set_scissor(rect);
+        if supported {
+            self.record(Command::SetScissor(rect));
+        }
+        supported
+    }
+
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        let supported = self.inner.set_projection(projection);
+        if supported {
+            self.record(Command::SetProjection(projection.copied()));
+        }
+        supported
+    }
+
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        let supported = self.inner.set_custom_brush(brush);
+        if supported {
+            self.record(Command::SetCustomBrush(brush.copied()));
+        }
+        supported
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        let supported = self.inner.set_sampler_interpolation(interpolation);
+        if supported {
+            self.record(Command::SetSamplerInterpolation(interpolation));
+        }
+        supported
+    }
+
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        let supported = self.inner.generate_mipmaps(&texture.inner);
+        if supported {
+            self.record(Command::GenerateMipmaps(texture.id));
+        }
+        supported
+    }
+
+    fn read_pixels(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.read_pixels(origin, size, target_size, data)?;
+        self.record(Command::ReadPixels {
+            origin,
+            size,
+            target_size,
+        });
+        Ok(supported)
+    }
+
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        target_size: (u32, u32),
+        color: Color,
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.clear_rect(origin, size, target_size, color)?;
+        if supported {
+            self.record(Command::ClearRect {
+                origin,
+                size,
+                target_size,
+                color,
+            });
+        }
+        Ok(supported)
+    }
+
+    fn show_overlay(&self, texture: &Self::Texture, source: Rect, rect: Rect) -> bool {
+        let supported = self.inner.show_overlay(&texture.inner, source, rect);
+        if supported {
+            self.record(Command::ShowOverlay {
+                id: texture.id,
+                source,
+                rect,
+            });
+        }
+        supported
+    }
+
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<Self::Texture>, Self::Error> {
+        let inner = match self.inner.create_render_target(size)? {
+            Some(inner) => inner,
+            None => return Ok(None),
+        };
+        let id = self.next_id();
+        self.record(Command::CreateRenderTarget { id, size });
+        Ok(Some(RecordingTexture { id, inner }))
+    }
+
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        self.inner
+            .set_render_target(target.map(|target| &target.inner))?;
+        self.record(Command::SetRenderTarget(target.map(|target| target.id)));
+        Ok(())
+    }
+
+    fn render_targets_flipped(&self) -> bool {
+        self.inner.render_targets_flipped()
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        self.inner.msaa_samples()
+    }
+
+    fn begin_occlusion_query(&self, id: u64) -> bool {
+        self.inner.begin_occlusion_query(id)
+    }
+
+    fn end_occlusion_query(&self, id: u64) {
+        self.inner.end_occlusion_query(id)
+    }
+
+    fn occlusion_query_result(&self, id: u64) -> Option<bool> {
+        self.inner.occlusion_query_result(id)
+    }
+
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+        let supported = self.inner.create_depth_stencil(size)?;
+        if supported {
+            self.record(Command::CreateDepthStencil(size));
+        }
+        Ok(supported)
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        self.inner.attach_depth_stencil(attach)?;
+        self.record(Command::AttachDepthStencil(attach));
+        Ok(())
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        let supported = self.inner.push_clip_path(&vertex_buffer.inner, size)?;
+        if supported {
+            self.record(Command::PushClipPath {
+                buffer: vertex_buffer.id,
+                size,
+            });
+        }
+        Ok(supported)
+    }
+
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        self.inner.pop_clip(&vertex_buffer.inner, size)?;
+        self.record(Command::PopClip {
+            buffer: vertex_buffer.id,
+            size,
+        });
+        Ok(())
+    }
+
+    fn present(&self) -> Result<(), Self::Error> {
+        self.inner.present()?;
+        self.record(Command::Present);
+        Ok(())
+    }
+
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        let supported = self.inner.set_present_mode(mode);
+        if supported {
+            self.record(Command::SetPresentMode(mode));
+        }
+        supported
+    }
+
+    fn barrier(&self) -> Result<(), Self::Error> {
+        self.inner.barrier()?;
+        self.record(Command::Barrier);
+        Ok(())
+    }
+}
@@ -21,14 +21,16 @@
 
 //! Defines useful resource wrappers.
 
+use super::brush::SpreadMode;
 use super::gpu_backend::{GpuContext, RepeatStrategy, Vertex};
+use super::memory::{Category, MemoryTracker};
 
-use piet::kurbo::{Size, Vec2};
-use piet::{
-    Error as Pierror, FixedLinearGradient, FixedRadialGradient, GradientStop, InterpolationMode,
-};
+use piet::kurbo::{Affine, Point, Size, Vec2};
+use piet::{Error as Pierror, FixedRadialGradient, GradientStop, InterpolationMode};
 use tiny_skia::{Paint, Pixmap, Shader};
 
+use std::cell::Cell;
+use std::mem;
 use std::rc::Rc;
 
 macro_rules! define_resource_wrappers {
@@ -37,6 +39,12 @@ macro_rules! define_resource_wrappers {
             pub(crate) struct $name<C: GpuContext + ?Sized> {
                 context: Rc<C>,
                 resource: Option<C::$res>,
+
+                /// The bytes that the resource takes up.
+                bytes: Cell<usize>,
+
+                /// The tracker that the bytes are counted in, and what they are counted as.
+                memory: Option<(Rc<MemoryTracker>, Category)>,
             }
 
             impl<C: GpuContext + ?Sized> Drop for $name<C> {
@@ -44,6 +52,7 @@ macro_rules! define_resource_wrappers {
                     if let Some(resource) = self.resource.take() {
                         self.context.$delete(resource);
                     }
+                    self.set_bytes(0);
                 }
             }
 
@@ -55,12 +64,33 @@ macro_rules! define_resource_wrappers {
                     Self {
                         context: context.clone(),
                         resource: Some(resource),
+                        bytes: Cell::new(0),
+                        memory: None,
                     }
                 }
 
                 pub(crate) fn resource(&self) -> &C::$res {
                     self.resource.as_ref().unwrap()
                 }
+
+                /// Count the memory of this resource as `category` in `memory`.
+                pub(crate) fn tracked(
+                    mut self,
+                    memory: &Rc<MemoryTracker>,
+                    category: Category,
+                ) -> Self {
+                    memory.resize(category, 0, self.bytes.get());
+                    self.memory = Some((memory.clone(), category));
+                    self
+                }
+
+                /// Set how many bytes the resource takes up.
+                pub(crate) fn set_bytes(&self, bytes: usize) {
+                    let old = self.bytes.replace(bytes);
+                    if let Some((memory, category)) = &self.memory {
+                        memory.resize(*category, old, bytes);
+                    }
+                }
             }
         )*
     };
@@ -82,36 +112,46 @@ impl<C: GpuContext + ?Sized> Texture<C> {
         Ok(Self::from_raw(context, resource))
     }
 
-    pub(crate) fn write_linear_gradient(
+    /// Write the colors of a linear gradient from its start to its end into a one pixel high
+    /// ramp of `width` pixels.
+    ///
+    /// Padded ramps have the colors of the first and last stops at the centers of their end
+    /// pixels, so that clamping to the edge continues them. Other ramps are spread over the
+    /// whole width, so that the texture tiles seamlessly.
+    pub(crate) fn write_linear_ramp(
         &self,
-        gradient: &FixedLinearGradient,
-        size: Size,
-        offset: Vec2,
+        stops: &[GradientStop],
+        width: u32,
+        spread: SpreadMode,
     ) -> Result<(), Pierror> {
+        let inset = match spread {
+            SpreadMode::Pad => 0.5,
+            SpreadMode::Repeat | SpreadMode::Reflect => 0.0,
+        };
         let shader = tiny_skia::LinearGradient::new(
-            convert_to_ts_point(gradient.start),
-            convert_to_ts_point(gradient.end),
-            gradient
-                .stops
-                .iter()
-                .map(convert_to_ts_gradient_stop)
-                .collect(),
+            tiny_skia::Point::from_xy(inset, 0.5),
+            tiny_skia::Point::from_xy(width as f32 - inset, 0.5),
+            stops.iter().map(convert_to_ts_gradient_stop).collect(),
             tiny_skia::SpreadMode::Pad,
-            tiny_skia::Transform::from_translate(offset.x as f32, offset.y as f32),
+            tiny_skia::Transform::identity(),
         )
         .ok_or_else(|| Pierror::BackendError("Invalid error".into()))?;
 
-        self.write_shader(shader, size);
+        self.write_shader(shader, Size::new(width as f64, 1.0));
 
         Ok(())
     }
 
+    /// Write a radial gradient into a texture of `size` pixels, where `transform` maps user
+    /// space to the pixels of the texture.
     pub(crate) fn write_radial_gradient(
         &self,
         gradient: &FixedRadialGradient,
         size: Size,
-        offset: Vec2,
+        spread: SpreadMode,
+        transform: Affine,
     ) -> Result<(), Pierror> {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
         let shader = tiny_skia::RadialGradient::new(
             convert_to_ts_point(gradient.center),
             convert_to_ts_point(gradient.center + gradient.origin_offset),
@@ -121,8 +161,10 @@ impl<C: GpuContext + ?Sized> Texture<C> {
                 .iter()
                 .map(convert_to_ts_gradient_stop)
                 .collect(),
-            tiny_skia::SpreadMode::Pad,
-            tiny_skia::Transform::from_translate(offset.x as f32, offset.y as f32),
+            spread.to_tiny_skia(),
+            tiny_skia::Transform::from_row(
+                a as f32, b as f32, c as f32, d as f32, e as f32, f as f32,
+            ),
         )
         .ok_or_else(|| Pierror::BackendError("Invalid error".into()))?;
 
@@ -131,27 +173,62 @@ impl<C: GpuContext + ?Sized> Texture<C> {
         Ok(())
     }
 
+    /// Write a sweep gradient around `center` into a texture of `size` pixels, whose origin is
+    /// at `-offset` in user space.
+    ///
+    /// tiny-skia can't render sweep gradients, so the stops are rendered into a ramp that is
+    /// looked up by the angle of every pixel.
+    pub(crate) fn write_sweep_gradient(
+        &self,
+        center: Point,
+        start_angle: f64,
+        stops: &[GradientStop],
+        size: Size,
+        offset: Vec2,
+    ) -> Result<(), Pierror> {
+        const RAMP_WIDTH: u32 = 1024;
+
+        let shader = tiny_skia::LinearGradient::new(
+            tiny_skia::Point::from_xy(0.5, 0.5),
+            tiny_skia::Point::from_xy(RAMP_WIDTH as f32 - 0.5, 0.5),
+            stops.iter().map(convert_to_ts_gradient_stop).collect(),
+            tiny_skia::SpreadMode::Pad,
+            tiny_skia::Transform::identity(),
+        )
+        .ok_or_else(|| Pierror::BackendError("Invalid error".into()))?;
+        let ramp = render_shader(shader, Size::new(RAMP_WIDTH as f64, 1.0));
+        let ramp = ramp.data();
+
+        let (width, height) = (size.width as u32, size.height as u32);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let point = Point::new(x as f64 + 0.5, y as f64 + 0.5) - offset;
+                let angle = (point - center).atan2() - start_angle;
+                let t = angle.rem_euclid(std::f64::consts::TAU) / std::f64::consts::TAU;
+
+                // Interpolate between the two closest pixels of the ramp.
+                let pos = t * (RAMP_WIDTH - 1) as f64;
+                let left = (pos.floor() as usize).min(RAMP_WIDTH as usize - 1);
+                let right = (left + 1).min(RAMP_WIDTH as usize - 1);
+                let frac = pos - left as f64;
+                for channel in 0..4 {
+                    let a = ramp[left * 4 + channel] as f64;
+                    let b = ramp[right * 4 + channel] as f64;
+                    data.push((a + (b - a) * frac).round() as u8);
+                }
+            }
+        }
+
+        self.write_texture((width, height), piet::ImageFormat::RgbaPremul, Some(&data));
+        self.set_interpolation(InterpolationMode::Bilinear);
+
+        Ok(())
+    }
+
     pub(crate) fn write_shader(&self, shader: Shader<'_>, size: Size) {
-        // Create a pixmap to render the shader into.
-        let mut pixmap =
-            Pixmap::new(size.width as _, size.height as _).expect("failed to create pixmap");
-
-        // Render the shader into the pixmap.
-        let paint = Paint {
-            shader,
-            ..Default::default()
-        };
-        pixmap
-            .fill_rect(
-                tiny_skia::Rect::from_xywh(0.0, 0.0, size.width as _, size.height as _).unwrap(),
-                &paint,
-                tiny_skia::Transform::identity(),
-                None,
-            )
-            .expect("failed to render shader");
-
-        // Write the pixmap into the texture.
-        let data = pixmap.take();
+        // Write the rendered shader into the texture.
+        let data = render_shader(shader, size).take();
         self.write_texture(
             (size.width as _, size.height as _),
             piet::ImageFormat::RgbaPremul,
@@ -168,6 +245,7 @@ impl<C: GpuContext + ?Sized> Texture<C> {
     ) {
         self.context
             .write_texture(self.resource(), size, format, data);
+        self.set_bytes(size.0 as usize * size.1 as usize * format.bytes_per_pixel());
     }
 
     pub(crate) fn write_subtexture(
@@ -194,10 +272,32 @@ impl<C: GpuContext + ?Sized> VertexBuffer<C> {
     }
 
     pub(crate) fn upload(&self, data: &[Vertex], indices: &[u32]) {
-        self.context.write_vertices(self.resource(), data, indices)
+        self.context.write_vertices(self.resource(), data, indices);
+        self.set_bytes(mem::size_of_val(data) + mem::size_of_val(indices));
     }
 }
 
+/// Render a shader into a new pixmap of `size` pixels.
+fn render_shader(shader: Shader<'_>, size: Size) -> Pixmap {
+    let mut pixmap =
+        Pixmap::new(size.width as _, size.height as _).expect("failed to create pixmap");
+
+    let paint = Paint {
+        shader,
+        ..Default::default()
+    };
+    pixmap
+        .fill_rect(
+            tiny_skia::Rect::from_xywh(0.0, 0.0, size.width as _, size.height as _).unwrap(),
+            &paint,
+            tiny_skia::Transform::identity(),
+            None,
+        )
+        .expect("failed to render shader");
+
+    pixmap
+}
+
 fn convert_to_ts_point(point: piet::kurbo::Point) -> tiny_skia::Point {
     tiny_skia::Point {
         x: point.x as f32,
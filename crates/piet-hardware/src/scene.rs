@@ -0,0 +1,337 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Geometry that is kept on the GPU and drawn again with different transforms.
+
+use super::gpu_backend::{GpuContext, Vertex};
+use super::memory::{Category, MemoryTracker};
+use super::resources::{Texture, VertexBuffer};
+use super::ResultExt;
+
+use piet::kurbo::Affine;
+use piet::{Error as Pierror, InterpolationMode};
+
+use std::fmt;
+use std::rc::Rc;
+
+/// Geometry that has been tessellated and uploaded to the GPU once, so that it can be drawn
+/// many times with different transforms.
+///
+/// Scenes are recorded with [`RenderContext::begin_scene`] and drawn with
+/// [`RenderContext::draw_scene`]. Drawing a scene costs one [`GpuContext::push_buffers`] call
+/// for every run of shapes that share a texture and transform, and doesn't tessellate or
+/// upload anything, which makes panning and zooming large canvases cheap.
+///
+/// [`RenderContext::begin_scene`]: crate::RenderContext::begin_scene
+/// [`RenderContext::draw_scene`]: crate::RenderContext::draw_scene
+pub struct Scene<C: GpuContext + ?Sized> {
+    /// The batches of geometry, in the order they are drawn.
+    batches: Vec<Batch<C>>,
+
+    /// The scale that the scene was recorded to be drawn at.
+    detail: f64,
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for Scene<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scene")
+            .field("batches", &self.batches.len())
+            .field("detail", &self.detail)
+            .finish()
+    }
+}
+
+impl<C: GpuContext + ?Sized> Scene<C> {
+    /// Whether nothing was drawn into this scene.
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// The scale that this scene was recorded to be drawn at.
+    ///
+    /// Curves are flattened finely enough for the scene to be drawn scaled up by this much.
+    /// See [`RenderContext::begin_scene_with_detail`].
+    ///
+    /// [`RenderContext::begin_scene_with_detail`]: crate::RenderContext::begin_scene_with_detail
+    pub fn detail(&self) -> f64 {
+        self.detail
+    }
+
+    /// Iterate over the batches of this scene.
+    pub(crate) fn batches(&self) -> impl Iterator<Item = &Batch<C>> {
+        self.batches.iter()
+    }
+}
+
+/// Recordings of the same geometry at several levels of detail.
+///
+/// Every level is a [`Scene`] recorded with [`RenderContext::begin_scene_with_detail`] at
+/// the detail given by [`missing_detail`](Self::missing_detail), which is a power of two.
+/// [`RenderContext::draw_scene_levels`] draws the coarsest level that is detailed enough for
+/// the current transform. Zooming in only records the geometry again once the scale doubles,
+/// and zooming back out goes back to the coarser meshes.
+///
+/// [`RenderContext::begin_scene_with_detail`]: crate::RenderContext::begin_scene_with_detail
+/// [`RenderContext::draw_scene_levels`]: crate::RenderContext::draw_scene_levels
+pub struct SceneLevels<C: GpuContext + ?Sized> {
+    /// The levels, from the least to the most detailed.
+    levels: Vec<Scene<C>>,
+}
+
+impl<C: GpuContext + ?Sized> fmt::Debug for SceneLevels<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.levels.iter().map(|scene| scene.detail))
+            .finish()
+    }
+}
+
+impl<C: GpuContext + ?Sized> Default for SceneLevels<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: GpuContext + ?Sized> SceneLevels<C> {
+    /// Create a new set of levels without any levels.
+    pub fn new() -> Self {
+        Self { levels: Vec::new() }
+    }
+
+    /// Get the number of levels.
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Whether there are no levels.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Iterate over the details of the levels, from the least to the most detailed.
+    pub fn details(&self) -> impl Iterator<Item = f64> + '_ {
+        self.levels.iter().map(|scene| scene.detail)
+    }
+
+    /// Get the level of detail that geometry drawn with `transform` should be recorded at.
+    ///
+    /// This is the scale of the transform, rounded up to a power of two.
+    pub fn detail_for(transform: Affine) -> f64 {
+        let scale = transform_scale(transform);
+        let exponent = scale
+            .log2()
+            .ceil()
+            .clamp(-MAX_DETAIL_EXPONENT, MAX_DETAIL_EXPONENT);
+        2.0f64.powi(exponent as i32)
+    }
+
+    /// Get the level of detail to record for drawing with `transform`, if there is no level
+    /// for it yet.
+    pub fn missing_detail(&self, transform: Affine) -> Option<f64> {
+        let detail = Self::detail_for(transform);
+        match self.levels.iter().any(|scene| scene.detail == detail) {
+            true => None,
+            false => Some(detail),
+        }
+    }
+
+    /// Add a level, returning the level with the same detail that it replaces.
+    pub fn insert(&mut self, scene: Scene<C>) -> Option<Scene<C>> {
+        match self
+            .levels
+            .binary_search_by(|level| level.detail.total_cmp(&scene.detail))
+        {
+            Ok(i) => Some(std::mem::replace(&mut self.levels[i], scene)),
+            Err(i) => {
+                self.levels.insert(i, scene);
+                None
+            }
+        }
+    }
+
+    /// Remove the level with the given detail.
+    pub fn remove(&mut self, detail: f64) -> Option<Scene<C>> {
+        let i = self
+            .levels
+            .iter()
+            .position(|scene| scene.detail == detail)?;
+        Some(self.levels.remove(i))
+    }
+
+    /// Remove every level.
+    pub fn clear(&mut self) {
+        self.levels.clear();
+    }
+
+    /// Remove the levels that are more than `distance` powers of two away from the level for
+    /// drawing with `transform`.
+    pub fn prune(&mut self, transform: Affine, distance: u32) {
+        let detail = Self::detail_for(transform);
+        let range = 0.5f64.powi(distance as i32)..=2.0f64.powi(distance as i32);
+        self.levels
+            .retain(|scene| range.contains(&(scene.detail / detail)));
+    }
+
+    /// Get the level to draw with `transform`.
+    ///
+    /// This is the least detailed level that can be scaled up by the transform, or the most
+    /// detailed level if none of them can.
+    pub(crate) fn select(&self, transform: Affine) -> Option<&Scene<C>> {
+        let scale = transform_scale(transform);
+        self.levels
+            .iter()
+            .find(|scene| scene.detail >= scale)
+            .or_else(|| self.levels.last())
+    }
+}
+
+/// The largest power of two that levels of detail are recorded at, and the inverse of the
+/// smallest.
+const MAX_DETAIL_EXPONENT: f64 = 16.0;
+
+/// Get how much a transform scales the geometry drawn with it.
+fn transform_scale(transform: Affine) -> f64 {
+    let scale = transform.determinant().abs().sqrt();
+    match scale.is_finite() && scale > 0.0 {
+        true => scale,
+        false => 1.0,
+    }
+}
+
+/// A run of triangles that share a texture and a transform.
+pub(crate) struct Batch<C: GpuContext + ?Sized> {
+    /// The buffer holding the triangles.
+    pub(crate) vbo: VertexBuffer<C>,
+
+    /// The texture to draw with and how to sample it, or `None` for the white pixel.
+    pub(crate) texture: Option<(Rc<Texture<C>>, InterpolationMode)>,
+
+    /// The transform from the scene's coordinates to the triangles' coordinates.
+    pub(crate) transform: Affine,
+}
+
+/// Collects pushed triangles into batches while a scene is being recorded.
+pub(crate) struct SceneRecorder<C: GpuContext + ?Sized> {
+    /// The batches that have been uploaded so far.
+    batches: Vec<Batch<C>>,
+
+    /// The vertices of the batch being collected.
+    vertices: Vec<Vertex>,
+
+    /// The indices of the batch being collected.
+    indices: Vec<u32>,
+
+    /// The texture of the batch being collected, and how to sample it.
+    texture: Option<(Rc<Texture<C>>, InterpolationMode)>,
+
+    /// The transform of the batch being collected.
+    transform: Affine,
+
+    /// The depth of the state stack when recording started.
+    depth: usize,
+
+    /// The tracker that the memory of the batches is counted in.
+    memory: Rc<MemoryTracker>,
+
+    /// The scale that the scene is recorded to be drawn at.
+    detail: f64,
+}
+
+impl<C: GpuContext + ?Sized> SceneRecorder<C> {
+    /// Create a new, empty recorder.
+    pub(crate) fn new(depth: usize, memory: Rc<MemoryTracker>, detail: f64) -> Self {
+        Self {
+            depth,
+            memory,
+            detail,
+            batches: Vec::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            texture: None,
+            transform: Affine::IDENTITY,
+        }
+    }
+
+    /// The depth of the state stack when recording started.
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The scale that the scene is recorded to be drawn at.
+    pub(crate) fn detail(&self) -> f64 {
+        self.detail
+    }
+
+    /// Add triangles to the scene.
+    pub(crate) fn push(
+        &mut self,
+        context: &Rc<C>,
+        vertices: &[Vertex],
+        indices: &[u32],
+        texture: Option<(&Rc<Texture<C>>, InterpolationMode)>,
+        transform: Affine,
+    ) -> Result<(), Pierror> {
+        let same_texture = match (&self.texture, texture) {
+            (None, None) => true,
+            (Some((a, a_interp)), Some((b, b_interp))) => Rc::ptr_eq(a, b) && *a_interp == b_interp,
+            _ => false,
+        };
+        if !same_texture || self.transform != transform {
+            self.upload(context)?;
+            self.texture = texture.map(|(texture, interp)| (texture.clone(), interp));
+            self.transform = transform;
+        }
+
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices
+            .extend(indices.iter().map(|index| index + base));
+        Ok(())
+    }
+
+    /// Finish recording the scene.
+    pub(crate) fn finish(mut self, context: &Rc<C>) -> Result<Scene<C>, Pierror> {
+        self.upload(context)?;
+        Ok(Scene {
+            batches: self.batches,
+            detail: self.detail,
+        })
+    }
+
+    /// Upload the batch being collected to the GPU.
+    fn upload(&mut self, context: &Rc<C>) -> Result<(), Pierror> {
+        if !self.indices.is_empty() {
+            let vbo = VertexBuffer::new(context)
+                .piet_err()?
+                .tracked(&self.memory, Category::VertexBuffers);
+            vbo.upload(&self.vertices, &self.indices);
+            self.batches.push(Batch {
+                vbo,
+                texture: self.texture.take(),
+                transform: self.transform,
+            });
+        }
+
+        self.vertices.clear();
+        self.indices.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,532 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Blurred rectangles, rendered once and stretched to size, and blurred text.
+
+use super::atlas::{self, Atlas};
+use super::gpu_backend::{GpuContext, RepeatStrategy};
+use super::mask::elements_to_skia_path;
+use super::memory::{Category, MemoryTracker};
+use super::rasterizer::TessRect;
+use super::resources::Texture;
+use super::text::{Text, TextLayout};
+use super::ResultExt;
+
+use cosmic_text::SwashContent;
+use piet::kurbo::{Point, Rect, RoundedRect, Shape, Size};
+use piet::{Error as Pierror, InterpolationMode};
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Transform};
+
+use ahash::RandomState;
+use hashbrown::HashMap;
+
+use std::hash::Hash;
+use std::mem;
+use std::rc::Rc;
+
+/// How far the blur reaches, in multiples of the blur radius.
+///
+/// This is the same extent that `piet` uses for its other backends.
+pub(crate) const BLUR_EXTENT: f64 = 2.5;
+
+/// The number of shadows to keep in each generation of the cache.
+const MAX_CACHED_SHADOWS: usize = 64;
+
+/// The blurred rectangles and text that were rendered recently.
+///
+/// UIs draw the same card and button shadows over and over, so shadows are keyed by their
+/// size, corner radius and blur radius. Sides that are long enough are split into a fixed
+/// size corner and a stretched middle, so shadows of any length share one texture.
+///
+/// Text shadows are keyed by their layout, blur radius and the scale they are rasterized at.
+pub(crate) struct ShadowCache<C: GpuContext + ?Sized> {
+    /// The shadows of rectangles.
+    rects: Generations<ShadowKey, Rc<Shadow<C>>>,
+
+    /// The shadows of text layouts, along with the identities of the layouts.
+    text: Generations<TextShadowKey, (Rc<()>, Rc<TextShadow<C>>)>,
+
+    /// The tracker that the memory of shadows is counted in.
+    memory: Rc<MemoryTracker>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ShadowKey {
+    /// The width of the rendered rectangle, or `None` if it is stretched.
+    width: Option<u64>,
+
+    /// The height of the rendered rectangle, or `None` if it is stretched.
+    height: Option<u64>,
+
+    /// The corner radius of the rectangle.
+    corner: u64,
+
+    /// The blur radius.
+    radius: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TextShadowKey {
+    /// The address of the identity of the layout.
+    ///
+    /// The shadow keeps the identity alive, so no other layout can take its address.
+    layout: usize,
+
+    /// The blur radius.
+    radius: u64,
+
+    /// The scale that the glyphs were rasterized at.
+    scale: u64,
+}
+
+/// Two generations of cached values.
+///
+/// When the newer one fills up, the older one is dropped, and values from it that are used
+/// again move into the newer one.
+struct Generations<K, V> {
+    /// The values that were used since the last generation was dropped.
+    current: HashMap<K, V, RandomState>,
+
+    /// The values of the previous generation.
+    previous: HashMap<K, V, RandomState>,
+}
+
+impl<K: Eq + Hash, V: Clone> Generations<K, V> {
+    fn new(hasher: RandomState) -> Self {
+        Self {
+            current: HashMap::with_hasher(hasher.clone()),
+            previous: HashMap::with_hasher(hasher),
+        }
+    }
+
+    fn get(&mut self, key: K) -> Option<V> {
+        if let Some(value) = self.current.get(&key) {
+            return Some(value.clone());
+        }
+
+        let value = self.previous.remove(&key)?;
+        self.insert(key, value.clone());
+        Some(value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.current.len() >= MAX_CACHED_SHADOWS {
+            mem::swap(&mut self.previous, &mut self.current);
+            self.current.clear();
+        }
+
+        self.current.insert(key, value);
+    }
+
+    fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+    }
+}
+
+impl<C: GpuContext + ?Sized> ShadowCache<C> {
+    /// Create a new, empty cache.
+    pub(crate) fn new(memory: Rc<MemoryTracker>, hasher: RandomState) -> Self {
+        Self {
+            rects: Generations::new(hasher.clone()),
+            text: Generations::new(hasher),
+            memory,
+        }
+    }
+
+    /// Get the shadow of a rectangle of `size`, rendering it if it isn't cached.
+    pub(crate) fn get(
+        &mut self,
+        context: &Rc<C>,
+        size: Size,
+        corner: f64,
+        radius: f64,
+    ) -> Result<Rc<Shadow<C>>, Pierror> {
+        let padding = (BLUR_EXTENT * radius).ceil();
+        let corner = corner.clamp(0.0, size.width.min(size.height) / 2.0);
+
+        // Past this far from an edge, the shadow no longer changes along that edge.
+        let edge = padding + corner.ceil();
+        let side = |len: f64| (len >= edge * 2.0).then_some(edge * 2.0);
+        let (stretch_x, stretch_y) = (side(size.width), side(size.height));
+
+        let key = ShadowKey {
+            width: stretch_x.is_none().then(|| size.width.to_bits()),
+            height: stretch_y.is_none().then(|| size.height.to_bits()),
+            corner: corner.to_bits(),
+            radius: radius.to_bits(),
+        };
+
+        if let Some(shadow) = self.rects.get(key) {
+            return Ok(shadow);
+        }
+
+        let inner = Size::new(
+            stretch_x.unwrap_or(size.width),
+            stretch_y.unwrap_or(size.height),
+        );
+        let stretch = (stretch_x.is_some(), stretch_y.is_some());
+        let shadow = Rc::new(Shadow::render(
+            context,
+            &self.memory,
+            inner,
+            corner,
+            radius,
+            padding,
+            stretch,
+        )?);
+        self.rects.insert(key, shadow.clone());
+        Ok(shadow)
+    }
+
+    /// Get the shadow of the text layout with `identity`, rendering it with `render` if it
+    /// isn't cached.
+    ///
+    /// `render` is given the tracker to count the memory of the texture in.
+    pub(crate) fn get_text(
+        &mut self,
+        identity: &Rc<()>,
+        radius: f64,
+        scale: f64,
+        render: impl FnOnce(&Rc<MemoryTracker>) -> Result<TextShadow<C>, Pierror>,
+    ) -> Result<Rc<TextShadow<C>>, Pierror> {
+        let key = TextShadowKey {
+            layout: Rc::as_ptr(identity) as usize,
+            radius: radius.to_bits(),
+            scale: scale.to_bits(),
+        };
+
+        if let Some((_, shadow)) = self.text.get(key) {
+            return Ok(shadow);
+        }
+
+        let shadow = Rc::new(render(&self.memory)?);
+        self.text.insert(key, (identity.clone(), shadow.clone()));
+        Ok(shadow)
+    }
+
+    /// Drop every cached shadow.
+    pub(crate) fn clear(&mut self) {
+        self.rects.clear();
+        self.text.clear();
+    }
+}
+
+/// The blurred glyphs of a text layout, rendered into a texture.
+pub(crate) struct TextShadow<C: GpuContext + ?Sized> {
+    /// The texture holding the shadow, as white with premultiplied alpha, or `None` if the
+    /// layout doesn't have any visible glyphs.
+    texture: Option<Rc<Texture<C>>>,
+
+    /// The area that the texture covers, relative to the origin of the layout.
+    area: Rect,
+}
+
+impl<C: GpuContext + ?Sized> TextShadow<C> {
+    /// Wrap a texture that covers `area`, relative to the origin of the layout.
+    fn new(texture: Option<Texture<C>>, area: Rect) -> Self {
+        Self {
+            texture: texture.map(Rc::new),
+            area,
+        }
+    }
+
+    /// Get the texture holding the shadow, if there is anything to draw.
+    pub(crate) fn texture(&self) -> Option<&Rc<Texture<C>>> {
+        self.texture.as_ref()
+    }
+
+    /// Get the area that the texture covers, relative to the origin of the layout.
+    pub(crate) fn area(&self) -> Rect {
+        self.area
+    }
+}
+
+/// A blurred rectangle, rendered into a texture.
+pub(crate) struct Shadow<C: GpuContext + ?Sized> {
+    /// The texture holding the shadow, as white with premultiplied alpha.
+    texture: Rc<Texture<C>>,
+
+    /// The size of the texture.
+    texture_size: Size,
+
+    /// The size of the rectangle in the texture.
+    inner: Size,
+
+    /// How far the blur reaches past the rectangle.
+    padding: f64,
+
+    /// Whether the middle of the texture is stretched horizontally and vertically.
+    stretch: (bool, bool),
+}
+
+impl<C: GpuContext + ?Sized> Shadow<C> {
+    /// Render a blurred rectangle of `inner` size.
+    fn render(
+        context: &Rc<C>,
+        memory: &Rc<MemoryTracker>,
+        inner: Size,
+        corner: f64,
+        radius: f64,
+        padding: f64,
+        stretch: (bool, bool),
+    ) -> Result<Self, Pierror> {
+        let width = inner.width.ceil() + padding * 2.0;
+        let height = inner.height.ceil() + padding * 2.0;
+        let (max_width, max_height) = context.max_texture_size();
+        if width > max_width as f64 || height > max_height as f64 {
+            return Err(Pierror::InvalidInput);
+        }
+        let (width, height) = (width as u32, height as u32);
+
+        // Rasterize the rectangle, then blur its coverage.
+        let mut pixmap = Pixmap::new(width, height).ok_or(Pierror::InvalidInput)?;
+        let shape =
+            RoundedRect::from_rect(Rect::from_origin_size((padding, padding), inner), corner);
+        let mut builder = PathBuilder::new();
+        elements_to_skia_path(&mut builder, shape.path_elements(0.1))?;
+        if let Some(path) = builder.finish() {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(0xFF, 0xFF, 0xFF, 0xFF);
+            pixmap.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+
+        let coverage: Vec<f32> = pixmap
+            .pixels()
+            .iter()
+            .map(|pixel| pixel.alpha() as f32 / 255.0)
+            .collect();
+        let texture = blurred_texture(context, coverage, (width, height), radius, padding)?
+            .tracked(memory, Category::Other);
+
+        Ok(Self {
+            texture: Rc::new(texture),
+            texture_size: Size::new(width as f64, height as f64),
+            inner,
+            padding,
+            stretch,
+        })
+    }
+
+    /// Get the texture holding the shadow.
+    pub(crate) fn texture(&self) -> &Rc<Texture<C>> {
+        &self.texture
+    }
+
+    /// Get the rectangles that draw the shadow of `rect` with `color`.
+    ///
+    /// Stretched sides are split into two fixed corners and a middle that repeats the
+    /// center of the texture, for up to nine rectangles.
+    pub(crate) fn rects(&self, rect: Rect, color: piet::Color) -> Vec<TessRect> {
+        let padding = self.padding;
+        let axis = |start: f64, end: f64, stretch: bool, inner: f64, len: f64| {
+            if stretch {
+                // The texture is split in the middle of the rectangle.
+                let edge = inner / 2.0;
+                let mid = (padding + edge) / len;
+                vec![
+                    (start - padding, start + edge, 0.0, mid),
+                    (start + edge, end - edge, mid, mid),
+                    (end - edge, end + padding, mid, 1.0),
+                ]
+            } else {
+                vec![(start - padding, start - padding + len, 0.0, 1.0)]
+            }
+        };
+
+        let xs = axis(
+            rect.x0,
+            rect.x1,
+            self.stretch.0,
+            self.inner.width,
+            self.texture_size.width,
+        );
+        let ys = axis(
+            rect.y0,
+            rect.y1,
+            self.stretch.1,
+            self.inner.height,
+            self.texture_size.height,
+        );
+
+        ys.iter()
+            .flat_map(|&(y0, y1, v0, v1)| {
+                xs.iter().map(move |&(x0, x1, u0, u1)| TessRect {
+                    pos: Rect::new(x0, y0, x1, y1),
+                    uv: Rect::new(u0, v0, u1, v1),
+                    color,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Render the blurred glyphs of a text layout, with the glyphs rasterized at `scale` times
+/// their size.
+pub(crate) fn render_text<C: GpuContext + ?Sized>(
+    context: &Rc<C>,
+    memory: &Rc<MemoryTracker>,
+    text: &Text,
+    atlas: &mut Atlas<C>,
+    layout: &TextLayout,
+    radius: f64,
+    scale: f64,
+) -> Result<TextShadow<C>, Pierror> {
+    // The atlas only keeps glyphs on the GPU, so rasterize them again.
+    let mut glyphs = Vec::new();
+    for (i, run) in layout.buffer().layout_runs().enumerate() {
+        for (j, glyph) in run.glyphs.iter().enumerate() {
+            let key = atlas::key_at_scale(glyph.cache_key, scale);
+            let image = text.with_font_system_mut(|fs| atlas.rasterize(key, fs))?;
+            let origin = Point::new(
+                (glyph.x_int as f64 + layout.tab_shift(i, j)) * scale + image.placement.left as f64,
+                (glyph.y_int as f64 + run.line_y as f64) * scale - image.placement.top as f64,
+            )
+            .round();
+            glyphs.push((origin, image));
+        }
+    }
+
+    // Measure the blur in pixels of the texture.
+    let radius = radius * scale;
+    let padding = (BLUR_EXTENT * radius).ceil();
+    let area = glyphs
+        .iter()
+        .map(|(origin, image)| {
+            let size = (image.placement.width as f64, image.placement.height as f64);
+            Rect::from_origin_size(*origin, size)
+        })
+        .filter(|rect| rect.area() > 0.0)
+        .reduce(|a, b| a.union(b));
+    let area = match area {
+        Some(area) => area.inflate(padding, padding),
+        None => return Ok(TextShadow::new(None, Rect::ZERO)),
+    };
+
+    let (max_width, max_height) = context.max_texture_size();
+    if area.width() > max_width as f64 || area.height() > max_height as f64 {
+        return Err(Pierror::InvalidInput);
+    }
+    let (width, height) = (area.width() as usize, area.height() as usize);
+
+    // Combine the coverage of the glyphs, which may overlap.
+    let mut coverage = vec![0.0f32; width * height];
+    for (origin, image) in &glyphs {
+        let (x0, y0) = ((origin.x - area.x0) as usize, (origin.y - area.y0) as usize);
+        let (glyph_width, glyph_height) = (
+            image.placement.width as usize,
+            image.placement.height as usize,
+        );
+        let alpha = |i: usize| match image.content {
+            SwashContent::Mask => image.data[i],
+            SwashContent::Color => image.data[i * 4 + 3],
+            _ => 0,
+        };
+
+        for row in 0..glyph_height {
+            for col in 0..glyph_width {
+                let alpha = alpha(row * glyph_width + col) as f32 / 255.0;
+                let value = &mut coverage[(y0 + row) * width + x0 + col];
+                *value += alpha * (1.0 - *value);
+            }
+        }
+    }
+
+    let texture = blurred_texture(
+        context,
+        coverage,
+        (width as u32, height as u32),
+        radius,
+        padding,
+    )?
+    .tracked(memory, Category::Other);
+    let area = Rect::new(
+        area.x0 / scale,
+        area.y0 / scale,
+        area.x1 / scale,
+        area.y1 / scale,
+    );
+    Ok(TextShadow::new(Some(texture), area))
+}
+
+/// Blur coverage values, and upload them as white with premultiplied alpha.
+///
+/// `padding` is the empty space around the shape, which the blur reaches into.
+pub(crate) fn blurred_texture<C: GpuContext + ?Sized>(
+    context: &Rc<C>,
+    mut coverage: Vec<f32>,
+    (width, height): (u32, u32),
+    radius: f64,
+    padding: f64,
+) -> Result<Texture<C>, Pierror> {
+    if radius > 0.0 {
+        blur(
+            &mut coverage,
+            width as usize,
+            height as usize,
+            radius,
+            padding as usize,
+        );
+    }
+
+    let data: Vec<u8> = coverage
+        .iter()
+        .flat_map(|&alpha| [(alpha.clamp(0.0, 1.0) * 255.0).round() as u8; 4])
+        .collect();
+    let texture =
+        Texture::new(context, InterpolationMode::Bilinear, RepeatStrategy::Clamp).piet_err()?;
+    texture.write_texture((width, height), piet::ImageFormat::RgbaPremul, Some(&data));
+    Ok(texture)
+}
+
+/// Blur coverage values with a gaussian whose standard deviation is `radius / sqrt(2)`,
+/// which matches the blurred rectangles of the other `piet` backends.
+fn blur(values: &mut [f32], width: usize, height: usize, radius: f64, reach: usize) {
+    let reach = reach as isize;
+    let mut kernel: Vec<f32> = (-reach..=reach)
+        .map(|x| (-((x * x) as f64) / (radius * radius)).exp() as f32)
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|weight| *weight /= total);
+
+    // Blur the rows into the scratch buffer, then the columns back into the values.
+    let mut scratch = vec![0.0; values.len()];
+    let pass = |src: &[f32], dst: &mut [f32], stride: usize, step: usize, lines: usize, len| {
+        for line in 0..lines {
+            for i in 0..len as isize {
+                dst[line * stride + i as usize * step] = kernel
+                    .iter()
+                    .zip(i - reach..)
+                    .filter(|&(_, j)| j >= 0 && j < len as isize)
+                    .map(|(weight, j)| weight * src[line * stride + j as usize * step])
+                    .sum();
+            }
+        }
+    };
+    pass(values, &mut scratch, width, 1, height, width);
+    pass(&scratch, values, 1, width, width, height);
+}
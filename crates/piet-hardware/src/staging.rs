@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! A reusable buffer for converting pixels before they are uploaded.
+
+/// A growable buffer that pixels are converted into on the CPU before they are uploaded.
+///
+/// The buffer keeps its allocation between uploads, so converting images and glyphs of a
+/// similar size doesn't allocate once it has grown large enough.
+#[derive(Debug, Default)]
+pub(crate) struct Staging {
+    /// The bytes of the last conversion.
+    data: Vec<u8>,
+}
+
+impl Staging {
+    /// Get a zeroed buffer of `len` bytes.
+    pub(crate) fn zeroed(&mut self, len: usize) -> &mut [u8] {
+        self.data.clear();
+        self.data.resize(len, 0);
+        &mut self.data
+    }
+
+    /// Get a buffer holding a copy of `data`.
+    pub(crate) fn copy_of(&mut self, data: &[u8]) -> &mut [u8] {
+        self.data.clear();
+        self.data.extend_from_slice(data);
+        &mut self.data
+    }
+
+    /// Free the memory held by the buffer.
+    pub(crate) fn shrink(&mut self) {
+        self.data = Vec::new();
+    }
+}
@@ -20,20 +20,52 @@
 // Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
 
 use piet::kurbo::{Point, Rect, Size};
-use piet::Error as Pierror;
+use piet::{Error as Pierror, FontFamily, FontStyle, FontWeight, TextAlignment, TextAttribute};
+use piet::{Text as _, TextLayout as _, TextLayoutBuilder as _};
 
-use piet_cosmic_text::{
-    Text as CosText, TextLayout as CosTextLayout, TextLayoutBuilder as CosTextLayoutBuilder,
-};
+use piet_cosmic_text::{Text as CosText, TextLayout as CosTextLayout};
+
+use ahash::RandomState;
+use hashbrown::HashMap;
+
+use std::cell::RefCell;
+use std::mem;
+use std::ops::{Bound, Range, RangeBounds};
+use std::rc::Rc;
+
+/// The string used to mark truncated text.
+const ELLIPSIS: &str = "\u{2026}";
+
+/// The number of layouts that are cached before the least recently built ones are dropped.
+const MAX_CACHED_LAYOUTS: usize = 512;
 
 /// The text layout engine for the GPU renderer.
 #[derive(Clone)]
-pub struct Text(CosText);
+pub struct Text(CosText, Rc<RefCell<LayoutCache>>);
 
 impl Text {
-    /// Create a new text layout engine.
-    pub(crate) fn new() -> Self {
-        Self(CosText::new())
+    /// Create a new text layout engine, caching layouts in maps that use `hasher`.
+    pub(crate) fn new(hasher: RandomState) -> Self {
+        Self(
+            CosText::new(),
+            Rc::new(RefCell::new(LayoutCache::new(hasher))),
+        )
+    }
+
+    /// Drop every cached text layout.
+    ///
+    /// Building a layout with the same text, attributes and options as a recent one reuses
+    /// it instead of shaping the text again. The cache is cleared automatically when fonts
+    /// are loaded.
+    pub fn clear_layout_cache(&self) {
+        self.1.borrow_mut().clear();
+    }
+
+    /// Run a function with read access to the [`FontSystem`] used for text layout.
+    ///
+    /// [`FontSystem`]: cosmic_text::FontSystem
+    pub fn with_font_system<R>(&self, f: impl FnOnce(&cosmic_text::FontSystem) -> R) -> R {
+        self.0.with_font_system(f)
     }
 
     /// Run a function with the `FontSystem` associated with this type.
@@ -54,89 +86,572 @@ impl piet::Text for Text {
     }
 
     fn load_font(&mut self, data: &[u8]) -> Result<piet::FontFamily, Pierror> {
+        // Text that was shaped with a fallback font may now be shaped differently.
+        self.clear_layout_cache();
         self.0.load_font(data)
     }
 
     fn new_text_layout(&mut self, text: impl piet::TextStorage) -> Self::TextLayoutBuilder {
-        TextLayoutBuilder(self.0.new_text_layout(text))
+        TextLayoutBuilder {
+            text: self.0.clone(),
+            cache: self.1.clone(),
+            string: Rc::from(text.as_str()),
+            ops: Vec::new(),
+            tab_width: None,
+            max_lines: None,
+            truncation: Truncation::default(),
+        }
+    }
+}
+
+/// How text is truncated when it has more lines than allowed.
+///
+/// See [`TextLayoutBuilder::max_lines`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Truncation {
+    /// End the last line with an ellipsis.
+    #[default]
+    Ellipsis,
+
+    /// Cut the text off after the last line.
+    Clip,
+}
+
+/// A call made on a [`TextLayoutBuilder`], replayed when the layout is built.
+#[derive(Clone)]
+enum BuilderOp {
+    MaxWidth(f64),
+    Alignment(TextAlignment),
+    DefaultAttribute(TextAttribute),
+    RangeAttribute(Range<usize>, TextAttribute),
+}
+
+impl BuilderOp {
+    /// Get a hashable key for this call.
+    fn key(&self) -> OpKey {
+        match self {
+            Self::MaxWidth(width) => OpKey::MaxWidth(width.to_bits()),
+            Self::Alignment(alignment) => OpKey::Alignment(match alignment {
+                TextAlignment::Start => 0,
+                TextAlignment::End => 1,
+                TextAlignment::Center => 2,
+                TextAlignment::Justified => 3,
+            }),
+            Self::DefaultAttribute(attr) => OpKey::Attribute(None, AttributeKey::new(attr)),
+            Self::RangeAttribute(range, attr) => {
+                OpKey::Attribute(Some((range.start, range.end)), AttributeKey::new(attr))
+            }
+        }
+    }
+}
+
+/// A hashable version of [`BuilderOp`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum OpKey {
+    MaxWidth(u64),
+    Alignment(u8),
+    Attribute(Option<(usize, usize)>, AttributeKey),
+}
+
+/// A hashable version of [`TextAttribute`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum AttributeKey {
+    FontFamily(FontFamily),
+    FontSize(u64),
+    Weight(FontWeight),
+    TextColor(u32),
+    Style(FontStyle),
+    Underline(bool),
+    Strikethrough(bool),
+}
+
+impl AttributeKey {
+    fn new(attr: &TextAttribute) -> Self {
+        match attr {
+            TextAttribute::FontFamily(family) => Self::FontFamily(family.clone()),
+            TextAttribute::FontSize(size) => Self::FontSize(size.to_bits()),
+            TextAttribute::Weight(weight) => Self::Weight(*weight),
+            TextAttribute::TextColor(color) => Self::TextColor(color.as_rgba_u32()),
+            TextAttribute::Style(style) => Self::Style(*style),
+            TextAttribute::Underline(underline) => Self::Underline(*underline),
+            TextAttribute::Strikethrough(strikethrough) => Self::Strikethrough(*strikethrough),
+        }
+    }
+}
+
+/// Everything that determines how a layout is built.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutKey {
+    string: Rc<str>,
+    ops: Vec<OpKey>,
+    tab_width: Option<u64>,
+    max_lines: Option<usize>,
+    truncation: Truncation,
+}
+
+/// The layouts that were built recently.
+///
+/// Layouts are kept in two generations. When the newer one fills up, the older one is
+/// dropped, and layouts from it that are built again move into the newer one.
+struct LayoutCache {
+    /// The layouts that were built or reused since the last generation was dropped.
+    current: HashMap<LayoutKey, TextLayout, RandomState>,
+
+    /// The layouts of the previous generation.
+    previous: HashMap<LayoutKey, TextLayout, RandomState>,
+}
+
+impl LayoutCache {
+    fn new(hasher: RandomState) -> Self {
+        Self {
+            current: HashMap::with_hasher(hasher.clone()),
+            previous: HashMap::with_hasher(hasher),
+        }
+    }
+
+    fn get(&mut self, key: &LayoutKey) -> Option<TextLayout> {
+        if let Some(layout) = self.current.get(key) {
+            return Some(layout.clone());
+        }
+
+        let (key, layout) = self.previous.remove_entry(key)?;
+        self.insert(key, layout.clone());
+        Some(layout)
+    }
+
+    fn insert(&mut self, key: LayoutKey, layout: TextLayout) {
+        if self.current.len() >= MAX_CACHED_LAYOUTS {
+            mem::swap(&mut self.previous, &mut self.current);
+            self.current.clear();
+        }
+
+        self.current.insert(key, layout);
+    }
+
+    fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
     }
 }
 
 /// The text layout builder for the GPU renderer.
-pub struct TextLayoutBuilder(CosTextLayoutBuilder);
+pub struct TextLayoutBuilder {
+    /// The text engine to build the layout with.
+    text: CosText,
+
+    /// The layouts that were built recently.
+    cache: Rc<RefCell<LayoutCache>>,
+
+    /// The text to lay out.
+    string: Rc<str>,
+
+    /// The calls made on this builder.
+    ops: Vec<BuilderOp>,
+
+    /// The distance between tab stops.
+    tab_width: Option<f64>,
+
+    /// The maximum number of lines.
+    max_lines: Option<usize>,
+
+    /// How to truncate text with too many lines.
+    truncation: Truncation,
+}
+
+impl TextLayoutBuilder {
+    /// Place tab stops at every multiple of `width`, measured from the start of the line.
+    ///
+    /// By default, tabs are drawn using the font's glyph for the tab character. Tab stops
+    /// don't affect where lines are wrapped.
+    pub fn tab_width(mut self, width: f64) -> Self {
+        self.tab_width = Some(width).filter(|width| *width > 0.0);
+        self
+    }
+
+    /// Limit the layout to the given number of lines.
+    ///
+    /// If the text has more lines than this, it is truncated according to
+    /// [`truncation`](Self::truncation). Combine this with `max_width` to truncate long
+    /// single-line labels.
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines.max(1));
+        self
+    }
+
+    /// Set how the text is truncated when it has too many lines.
+    ///
+    /// Defaults to [`Truncation::Ellipsis`].
+    pub fn truncation(mut self, truncation: Truncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// The maximum width set on this builder.
+    fn width(&self) -> Option<f64> {
+        self.ops.iter().rev().find_map(|op| match op {
+            BuilderOp::MaxWidth(width) => Some(*width),
+            _ => None,
+        })
+    }
+
+    /// Lay out `text[..cut]` followed by `suffix`.
+    ///
+    /// Attributes that cover the end of the kept text also cover the suffix.
+    fn layout(&self, cut: usize, suffix: &str) -> Result<CosTextLayout, Pierror> {
+        let string: Rc<str> = if cut == self.string.len() && suffix.is_empty() {
+            self.string.clone()
+        } else {
+            format!("{}{}", &self.string[..cut], suffix).into()
+        };
+        let len = string.len();
+
+        let mut builder = self.text.clone().new_text_layout(string);
+        for op in &self.ops {
+            builder = match op.clone() {
+                BuilderOp::MaxWidth(width) => builder.max_width(width),
+                BuilderOp::Alignment(alignment) => builder.alignment(alignment),
+                BuilderOp::DefaultAttribute(attr) => builder.default_attribute(attr),
+                BuilderOp::RangeAttribute(range, attr) => {
+                    if range.start >= cut {
+                        continue;
+                    }
+
+                    let end = if range.end >= cut { len } else { range.end };
+                    builder.range_attribute(range.start..end, attr)
+                }
+            };
+        }
+
+        builder.build()
+    }
+
+    /// Truncate the text so that it fits in `max_lines` lines.
+    fn truncate(
+        &self,
+        full: &CosTextLayout,
+        max_lines: usize,
+    ) -> Result<(CosTextLayout, usize), Pierror> {
+        // Cut the text at the end of the last line that fits. Glyphs count bytes from the
+        // start of their paragraph.
+        let last_line = full.line_metric(max_lines - 1).unwrap();
+        let run = full.layout_runs().nth(max_lines - 1).unwrap();
+        let start = paragraph_start(&self.string, run.line_i);
+        let line_start = start
+            + run
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.start)
+                .min()
+                .unwrap_or(0);
+        let line_end = start + run.glyphs.iter().map(|glyph| glyph.end).max().unwrap_or(0);
+        let line_end = floor_char_boundary(&self.string, line_end);
+        let mut cut = self.string[..line_end].trim_end().len();
+
+        let suffix = match self.truncation {
+            Truncation::Clip => return Ok((self.layout(cut, "")?, cut)),
+            Truncation::Ellipsis => ELLIPSIS,
+        };
+
+        // Make an initial guess at how much room the ellipsis needs.
+        if let Some(width) = self.width() {
+            let ellipsis_width = self.layout(0, suffix)?.size().width;
+            let guess = full.hit_test_point(Point::new(
+                (width - ellipsis_width).max(0.0),
+                last_line.y_offset + last_line.baseline,
+            ));
+            let idx = floor_char_boundary(&self.string, start + guess.idx);
+            if guess.is_inside && idx >= line_start {
+                cut = cut.min(idx);
+            }
+        }
+
+        // Back off one character at a time until the ellipsis fits.
+        loop {
+            let layout = self.layout(cut, suffix)?;
+            if layout.line_count() <= max_lines || cut == 0 {
+                return Ok((layout, cut));
+            }
+
+            cut = self.string[..cut]
+                .char_indices()
+                .next_back()
+                .map_or(0, |(i, _)| i);
+        }
+    }
+}
 
 impl piet::TextLayoutBuilder for TextLayoutBuilder {
     type Out = TextLayout;
 
-    fn max_width(self, width: f64) -> Self {
-        Self(self.0.max_width(width))
+    fn max_width(mut self, width: f64) -> Self {
+        self.ops.push(BuilderOp::MaxWidth(width));
+        self
     }
 
-    fn alignment(self, alignment: piet::TextAlignment) -> Self {
-        Self(self.0.alignment(alignment))
+    fn alignment(mut self, alignment: piet::TextAlignment) -> Self {
+        self.ops.push(BuilderOp::Alignment(alignment));
+        self
     }
 
-    fn default_attribute(self, attribute: impl Into<piet::TextAttribute>) -> Self {
-        Self(self.0.default_attribute(attribute))
+    fn default_attribute(mut self, attribute: impl Into<piet::TextAttribute>) -> Self {
+        self.ops.push(BuilderOp::DefaultAttribute(attribute.into()));
+        self
     }
 
     fn range_attribute(
-        self,
+        mut self,
         range: impl std::ops::RangeBounds<usize>,
         attribute: impl Into<piet::TextAttribute>,
     ) -> Self {
-        Self(self.0.range_attribute(range, attribute))
+        let range = resolve_range(range, self.string.len());
+        self.ops
+            .push(BuilderOp::RangeAttribute(range, attribute.into()));
+        self
     }
 
     fn build(self) -> Result<Self::Out, Pierror> {
-        Ok(TextLayout(self.0.build()?))
+        let key = LayoutKey {
+            string: self.string.clone(),
+            ops: self.ops.iter().map(BuilderOp::key).collect(),
+            tab_width: self.tab_width.map(f64::to_bits),
+            max_lines: self.max_lines,
+            truncation: self.truncation,
+        };
+        if let Some(layout) = self.cache.borrow_mut().get(&key) {
+            return Ok(layout);
+        }
+
+        let mut layout = self.layout(self.string.len(), "")?;
+        let mut truncated_at = None;
+
+        if let Some(max_lines) = self.max_lines {
+            if layout.line_count() > max_lines {
+                let (truncated, cut) = self.truncate(&layout, max_lines)?;
+                layout = truncated;
+                truncated_at = Some(cut);
+            }
+        }
+
+        let tab_shifts = match self.tab_width {
+            Some(width) => tab_shifts(&layout, width),
+            None => Rc::from([]),
+        };
+
+        let layout = TextLayout {
+            inner: layout,
+            truncated_at,
+            tab_shifts,
+            identity: Rc::new(()),
+        };
+        self.cache.borrow_mut().insert(key, layout.clone());
+        Ok(layout)
     }
 }
 
 /// The text layout for the GPU renderer.
 #[derive(Clone)]
-pub struct TextLayout(CosTextLayout);
+pub struct TextLayout {
+    /// The underlying layout.
+    inner: CosTextLayout,
+
+    /// The index in the original text where it was truncated.
+    truncated_at: Option<usize>,
+
+    /// For each layout run, how far each glyph is moved to the right by tab stops.
+    ///
+    /// This is empty if there are no tab stops.
+    tab_shifts: Rc<[Box<[f64]>]>,
+
+    /// Shared by the clones of this layout and by no other layout.
+    identity: Rc<()>,
+}
 
 impl TextLayout {
-    pub(crate) fn buffer(&self) -> &cosmic_text::Buffer {
-        self.0.buffer()
+    /// Get the underlying [`Buffer`] that holds the laid out text.
+    ///
+    /// This can be used for things that the `piet` API doesn't cover, like cursor movement
+    /// or drawing IME compositions. Note that glyph positions in the buffer don't include
+    /// the shifts from [`TextLayoutBuilder::tab_width`].
+    ///
+    /// [`Buffer`]: cosmic_text::Buffer
+    pub fn buffer(&self) -> &cosmic_text::Buffer {
+        self.inner.buffer()
+    }
+
+    /// The index in the original text where it was truncated, if it was truncated.
+    ///
+    /// The text of a truncated layout is the original text up to this index, followed by an
+    /// ellipsis if [`Truncation::Ellipsis`] is used.
+    pub fn truncated_at(&self) -> Option<usize> {
+        self.truncated_at
+    }
+
+    /// A handle that is the same for clones of this layout, for caching what is drawn from it.
+    ///
+    /// No other layout has the same handle while it is alive.
+    pub(crate) fn identity(&self) -> &Rc<()> {
+        &self.identity
+    }
+
+    /// How far a glyph is moved to the right by tab stops.
+    pub(crate) fn tab_shift(&self, run: usize, glyph: usize) -> f64 {
+        self.tab_shifts
+            .get(run)
+            .and_then(|shifts| shifts.get(glyph))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The largest distance that tab stops move a line to the right.
+    fn max_tab_shift(&self) -> f64 {
+        self.tab_shifts
+            .iter()
+            .filter_map(|shifts| shifts.last())
+            .fold(0.0, |a, b| a.max(*b))
     }
 }
 
 impl piet::TextLayout for TextLayout {
     fn size(&self) -> Size {
-        self.0.size()
+        let size = self.inner.size();
+        Size::new(size.width + self.max_tab_shift(), size.height)
     }
 
     fn trailing_whitespace_width(&self) -> f64 {
-        self.0.trailing_whitespace_width()
+        self.inner.trailing_whitespace_width() + self.max_tab_shift()
     }
 
     fn image_bounds(&self) -> Rect {
-        self.0.image_bounds()
+        let mut bounds = self.inner.image_bounds();
+        bounds.x1 += self.max_tab_shift();
+        bounds
     }
 
     fn text(&self) -> &str {
-        self.0.text()
+        self.inner.text()
     }
 
     fn line_text(&self, line_number: usize) -> Option<&str> {
-        self.0.line_text(line_number)
+        self.inner.line_text(line_number)
     }
 
     fn line_metric(&self, line_number: usize) -> Option<piet::LineMetric> {
-        self.0.line_metric(line_number)
+        self.inner.line_metric(line_number)
     }
 
     fn line_count(&self) -> usize {
-        self.0.line_count()
+        self.inner.line_count()
     }
 
     fn hit_test_point(&self, point: Point) -> piet::HitTestPoint {
-        self.0.hit_test_point(point)
+        if self.tab_shifts.is_empty() {
+            return self.inner.hit_test_point(point);
+        }
+
+        // Undo the tab shift of the glyph under the point.
+        let line_height = self.buffer().metrics().line_height as f64;
+        let shift = self
+            .buffer()
+            .layout_runs()
+            .enumerate()
+            .find(|(_, run)| {
+                let bottom = run.line_y as f64;
+                point.y >= bottom - line_height && point.y <= bottom
+            })
+            .and_then(|(i, run)| {
+                run.glyphs
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find(|(j, glyph)| glyph.x as f64 + self.tab_shift(i, *j) <= point.x)
+                    .map(|(j, _)| self.tab_shift(i, j))
+            })
+            .unwrap_or(0.0);
+
+        self.inner
+            .hit_test_point(Point::new(point.x - shift, point.y))
     }
 
     fn hit_test_text_position(&self, idx: usize) -> piet::HitTestPosition {
-        self.0.hit_test_text_position(idx)
+        let mut htp = self.inner.hit_test_text_position(idx);
+
+        if let Some(run) = self.buffer().layout_runs().nth(htp.line) {
+            if let Some(glyph) = run
+                .glyphs
+                .iter()
+                .position(|glyph| (glyph.start..glyph.end).contains(&idx))
+            {
+                htp.point.x += self.tab_shift(htp.line, glyph);
+            }
+        }
+
+        htp
+    }
+}
+
+/// Figure out how far each glyph is moved to the right by tab stops.
+fn tab_shifts(layout: &CosTextLayout, tab_width: f64) -> Rc<[Box<[f64]>]> {
+    if !layout.text().contains('\t') {
+        return Rc::from([]);
+    }
+
+    layout
+        .buffer()
+        .layout_runs()
+        .map(|run| {
+            let mut shift = 0.0;
+            run.glyphs
+                .iter()
+                .map(|glyph| {
+                    let current = shift;
+
+                    // The glyph after a tab starts at the next tab stop.
+                    if run.text.get(glyph.start..glyph.end) == Some("\t") {
+                        let start = glyph.x as f64 + shift;
+                        let stop = ((start / tab_width).floor() + 1.0) * tab_width;
+                        shift = (stop - (glyph.x + glyph.w) as f64).round();
+                    }
+
+                    current
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Resolve a range of text indices.
+fn resolve_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end + 1,
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    start.min(len)..end.min(len)
+}
+
+/// Get the byte offset where a paragraph starts, splitting lines like `cosmic-text` does.
+fn paragraph_start(string: &str, paragraph: usize) -> usize {
+    match paragraph.checked_sub(1) {
+        Some(newline) => string
+            .match_indices('\n')
+            .nth(newline)
+            .map_or(string.len(), |(i, _)| i + 1),
+        None => 0,
+    }
+}
+
+/// Round a byte offset down to the start of the character that it is in.
+fn floor_char_boundary(string: &str, index: usize) -> usize {
+    let mut index = index.min(string.len());
+    while !string.is_char_boundary(index) {
+        index -= 1;
     }
+    index
 }
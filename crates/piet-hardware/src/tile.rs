@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Splitting large frames into tiles.
+
+use piet::kurbo::Rect;
+
+/// A tile of a larger frame.
+///
+/// See [`Source::tile_context`](crate::Source::tile_context) for more information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tile {
+    /// The X coordinate of the tile's top-left corner in the frame.
+    pub x: u32,
+
+    /// The Y coordinate of the tile's top-left corner in the frame.
+    pub y: u32,
+
+    /// The width of the tile.
+    pub width: u32,
+
+    /// The height of the tile.
+    pub height: u32,
+}
+
+impl Tile {
+    /// Split a frame into tiles of at most `tile_size` by `tile_size` pixels.
+    ///
+    /// Tiles are produced row by row, starting at the top-left corner. Tiles on the right and
+    /// bottom edges are smaller if the frame size isn't a multiple of the tile size.
+    pub fn split(width: u32, height: u32, tile_size: u32) -> Tiles {
+        Tiles {
+            frame: (width, height),
+            tile_size: tile_size.max(1),
+            next: (0, 0),
+        }
+    }
+
+    /// Get the area of the frame this tile covers.
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.x as f64,
+            self.y as f64,
+            self.x as f64 + self.width as f64,
+            self.y as f64 + self.height as f64,
+        )
+    }
+}
+
+/// An iterator over the tiles of a frame.
+///
+/// This is returned by [`Tile::split`].
+#[derive(Debug, Clone)]
+pub struct Tiles {
+    /// The size of the whole frame.
+    frame: (u32, u32),
+
+    /// The maximum size of a tile.
+    tile_size: u32,
+
+    /// The top-left corner of the next tile.
+    next: (u32, u32),
+}
+
+impl Iterator for Tiles {
+    type Item = Tile;
+
+    fn next(&mut self) -> Option<Tile> {
+        let (width, height) = self.frame;
+        let (x, y) = self.next;
+        if width == 0 || y >= height {
+            return None;
+        }
+
+        let tile = Tile {
+            x,
+            y,
+            width: (width - x).min(self.tile_size),
+            height: (height - y).min(self.tile_size),
+        };
+
+        // Move to the next tile, wrapping around to the next row.
+        self.next = match x.checked_add(self.tile_size) {
+            Some(next_x) if next_x < width => (next_x, y),
+            _ => (0, y.saturating_add(self.tile_size)),
+        };
+
+        Some(tile)
+    }
+}
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Spreading image uploads over several frames.
+
+use super::gpu_backend::GpuContext;
+use super::resources::Texture;
+
+use std::collections::VecDeque;
+use std::ptr;
+use std::rc::{Rc, Weak};
+
+/// The pixels of an image that are waiting to be uploaded.
+struct PendingUpload<C: GpuContext + ?Sized> {
+    /// The texture to upload them to, if the image is still alive.
+    texture: Weak<Texture<C>>,
+
+    /// The size of the image.
+    size: (u32, u32),
+
+    /// The format of the pixels.
+    format: piet::ImageFormat,
+
+    /// The pixels.
+    data: Vec<u8>,
+}
+
+/// Images that didn't fit in the upload budget of the frame they were made in.
+pub(crate) struct UploadQueue<C: GpuContext + ?Sized> {
+    /// The bytes that can be uploaded every frame, or `None` for no limit.
+    budget: Option<usize>,
+
+    /// The bytes uploaded so far this frame.
+    spent: usize,
+
+    /// The images to upload, oldest first.
+    pending: VecDeque<PendingUpload<C>>,
+}
+
+impl<C: GpuContext + ?Sized> UploadQueue<C> {
+    /// Create a queue without a budget.
+    pub(crate) fn new() -> Self {
+        Self {
+            budget: None,
+            spent: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Set the bytes that can be uploaded every frame.
+    pub(crate) fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Whether any images are waiting to be uploaded.
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether the pixels of `texture` are still waiting to be uploaded.
+    pub(crate) fn is_pending(&self, texture: &Texture<C>) -> bool {
+        self.pending
+            .iter()
+            .any(|upload| ptr::eq(upload.texture.as_ptr(), texture))
+    }
+
+    /// Start a new frame, uploading as many of the waiting images as the budget allows.
+    pub(crate) fn begin_frame(&mut self) {
+        self.spent = 0;
+
+        while let Some(upload) = self.pending.front() {
+            let texture = match upload.texture.upgrade() {
+                Some(texture) => texture,
+                None => {
+                    // The image was dropped before it was ever drawn.
+                    self.pending.pop_front();
+                    continue;
+                }
+            };
+
+            if !self.reserve(upload.data.len()) {
+                break;
+            }
+
+            let upload = self.pending.pop_front().unwrap();
+            texture.write_texture(upload.size, upload.format, Some(&upload.data));
+        }
+    }
+
+    /// Upload the pixels of `texture` right away if they are waiting, whatever the budget.
+    pub(crate) fn upload_now(&mut self, texture: &Texture<C>) {
+        if let Some(i) = self
+            .pending
+            .iter()
+            .position(|upload| ptr::eq(upload.texture.as_ptr(), texture))
+        {
+            let upload = self.pending.remove(i).unwrap();
+            self.spent = self.spent.saturating_add(upload.data.len());
+            texture.write_texture(upload.size, upload.format, Some(&upload.data));
+        }
+    }
+
+    /// Write an image into `texture` if it fits in the budget of this frame, or keep it to
+    /// be uploaded in a later frame.
+    pub(crate) fn write(
+        &mut self,
+        texture: &Rc<Texture<C>>,
+        size: (u32, u32),
+        format: piet::ImageFormat,
+        data: &[u8],
+    ) {
+        // Don't let new images overtake the ones that are already waiting.
+        if self.pending.is_empty() && self.reserve(data.len()) {
+            texture.write_texture(size, format, Some(data));
+            return;
+        }
+
+        self.pending.push_back(PendingUpload {
+            texture: Rc::downgrade(texture),
+            size,
+            format,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Take `bytes` out of the budget of this frame, if they fit.
+    fn reserve(&mut self, bytes: usize) -> bool {
+        if let Some(budget) = self.budget {
+            // The first upload of a frame always goes through, so that images larger than
+            // the budget are uploaded eventually.
+            if self.spent > 0 && self.spent.saturating_add(bytes) > budget {
+                return false;
+            }
+        }
+
+        self.spent = self.spent.saturating_add(bytes);
+        true
+    }
+}
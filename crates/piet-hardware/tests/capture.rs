@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for capturing frames and replaying them.
+
+use piet_gpu_soft::SoftwareContext;
+use piet_hardware::piet::kurbo::{Circle, Rect};
+use piet_hardware::piet::{Color, RenderContext as _, Text as _, TextLayoutBuilder as _};
+use piet_hardware::{replay, CaptureContext, Source};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// A writer that can be read from after it is handed to the capture.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Draw a frame with some text, which is uploaded into parts of the atlas.
+fn draw_frame(source: &mut Source<CaptureContext<SoftwareContext>>, text: &str) {
+    let mut rc = source.render_context(64, 32);
+    rc.clear(None, Color::WHITE);
+    rc.save().unwrap();
+    rc.clip(Circle::new((48.0, 16.0), 12.0));
+    rc.fill(Rect::new(32.0, 0.0, 64.0, 32.0), &Color::rgb(0.2, 0.4, 0.8));
+    rc.restore().unwrap();
+
+    let layout = rc
+        .text()
+        .new_text_layout(text.to_owned())
+        .text_color(Color::BLACK)
+        .build()
+        .unwrap();
+    rc.draw_text(&layout, (2.0, 2.0));
+    rc.finish().unwrap();
+}
+
+#[test]
+fn replayed_capture_matches_frame() {
+    let mut source = Source::new(CaptureContext::new(SoftwareContext::new(64, 32))).unwrap();
+
+    // The glyphs of the first frame are only in the capture through the atlas contents that
+    // are written out when it starts.
+    draw_frame(&mut source, "Hello");
+    let buffer = SharedBuffer::default();
+    source.context().capture_next_frame(buffer.clone(), false);
+    draw_frame(&mut source, "Hello world");
+    assert!(!source.context().is_capturing());
+
+    let expected = source.context().inner().pixels();
+    assert!(expected.iter().any(|px| px[0] < 0x40 && px[2] < 0x40));
+
+    let replayed = SoftwareContext::new(64, 32);
+    replay(&replayed, &buffer.0.borrow()[..]).unwrap();
+    assert_eq!(replayed.pixels(), expected);
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for rendering offscreen in the middle of a frame.
+
+use piet_hardware::piet::kurbo::{Affine, Rect, Size};
+use piet_hardware::piet::{Color, ImageFormat, InterpolationMode, RenderContext as _};
+use piet_hardware::{GpuContext, RepeatStrategy, Source, Vertex};
+
+use std::cell::Cell;
+use std::convert::Infallible;
+
+/// The size of the images that are uploaded.
+const SIZE: usize = 16;
+
+/// A context that supports render targets and counts the images that are uploaded.
+#[derive(Default)]
+struct CountingContext {
+    uploads: Cell<usize>,
+}
+
+impl GpuContext for CountingContext {
+    type Texture = ();
+    type VertexBuffer = ();
+    type Error = Infallible;
+
+    fn clear(&self, _color: Color) {}
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        _interpolation: InterpolationMode,
+        _repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        Ok(())
+    }
+
+    fn delete_texture(&self, _texture: Self::Texture) {}
+
+    fn write_texture(
+        &self,
+        _texture: &Self::Texture,
+        size: (u32, u32),
+        _format: ImageFormat,
+        data: Option<&[u8]>,
+    ) {
+        if data.is_some() && size == (SIZE as u32, SIZE as u32) {
+            self.uploads.set(self.uploads.get() + 1);
+        }
+    }
+
+    fn write_subtexture(
+        &self,
+        _texture: &Self::Texture,
+        _offset: (u32, u32),
+        _size: (u32, u32),
+        _format: ImageFormat,
+        _data: &[u8],
+    ) {
+    }
+
+    fn set_texture_interpolation(
+        &self,
+        _texture: &Self::Texture,
+        _interpolation: InterpolationMode,
+    ) {
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        (4096, 4096)
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        Ok(())
+    }
+
+    fn delete_vertex_buffer(&self, _buffer: Self::VertexBuffer) {}
+
+    fn write_vertices(&self, _buffer: &Self::VertexBuffer, _vertices: &[Vertex], _indices: &[u32]) {
+    }
+
+    fn push_buffers(
+        &self,
+        _vertex_buffer: &Self::VertexBuffer,
+        _current_texture: &Self::Texture,
+        _mask_texture: &Self::Texture,
+        _transform: &Affine,
+        _size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn read_pixels(
+        &self,
+        _origin: (u32, u32),
+        _size: (u32, u32),
+        _target_size: (u32, u32),
+        _data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
+    fn create_render_target(
+        &self,
+        _size: (u32, u32),
+    ) -> Result<Option<Self::Texture>, Self::Error> {
+        Ok(Some(()))
+    }
+
+    fn set_render_target(&self, _target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn thumbnails_do_not_reset_the_upload_budget() {
+    let pixels = vec![0xFF; SIZE * SIZE * 4];
+    let mut source = Source::new(CountingContext::default()).unwrap();
+    source.set_upload_budget(Some(pixels.len()));
+
+    // Only the first image fits in the budget of this frame.
+    let mut rc = source.render_context(64, 64);
+    let _images = (0..3)
+        .map(|_| rc.make_image(SIZE, SIZE, &pixels, ImageFormat::RgbaPremul))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    rc.finish().unwrap();
+    drop(rc);
+    assert_eq!(source.context().uploads.get(), 1);
+
+    // Thumbnails are part of the same frame, so they don't upload the waiting images.
+    for _ in 0..2 {
+        source
+            .render_thumbnail(Size::new(64.0, 64.0), (8, 8), |mut rc| {
+                rc.fill(Rect::new(0.0, 0.0, 32.0, 32.0), &Color::RED);
+                rc.status()
+            })
+            .unwrap();
+    }
+    assert_eq!(source.context().uploads.get(), 1);
+
+    // The next frame has a budget of its own.
+    let mut rc = source.render_context(64, 64);
+    rc.finish().unwrap();
+    drop(rc);
+    assert_eq!(source.context().uploads.get(), 2);
+}
@@ -0,0 +1,549 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for drawing with a render context.
+
+use piet_gpu_soft::SoftwareContext;
+use piet_hardware::piet::kurbo::{
+    Affine, BezPath, Circle, Line, ParamCurveNearest, PathEl, PathSeg, Point, Rect, RoundedRect,
+    Shape,
+};
+use piet_hardware::piet::{
+    Color, FixedLinearGradient, GradientStop, ImageFormat, LineCap, RenderContext as _,
+    StrokeStyle, Text as _, TextLayoutBuilder as _,
+};
+use piet_hardware::{Antialiasing, Command, RecordingContext, Source, SourceBuilder};
+
+/// Draw a few frames of a scene that uses every cache, and return the pixels of the last one.
+fn render_deterministic() -> Vec<[u8; 4]> {
+    let mut source = SourceBuilder::new()
+        .deterministic(true)
+        .build(SoftwareContext::new(64, 64))
+        .unwrap();
+
+    for frame in 0..3 {
+        source.begin_occlusion_frame();
+        let mut rc = source.render_context(64, 64);
+        rc.clear(None, Color::WHITE);
+        rc.occlusion_query(1, Rect::new(0.0, 0.0, 8.0, 8.0));
+
+        let gradient = rc
+            .gradient(FixedLinearGradient {
+                start: Point::new(0.0, 0.0),
+                end: Point::new(64.0, 0.0),
+                stops: vec![
+                    GradientStop {
+                        pos: 0.0,
+                        color: Color::RED,
+                    },
+                    GradientStop {
+                        pos: 1.0,
+                        color: Color::BLUE,
+                    },
+                ],
+            })
+            .unwrap();
+        rc.blurred_rect(Rect::new(8.0, 8.0, 40.0, 24.0), 3.0, &Color::BLACK);
+        rc.fill(RoundedRect::new(4.0, 4.0, 36.0, 20.0, 4.0), &gradient);
+
+        rc.save().unwrap();
+        rc.clip(Circle::new((32.0, 40.0), 16.0 + frame as f64));
+        rc.fill(Rect::new(0.0, 24.0, 64.0, 64.0), &Color::rgb(0.2, 0.6, 0.2));
+        rc.restore().unwrap();
+
+        let layout = rc
+            .text()
+            .new_text_layout("Hello")
+            .text_color(Color::BLACK)
+            .build()
+            .unwrap();
+        rc.draw_text(&layout, (4.0, 40.0));
+        rc.finish().unwrap();
+    }
+
+    source.context().pixels()
+}
+
+#[test]
+fn nan_global_alpha() {
+    let mut source = Source::new(SoftwareContext::new(4, 4)).unwrap();
+    let mut rc = source.render_context(4, 4);
+    rc.clear(None, Color::TRANSPARENT);
+
+    rc.set_global_alpha(f64::NAN);
+    assert_eq!(rc.global_alpha(), 0.0);
+    rc.fill(Rect::new(0.0, 0.0, 4.0, 4.0), &Color::RED);
+
+    rc.set_global_alpha(f64::INFINITY);
+    assert_eq!(rc.global_alpha(), 1.0);
+    rc.set_global_alpha(f64::NEG_INFINITY);
+    assert_eq!(rc.global_alpha(), 0.0);
+
+    rc.finish().unwrap();
+    drop(rc);
+    assert!(source.context().pixels().iter().all(|px| *px == [0; 4]));
+}
+
+#[test]
+fn deterministic_output() {
+    let first = render_deterministic();
+    assert!(first.iter().any(|px| *px != [0xFF; 4]));
+    assert_eq!(first, render_deterministic());
+}
+
+#[test]
+fn default_antialiasing_keeps_aligned_edges_crisp() {
+    let mut source = Source::new(SoftwareContext::new(24, 24)).unwrap();
+    assert_eq!(source.antialiasing(), Antialiasing::None);
+
+    let mut rc = source.render_context(24, 24);
+    rc.clear(None, Color::WHITE);
+    rc.fill(Rect::new(8.0, 8.0, 16.0, 16.0), &Color::BLACK);
+    rc.finish().unwrap();
+    drop(rc);
+
+    let pixels = source.context().pixels();
+    for x in 0..24 {
+        let expected = if (8..16).contains(&x) {
+            [0, 0, 0, 0xFF]
+        } else {
+            [0xFF; 4]
+        };
+        assert_eq!(pixels[12 * 24 + x], expected, "column {x}");
+    }
+}
+
+/// Clip to `shape`, save the state, fill the whole target and return the pixels.
+fn fill_after_saving_clip(shape: impl Shape) -> Vec<[u8; 4]> {
+    let mut source = Source::new(SoftwareContext::new(16, 16)).unwrap();
+    let mut rc = source.render_context(16, 16);
+    rc.clear(None, Color::WHITE);
+    rc.clip(shape);
+    rc.save().unwrap();
+    rc.fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::BLACK);
+    rc.restore().unwrap();
+    rc.finish().unwrap();
+    drop(rc);
+    source.context().pixels()
+}
+
+#[test]
+fn clips_survive_save() {
+    let circle = fill_after_saving_clip(Circle::new((8.0, 8.0), 6.0));
+    let mut triangle = BezPath::new();
+    triangle.move_to((8.0, 1.0));
+    triangle.line_to((15.0, 15.0));
+    triangle.line_to((1.0, 15.0));
+    triangle.close_path();
+    let triangle = fill_after_saving_clip(triangle);
+
+    for pixels in [circle, triangle] {
+        assert_eq!(pixels[0], [0xFF; 4]);
+        assert_eq!(pixels[15], [0xFF; 4]);
+        assert_eq!(pixels[8 * 16 + 8], [0, 0, 0, 0xFF]);
+    }
+}
+
+/// Stroke a dashed horizontal line across the middle of a target, and return its pixels.
+fn stroke_dashed(pattern: &[f64], offset: f64, cap: LineCap) -> Vec<[u8; 4]> {
+    let mut source = Source::new(SoftwareContext::new(64, 8)).unwrap();
+    let mut rc = source.render_context(64, 8);
+    rc.clear(None, Color::WHITE);
+
+    let mut style = StrokeStyle::new().dash_offset(offset).line_cap(cap);
+    style.set_dash_pattern(pattern.to_vec());
+    rc.stroke_styled(
+        Line::new((4.0, 4.0), (60.0, 4.0)),
+        &Color::BLACK,
+        4.0,
+        &style,
+    );
+    rc.finish().unwrap();
+    assert!(rc.take_errors().is_empty());
+    drop(rc);
+    source.context().pixels()
+}
+
+/// Whether each pixel in the middle row of `pixels` was drawn over.
+fn middle_row(pixels: &[[u8; 4]]) -> Vec<bool> {
+    pixels[4 * 64..5 * 64]
+        .iter()
+        .map(|px| *px != [0xFF; 4])
+        .collect()
+}
+
+#[test]
+fn dash_offset_negative() {
+    let pattern = [6.0, 2.0];
+    let row = middle_row(&stroke_dashed(&pattern, -3.0, LineCap::Butt));
+    assert_eq!(
+        row,
+        middle_row(&stroke_dashed(&pattern, 5.0, LineCap::Butt))
+    );
+
+    // Going back three units from the start of the pattern is five units into it, where
+    // one unit of the first dash is left.
+    for (x, drawn) in row.iter().enumerate().take(60).skip(4) {
+        assert_eq!(*drawn, (x + 1) % 8 < 6, "pixel {x}");
+    }
+}
+
+#[test]
+fn dash_offset_longer_than_pattern() {
+    let pattern = [6.0, 2.0];
+    let row = middle_row(&stroke_dashed(&pattern, 3.0, LineCap::Butt));
+    for offset in [11.0, 19.0, 8.0 * 100.0 + 3.0] {
+        assert_eq!(
+            row,
+            middle_row(&stroke_dashed(&pattern, offset, LineCap::Butt)),
+            "offset {offset}"
+        );
+    }
+
+    // An odd pattern repeats twice per period, so its period is twice its sum.
+    let odd = middle_row(&stroke_dashed(&[3.0], 1.0, LineCap::Butt));
+    assert_eq!(odd, middle_row(&stroke_dashed(&[3.0], 7.0, LineCap::Butt)));
+}
+
+#[test]
+fn dash_zero_length() {
+    // Zero-length dashes with round caps are dots.
+    let dots = middle_row(&stroke_dashed(&[0.0, 8.0], 0.0, LineCap::Round));
+    assert!(dots[4] && !dots[8] && dots[12]);
+
+    // Without caps, they aren't drawn at all.
+    let nothing = middle_row(&stroke_dashed(&[0.0, 8.0], 0.0, LineCap::Butt));
+    assert!(nothing.iter().all(|drawn| !drawn));
+
+    // A pattern without any length is ignored.
+    let solid = middle_row(&stroke_dashed(&[0.0, 0.0], 0.0, LineCap::Butt));
+    assert!(solid[4..60].iter().all(|drawn| *drawn));
+}
+
+/// Fill `path` and check that it covers the same pixels as it does with `tiny-skia`.
+///
+/// Pixels near the outline are skipped, since curves are flattened differently and the two
+/// rasterizers handle edges differently.
+fn assert_fill_matches_skia(path: &BezPath, even_odd: bool) {
+    const AREA: u32 = 64;
+    const MARGIN: f64 = 2.0;
+
+    let mut source = Source::new(SoftwareContext::new(AREA, AREA)).unwrap();
+    let mut rc = source.render_context(AREA, AREA);
+    rc.clear(None, Color::WHITE);
+    if even_odd {
+        rc.fill_even_odd(path, &Color::BLACK);
+    } else {
+        rc.fill(path, &Color::BLACK);
+    }
+    rc.finish().unwrap();
+    assert!(rc.take_errors().is_empty());
+    drop(rc);
+    let ours = source.context().pixels();
+
+    let mut builder = tiny_skia::PathBuilder::new();
+    let mut segments: Vec<_> = path.segments().collect();
+    let (mut start, mut last) = (Point::ZERO, Point::ZERO);
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => {
+                builder.move_to(p.x as f32, p.y as f32);
+                segments.push(PathSeg::Line(Line::new(last, start)));
+                (start, last) = (p, p);
+            }
+            PathEl::LineTo(p) => {
+                builder.line_to(p.x as f32, p.y as f32);
+                last = p;
+            }
+            PathEl::QuadTo(p1, p2) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32);
+                last = p2;
+            }
+            PathEl::CurveTo(p1, p2, p3) => {
+                builder.cubic_to(
+                    p1.x as f32,
+                    p1.y as f32,
+                    p2.x as f32,
+                    p2.y as f32,
+                    p3.x as f32,
+                    p3.y as f32,
+                );
+                last = p3;
+            }
+            PathEl::ClosePath => {
+                builder.close();
+                last = start;
+            }
+        }
+    }
+    segments.push(PathSeg::Line(Line::new(last, start)));
+
+    let mut theirs = tiny_skia::Pixmap::new(AREA, AREA).unwrap();
+    if let Some(skia_path) = builder.finish() {
+        let paint = tiny_skia::Paint {
+            anti_alias: false,
+            ..Default::default()
+        };
+        let rule = if even_odd {
+            tiny_skia::FillRule::EvenOdd
+        } else {
+            tiny_skia::FillRule::Winding
+        };
+        theirs.fill_path(
+            &skia_path,
+            &paint,
+            rule,
+            tiny_skia::Transform::identity(),
+            None,
+        );
+    }
+
+    for y in 0..AREA {
+        for x in 0..AREA {
+            let center = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+            if segments
+                .iter()
+                .any(|seg| seg.nearest(center, 0.01).distance_sq < MARGIN * MARGIN)
+            {
+                continue;
+            }
+
+            let covered = ours[(y * AREA + x) as usize] != [0xFF; 4];
+            let expected = theirs.pixel(x, y).unwrap().alpha() > 0;
+            assert_eq!(covered, expected, "coverage differs at {center:?}");
+        }
+    }
+}
+
+#[test]
+fn fill_coverage_matches_skia() {
+    // A star that covers its center twice.
+    let mut star = BezPath::new();
+    star.move_to((32.0, 2.0));
+    star.line_to((50.0, 60.0));
+    star.line_to((2.0, 22.0));
+    star.line_to((62.0, 22.0));
+    star.line_to((14.0, 60.0));
+    star.close_path();
+
+    // Two squares wound in opposite directions.
+    let mut frame = BezPath::new();
+    frame.move_to((4.0, 4.0));
+    frame.line_to((60.0, 4.0));
+    frame.line_to((60.0, 60.0));
+    frame.line_to((4.0, 60.0));
+    frame.close_path();
+    frame.move_to((20.0, 20.0));
+    frame.line_to((20.0, 44.0));
+    frame.line_to((44.0, 44.0));
+    frame.line_to((44.0, 20.0));
+    frame.close_path();
+
+    // Curves, an open subpath and a subpath that continues after `ClosePath`.
+    let curves = BezPath::from_vec(vec![
+        PathEl::MoveTo(Point::new(4.0, 32.0)),
+        PathEl::QuadTo(Point::new(32.0, -20.0), Point::new(60.0, 32.0)),
+        PathEl::CurveTo(
+            Point::new(40.0, 80.0),
+            Point::new(24.0, 0.0),
+            Point::new(4.0, 32.0),
+        ),
+        PathEl::ClosePath,
+        PathEl::LineTo(Point::new(16.0, 60.0)),
+        PathEl::LineTo(Point::new(48.0, 60.0)),
+        PathEl::MoveTo(Point::new(40.0, 8.0)),
+        PathEl::LineTo(Point::new(56.0, 8.0)),
+        PathEl::LineTo(Point::new(48.0, 20.0)),
+    ]);
+
+    for path in [star, frame, curves] {
+        for even_odd in [false, true] {
+            assert_fill_matches_skia(&path, even_odd);
+        }
+    }
+}
+
+/// Draw the shadow of some text twice under a scale, and return the sizes of the textures
+/// that were written in each draw.
+fn text_shadow_textures(scale: f64) -> [Vec<(u32, u32)>; 2] {
+    let mut source = Source::new(RecordingContext::new(SoftwareContext::new(256, 128))).unwrap();
+    let mut rc = source.render_context(256, 128);
+    let layout = rc.text().new_text_layout("Shadow").build().unwrap();
+    rc.transform(Affine::scale(scale));
+    rc.source().context().take_commands();
+
+    let mut draw = || {
+        rc.draw_text_shadow(&layout, (4.0, 4.0), 2.0, &Color::BLACK);
+        assert!(rc.take_errors().is_empty());
+        rc.source().context().take_commands()
+    };
+    let draws = [draw(), draw()];
+    rc.finish().unwrap();
+
+    draws.map(|commands| {
+        commands
+            .into_iter()
+            .filter_map(|command| match command {
+                Command::WriteTexture {
+                    size,
+                    format: ImageFormat::RgbaPremul,
+                    ..
+                } => Some(size),
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+#[test]
+fn text_shadow_cached_at_scale() {
+    let [first, second] = text_shadow_textures(1.0);
+    assert_eq!(first.len(), 1);
+    assert!(second.is_empty(), "the shadow was rendered again");
+
+    // The glyphs are rasterized at the scale they are drawn at, so the blurred texture is
+    // twice as large, give or take the rounding of the glyphs.
+    let [scaled, _] = text_shadow_textures(2.0);
+    let ((width, height), (scaled_width, scaled_height)) = (first[0], scaled[0]);
+    assert!((scaled_width as i32 - 2 * width as i32).abs() <= 4);
+    assert!((scaled_height as i32 - 2 * height as i32).abs() <= 4);
+}
+
+/// Draw a clipped widget with any render context.
+fn draw_widget(rc: &mut impl piet_hardware::piet::RenderContext) {
+    rc.save().unwrap();
+    rc.clip(Circle::new((8.0, 8.0), 6.0));
+    rc.fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::BLACK);
+    rc.restore().unwrap();
+    rc.transform(Affine::translate((12.0, 0.0)));
+    rc.fill(Rect::new(0.0, 12.0, 4.0, 16.0), &Color::RED);
+}
+
+#[test]
+fn display_list_replays_clips_relative_to_transform() {
+    let offsets = [(0.0, 0.0), (16.0, 8.0)];
+
+    let mut source = Source::new(SoftwareContext::new(32, 32)).unwrap();
+    let mut rc = source.render_context(32, 32);
+    rc.clear(None, Color::WHITE);
+    for offset in offsets {
+        rc.save().unwrap();
+        rc.transform(Affine::translate(offset));
+        draw_widget(&mut rc);
+        rc.restore().unwrap();
+    }
+    rc.finish().unwrap();
+    drop(rc);
+    let direct = source.context().pixels();
+
+    let mut source = Source::new(SoftwareContext::new(32, 32)).unwrap();
+    let mut rc = source.render_context(32, 32);
+    rc.clear(None, Color::WHITE);
+    let mut recorder = rc.recorder();
+    draw_widget(&mut recorder);
+    assert_eq!(recorder.current_transform(), Affine::translate((12.0, 0.0)));
+    let list = recorder.into_display_list();
+    for offset in offsets {
+        rc.save().unwrap();
+        rc.transform(Affine::translate(offset));
+        rc.play(&list).unwrap();
+        rc.restore().unwrap();
+    }
+
+    // The transform of the list doesn't leak out of it.
+    assert_eq!(rc.current_transform(), Affine::IDENTITY);
+    assert!(rc.take_errors().is_empty());
+    rc.finish().unwrap();
+    drop(rc);
+    let played = source.context().pixels();
+
+    assert_eq!(direct, played);
+    assert_eq!(played[8 * 32 + 8], [0, 0, 0, 0xFF]);
+    assert_eq!(played[32], [0xFF; 4]);
+    assert_eq!(played[16 * 32 + 24], [0, 0, 0, 0xFF]);
+    assert_eq!(played[13 * 32 + 13], [0xFF, 0, 0, 0xFF]);
+}
+
+/// Stroke a shape and return the number of vertices written and the pixels.
+fn stroke_counting_vertices(
+    shape: impl Shape,
+    width: f64,
+    style: &StrokeStyle,
+) -> (usize, Vec<[u8; 4]>) {
+    let mut source = Source::new(RecordingContext::new(SoftwareContext::new(16, 16))).unwrap();
+    let mut rc = source.render_context(16, 16);
+    rc.clear(None, Color::WHITE);
+    rc.source().context().take_commands();
+    rc.stroke_styled(shape, &Color::BLACK, width, style);
+    rc.finish().unwrap();
+    drop(rc);
+
+    let vertices = source
+        .context()
+        .take_commands()
+        .into_iter()
+        .map(|command| match command {
+            Command::WriteVertices { vertices, .. } => vertices.len(),
+            _ => 0,
+        })
+        .sum();
+    (vertices, source.context().inner().pixels())
+}
+
+#[test]
+fn strokes_inside_a_pixel_collapse_to_it() {
+    let style = StrokeStyle::default();
+    let (vertices, pixels) = stroke_counting_vertices(Circle::new((8.3, 8.4), 0.2), 0.1, &style);
+    assert_eq!(vertices, 4);
+    let [r, g, b, a] = pixels[8 * 16 + 8];
+    assert!((200..0xFF).contains(&r), "{r}");
+    assert_eq!((r, g, b, a), (r, r, r, 0xFF));
+    assert_eq!(pixels.iter().filter(|px| **px != [0xFF; 4]).count(), 1);
+
+    // Strokes that would hardly cover any of the pixel are skipped.
+    let (vertices, _) = stroke_counting_vertices(Circle::new((8.3, 8.4), 0.01), 0.01, &style);
+    assert_eq!(vertices, 0);
+}
+
+#[test]
+fn thin_strokes_are_drawn_as_hairlines() {
+    let mut polyline = BezPath::new();
+    polyline.move_to((1.8, 8.5));
+    polyline.line_to((8.0, 8.5));
+    polyline.line_to((14.0, 4.5));
+
+    // Hairlines are a quad strip, with two vertices for each point.
+    let butt = StrokeStyle::new().line_cap(LineCap::Butt);
+    let (vertices, pixels) = stroke_counting_vertices(polyline.clone(), 0.5, &butt);
+    assert_eq!(vertices, 6);
+    let [r, ..] = pixels[8 * 16 + 5];
+    assert!((0x60..0xA0).contains(&r), "{r}");
+    assert_eq!(pixels[8 * 16 + 1], [0xFF; 4]);
+
+    // Square caps reach half of a pixel further.
+    let square = StrokeStyle::new().line_cap(LineCap::Square);
+    let (_, pixels) = stroke_counting_vertices(polyline.clone(), 0.5, &square);
+    assert_ne!(pixels[8 * 16 + 1], [0xFF; 4]);
+
+    // Strokes of a pixel or more are tessellated at full strength.
+    let (_, pixels) = stroke_counting_vertices(polyline, 1.0, &butt);
+    assert_eq!(pixels[8 * 16 + 5], [0, 0, 0, 0xFF]);
+}
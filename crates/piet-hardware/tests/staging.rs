@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests that converting pixels before they are uploaded doesn't allocate.
+
+use piet_hardware::piet::kurbo::Affine;
+use piet_hardware::piet::{Color, ImageFormat, InterpolationMode, RenderContext as _};
+use piet_hardware::{GpuContext, RepeatStrategy, Source, Vertex};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of the images that are uploaded.
+const SIZE: usize = 64;
+
+/// Counts the allocations that are at least as large as an image.
+///
+/// Every image makes a few small allocations for its handles, whatever its format, so only
+/// the buffers that pixels could be converted into are counted.
+struct Counting;
+
+static LARGE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn count(size: usize) {
+    if size >= SIZE * SIZE * 4 {
+        LARGE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// A context that throws away everything, so that it doesn't allocate on its own.
+struct NullContext;
+
+impl GpuContext for NullContext {
+    type Texture = ();
+    type VertexBuffer = ();
+    type Error = Infallible;
+
+    fn clear(&self, _color: Color) {}
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        _interpolation: InterpolationMode,
+        _repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        Ok(())
+    }
+
+    fn delete_texture(&self, _texture: Self::Texture) {}
+
+    fn write_texture(
+        &self,
+        _texture: &Self::Texture,
+        _size: (u32, u32),
+        _format: ImageFormat,
+        _data: Option<&[u8]>,
+    ) {
+    }
+
+    fn write_subtexture(
+        &self,
+        _texture: &Self::Texture,
+        _offset: (u32, u32),
+        _size: (u32, u32),
+        _format: ImageFormat,
+        _data: &[u8],
+    ) {
+    }
+
+    fn set_texture_interpolation(
+        &self,
+        _texture: &Self::Texture,
+        _interpolation: InterpolationMode,
+    ) {
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        (4096, 4096)
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        Ok(())
+    }
+
+    fn delete_vertex_buffer(&self, _buffer: Self::VertexBuffer) {}
+
+    fn write_vertices(&self, _buffer: &Self::VertexBuffer, _vertices: &[Vertex], _indices: &[u32]) {
+    }
+
+    fn push_buffers(
+        &self,
+        _vertex_buffer: &Self::VertexBuffer,
+        _current_texture: &Self::Texture,
+        _mask_texture: &Self::Texture,
+        _transform: &Affine,
+        _size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn steady_state_conversions_do_not_allocate() {
+    let pixels: Vec<u8> = (0..SIZE * SIZE * 4).map(|i| i as u8).collect();
+    let mut source = Source::new(NullContext).unwrap();
+    let mut rc = source.render_context(SIZE as u32, SIZE as u32);
+
+    // Let the staging buffer grow to the size of the image.
+    for format in [ImageFormat::RgbaSeparate, ImageFormat::Grayscale] {
+        rc.make_image(
+            SIZE,
+            SIZE,
+            &pixels[..SIZE * SIZE * format.bytes_per_pixel()],
+            format,
+        )
+        .unwrap();
+    }
+
+    for format in [ImageFormat::RgbaSeparate, ImageFormat::Grayscale] {
+        let data = &pixels[..SIZE * SIZE * format.bytes_per_pixel()];
+        let start = LARGE_ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..8 {
+            rc.make_image(SIZE, SIZE, data, format).unwrap();
+        }
+        let allocations = LARGE_ALLOCATIONS.load(Ordering::Relaxed) - start;
+        assert_eq!(allocations, 0, "converting {format:?} images allocated");
+    }
+
+    rc.finish().unwrap();
+}
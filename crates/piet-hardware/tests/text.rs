@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for laying out text.
+
+use piet_gpu_soft::SoftwareContext;
+use piet_hardware::piet::{
+    Color, FontFamily, RenderContext as _, Text as _, TextAttribute, TextLayout as _,
+    TextLayoutBuilder as _,
+};
+use piet_hardware::{Command, RecordingContext, Source, Text};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The color that highlighted ranges are drawn in.
+const HIGHLIGHT: [u8; 4] = [0xFF, 0, 0, 0xFF];
+
+fn text() -> Text {
+    Source::new(SoftwareContext::new(1, 1))
+        .unwrap()
+        .text()
+        .clone()
+}
+
+/// Draw `string` in black with `range` highlighted, and return the vertex colors of each draw.
+fn draw_highlighted(string: &str, range: Range<usize>) -> Vec<Vec<[u8; 4]>> {
+    let mut source = Source::new(RecordingContext::new(SoftwareContext::new(128, 64))).unwrap();
+    let mut rc = source.render_context(128, 64);
+    let layout = rc
+        .text()
+        .new_text_layout(string.to_string())
+        .text_color(Color::BLACK)
+        .range_attribute(range, TextAttribute::TextColor(Color::RED))
+        .build()
+        .unwrap();
+    rc.draw_text(&layout, (0.0, 0.0));
+    rc.finish().unwrap();
+    drop(rc);
+
+    let mut buffers = HashMap::new();
+    let mut draws = Vec::new();
+    for command in source.context().take_commands() {
+        match command {
+            Command::WriteVertices {
+                id,
+                vertices,
+                indices,
+            } => {
+                let colors: Vec<_> = indices
+                    .iter()
+                    .map(|&index| vertices[index as usize].color)
+                    .collect();
+                buffers.insert(id, colors);
+            }
+            Command::PushBuffers { buffer, .. } => {
+                if let Some(colors) = buffers.get(&buffer).filter(|colors| !colors.is_empty()) {
+                    draws.push(colors.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    draws
+}
+
+/// Get the colors of the glyphs in the only draw of `draws`.
+fn glyph_colors(draws: &[Vec<[u8; 4]>]) -> Vec<[u8; 4]> {
+    assert_eq!(
+        draws.len(),
+        1,
+        "the text was split into {} draws",
+        draws.len()
+    );
+    draws[0]
+        .chunks(6)
+        .map(|quad| {
+            assert!(quad.iter().all(|color| *color == quad[0]));
+            quad[0]
+        })
+        .collect()
+}
+
+#[test]
+fn highlight_across_line_breaks() {
+    let colors = glyph_colors(&draw_highlighted("abc\ndef\nghi", 2..9));
+
+    // Everything from "c" to "g" is highlighted, including the whole middle line.
+    assert_eq!(colors.len(), 9);
+    assert_eq!(
+        colors.iter().filter(|&&color| color == HIGHLIGHT).count(),
+        5
+    );
+    assert_ne!(colors[0], HIGHLIGHT);
+    assert_ne!(colors[8], HIGHLIGHT);
+}
+
+#[test]
+fn highlight_empty_range() {
+    let colors = glyph_colors(&draw_highlighted("abc\ndef", 2..2));
+    assert_eq!(colors.len(), 6);
+    assert!(!colors.contains(&HIGHLIGHT));
+}
+
+#[test]
+fn highlight_reversed_range() {
+    #[allow(clippy::reversed_empty_ranges)]
+    let colors = glyph_colors(&draw_highlighted("abc\ndef", 5..1));
+    assert_eq!(colors.len(), 6);
+    assert!(!colors.contains(&HIGHLIGHT));
+}
+
+#[test]
+fn highlight_out_of_bounds() {
+    let colors = glyph_colors(&draw_highlighted("abc\ndef", 4..100));
+    assert_eq!(colors.len(), 6);
+    assert_eq!(
+        colors.iter().filter(|&&color| color == HIGHLIGHT).count(),
+        3
+    );
+
+    let colors = glyph_colors(&draw_highlighted("abc\ndef", 50..100));
+    assert_eq!(colors.len(), 6);
+    assert!(!colors.contains(&HIGHLIGHT));
+}
+
+#[test]
+fn truncate_after_paragraphs() {
+    let layout = text()
+        .new_text_layout("aaa\nbbb\nccc")
+        .max_lines(2)
+        .build()
+        .unwrap();
+
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.truncated_at(), Some("aaa\nbbb".len()));
+    assert_eq!(layout.text(), "aaa\nbbb\u{2026}");
+}
+
+#[test]
+fn truncate_non_ascii() {
+    let string = "éé\nabc\nd";
+    let layout = text().new_text_layout(string).max_lines(2).build().unwrap();
+
+    let cut = layout.truncated_at().unwrap();
+    assert!(string.is_char_boundary(cut));
+    assert_eq!(&string[..cut], "éé\nabc");
+}
+
+/// Draw `extra` at `size` after the printable ASCII characters, and return the pixels.
+fn draw_ascii(
+    source: &mut Source<RecordingContext<SoftwareContext>>,
+    extra: Option<f64>,
+) -> Vec<[u8; 4]> {
+    let ascii = (b'!'..=b'~').map(char::from).collect::<String>();
+    let mut rc = source.render_context(640, 48);
+    rc.clear(None, Color::WHITE);
+    let layout = rc
+        .text()
+        .new_text_layout(ascii)
+        .font(FontFamily::SANS_SERIF, 12.0)
+        .text_color(Color::BLACK)
+        .build()
+        .unwrap();
+    rc.draw_text(&layout, (0.0, 0.0));
+
+    if let Some(size) = extra {
+        let layout = rc
+            .text()
+            .new_text_layout("ABCDEFGHIJ")
+            .font(FontFamily::SANS_SERIF, size)
+            .text_color(Color::BLACK)
+            .build()
+            .unwrap();
+        rc.draw_text(&layout, (0.0, 16.0));
+    }
+
+    rc.finish().unwrap();
+    drop(rc);
+    source.context().inner().pixels()
+}
+
+#[test]
+fn full_atlas_is_repacked_over_several_frames() {
+    let context = SoftwareContext::new(640, 48).with_max_texture_size(128, 128);
+    let mut source = Source::new(RecordingContext::new(context)).unwrap();
+    draw_ascii(&mut source, None);
+    source.context().take_commands();
+
+    // Draw larger and larger text until the atlas fills up and a new texture is made for
+    // the glyphs that are still drawn.
+    let writes = |commands: &[Command], repacked: u32| {
+        commands
+            .iter()
+            .filter(
+                |command| matches!(command, Command::WriteSubtexture { id, .. } if *id == repacked),
+            )
+            .count()
+    };
+    let mut writes_per_frame = Vec::new();
+    for size in (1..20).map(|i| 12.0 + 8.0 * i as f64) {
+        draw_ascii(&mut source, Some(size));
+        let commands = source.context().take_commands();
+        let created = commands.iter().find_map(|command| match command {
+            Command::CreateTexture { id, .. } => Some(*id),
+            _ => None,
+        });
+        if let Some(id) = created {
+            writes_per_frame.push((id, writes(&commands, id)));
+            break;
+        }
+    }
+    let (repacked, first_writes) = writes_per_frame.pop().expect("the atlas never filled up");
+    let mut writes_per_frame = vec![first_writes];
+
+    // The glyphs are moved into it a few at a time, until it replaces the old one.
+    let mut pixels = Vec::new();
+    for _ in 0..4 {
+        pixels = draw_ascii(&mut source, None);
+        let commands = source.context().take_commands();
+        writes_per_frame.push(writes(&commands, repacked));
+        if commands.iter().any(|command| {
+            matches!(command, Command::PushBuffers { texture, .. } if *texture == repacked)
+        }) {
+            break;
+        }
+    }
+    assert!(writes_per_frame.len() > 1, "{writes_per_frame:?}");
+    assert!(writes_per_frame.iter().all(|writes| *writes <= 64));
+    assert!(writes_per_frame.iter().sum::<usize>() >= 94);
+
+    // The glyphs are drawn from the new texture just like from a fresh atlas.
+    let context = SoftwareContext::new(640, 48).with_max_texture_size(128, 128);
+    let mut fresh = Source::new(RecordingContext::new(context)).unwrap();
+    assert_eq!(pixels, draw_ascii(&mut fresh, None));
+}
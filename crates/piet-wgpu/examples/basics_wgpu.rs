@@ -19,7 +19,6 @@
 
 use futures_lite::future;
 use instant::{Duration, Instant};
-use std::rc::Rc;
 
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::EventLoop;
@@ -27,7 +26,7 @@ use winit::window::WindowBuilder;
 
 use piet_hardware::piet::kurbo::{Affine, BezPath, Point, Rect, Vec2};
 use piet_hardware::piet::{self, GradientStop, RenderContext as _};
-use piet_wgpu::{RenderContext, WgpuContext};
+use piet_wgpu::{RenderContext, SurfaceTarget, WgpuContext};
 
 const ORANGES: &[u8] = include_bytes!("../../piet-glow/examples/assets/oranges.jpg");
 
@@ -204,7 +203,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut state = None;
     let format = wgpu::TextureFormat::Bgra8Unorm;
-    let mut config = wgpu::SurfaceConfiguration {
+    let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format,
         width: 0,
@@ -213,7 +212,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         alpha_mode: wgpu::CompositeAlphaMode::Opaque,
         view_formats: vec![format],
     };
-    let mut window_size = (0, 0);
 
     event_loop.run(move |ev, elwt, control_flow| {
         control_flow.set_poll();
@@ -247,16 +245,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     None,
                 ))
                 .expect("Failed to create device");
-                let device = Rc::new(device);
 
-                config.width = size.width;
-                config.height = size.height;
-                surface.configure(&device, &config);
+                let present_modes = surface.get_capabilities(&adaptor).present_modes;
+                let mut surface = SurfaceTarget::new(
+                    surface,
+                    wgpu::SurfaceConfiguration {
+                        width: size.width,
+                        height: size.height,
+                        ..config.clone()
+                    },
+                );
+                surface.set_supported_present_modes(&present_modes);
 
-                let context = WgpuContext::new((device.clone(), queue), format, 1)
+                let context = WgpuContext::new((device, queue), format, 1)
                     .expect("Failed to create WgpuContext");
 
-                state = Some((window, surface, context, device));
+                state = Some((window, surface, context));
             }
 
             Event::Suspended => {
@@ -265,37 +269,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             Event::RedrawEventsCleared => {
-                if let Some((_, surface, context, _)) = &mut state {
-                    let frame = surface
-                        .get_current_texture()
-                        .expect("Failed to get texture view");
-
-                    // Draw using piet.
+                if let Some((_, surface, context)) = &mut state {
+                    // Draw using piet. The frame is presented when the context is finished.
+                    let (width, height) = surface.size();
                     draw(
-                        &mut context.render_context(
-                            frame
-                                .texture
-                                .create_view(&wgpu::TextureViewDescriptor::default()),
-                            window_size.0,
-                            window_size.1,
-                        ),
-                        window_size.0,
-                        window_size.1,
+                        &mut context
+                            .surface_context(surface)
+                            .expect("Failed to get the next frame"),
+                        width,
+                        height,
                     );
-
-                    // Present the frame.
-                    frame.present();
                 }
             }
 
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => control_flow.set_exit(),
                 WindowEvent::Resized(size) => {
-                    window_size = (size.width, size.height);
-                    if let Some((_, surface, _, device)) = &state {
-                        config.width = size.width;
-                        config.height = size.height;
-                        surface.configure(device, &config);
+                    if let Some((_, surface, context)) = &mut state {
+                        surface.resize(size.width, size.height);
+                        context.resize(size.width, size.height).ok();
                     }
                 }
                 _ => {}
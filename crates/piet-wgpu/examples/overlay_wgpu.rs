@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-glow`.
+//
+// `piet-glow` is free software: you can redistribute it and/or modify it under the terms of
+// either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+//
+// `piet-glow` is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the GNU Lesser General Public License or the Mozilla Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-glow`. If not, see <https://www.gnu.org/licenses/>.
+
+//! The `piet-glow` basics.rs example, but with piet-wgpu.
+
+//! Draw a HUD with piet into a texture, and composite it over a scene drawn by another
+//! pipeline.
+
+use futures_lite::future;
+use std::rc::Rc;
+
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+use piet_hardware::piet::kurbo::{Rect, RoundedRect};
+use piet_hardware::piet::{self, RenderContext as _, Text as _, TextLayoutBuilder as _};
+use piet_wgpu::{DeviceAndQueue, TextureTarget, WgpuContext};
+
+/// Samples the HUD texture over the whole window.
+const COMPOSITE_SHADER: &str = r#"
+@group(0) @binding(0) var hud: texture_2d<f32>;
+@group(0) @binding(1) var hud_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // A triangle that covers the whole screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The HUD is premultiplied, which the blend state expects.
+    return textureSample(hud, hud_sampler, in.uv);
+}
+"#;
+
+struct State {
+    window: winit::window::Window,
+    surface: wgpu::Surface,
+    device: Rc<wgpu::Device>,
+    context: WgpuContext<(Rc<wgpu::Device>, wgpu::Queue)>,
+    hud: TextureTarget,
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+}
+
+impl State {
+    fn bind_group(&self) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("HUD bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.hud.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+    let event_loop = EventLoop::new();
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::PRIMARY,
+        dx12_shader_compiler: Default::default(),
+    });
+
+    let format = wgpu::TextureFormat::Bgra8Unorm;
+    let mut config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: 0,
+        height: 0,
+        present_mode: wgpu::PresentMode::AutoVsync,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![format],
+    };
+
+    let mut state: Option<State> = None;
+    let mut tick = 0u32;
+
+    event_loop.run(move |ev, elwt, control_flow| {
+        control_flow.set_poll();
+
+        match ev {
+            Event::Resumed => {
+                let window = WindowBuilder::new()
+                    .with_title("piet-wgpu overlay")
+                    .build(elwt)
+                    .expect("Failed to create window");
+                let size = window.inner_size();
+
+                let surface =
+                    unsafe { instance.create_surface(&window) }.expect("Failed to create surface");
+                let adapter =
+                    future::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                        compatible_surface: Some(&surface),
+                        ..Default::default()
+                    }))
+                    .expect("Failed to find an appropriate adapter");
+                let (device, queue) = future::block_on(adapter.request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: Some("Device descriptor"),
+                        features: wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
+                        limits: wgpu::Limits::default(),
+                    },
+                    None,
+                ))
+                .expect("Failed to create device");
+                let device = Rc::new(device);
+
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(&device, &config);
+
+                // The piet context draws into the HUD texture, not the window.
+                let context = WgpuContext::new((device.clone(), queue), TextureTarget::FORMAT, 1)
+                    .expect("Failed to create WgpuContext");
+                let hud = TextureTarget::new(&device, size.width, size.height);
+
+                // Set up the pipeline that composites the HUD onto the window.
+                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("HUD shader"),
+                    source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()),
+                });
+                let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("HUD bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                });
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("HUD pipeline layout"),
+                        bind_group_layouts: &[&layout],
+                        push_constant_ranges: &[],
+                    });
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("HUD pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                });
+                let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+                // The bind group is recreated whenever the HUD texture is.
+                let placeholder = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("HUD bind group"),
+                    layout: &layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&hud.view()),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                });
+
+                state = Some(State {
+                    window,
+                    surface,
+                    device,
+                    context,
+                    hud,
+                    pipeline,
+                    layout,
+                    sampler,
+                    bind_group: placeholder,
+                });
+            }
+
+            Event::Suspended => state = None,
+
+            Event::RedrawEventsCleared => {
+                let state = match &mut state {
+                    Some(state) => state,
+                    None => return,
+                };
+                tick = tick.wrapping_add(1);
+
+                // Draw the HUD with piet.
+                {
+                    let (width, height) = state.hud.size();
+                    let mut rc = state.context.texture_context(&state.hud);
+                    rc.clear(None, piet::Color::TRANSPARENT);
+
+                    let panel = RoundedRect::from_rect(
+                        Rect::new(
+                            20.0,
+                            height as f64 - 100.0,
+                            width as f64 - 20.0,
+                            height as f64 - 20.0,
+                        ),
+                        12.0,
+                    );
+                    rc.fill(panel, &piet::Color::rgba8(0x10, 0x10, 0x20, 0xC0));
+
+                    // A health bar that drains and refills.
+                    let health = ((tick as f64 / 60.0).sin() + 1.0) / 2.0;
+                    let bar = Rect::new(
+                        40.0,
+                        height as f64 - 80.0,
+                        40.0 + 300.0 * health,
+                        height as f64 - 60.0,
+                    );
+                    rc.fill(bar, &piet::Color::rgba8(0xE0, 0x30, 0x30, 0xFF));
+
+                    if let Ok(label) = rc
+                        .text()
+                        .new_text_layout("HP")
+                        .font(piet::FontFamily::SANS_SERIF, 18.0)
+                        .text_color(piet::Color::WHITE)
+                        .build()
+                    {
+                        rc.draw_text(&label, (360.0, height as f64 - 82.0));
+                    }
+
+                    rc.finish().unwrap();
+                }
+
+                // Draw the "game" and composite the HUD on top of it.
+                let frame = state
+                    .surface
+                    .get_current_texture()
+                    .expect("Failed to get texture view");
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                let device_and_queue = state.context.device_and_queue();
+                let mut encoder = device_and_queue
+                    .device()
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+                {
+                    let shade = ((tick as f64 / 120.0).sin() + 1.0) / 4.0;
+                    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Game pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color {
+                                    r: 0.1,
+                                    g: 0.2 + shade,
+                                    b: 0.4,
+                                    a: 1.0,
+                                }),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+
+                    pass.set_pipeline(&state.pipeline);
+                    pass.set_bind_group(0, &state.bind_group, &[]);
+                    pass.draw(0..3, 0..1);
+                }
+                device_and_queue
+                    .queue()
+                    .submit(std::iter::once(encoder.finish()));
+                frame.present();
+            }
+
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => control_flow.set_exit(),
+                WindowEvent::Resized(size) => {
+                    if let Some(state) = &mut state {
+                        config.width = size.width;
+                        config.height = size.height;
+                        state.surface.configure(&state.device, &config);
+
+                        if state.hud.resize(&state.device, size.width, size.height) {
+                            state.bind_group = state.bind_group();
+                        }
+                        state.window.request_redraw();
+                    }
+                }
+                _ => {}
+            },
+
+            _ => {}
+        }
+    })
+}
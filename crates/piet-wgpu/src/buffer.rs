@@ -132,6 +132,11 @@ pub(crate) struct Buffer {
 
     /// The inner WGPU buffer.
     buffer: BufferCollection,
+
+    /// The slice that was popped last, if nothing has been written since.
+    ///
+    /// This is popped again when the buffer is drawn several times without being written to.
+    last_slice: Option<BufferSlice>,
 }
 
 /// Either a single buffer or a list of them.
@@ -183,7 +188,7 @@ impl BufferCollection {
         match mem::replace(self, Self::Hole) {
             Self::Hole => *self = Self::Single(buffer),
             Self::Single(old_buffer) => {
-                tracing::debug!("using list-based buffering strategy");
+                debug!("using list-based buffering strategy");
                 *self = Self::List(vec![old_buffer, buffer])
             }
             Self::List(mut buffers) => {
@@ -284,25 +289,29 @@ impl Buffer {
 
         // Update the cursor.
         self.end_cursor = self.start_cursor + data.len();
-        tracing::debug!(
+        self.last_slice = None;
+        debug!(
             "Wrote to {} buffer from {} to {}",
-            self.buffer_id,
-            self.start_cursor,
-            self.end_cursor
+            self.buffer_id, self.start_cursor, self.end_cursor
         );
     }
 
     /// Pop off a slice of the buffer.
     pub(crate) fn pop_slice(&mut self) -> BufferSlice {
+        if let Some(slice) = self.last_slice {
+            return slice;
+        }
+
         let slice = BufferSlice {
             buffer_index: self.buffer.len() - 1,
             range: (self.start_cursor as u64, self.end_cursor as u64),
         };
 
-        tracing::debug!(slice=?slice, "Popped {} buffer slice", self.buffer_id);
+        debug!("Popped {} buffer slice: {:?}", self.buffer_id, slice);
 
         // Update the cursor.
         self.start_cursor = self.end_cursor;
+        self.last_slice = Some(slice);
 
         slice
     }
@@ -316,11 +325,12 @@ impl Buffer {
         // If we are using multiple buffers, combine them all into one.
         if matches!(self.buffer, BufferCollection::List(..)) {
             let desired_capacity = self.capacity.next_power_of_two();
-            tracing::debug!("Resizing {} buffer to {}", self.buffer_id, desired_capacity);
+            debug!("Resizing {} buffer to {}", self.buffer_id, desired_capacity);
             let new_buffer = self.create_buffer(device, desired_capacity);
             self.buffer = BufferCollection::Single(new_buffer);
             self.capacity = desired_capacity;
             self.last_capacity = desired_capacity;
+            self.last_slice = None;
         }
     }
 
@@ -341,6 +351,7 @@ impl Buffer {
             buffer_id,
             usage,
             buffer: BufferCollection::Hole,
+            last_slice: None,
         };
 
         this.buffer = BufferCollection::Single(
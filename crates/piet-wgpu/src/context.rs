@@ -22,28 +22,58 @@
 //! The underlying GPU context.
 
 use super::buffer::{Buffer, BufferSlice, WgpuVertexBuffer};
-use super::texture::{BorrowedTexture, WgpuTexture};
-use super::DeviceAndQueue;
+use super::mipmap::MipmapPipeline;
+use super::texture::{BorrowedTexture, DepthStencil, WgpuTexture};
+use super::{DeviceAndQueue, Overlay};
 
 use std::cell::{Cell, Ref, RefCell};
 use std::collections::hash_map::{Entry, HashMap};
 use std::convert::Infallible;
+use std::future::Future;
 use std::mem;
 use std::num::NonZeroU64;
+use std::ptr;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
 
-use piet_hardware::piet::kurbo::Affine;
+use piet_hardware::piet::kurbo::{Affine, Rect};
 use piet_hardware::piet::{Color, InterpolationMode};
-use piet_hardware::Vertex;
+use piet_hardware::{
+    BlendFactor, ClipShape, ColorMatrix, CompositeMode, CustomBrush, PresentMode, Projection,
+    Vertex,
+};
 
 use wgpu::util::DeviceExt;
 
 const SHADER_SOURCE: &str = include_str!("piet.wgsl");
+const BRUSH_SHADER_SOURCE: &str = include_str!("brush.wgsl");
 
 /// A wrapper around a `wgpu` context.
+/// The callback that shows images on overlays.
+type OverlayHandler = Box<dyn FnMut(Overlay<'_>) -> bool>;
+
 pub(crate) struct GpuContext<DaQ: ?Sized> {
-    /// The rendering pipeline.
-    pipeline: wgpu::RenderPipeline,
+    /// The rendering pipelines.
+    pipelines: Rc<Pipelines>,
+
+    /// The layout shared by every rendering pipeline.
+    pipeline_layout: wgpu::PipelineLayout,
+
+    /// The pipelines that push and pop clips in the stencil buffer.
+    clip_pipelines: ClipPipelines,
+
+    /// The texture that clips are drawn with, since their colors aren't written.
+    clip_texture: RefCell<Option<WgpuTexture>>,
+
+    /// The rendering pipelines of the custom brushes that were registered, by their index.
+    ///
+    /// These composite with [`CompositeMode::SourceOver`]. Like the built-in pipelines, the
+    /// ones for other composite modes are created from them and cached as they are needed.
+    custom_pipelines: RefCell<Vec<Rc<Pipelines>>>,
+
+    /// The custom brush that draws are filled with, if any.
+    custom_brush: Cell<Option<CustomBrush>>,
 
     /// The bind group layout for uniforms.
     uniform_bind_layout: wgpu::BindGroupLayout,
@@ -51,15 +81,57 @@ pub(crate) struct GpuContext<DaQ: ?Sized> {
     /// Bind group for textures.
     texture_bind_layout: wgpu::BindGroupLayout,
 
+    /// The pipeline that generates mip levels, once a texture has needed them.
+    mipmap_pipeline: RefCell<Option<Rc<MipmapPipeline>>>,
+
     /// The existing uniform buffers.
     uniform_buffers: RefCell<HashMap<UniformBytes, (wgpu::Buffer, Rc<wgpu::BindGroup>)>>,
 
     /// The clearing color.
     clear_color: Cell<Option<Color>>,
 
+    /// The color matrix applied to texture colors.
+    color_matrix: Cell<ColorMatrix>,
+
+    /// How draws are blended with the target.
+    composite_mode: Cell<CompositeMode>,
+
+    /// The shape that draws are clipped to in the fragment shader.
+    clip_shape: Cell<Option<ClipShape>>,
+
+    /// The pixels that draws are restricted to with a scissor rectangle.
+    scissor: Cell<Option<[u32; 4]>>,
+
+    /// The projection applied after the transform of each draw.
+    projection: Cell<Projection>,
+
+    /// The interpolation that textures are sampled with, if it is overridden.
+    sampler_interpolation: Cell<Option<InterpolationMode>>,
+
+    /// How frames of surfaces are presented, if it was chosen.
+    present_mode: Cell<Option<PresentMode>>,
+
     /// The view of the texture.
     texture_view: RefCell<Option<wgpu::TextureView>>,
 
+    /// The surface frame being drawn to, which is presented when the drawing is finished.
+    frame: RefCell<Option<wgpu::SurfaceTexture>>,
+
+    /// The render target that is currently bound, and the view that it replaced.
+    render_target: RefCell<Option<(WgpuTexture, Option<wgpu::TextureView>)>>,
+
+    /// The depth and stencil attachment of the texture view, when no render target is bound.
+    depth_stencil: RefCell<Option<DepthStencil>>,
+
+    /// The format of the texture that is rendered to.
+    output_format: wgpu::TextureFormat,
+
+    /// The number of samples per pixel of the texture that is rendered to.
+    samples: u32,
+
+    /// The callback that shows images on overlays, if the application set one.
+    overlay_handler: RefCell<Option<OverlayHandler>>,
+
     /// Latest buffer pushes.
     pushed_buffers: RefCell<Vec<PushedBuffer>>,
 
@@ -70,6 +142,271 @@ pub(crate) struct GpuContext<DaQ: ?Sized> {
     device_and_queue: DaQ,
 }
 
+/// The pipelines that draw with a fragment shader.
+struct Pipelines {
+    /// The rendering pipeline.
+    pipeline: wgpu::RenderPipeline,
+
+    /// The rendering pipeline for targets with a depth and stencil attachment.
+    depth_pipeline: wgpu::RenderPipeline,
+
+    /// The rendering pipeline that only draws inside of the clips in the stencil buffer.
+    clip_pipeline: wgpu::RenderPipeline,
+
+    /// The shader that the pipelines draw with.
+    shader: Rc<wgpu::ShaderModule>,
+
+    /// The entry point of the fragment shader.
+    fragment_entry: &'static str,
+
+    /// The pipelines that draw with other composite modes, created as they are needed.
+    composite: RefCell<HashMap<CompositeMode, Rc<Pipelines>>>,
+}
+
+impl Pipelines {
+    /// Create the pipelines, which differ in whether there is a depth and stencil attachment,
+    /// and whether its stencil is tested.
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: Rc<wgpu::ShaderModule>,
+        fragment_entry: &'static str,
+        output_color_format: wgpu::TextureFormat,
+        samples: u32,
+        mode: CompositeMode,
+    ) -> Self {
+        let create_pipeline = |label, depth_stencil| {
+            create_pipeline(
+                device,
+                layout,
+                &shader,
+                fragment_entry,
+                output_color_format,
+                samples,
+                label,
+                depth_stencil,
+                blend_state(mode),
+                wgpu::ColorWrites::ALL,
+            )
+        };
+
+        Self {
+            pipeline: create_pipeline("piet-wgpu pipeline", None),
+            depth_pipeline: create_pipeline(
+                "piet-wgpu depth and stencil pipeline",
+                Some(stencil_state(
+                    wgpu::CompareFunction::Always,
+                    wgpu::StencilOperation::Keep,
+                )),
+            ),
+            clip_pipeline: create_pipeline(
+                "piet-wgpu clipped pipeline",
+                Some(stencil_state(
+                    wgpu::CompareFunction::Equal,
+                    wgpu::StencilOperation::Keep,
+                )),
+            ),
+            shader,
+            fragment_entry,
+            composite: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Get the pipelines that draw with the same shader as these, with the composite mode
+    /// `mode`.
+    fn with_composite_mode(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        output_color_format: wgpu::TextureFormat,
+        samples: u32,
+        mode: CompositeMode,
+    ) -> Rc<Self> {
+        self.composite
+            .borrow_mut()
+            .entry(mode)
+            .or_insert_with(|| {
+                Rc::new(Self::new(
+                    device,
+                    layout,
+                    self.shader.clone(),
+                    self.fragment_entry,
+                    output_color_format,
+                    samples,
+                    mode,
+                ))
+            })
+            .clone()
+    }
+
+    /// Get the pipeline for a target with or without a depth and stencil attachment, and with
+    /// or without clips in its stencil buffer.
+    fn get(&self, depth_stencil: bool, clipped: bool) -> &wgpu::RenderPipeline {
+        match (depth_stencil, clipped) {
+            (false, _) => &self.pipeline,
+            (true, false) => &self.depth_pipeline,
+            (true, true) => &self.clip_pipeline,
+        }
+    }
+}
+
+/// The pipelines that write clips to the stencil buffer, without writing colors.
+struct ClipPipelines {
+    /// The pipeline that increments the stencil inside of a clip.
+    push: wgpu::RenderPipeline,
+
+    /// The pipeline that decrements the stencil inside of a clip.
+    pop: wgpu::RenderPipeline,
+}
+
+impl ClipPipelines {
+    /// Create the pipelines.
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        output_color_format: wgpu::TextureFormat,
+        samples: u32,
+    ) -> Self {
+        let create_pipeline = |label, operation| {
+            create_pipeline(
+                device,
+                layout,
+                shader,
+                "fragment_main",
+                output_color_format,
+                samples,
+                label,
+                Some(stencil_state(wgpu::CompareFunction::Equal, operation)),
+                blend_state(CompositeMode::SourceOver),
+                wgpu::ColorWrites::empty(),
+            )
+        };
+
+        Self {
+            push: create_pipeline(
+                "piet-wgpu clip push pipeline",
+                wgpu::StencilOperation::IncrementClamp,
+            ),
+            pop: create_pipeline(
+                "piet-wgpu clip pop pipeline",
+                wgpu::StencilOperation::DecrementClamp,
+            ),
+        }
+    }
+}
+
+/// Create a rendering pipeline for the vertices of `piet-hardware`.
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry: &str,
+    output_color_format: wgpu::TextureFormat,
+    samples: u32,
+    label: &str,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    blend: wgpu::BlendState,
+    write_mask: wgpu::ColorWrites,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            entry_point: "vertex_main",
+            module: shader,
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: Vertex::STRIDE as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![
+                    // pos: [f32; 2]
+                    0 => Float32x2,
+                    // uv: [f32; 2]
+                    1 => Float32x2,
+                    // color: [u8; 4]
+                    2 => Uint32,
+                ],
+            }],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            unclipped_depth: false,
+            conservative: false,
+            cull_mode: None,
+            front_face: wgpu::FrontFace::default(),
+            polygon_mode: wgpu::PolygonMode::default(),
+            strip_index_format: None,
+        },
+        depth_stencil,
+        multisample: wgpu::MultisampleState {
+            alpha_to_coverage_enabled: false,
+            count: samples,
+            mask: !0,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: output_color_format,
+                blend: Some(blend),
+                write_mask,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+/// Get the blend state that combines draws with the target using `mode`.
+fn blend_state(mode: CompositeMode) -> wgpu::BlendState {
+    let blend_factor = |factor| match factor {
+        BlendFactor::Zero => wgpu::BlendFactor::Zero,
+        BlendFactor::SourceAlpha => wgpu::BlendFactor::SrcAlpha,
+        BlendFactor::OneMinusSourceAlpha => wgpu::BlendFactor::OneMinusSrcAlpha,
+        BlendFactor::DestinationAlpha => wgpu::BlendFactor::DstAlpha,
+        BlendFactor::OneMinusDestinationAlpha => wgpu::BlendFactor::OneMinusDstAlpha,
+        _ => wgpu::BlendFactor::One,
+    };
+
+    let (src, dst) = mode.blend_factors();
+    let component = wgpu::BlendComponent {
+        src_factor: blend_factor(src),
+        dst_factor: blend_factor(dst),
+        operation: wgpu::BlendOperation::Add,
+    };
+    wgpu::BlendState {
+        color: component,
+        alpha: component,
+    }
+}
+
+/// Get the state of a depth and stencil attachment whose depth test always passes, and
+/// whose stencil is compared to the reference with `compare`, then changed by `pass_op`.
+fn stencil_state(
+    compare: wgpu::CompareFunction,
+    pass_op: wgpu::StencilOperation,
+) -> wgpu::DepthStencilState {
+    let face = wgpu::StencilFaceState {
+        compare,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op,
+    };
+
+    wgpu::DepthStencilState {
+        format: DepthStencil::FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+        },
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
 /// Represents a pushed buffer call.
 struct PushedBuffer {
     /// The vertex and index buffers.
@@ -81,8 +418,8 @@ struct PushedBuffer {
     /// The slice into the index buffer.
     index: BufferSlice,
 
-    /// The color texture to use.
-    color_texture: WgpuTexture,
+    /// The bind group of the color texture, as it was sampled when it was pushed.
+    color_bind_group: Rc<wgpu::BindGroup>,
 
     /// The mask texture to use.
     mask_texture: WgpuTexture,
@@ -92,6 +429,27 @@ struct PushedBuffer {
 
     /// The bind group for uniforms.
     uniform_bind_group: Rc<wgpu::BindGroup>,
+
+    /// The pipeline to draw with.
+    pipeline: PushedPipeline,
+
+    /// The value that the stencil is compared to.
+    stencil_reference: u32,
+
+    /// The scissor rectangle, if any.
+    scissor: Option<[u32; 4]>,
+}
+
+/// The pipeline of a pushed buffer.
+enum PushedPipeline {
+    /// Draw with one of these pipelines.
+    Draw(Rc<Pipelines>),
+
+    /// Push a clip to the stencil buffer.
+    PushClip,
+
+    /// Pop a clip from the stencil buffer.
+    PopClip,
 }
 
 /// A borrowed pushed buffer.
@@ -105,9 +463,6 @@ struct BorrowedPush<'a> {
     /// Borrowed index buffer.
     ib: Ref<'a, Buffer>,
 
-    /// Borrowed color texture.
-    color_texture: BorrowedTexture<'a>,
-
     /// Borrowed mask texture.
     mask_texture: BorrowedTexture<'a>,
 
@@ -121,7 +476,6 @@ impl PushedBuffer {
             source: self,
             vb: self.buffers.borrow_vertex_buffer(),
             ib: self.buffers.borrow_index_buffer(),
-            color_texture: self.color_texture.borrow(),
             mask_texture: self.mask_texture.borrow(),
             uniform_bind_group: self.uniform_bind_group.clone(),
         }
@@ -135,11 +489,29 @@ struct Uniforms {
     /// Viewport size.
     viewport_size: [f32; 2],
 
-    /// Padding.
-    pad: [u32; 2],
+    /// Whether the color and mask textures hold a single channel of coverage.
+    coverage: [u32; 2],
 
     /// 3x3 transformation matrix.
     transform: [[f32; 4]; 3],
+
+    /// Color matrix for texture colors, in column-major order.
+    color_matrix: [[f32; 4]; 4],
+
+    /// Offset column of the color matrix.
+    color_offset: [f32; 4],
+
+    /// Rectangle that is clipped to.
+    clip_rect: [f32; 4],
+
+    /// Radii of the corners of the clip, or negative if there is no clip.
+    clip_radii: [f32; 2],
+
+    /// Padding.
+    clip_pad: [u32; 2],
+
+    /// Parameters of the custom brush.
+    brush_params: [[f32; 4]; 2],
 }
 
 type UniformBytes = [u8; mem::size_of::<Uniforms>()];
@@ -149,7 +521,6 @@ impl<DaQ: DeviceAndQueue + ?Sized> GpuContext<DaQ> {
     pub(crate) fn new(
         device_and_queue: DaQ,
         output_color_format: wgpu::TextureFormat,
-        output_depth_format: Option<wgpu::TextureFormat>,
         samples: u32,
     ) -> Self
     where
@@ -157,10 +528,10 @@ impl<DaQ: DeviceAndQueue + ?Sized> GpuContext<DaQ> {
     {
         // Create the shader module.
         let device = device_and_queue.device();
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let shader = Rc::new(device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("piet-wgpu shader"),
             source: wgpu::ShaderSource::Wgsl(SHADER_SOURCE.into()),
-        });
+        }));
 
         // Create a buffer layout for the uniforms.
         let uniform_bind_layout =
@@ -168,12 +539,10 @@ impl<DaQ: DeviceAndQueue + ?Sized> GpuContext<DaQ> {
                 label: Some("piet-wgpu uniform buffer layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(
-                            mem::size_of::<Uniforms>().next_power_of_two() as u64,
-                        ),
+                        min_binding_size: NonZeroU64::new(mem::size_of::<Uniforms>() as u64),
                         ty: wgpu::BufferBindingType::Uniform,
                     },
                     count: None,
@@ -215,80 +584,51 @@ impl<DaQ: DeviceAndQueue + ?Sized> GpuContext<DaQ> {
             push_constant_ranges: &[],
         });
 
-        let depth_stencil = output_depth_format.map(|format| wgpu::DepthStencilState {
-            format,
-            depth_write_enabled: false,
-            depth_compare: wgpu::CompareFunction::Always,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        });
+        let pipelines = Pipelines::new(
+            device,
+            &pipeline_layout,
+            shader.clone(),
+            "fragment_main",
+            output_color_format,
+            samples,
+            CompositeMode::SourceOver,
+        );
 
-        // Create the pipeline.
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("piet-wgpu pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                entry_point: "vertex_main",
-                module: &shader,
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: mem::size_of::<Vertex>() as u64,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![
-                        // pos: [f32; 2]
-                        0 => Float32x2,
-                        // uv: [f32; 2]
-                        1 => Float32x2,
-                        // color: [u8; 4]
-                        2 => Uint32,
-                    ],
-                }],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                unclipped_depth: false,
-                conservative: false,
-                cull_mode: None,
-                front_face: wgpu::FrontFace::default(),
-                polygon_mode: wgpu::PolygonMode::default(),
-                strip_index_format: None,
-            },
-            depth_stencil,
-            multisample: wgpu::MultisampleState {
-                alpha_to_coverage_enabled: false,
-                count: samples,
-                mask: !0,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fragment_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: output_color_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::OneMinusDstAlpha,
-                            dst_factor: wgpu::BlendFactor::DstAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            multiview: None,
-        });
+        let clip_pipelines = ClipPipelines::new(
+            device,
+            &pipeline_layout,
+            &shader,
+            output_color_format,
+            samples,
+        );
 
         Self {
             device_and_queue,
-            pipeline,
+            pipelines: Rc::new(pipelines),
+            clip_pipelines,
+            clip_texture: RefCell::new(None),
+            pipeline_layout,
+            custom_pipelines: RefCell::new(Vec::new()),
+            custom_brush: Cell::new(None),
             uniform_bind_layout,
             texture_bind_layout: texture_buffer_layout,
+            mipmap_pipeline: RefCell::new(None),
             uniform_buffers: RefCell::new(HashMap::new()),
+            color_matrix: Cell::new(ColorMatrix::IDENTITY),
+            composite_mode: Cell::new(CompositeMode::SourceOver),
+            clip_shape: Cell::new(None),
+            scissor: Cell::new(None),
+            projection: Cell::new(Projection::IDENTITY),
+            sampler_interpolation: Cell::new(None),
+            present_mode: Cell::new(None),
             clear_color: Cell::new(None),
             texture_view: RefCell::new(None),
+            frame: RefCell::new(None),
+            render_target: RefCell::new(None),
+            depth_stencil: RefCell::new(None),
+            output_format: output_color_format,
+            samples,
+            overlay_handler: RefCell::new(None),
             pushed_buffers: RefCell::new(Vec::new()),
             next_id: Cell::new(0),
         }
@@ -299,15 +639,204 @@ impl<DaQ: DeviceAndQueue + ?Sized> GpuContext<DaQ> {
         &self.device_and_queue
     }
 
+    /// Set the callback that shows images on overlays.
+    pub(crate) fn set_overlay_handler(&self, handler: OverlayHandler) {
+        *self.overlay_handler.borrow_mut() = Some(handler);
+    }
+
+    /// Get how frames of surfaces are presented, if it was chosen.
+    pub(crate) fn present_mode(&self) -> Option<PresentMode> {
+        self.present_mode.get()
+    }
+
     /// Set the texture view that this GPU context renders to.
     pub(crate) fn set_texture_view(&self, view: wgpu::TextureView) {
+        // A frame that was never finished is dropped without being presented.
+        self.frame.borrow_mut().take();
         *self.texture_view.borrow_mut() = Some(view);
     }
 
+    /// Render to a surface frame, and present it once the drawing is finished.
+    pub(crate) fn set_frame(&self, frame: wgpu::SurfaceTexture) {
+        self.set_texture_view(
+            frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        *self.frame.borrow_mut() = Some(frame);
+    }
+
+    /// Flush any drawing that hasn't been submitted yet.
+    fn flush_pending(&self) {
+        if !self.pushed_buffers.borrow().is_empty() || self.clear_color.get().is_some() {
+            match piet_hardware::GpuContext::flush(self) {
+                Ok(()) => {}
+                Err(err) => match err {},
+            }
+        }
+    }
+
+    /// Compile the code of a custom brush, returning its index.
+    ///
+    /// Returns the validation error if the code fails to compile. Only the pipelines for
+    /// [`CompositeMode::SourceOver`] are created here; the ones for other composite modes are
+    /// created from the same shader when a draw first needs them.
+    pub(crate) fn register_brush_shader(&self, source: &str) -> Result<u32, wgpu::Error> {
+        let device = self.device_and_queue.device();
+
+        // The code goes after the shared shader code and the fragment shader that calls it.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("piet-wgpu custom brush shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{SHADER_SOURCE}\n{BRUSH_SHADER_SOURCE}\n{source}").into(),
+            ),
+        });
+        let pipelines = Pipelines::new(
+            device,
+            &self.pipeline_layout,
+            Rc::new(shader),
+            "fragment_brush",
+            self.output_format,
+            self.samples,
+            CompositeMode::SourceOver,
+        );
+        if let Some(Some(error)) = poll_now(device.pop_error_scope()) {
+            return Err(error);
+        }
+
+        let mut custom_pipelines = self.custom_pipelines.borrow_mut();
+        custom_pipelines.push(Rc::new(pipelines));
+        Ok(custom_pipelines.len() as u32 - 1)
+    }
+
+    /// Get the bind group of a uniform buffer holding `uniforms`, creating it if needed.
+    fn uniform_bind_group(&self, uniforms: Uniforms) -> Rc<wgpu::BindGroup> {
+        let bytes: UniformBytes = bytemuck::cast(uniforms);
+
+        match self.uniform_buffers.borrow_mut().entry(bytes) {
+            Entry::Occupied(o) => o.get().1.clone(),
+            Entry::Vacant(entry) => {
+                // Create a new buffer.
+                let buffer = self.device_and_queue.device().create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: None,
+                        contents: &bytes,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    },
+                );
+
+                // Create a new bind group.
+                let bind_group =
+                    self.device_and_queue
+                        .device()
+                        .create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: None,
+                            layout: &self.uniform_bind_layout,
+                            entries: &[wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: buffer.as_entire_binding(),
+                            }],
+                        });
+
+                // Insert it into the set.
+                let (_, bind_group) = entry.insert((buffer, Rc::new(bind_group)));
+
+                // Return the bind group.
+                bind_group.clone()
+            }
+        }
+    }
+
+    /// Run a function on the depth and stencil attachment of the target, if it is attached.
+    fn with_depth_stencil<R>(&self, f: impl FnOnce(&mut DepthStencil) -> R) -> Option<R> {
+        let render_target = self.render_target.borrow();
+        match &*render_target {
+            Some((texture, _)) => texture
+                .borrow_mut()
+                .depth_stencil_mut()
+                .as_mut()
+                .filter(|ds| ds.attached)
+                .map(f),
+            None => self
+                .depth_stencil
+                .borrow_mut()
+                .as_mut()
+                .filter(|ds| ds.attached)
+                .map(f),
+        }
+    }
+
+    /// Push the triangles of a clip, which change the stencil where it is `reference`.
+    fn push_clip(
+        &self,
+        vertex_buffer: &WgpuVertexBuffer,
+        (width, height): (u32, u32),
+        pipeline: PushedPipeline,
+        reference: u32,
+    ) {
+        let vb_slice = vertex_buffer.borrow_vertex_buffer_mut().pop_slice();
+        let ib_slice = vertex_buffer.borrow_index_buffer_mut().pop_slice();
+
+        // The triangles are already in pixels, and their colors aren't written.
+        let texture = self
+            .clip_texture
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let texture = WgpuTexture::create_texture(
+                    self,
+                    InterpolationMode::NearestNeighbor,
+                    piet_hardware::RepeatStrategy::Clamp,
+                );
+                texture.borrow_mut().write_texture(
+                    self,
+                    (1, 1),
+                    piet_hardware::piet::ImageFormat::RgbaPremul,
+                    Some(&[0xFF; 4]),
+                );
+                texture
+            })
+            .clone();
+        let (color_matrix, color_offset) = color_matrix_to_column_major(&ColorMatrix::IDENTITY);
+        let uniforms = Uniforms {
+            transform: to_column_major(Projection::IDENTITY.after(&Affine::IDENTITY)),
+            color_matrix,
+            color_offset,
+            clip_rect: [0.0; 4],
+            clip_radii: [-1.0; 2],
+            clip_pad: [0xFFFFFFFF; 2],
+            brush_params: [[0.0; 4]; 2],
+            coverage: [0; 2],
+            viewport_size: [width as f32, height as f32],
+        };
+
+        let color_bind_group = texture.borrow().bind_group().clone();
+        self.pushed_buffers.borrow_mut().push(PushedBuffer {
+            buffers: vertex_buffer.clone(),
+            vertex: vb_slice,
+            index: ib_slice,
+            color_bind_group,
+            mask_texture: texture,
+            uniform_bind_group: self.uniform_bind_group(uniforms),
+            pipeline,
+            stencil_reference: reference,
+            scissor: None,
+            viewport_size: [width as f32, height as f32],
+        });
+    }
+
     pub(crate) fn texture_bind_layout(&self) -> &wgpu::BindGroupLayout {
         &self.texture_bind_layout
     }
 
+    /// Get the pipeline that generates mip levels, creating it if needed.
+    pub(crate) fn mipmap_pipeline(&self) -> Rc<MipmapPipeline> {
+        self.mipmap_pipeline
+            .borrow_mut()
+            .get_or_insert_with(|| Rc::new(MipmapPipeline::new(self.device_and_queue.device())))
+            .clone()
+    }
+
     pub(crate) fn next_id(&self) -> usize {
         let id = self.next_id.get();
         self.next_id.set(id + 1);
@@ -347,6 +876,16 @@ impl<DaQ: DeviceAndQueue + ?Sized> piet_hardware::GpuContext for GpuContext<DaQ>
         let mut buffers_to_clear = Vec::with_capacity(1);
         let texture_view = self.texture_view.borrow();
 
+        // Draw with the depth and stencil attachment of the target, if it is attached.
+        let render_target = self.render_target.borrow();
+        let target = render_target.as_ref().map(|(texture, _)| texture.borrow());
+        let surface_depth_stencil = self.depth_stencil.borrow();
+        let depth_stencil = match &target {
+            Some(target) => target.depth_stencil(),
+            None => surface_depth_stencil.as_ref(),
+        }
+        .filter(|depth_stencil| depth_stencil.attached);
+
         // Create a render pass.
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("piet-wgpu render pass"),
@@ -358,17 +897,24 @@ impl<DaQ: DeviceAndQueue + ?Sized> piet_hardware::GpuContext for GpuContext<DaQ>
                         None => wgpu::LoadOp::Load,
                         Some(clr) => wgpu::LoadOp::Clear({
                             let (r, g, b, a) = clr.as_rgba();
-                            wgpu::Color { r, g, b, a }
+                            wgpu::Color {
+                                r: r * a,
+                                g: g * a,
+                                b: b * a,
+                                a,
+                            }
                         }),
                     },
                     store: true,
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: depth_stencil.map(DepthStencil::attachment),
         });
 
-        // Set the pipeline.
-        pass.set_pipeline(&self.pipeline);
+        // Pipelines and stencil references are set as they change.
+        let mut current_pipeline: Option<&wgpu::RenderPipeline> = None;
+        let mut current_reference = 0;
+        let mut current_scissor = None;
 
         // Iterate over the pushed buffers.
         for BorrowedPush {
@@ -378,23 +924,51 @@ impl<DaQ: DeviceAndQueue + ?Sized> piet_hardware::GpuContext for GpuContext<DaQ>
                     vertex: vertex_slice,
                     index: index_slice,
                     viewport_size: [width, height],
+                    color_bind_group,
+                    pipeline,
+                    stencil_reference,
+                    scissor,
                     ..
                 },
             vb,
             ib,
-            color_texture,
             mask_texture,
             uniform_bind_group,
         } in &pushes
         {
+            // Set the pipeline. Clips can't be drawn once the stencil buffer is detached.
+            let pipeline = match (pipeline, depth_stencil.is_some()) {
+                (PushedPipeline::Draw(pipelines), attached) => {
+                    pipelines.get(attached, attached && *stencil_reference > 0)
+                }
+                (PushedPipeline::PushClip, true) => &self.clip_pipelines.push,
+                (PushedPipeline::PopClip, true) => &self.clip_pipelines.pop,
+                (_, false) => continue,
+            };
+            if !current_pipeline.map_or(false, |current| ptr::eq(current, pipeline)) {
+                pass.set_pipeline(pipeline);
+                current_pipeline = Some(pipeline);
+            }
+            if *stencil_reference != current_reference {
+                pass.set_stencil_reference(*stencil_reference);
+                current_reference = *stencil_reference;
+            }
+
             // Set a viewport.
             pass.set_viewport(0.0, 0.0, *width, *height, 0.0, 1.0);
 
+            // Set the scissor rectangle, which covers the whole target without one.
+            if *scissor != current_scissor {
+                let [x0, y0, x1, y1] = scissor.unwrap_or([0, 0, *width as u32, *height as u32]);
+                pass.set_scissor_rect(x0, y0, x1 - x0, y1 - y0);
+                current_scissor = *scissor;
+            }
+
             // Set the uniforms.
             pass.set_bind_group(0, uniform_bind_group, &[]);
 
             // Bind textures.
-            pass.set_bind_group(1, color_texture.bind_group(), &[]);
+            pass.set_bind_group(1, color_bind_group, &[]);
             pass.set_bind_group(2, mask_texture.bind_group(), &[]);
 
             // Get the bufer slices to pass to the shader.
@@ -520,67 +1094,449 @@ impl<DaQ: DeviceAndQueue + ?Sized> piet_hardware::GpuContext for GpuContext<DaQ>
         let ib_slice = vertex_buffer.borrow_index_buffer_mut().pop_slice();
 
         // See if we have an existing bind group for this buffer.
+        let (color_matrix, color_offset) = color_matrix_to_column_major(&self.color_matrix.get());
+        let (clip_rect, clip_radii) = match self.clip_shape.get() {
+            Some(shape) => (shape.rect, shape.radii),
+            None => ([0.0; 4], [-1.0; 2]),
+        };
+        let custom_brush = self.custom_brush.get();
         let uniforms = Uniforms {
-            transform: affine_to_column_major(transform),
-            pad: [0xFFFFFFFF; 2],
+            transform: to_column_major(self.projection.get().after(transform)),
+            color_matrix,
+            color_offset,
+            clip_rect,
+            clip_radii,
+            clip_pad: [0xFFFFFFFF; 2],
+            brush_params: match custom_brush {
+                Some(brush) => bytemuck::cast(brush.params),
+                None => [[0.0; 4]; 2],
+            },
+            coverage: [
+                current_texture.borrow().is_coverage() as u32,
+                mask_texture.borrow().is_coverage() as u32,
+            ],
             viewport_size: [viewport_width as f32, viewport_height as f32],
         };
-        let bytes: UniformBytes = bytemuck::cast(uniforms);
-
-        let bind_group = match self.uniform_buffers.borrow_mut().entry(bytes) {
-            Entry::Occupied(o) => o.get().1.clone(),
-            Entry::Vacant(entry) => {
-                // Create a new buffer.
-                let buffer = self.device_and_queue.device().create_buffer_init(
-                    &wgpu::util::BufferInitDescriptor {
-                        label: None,
-                        contents: &bytes,
-                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                    },
-                );
-
-                // Create a new bind group.
-                let bind_group =
-                    self.device_and_queue
-                        .device()
-                        .create_bind_group(&wgpu::BindGroupDescriptor {
-                            label: None,
-                            layout: &self.uniform_bind_layout,
-                            entries: &[wgpu::BindGroupEntry {
-                                binding: 0,
-                                resource: buffer.as_entire_binding(),
-                            }],
-                        });
+        let bind_group = self.uniform_bind_group(uniforms);
 
-                // Insert it into the set.
-                let (_, bind_group) = entry.insert((buffer, Rc::new(bind_group)));
-
-                // Return the bind group.
-                bind_group.clone()
-            }
+        let pipelines = match custom_brush {
+            Some(brush) => self.custom_pipelines.borrow()[brush.shader.into_raw() as usize].clone(),
+            None => self.pipelines.clone(),
+        };
+        let pipelines = match self.composite_mode.get() {
+            CompositeMode::SourceOver => pipelines,
+            mode => pipelines.with_composite_mode(
+                self.device_and_queue.device(),
+                &self.pipeline_layout,
+                self.output_format,
+                self.samples,
+                mode,
+            ),
         };
 
         self.pushed_buffers.borrow_mut().push(PushedBuffer {
             buffers: vertex_buffer.clone(),
             vertex: vb_slice,
             index: ib_slice,
-            color_texture: current_texture.clone(),
+            color_bind_group: match self.sampler_interpolation.get() {
+                Some(interpolation) => current_texture
+                    .borrow_mut()
+                    .bind_group_with(self, interpolation),
+                None => current_texture.borrow().bind_group().clone(),
+            },
             mask_texture: mask_texture.clone(),
             uniform_bind_group: bind_group,
+            pipeline: PushedPipeline::Draw(pipelines),
+            stencil_reference: self.with_depth_stencil(|ds| ds.clips).unwrap_or(0),
+            scissor: self.scissor.get(),
             viewport_size: [viewport_width as f32, viewport_height as f32],
         });
 
         Ok(())
     }
+
+    fn set_color_matrix(&self, matrix: Option<&ColorMatrix>) -> bool {
+        self.color_matrix.set(matrix.copied().unwrap_or_default());
+        true
+    }
+
+    fn set_composite_mode(&self, mode: Option<CompositeMode>) -> bool {
+        self.composite_mode.set(mode.unwrap_or_default());
+        true
+    }
+
+    fn set_clip_shape(&self, shape: Option<&ClipShape>) -> bool {
+        self.clip_shape.set(shape.copied());
+        true
+    }
+
+    fn set_scissor(&self, rect: Option<[u32; 4]>) -> bool {
+        self.scissor.set(rect);
+        true
+    }
+
+    fn set_projection(&self, projection: Option<&Projection>) -> bool {
+        self.projection.set(projection.copied().unwrap_or_default());
+        true
+    }
+
+    fn set_custom_brush(&self, brush: Option<&CustomBrush>) -> bool {
+        let known = brush.map_or(true, |brush| {
+            (brush.shader.into_raw() as usize) < self.custom_pipelines.borrow().len()
+        });
+        if known {
+            self.custom_brush.set(brush.copied());
+        }
+        known
+    }
+
+    fn push_clip_path(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<bool, Self::Error> {
+        let clips = match self.with_depth_stencil(|ds| ds.clips) {
+            Some(clips) if clips < 0xFF => clips,
+            _ => return Ok(false),
+        };
+
+        // Start from an empty stencil buffer, after what was drawn with the last one.
+        if clips == 0 {
+            self.flush_pending();
+            self.with_depth_stencil(|ds| ds.clear(self, wgpu::LoadOp::Load));
+        }
+
+        self.push_clip(vertex_buffer, size, PushedPipeline::PushClip, clips);
+        self.with_depth_stencil(|ds| ds.clips += 1);
+        Ok(true)
+    }
+
+    fn pop_clip(
+        &self,
+        vertex_buffer: &Self::VertexBuffer,
+        size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        let clips = match self.with_depth_stencil(|ds| ds.clips) {
+            Some(clips) if clips > 0 => clips,
+            _ => return Ok(()),
+        };
+
+        self.push_clip(vertex_buffer, size, PushedPipeline::PopClip, clips);
+        self.with_depth_stencil(|ds| ds.clips -= 1);
+        Ok(())
+    }
+
+    fn set_sampler_interpolation(&self, interpolation: Option<InterpolationMode>) -> bool {
+        self.sampler_interpolation.set(interpolation);
+        true
+    }
+
+    fn generate_mipmaps(&self, texture: &Self::Texture) -> bool {
+        texture.borrow_mut().generate_mipmaps(self)
+    }
+
+    fn read_pixels(
+        &self,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        _target_size: (u32, u32),
+        data: &mut [u8],
+    ) -> Result<bool, Self::Error> {
+        // Only render targets can be copied from; views of other textures can't.
+        self.flush_pending();
+        let render_target = self.render_target.borrow();
+        let texture = match &*render_target {
+            Some((texture, _)) => texture.borrow(),
+            None => return Ok(false),
+        };
+
+        let device = self.device_and_queue.device();
+        let row_len = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (row_len + align - 1) / align * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("piet-wgpu readback buffer"),
+            size: bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("piet-wgpu readback encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: texture.texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.device_and_queue.queue().submit(Some(encoder.finish()));
+
+        // Wait for the copy to finish.
+        let slice = buffer.slice(..);
+        let (send, recv) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            send.send(result).ok();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = recv.recv().expect("map callback was not called") {
+            warn!("failed to map readback buffer: {}", err);
+            return Ok(false);
+        }
+
+        let mapped = slice.get_mapped_range();
+        for (row, dest) in mapped
+            .chunks_exact(bytes_per_row as usize)
+            .zip(data.chunks_exact_mut(row_len as usize))
+        {
+            dest.copy_from_slice(&row[..row_len as usize]);
+        }
+        drop(mapped);
+        buffer.unmap();
+
+        if matches!(
+            self.output_format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn clear_rect(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        _target_size: (u32, u32),
+        color: Color,
+    ) -> Result<bool, Self::Error> {
+        // Only render targets can be written to directly; surfaces can only be cleared whole.
+        let render_target = self.render_target.borrow();
+        let texture = match &*render_target {
+            Some((texture, _)) => texture,
+            None => return Ok(false),
+        };
+
+        // The write has to land after everything drawn so far.
+        self.flush_pending();
+
+        // Encode the color the same way the clear color of a render pass is.
+        let (r, g, b, a) = color.as_rgba();
+        let srgb = self.output_format.is_srgb();
+        let encode = |channel: f64| {
+            let channel = if srgb {
+                linear_to_srgb(channel)
+            } else {
+                channel
+            };
+            (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        let (r, g, b, a) = (encode(r * a), encode(g * a), encode(b * a), encode(a));
+        let pixel = match self.output_format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => [b, g, r, a],
+            _ => [r, g, b, a],
+        };
+
+        let data = pixel.repeat(size.0 as usize * size.1 as usize);
+        texture.borrow_mut().write_subtexture(
+            self,
+            origin,
+            size,
+            piet_hardware::piet::ImageFormat::RgbaPremul,
+            &data,
+        );
+        Ok(true)
+    }
+
+    fn show_overlay(&self, texture: &Self::Texture, source: Rect, rect: Rect) -> bool {
+        // Only the surface is composited with the overlays below it.
+        if self.render_target.borrow().is_some() {
+            return false;
+        }
+
+        match &mut *self.overlay_handler.borrow_mut() {
+            Some(handler) => handler(Overlay {
+                texture: texture.borrow().texture(),
+                source,
+                rect,
+            }),
+            None => false,
+        }
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        self.samples
+    }
+
+    fn create_render_target(&self, size: (u32, u32)) -> Result<Option<Self::Texture>, Self::Error> {
+        // Render targets have to match the pipeline, and be read back as RGBA bytes.
+        let readable = matches!(
+            self.output_format,
+            wgpu::TextureFormat::Rgba8Unorm
+                | wgpu::TextureFormat::Rgba8UnormSrgb
+                | wgpu::TextureFormat::Bgra8Unorm
+                | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        if self.samples != 1 || !readable {
+            return Ok(None);
+        }
+
+        let texture = WgpuTexture::create_texture(
+            self,
+            InterpolationMode::Bilinear,
+            piet_hardware::RepeatStrategy::Clamp,
+        );
+        texture
+            .borrow_mut()
+            .allocate_render_target(self, size, self.output_format);
+        Ok(Some(texture))
+    }
+
+    fn set_render_target(&self, target: Option<&Self::Texture>) -> Result<(), Self::Error> {
+        // Draw everything that was meant for the previous target.
+        self.flush_pending();
+
+        let mut render_target = self.render_target.borrow_mut();
+        let previous = match render_target.take() {
+            Some((_, previous)) => previous,
+            None => self.texture_view.take(),
+        };
+
+        match target {
+            Some(texture) => {
+                let view = texture
+                    .borrow()
+                    .texture()
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                *self.texture_view.borrow_mut() = Some(view);
+                *render_target = Some((texture.clone(), previous));
+            }
+            None => *self.texture_view.borrow_mut() = previous,
+        }
+
+        Ok(())
+    }
+
+    fn create_depth_stencil(&self, size: (u32, u32)) -> Result<bool, Self::Error> {
+        // Draw everything that was meant for the previous attachment.
+        self.flush_pending();
+
+        let render_target = self.render_target.borrow();
+        match &*render_target {
+            Some((texture, _)) => {
+                let mut texture = texture.borrow_mut();
+                let slot = texture.depth_stencil_mut();
+                let attached = slot.as_ref().map_or(false, |ds| ds.attached);
+                *slot = Some(DepthStencil::new(self, size, 1, attached));
+            }
+            None => {
+                let mut slot = self.depth_stencil.borrow_mut();
+                let attached = slot.as_ref().map_or(false, |ds| ds.attached);
+                *slot = Some(DepthStencil::new(self, size, self.samples, attached));
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn attach_depth_stencil(&self, attach: bool) -> Result<(), Self::Error> {
+        self.flush_pending();
+
+        let render_target = self.render_target.borrow();
+        match &*render_target {
+            Some((texture, _)) => {
+                if let Some(depth_stencil) = texture.borrow_mut().depth_stencil_mut() {
+                    depth_stencil.attached = attach;
+                }
+            }
+            None => {
+                if let Some(depth_stencil) = &mut *self.depth_stencil.borrow_mut() {
+                    depth_stencil.attached = attach;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn present(&self) -> Result<(), Self::Error> {
+        if let Some(frame) = self.frame.borrow_mut().take() {
+            // The view can't be drawn to once the frame is presented.
+            self.texture_view.borrow_mut().take();
+            frame.present();
+        }
+
+        Ok(())
+    }
+
+    fn set_present_mode(&self, mode: PresentMode) -> bool {
+        self.present_mode.set(Some(mode));
+        true
+    }
+}
+
+/// Encode a linear color channel as sRGB.
+fn linear_to_srgb(channel: f64) -> f64 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
 }
 
-fn affine_to_column_major(affine: &Affine) -> [[f32; 4]; 3] {
-    let [a, b, c, d, e, f] = affine.as_coeffs();
+fn color_matrix_to_column_major(matrix: &ColorMatrix) -> ([[f32; 4]; 4], [f32; 4]) {
+    let rows = matrix.rows();
+    let mut columns = [[0.0; 4]; 4];
+    for (column, slot) in columns.iter_mut().enumerate() {
+        for (row, value) in slot.iter_mut().enumerate() {
+            *value = rows[row][column];
+        }
+    }
+
+    (columns, [rows[0][4], rows[1][4], rows[2][4], rows[3][4]])
+}
 
-    // Column major
+fn to_column_major(m: [f32; 9]) -> [[f32; 4]; 3] {
+    // Column major, with every column padded to four components.
     [
-        [a as f32, b as f32, 0.0, 0.0],
-        [c as f32, d as f32, 0.0, 0.0],
-        [e as f32, f as f32, 1.0, 0.0],
+        [m[0], m[3], m[6], 0.0],
+        [m[1], m[4], m[7], 0.0],
+        [m[2], m[5], m[8], 0.0],
     ]
 }
+
+/// Poll a future once, for futures that `wgpu` resolves immediately on native platforms.
+fn poll_now<F: Future>(future: F) -> Option<F::Output> {
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(NoopWaker));
+    match Box::pin(future)
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+    {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
@@ -31,12 +31,27 @@ use std::borrow;
 use piet_hardware::piet::kurbo::Affine;
 use piet_hardware::piet::{self, Color, Error as Pierror, ImageFormat, InterpolationMode};
 
+#[macro_use]
+mod logging;
+
 mod buffer;
 mod context;
+mod mipmap;
+pub mod prelude;
+mod surface;
+mod target;
 mod texture;
 
 use context::GpuContext;
 
+pub use piet_hardware::{
+    Antialiasing, CompositeMode, CustomShader, HitTester, LayerId, Marker, MarkerPlacement,
+    MemoryUsage, MissingResource, Placeholder, ResourceMemory, SpreadMode, SweepGradient,
+    Transform3d,
+};
+pub use surface::SurfaceTarget;
+pub use target::TextureTarget;
+
 /// A reference to a [`wgpu`] [`Device`], and [`Queue`].
 ///
 /// This is used by the GPU context to create resources. It can take the form of a (Device, Queue)
@@ -63,6 +78,21 @@ impl<A: borrow::Borrow<wgpu::Device>, B: borrow::Borrow<wgpu::Queue>> DeviceAndQ
     }
 }
 
+/// An image that the application is asked to show on an overlay plane or sub-surface.
+///
+/// See [`WgpuContext::on_overlay`] for more information.
+#[non_exhaustive]
+pub struct Overlay<'a> {
+    /// The texture that holds the image.
+    pub texture: &'a wgpu::Texture,
+
+    /// The area of the texture to show, in texels.
+    pub source: piet::kurbo::Rect,
+
+    /// Where to show the image, in pixels of the surface from its top-left corner.
+    pub rect: piet::kurbo::Rect,
+}
+
 /// A wrapper around a [`wgpu`] [`Device`] and [`Queue`] with cached information.
 pub struct WgpuContext<D: DeviceAndQueue + ?Sized> {
     source: piet_hardware::Source<GpuContext<D>>,
@@ -79,12 +109,30 @@ impl<D: DeviceAndQueue + ?Sized> WgpuContext<D> {
     where
         D: Sized,
     {
-        let source = piet_hardware::Source::new(GpuContext::new(
+        Self::with_antialiasing(
             device_and_queue,
             output_format,
-            None,
             samples,
-        ))?;
+            Antialiasing::default(),
+        )
+    }
+
+    /// Create a new [`WgpuContext`] that antialiases the edges of shapes with `antialiasing`.
+    ///
+    /// Multisampling is used if `samples` is more than one. Otherwise, `antialiasing` decides
+    /// whether edges are feathered instead; see [`Antialiasing`].
+    pub fn with_antialiasing(
+        device_and_queue: D,
+        output_format: wgpu::TextureFormat,
+        samples: u32,
+        antialiasing: Antialiasing,
+    ) -> Result<Self, Pierror>
+    where
+        D: Sized,
+    {
+        let source = piet_hardware::SourceBuilder::new()
+            .antialiasing(antialiasing)
+            .build(GpuContext::new(device_and_queue, output_format, samples))?;
         let text = source.text().clone();
 
         Ok(Self {
@@ -98,7 +146,169 @@ impl<D: DeviceAndQueue + ?Sized> WgpuContext<D> {
         self.source.context().device_and_queue()
     }
 
+    /// Compile the code of a custom brush, for use with [`Brush::custom`].
+    ///
+    /// The code is WGSL, and is added to the end of the shader module. It defines
+    /// `fn piet_brush(position: vec2<f32>, params: array<vec4<f32>, 2>) -> vec4<f32>`, which
+    /// returns the premultiplied color at a position in user space; see
+    /// [`piet_hardware::BrushShaderInterface`]. Returns an error if the code doesn't define it
+    /// or fails to compile.
+    pub fn register_brush_shader(&self, source: &str) -> Result<CustomShader, Pierror> {
+        piet_hardware::ShaderInterface::CURRENT
+            .check_brush_shader(source)
+            .map_err(|e| Pierror::BackendError(e.into()))?;
+
+        self.source
+            .context()
+            .register_brush_shader(source)
+            .map(CustomShader::from_raw)
+            .map_err(|e| Pierror::BackendError(e.to_string().into()))
+    }
+
+    /// Set a callback that shows images on an overlay plane or sub-surface of the window.
+    ///
+    /// Images made with [`Image::with_overlay`] are handed to the callback when they are
+    /// drawn onto the surface, for example to import the texture into a Vulkan swapchain of
+    /// a sub-surface below the window. If the callback returns `true`, the area of the
+    /// surface is cleared to transparent so that the overlay shows through, which needs a
+    /// surface with an alpha channel. If it returns `false`, the image is drawn onto the
+    /// surface as usual. The callback replaces the one that was set before.
+    pub fn on_overlay(&mut self, handler: impl FnMut(Overlay<'_>) -> bool + 'static) {
+        self.source.context().set_overlay_handler(Box::new(handler));
+    }
+
+    /// Repack the glyphs in the text atlas to reduce fragmentation.
+    ///
+    /// See [`piet_hardware::Source::compact_atlas`] for more information.
+    pub fn compact_atlas(&mut self) {
+        self.source.compact_atlas()
+    }
+
+    /// Rasterize the glyphs of a text layout into the text atlas, and keep them there.
+    ///
+    /// See [`piet_hardware::Source::pin_text`] for more information.
+    pub fn pin_text(&mut self, layout: &TextLayout, scale: f64) -> Result<(), Pierror> {
+        self.source.pin_text(&layout.0, scale)
+    }
+
+    /// Let the glyphs of a text layout pinned with [`pin_text`](Self::pin_text) be evicted
+    /// again.
+    pub fn unpin_text(&mut self, layout: &TextLayout, scale: f64) {
+        self.source.unpin_text(&layout.0, scale)
+    }
+
+    /// Upload an image that is waiting for the upload budget right away.
+    ///
+    /// See [`piet_hardware::Source::upload_now`] for more information.
+    pub fn upload_now(&mut self, image: &Image<D>) {
+        self.source.upload_now(&image.0)
+    }
+
+    /// Drop the blurred rectangles that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_shadow_cache`] for more information.
+    pub fn clear_shadow_cache(&mut self) {
+        self.source.clear_shadow_cache()
+    }
+
+    /// Drop the color ramps of linear gradients that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_gradient_cache`] for more information.
+    pub fn clear_gradient_cache(&mut self) {
+        self.source.clear_gradient_cache()
+    }
+
+    /// Start a new frame of occlusion queries.
+    ///
+    /// See [`piet_hardware::Source::begin_occlusion_frame`] for more information.
+    pub fn begin_occlusion_frame(&mut self) {
+        self.source.begin_occlusion_frame()
+    }
+
+    /// Drop the tessellations of fills that are kept around for reuse.
+    ///
+    /// See [`piet_hardware::Source::clear_tessellation_cache`] for more information.
+    pub fn clear_tessellation_cache(&mut self) {
+        self.source.clear_tessellation_cache()
+    }
+
+    /// Get the strategy that the edges of shapes are antialiased with.
+    ///
+    /// See [`piet_hardware::Source::antialiasing`] for more information.
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.source.antialiasing()
+    }
+
+    /// Get how much GPU memory the resources of this context take up, by kind of resource.
+    ///
+    /// See [`piet_hardware::Source::memory_usage`] for more information.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.source.memory_usage()
+    }
+
+    /// Start measuring the peaks of [`memory_usage`](Self::memory_usage) again.
+    ///
+    /// See [`piet_hardware::Source::reset_peak_memory_usage`] for more information.
+    pub fn reset_peak_memory_usage(&mut self) {
+        self.source.reset_peak_memory_usage()
+    }
+
+    /// Limit how many bytes of images are uploaded to the GPU every frame.
+    ///
+    /// See [`piet_hardware::Source::set_upload_budget`] for more information.
+    pub fn set_upload_budget(&mut self, budget: Option<usize>) {
+        self.source.set_upload_budget(budget)
+    }
+
+    /// Set what is drawn instead of images that are waiting to be uploaded, and glyphs that
+    /// failed to rasterize.
+    ///
+    /// See [`piet_hardware::Source::set_placeholder`] for more information.
+    pub fn set_placeholder(&mut self, placeholder: impl Into<Placeholder>) {
+        self.source.set_placeholder(placeholder)
+    }
+
+    /// Set a callback that is told every time a resource is drawn as the placeholder.
+    ///
+    /// See [`piet_hardware::Source::on_missing_resource`] for more information.
+    pub fn on_missing_resource(&mut self, callback: impl FnMut(MissingResource<'_>) + 'static) {
+        self.source.on_missing_resource(callback)
+    }
+
+    /// Whether any images are waiting for a later frame to be uploaded.
+    ///
+    /// See [`piet_hardware::Source::has_pending_uploads`] for more information.
+    pub fn has_pending_uploads(&self) -> bool {
+        self.source.has_pending_uploads()
+    }
+
+    /// Free the buffer that pixels are converted in before they are uploaded.
+    ///
+    /// See [`piet_hardware::Source::release_staging_buffer`] for more information.
+    pub fn release_staging_buffer(&mut self) {
+        self.source.release_staging_buffer()
+    }
+
+    /// Set how frames of [`SurfaceTarget`]s are presented, from the next frame on.
+    ///
+    /// See [`piet_hardware::Source::set_present_mode`] for more information.
+    pub fn set_present_mode(&mut self, mode: piet_hardware::PresentMode) -> Result<(), Pierror> {
+        self.source.set_present_mode(mode)
+    }
+
+    /// Resize the target that this context renders to, such as when the window is resized.
+    ///
+    /// See [`piet_hardware::Source::resize`] for more information.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Pierror> {
+        self.source.resize(width, height)
+    }
+
     /// Get the render context.
+    ///
+    /// The render context draws to `view`, which is `width` by `height` pixels. Several views,
+    /// like the swapchains of different windows, can be drawn to one after another, as long as
+    /// they have the output format this context was created with and each render context is
+    /// finished before the next one is created.
     pub fn render_context(
         &mut self,
         view: wgpu::TextureView,
@@ -112,6 +322,95 @@ impl<D: DeviceAndQueue + ?Sized> WgpuContext<D> {
             context: self.source.render_context(width, height),
         }
     }
+
+    /// Get a render context that draws the next frame of a [`SurfaceTarget`].
+    ///
+    /// The surface is reconfigured first if it was resized or has gone out of date. The frame
+    /// is presented when the render context is [`finish`](piet::RenderContext::finish)ed, and
+    /// dropped without being shown otherwise.
+    pub fn surface_context(
+        &mut self,
+        surface: &mut SurfaceTarget,
+    ) -> Result<RenderContext<'_, D>, Pierror> {
+        if let Some(mode) = self.source.context().present_mode() {
+            surface.use_present_mode(mode);
+        }
+        let frame = surface.acquire(self.device_and_queue().device())?;
+        let (width, height) = surface.size();
+        self.source.context().set_frame(frame);
+
+        Ok(RenderContext {
+            text: &mut self.text,
+            context: self.source.render_context(width, height),
+        })
+    }
+
+    /// Get a render context that draws into a [`TextureTarget`].
+    ///
+    /// This context must have been created with [`TextureTarget::FORMAT`] as its output format.
+    /// The texture isn't cleared, so call [`clear`](piet::RenderContext::clear) first to
+    /// redraw it from scratch.
+    pub fn texture_context(&mut self, target: &TextureTarget) -> RenderContext<'_, D> {
+        let (width, height) = target.size();
+        self.render_context(target.view(), width, height)
+    }
+
+    /// Get a render context that draws one tile of a larger frame.
+    ///
+    /// `view` must be `tile.width` by `tile.height` pixels. See
+    /// [`piet_hardware::Source::tile_context`] for more information.
+    pub fn tile_context(
+        &mut self,
+        view: wgpu::TextureView,
+        tile: piet_hardware::Tile,
+    ) -> RenderContext<'_, D> {
+        self.source.context().set_texture_view(view);
+
+        RenderContext {
+            text: &mut self.text,
+            context: self.source.tile_context(tile),
+        }
+    }
+
+    /// Render a scene into an offscreen texture and read it back as a thumbnail.
+    ///
+    /// This needs the context to have been created with one sample per pixel and an 8-bit
+    /// RGBA or BGRA output format. See [`piet_hardware::Source::render_thumbnail`] for more
+    /// information.
+    pub fn render_thumbnail(
+        &mut self,
+        scene_size: piet::kurbo::Size,
+        max_size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, D>) -> Result<(), Pierror>,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        let text = &mut self.text;
+        self.source
+            .render_thumbnail(scene_size, max_size, |context| {
+                draw(RenderContext { text, context })
+            })
+    }
+
+    /// Render a scene into an offscreen texture and keep only its coverage as an image.
+    ///
+    /// This has the same requirements as [`render_thumbnail`](Self::render_thumbnail). See
+    /// [`piet_hardware::Source::render_coverage`] for more information.
+    pub fn render_coverage(
+        &mut self,
+        size: (u32, u32),
+        draw: impl FnOnce(RenderContext<'_, D>) -> Result<(), Pierror>,
+    ) -> Result<Image<D>, Pierror> {
+        let text = &mut self.text;
+        self.source
+            .render_coverage(size, |context| draw(RenderContext { text, context }))
+            .map(Image)
+    }
+
+    /// Draw a series of test scenes and check the pixels that are read back.
+    ///
+    /// See [`piet_hardware::conformance::run`] for more information.
+    pub fn check_conformance(&mut self) -> Result<piet_hardware::conformance::Report, Pierror> {
+        piet_hardware::conformance::run(&mut self.source)
+    }
 }
 
 /// The whole point.
@@ -120,6 +419,337 @@ pub struct RenderContext<'a, D: DeviceAndQueue + ?Sized> {
     text: &'a mut Text,
 }
 
+impl<D: DeviceAndQueue + ?Sized> RenderContext<'_, D> {
+    /// Get the errors that have occurred since the last call to `status` or `take_errors`.
+    ///
+    /// See [`piet_hardware::RenderContext::errors`] for more information.
+    pub fn errors(&self) -> impl Iterator<Item = &piet_hardware::DrawError> + '_ {
+        self.context.errors()
+    }
+
+    /// Take the errors that have occurred since the last call to `status` or `take_errors`.
+    pub fn take_errors(&mut self) -> Vec<piet_hardware::DrawError> {
+        self.context.take_errors()
+    }
+
+    /// Get the opacity that everything drawn is multiplied by.
+    pub fn global_alpha(&self) -> f64 {
+        self.context.global_alpha()
+    }
+
+    /// Set the opacity that everything drawn is multiplied by.
+    ///
+    /// See [`piet_hardware::RenderContext::set_global_alpha`] for more information.
+    pub fn set_global_alpha(&mut self, alpha: f64) {
+        self.context.set_global_alpha(alpha)
+    }
+
+    /// Get the tolerance that curves are flattened with, in pixels of the target.
+    pub fn tolerance(&self) -> f64 {
+        self.context.tolerance()
+    }
+
+    /// Set the tolerance that curves are flattened with, in pixels of the target.
+    ///
+    /// See [`piet_hardware::RenderContext::set_tolerance`] for more information.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.context.set_tolerance(tolerance)
+    }
+
+    /// Whether stroke widths are measured in device pixels instead of user space.
+    pub fn non_scaling_stroke(&self) -> bool {
+        self.context.non_scaling_stroke()
+    }
+
+    /// Set whether stroke widths are measured in device pixels instead of user space.
+    ///
+    /// See [`piet_hardware::RenderContext::set_non_scaling_stroke`] for more information.
+    pub fn set_non_scaling_stroke(&mut self, enabled: bool) {
+        self.context.set_non_scaling_stroke(enabled)
+    }
+
+    /// Read back an area of the target into a CPU-side image with the given format.
+    ///
+    /// See [`piet_hardware::RenderContext::capture_image_buf`] for more information.
+    pub fn capture_image_buf(
+        &mut self,
+        src_rect: impl Into<piet::kurbo::Rect>,
+        format: ImageFormat,
+    ) -> Result<piet::ImageBuf, Pierror> {
+        self.context.capture_image_buf(src_rect, format)
+    }
+
+    /// Start recording the shapes that are drawn into a [`Scene`] instead of drawing them.
+    ///
+    /// See [`piet_hardware::RenderContext::begin_scene`] for more information.
+    pub fn begin_scene(&mut self) -> Result<(), Pierror> {
+        self.context.begin_scene()
+    }
+
+    /// Start recording a [`Scene`] that stays accurate when it is drawn scaled up by `detail`.
+    ///
+    /// See [`piet_hardware::RenderContext::begin_scene_with_detail`] for more information.
+    pub fn begin_scene_with_detail(&mut self, detail: f64) -> Result<(), Pierror> {
+        self.context.begin_scene_with_detail(detail)
+    }
+
+    /// Finish recording a [`Scene`].
+    ///
+    /// See [`piet_hardware::RenderContext::end_scene`] for more information.
+    pub fn end_scene(&mut self) -> Result<Scene<D>, Pierror> {
+        self.context.end_scene().map(Scene)
+    }
+
+    /// Draw a [`Scene`] with the current transform, clip and global alpha.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_scene`] for more information.
+    pub fn draw_scene(&mut self, scene: &Scene<D>) -> Result<(), Pierror> {
+        self.context.draw_scene(&scene.0)
+    }
+
+    /// Draw the level of a [`SceneLevels`] that fits the current transform best.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_scene_levels`] for more information.
+    pub fn draw_scene_levels(&mut self, levels: &SceneLevels<D>) -> Result<(), Pierror> {
+        self.context.draw_scene_levels(&levels.0)
+    }
+
+    /// Draw every layer of a [`LayerTree`] with the current transform, clip and global alpha.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_layers`] for more information.
+    pub fn draw_layers(&mut self, tree: &LayerTree<D>) -> Result<(), Pierror> {
+        self.context.draw_layers(&tree.0)
+    }
+
+    /// Save the state, and draw into an offscreen layer that is composited back with the
+    /// opacity `alpha` when it is restored.
+    ///
+    /// See [`piet_hardware::RenderContext::save_layer`] for more information.
+    pub fn save_layer(&mut self, bounds: piet::kurbo::Rect, alpha: f64) -> Result<(), Pierror> {
+        self.context.save_layer(bounds, alpha)
+    }
+
+    /// Get the Porter-Duff operator that drawing is combined with the target with.
+    pub fn composite_mode(&self) -> CompositeMode {
+        self.context.composite_mode()
+    }
+
+    /// Set the Porter-Duff operator that drawing is combined with the target with.
+    ///
+    /// See [`piet_hardware::RenderContext::set_composite_mode`] for more information.
+    pub fn set_composite_mode(&mut self, mode: CompositeMode) -> Result<(), Pierror> {
+        self.context.set_composite_mode(mode)
+    }
+
+    /// Draw with the Porter-Duff operator `mode` inside of `f`, and go back to the previous
+    /// one afterwards.
+    ///
+    /// See [`piet_hardware::RenderContext::set_composite_mode`] for more information.
+    pub fn with_composite_mode<R>(
+        &mut self,
+        mode: CompositeMode,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> Result<R, Pierror> {
+        let previous = self.composite_mode();
+        self.set_composite_mode(mode)?;
+
+        let result = f(self);
+        self.set_composite_mode(previous)?;
+        Ok(result)
+    }
+
+    /// Submit the drawing so far to the GPU without finishing the frame.
+    ///
+    /// See [`piet_hardware::RenderContext::flush_pending`] for more information.
+    pub fn flush_pending(&mut self) -> Result<(), Pierror> {
+        self.context.flush_pending()
+    }
+
+    /// Submit the drawing so far, so that the application can issue its own GPU commands.
+    ///
+    /// See [`piet_hardware::RenderContext::barrier`] for more information.
+    pub fn barrier(&mut self) -> Result<(), Pierror> {
+        self.context.barrier()
+    }
+
+    /// Create a brush from a gradient that continues past its ends with `spread`.
+    ///
+    /// See [`piet_hardware::RenderContext::gradient_with_spread`] for more information.
+    pub fn gradient_with_spread(
+        &mut self,
+        gradient: impl Into<piet::FixedGradient>,
+        spread: SpreadMode,
+    ) -> Result<Brush<D>, Pierror> {
+        self.context
+            .gradient_with_spread(gradient, spread)
+            .map(Brush)
+    }
+
+    /// Create a brush that fills shapes with a sweep gradient, like a color wheel.
+    ///
+    /// See [`piet_hardware::RenderContext::sweep_gradient`] for more information.
+    pub fn sweep_gradient(&mut self, gradient: &SweepGradient) -> Result<Brush<D>, Pierror> {
+        self.context.sweep_gradient(gradient).map(Brush)
+    }
+
+    /// Draw a blurred rectangle with rounded corners, like the shadow of a card.
+    ///
+    /// See [`piet_hardware::RenderContext::blurred_rounded_rect`] for more information.
+    pub fn blurred_rounded_rect(
+        &mut self,
+        rect: impl Into<piet::kurbo::Rect>,
+        corner_radius: f64,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let rect = rect.into();
+        let brush = brush.make_brush(self, || rect);
+        self.context
+            .blurred_rounded_rect(rect, corner_radius, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Draw a text layout with a soft shadow below it, like a subtitle.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_text_with_shadow`] for more information.
+    pub fn draw_text_with_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<piet::kurbo::Point>,
+        offset: impl Into<piet::kurbo::Vec2>,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let offset = offset.into();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2() + offset;
+        let brush = brush.make_brush(self, || bounds);
+        self.context
+            .draw_text_with_shadow(&layout.0, pos, offset, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Draw only the soft shadow of a text layout at `pos`.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_text_shadow`] for more information.
+    pub fn draw_text_shadow(
+        &mut self,
+        layout: &TextLayout,
+        pos: impl Into<piet::kurbo::Point>,
+        blur_radius: f64,
+        brush: &impl piet::IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let bounds = piet::TextLayout::image_bounds(layout) + pos.to_vec2();
+        let brush = brush.make_brush(self, || bounds);
+        self.context
+            .draw_text_shadow(&layout.0, pos, blur_radius, &brush.as_ref().0)
+    }
+
+    /// Tessellate a filled shape into a [`Marker`], measured in device pixels.
+    ///
+    /// See [`piet_hardware::RenderContext::fill_marker`] for more information.
+    pub fn fill_marker(&mut self, shape: impl piet::kurbo::Shape) -> Result<Marker, Pierror> {
+        self.context.fill_marker(shape)
+    }
+
+    /// Tessellate the stroke of a shape into a [`Marker`], measured in device pixels.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_marker`] for more information.
+    pub fn stroke_marker(
+        &mut self,
+        shape: impl piet::kurbo::Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<Marker, Pierror> {
+        self.context.stroke_marker(shape, width, style)
+    }
+
+    /// Tessellate the fill of a shape into a [`CachedPath`] that is kept on the GPU.
+    ///
+    /// See [`piet_hardware::RenderContext::make_cached_path`] for more information.
+    pub fn make_cached_path(
+        &mut self,
+        shape: impl piet::kurbo::Shape,
+    ) -> Result<CachedPath<D>, Pierror> {
+        self.context.make_cached_path(shape).map(CachedPath)
+    }
+
+    /// Fill a [`CachedPath`] with the non-zero fill rule, without tessellating it again.
+    ///
+    /// See [`piet_hardware::RenderContext::fill_cached`] for more information.
+    pub fn fill_cached(
+        &mut self,
+        path: &CachedPath<D>,
+        brush: &impl piet::IntoBrush<Self>,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        self.context.fill_cached(&path.0, &brush.as_ref().0)
+    }
+
+    /// Stroke a [`CachedPath`], only tessellating the stroke if it was last drawn with another
+    /// width or style.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_cached`] for more information.
+    pub fn stroke_cached(
+        &mut self,
+        path: &CachedPath<D>,
+        brush: &impl piet::IntoBrush<Self>,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || path.bounds());
+        self.context
+            .stroke_cached(&path.0, &brush.as_ref().0, width, style)
+    }
+
+    /// Make an occlusion query for an area, and get whether it was hidden in the last frame.
+    ///
+    /// See [`piet_hardware::RenderContext::occlusion_query`] for more information.
+    pub fn occlusion_query(&mut self, id: u64, bounds: piet::kurbo::Rect) -> bool {
+        self.context.occlusion_query(id, bounds)
+    }
+
+    /// Create a [`HitTester`] that flattens shapes like this context does.
+    ///
+    /// See [`piet_hardware::RenderContext::hit_tester`] for more information.
+    pub fn hit_tester(&self) -> HitTester {
+        self.context.hit_tester()
+    }
+
+    /// Decode an image and upload it.
+    ///
+    /// See [`piet_hardware::RenderContext::make_image_from_encoded`] for more information.
+    #[cfg(feature = "loader")]
+    pub fn make_image_from_encoded(&mut self, bytes: &[u8]) -> Result<Image<D>, Pierror> {
+        self.context.make_image_from_encoded(bytes).map(Image)
+    }
+
+    /// Get the outline of a stroke as a path, in user space.
+    ///
+    /// See [`piet_hardware::RenderContext::stroke_outline`] for more information.
+    pub fn stroke_outline(
+        &mut self,
+        shape: impl piet::kurbo::Shape,
+        width: f64,
+        style: &piet::StrokeStyle,
+    ) -> Result<piet::kurbo::BezPath, Pierror> {
+        self.context.stroke_outline(shape, width, style)
+    }
+
+    /// Stamp a [`Marker`] at every placement with one draw call.
+    ///
+    /// See [`piet_hardware::RenderContext::draw_markers`] for more information.
+    pub fn draw_markers<P: Into<MarkerPlacement>>(
+        &mut self,
+        marker: &Marker,
+        brush: &impl piet::IntoBrush<Self>,
+        placements: impl IntoIterator<Item = P>,
+    ) -> Result<(), Pierror> {
+        let brush = brush.make_brush(self, || marker.bounds());
+        self.context
+            .draw_markers(marker, &brush.as_ref().0, placements)
+    }
+}
+
 impl<D: DeviceAndQueue + ?Sized> piet::RenderContext for RenderContext<'_, D> {
     type Brush = Brush<D>;
     type Image = Image<D>;
@@ -267,6 +897,30 @@ impl<D: DeviceAndQueue + ?Sized> piet::RenderContext for RenderContext<'_, D> {
 /// The brush type.
 pub struct Brush<D: DeviceAndQueue + ?Sized>(piet_hardware::Brush<GpuContext<D>>);
 
+impl<D: DeviceAndQueue + ?Sized> Brush<D> {
+    /// Get a copy of this brush with its opacity multiplied by `alpha`.
+    pub fn with_alpha(&self, alpha: f64) -> Self {
+        Self(self.0.with_alpha(alpha))
+    }
+
+    /// Get a copy of this brush with its color multiplied by `color`.
+    pub fn with_tint(&self, color: piet::Color) -> Self {
+        Self(self.0.with_tint(color))
+    }
+
+    /// Create a brush that fills shapes with an image, stretched over `bounds`.
+    pub fn image(image: &Image<D>, bounds: piet::kurbo::Rect) -> Self {
+        Self(piet_hardware::Brush::image(&image.0, bounds))
+    }
+
+    /// Create a brush that fills shapes with the color computed by a custom shader.
+    ///
+    /// The shader is created by [`WgpuContext::register_brush_shader`].
+    pub fn custom(shader: CustomShader, params: [f32; 8]) -> Self {
+        Self(piet_hardware::Brush::custom(shader, params))
+    }
+}
+
 impl<D: DeviceAndQueue + ?Sized> Clone for Brush<D> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
@@ -283,6 +937,195 @@ impl<D: DeviceAndQueue + ?Sized> piet::IntoBrush<RenderContext<'_, D>> for Brush
     }
 }
 
+/// A path whose triangles are tessellated once and kept on the GPU.
+///
+/// See [`piet_hardware::CachedPath`] for more information.
+pub struct CachedPath<D: DeviceAndQueue + ?Sized>(piet_hardware::CachedPath<GpuContext<D>>);
+
+impl<D: DeviceAndQueue + ?Sized> CachedPath<D> {
+    /// Get the path, in user space.
+    pub fn path(&self) -> &piet::kurbo::BezPath {
+        self.0.path()
+    }
+
+    /// Get the bounding box of the path, in user space.
+    pub fn bounds(&self) -> piet::kurbo::Rect {
+        self.0.bounds()
+    }
+}
+
+/// Geometry that is kept on the GPU and drawn again with different transforms.
+///
+/// See [`piet_hardware::Scene`] for more information.
+pub struct Scene<D: DeviceAndQueue + ?Sized>(piet_hardware::Scene<GpuContext<D>>);
+
+impl<D: DeviceAndQueue + ?Sized> Scene<D> {
+    /// Whether nothing was drawn into this scene.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The scale that this scene was recorded to be drawn at.
+    pub fn detail(&self) -> f64 {
+        self.0.detail()
+    }
+}
+
+/// Recordings of the same geometry at several levels of detail.
+///
+/// See [`piet_hardware::SceneLevels`] for more information.
+pub struct SceneLevels<D: DeviceAndQueue + ?Sized>(piet_hardware::SceneLevels<GpuContext<D>>);
+
+impl<D: DeviceAndQueue + ?Sized> Default for SceneLevels<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: DeviceAndQueue + ?Sized> SceneLevels<D> {
+    /// Create a new set of levels without any levels.
+    pub fn new() -> Self {
+        Self(piet_hardware::SceneLevels::new())
+    }
+
+    /// Get the number of levels.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no levels.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the details of the levels, from the least to the most detailed.
+    pub fn details(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.details()
+    }
+
+    /// Get the level of detail that geometry drawn with `transform` should be recorded at.
+    pub fn detail_for(transform: piet::kurbo::Affine) -> f64 {
+        piet_hardware::SceneLevels::<GpuContext<D>>::detail_for(transform)
+    }
+
+    /// Get the level of detail to record for drawing with `transform`, if there is no level
+    /// for it yet.
+    pub fn missing_detail(&self, transform: piet::kurbo::Affine) -> Option<f64> {
+        self.0.missing_detail(transform)
+    }
+
+    /// Add a level, returning the level with the same detail that it replaces.
+    pub fn insert(&mut self, scene: Scene<D>) -> Option<Scene<D>> {
+        self.0.insert(scene.0).map(Scene)
+    }
+
+    /// Remove the level with the given detail.
+    pub fn remove(&mut self, detail: f64) -> Option<Scene<D>> {
+        self.0.remove(detail).map(Scene)
+    }
+
+    /// Remove every level.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// Remove the levels that are more than `distance` powers of two away from the level for
+    /// drawing with `transform`.
+    pub fn prune(&mut self, transform: piet::kurbo::Affine, distance: u32) {
+        self.0.prune(transform, distance)
+    }
+}
+
+/// A tree of layers that are only recorded again when they change.
+///
+/// See [`piet_hardware::LayerTree`] for more information.
+pub struct LayerTree<D: DeviceAndQueue + ?Sized>(piet_hardware::LayerTree<GpuContext<D>>);
+
+impl<D: DeviceAndQueue + ?Sized> Default for LayerTree<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: DeviceAndQueue + ?Sized> LayerTree<D> {
+    /// Create a new, empty layer tree.
+    pub fn new() -> Self {
+        Self(piet_hardware::LayerTree::new())
+    }
+
+    /// Get the number of layers in the tree.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no layers in the tree.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add an empty, invalid layer on top of the children of `parent`, or on top of the
+    /// tree if `parent` is `None`.
+    pub fn add_layer(&mut self, parent: Option<LayerId>) -> Result<LayerId, Pierror> {
+        self.0.add_layer(parent)
+    }
+
+    /// Remove a layer and all of its children.
+    pub fn remove_layer(&mut self, id: LayerId) -> Result<(), Pierror> {
+        self.0.remove_layer(id)
+    }
+
+    /// Whether the layer with this ID is in the tree.
+    pub fn contains(&self, id: LayerId) -> bool {
+        self.0.contains(id)
+    }
+
+    /// Get the transform from a layer's coordinates to its parent's.
+    pub fn transform(&self, id: LayerId) -> Result<piet::kurbo::Affine, Pierror> {
+        self.0.transform(id)
+    }
+
+    /// Set the transform from a layer's coordinates to its parent's.
+    pub fn set_transform(
+        &mut self,
+        id: LayerId,
+        transform: piet::kurbo::Affine,
+    ) -> Result<(), Pierror> {
+        self.0.set_transform(id, transform)
+    }
+
+    /// Get the transform in three dimensions that a layer is composited with.
+    pub fn transform_3d(&self, id: LayerId) -> Result<Transform3d, Pierror> {
+        self.0.transform_3d(id)
+    }
+
+    /// Set the transform in three dimensions that a layer is composited with.
+    ///
+    /// See [`piet_hardware::LayerTree::set_transform_3d`] for more information.
+    pub fn set_transform_3d(&mut self, id: LayerId, transform: Transform3d) -> Result<(), Pierror> {
+        self.0.set_transform_3d(id, transform)
+    }
+
+    /// Mark the contents of a layer as changed, so it has to be recorded again.
+    pub fn invalidate(&mut self, id: LayerId) -> Result<(), Pierror> {
+        self.0.invalidate(id)
+    }
+
+    /// Whether the contents of a layer have to be recorded again.
+    pub fn is_invalid(&self, id: LayerId) -> Result<bool, Pierror> {
+        self.0.is_invalid(id)
+    }
+
+    /// Iterate over the layers that have to be recorded again.
+    pub fn invalid_layers(&self) -> impl Iterator<Item = LayerId> + '_ {
+        self.0.invalid_layers()
+    }
+
+    /// Set the recorded contents of a layer, which makes it valid again.
+    pub fn set_scene(&mut self, id: LayerId, scene: Scene<D>) -> Result<Option<Scene<D>>, Pierror> {
+        self.0.set_scene(id, scene.0).map(|old| old.map(Scene))
+    }
+}
+
 /// The image type.
 pub struct Image<D: DeviceAndQueue + ?Sized>(piet_hardware::Image<GpuContext<D>>);
 
@@ -292,6 +1135,32 @@ impl<D: DeviceAndQueue + ?Sized> Clone for Image<D> {
     }
 }
 
+impl<D: DeviceAndQueue + ?Sized> Image<D> {
+    /// Create a view of the `rect` portion of this image that shares its texture.
+    pub fn sub_image(&self, rect: piet::kurbo::Rect) -> Self {
+        Self(self.0.sub_image(rect))
+    }
+
+    /// Get a copy of this image that is sampled with `interpolation` when it is used as a
+    /// brush.
+    pub fn with_interpolation(&self, interpolation: InterpolationMode) -> Self {
+        Self(self.0.with_interpolation(interpolation))
+    }
+
+    /// Whether drawing this image tries to show it on an overlay of the window.
+    pub fn overlay(&self) -> bool {
+        self.0.overlay()
+    }
+
+    /// Get a copy of this image that is shown on an overlay of the window when it is drawn,
+    /// if an [overlay callback](WgpuContext::on_overlay) accepts it.
+    ///
+    /// See [`piet_hardware::Image::with_overlay`] for more information.
+    pub fn with_overlay(&self, overlay: bool) -> Self {
+        Self(self.0.with_overlay(overlay))
+    }
+}
+
 impl<D: DeviceAndQueue + ?Sized> piet::Image for Image<D> {
     fn size(&self) -> piet::kurbo::Size {
         self.0.size()
@@ -302,6 +1171,20 @@ impl<D: DeviceAndQueue + ?Sized> piet::Image for Image<D> {
 #[derive(Clone)]
 pub struct TextLayout(piet_hardware::TextLayout);
 
+impl TextLayout {
+    /// The index in the original text where it was truncated, if it was truncated.
+    pub fn truncated_at(&self) -> Option<usize> {
+        self.0.truncated_at()
+    }
+
+    /// Get the underlying [`Buffer`] that holds the laid out text.
+    ///
+    /// [`Buffer`]: piet_hardware::cosmic_text::Buffer
+    pub fn buffer(&self) -> &piet_hardware::cosmic_text::Buffer {
+        self.0.buffer()
+    }
+}
+
 impl piet::TextLayout for TextLayout {
     fn size(&self) -> piet::kurbo::Size {
         self.0.size()
@@ -343,6 +1226,23 @@ impl piet::TextLayout for TextLayout {
 /// The text layout builder type.
 pub struct TextLayoutBuilder(piet_hardware::TextLayoutBuilder);
 
+impl TextLayoutBuilder {
+    /// Place tab stops at every multiple of `width`, measured from the start of the line.
+    pub fn tab_width(self, width: f64) -> Self {
+        Self(self.0.tab_width(width))
+    }
+
+    /// Limit the layout to the given number of lines.
+    pub fn max_lines(self, max_lines: usize) -> Self {
+        Self(self.0.max_lines(max_lines))
+    }
+
+    /// Set how the text is truncated when it has too many lines.
+    pub fn truncation(self, truncation: piet_hardware::Truncation) -> Self {
+        Self(self.0.truncation(truncation))
+    }
+}
+
 impl piet::TextLayoutBuilder for TextLayoutBuilder {
     type Out = TextLayout;
 
@@ -375,6 +1275,18 @@ impl piet::TextLayoutBuilder for TextLayoutBuilder {
 #[derive(Clone)]
 pub struct Text(piet_hardware::Text);
 
+impl Text {
+    /// Run a function with read access to the [`FontSystem`] used for text layout.
+    ///
+    /// [`FontSystem`]: piet_hardware::cosmic_text::FontSystem
+    pub fn with_font_system<R>(
+        &self,
+        f: impl FnOnce(&piet_hardware::cosmic_text::FontSystem) -> R,
+    ) -> R {
+        self.0.with_font_system(f)
+    }
+}
+
 impl piet::Text for Text {
     type TextLayoutBuilder = TextLayoutBuilder;
     type TextLayout = TextLayout;
@@ -23,16 +23,30 @@ struct Uniforms {
     // Viewport size.
     viewport_size: vec2<f32>,
 
-    // 64-byte padding.
-    padding: vec2<u32>,
+    // Whether the color and mask textures hold a single channel of coverage.
+    coverage: vec2<u32>,
 
     // 3x3 matrix for transforming vertices.
     transform: mat3x3<f32>,
+
+    // Color matrix for texture colors, with its offset column split out.
+    color_matrix: mat4x4<f32>,
+    color_offset: vec4<f32>,
+
+    // The rectangle that is clipped to and the radii of its corners, in pixels. A negative
+    // radius turns the clip off.
+    clip_rect: vec4<f32>,
+    clip_radii: vec2<f32>,
+
+    // The parameters of the custom brush, if there is one.
+    brush_params: array<vec4<f32>, 2>,
 };
 
 struct VertexShaderOutput {
     @location(0) tex_coords: vec2<f32>,
-    @location(1) mask_coords: vec2<f32>,
+    // The position in pixels multiplied by w, followed by w, so that dividing by the
+    // interpolated w interpolates it linearly on the screen, even for draws with perspective.
+    @location(1) pixel_coords: vec3<f32>,
     @location(2) color: vec4<f32>,
     @builtin(position) position: vec4<f32>,
 }
@@ -52,12 +66,14 @@ fn unpack_color(color: u32) -> vec4<f32> {
     ) / 255.0;
 }
 
-fn unpack_position(posn: vec2<f32>) -> vec4<f32> {
+// Map a position in pixels, multiplied by w, to clip space. The perspective divide is left to
+// the GPU so that it clips points behind the viewer.
+fn unpack_position(posn: vec3<f32>) -> vec4<f32> {
     return vec4<f32>(
-        (2.0 * posn.x / uniforms.viewport_size.x) - 1.0,
-        1.0 - (2.0 * posn.y / uniforms.viewport_size.y),
+        (2.0 * posn.x / uniforms.viewport_size.x) - posn.z,
+        posn.z - (2.0 * posn.y / uniforms.viewport_size.y),
         0.0,
-        1.0,
+        posn.z,
     );
 }
 
@@ -67,31 +83,86 @@ struct InVertex {
     @location(2) color: u32,
 }
 
+// Apply the color matrix to a premultiplied color.
+fn apply_color_matrix(color: vec4<f32>) -> vec4<f32> {
+    var straight = vec4<f32>(0.0);
+    if (color.a > 0.0) {
+        straight = vec4<f32>(color.rgb / color.a, color.a);
+    }
+    let result = clamp(
+        uniforms.color_matrix * straight + uniforms.color_offset,
+        vec4<f32>(0.0),
+        vec4<f32>(1.0),
+    );
+    return vec4<f32>(result.rgb * result.a, result.a);
+}
+
+// Get the coverage of the clip at a pixel's center, like `ClipShape::coverage`.
+fn clip_coverage(pixel: vec2<f32>) -> f32 {
+    let radii = uniforms.clip_radii;
+    if (radii.x < 0.0) {
+        return 1.0;
+    }
+
+    // Fold the point into the bottom right quadrant, measured from the corner's center.
+    let rect = uniforms.clip_rect;
+    let center = (rect.xy + rect.zw) / 2.0;
+    let half_size = (rect.zw - rect.xy) / 2.0;
+    let q = abs(pixel - center) - (half_size - radii);
+
+    var dist: f32;
+    if (q.x > 0.0 && q.y > 0.0) {
+        if (radii.x > 0.0 && radii.y > 0.0) {
+            let f = length(q / radii);
+            let gradient = length(q / radii / radii);
+            dist = f * (f - 1.0) / gradient;
+        } else {
+            dist = length(q);
+        }
+    } else {
+        dist = max(q.x - radii.x, q.y - radii.y);
+    }
+
+    return clamp(0.5 - dist, 0.0, 1.0);
+}
+
 @vertex
 fn vertex_main(vert: InVertex) -> VertexShaderOutput {
     var out: VertexShaderOutput;
 
-    // Transform the vertex position.
-    var pos: vec3<f32> = uniforms.transform * vec3<f32>(vert.position, 1.0);
-    pos = pos / pos.z;
+    // Transform the vertex position. The third component is w, which is only different from
+    // one for draws with perspective.
+    let pos: vec3<f32> = uniforms.transform * vec3<f32>(vert.position, 1.0);
 
-    out.position = unpack_position(pos.xy);
+    out.position = unpack_position(pos);
     out.tex_coords = vert.tex_coords;
-    out.mask_coords = vec2<f32>(
-        vert.tex_coords.x / uniforms.viewport_size.x,
-        vert.tex_coords.y / uniforms.viewport_size.y,
-    );
+    out.pixel_coords = pos;
     out.color = unpack_color(vert.color);
 
     return out;
 }
 
+// Multiply the premultiplied color of the texture by the vertex color and the coverage.
+fn finish_color(in: VertexShaderOutput, tex_color: vec4<f32>) -> vec4<f32> {
+    // Vertex colors use straight alpha, while textures are premultiplied.
+    let main_color = vec4<f32>(in.color.rgb * in.color.a, in.color.a) * tex_color;
+
+    // The mask stores coverage in its alpha channel, or in its only channel.
+    let pixel = in.pixel_coords.xy / in.pixel_coords.z;
+    let mask = textureSample(maskColor, maskSampler, pixel / uniforms.viewport_size);
+    let coverage = select(mask.a, mask.r, uniforms.coverage.y != 0u) * clip_coverage(pixel);
+    return main_color * coverage;
+}
+
+// Sample the color texture, reading coverage as premultiplied white.
+fn sample_color(tex_coords: vec2<f32>) -> vec4<f32> {
+    let color = textureSample(texColor, texSampler, tex_coords);
+    return select(color, vec4<f32>(color.r), uniforms.coverage.x != 0u);
+}
+
 @fragment
 fn fragment_main(in: VertexShaderOutput) -> @location(0) vec4<f32> {
-    let tex_color = textureSample(texColor, texSampler, in.tex_coords);
-    let mask_color = textureSample(maskColor, maskSampler, in.mask_coords);
-
-    let main_color = in.color * tex_color;
-    return main_color * mask_color;
+    let tex_color = apply_color_matrix(sample_color(in.tex_coords));
+    return finish_color(in, tex_color);
 }
 
@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Rendering into window surfaces.
+
+use piet_hardware::piet::Error as Pierror;
+use piet_hardware::PresentMode;
+
+/// A window surface that piet scenes are presented to.
+///
+/// The surface is configured when it is first drawn to, and again after it has been resized
+/// or the swapchain has become out of date. Draw to it with
+/// [`WgpuContext::surface_context`](crate::WgpuContext::surface_context); the frame is presented
+/// when the render context is [`finish`](piet_hardware::piet::RenderContext::finish)ed.
+#[derive(Debug)]
+pub struct SurfaceTarget {
+    /// The surface being rendered to.
+    surface: wgpu::Surface,
+
+    /// The configuration of the surface.
+    config: wgpu::SurfaceConfiguration,
+
+    /// Whether the surface needs to be configured before the next frame.
+    dirty: bool,
+
+    /// The present modes that the surface supports, if they are known.
+    present_modes: Vec<wgpu::PresentMode>,
+}
+
+impl SurfaceTarget {
+    /// Create a new surface target with the given configuration.
+    ///
+    /// The [`WgpuContext`](crate::WgpuContext) used to draw to it has to be created with the
+    /// format of `config` as its output format, and one sample per pixel.
+    pub fn new(surface: wgpu::Surface, config: wgpu::SurfaceConfiguration) -> Self {
+        Self {
+            surface,
+            config,
+            dirty: true,
+            present_modes: Vec::new(),
+        }
+    }
+
+    /// Tell the surface which present modes it supports.
+    ///
+    /// These come from [`wgpu::Surface::get_capabilities`]. Unless mailbox presentation is
+    /// known to be supported, [`PresentMode::Mailbox`] falls back to vsync.
+    pub fn set_supported_present_modes(&mut self, modes: &[wgpu::PresentMode]) {
+        self.present_modes = modes.to_vec();
+    }
+
+    /// Resize the surface.
+    ///
+    /// The swapchain is recreated before the next frame is drawn.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (self.config.width, self.config.height) != (width, height) {
+            self.config.width = width;
+            self.config.height = height;
+            self.dirty = true;
+        }
+    }
+
+    /// Get the size of the surface.
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    /// Get the underlying surface.
+    pub fn surface(&self) -> &wgpu::Surface {
+        &self.surface
+    }
+
+    /// Get the configuration of the surface.
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    /// Present frames with the mode closest to `mode`, starting with the next frame.
+    pub(crate) fn use_present_mode(&mut self, mode: PresentMode) {
+        let present_mode = match mode {
+            // This falls back to mailbox presentation, and then to vsync.
+            PresentMode::Immediate => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Mailbox if self.present_modes.contains(&wgpu::PresentMode::Mailbox) => {
+                wgpu::PresentMode::Mailbox
+            }
+            _ => wgpu::PresentMode::AutoVsync,
+        };
+
+        if self.config.present_mode != present_mode {
+            self.config.present_mode = present_mode;
+            self.dirty = true;
+        }
+    }
+
+    /// Acquire the next frame, configuring the surface if needed.
+    pub(crate) fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> Result<wgpu::SurfaceTexture, Pierror> {
+        if self.dirty {
+            self.configure(device);
+        }
+
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                // The window changed under us, so recreate the swapchain and try again.
+                self.configure(device);
+                self.surface
+                    .get_current_texture()
+                    .map_err(|err| Pierror::BackendError(err.into()))?
+            }
+            Err(err) => return Err(Pierror::BackendError(err.into())),
+        };
+
+        // Recreate the swapchain for the next frame if it no longer matches the window.
+        if frame.suboptimal {
+            self.dirty = true;
+        }
+
+        Ok(frame)
+    }
+
+    fn configure(&mut self, device: &wgpu::Device) {
+        self.config.width = self.config.width.max(1);
+        self.config.height = self.config.height.max(1);
+        self.surface.configure(device, &self.config);
+        self.dirty = false;
+    }
+}
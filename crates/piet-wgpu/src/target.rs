@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Rendering into textures that are used by other pipelines.
+
+/// A texture that piet scenes are rendered into, to be used by another rendering pipeline.
+///
+/// This is useful for HUDs and overlays, where a scene is drawn with piet every frame and
+/// then composited by a game engine or a UI library like `egui`. The texture has the
+/// [`FORMAT`](Self::FORMAT) format, so the [`WgpuContext`](crate::WgpuContext) used to draw
+/// into it has to be created with that output format.
+///
+/// The contents use premultiplied alpha, and the color channels are sRGB-encoded. Pipelines
+/// that blend in sRGB space can sample [`view`](Self::view) directly. Pipelines that expect
+/// sRGB textures to be decoded when sampled, like `egui`, should sample
+/// [`srgb_view`](Self::srgb_view) instead.
+#[derive(Debug)]
+pub struct TextureTarget {
+    /// The texture being rendered to.
+    texture: wgpu::Texture,
+
+    /// The size of the texture.
+    size: (u32, u32),
+}
+
+impl TextureTarget {
+    /// The format of the texture.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// The format of the texture when it is viewed with sRGB decoding.
+    pub const SRGB_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Create a new texture target of the given size.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("piet-wgpu texture target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[Self::SRGB_FORMAT],
+        });
+
+        Self {
+            texture,
+            size: (width, height),
+        }
+    }
+
+    /// Resize the texture, creating a new one if the size changed.
+    ///
+    /// Returns `true` if the texture was replaced, in which case any bind groups that use it
+    /// need to be created again.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) -> bool {
+        if self.size == (width, height) {
+            return false;
+        }
+
+        *self = Self::new(device, width, height);
+        true
+    }
+
+    /// Get the size of the texture.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Get the underlying texture.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Create a view of the texture that samples the sRGB-encoded values as they are.
+    pub fn view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Create a view of the texture that decodes sRGB values to linear ones when sampled.
+    pub fn srgb_view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(Self::SRGB_FORMAT),
+            ..Default::default()
+        })
+    }
+}
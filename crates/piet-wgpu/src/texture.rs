@@ -21,9 +21,11 @@
 
 //! Convenient wrappers around WGPU textures.
 
+use super::mipmap::MipmapPipeline;
 use super::{DeviceAndQueue, GpuContext};
 
 use std::cell::{Ref, RefCell, RefMut};
+use std::mem;
 use std::rc::Rc;
 
 use piet_hardware::piet::{Color, ImageFormat, InterpolationMode};
@@ -50,6 +52,7 @@ impl WgpuTexture {
         let address_mode = match repeat {
             RepeatStrategy::Clamp => wgpu::AddressMode::ClampToEdge,
             RepeatStrategy::Repeat => wgpu::AddressMode::Repeat,
+            RepeatStrategy::Reflect => wgpu::AddressMode::MirrorRepeat,
             RepeatStrategy::Color(color) => {
                 border_color = Some({
                     if color == Color::TRANSPARENT {
@@ -59,7 +62,7 @@ impl WgpuTexture {
                     } else if color == Color::WHITE {
                         wgpu::SamplerBorderColor::OpaqueWhite
                     } else {
-                        tracing::warn!("Invalid border color for sampler: {:?}", color);
+                        warn!("Invalid border color for sampler: {:?}", color);
                         wgpu::SamplerBorderColor::OpaqueWhite
                     }
                 });
@@ -77,6 +80,8 @@ impl WgpuTexture {
                 compare: None,
                 mag_filter: filter_mode,
                 min_filter: filter_mode,
+                mipmap_filter: filter_mode,
+                lod_max_clamp: lod_max_clamp(interpolation),
                 address_mode_u: address_mode,
                 address_mode_v: address_mode,
                 border_color,
@@ -92,6 +97,9 @@ impl WgpuTexture {
             border_color,
             address_mode,
             bind_group: None,
+            spare: None,
+            depth_stencil: None,
+            mipmapped: false,
         })))
     }
 
@@ -111,15 +119,35 @@ pub(crate) struct BorrowedTexture<'a>(Ref<'a, TextureInner>);
 
 impl BorrowedTexture<'_> {
     /// Get the bind group for this texture.
-    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+    pub(crate) fn bind_group(&self) -> &Rc<wgpu::BindGroup> {
         self.0.bind_group.as_ref().unwrap()
     }
+
+    /// Get the underlying texture.
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        self.0.texture.as_ref().expect("texture")
+    }
+
+    /// Get the depth and stencil attachment of this render target.
+    pub(crate) fn depth_stencil(&self) -> Option<&DepthStencil> {
+        self.0.depth_stencil.as_ref()
+    }
+
+    /// Whether this texture holds a single channel of coverage.
+    pub(crate) fn is_coverage(&self) -> bool {
+        self.0.format == ImageFormat::Grayscale
+    }
 }
 
 /// Mutably borrowed texture guard.
 pub(crate) struct BorrowedTextureMut<'a>(RefMut<'a, TextureInner>);
 
 impl BorrowedTextureMut<'_> {
+    /// Get the depth and stencil attachment of this render target.
+    pub(crate) fn depth_stencil_mut(&mut self) -> &mut Option<DepthStencil> {
+        &mut self.0.depth_stencil
+    }
+
     /// Write data to this texture.
     pub(crate) fn write_texture<DaQ: DeviceAndQueue + ?Sized>(
         &mut self,
@@ -137,10 +165,18 @@ impl BorrowedTextureMut<'_> {
         };
 
         let data_len = data.map_or(0, |d| d.len());
-        tracing::debug!(size=?size, format=?format, data_len=%data_len, "Writing a texture");
+        debug!(
+            "Writing a texture: size={:?}, format={:?}, data_len={}",
+            size, format, data_len
+        );
 
-        // Get the texture to write to.
-        let texture = if self.0.texture.is_none() || self.0.format != format {
+        // Get the texture to write to. Mip levels are dropped, since they no longer match.
+        let resized = self
+            .0
+            .texture
+            .as_ref()
+            .map_or(true, |texture| texture.size() != size);
+        let texture = if resized || self.0.format != format || self.0.mipmapped {
             let texture =
                 base.device_and_queue()
                     .device()
@@ -157,12 +193,18 @@ impl BorrowedTextureMut<'_> {
                             ImageFormat::RgbaSeparate => wgpu::TextureFormat::Rgba8Unorm,
                             _ => panic!("Unsupported"),
                         },
-                        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::COPY_DST
+                            | wgpu::TextureUsages::COPY_SRC,
+                        view_formats: match format {
+                            ImageFormat::Grayscale => &[],
+                            _ => &[wgpu::TextureFormat::Rgba8Unorm],
+                        },
                     });
 
             self.0.format = format;
             self.0.texture = Some(texture);
+            self.0.mipmapped = false;
 
             // Reset the bind group.
             self.0.recompute_bind_group(base);
@@ -201,6 +243,39 @@ impl BorrowedTextureMut<'_> {
         );
     }
 
+    /// Allocate this texture as a target that can be rendered into.
+    pub(crate) fn allocate_render_target<DaQ: DeviceAndQueue + ?Sized>(
+        &mut self,
+        base: &GpuContext<DaQ>,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+    ) {
+        let texture = base
+            .device_and_queue()
+            .device()
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("piet-wgpu render target {}", self.0.id)),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+
+        self.0.format = ImageFormat::RgbaPremul;
+        self.0.texture = Some(texture);
+        self.0.recompute_bind_group(base);
+    }
+
     /// Write to a sub-area of this texture.
     pub(crate) fn write_subtexture<DaQ: DeviceAndQueue + ?Sized>(
         &mut self,
@@ -240,6 +315,48 @@ impl BorrowedTextureMut<'_> {
                 depth_or_array_layers: 1,
             },
         );
+
+        if self.0.mipmapped {
+            base.mipmap_pipeline().update(
+                base.device_and_queue().device(),
+                base.device_and_queue().queue(),
+                self.0.texture.as_ref().unwrap(),
+            );
+        }
+    }
+
+    /// Give this texture mip levels, unless it is a render target or can't have them.
+    pub(crate) fn generate_mipmaps<DaQ: DeviceAndQueue + ?Sized>(
+        &mut self,
+        base: &GpuContext<DaQ>,
+    ) -> bool {
+        if self.0.mipmapped {
+            return true;
+        }
+
+        // Render targets are drawn into after the levels would be generated.
+        let texture = match &self.0.texture {
+            Some(texture)
+                if texture.format() == MipmapPipeline::FORMAT
+                    && !texture
+                        .usage()
+                        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT) =>
+            {
+                texture
+            }
+            _ => return false,
+        };
+
+        let mipmapped = base.mipmap_pipeline().generate(
+            base.device_and_queue().device(),
+            base.device_and_queue().queue(),
+            texture,
+            &format!("piet-wgpu texture {}", self.0.id),
+        );
+        self.0.texture = Some(mipmapped);
+        self.0.mipmapped = true;
+        self.0.recompute_bind_group(base);
+        true
     }
 
     /// Change the interpolation mode.
@@ -249,31 +366,149 @@ impl BorrowedTextureMut<'_> {
         interpolation: InterpolationMode,
     ) {
         if self.0.interpolation != interpolation {
-            let interp_mode = match interpolation {
-                InterpolationMode::NearestNeighbor => wgpu::FilterMode::Nearest,
-                InterpolationMode::Bilinear => wgpu::FilterMode::Linear,
-            };
+            let inner = &mut *self.0;
+            inner.interpolation = interpolation;
+
+            // Switch to the sampler for the other interpolation mode, creating it if needed.
+            let (sampler, bind_group) = inner
+                .spare
+                .take()
+                .unwrap_or_else(|| (inner.create_sampler(base, interpolation), None));
+            let sampler = mem::replace(&mut inner.sampler, sampler);
+            let bind_group = mem::replace(&mut inner.bind_group, bind_group);
+            inner.spare = Some((sampler, bind_group));
+
+            if inner.bind_group.is_none() {
+                inner.bind_group = inner.create_bind_group(base, &inner.sampler);
+            }
+        }
+    }
 
-            self.0.interpolation = interpolation;
-            self.0.sampler =
-                base.device_and_queue()
-                    .device()
-                    .create_sampler(&wgpu::SamplerDescriptor {
-                        label: Some(&format!("piet-wgpu sampler {}", self.0.id)),
-                        compare: None,
-                        mag_filter: interp_mode,
-                        min_filter: interp_mode,
-                        address_mode_u: self.0.address_mode,
-                        address_mode_v: self.0.address_mode,
-                        border_color: self.0.border_color,
-                        ..Default::default()
-                    });
-            self.0.recompute_bind_group(base);
+    /// Get the bind group that samples the texture with `interpolation`, without changing the
+    /// interpolation mode of the texture.
+    pub(crate) fn bind_group_with<DaQ: DeviceAndQueue + ?Sized>(
+        &mut self,
+        base: &GpuContext<DaQ>,
+        interpolation: InterpolationMode,
+    ) -> Rc<wgpu::BindGroup> {
+        let inner = &mut *self.0;
+        if inner.interpolation == interpolation {
+            return inner.bind_group.clone().unwrap();
+        }
+
+        // The spare sampler is for the other interpolation mode.
+        if inner.spare.is_none() {
+            inner.spare = Some((inner.create_sampler(base, interpolation), None));
         }
+        let (sampler, bind_group) = inner.spare.as_ref().unwrap();
+        if let Some(bind_group) = bind_group {
+            return bind_group.clone();
+        }
+
+        let bind_group = inner.create_bind_group(base, sampler).unwrap();
+        inner.spare.as_mut().unwrap().1 = Some(bind_group.clone());
+        bind_group
     }
 }
 
 /// Inner data for a texture.
+/// A depth and stencil attachment that belongs to a render target.
+pub(crate) struct DepthStencil {
+    /// The view of the attachment.
+    view: wgpu::TextureView,
+
+    /// Whether the attachment is drawn with.
+    pub(crate) attached: bool,
+
+    /// The number of clips in the stencil buffer.
+    pub(crate) clips: u32,
+}
+
+impl DepthStencil {
+    /// The format of depth and stencil attachments.
+    pub(crate) const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    /// Create a cleared attachment.
+    pub(crate) fn new<DaQ: DeviceAndQueue + ?Sized>(
+        base: &GpuContext<DaQ>,
+        size: (u32, u32),
+        samples: u32,
+        attached: bool,
+    ) -> Self {
+        let device = base.device_and_queue().device();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("piet-wgpu depth and stencil attachment"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: samples,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Start out with a depth of 1 and a stencil of 0.
+        let this = Self {
+            view,
+            attached,
+            clips: 0,
+        };
+        this.clear(base, wgpu::LoadOp::Clear(1.0));
+        this
+    }
+
+    /// Clear the stencil buffer to 0, and apply `depth` to the depth buffer.
+    pub(crate) fn clear<DaQ: DeviceAndQueue + ?Sized>(
+        &self,
+        base: &GpuContext<DaQ>,
+        depth: wgpu::LoadOp<f32>,
+    ) {
+        let mut encoder = base.device_and_queue().device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("piet-wgpu depth and stencil clear"),
+            },
+        );
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("piet-wgpu depth and stencil clear pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth,
+                    store: true,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+        });
+        base.device_and_queue()
+            .queue()
+            .submit(Some(encoder.finish()));
+    }
+
+    /// Get the attachment to draw with, keeping its contents between passes.
+    pub(crate) fn attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            }),
+            stencil_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            }),
+        }
+    }
+}
+
 struct TextureInner {
     /// The texture ID.
     id: usize,
@@ -297,20 +532,67 @@ struct TextureInner {
     border_color: Option<wgpu::SamplerBorderColor>,
 
     /// The bind group to use to bind to the pipeline.
-    bind_group: Option<wgpu::BindGroup>,
+    bind_group: Option<Rc<wgpu::BindGroup>>,
+
+    /// The sampler and bind group for the other interpolation mode.
+    ///
+    /// Images are drawn with different interpolation modes from draw to draw, so this keeps
+    /// switching between them cheap.
+    spare: Option<(wgpu::Sampler, Option<Rc<wgpu::BindGroup>>)>,
+
+    /// The depth and stencil attachment, if this is a render target that has one.
+    depth_stencil: Option<DepthStencil>,
+
+    /// Whether the texture has mip levels that are kept up to date.
+    mipmapped: bool,
 }
 
 impl TextureInner {
     /// Re-create the `BindGroup` from the current data.
     fn recompute_bind_group<DaQ: DeviceAndQueue + ?Sized>(&mut self, base: &GpuContext<DaQ>) {
-        let texture = match self.texture.as_ref() {
-            Some(texture) => texture,
-            None => {
-                self.bind_group = None;
-                return;
-            }
+        self.bind_group = self.create_bind_group(base, &self.sampler);
+
+        // The spare bind group refers to the old texture.
+        if let Some((_, bind_group)) = &mut self.spare {
+            *bind_group = None;
+        }
+    }
+
+    /// Create a sampler for the texture with the given interpolation.
+    fn create_sampler<DaQ: DeviceAndQueue + ?Sized>(
+        &self,
+        base: &GpuContext<DaQ>,
+        interpolation: InterpolationMode,
+    ) -> wgpu::Sampler {
+        let interp_mode = match interpolation {
+            InterpolationMode::NearestNeighbor => wgpu::FilterMode::Nearest,
+            InterpolationMode::Bilinear => wgpu::FilterMode::Linear,
         };
 
+        base.device_and_queue()
+            .device()
+            .create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(&format!("piet-wgpu sampler {}", self.id)),
+                compare: None,
+                mag_filter: interp_mode,
+                min_filter: interp_mode,
+                mipmap_filter: interp_mode,
+                lod_max_clamp: lod_max_clamp(interpolation),
+                address_mode_u: self.address_mode,
+                address_mode_v: self.address_mode,
+                border_color: self.border_color,
+                ..Default::default()
+            })
+    }
+
+    /// Create a `BindGroup` for the texture and a sampler.
+    fn create_bind_group<DaQ: DeviceAndQueue + ?Sized>(
+        &self,
+        base: &GpuContext<DaQ>,
+        sampler: &wgpu::Sampler,
+    ) -> Option<Rc<wgpu::BindGroup>> {
+        let texture = self.texture.as_ref()?;
+
         let new_bind_group =
             base.device_and_queue()
                 .device()
@@ -326,12 +608,22 @@ impl TextureInner {
                         },
                         wgpu::BindGroupEntry {
                             binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                            resource: wgpu::BindingResource::Sampler(sampler),
                         },
                     ],
                 });
 
-        self.bind_group = Some(new_bind_group);
+        Some(Rc::new(new_bind_group))
+    }
+}
+
+/// Get the highest mip level that is sampled with `interpolation`.
+///
+/// Nearest-neighbor sampling always uses the full-size level, like it does without mip levels.
+fn lod_max_clamp(interpolation: InterpolationMode) -> f32 {
+    match interpolation {
+        InterpolationMode::NearestNeighbor => 0.0,
+        InterpolationMode::Bilinear => 32.0,
     }
 }
 
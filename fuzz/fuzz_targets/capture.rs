@@ -0,0 +1,90 @@
+//! Check that replaying a captured frame draws the same pixels as the frame itself.
+//!
+//! The source is reused between runs, so captures also need to bring along the resources that
+//! were created in earlier frames.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::kurbo::Rect;
+use piet_hardware::piet::{
+    Color, ImageFormat, InterpolationMode, RenderContext, Text, TextLayoutBuilder,
+};
+use piet_hardware::{replay, CaptureContext, GpuContext, Source};
+use piet_hardware_fuzz::grid::{to_path, GridEl};
+use piet_hardware_fuzz::software::{SoftwareContext, SOFTWARE_SIZE};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// A writer that can be read after the capture is done.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(
+    |input: (bool, bool, Option<[u8; 4]>, [u8; 4], Vec<GridEl>)| {
+        let (clip, text, image, [r, g, b, a], elements) = input;
+        let (width, height) = SOFTWARE_SIZE;
+
+        thread_local! {
+            static SOURCE: RefCell<Source<CaptureContext<SoftwareContext>>> = RefCell::new(
+                Source::new(CaptureContext::new(SoftwareContext::new(SOFTWARE_SIZE.0, SOFTWARE_SIZE.1)))
+                    .expect("failed to create source"),
+            );
+        }
+
+        SOURCE.with(|source| {
+            let mut source = source.borrow_mut();
+            let capture = Shared::default();
+            source.context().capture_next_frame(capture.clone(), false);
+            source.context().clear(Color::TRANSPARENT);
+
+            {
+                let mut rc = source.render_context(width, height);
+                if clip {
+                    rc.clip(Rect::new(8.0, 8.0, 40.0, 56.0));
+                }
+                rc.fill(to_path(&elements), &Color::rgba8(r, g, b, a));
+                if let Some(pixel) = image {
+                    let image = rc
+                        .make_image(1, 1, &pixel, ImageFormat::RgbaSeparate)
+                        .unwrap();
+                    rc.draw_image(
+                        &image,
+                        Rect::new(16.0, 16.0, 48.0, 48.0),
+                        InterpolationMode::Bilinear,
+                    );
+                }
+                if text {
+                    // Glyphs are only uploaded the first time they are drawn.
+                    let layout = rc.text().new_text_layout("Hi").build().unwrap();
+                    rc.draw_text(&layout, (4.0, 4.0));
+                }
+                rc.finish().ok();
+            }
+            assert!(!source.context().is_capturing(), "the capture didn't end");
+
+            let replayed = SoftwareContext::new(width, height);
+            let data = capture.0.borrow();
+            if let Err(err) = replay(&replayed, &data[..]) {
+                panic!("{err}\n{}", String::from_utf8_lossy(&data));
+            }
+            assert!(
+                source.context().inner().pixels() == replayed.pixels(),
+                "the replayed frame differs"
+            );
+        });
+    }
+);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::kurbo::Rect;
+use piet_hardware::piet::{Color, RenderContext};
+use piet_hardware_fuzz::{with_render_context, FuzzEl, FuzzShape};
+
+fuzz_target!(|input: (Vec<FuzzEl>, Vec<FuzzEl>)| {
+    let (first, second) = input;
+
+    with_render_context(|rc| {
+        // Clip twice to go through both creating and intersecting the mask.
+        rc.clip(FuzzShape::new(&first));
+        rc.clip(FuzzShape::new(&second));
+        rc.fill(Rect::new(0.0, 0.0, 256.0, 256.0), &Color::RED);
+    });
+});
@@ -0,0 +1,70 @@
+//! Check that animating the dash offset of a stroke produces stable geometry.
+//!
+//! Selection marquees ("marching ants") move the dash offset every frame. Shifting the offset
+//! by a whole period of the pattern must produce exactly the same triangles, and every
+//! offset in between must stay within the stroke. Lengths and offsets are multiples of a
+//! quarter, so that adding a period is exact.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::kurbo::Shape;
+use piet_hardware::piet::{Color, LineCap, LineJoin, RenderContext, StrokeStyle};
+use piet_hardware_fuzz::grid::{to_path, GridEl};
+use piet_hardware_fuzz::with_render_context;
+
+/// The width of the stroke.
+const WIDTH: f64 = 2.0;
+
+fuzz_target!(|input: (Vec<u8>, i16, u8, Vec<GridEl>)| {
+    let (pattern, offset, steps, elements) = input;
+    let path = to_path(&elements);
+
+    let pattern: Vec<f64> = pattern
+        .iter()
+        .take(8)
+        .map(|&len| (len % 32 + 1) as f64 / 4.0)
+        .collect();
+    if pattern.is_empty() {
+        return;
+    }
+    let period: f64 = pattern.iter().sum::<f64>() * if pattern.len() % 2 == 1 { 2.0 } else { 1.0 };
+    let offset = offset as f64 / 4.0;
+
+    let stroke = |offset: f64| {
+        let mut style = StrokeStyle::new()
+            .dash_offset(offset)
+            .line_cap(LineCap::Square)
+            .line_join(LineJoin::Round);
+        style.set_dash_pattern(pattern.clone());
+
+        let (errors, triangles) = with_render_context(|rc| {
+            rc.stroke_styled(&path, &Color::RED, WIDTH, &style);
+        });
+        assert!(errors.is_empty(), "errors while stroking: {errors:?}");
+        triangles
+    };
+
+    // Whole periods don't change anything.
+    let base = stroke(offset);
+    for shift in [-1.0, 1.0, 3.0] {
+        assert_eq!(
+            base,
+            stroke(offset + shift * period),
+            "shifting the offset by {shift} periods changed the geometry"
+        );
+    }
+
+    // Every step of the animation stays within the stroke.
+    let bounds = path.bounding_box().inflate(WIDTH * 2.0, WIDTH * 2.0);
+    for step in 0..steps % 8 {
+        let triangles = stroke(offset + step as f64 / 4.0);
+        for point in triangles.iter().flatten() {
+            assert!(
+                point.is_finite() && bounds.contains(*point),
+                "dash at {point:?} is outside of {bounds:?}"
+            );
+        }
+    }
+});
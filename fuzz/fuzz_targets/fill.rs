@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::{Color, RenderContext};
+use piet_hardware_fuzz::{with_render_context, FuzzEl, FuzzShape};
+
+fuzz_target!(|input: (bool, Vec<FuzzEl>)| {
+    let (even_odd, elements) = input;
+    let path = FuzzShape::new(&elements);
+
+    with_render_context(|rc| {
+        if even_odd {
+            rc.fill_even_odd(path, &Color::RED);
+        } else {
+            rc.fill(path, &Color::RED);
+        }
+    });
+});
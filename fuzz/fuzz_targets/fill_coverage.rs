@@ -0,0 +1,86 @@
+//! Compare the coverage of filled paths against `tiny-skia`.
+//!
+//! Paths are placed on a small grid, see [`piet_hardware_fuzz::grid`]. Pixels near the
+//! outline are skipped, since curves are flattened differently and the two rasterizers handle
+//! edges differently.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::kurbo::{ParamCurveNearest, Point};
+use piet_hardware::piet::{Color, RenderContext};
+use piet_hardware_fuzz::grid::{outline, skia_path, to_path, GridEl};
+use piet_hardware_fuzz::with_render_context;
+
+/// The size of the area that is compared, in pixels.
+const AREA: u32 = 64;
+
+/// Pixels closer than this to the outline aren't compared.
+const MARGIN: f64 = 2.0;
+
+fn in_triangle(pt: Point, [a, b, c]: [Point; 3]) -> bool {
+    // Degenerate triangles don't cover anything.
+    if (b - a).cross(c - a) == 0.0 {
+        return false;
+    }
+
+    let side = |p: Point, q: Point| (q - p).cross(pt - p);
+    let (d1, d2, d3) = (side(a, b), side(b, c), side(c, a));
+    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(has_neg && has_pos)
+}
+
+fuzz_target!(|input: (bool, Vec<GridEl>)| {
+    let (even_odd, elements) = input;
+    let path = to_path(&elements);
+
+    // Fill the path with our rasterizer.
+    let (errors, triangles) = with_render_context(|rc| {
+        if even_odd {
+            rc.fill_even_odd(&path, &Color::RED);
+        } else {
+            rc.fill(&path, &Color::RED);
+        }
+    });
+    assert!(errors.is_empty(), "failed to fill path: {:?}", errors);
+
+    // Fill the path with tiny-skia.
+    let mut pixmap = tiny_skia::Pixmap::new(AREA, AREA).unwrap();
+    if let Some(skia_path) = skia_path(&path) {
+        let paint = tiny_skia::Paint {
+            anti_alias: false,
+            ..Default::default()
+        };
+        pixmap.fill_path(
+            &skia_path,
+            &paint,
+            if even_odd {
+                tiny_skia::FillRule::EvenOdd
+            } else {
+                tiny_skia::FillRule::Winding
+            },
+            tiny_skia::Transform::identity(),
+            None,
+        );
+    }
+
+    // Compare the pixel centers that aren't close to the outline.
+    let outline = outline(&path);
+    for y in 0..AREA {
+        for x in 0..AREA {
+            let center = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+            let near_outline = outline
+                .iter()
+                .any(|seg| seg.nearest(center, 0.01).distance_sq < MARGIN * MARGIN);
+            if near_outline {
+                continue;
+            }
+
+            let ours = triangles.iter().any(|&tri| in_triangle(center, tri));
+            let theirs = pixmap.pixel(x, y).unwrap().alpha() > 0;
+            assert_eq!(ours, theirs, "coverage differs at {:?}", center);
+        }
+    }
+});
@@ -0,0 +1,84 @@
+//! Check that syntax highlighted text is drawn in a single batch.
+//!
+//! Code editors color every token differently. The glyphs of a layout must stay in one draw
+//! call no matter how many color runs it has, with each glyph carrying its own vertex color.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::{Color, RenderContext, Text, TextAttribute, TextLayoutBuilder};
+use piet_hardware_fuzz::with_render_context_draws;
+
+/// The snippet that is highlighted.
+const SNIPPET: &str = "fn main() {\n    let x = [1, 2];\n    println!(\"{:?}\", x);\n}";
+
+/// The maximum number of color runs.
+const MAX_RUNS: usize = 16;
+
+fuzz_target!(|runs: Vec<(u8, [u8; 3])>| {
+    // Split the snippet into runs of the given colors.
+    let mut start = 0;
+    let mut byte_colors = vec![None; SNIPPET.len()];
+    let runs: Vec<_> = runs
+        .iter()
+        .take(MAX_RUNS)
+        .filter_map(|&(len, [r, g, b])| {
+            let end = (start + len as usize % 8).min(SNIPPET.len());
+            let range = start..end;
+            start = end;
+            byte_colors[range.clone()].fill(Some([r, g, b, 255]));
+            (!range.is_empty()).then_some((range, Color::rgb8(r, g, b)))
+        })
+        .collect();
+
+    let (errors, draws) = with_render_context_draws(|rc| {
+        let mut builder = rc.text().new_text_layout(SNIPPET);
+        for (range, color) in runs {
+            builder = builder.range_attribute(range, TextAttribute::TextColor(color));
+        }
+        let layout = builder.build().unwrap();
+        rc.draw_text(&layout, (0.0, 0.0));
+    });
+    assert!(errors.is_empty(), "errors while drawing: {errors:?}");
+
+    // Glyphs that aren't covered by a run use the default color.
+    let default = {
+        let (r, g, b, a) = piet_hardware::piet::util::DEFAULT_TEXT_COLOR.as_rgba8();
+        [r, g, b, a]
+    };
+    let palette: Vec<_> = byte_colors
+        .iter()
+        .map(|color| color.unwrap_or(default))
+        .collect();
+
+    assert_eq!(
+        draws.len(),
+        1,
+        "the text was split into {} draws",
+        draws.len()
+    );
+    let colors = &draws[0];
+
+    // Every glyph is a single color, and only colors from the runs are used.
+    for quad in colors.chunks(6) {
+        assert!(
+            quad.iter().all(|color| *color == quad[0]),
+            "a glyph has more than one color: {quad:?}"
+        );
+        assert!(
+            palette.contains(&quad[0]),
+            "the color {:?} isn't in any run",
+            quad[0]
+        );
+    }
+
+    // Every visible character's color made it into the batch.
+    for (i, c) in SNIPPET.char_indices() {
+        let color = &palette[i];
+        assert!(
+            c.is_whitespace() || colors.contains(color),
+            "the color {color:?} of {c:?} was dropped"
+        );
+    }
+});
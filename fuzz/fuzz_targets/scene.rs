@@ -0,0 +1,201 @@
+//! Compare whole scenes drawn through the software backend against `tiny-skia`.
+//!
+//! This catches differences in blending, clipping and transforms between the GPU pipeline and
+//! a conventional 2D renderer. Paths are placed on a small grid, see
+//! [`piet_hardware_fuzz::grid`], and pixels near any outline are skipped.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::kurbo::{Affine, BezPath, ParamCurveNearest, PathSeg, Point, Rect, Shape};
+use piet_hardware::piet::{Color, RenderContext};
+use piet_hardware_fuzz::grid::{grid_point, outline, skia_path, to_path, GridEl, CELL};
+use piet_hardware_fuzz::software::{with_software_context, SOFTWARE_SIZE};
+
+/// Pixels closer than this to an outline aren't compared.
+const MARGIN: f64 = 2.0;
+
+/// The maximum number of commands in a scene.
+const MAX_COMMANDS: usize = 8;
+
+/// The largest difference allowed in each channel, for each command.
+const TOLERANCE: u8 = 2;
+
+#[derive(Debug, Arbitrary)]
+enum Command {
+    FillRect(u8, u8, u8, u8, [u8; 4]),
+    Fill(Vec<GridEl>, bool, [u8; 4]),
+    ClipRect(u8, u8, u8, u8),
+    Clip(Vec<GridEl>),
+    Translate(i8, i8),
+    GlobalAlpha(u8),
+}
+
+fn grid_rect(x0: u8, y0: u8, x1: u8, y1: u8) -> Rect {
+    Rect::from_points(grid_point(x0, y0), grid_point(x1, y1))
+}
+
+fn color([r, g, b, a]: [u8; 4]) -> Color {
+    Color::rgba8(r, g, b, a)
+}
+
+/// The state of the `tiny-skia` renderer.
+struct Reference {
+    pixmap: tiny_skia::Pixmap,
+    mask: Option<tiny_skia::ClipMask>,
+    clipped_out: bool,
+    transform: Affine,
+    alpha: f64,
+}
+
+impl Reference {
+    fn transform(&self) -> tiny_skia::Transform {
+        let [a, b, c, d, e, f] = self.transform.as_coeffs();
+        tiny_skia::Transform::from_row(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+    }
+
+    fn fill(&mut self, path: &BezPath, even_odd: bool, [r, g, b, a]: [u8; 4]) {
+        let path = match skia_path(path) {
+            Some(path) if !self.clipped_out => path,
+            _ => return,
+        };
+
+        let alpha = (a as f64 * self.alpha).round() as u8;
+        let mut paint = tiny_skia::Paint::default();
+        paint.set_color_rgba8(r, g, b, alpha);
+        paint.anti_alias = false;
+
+        let rule = if even_odd {
+            tiny_skia::FillRule::EvenOdd
+        } else {
+            tiny_skia::FillRule::Winding
+        };
+        self.pixmap
+            .fill_path(&path, &paint, rule, self.transform(), self.mask.as_ref());
+    }
+
+    fn clip(&mut self, path: &BezPath) {
+        let path = match skia_path(path).and_then(|path| path.transform(self.transform())) {
+            Some(path) => path,
+            None => {
+                self.clipped_out = true;
+                return;
+            }
+        };
+
+        let (width, height) = SOFTWARE_SIZE;
+        let rule = tiny_skia::FillRule::Winding;
+        let filled = match &mut self.mask {
+            Some(mask) => mask.intersect_path(&path, rule, false),
+            None => {
+                let mut mask = tiny_skia::ClipMask::new();
+                let filled = mask.set_path(width, height, &path, rule, false);
+                self.mask = Some(mask);
+                filled
+            }
+        };
+
+        if filled.is_none() {
+            self.clipped_out = true;
+        }
+    }
+}
+
+fuzz_target!(|commands: Vec<Command>| {
+    let commands = &commands[..commands.len().min(MAX_COMMANDS)];
+
+    let mut reference = Reference {
+        pixmap: tiny_skia::Pixmap::new(SOFTWARE_SIZE.0, SOFTWARE_SIZE.1).unwrap(),
+        mask: None,
+        clipped_out: false,
+        transform: Affine::IDENTITY,
+        alpha: 1.0,
+    };
+    let mut outlines: Vec<PathSeg> = vec![];
+
+    let (errors, pixels) = with_software_context(|rc| {
+        for command in commands {
+            // Keep track of every edge, in device space.
+            let mut add_outline = |path: &BezPath, transform: Affine| {
+                outlines.extend(outline(&(transform * path.clone())).into_iter());
+            };
+
+            match command {
+                Command::FillRect(x0, y0, x1, y1, paint) => {
+                    let rect = grid_rect(*x0, *y0, *x1, *y1);
+                    rc.fill(rect, &color(*paint));
+
+                    let path = rect.to_path(0.1);
+                    add_outline(&path, reference.transform);
+                    reference.fill(&path, false, *paint);
+                }
+                Command::Fill(elements, even_odd, paint) => {
+                    let path = to_path(elements);
+                    if *even_odd {
+                        rc.fill_even_odd(&path, &color(*paint));
+                    } else {
+                        rc.fill(&path, &color(*paint));
+                    }
+
+                    add_outline(&path, reference.transform);
+                    reference.fill(&path, *even_odd, *paint);
+                }
+                Command::ClipRect(x0, y0, x1, y1) => {
+                    let rect = grid_rect(*x0, *y0, *x1, *y1);
+                    rc.clip(rect);
+
+                    let path = rect.to_path(0.1);
+                    add_outline(&path, reference.transform);
+                    reference.clip(&path);
+                }
+                Command::Clip(elements) => {
+                    let path = to_path(elements);
+                    rc.clip(&path);
+
+                    add_outline(&path, reference.transform);
+                    reference.clip(&path);
+                }
+                Command::Translate(x, y) => {
+                    let offset = ((*x % 8) as f64 * CELL, (*y % 8) as f64 * CELL);
+                    rc.transform(Affine::translate(offset));
+                    reference.transform *= Affine::translate(offset);
+                }
+                Command::GlobalAlpha(alpha) => {
+                    let alpha = *alpha as f64 / 255.0;
+                    rc.set_global_alpha(alpha);
+                    reference.alpha = alpha;
+                }
+            }
+        }
+    });
+    assert!(errors.is_empty(), "failed to draw scene: {:?}", errors);
+
+    // Compare the pixel centers that aren't close to any outline.
+    let tolerance = TOLERANCE.saturating_mul(commands.len().max(1) as u8);
+    for y in 0..SOFTWARE_SIZE.1 {
+        for x in 0..SOFTWARE_SIZE.0 {
+            let center = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+            let near_outline = outlines
+                .iter()
+                .any(|seg| seg.nearest(center, 0.01).distance_sq < MARGIN * MARGIN);
+            if near_outline {
+                continue;
+            }
+
+            let ours = pixels[(y * SOFTWARE_SIZE.0 + x) as usize];
+            let theirs = reference.pixmap.pixel(x, y).unwrap();
+            let theirs = [theirs.red(), theirs.green(), theirs.blue(), theirs.alpha()];
+            let close = ours
+                .iter()
+                .zip(theirs.iter())
+                .all(|(a, b)| a.abs_diff(*b) <= tolerance);
+            assert!(
+                close,
+                "pixels differ at {:?}: {:?} != {:?}",
+                center, ours, theirs
+            );
+        }
+    }
+});
@@ -0,0 +1,76 @@
+//! Check that converting images before uploading them doesn't allocate.
+//!
+//! Images with separate alpha are premultiplied in a buffer that is kept on the source. Once
+//! it has grown, uploading them should allocate no more than uploading premultiplied images.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::{ImageFormat, RenderContext};
+use piet_hardware_fuzz::with_render_context;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the allocations made by the process.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Count the allocations made by a closure.
+fn allocations(f: impl FnOnce()) -> usize {
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - start
+}
+
+fuzz_target!(|input: (u8, u8, Vec<u8>)| {
+    let (width, height, seed) = input;
+    let (width, height) = (width as usize % 64 + 1, height as usize % 64 + 1);
+    let pixels: Vec<u8> = (0..width * height * 4)
+        .map(|i| seed.get(i % seed.len().max(1)).copied().unwrap_or(i as u8))
+        .collect();
+
+    let (errors, _) = with_render_context(|rc| {
+        let mut upload = |format| {
+            allocations(|| {
+                rc.make_image(width, height, &pixels, format).unwrap();
+            })
+        };
+
+        // Let the staging buffer grow to the size of the image.
+        upload(ImageFormat::RgbaSeparate);
+        upload(ImageFormat::RgbaPremul);
+
+        for _ in 0..4 {
+            let premul = upload(ImageFormat::RgbaPremul);
+            let separate = upload(ImageFormat::RgbaSeparate);
+            assert!(
+                separate <= premul,
+                "converting a {width}x{height} image allocated {separate} times, \
+                 but uploading it allocated {premul} times"
+            );
+        }
+    });
+    assert!(errors.is_empty(), "errors while uploading: {errors:?}");
+});
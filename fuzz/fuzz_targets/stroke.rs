@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use piet_hardware::piet::{Color, LineCap, LineJoin, RenderContext, StrokeStyle};
+use piet_hardware_fuzz::{with_render_context, FuzzEl, FuzzShape};
+
+fuzz_target!(|input: (f32, u8, u8, Vec<f32>, f32, Vec<FuzzEl>)| {
+    let (width, cap, join, dashes, dash_offset, elements) = input;
+    let path = FuzzShape::new(&elements);
+    let dashes: Vec<f64> = dashes.into_iter().map(|len| len as f64).collect();
+
+    let mut style = StrokeStyle::new()
+        .dash_offset(dash_offset as f64)
+        .line_cap(match cap % 3 {
+            0 => LineCap::Butt,
+            1 => LineCap::Round,
+            _ => LineCap::Square,
+        })
+        .line_join(match join % 3 {
+            0 => LineJoin::Bevel,
+            1 => LineJoin::Round,
+            _ => LineJoin::Miter { limit: 10.0 },
+        });
+    style.set_dash_pattern(dashes);
+
+    with_render_context(|rc| {
+        rc.stroke_styled(path, &Color::RED, width as f64, &style);
+    });
+});
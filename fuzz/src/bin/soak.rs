@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Create and drop resources for a long time, and check that none of them leak.
+//!
+//! Run with `cargo run --release --features soak --bin soak [frames]`. Every frame makes
+//! images, text layouts, clips, gradients, shadows and scenes and drops them again. The
+//! parameters repeat every few hundred frames, so every cache is full by the first
+//! checkpoint, and the number of live resources must not grow after it.
+
+use piet_hardware::piet::kurbo::{Circle, Point, Rect, RoundedRect};
+use piet_hardware::piet::{
+    self, Color, FixedLinearGradient, FixedRadialGradient, GradientStop, ImageFormat,
+    InterpolationMode, RenderContext as _, Text as _, TextLayoutBuilder as _,
+};
+use piet_hardware::{RenderContext, Source};
+use piet_hardware_fuzz::NullContext;
+
+use std::rc::Rc;
+
+/// The number of frames to draw if none is given.
+const DEFAULT_FRAMES: usize = 20_000;
+
+/// The number of frames between checks of the live resources.
+const CHECKPOINT: usize = 1_000;
+
+/// The size of the render target.
+const SIZE: (u32, u32) = (256, 256);
+
+fn main() {
+    let frames = std::env::args().nth(1).map_or(DEFAULT_FRAMES, |arg| {
+        arg.parse().expect("invalid frame count")
+    });
+
+    let context = Rc::new(NullContext::default());
+    let mut source = Source::from_rc(context.clone()).expect("failed to create source");
+    let mut baseline = None;
+
+    for frame in 0..frames {
+        let errors = {
+            let mut rc = source.render_context(SIZE.0, SIZE.1);
+            draw_frame(&mut rc, frame);
+            rc.finish().expect("failed to finish frame");
+            rc.take_errors()
+        };
+        assert!(errors.is_empty(), "errors in frame {frame}: {errors:?}");
+
+        if (frame + 1) % CHECKPOINT == 0 {
+            let live = (context.live_textures(), context.live_vertex_buffers());
+            let memory = source.memory_usage().total.current;
+            println!(
+                "frame {}: {} textures, {} vertex buffers, {} bytes",
+                frame + 1,
+                live.0,
+                live.1,
+                memory
+            );
+
+            let (textures, buffers, bytes) = *baseline.get_or_insert((live.0, live.1, memory));
+            assert!(
+                live.0 <= textures && live.1 <= buffers && memory <= bytes,
+                "resources grew from {textures} textures, {buffers} vertex buffers and \
+                 {bytes} bytes at the first checkpoint"
+            );
+        }
+    }
+
+    drop(source);
+    assert_eq!(context.live_textures(), 0, "textures outlived the source");
+    assert_eq!(
+        context.live_vertex_buffers(),
+        0,
+        "vertex buffers outlived the source"
+    );
+    println!("no resources leaked in {frames} frames");
+}
+
+/// Draw a frame that creates and drops every kind of resource.
+fn draw_frame(rc: &mut RenderContext<'_, NullContext>, frame: usize) {
+    let step = (frame % 200) as f64;
+    let color = Color::rgba8(frame as u8, (frame / 3) as u8, (frame / 7) as u8, 200);
+    let brush = rc.solid_brush(color);
+
+    // Images of changing sizes and formats, and views of them.
+    let (width, height) = (frame % 31 + 1, frame % 17 + 1);
+    let pixels = vec![frame as u8; width * height * 4];
+    let format = [ImageFormat::RgbaSeparate, ImageFormat::RgbaPremul][frame % 2];
+    let image = rc
+        .make_image(width, height, &pixels, format)
+        .expect("failed to make image");
+    rc.draw_image(
+        &image,
+        Rect::new(step, 0.0, step + 40.0, 30.0),
+        InterpolationMode::Bilinear,
+    );
+    let view = image.sub_image(Rect::new(0.0, 0.0, 4.0, 4.0));
+    rc.fill(Rect::new(0.0, 40.0, 20.0, 60.0), &brush);
+    rc.draw_image(
+        &view,
+        Rect::new(0.0, 40.0, 20.0, 60.0),
+        InterpolationMode::NearestNeighbor,
+    );
+
+    // Text that keeps putting new glyphs into the atlas.
+    let layout = rc
+        .text()
+        .new_text_layout(format!("soak {frame} {}", frame * 7919))
+        .font(piet::FontFamily::SANS_SERIF, 10.0 + (frame % 13) as f64)
+        .text_color(color)
+        .build()
+        .expect("failed to build text layout");
+    rc.draw_text(&layout, (10.0, 100.0));
+
+    // Nested clips that need masks.
+    rc.save().expect("failed to save");
+    rc.clip(Circle::new((128.0, 128.0), 20.0 + step / 4.0));
+    rc.save().expect("failed to save");
+    rc.clip(RoundedRect::new(100.0, 100.0, 200.0, 180.0, step / 10.0));
+    rc.fill(Rect::new(0.0, 0.0, 256.0, 256.0), &brush);
+    rc.restore().expect("failed to restore");
+    rc.restore().expect("failed to restore");
+
+    // Gradients with changing stops.
+    let stops = vec![
+        GradientStop {
+            pos: 0.0,
+            color: Color::WHITE,
+        },
+        GradientStop { pos: 1.0, color },
+    ];
+    let linear = rc
+        .gradient(FixedLinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(step, 100.0),
+            stops: stops.clone(),
+        })
+        .expect("failed to make linear gradient");
+    rc.fill(Rect::new(0.0, 150.0, 100.0, 200.0), &linear);
+    let radial = rc
+        .gradient(FixedRadialGradient {
+            center: Point::new(50.0, 50.0),
+            origin_offset: Default::default(),
+            radius: 10.0 + step / 10.0,
+            stops,
+        })
+        .expect("failed to make radial gradient");
+    rc.fill(Circle::new((50.0, 50.0), 30.0), &radial);
+
+    // Shadows of changing sizes.
+    rc.blurred_rect(
+        Rect::new(150.0, 10.0, 160.0 + step / 5.0, 40.0),
+        2.0 + (frame % 5) as f64,
+        &color,
+    );
+
+    // Scenes that are recorded, drawn and dropped.
+    rc.begin_scene().expect("failed to begin scene");
+    rc.fill(Rect::new(0.0, 0.0, step, 10.0), &brush);
+    rc.draw_image(
+        &image,
+        Rect::new(10.0, 10.0, 30.0, 30.0),
+        InterpolationMode::Bilinear,
+    );
+    let scene = rc.end_scene().expect("failed to end scene");
+    rc.draw_scene(&scene).expect("failed to draw scene");
+}
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Paths with points on a small grid, for comparing against `tiny-skia`.
+//!
+//! Points are placed on a small grid, so that subpaths often return to their starting point
+//! and `ClosePath` often follows a `MoveTo` directly.
+
+use arbitrary::Arbitrary;
+
+use piet_hardware::piet::kurbo::{BezPath, Line, PathEl, PathSeg, Point};
+
+/// The size of the grid that points are placed on.
+pub const GRID: u8 = 16;
+
+/// The size of a grid cell, in pixels.
+pub const CELL: f64 = 4.0;
+
+/// A path element with its points on the grid.
+#[derive(Debug, Arbitrary)]
+pub enum GridEl {
+    MoveTo(u8, u8),
+    LineTo(u8, u8),
+    QuadTo(u8, u8, u8, u8),
+    CurveTo(u8, u8, u8, u8, u8, u8),
+    ClosePath,
+}
+
+/// Get the point at the given grid coordinates.
+pub fn grid_point(x: u8, y: u8) -> Point {
+    Point::new((x % GRID) as f64 * CELL, (y % GRID) as f64 * CELL)
+}
+
+/// Convert grid elements to a path, starting it at the origin if needed.
+pub fn to_path(elements: &[GridEl]) -> BezPath {
+    let mut path = vec![];
+    if !matches!(elements.first(), Some(GridEl::MoveTo(..))) {
+        path.push(PathEl::MoveTo(Point::ZERO));
+    }
+
+    path.extend(elements.iter().map(|el| match *el {
+        GridEl::MoveTo(x, y) => PathEl::MoveTo(grid_point(x, y)),
+        GridEl::LineTo(x, y) => PathEl::LineTo(grid_point(x, y)),
+        GridEl::QuadTo(x1, y1, x2, y2) => PathEl::QuadTo(grid_point(x1, y1), grid_point(x2, y2)),
+        GridEl::CurveTo(x1, y1, x2, y2, x3, y3) => {
+            PathEl::CurveTo(grid_point(x1, y1), grid_point(x2, y2), grid_point(x3, y3))
+        }
+        GridEl::ClosePath => PathEl::ClosePath,
+    }));
+
+    BezPath::from_vec(path)
+}
+
+/// Get every segment of the path, including the ones that implicitly close each subpath.
+pub fn outline(path: &BezPath) -> Vec<PathSeg> {
+    let mut segments: Vec<_> = path.segments().collect();
+
+    let mut start = Point::ZERO;
+    let mut last = Point::ZERO;
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => {
+                segments.push(PathSeg::Line(Line::new(last, start)));
+                start = p;
+                last = p;
+            }
+            PathEl::LineTo(p) | PathEl::QuadTo(_, p) | PathEl::CurveTo(_, _, p) => last = p,
+            PathEl::ClosePath => last = start,
+        }
+    }
+    segments.push(PathSeg::Line(Line::new(last, start)));
+
+    segments
+}
+
+/// Convert a path to a `tiny-skia` path.
+///
+/// Returns `None` if the path doesn't contain any segments.
+pub fn skia_path(path: &BezPath) -> Option<tiny_skia::Path> {
+    let mut builder = tiny_skia::PathBuilder::new();
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => builder.move_to(p.x as f32, p.y as f32),
+            PathEl::LineTo(p) => builder.line_to(p.x as f32, p.y as f32),
+            PathEl::QuadTo(p1, p2) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32)
+            }
+            PathEl::CurveTo(p1, p2, p3) => builder.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p3.x as f32,
+                p3.y as f32,
+            ),
+            PathEl::ClosePath => builder.close(),
+        }
+    }
+    builder.finish()
+}
@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! Shared code for the `piet-hardware` fuzz targets.
+//!
+//! The targets run arbitrary paths through the rasterizer and the clipping mask, using a
+//! backend that doesn't draw anything. The [`software`] backend draws on the CPU, so that whole
+//! scenes can be compared against `tiny-skia`.
+
+pub mod grid;
+pub mod software;
+
+use arbitrary::Arbitrary;
+
+use piet_hardware::piet::kurbo::{Affine, PathEl, Point, Rect, Shape};
+use piet_hardware::piet::{self, InterpolationMode};
+use piet_hardware::{DrawError, GpuContext, RenderContext, RepeatStrategy, Source, Vertex};
+
+use std::cell::{Cell, RefCell};
+use std::convert::Infallible;
+
+/// The size of the render target.
+const SIZE: (u32, u32) = (256, 256);
+
+/// A GPU context that doesn't draw anything, but records the triangles it is given.
+#[derive(Default)]
+pub struct NullContext {
+    /// The triangles written since the last call to `take_triangles`.
+    triangles: RefCell<Vec<[Point; 3]>>,
+
+    /// The colors of the vertices that were written last.
+    colors: RefCell<Vec<[u8; 4]>>,
+
+    /// The vertex colors of every non-empty draw since the last call to `take_draws`.
+    draws: RefCell<Vec<Vec<[u8; 4]>>>,
+
+    /// The number of textures that were created and not deleted yet.
+    textures: Cell<usize>,
+
+    /// The number of vertex buffers that were created and not deleted yet.
+    vertex_buffers: Cell<usize>,
+}
+
+impl NullContext {
+    /// Take the triangles that have been written to this context.
+    pub fn take_triangles(&self) -> Vec<[Point; 3]> {
+        self.triangles.take()
+    }
+
+    /// Take the vertex colors of the draws that have been pushed to this context.
+    pub fn take_draws(&self) -> Vec<Vec<[u8; 4]>> {
+        self.draws.take()
+    }
+
+    /// Get the number of textures that were created and not deleted yet.
+    pub fn live_textures(&self) -> usize {
+        self.textures.get()
+    }
+
+    /// Get the number of vertex buffers that were created and not deleted yet.
+    pub fn live_vertex_buffers(&self) -> usize {
+        self.vertex_buffers.get()
+    }
+}
+
+impl GpuContext for NullContext {
+    type Texture = ();
+    type VertexBuffer = ();
+    type Error = Infallible;
+
+    fn clear(&self, _color: piet::Color) {}
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        _interpolation: InterpolationMode,
+        _repeat: RepeatStrategy,
+    ) -> Result<Self::Texture, Self::Error> {
+        self.textures.set(self.textures.get() + 1);
+        Ok(())
+    }
+
+    fn delete_texture(&self, _texture: Self::Texture) {
+        self.textures.set(self.textures.get() - 1);
+    }
+
+    fn write_texture(
+        &self,
+        _texture: &Self::Texture,
+        _size: (u32, u32),
+        _format: piet::ImageFormat,
+        _data: Option<&[u8]>,
+    ) {
+    }
+
+    fn write_subtexture(
+        &self,
+        _texture: &Self::Texture,
+        _offset: (u32, u32),
+        _size: (u32, u32),
+        _format: piet::ImageFormat,
+        _data: &[u8],
+    ) {
+    }
+
+    fn set_texture_interpolation(
+        &self,
+        _texture: &Self::Texture,
+        _interpolation: InterpolationMode,
+    ) {
+    }
+
+    fn max_texture_size(&self) -> (u32, u32) {
+        (4096, 4096)
+    }
+
+    fn create_vertex_buffer(&self) -> Result<Self::VertexBuffer, Self::Error> {
+        self.vertex_buffers.set(self.vertex_buffers.get() + 1);
+        Ok(())
+    }
+
+    fn delete_vertex_buffer(&self, _buffer: Self::VertexBuffer) {
+        self.vertex_buffers.set(self.vertex_buffers.get() - 1);
+    }
+
+    fn write_vertices(&self, _buffer: &Self::VertexBuffer, vertices: &[Vertex], indices: &[u32]) {
+        // The backends trust the rasterizer to produce valid indices.
+        let len = vertices.len() as u32;
+        assert!(
+            indices.iter().all(|&index| index < len),
+            "index out of bounds"
+        );
+
+        *self.colors.borrow_mut() = indices
+            .iter()
+            .map(|&index| vertices[index as usize].color)
+            .collect();
+
+        let point = |index: u32| {
+            let [x, y] = vertices[index as usize].pos;
+            Point::new(x as f64, y as f64)
+        };
+        self.triangles.borrow_mut().extend(
+            indices
+                .chunks_exact(3)
+                .map(|tri| [point(tri[0]), point(tri[1]), point(tri[2])]),
+        );
+    }
+
+    fn push_buffers(
+        &self,
+        _vertex_buffer: &Self::VertexBuffer,
+        _current_texture: &Self::Texture,
+        _mask_texture: &Self::Texture,
+        _transform: &Affine,
+        _size: (u32, u32),
+    ) -> Result<(), Self::Error> {
+        let colors = self.colors.take();
+        if !colors.is_empty() {
+            self.draws.borrow_mut().push(colors);
+        }
+        Ok(())
+    }
+}
+
+/// A path element, generated by the fuzzer.
+#[derive(Debug, Arbitrary)]
+pub enum FuzzEl {
+    MoveTo(FuzzPoint),
+    LineTo(FuzzPoint),
+    QuadTo(FuzzPoint, FuzzPoint),
+    CurveTo(FuzzPoint, FuzzPoint, FuzzPoint),
+    ClosePath,
+}
+
+/// A point, generated by the fuzzer.
+#[derive(Debug, Arbitrary)]
+pub struct FuzzPoint(f32, f32);
+
+impl From<&FuzzPoint> for Point {
+    fn from(pt: &FuzzPoint) -> Self {
+        Point::new(pt.0 as f64, pt.1 as f64)
+    }
+}
+
+/// A shape made of the fuzzer's path elements.
+///
+/// The elements aren't validated, so the path may not start with a `MoveTo`.
+#[derive(Debug, Clone)]
+pub struct FuzzShape(Vec<PathEl>);
+
+impl FuzzShape {
+    /// Create a shape from the fuzzer's path elements.
+    pub fn new(elements: &[FuzzEl]) -> Self {
+        Self(
+            elements
+                .iter()
+                .map(|el| match el {
+                    FuzzEl::MoveTo(p) => PathEl::MoveTo(p.into()),
+                    FuzzEl::LineTo(p) => PathEl::LineTo(p.into()),
+                    FuzzEl::QuadTo(p1, p2) => PathEl::QuadTo(p1.into(), p2.into()),
+                    FuzzEl::CurveTo(p1, p2, p3) => PathEl::CurveTo(p1.into(), p2.into(), p3.into()),
+                    FuzzEl::ClosePath => PathEl::ClosePath,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Shape for FuzzShape {
+    type PathElementsIter<'iter> = std::iter::Copied<std::slice::Iter<'iter, PathEl>>;
+
+    fn path_elements(&self, _tolerance: f64) -> Self::PathElementsIter<'_> {
+        self.0.iter().copied()
+    }
+
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    fn perimeter(&self, _accuracy: f64) -> f64 {
+        0.0
+    }
+
+    fn winding(&self, _pt: Point) -> i32 {
+        0
+    }
+
+    fn bounding_box(&self) -> Rect {
+        let points = self.0.iter().flat_map(|el| match *el {
+            PathEl::MoveTo(p) | PathEl::LineTo(p) => vec![p],
+            PathEl::QuadTo(p1, p2) => vec![p1, p2],
+            PathEl::CurveTo(p1, p2, p3) => vec![p1, p2, p3],
+            PathEl::ClosePath => vec![],
+        });
+
+        points
+            .map(|p| Rect::from_points(p, p))
+            .reduce(|a, b| a.union(b))
+            .unwrap_or(Rect::ZERO)
+    }
+}
+
+/// Run a closure with a render context backed by a [`NullContext`].
+///
+/// The source is reused between runs, since loading fonts is slow. Returns the errors that
+/// occurred while drawing and the triangles that were written to the context.
+pub fn with_render_context(
+    f: impl FnOnce(&mut RenderContext<'_, NullContext>),
+) -> (Vec<DrawError>, Vec<[Point; 3]>) {
+    with_null_context(f, NullContext::take_triangles)
+}
+
+/// Run a closure with a render context backed by a [`NullContext`].
+///
+/// Returns the errors that occurred while drawing and the vertex colors of every draw that
+/// was pushed to the context, in order.
+pub fn with_render_context_draws(
+    f: impl FnOnce(&mut RenderContext<'_, NullContext>),
+) -> (Vec<DrawError>, Vec<Vec<[u8; 4]>>) {
+    with_null_context(f, NullContext::take_draws)
+}
+
+/// Run a closure with the shared render context, then take what was recorded with `take`.
+fn with_null_context<T>(
+    f: impl FnOnce(&mut RenderContext<'_, NullContext>),
+    take: impl FnOnce(&NullContext) -> T,
+) -> (Vec<DrawError>, T) {
+    thread_local! {
+        static SOURCE: RefCell<Source<NullContext>> = RefCell::new(
+            Source::new(NullContext::default()).expect("failed to create source"),
+        );
+    }
+
+    SOURCE.with(|source| {
+        let mut source = source.borrow_mut();
+        source.context().take_triangles();
+        source.context().take_draws();
+
+        let errors = {
+            let mut rc = source.render_context(SIZE.0, SIZE.1);
+            f(&mut rc);
+            piet::RenderContext::finish(&mut rc).ok();
+            rc.take_errors()
+        };
+
+        (errors, take(source.context()))
+    })
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later OR MPL-2.0
+// This file is a part of `piet-hardware`.
+//
+// `piet-hardware` is free software: you can redistribute it and/or modify it under the
+// terms of either:
+//
+// * GNU Lesser General Public License as published by the Free Software Foundation, either
+//   version 3 of the License, or (at your option) any later version.
+// * Mozilla Public License as published by the Mozilla Foundation, version 2.
+// * The Patron License (https://github.com/notgull/piet-hardware/blob/main/LICENSE-PATRON.md)
+//   for sponsors and contributors, who can ignore the copyleft provisions of the above licenses
+//   for this project.
+//
+// `piet-hardware` is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR
+// PURPOSE. See the GNU Lesser General Public License or the Mozilla Public License for more
+// details.
+//
+// You should have received a copy of the GNU Lesser General Public License and the Mozilla
+// Public License along with `piet-hardware`. If not, see <https://www.gnu.org/licenses/>.
+
+//! The software context used by the fuzz targets.
+
+use piet_hardware::piet;
+use piet_hardware::{DrawError, GpuContext, RenderContext, Source};
+
+use std::cell::RefCell;
+
+pub use piet_gpu_soft::SoftwareContext;
+
+/// The size of the target of [`with_software_context`].
+pub const SOFTWARE_SIZE: (u32, u32) = (64, 64);
+
+/// Run a closure with a render context backed by a [`SoftwareContext`].
+///
+/// The source is reused between runs, and the target is cleared to transparent before the
+/// closure is called. Returns the errors that occurred while drawing and the premultiplied
+/// RGBA pixels of the target, row by row.
+pub fn with_software_context(
+    f: impl FnOnce(&mut RenderContext<'_, SoftwareContext>),
+) -> (Vec<DrawError>, Vec<[u8; 4]>) {
+    thread_local! {
+        static SOURCE: RefCell<Source<SoftwareContext>> = RefCell::new(
+            Source::new(SoftwareContext::new(SOFTWARE_SIZE.0, SOFTWARE_SIZE.1))
+                .expect("failed to create source"),
+        );
+    }
+
+    SOURCE.with(|source| {
+        let mut source = source.borrow_mut();
+        source.context().clear(piet::Color::TRANSPARENT);
+
+        let errors = {
+            let mut rc = source.render_context(SOFTWARE_SIZE.0, SOFTWARE_SIZE.1);
+            f(&mut rc);
+            piet::RenderContext::finish(&mut rc).ok();
+            rc.take_errors()
+        };
+
+        (errors, source.context().pixels())
+    })
+}